@@ -0,0 +1,115 @@
+// Transparent decompression of downloaded or local files
+use async_compression::tokio::bufread::{GzipDecoder, XzDecoder, ZstdDecoder};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, BufReader};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Xz,
+}
+
+// Pick the compression from a Content-Encoding header, falling back to
+// the magic bytes at the start of the body
+pub fn detect(content_encoding: Option<&str>, head: &[u8]) -> Compression {
+    let from_header = match content_encoding.map(|e| e.trim().to_ascii_lowercase()) {
+        Some(e) if e == "gzip" || e == "x-gzip" => Compression::Gzip,
+        Some(e) if e == "zstd" => Compression::Zstd,
+        Some(e) if e == "xz" || e == "x-xz" => Compression::Xz,
+        _ => Compression::None,
+    };
+    if from_header != Compression::None {
+        return from_header;
+    }
+
+    if head.starts_with(GZIP_MAGIC) {
+        Compression::Gzip
+    } else if head.starts_with(ZSTD_MAGIC) {
+        Compression::Zstd
+    } else if head.starts_with(XZ_MAGIC) {
+        Compression::Xz
+    } else {
+        Compression::None
+    }
+}
+
+// Wrap a reader so compressed content comes out decompressed
+pub async fn decompress(
+    mut reader: Box<dyn AsyncBufRead + Unpin + Send>,
+    content_encoding: Option<&str>,
+) -> std::io::Result<(Box<dyn AsyncBufRead + Unpin + Send>, Compression)> {
+    let head = reader.fill_buf().await?;
+    let compression = detect(content_encoding, head);
+
+    let reader: Box<dyn AsyncBufRead + Unpin + Send> = match compression {
+        Compression::None => reader,
+        Compression::Gzip => {
+            let mut decoder = GzipDecoder::new(reader);
+            // Some servers concatenate gzip members
+            decoder.multiple_members(true);
+            Box::new(BufReader::new(decoder))
+        }
+        Compression::Zstd => Box::new(BufReader::new(ZstdDecoder::new(reader))),
+        Compression::Xz => Box::new(BufReader::new(XzDecoder::new(reader))),
+    };
+    Ok((reader, compression))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_compression::tokio::bufread::{GzipEncoder, XzEncoder, ZstdEncoder};
+    use tokio::io::AsyncReadExt;
+
+    const SAMPLE: &[u8] = b"#EXTM3U\n#EXTINF:-1,Channel\nhttp://example.com/c.ts\n";
+
+    fn read_all(reader: impl tokio::io::AsyncRead + Unpin) -> Vec<u8> {
+        tauri::async_runtime::block_on(async move {
+            let mut reader = reader;
+            let mut out = Vec::new();
+            reader.read_to_end(&mut out).await.unwrap();
+            out
+        })
+    }
+
+    fn roundtrip(compressed: Vec<u8>, encoding: Option<&str>) -> (Vec<u8>, Compression) {
+        let (reader, compression) = tauri::async_runtime::block_on(decompress(
+            Box::new(std::io::Cursor::new(compressed)),
+            encoding,
+        ))
+        .unwrap();
+        (read_all(reader), compression)
+    }
+
+    #[test]
+    fn test_detect_from_header_and_magic() {
+        // Test that the header wins and magic bytes are the fallback
+        assert_eq!(detect(Some("GZIP"), b"plain"), Compression::Gzip);
+        assert_eq!(detect(Some("identity"), &[0x28, 0xb5, 0x2f, 0xfd, 0]), Compression::Zstd);
+        assert_eq!(detect(None, XZ_MAGIC), Compression::Xz);
+        assert_eq!(detect(None, b"#EXTM3U"), Compression::None);
+    }
+
+    #[test]
+    fn test_decompress_gzip_zstd_xz() {
+        // Test that every supported format decompresses to the original
+        let gzip = read_all(GzipEncoder::new(SAMPLE));
+        let zstd = read_all(ZstdEncoder::new(SAMPLE));
+        let xz = read_all(XzEncoder::new(SAMPLE));
+
+        assert_eq!(roundtrip(gzip, None), (SAMPLE.to_vec(), Compression::Gzip));
+        assert_eq!(roundtrip(zstd, Some("zstd")), (SAMPLE.to_vec(), Compression::Zstd));
+        assert_eq!(roundtrip(xz, None), (SAMPLE.to_vec(), Compression::Xz));
+    }
+
+    #[test]
+    fn test_decompress_passthrough() {
+        // Test that uncompressed content is returned untouched
+        assert_eq!(roundtrip(SAMPLE.to_vec(), None), (SAMPLE.to_vec(), Compression::None));
+    }
+}
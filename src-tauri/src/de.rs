@@ -0,0 +1,161 @@
+// Lenient deserializers for provider APIs (Xtream, Stalker), which send the
+// same field as a string, a number, a bool or null depending on the version
+use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD};
+use base64::Engine;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+
+// Text of a scalar value; None for null, empty strings and containers
+pub fn text(value: &Value) -> Option<String> {
+    let text = match value {
+        Value::String(s) => s.trim().to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        _ => return None,
+    };
+    (!text.is_empty()).then_some(text)
+}
+
+pub fn integer(value: &Value) -> Option<i64> {
+    match value {
+        Value::Number(n) => n.as_i64().or_else(|| n.as_f64().map(|f| f as i64)),
+        Value::String(s) => {
+            let s = s.trim();
+            s.parse().ok().or_else(|| s.parse::<f64>().ok().map(|f| f as i64))
+        }
+        Value::Bool(b) => Some(i64::from(*b)),
+        _ => None,
+    }
+}
+
+pub fn string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    Ok(text(&Value::deserialize(deserializer)?).unwrap_or_default())
+}
+
+pub fn opt_i64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<i64>, D::Error> {
+    Ok(integer(&Value::deserialize(deserializer)?))
+}
+
+pub fn opt_u32<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    Ok(integer(&Value::deserialize(deserializer)?).and_then(|n| u32::try_from(n).ok()))
+}
+
+pub fn u32<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    opt_u32(deserializer).map(Option::unwrap_or_default)
+}
+
+// 1, "1", true and "true" are all true
+pub fn bool<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    let value = Value::deserialize(deserializer)?;
+    Ok(match &value {
+        Value::String(s) => matches!(s.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes"),
+        other => integer(other).is_some_and(|n| n != 0),
+    })
+}
+
+// An array of scalars; null or a single value are tolerated
+pub fn string_list<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    Ok(match Value::deserialize(deserializer)? {
+        Value::Array(values) => values.iter().filter_map(text).collect(),
+        other => text(&other).into_iter().collect(),
+    })
+}
+
+pub fn opt_string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Ok(text(&Value::deserialize(deserializer)?))
+}
+
+// A nested object; servers send [] or null instead when there's no data
+pub fn object<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned + Default,
+{
+    Ok(object_value(Value::deserialize(deserializer)?))
+}
+
+pub fn object_value<T: DeserializeOwned + Default>(value: Value) -> T {
+    match value {
+        value @ Value::Object(_) => serde_json::from_value(value).unwrap_or_default(),
+        _ => T::default(),
+    }
+}
+
+// The items of a list answer; empty lists arrive as [], {} or null, and
+// malformed items are skipped rather than failing the whole list
+pub fn list<T: DeserializeOwned>(value: Value) -> Vec<T> {
+    let items = match value {
+        Value::Array(items) => items,
+        // Some panels send lists as objects keyed by index
+        Value::Object(map) => map.into_iter().map(|(_, item)| item).collect(),
+        _ => Vec::new(),
+    };
+    items
+        .into_iter()
+        .filter_map(|item| serde_json::from_value(item).ok())
+        .collect()
+}
+
+// EPG text fields are base64; anything that doesn't decode to clean UTF-8
+// is taken as already being plain text
+pub fn base64_text(value: &str) -> String {
+    let value = value.trim();
+    let decoded = STANDARD.decode(value).or_else(|_| STANDARD_NO_PAD.decode(value));
+    match decoded.ok().and_then(|bytes| String::from_utf8(bytes).ok()) {
+        Some(text) if !text.chars().any(|c| c.is_control() && !c.is_whitespace()) => text.trim().to_string(),
+        _ => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_scalars_are_coerced() {
+        // Test that strings, numbers and bools convert consistently
+        assert_eq!(text(&json!(12)).as_deref(), Some("12"));
+        assert_eq!(text(&json!("  ")), None);
+        assert_eq!(integer(&json!("1700000000")), Some(1_700_000_000));
+        assert_eq!(integer(&json!("2.0")), Some(2));
+        assert_eq!(integer(&json!(null)), None);
+
+        #[derive(Deserialize)]
+        struct Flags {
+            #[serde(deserialize_with = "bool")]
+            a: bool,
+            #[serde(deserialize_with = "bool")]
+            b: bool,
+            #[serde(deserialize_with = "string_list")]
+            list: Vec<String>,
+        }
+        let flags: Flags = serde_json::from_value(json!({"a": "1", "b": 0, "list": ["ts", 8]})).unwrap();
+        assert!(flags.a);
+        assert!(!flags.b);
+        assert_eq!(flags.list, vec!["ts", "8"]);
+    }
+
+    #[test]
+    fn test_list_tolerates_shapes() {
+        // Test that empty objects, null and bad items don't fail a list
+        #[derive(Deserialize)]
+        struct Item {
+            id: u32,
+        }
+        let items: Vec<Item> = list(json!([{"id": 1}, {"id": "x"}, {"id": 3}]));
+        assert_eq!(items.iter().map(|i| i.id).collect::<Vec<_>>(), vec![1, 3]);
+        assert!(list::<Item>(json!({})).is_empty());
+        assert!(list::<Item>(json!(null)).is_empty());
+    }
+
+    #[test]
+    fn test_base64_text() {
+        // Test EPG text decoding with plain-text fallback
+        assert_eq!(base64_text("TmV3cyBhdCBTaXg="), "News at Six");
+        assert_eq!(base64_text("TmV3cyBhdCBTaXg"), "News at Six");
+        assert_eq!(base64_text("w5xiZXJzaWNodA=="), "Übersicht");
+        assert_eq!(base64_text("Late Show!"), "Late Show!");
+    }
+}
@@ -0,0 +1,189 @@
+// Import of files dragged onto the window (playlists, CSV and XMLTV)
+use std::path::Path;
+
+use serde::Serialize;
+use tauri::{AppHandle, DragDropEvent, Emitter, Manager, Window, WindowEvent};
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::compression;
+use crate::epg::{sources, EpgSource};
+use crate::playlist::{self, DownloadCache, PlaylistStore, PlaylistSummary, PlaylistWatcher};
+use crate::validate_string_length;
+
+// Maximum accepted length for a dropped file path
+const MAX_PATH_LENGTH: usize = 4096;
+
+// How much of the (decompressed) file is inspected to detect its type
+const SNIFF_LENGTH: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DroppedKind {
+    Playlist,
+    Csv,
+    Xmltv,
+    Unsupported,
+}
+
+// What was detected in a dropped file and what it was imported as
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DroppedFile {
+    pub path: String,
+    pub kind: DroppedKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub playlist: Option<PlaylistSummary>,
+    // The guide stored from an XMLTV file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub guide: Option<EpgSource>,
+    // Why nothing was imported, when that is the case
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+// Payload of the file-drop-failed event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DropFailure {
+    path: String,
+    error: String,
+}
+
+// Name of the guide source a dropped file becomes: its name without the
+// extensions, "epg.xml.gz" -> "epg"
+fn guide_name(path: &Path) -> String {
+    let name = path.file_name().map(|f| f.to_string_lossy()).unwrap_or_default();
+    let base = name.split('.').next().unwrap_or_default();
+    match base.trim() {
+        "" => name.into_owned(),
+        base => base.to_string(),
+    }
+}
+
+// Decide what a file is from its content, falling back to the extension
+pub fn classify(file_name: &str, head: &str) -> DroppedKind {
+    let head = head.trim_start_matches('\u{feff}').trim_start().to_ascii_lowercase();
+    let is_xmltv = ["<tv>", "<tv ", "<tv\n", "<tv\r"].iter().any(|tag| head.contains(tag));
+    if is_xmltv && !head.contains("<playlist") {
+        return DroppedKind::Xmltv;
+    }
+    if head.starts_with("#extm3u") || head.starts_with("[playlist]") || head.contains("<playlist") {
+        return DroppedKind::Playlist;
+    }
+
+    let name = file_name.to_ascii_lowercase();
+    let name = [".gz", ".xz", ".zst"]
+        .iter()
+        .find_map(|suffix| name.strip_suffix(suffix))
+        .unwrap_or(&name);
+    match name.rsplit_once('.').map(|(_, extension)| extension) {
+        Some("m3u" | "m3u8" | "pls" | "xspf") => DroppedKind::Playlist,
+        Some("csv") => DroppedKind::Csv,
+        Some("xml" | "xmltv") => DroppedKind::Xmltv,
+        _ => DroppedKind::Unsupported,
+    }
+}
+
+async fn sniff(path: &Path) -> Result<DroppedKind, String> {
+    let file = tokio::fs::File::open(path)
+        .await
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let (mut reader, _) = compression::decompress(Box::new(BufReader::new(file)), None)
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let head = reader
+        .fill_buf()
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let head = String::from_utf8_lossy(&head[..head.len().min(SNIFF_LENGTH)]);
+
+    let file_name = path.file_name().map(|f| f.to_string_lossy()).unwrap_or_default();
+    Ok(classify(&file_name, &head))
+}
+
+// Detect a dropped file and import it into the matching store
+pub async fn import_dropped(app: &AppHandle, path: &Path) -> Result<DroppedFile, String> {
+    let kind = sniff(path).await?;
+    let source = path.to_string_lossy().into_owned();
+    let store = app.state::<PlaylistStore>();
+
+    let (mut playlist, mut guide, mut note) = (None, None, None);
+    match kind {
+        DroppedKind::Playlist => {
+            let cache = app.state::<DownloadCache>();
+            let outcome = playlist::fetch_into_store(app, &store, &cache, &source, None, false).await?;
+            app.state::<PlaylistWatcher>().watch(&source);
+            playlist = Some(outcome.summary().clone());
+        }
+        DroppedKind::Csv => playlist = Some(playlist::import_csv(&store, &source).await?),
+        // Saved as a guide source, refreshed from the file like any other
+        DroppedKind::Xmltv => guide = Some(sources::add(app, &guide_name(path), &source, None).await?),
+        DroppedKind::Unsupported => note = Some("Unsupported file type".to_string()),
+    }
+
+    Ok(DroppedFile {
+        path: source,
+        kind,
+        playlist,
+        guide,
+        note,
+    })
+}
+
+// Command handler for files dropped onto the frontend
+#[tauri::command]
+pub async fn handle_dropped_file(app: AppHandle, path: String) -> Result<DroppedFile, String> {
+    validate_string_length(&path, MAX_PATH_LENGTH)?;
+    if path.trim().is_empty() {
+        return Err("Dropped file path cannot be empty".to_string());
+    }
+    import_dropped(&app, Path::new(path.trim())).await
+}
+
+// Import files dropped onto a window, reporting each one through the
+// file-dropped / file-drop-failed events
+pub fn on_window_event(window: &Window, event: &WindowEvent) {
+    let WindowEvent::DragDrop(DragDropEvent::Drop { paths, .. }) = event else {
+        return;
+    };
+    let app = window.app_handle().clone();
+    let paths = paths.clone();
+    tauri::async_runtime::spawn(async move {
+        for path in paths {
+            match import_dropped(&app, &path).await {
+                Ok(dropped) => {
+                    let _ = app.emit("file-dropped", dropped);
+                }
+                Err(error) => {
+                    let path = path.to_string_lossy().into_owned();
+                    let _ = app.emit("file-drop-failed", DropFailure { path, error });
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_by_content() {
+        // Test that content wins over a misleading extension
+        assert_eq!(classify("guide.m3u", "<?xml version=\"1.0\"?>\n<tv generator-info-name=\"x\">"), DroppedKind::Xmltv);
+        assert_eq!(classify("list.xml", "<?xml?><playlist version=\"1\">"), DroppedKind::Playlist);
+        assert_eq!(classify("list.txt", "\u{feff}#EXTM3U\n"), DroppedKind::Playlist);
+        assert_eq!(classify("x", "#EXTINF:-1 tvg-id=\"a\",<tvg>"), DroppedKind::Unsupported);
+    }
+
+    #[test]
+    fn test_classify_by_extension() {
+        // Test the extension fallback, including compressed files
+        assert_eq!(classify("epg.xml.gz", ""), DroppedKind::Xmltv);
+        assert_eq!(classify("lineup.CSV", "name,url"), DroppedKind::Csv);
+        assert_eq!(classify("radio.pls", ""), DroppedKind::Playlist);
+        assert_eq!(classify("photo.png", ""), DroppedKind::Unsupported);
+        assert_eq!(guide_name(Path::new("/tmp/epg.xml.gz")), "epg");
+        assert_eq!(guide_name(Path::new("/tmp/.xmltv")), ".xmltv");
+    }
+}
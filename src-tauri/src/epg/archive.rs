@@ -0,0 +1,91 @@
+// Past programmes a channel's catch-up archive still holds. How far back
+// the archive goes comes from the playlist (catchup-days, timeshift,
+// tvg-rec); a programme can be played back once it has started and until
+// it falls out of that window
+use serde::Serialize;
+
+use super::{programme_id, Programme};
+use crate::playlist::catchup::{self, ArchiveWindow};
+use crate::playlist::ChannelEntry;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveProgramme {
+    // See programme_id
+    pub id: String,
+    pub programme: Programme,
+    // Archive URL playing it back
+    pub url: String,
+    // Unix seconds when it leaves the archive
+    pub expires_at: i64,
+}
+
+// The guide times [from, to) the channel's archive covers
+pub fn archive_range(entry: &ChannelEntry, now: i64) -> Result<(i64, i64), String> {
+    if catchup::mode(entry).is_none() {
+        return Err(format!("Channel '{}' doesn't support catch-up", entry.name));
+    }
+    Ok((now - i64::from(catchup::archive_days(entry)) * 86_400, now))
+}
+
+// The programmes among `programmes` the archive can still play back,
+// with the URL for each
+pub fn archived(entry: &ChannelEntry, programmes: Vec<Programme>, now: i64) -> Vec<ArchiveProgramme> {
+    let depth = i64::from(catchup::archive_days(entry)) * 86_400;
+    programmes
+        .into_iter()
+        .filter_map(|programme| {
+            let window = ArchiveWindow {
+                start: programme.start,
+                end: programme.stop,
+                now,
+            };
+            let url = catchup::archive_url(entry, window).ok()?;
+            Some(ArchiveProgramme {
+                id: programme_id(&programme.channel, programme.start),
+                expires_at: programme.start + depth,
+                url,
+                programme,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archived_programmes() {
+        // Test that only programmes that started within the archive depth are kept, with their URLs
+        let entry = ChannelEntry {
+            name: "One".to_string(),
+            url: "http://tv/one.m3u8".to_string(),
+            catchup: Some("shift".to_string()),
+            catchup_days: Some(1),
+            ..Default::default()
+        };
+        let now = 1_000_000;
+        let programme = |start: i64| Programme {
+            channel: "one.uk".to_string(),
+            start,
+            stop: start + 3600,
+            title: "Show".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(archive_range(&entry, now).unwrap(), (now - 86_400, now));
+        let found = archived(&entry, [now - 90_000, now - 80_000, now - 600, now + 600].map(programme).to_vec(), now);
+        let starts: Vec<i64> = found.iter().map(|found| found.programme.start).collect();
+        assert_eq!(starts, vec![now - 80_000, now - 600]);
+        assert_eq!(found[0].id, format!("one.uk@{}", now - 80_000));
+        assert_eq!(found[0].expires_at, now - 80_000 + 86_400);
+        assert!(found[0].url.starts_with("http://tv/one.m3u8?utc="));
+
+        let live_only = ChannelEntry {
+            catchup: None,
+            catchup_days: None,
+            ..entry
+        };
+        assert!(archive_range(&live_only, now).is_err());
+    }
+}
@@ -0,0 +1,318 @@
+// Programme artwork from guide <icon> URLs, downloaded once into the app
+// cache and handed to the webview as asset URLs. Guide queries swap in
+// the cached copy and queue the rest; remote servers see one request per
+// image, and failures aren't retried for a while. The least recently used
+// images go first when the cache outgrows its limit
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::Semaphore;
+
+use super::Programme;
+use crate::playlist::content_hash;
+use crate::{http, storage, unix_now};
+
+const INDEX_FILE: &str = "index.json";
+
+// Largest image downloaded and the whole cache, in bytes
+pub const MAX_IMAGE_BYTES: u64 = 2 * 1024 * 1024;
+pub const MAX_CACHE_BYTES: u64 = 200 * 1024 * 1024;
+
+// Seconds before a failed image is tried again
+const RETRY_AFTER: i64 = 60 * 60;
+
+// Downloads running at once
+const MAX_DOWNLOADS: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ArtEntry {
+    file: String,
+    bytes: u64,
+    // Unix seconds of the last lookup; kept in memory between downloads
+    used_at: i64,
+}
+
+#[derive(Default)]
+struct ArtState {
+    // By image URL
+    entries: HashMap<String, ArtEntry>,
+    // Image URL -> when it may be tried again
+    failed: HashMap<String, i64>,
+    downloading: HashSet<String>,
+}
+
+// Payload of the epg-artwork event, sent when an image is cached
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtworkCached {
+    pub url: String,
+    pub asset_url: String,
+}
+
+// The URL the webview loads a local file from, as convertFileSrc builds it
+pub fn asset_url(path: &Path) -> String {
+    let mut encoded = String::new();
+    for byte in path.to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.!~*'()".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    if cfg!(any(windows, target_os = "android")) {
+        format!("http://asset.localhost/{}", encoded)
+    } else {
+        format!("asset://localhost/{}", encoded)
+    }
+}
+
+fn extension(content_type: &str) -> Option<&'static str> {
+    let mime = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    Some(match mime.as_str() {
+        "image/jpeg" | "image/jpg" => "jpg",
+        "image/png" => "png",
+        "image/webp" => "webp",
+        "image/gif" => "gif",
+        "image/avif" => "avif",
+        "image/svg+xml" => "svg",
+        _ => return None,
+    })
+}
+
+// Remote http(s) URLs only; anything else is left as the guide gave it
+fn is_remote(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+pub struct ArtworkCache {
+    dir: PathBuf,
+    limit: u64,
+    state: Mutex<ArtState>,
+    downloads: Semaphore,
+}
+
+impl ArtworkCache {
+    pub fn open(dir: PathBuf, limit: u64) -> Self {
+        let entries = storage::read_json(&dir.join(INDEX_FILE)).unwrap_or_else(|e| {
+            log::warn!("Discarding artwork cache index: {}", e);
+            HashMap::new()
+        });
+        Self {
+            dir,
+            limit,
+            state: Mutex::new(ArtState {
+                entries,
+                ..Default::default()
+            }),
+            downloads: Semaphore::new(MAX_DOWNLOADS),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // The cached copy of an image, marking it used
+    pub fn lookup(&self, url: &str, now: i64) -> Option<PathBuf> {
+        let mut state = self.state.lock().unwrap();
+        let entry = state.entries.get_mut(url)?;
+        let path = self.dir.join(&entry.file);
+        if !path.exists() {
+            state.entries.remove(url);
+            return None;
+        }
+        entry.used_at = now;
+        Some(path)
+    }
+
+    // Whether the caller should download an image: not cached, not being
+    // downloaded and not failed recently
+    fn claim(&self, url: &str, now: i64) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.entries.contains_key(url) || state.failed.get(url).is_some_and(|retry| *retry > now) {
+            return false;
+        }
+        state.downloading.insert(url.to_string())
+    }
+
+    async fn fetch(&self, url: &str) -> Result<(Vec<u8>, &'static str), String> {
+        let _permit = self.downloads.acquire().await.map_err(|e| e.to_string())?;
+        let mut response = http::get(url, None)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to fetch {}: {}", url, e))?;
+        if !response.status().is_success() {
+            return Err(format!("Artwork download failed with HTTP {}", response.status().as_u16()));
+        }
+        let ext = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(extension)
+            .ok_or_else(|| format!("{} isn't an image", url))?;
+        if response.content_length().is_some_and(|length| length > MAX_IMAGE_BYTES) {
+            return Err(format!("{} is larger than {} bytes", url, MAX_IMAGE_BYTES));
+        }
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(|e| format!("Failed to read {}: {}", url, e))? {
+            body.extend_from_slice(&chunk);
+            if body.len() as u64 > MAX_IMAGE_BYTES {
+                return Err(format!("{} is larger than {} bytes", url, MAX_IMAGE_BYTES));
+            }
+        }
+        Ok((body, ext))
+    }
+
+    // Download a claimed image into the cache
+    async fn download(&self, url: &str) -> Result<PathBuf, String> {
+        let result = match self.fetch(url).await {
+            Ok((body, ext)) => self.store(url, &body, ext),
+            Err(e) => Err(e),
+        };
+        let mut state = self.state.lock().unwrap();
+        state.downloading.remove(url);
+        if result.is_err() {
+            state.failed.insert(url.to_string(), unix_now() + RETRY_AFTER);
+        }
+        result
+    }
+
+    fn store(&self, url: &str, body: &[u8], ext: &str) -> Result<PathBuf, String> {
+        fs::create_dir_all(&self.dir).map_err(|e| format!("Failed to create {}: {}", self.dir.display(), e))?;
+        let file = format!("{}.{}", content_hash(url.as_bytes()), ext);
+        let path = self.dir.join(&file);
+        fs::write(&path, body).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+        let mut state = self.state.lock().unwrap();
+        state.entries.insert(
+            url.to_string(),
+            ArtEntry {
+                file,
+                bytes: body.len() as u64,
+                used_at: unix_now(),
+            },
+        );
+        self.evict(&mut state.entries, url);
+        storage::write_json(&self.dir.join(INDEX_FILE), &state.entries)?;
+        Ok(path)
+    }
+
+    // Drop the least recently used images, never `keep`, until the cache
+    // fits its limit
+    fn evict(&self, entries: &mut HashMap<String, ArtEntry>, keep: &str) {
+        let mut total: u64 = entries.values().map(|entry| entry.bytes).sum();
+        if total <= self.limit {
+            return;
+        }
+        let mut oldest: Vec<(i64, String)> = entries
+            .iter()
+            .filter(|(url, _)| url.as_str() != keep)
+            .map(|(url, entry)| (entry.used_at, url.clone()))
+            .collect();
+        oldest.sort();
+        for (_, url) in oldest {
+            if total <= self.limit {
+                break;
+            }
+            if let Some(entry) = entries.remove(&url) {
+                total -= entry.bytes;
+                let _ = fs::remove_file(self.dir.join(&entry.file));
+            }
+        }
+    }
+
+    // Delete every cached image; returns the bytes freed
+    pub fn clear(&self) -> Result<u64, String> {
+        let mut state = self.state.lock().unwrap();
+        let freed = state.entries.values().map(|entry| entry.bytes).sum();
+        for entry in state.entries.values() {
+            let _ = fs::remove_file(self.dir.join(&entry.file));
+        }
+        state.entries.clear();
+        state.failed.clear();
+        storage::write_json(&self.dir.join(INDEX_FILE), &state.entries)?;
+        Ok(freed)
+    }
+}
+
+// Point programme icons at cached copies; icons not cached yet are left
+// out and downloaded in the background, with an epg-artwork event for each
+pub fn localize<'a>(app: &AppHandle, programmes: impl IntoIterator<Item = &'a mut Programme>) {
+    let cache = app.state::<ArtworkCache>();
+    let now = unix_now();
+    for programme in programmes {
+        let Some(url) = programme.icon.take() else {
+            continue;
+        };
+        if !is_remote(&url) {
+            continue;
+        }
+        if let Some(path) = cache.lookup(&url, now) {
+            programme.icon = Some(asset_url(&path));
+        } else if cache.claim(&url, now) {
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                match app.state::<ArtworkCache>().download(&url).await {
+                    Ok(path) => {
+                        let asset_url = asset_url(&path);
+                        let _ = app.emit("epg-artwork", ArtworkCached { url, asset_url });
+                    }
+                    Err(e) => log::debug!("{}", e),
+                }
+            });
+        }
+    }
+}
+
+// Command handler deleting the cached artwork; returns the bytes freed
+#[tauri::command]
+pub fn clear_epg_artwork(cache: State<'_, ArtworkCache>) -> Result<u64, String> {
+    cache.clear()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{response, serve, temp_dir};
+
+    #[test]
+    fn test_artwork_cached_and_evicted() {
+        // Test that images are downloaded once, non-images and failures aren't retried and old images are evicted
+        let image = "x".repeat(60);
+        let (base, requests) = serve(vec![
+            response("200 OK", &[("Content-Type", "image/png")], &image),
+            response("200 OK", &[("Content-Type", "text/html")], "<html>"),
+            response("200 OK", &[("Content-Type", "image/jpeg")], &image),
+        ]);
+        let cache = ArtworkCache::open(temp_dir("epg-artwork"), 100);
+        let first = format!("{}/a.png", base);
+        let page = format!("{}/page", base);
+        let second = format!("{}/b.jpg", base);
+
+        assert!(cache.claim(&first, 0) && !cache.claim(&first, 0));
+        let path = tauri::async_runtime::block_on(cache.download(&first)).unwrap();
+        assert_eq!(fs::read(&path).unwrap().len(), 60);
+        assert_eq!(cache.lookup(&first, 1), Some(path.clone()));
+        assert!(!cache.claim(&first, 1));
+
+        assert!(cache.claim(&page, 0));
+        assert!(tauri::async_runtime::block_on(cache.download(&page)).is_err());
+        assert!(!cache.claim(&page, unix_now()));
+
+        assert!(cache.claim(&second, 0));
+        let newer = tauri::async_runtime::block_on(cache.download(&second)).unwrap();
+        assert!(newer.to_string_lossy().ends_with(".jpg"));
+        assert!(!path.exists() && cache.lookup(&first, 2).is_none());
+        assert_eq!(requests.lock().unwrap().len(), 3);
+
+        let reopened = ArtworkCache::open(cache.dir().to_path_buf(), 100);
+        assert_eq!(reopened.lookup(&second, 3), Some(newer));
+        assert_eq!(reopened.clear().unwrap(), 60);
+        assert!(asset_url(Path::new("/a b/c.png")).ends_with("localhost/%2Fa%20b%2Fc.png"));
+    }
+}
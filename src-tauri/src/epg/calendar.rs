@@ -0,0 +1,127 @@
+// Reminders as an iCalendar (RFC 5545) file, so phone and desktop
+// calendars can import or subscribe to them. Each reminder is an event
+// with an alarm at the reminder's lead time, and each scheduled recording
+// one over the span it records, padding included
+use super::reminders::Reminder;
+use super::time::utc_datetime;
+use crate::playlist::content_hash;
+use crate::recording::RecordingJob;
+
+// Longest content line, in bytes, before it's folded
+const LINE_LENGTH: usize = 75;
+
+fn ics_time(timestamp: i64) -> String {
+    let time = utc_datetime(timestamp);
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        time.year, time.month, time.day, time.hour, time.minute, time.second
+    )
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Append a content line, folded so no line is longer than LINE_LENGTH bytes
+fn push_line(ics: &mut String, line: &str) {
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > LINE_LENGTH {
+            ics.push_str("\r\n ");
+            length = 1;
+        }
+        ics.push(c);
+        length += c.len_utf8();
+    }
+    ics.push_str("\r\n");
+}
+
+pub fn to_ics(reminders: &[Reminder], recordings: &[RecordingJob], lead_minutes: u32, now: i64) -> String {
+    let mut ics = String::new();
+    for line in ["BEGIN:VCALENDAR", "VERSION:2.0", "PRODID:-//tiptv//Reminders//EN", "CALSCALE:GREGORIAN"] {
+        push_line(&mut ics, line);
+    }
+    for reminder in reminders {
+        push_line(&mut ics, "BEGIN:VEVENT");
+        push_line(&mut ics, &format!("UID:{}@tiptv", content_hash(reminder.id.as_bytes())));
+        push_line(&mut ics, &format!("DTSTAMP:{}", ics_time(now)));
+        push_line(&mut ics, &format!("DTSTART:{}", ics_time(reminder.start)));
+        push_line(&mut ics, &format!("DTEND:{}", ics_time(reminder.stop)));
+        push_line(&mut ics, &format!("SUMMARY:{}", escape(&reminder.title)));
+        push_line(&mut ics, &format!("LOCATION:{}", escape(&reminder.channel)));
+        push_line(&mut ics, "BEGIN:VALARM");
+        push_line(&mut ics, "ACTION:DISPLAY");
+        push_line(&mut ics, &format!("DESCRIPTION:{}", escape(&reminder.title)));
+        push_line(&mut ics, &format!("TRIGGER:-PT{}M", lead_minutes));
+        push_line(&mut ics, "END:VALARM");
+        push_line(&mut ics, "END:VEVENT");
+    }
+    for job in recordings {
+        push_line(&mut ics, "BEGIN:VEVENT");
+        push_line(&mut ics, &format!("UID:{}@tiptv", content_hash(format!("recording|{}", job.id).as_bytes())));
+        push_line(&mut ics, &format!("DTSTAMP:{}", ics_time(now)));
+        push_line(&mut ics, &format!("DTSTART:{}", ics_time(job.start)));
+        push_line(&mut ics, &format!("DTEND:{}", ics_time(job.stop)));
+        push_line(&mut ics, &format!("SUMMARY:{}", escape(&format!("Recording: {}", job.title))));
+        push_line(&mut ics, &format!("LOCATION:{}", escape(&job.channel_name)));
+        push_line(&mut ics, "END:VEVENT");
+    }
+    push_line(&mut ics, "END:VCALENDAR");
+    ics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reminders_to_ics() {
+        // Test that reminders become escaped, folded events with an alarm at the lead time, and pending
+        // recordings events over their padded span
+        let reminder = Reminder {
+            id: "bbc1.uk@1700000000".to_string(),
+            channel: "bbc1.uk".to_string(),
+            start: 1_700_000_000,
+            stop: 1_700_003_600,
+            title: format!("News, weather; sport {}", "x".repeat(80)),
+            channel_id: None,
+            notified: false,
+        };
+        let recording = RecordingJob {
+            id: "job".to_string(),
+            title: "Final".to_string(),
+            channel_name: "One HD".to_string(),
+            programme_start: 1_700_010_000,
+            programme_stop: 1_700_013_600,
+            start: 1_700_009_880,
+            stop: 1_700_013_900,
+            ..Default::default()
+        };
+        let ics = to_ics(&[reminder], &[recording], 5, 0);
+
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(ics.ends_with("END:VEVENT\r\nEND:VCALENDAR\r\n"));
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 2);
+        assert_eq!(ics.matches("BEGIN:VALARM").count(), 1);
+        assert!(ics.contains("\r\nDTSTART:20231115T005800Z\r\nDTEND:20231115T020500Z\r\n"));
+        assert!(ics.contains("\r\nSUMMARY:Recording: Final\r\nLOCATION:One HD\r\n"));
+        assert!(ics.contains("\r\nDTSTART:20231114T221320Z\r\nDTEND:20231114T231320Z\r\n"));
+        assert!(ics.contains("\r\nDTSTAMP:19700101T000000Z\r\n"));
+        assert!(ics.contains("\r\nSUMMARY:News\\, weather\\; sport xxx"));
+        assert!(ics.contains("\r\nTRIGGER:-PT5M\r\n"));
+        assert!(ics.split("\r\n").all(|line| line.len() <= LINE_LENGTH));
+        assert!(ics.split("\r\n").any(|line| line.starts_with(" x")));
+    }
+}
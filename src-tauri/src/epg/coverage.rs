@@ -0,0 +1,160 @@
+// How well the stored guides cover a playlist, group by group: how many
+// channels are matched to a guide channel, how many days of programmes lie
+// ahead for them and which source supplies them
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use super::matching::{Matcher, DEFAULT_THRESHOLD};
+use super::{GuideIndex, TimeOffsets};
+use crate::playlist::ChannelEntry;
+
+// Channel names listed per group as missing guide data
+const MAX_MISSING: usize = 100;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupCoverage {
+    // group-title, None for channels without one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    pub channels: usize,
+    // Matched to a guide channel
+    pub matched: usize,
+    // With programmes from now on
+    pub covered: usize,
+    // Days of programmes ahead: the fewest and the average among covered
+    // channels, to a tenth of a day
+    pub min_days: f64,
+    pub average_days: f64,
+    // Source id -> covered channels it's the most trusted source for
+    pub sources: BTreeMap<String, usize>,
+    // Names of channels without programmes ahead, the first MAX_MISSING
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub missing: Vec<String>,
+}
+
+fn tenths(days: f64) -> f64 {
+    (days * 10.0).round() / 10.0
+}
+
+// Groups in playlist order
+pub fn coverage(
+    guides: &[(String, Arc<GuideIndex>)],
+    offsets: &TimeOffsets,
+    entries: &[ChannelEntry],
+    matcher: &Matcher,
+    manual: &BTreeMap<String, String>,
+    now: i64,
+) -> Vec<GroupCoverage> {
+    let mut groups: Vec<(GroupCoverage, Vec<f64>)> = Vec::new();
+    let mut positions: HashMap<Option<String>, usize> = HashMap::new();
+    for entry in entries {
+        let group = entry
+            .group_title
+            .as_deref()
+            .map(str::trim)
+            .filter(|group| !group.is_empty())
+            .map(str::to_string);
+        let position = *positions.entry(group.clone()).or_insert_with(|| {
+            groups.push((
+                GroupCoverage {
+                    group,
+                    ..Default::default()
+                },
+                Vec::new(),
+            ));
+            groups.len() - 1
+        });
+        let (coverage, days) = &mut groups[position];
+        coverage.channels += 1;
+
+        let manual = manual.get(&entry.channel_id).map(String::as_str);
+        let found = matcher.find(entry, manual, DEFAULT_THRESHOLD).ok();
+        let mut source = None;
+        let mut last_stop = None;
+        if let Some(found) = &found {
+            coverage.matched += 1;
+            for (id, guide) in guides {
+                let run = offsets.window(id, guide, &found.epg_channel_id, now, i64::MAX);
+                if let Some(last) = run.last() {
+                    source.get_or_insert(id);
+                    last_stop = last_stop.max(Some(last.stop));
+                }
+            }
+        }
+        match (source, last_stop) {
+            (Some(source), Some(stop)) => {
+                coverage.covered += 1;
+                *coverage.sources.entry(source.clone()).or_default() += 1;
+                days.push((stop - now) as f64 / 86_400.0);
+            }
+            _ if coverage.missing.len() < MAX_MISSING => coverage.missing.push(entry.name.clone()),
+            _ => {}
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(mut coverage, days)| {
+            if !days.is_empty() {
+                coverage.min_days = tenths(days.iter().copied().fold(f64::INFINITY, f64::min));
+                coverage.average_days = tenths(days.iter().sum::<f64>() / days.len() as f64);
+            }
+            coverage
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epg::{EpgChannel, Programme};
+
+    #[test]
+    fn test_coverage_by_group() {
+        // Test that groups count matched and covered channels, days ahead and the source supplying them
+        let programme = |channel: &str, start: i64, stop: i64| Programme {
+            channel: channel.to_string(),
+            start,
+            stop,
+            title: "Show".to_string(),
+            ..Default::default()
+        };
+        let guide = GuideIndex::new(vec![programme("one", 0, 2 * 86_400), programme("two", 0, 86_400 / 2)]);
+        let stale = GuideIndex::new(vec![programme("old", 0, 100)]);
+        let guides = vec![("a".to_string(), Arc::new(guide)), ("b".to_string(), Arc::new(stale))];
+        let channel = |id: &str| {
+            (
+                if id == "old" { "b" } else { "a" }.to_string(),
+                EpgChannel {
+                    id: id.to_string(),
+                    names: vec![id.to_string()],
+                    icon: None,
+                },
+            )
+        };
+        let matcher = Matcher::new(vec![channel("one"), channel("two"), channel("old")]);
+        let entry = |name: &str, group: Option<&str>| ChannelEntry {
+            channel_id: format!("p:{}", name),
+            name: name.to_string(),
+            group_title: group.map(str::to_string),
+            ..Default::default()
+        };
+        let entries = vec![
+            entry("one", Some("UK")),
+            entry("two", Some("UK")),
+            entry("old", Some("UK")),
+            entry("Unknown", None),
+        ];
+
+        let groups = coverage(&guides, &TimeOffsets::default(), &entries, &matcher, &BTreeMap::new(), 1000);
+        assert_eq!(groups.len(), 2);
+        let uk = &groups[0];
+        assert_eq!((uk.channels, uk.matched, uk.covered), (3, 3, 2));
+        assert_eq!((uk.min_days, uk.average_days), (0.5, 1.2));
+        assert_eq!(uk.sources, BTreeMap::from([("a".to_string(), 2)]));
+        assert_eq!(uk.missing, vec!["old"]);
+        assert_eq!((groups[1].group.as_deref(), groups[1].matched, groups[1].covered), (None, 0, 0));
+    }
+}
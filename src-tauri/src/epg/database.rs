@@ -0,0 +1,304 @@
+// The programmes of every stored guide in one SQLite database, indexed on
+// channel and start time, so a channel's window is an index range scan
+// whatever the size of the guides. A full-text index over their titles,
+// descriptions and genres answers searches. Programmes are kept as they
+// were published, time offsets are applied by the queries' callers
+use std::path::Path;
+use std::sync::Mutex;
+
+use rusqlite::{params, Connection};
+
+use super::Programme;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS programmes (
+        id INTEGER PRIMARY KEY,
+        source TEXT NOT NULL,
+        channel TEXT NOT NULL,
+        start INTEGER NOT NULL,
+        stop INTEGER NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS programmes_channel_start ON programmes (channel, start);
+    CREATE INDEX IF NOT EXISTS programmes_source ON programmes (source);
+    CREATE VIRTUAL TABLE IF NOT EXISTS programme_words USING fts5(
+        words,
+        genres,
+        tokenize = 'unicode61 remove_diacritics 2'
+    );
+";
+
+// Stored in the database's user_version once the full-text index holds
+// every programme; databases written before it existed are indexed on
+// opening
+const WORDS_VERSION: i64 = 1;
+
+pub struct GuideDatabase {
+    connection: Mutex<Connection>,
+}
+
+fn failed(e: rusqlite::Error) -> String {
+    format!("Guide database error: {}", e)
+}
+
+fn parse(data: &str) -> Result<Programme, String> {
+    serde_json::from_str(data).map_err(|e| format!("Failed to parse a stored programme: {}", e))
+}
+
+// What a programme is found by: its title and description in every
+// language, and its categories and genres
+fn words(programme: &Programme) -> (String, String) {
+    let texts = programme.titles.iter().chain(&programme.descriptions).map(|text| text.text.as_str());
+    let words = [programme.title.as_str(), programme.description.as_deref().unwrap_or_default()]
+        .into_iter()
+        .chain(texts)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let genres = programme
+        .categories
+        .iter()
+        .map(String::as_str)
+        .chain(programme.genres.iter().map(|genre| genre.as_str()))
+        .collect::<Vec<_>>()
+        .join(" ");
+    (words, genres)
+}
+
+fn insert_words(connection: &Connection, id: i64, programme: &Programme) -> rusqlite::Result<()> {
+    let (words, genres) = words(programme);
+    connection
+        .prepare_cached("INSERT INTO programme_words (rowid, words, genres) VALUES (?1, ?2, ?3)")?
+        .execute(params![id, words, genres])?;
+    Ok(())
+}
+
+fn remove_words(connection: &Connection, source: &str) -> rusqlite::Result<()> {
+    connection.execute(
+        "DELETE FROM programme_words WHERE rowid IN (SELECT id FROM programmes WHERE source = ?1)",
+        params![source],
+    )?;
+    Ok(())
+}
+
+// Fill the full-text index from the programmes already stored
+fn index_words(connection: &mut Connection) -> Result<(), String> {
+    let transaction = connection.transaction().map_err(failed)?;
+    transaction.execute("DELETE FROM programme_words", []).map_err(failed)?;
+    {
+        let mut select = transaction.prepare("SELECT id, data FROM programmes").map_err(failed)?;
+        let rows = select
+            .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))
+            .map_err(failed)?;
+        for row in rows {
+            let (id, data) = row.map_err(failed)?;
+            insert_words(&transaction, id, &parse(&data)?).map_err(failed)?;
+        }
+    }
+    transaction
+        .pragma_update(None, "user_version", WORDS_VERSION)
+        .map_err(failed)?;
+    transaction.commit().map_err(failed)
+}
+
+impl GuideDatabase {
+    // A database that fails to open is replaced by an empty one in memory,
+    // so the guide still works until the app restarts
+    pub fn open(path: &Path) -> Self {
+        let connection = Self::connect(path).unwrap_or_else(|e| {
+            log::error!("Failed to open the guide database {}: {}", path.display(), e);
+            let connection = Connection::open_in_memory().expect("SQLite can always open a database in memory");
+            connection.execute_batch(SCHEMA).expect("The guide schema is valid");
+            connection
+        });
+        Self {
+            connection: Mutex::new(connection),
+        }
+    }
+
+    fn connect(path: &Path) -> Result<Connection, String> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let mut connection = Connection::open(path).map_err(failed)?;
+        connection.execute_batch(SCHEMA).map_err(failed)?;
+        let version: i64 = connection
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(failed)?;
+        if version < WORDS_VERSION {
+            index_words(&mut connection)?;
+        }
+        Ok(connection)
+    }
+
+    // Replace a source's programmes, all at once or not at all
+    pub fn replace(&self, source: &str, programmes: &[Programme]) -> Result<(), String> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction().map_err(failed)?;
+        remove_words(&transaction, source).map_err(failed)?;
+        transaction
+            .execute("DELETE FROM programmes WHERE source = ?1", params![source])
+            .map_err(failed)?;
+        {
+            let mut insert = transaction
+                .prepare("INSERT INTO programmes (source, channel, start, stop, data) VALUES (?1, ?2, ?3, ?4, ?5)")
+                .map_err(failed)?;
+            for programme in programmes {
+                let data =
+                    serde_json::to_string(programme).map_err(|e| format!("Failed to serialize guide: {}", e))?;
+                insert
+                    .execute(params![source, programme.channel, programme.start, programme.stop, data])
+                    .map_err(failed)?;
+                insert_words(&transaction, transaction.last_insert_rowid(), programme).map_err(failed)?;
+            }
+        }
+        transaction.commit().map_err(failed)
+    }
+
+    pub fn remove(&self, source: &str) -> Result<(), String> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction().map_err(failed)?;
+        remove_words(&transaction, source).map_err(failed)?;
+        transaction
+            .execute("DELETE FROM programmes WHERE source = ?1", params![source])
+            .map_err(failed)?;
+        transaction.commit().map_err(failed)
+    }
+
+    // Every programme of a source, in no particular order
+    pub fn programmes(&self, source: &str) -> Result<Vec<Programme>, String> {
+        let connection = self.connection.lock().unwrap();
+        let mut select = connection
+            .prepare("SELECT data FROM programmes WHERE source = ?1")
+            .map_err(failed)?;
+        let rows = select
+            .query_map(params![source], |row| row.get::<_, String>(0))
+            .map_err(failed)?;
+        let mut programmes = Vec::new();
+        for data in rows {
+            programmes.push(parse(&data.map_err(failed)?)?);
+        }
+        Ok(programmes)
+    }
+
+    // A channel's programmes overlapping [from, to) with the source of
+    // each, by start time
+    pub fn window(&self, channel: &str, from: i64, to: i64) -> Result<Vec<(String, Programme)>, String> {
+        let connection = self.connection.lock().unwrap();
+        let mut select = connection
+            .prepare_cached(
+                "SELECT source, data FROM programmes
+                 WHERE channel = ?1 AND start < ?2 AND stop > ?3
+                 ORDER BY start",
+            )
+            .map_err(failed)?;
+        let rows = select
+            .query_map(params![channel, to, from], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(failed)?;
+        let mut found = Vec::new();
+        for row in rows {
+            let (source, data) = row.map_err(failed)?;
+            found.push((source, parse(&data)?));
+        }
+        Ok(found)
+    }
+
+    // Programmes overlapping [from, to) that the full-text query `matching`
+    // finds, with the source of each, by start time
+    pub fn search(&self, matching: &str, from: i64, to: i64) -> Result<Vec<(String, Programme)>, String> {
+        let connection = self.connection.lock().unwrap();
+        let mut select = connection
+            .prepare_cached(
+                "SELECT programmes.source, programmes.data FROM programme_words
+                 JOIN programmes ON programmes.id = programme_words.rowid
+                 WHERE programme_words MATCH ?1 AND programmes.start < ?2 AND programmes.stop > ?3
+                 ORDER BY programmes.start",
+            )
+            .map_err(failed)?;
+        let rows = select
+            .query_map(params![matching, to, from], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(failed)?;
+        let mut found = Vec::new();
+        for row in rows {
+            let (source, data) = row.map_err(failed)?;
+            found.push((source, parse(&data)?));
+        }
+        Ok(found)
+    }
+
+    // Bytes of programme data a source holds
+    pub fn size(&self, source: &str) -> u64 {
+        let connection = self.connection.lock().unwrap();
+        connection
+            .query_row(
+                "SELECT COALESCE(SUM(LENGTH(data)), 0) FROM programmes WHERE source = ?1",
+                params![source],
+                |row| row.get::<_, i64>(0),
+            )
+            .map_or(0, |bytes| bytes as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    fn programme(channel: &str, start: i64) -> Programme {
+        Programme {
+            channel: channel.to_string(),
+            start,
+            stop: start + 100,
+            title: format!("{}@{}", channel, start),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_windows_by_channel_and_time() {
+        // Test that windows only hold the channel's programmes overlapping them and replacing a source drops its old ones
+        let path = temp_dir("epg-database").join("guide.db");
+        let database = GuideDatabase::open(&path);
+        database.replace("s1", &[programme("a", 200), programme("b", 100), programme("a", 0)]).unwrap();
+        database.replace("s2", &[programme("a", 100)]).unwrap();
+        let window = |database: &GuideDatabase, from, to| -> Vec<(String, i64)> {
+            let found = database.window("a", from, to).unwrap();
+            found.into_iter().map(|(source, programme)| (source, programme.start)).collect()
+        };
+        assert_eq!(window(&database, 150, 201), vec![("s2".to_string(), 100), ("s1".to_string(), 200)]);
+        assert!(window(&database, 500, 400).is_empty());
+
+        database.replace("s1", &[programme("b", 0)]).unwrap();
+        let reopened = GuideDatabase::open(&path);
+        assert_eq!(window(&reopened, 0, 1000), vec![("s2".to_string(), 100)]);
+        assert_eq!(reopened.programmes("s1").unwrap(), vec![programme("b", 0)]);
+        assert!(reopened.size("s1") > 0);
+        reopened.remove("s2").unwrap();
+        assert!(window(&reopened, 0, 1000).is_empty() && reopened.size("s2") == 0);
+    }
+
+    #[test]
+    fn test_search_words_and_genres() {
+        // Test that searches find words by prefix in any case and accent, and genres, only in the time range
+        let database = GuideDatabase::open(&temp_dir("epg-database-search").join("guide.db"));
+        let mut first = programme("a", 0);
+        first.title = "UEFA Champions League".to_string();
+        first.categories = vec!["Sports".to_string()];
+        let mut second = programme("b", 500);
+        second.description = Some("Les Équipes de la Ligue".to_string());
+        database.replace("s1", &[first, second, programme("c", 0)]).unwrap();
+        let starts = |matching: &str, to| -> Vec<i64> {
+            let found = database.search(matching, 0, to).unwrap();
+            found.into_iter().map(|(_, programme)| programme.start).collect()
+        };
+        assert_eq!(starts("words : (\"champ\"* \"LEAGUE\"*)", 1000), vec![0]);
+        assert_eq!(starts("words : (\"equipes\"*)", 1000), vec![500]);
+        assert!(starts("words : (\"equipes\"*)", 400).is_empty());
+        assert_eq!(starts("genres : (\"sports\")", 1000), vec![0]);
+        database.remove("s1").unwrap();
+        assert!(starts("words : (\"champ\"*)", 1000).is_empty());
+    }
+}
@@ -0,0 +1,203 @@
+// Writing the curated guide back out as XMLTV for other players (Kodi,
+// Jellyfin). Channels are the playlist's, under the tvg-id the playlist
+// gives them so the other player matches them the same way; programmes are
+// the merged, time-corrected ones of the guide channel each is matched to
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+use quick_xml::escape::escape;
+
+use super::matching::Matcher;
+use super::time::utc_datetime;
+use super::{merged_window, GuideIndex, LocalizedText, TimeOffsets};
+use crate::playlist::ChannelEntry;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportChannel {
+    // XMLTV channel id written out
+    pub id: String,
+    pub name: String,
+    pub icon: Option<String>,
+    pub epg_channel_id: String,
+}
+
+// The matched channels among `entries`, limited to `channel_ids` unless
+// it's empty; each output id is written once
+pub fn export_channels(
+    entries: &[ChannelEntry],
+    matcher: &Matcher,
+    manual: &BTreeMap<String, String>,
+    threshold: f64,
+    channel_ids: &[String],
+) -> Vec<ExportChannel> {
+    let wanted: HashSet<&str> = channel_ids.iter().map(String::as_str).collect();
+    let mut seen = HashSet::new();
+    let mut channels = Vec::new();
+    for entry in entries {
+        if !wanted.is_empty() && !wanted.contains(entry.channel_id.as_str()) {
+            continue;
+        }
+        let manual = manual.get(&entry.channel_id).map(String::as_str);
+        let Ok(found) = matcher.find(entry, manual, threshold) else {
+            continue;
+        };
+        let id = entry
+            .tvg_id
+            .as_deref()
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .unwrap_or(&found.epg_channel_id)
+            .to_string();
+        if seen.insert(id.clone()) {
+            channels.push(ExportChannel {
+                id,
+                name: entry.name.clone(),
+                icon: entry.tvg_logo.clone(),
+                epg_channel_id: found.epg_channel_id,
+            });
+        }
+    }
+    channels
+}
+
+fn xmltv_time(timestamp: i64) -> String {
+    let time = utc_datetime(timestamp);
+    format!(
+        "{:04}{:02}{:02}{:02}{:02}{:02} +0000",
+        time.year, time.month, time.day, time.hour, time.minute, time.second
+    )
+}
+
+// One element per language, or the text alone when there's only one
+fn push_localized(xml: &mut String, element: &str, text: Option<&str>, texts: &[LocalizedText]) {
+    if texts.is_empty() {
+        if let Some(text) = text {
+            xml.push_str(&format!("    <{0}>{1}</{0}>\n", element, escape(text)));
+        }
+        return;
+    }
+    for text in texts {
+        let lang = match &text.lang {
+            Some(lang) => format!(" lang=\"{}\"", escape(lang)),
+            None => String::new(),
+        };
+        xml.push_str(&format!("    <{0}{1}>{2}</{0}>\n", element, lang, escape(&text.text)));
+    }
+}
+
+// The XMLTV document and the number of programmes in it
+pub fn to_xmltv(
+    guides: &[(String, Arc<GuideIndex>)],
+    offsets: &TimeOffsets,
+    channels: &[ExportChannel],
+) -> (String, usize) {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<!DOCTYPE tv SYSTEM \"xmltv.dtd\">\n<tv generator-info-name=\"TIPTV\">\n");
+    for channel in channels {
+        xml.push_str(&format!("  <channel id=\"{}\">\n", escape(&channel.id)));
+        xml.push_str(&format!("    <display-name>{}</display-name>\n", escape(&channel.name)));
+        if let Some(icon) = &channel.icon {
+            xml.push_str(&format!("    <icon src=\"{}\" />\n", escape(icon)));
+        }
+        xml.push_str("  </channel>\n");
+    }
+    let mut total = 0;
+    for channel in channels {
+        for programme in merged_window(guides, offsets, &channel.epg_channel_id, i64::MIN, i64::MAX) {
+            total += 1;
+            xml.push_str(&format!(
+                "  <programme start=\"{}\" stop=\"{}\" channel=\"{}\">\n",
+                xmltv_time(programme.start),
+                xmltv_time(programme.stop),
+                escape(&channel.id)
+            ));
+            push_localized(&mut xml, "title", Some(&programme.title), &programme.titles);
+            push_localized(&mut xml, "desc", programme.description.as_deref(), &programme.descriptions);
+            for category in &programme.categories {
+                xml.push_str(&format!("    <category>{}</category>\n", escape(category)));
+            }
+            for rating in &programme.ratings {
+                match &rating.system {
+                    Some(system) => xml.push_str(&format!("    <rating system=\"{}\">\n", escape(system))),
+                    None => xml.push_str("    <rating>\n"),
+                }
+                xml.push_str(&format!("      <value>{}</value>\n    </rating>\n", escape(&rating.value)));
+            }
+            if let Some(icon) = &programme.icon {
+                xml.push_str(&format!("    <icon src=\"{}\" />\n", escape(icon)));
+            }
+            if programme.premiere {
+                xml.push_str("    <new />\n");
+            }
+            xml.push_str("  </programme>\n");
+        }
+    }
+    xml.push_str("</tv>\n");
+    (xml, total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epg::xmltv::read_guide;
+    use crate::epg::{EpgChannel, Programme};
+
+    #[test]
+    fn test_export_reads_back() {
+        // Test that the export uses playlist ids, shifts times and reads back as the same guide
+        let guide = GuideIndex::new(vec![Programme {
+            channel: "one.uk".to_string(),
+            start: 1_704_103_200,
+            stop: 1_704_106_800,
+            title: "News & Weather".to_string(),
+            description: Some("<Headlines>".to_string()),
+            categories: vec!["News".to_string()],
+            premiere: true,
+            titles: ["en", "cy"]
+                .map(|lang| LocalizedText {
+                    lang: Some(lang.to_string()),
+                    text: format!("News & Weather ({})", lang),
+                })
+                .to_vec(),
+            ..Default::default()
+        }]);
+        let guides = vec![("s".to_string(), Arc::new(guide))];
+        let mut offsets = TimeOffsets::default();
+        offsets.sources.insert("s".to_string(), 3600);
+        let matcher = Matcher::new(vec![(
+            "s".to_string(),
+            EpgChannel {
+                id: "one.uk".to_string(),
+                names: vec!["One".to_string()],
+                icon: None,
+            },
+        )]);
+        let entries: Vec<ChannelEntry> = ["One HD", "One", "Other"]
+            .iter()
+            .map(|name| ChannelEntry {
+                channel_id: format!("p:{}", name),
+                name: name.to_string(),
+                tvg_id: Some("one-playlist".to_string()),
+                tvg_logo: Some("http://logos/one.png?a=1&b=2".to_string()),
+                ..Default::default()
+            })
+            .collect();
+
+        let channels = export_channels(&entries, &matcher, &BTreeMap::new(), 0.9, &[]);
+        assert_eq!(channels.len(), 1);
+        let (xml, total) = to_xmltv(&guides, &offsets, &channels);
+        assert_eq!(total, 1);
+
+        let read = tauri::async_runtime::block_on(read_guide(xml.as_bytes())).unwrap();
+        assert_eq!(read.channels[0].id, "one-playlist");
+        assert_eq!(read.channels[0].names, vec!["One HD"]);
+        assert_eq!(read.channels[0].icon.as_deref(), Some("http://logos/one.png?a=1&b=2"));
+        let programme = &read.programmes[0];
+        assert_eq!((programme.channel.as_str(), programme.start), ("one-playlist", 1_704_106_800));
+        assert_eq!(programme.title, "News & Weather (en)");
+        assert_eq!(programme.titles[1].lang.as_deref(), Some("cy"));
+        assert_eq!(programme.description.as_deref(), Some("<Headlines>"));
+        assert!(programme.premiere && programme.categories == vec!["News"]);
+        assert!(export_channels(&entries, &matcher, &BTreeMap::new(), 0.9, &["p:Other".to_string()]).is_empty());
+    }
+}
@@ -0,0 +1,324 @@
+// Guides name genres however they like ("Sport", "Football", "Sports
+// news", "Nachrichten"), so stored programmes also carry a fixed genre set
+// worked out when the guide is stored. The built-in table matches words in
+// the category; users can pin a category to another genre, which rewrites
+// the stored guides
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+
+use super::{EpgStore, GuideIndex, Programme, GENRES_FILE};
+use crate::{storage, validate_string_length};
+
+const MAX_OVERRIDES: usize = 1000;
+const MAX_CATEGORY_LENGTH: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Genre {
+    Movies,
+    Series,
+    News,
+    Sports,
+    Kids,
+    Documentary,
+    Music,
+    Education,
+    Lifestyle,
+    Entertainment,
+}
+
+impl Genre {
+    // The name used in JSON
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Genre::Movies => "movies",
+            Genre::Series => "series",
+            Genre::News => "news",
+            Genre::Sports => "sports",
+            Genre::Kids => "kids",
+            Genre::Documentary => "documentary",
+            Genre::Music => "music",
+            Genre::Education => "education",
+            Genre::Lifestyle => "lifestyle",
+            Genre::Entertainment => "entertainment",
+        }
+    }
+}
+
+// Words looked for in lowercased categories, in order, so "Children's
+// drama" is Kids and "Documentary film" is Documentary
+const TABLE: &[(&str, Genre)] = &[
+    ("kid", Genre::Kids),
+    ("child", Genre::Kids),
+    ("cartoon", Genre::Kids),
+    ("animation", Genre::Kids),
+    ("kinder", Genre::Kids),
+    ("enfant", Genre::Kids),
+    ("infantil", Genre::Kids),
+    ("sport", Genre::Sports),
+    ("football", Genre::Sports),
+    ("soccer", Genre::Sports),
+    ("fussball", Genre::Sports),
+    ("fußball", Genre::Sports),
+    ("futbol", Genre::Sports),
+    ("tennis", Genre::Sports),
+    ("golf", Genre::Sports),
+    ("cricket", Genre::Sports),
+    ("rugby", Genre::Sports),
+    ("hockey", Genre::Sports),
+    ("basketball", Genre::Sports),
+    ("motor", Genre::Sports),
+    ("racing", Genre::Sports),
+    ("news", Genre::News),
+    ("nachrichten", Genre::News),
+    ("noticias", Genre::News),
+    ("actualit", Genre::News),
+    ("notizie", Genre::News),
+    ("current affairs", Genre::News),
+    ("politic", Genre::News),
+    ("weather", Genre::News),
+    ("documentar", Genre::Documentary),
+    ("docu", Genre::Documentary),
+    ("doku", Genre::Documentary),
+    ("nature", Genre::Documentary),
+    ("history", Genre::Documentary),
+    ("science", Genre::Documentary),
+    ("music", Genre::Music),
+    ("musik", Genre::Music),
+    ("musique", Genre::Music),
+    ("concert", Genre::Music),
+    ("education", Genre::Education),
+    ("learning", Genre::Education),
+    ("bildung", Genre::Education),
+    ("lifestyle", Genre::Lifestyle),
+    ("cook", Genre::Lifestyle),
+    ("food", Genre::Lifestyle),
+    ("travel", Genre::Lifestyle),
+    ("garden", Genre::Lifestyle),
+    ("fashion", Genre::Lifestyle),
+    ("health", Genre::Lifestyle),
+    ("medic", Genre::Lifestyle),
+    ("home", Genre::Lifestyle),
+    ("movie", Genre::Movies),
+    ("film", Genre::Movies),
+    ("cinema", Genre::Movies),
+    ("kino", Genre::Movies),
+    ("cine", Genre::Movies),
+    ("thriller", Genre::Movies),
+    ("horror", Genre::Movies),
+    ("western", Genre::Movies),
+    ("series", Genre::Series),
+    ("serie", Genre::Series),
+    ("drama", Genre::Series),
+    ("soap", Genre::Series),
+    ("sitcom", Genre::Series),
+    ("episode", Genre::Series),
+    ("entertainment", Genre::Entertainment),
+    ("unterhaltung", Genre::Entertainment),
+    ("divertissement", Genre::Entertainment),
+    ("comedy", Genre::Entertainment),
+    ("game show", Genre::Entertainment),
+    ("quiz", Genre::Entertainment),
+    ("reality", Genre::Entertainment),
+    ("talk", Genre::Entertainment),
+    ("show", Genre::Entertainment),
+    ("variety", Genre::Entertainment),
+];
+
+// User overrides are keyed by the trimmed, lowercased category
+fn category_key(category: &str) -> String {
+    category.trim().to_lowercase()
+}
+
+pub fn genre_of(category: &str, overrides: &BTreeMap<String, Genre>) -> Option<Genre> {
+    let key = category_key(category);
+    if let Some(genre) = overrides.get(&key) {
+        return Some(*genre);
+    }
+    TABLE.iter().find(|(word, _)| key.contains(word)).map(|(_, genre)| *genre)
+}
+
+// The genres of a programme's categories, each once, in category order
+pub fn normalize(categories: &[String], overrides: &BTreeMap<String, Genre>) -> Vec<Genre> {
+    let mut genres = Vec::new();
+    for genre in categories.iter().filter_map(|category| genre_of(category, overrides)) {
+        if !genres.contains(&genre) {
+            genres.push(genre);
+        }
+    }
+    genres
+}
+
+// A category the stored guides use and the genre it maps to
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CategoryGenre {
+    pub category: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub genre: Option<Genre>,
+    // Whether a user override decided the genre
+    pub overridden: bool,
+    pub programmes: usize,
+}
+
+impl EpgStore {
+    pub fn genre_overrides(&self) -> BTreeMap<String, Genre> {
+        self.genre_overrides.lock().unwrap().clone()
+    }
+
+    // Pin a category to a genre, or back to the built-in table with None;
+    // returns how many stored programmes changed genre
+    pub fn set_genre_override(&self, category: &str, genre: Option<Genre>) -> Result<usize, String> {
+        validate_string_length(category, MAX_CATEGORY_LENGTH)?;
+        let key = category_key(category);
+        if key.is_empty() {
+            return Err("Category cannot be empty".to_string());
+        }
+        let overrides = {
+            let _writing = self.writing.lock().unwrap();
+            let mut overrides = self.genre_overrides.lock().unwrap();
+            let mut updated = overrides.clone();
+            match genre {
+                Some(genre) => updated.insert(key, genre),
+                None => updated.remove(&key),
+            };
+            if updated.len() > MAX_OVERRIDES {
+                return Err(format!("At most {} categories can be overridden", MAX_OVERRIDES));
+            }
+            storage::write_json(&self.dir.join(GENRES_FILE), &updated)?;
+            *overrides = updated.clone();
+            updated
+        };
+        self.renormalize(&overrides)
+    }
+
+    // Work genres out again in every stored guide
+    fn renormalize(&self, overrides: &BTreeMap<String, Genre>) -> Result<usize, String> {
+        let mut changed = 0;
+        for source in self.sources() {
+            let guide = match self.guide(&source.id) {
+                Ok(guide) => guide,
+                Err(e) => {
+                    log::warn!("Skipping EPG source '{}' while updating genres: {}", source.id, e);
+                    continue;
+                }
+            };
+            let mut updated = 0;
+            let programmes: Vec<Programme> = guide
+                .programmes()
+                .iter()
+                .map(|programme| {
+                    let genres = normalize(&programme.categories, overrides);
+                    if genres != programme.genres {
+                        updated += 1;
+                    }
+                    Programme {
+                        genres,
+                        ..programme.clone()
+                    }
+                })
+                .collect();
+            if updated == 0 {
+                continue;
+            }
+            let _writing = self.writing.lock().unwrap();
+            // A new download replaced the guide meanwhile, already with these genres
+            let current = self.loaded.lock().unwrap().get(&source.id).cloned();
+            if !current.is_some_and(|current| Arc::ptr_eq(&current, &guide)) {
+                continue;
+            }
+            changed += updated;
+            self.store_index(&source.id, GuideIndex::new(programmes), source.updated_at)?;
+        }
+        Ok(changed)
+    }
+
+    // Every category in the stored guides, most used first
+    pub fn categories(&self) -> Vec<CategoryGenre> {
+        let overrides = self.genre_overrides();
+        let mut counts: HashMap<String, (String, usize)> = HashMap::new();
+        for (_, guide) in self.guides() {
+            for category in guide.programmes().iter().flat_map(|programme| &programme.categories) {
+                let entry = counts.entry(category_key(category)).or_insert_with(|| (category.clone(), 0));
+                entry.1 += 1;
+            }
+        }
+        let mut categories: Vec<CategoryGenre> = counts
+            .into_iter()
+            .map(|(key, (category, programmes))| CategoryGenre {
+                genre: genre_of(&category, &overrides),
+                overridden: overrides.contains_key(&key),
+                category,
+                programmes,
+            })
+            .collect();
+        categories.sort_by(|a, b| b.programmes.cmp(&a.programmes).then_with(|| a.category.cmp(&b.category)));
+        categories
+    }
+}
+
+// Command handler listing the guides' categories with their genres
+#[tauri::command]
+pub async fn list_epg_categories(app: AppHandle) -> Result<Vec<CategoryGenre>, String> {
+    tauri::async_runtime::spawn_blocking(move || app.state::<EpgStore>().categories())
+        .await
+        .map_err(|e| format!("Failed to list categories: {}", e))
+}
+
+// Command handler returning the user's category -> genre overrides
+#[tauri::command]
+pub fn get_epg_genre_overrides(store: State<'_, EpgStore>) -> BTreeMap<String, Genre> {
+    store.genre_overrides()
+}
+
+// Command handler pinning a category to a genre (None restores the
+// built-in mapping); returns how many programmes changed genre
+#[tauri::command]
+pub async fn set_epg_genre_override(app: AppHandle, category: String, genre: Option<Genre>) -> Result<usize, String> {
+    tauri::async_runtime::spawn_blocking(move || app.state::<EpgStore>().set_genre_override(&category, genre))
+        .await
+        .map_err(|e| format!("Failed to update genres: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epg::Guide;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn test_genres_normalized_with_overrides() {
+        // Test that categories map through the table at ingest and overrides rewrite stored guides
+        let categories = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        let none = BTreeMap::new();
+        assert_eq!(normalize(&categories(&["Children's drama", "Kids"]), &none), vec![Genre::Kids]);
+        assert_eq!(
+            normalize(&categories(&["Fußball", "Sports news", "Nachrichten"]), &none),
+            vec![Genre::Sports, Genre::News]
+        );
+        assert_eq!(normalize(&categories(&["Documentary film", "Gibberish"]), &none), vec![Genre::Documentary]);
+
+        let store = EpgStore::open(temp_dir("epg-genres"));
+        let programme = Programme {
+            channel: "a".to_string(),
+            start: 0,
+            stop: 100,
+            title: "Tonight".to_string(),
+            categories: categories(&["Magazine"]),
+            ..Default::default()
+        };
+        store.replace("s", Guide::from(vec![programme]), 0).unwrap();
+        assert!(store.guide("s").unwrap().programmes()[0].genres.is_empty());
+
+        assert_eq!(store.set_genre_override(" MAGAZINE ", Some(Genre::Lifestyle)).unwrap(), 1);
+        assert_eq!(store.guide("s").unwrap().programmes()[0].genres, vec![Genre::Lifestyle]);
+        let listed = store.categories();
+        assert_eq!((listed[0].genre, listed[0].overridden, listed[0].programmes), (Some(Genre::Lifestyle), true, 1));
+        assert!(store.set_genre_override(" ", None).is_err());
+        assert_eq!(store.set_genre_override("magazine", None).unwrap(), 1);
+        assert!(store.genre_overrides().is_empty());
+    }
+}
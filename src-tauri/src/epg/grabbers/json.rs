@@ -0,0 +1,245 @@
+// Grabber for JSON guide APIs. The source URL is a template: {channel} is
+// replaced by the channel's id and {date} by a day (YYYY-MM-DD, UTC), and
+// one request is made per channel and day the template names. Answers are
+// a list of programmes, or an object holding one under "programmes",
+// "programs" or "events"; times are Unix seconds (or milliseconds), ISO
+// 8601 or XMLTV times
+use futures_util::future::BoxFuture;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::{GrabChannel, Grabber, GrabberSource};
+use crate::de;
+use crate::epg::time::{parse_datetime, parse_xmltv, utc_datetime};
+use crate::epg::{EpgChannel, Guide, Programme};
+use crate::http;
+
+// Most requests one grab makes
+const MAX_REQUESTS: usize = 1000;
+
+// Numbers above this are milliseconds
+const MILLISECONDS_FROM: i64 = 100_000_000_000;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct JsonProgramme {
+    #[serde(alias = "channel_id", alias = "channelId", deserialize_with = "de::string")]
+    channel: String,
+    start: Value,
+    #[serde(alias = "end")]
+    stop: Value,
+    #[serde(alias = "name", deserialize_with = "de::string")]
+    title: String,
+    #[serde(alias = "desc", deserialize_with = "de::opt_string")]
+    description: Option<String>,
+    #[serde(alias = "category", alias = "genre", deserialize_with = "de::string_list")]
+    categories: Vec<String>,
+    #[serde(alias = "image", deserialize_with = "de::opt_string")]
+    icon: Option<String>,
+}
+
+// Seconds east of UTC of "Z", "+01:00" or "-0500"
+fn zone_offset(zone: &str) -> Option<i64> {
+    let zone = zone.trim();
+    if zone.is_empty() || zone.eq_ignore_ascii_case("z") {
+        return Some(0);
+    }
+    let sign = match zone.as_bytes()[0] {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let digits: String = zone[1..].chars().filter(|c| *c != ':').collect();
+    if digits.len() != 4 || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i64 = digits[..2].parse().ok()?;
+    let minutes: i64 = digits[2..].parse().ok()?;
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+fn json_time(value: &Value) -> Option<i64> {
+    if let Some(number) = de::integer(value).filter(|_| !value.is_boolean()) {
+        return Some(if number.abs() >= MILLISECONDS_FROM { number / 1000 } else { number });
+    }
+    let text = de::text(value)?;
+    if !text.contains('-') || text.len() < 10 {
+        return parse_xmltv(&text);
+    }
+    // ISO 8601: the local time, maybe fractional seconds, then the zone
+    let split = text.get(10..).and_then(|rest| rest.find(['Z', 'z', '+', '-'])).map_or(text.len(), |at| at + 10);
+    let (local, zone) = text.split_at(split);
+    let local = local.split('.').next().unwrap_or(local);
+    Some(parse_datetime(local)? - zone_offset(zone)?)
+}
+
+fn programmes(answer: Value) -> Vec<JsonProgramme> {
+    match answer {
+        Value::Object(mut object) => ["programmes", "programs", "events"]
+            .iter()
+            .find_map(|key| object.remove(*key))
+            .map(de::list)
+            .unwrap_or_default(),
+        list => de::list(list),
+    }
+}
+
+fn date(timestamp: i64) -> String {
+    let day = utc_datetime(timestamp);
+    format!("{:04}-{:02}-{:02}", day.year, day.month, day.day)
+}
+
+// A URL to fetch and the channel its programmes default to
+type Request = (String, Option<String>);
+
+fn requests(template: &str, channels: &[GrabChannel], from: i64, to: i64) -> Result<Vec<Request>, String> {
+    let days: Vec<String> = if template.contains("{date}") {
+        (from.div_euclid(86_400)..=(to - 1).div_euclid(86_400)).map(|day| date(day * 86_400)).collect()
+    } else {
+        vec![String::new()]
+    };
+    let channels: Vec<Option<&GrabChannel>> = if template.contains("{channel}") {
+        channels.iter().map(Some).collect()
+    } else {
+        vec![None]
+    };
+    if days.len() * channels.len() > MAX_REQUESTS {
+        return Err(format!("That guide would take more than {} requests", MAX_REQUESTS));
+    }
+    let mut urls = Vec::new();
+    for channel in &channels {
+        for day in &days {
+            let mut url = template.replace("{date}", day);
+            if let Some(channel) = channel {
+                url = url.replace("{channel}", &utf8_percent_encode(&channel.id, NON_ALPHANUMERIC).to_string());
+            }
+            urls.push((url, channel.map(|channel| channel.id.clone())));
+        }
+    }
+    Ok(urls)
+}
+
+async fn fetch(url: &str) -> Result<Value, String> {
+    let response = http::get(url, None)
+        .send()
+        .await
+        .map_err(|e| format!("Guide request failed: {}", e.without_url()))?;
+    if !response.status().is_success() {
+        return Err(format!("Guide request failed with HTTP {}", response.status().as_u16()));
+    }
+    let body = response
+        .bytes()
+        .await
+        .map_err(|e| format!("Guide request failed: {}", e.without_url()))?;
+    serde_json::from_slice(&body).map_err(|e| format!("Invalid guide answer: {}", e))
+}
+
+pub struct JsonApiGrabber;
+
+impl JsonApiGrabber {
+    async fn guide(source: &GrabberSource, channels: &[GrabChannel], from: i64, to: i64) -> Result<Guide, String> {
+        let mut found = Vec::new();
+        for (url, channel) in requests(&source.url, channels, from, to)? {
+            for item in programmes(fetch(&url).await?) {
+                let channel = match item.channel.as_str() {
+                    "" => channel.clone().unwrap_or_default(),
+                    id => id.to_string(),
+                };
+                let (Some(start), Some(stop)) = (json_time(&item.start), json_time(&item.stop)) else {
+                    continue;
+                };
+                if channel.is_empty() || item.title.is_empty() || stop <= start || stop <= from || start >= to {
+                    continue;
+                }
+                found.push(Programme {
+                    channel,
+                    start,
+                    stop,
+                    title: item.title,
+                    description: item.description,
+                    categories: item.categories,
+                    icon: item.icon,
+                    ..Default::default()
+                });
+            }
+        }
+        Ok(Guide {
+            channels: channels
+                .iter()
+                .map(|channel| EpgChannel {
+                    id: channel.id.clone(),
+                    names: vec![channel.name.clone()],
+                    icon: None,
+                })
+                .collect(),
+            programmes: found,
+        })
+    }
+}
+
+impl Grabber for JsonApiGrabber {
+    fn kind(&self) -> &'static str {
+        "json"
+    }
+
+    fn name(&self) -> &'static str {
+        "JSON guide API"
+    }
+
+    fn grab<'a>(
+        &'a self,
+        source: &'a GrabberSource,
+        channels: &'a [GrabChannel],
+        from: i64,
+        to: i64,
+    ) -> BoxFuture<'a, Result<Guide, String>> {
+        Box::pin(Self::guide(source, channels, from, to))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{response, serve};
+
+    #[test]
+    fn test_json_api_grab() {
+        // Test that the template is filled per channel and day and both answer shapes and time formats are read
+        let (base, served) = serve(vec![
+            response(
+                "200 OK",
+                &[("Content-Type", "application/json")],
+                r#"{"programmes": [{"start": 1704103200, "stop": "2024-01-01T12:00:00Z", "title": "News",
+                    "category": "News"}, {"start": 1704103200000, "title": "No stop"}]}"#,
+            ),
+            response(
+                "200 OK",
+                &[("Content-Type", "application/json")],
+                r#"[{"channel": "two", "start": "2024-01-01T13:00:00+01:00", "end": "20240101140000 +0000",
+                    "name": "Film", "desc": "Late"}]"#,
+            ),
+        ]);
+        let source = GrabberSource {
+            url: format!("{}/epg/{{channel}}/{{date}}.json", base),
+            ..Default::default()
+        };
+        let channels = [("One HD", "one hd"), ("two", "Two")].map(|(id, name)| GrabChannel {
+            id: id.to_string(),
+            name: name.to_string(),
+        });
+        let from = 1_704_067_200;
+        let grab = JsonApiGrabber.grab(&source, &channels, from, from + 86_400);
+        let guide = tauri::async_runtime::block_on(grab).unwrap();
+
+        assert!(served.lock().unwrap()[0].starts_with("GET /epg/One%20HD/2024-01-01.json"));
+        assert_eq!(guide.channels[1].names, vec!["Two"]);
+        let programmes = &guide.programmes;
+        assert_eq!(programmes.len(), 2);
+        assert_eq!((programmes[0].channel.as_str(), programmes[0].stop), ("One HD", 1_704_110_400));
+        assert_eq!(programmes[0].categories, vec!["News"]);
+        assert_eq!((programmes[1].start, programmes[1].stop), (1_704_110_400, 1_704_117_600));
+        assert_eq!(programmes[1].description.as_deref(), Some("Late"));
+        assert_eq!(requests(&source.url, &channels, from, from + 2 * 86_400).unwrap().len(), 4);
+    }
+}
@@ -0,0 +1,361 @@
+// Guide sources other than XMLTV files. A grabber knows how to fetch
+// programmes for a list of channels from one kind of service; what the user
+// set up for it (the address, the playlist groups it covers) is a grabber
+// source. Grabbed guides are stored like any other and take part in
+// matching, merging and priorities. Further grabbers (a country's listings
+// site, another API) are structs implementing Grabber listed in GRABBERS
+mod json;
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use super::{EpgSource, EpgStore, Guide};
+use crate::playlist::{content_hash, ChannelEntry, PlaylistStore};
+use crate::{storage, unix_now, validate_string_length};
+
+const SOURCES_FILE: &str = "grabber_sources.json";
+
+// Limits for user-entered source details
+const MAX_URL_LENGTH: usize = 2048;
+const MAX_NAME_LENGTH: usize = 200;
+const MAX_GROUPS: usize = 100;
+
+// Days of guide grabbed when the source sets none, and the most allowed
+const DEFAULT_DAYS: u32 = 3;
+const MAX_DAYS: u32 = 14;
+
+// How often the refresh job looks for sources that are due
+const CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+// Seconds between refreshes, and before a failed one is tried again
+const REFRESH_INTERVAL: i64 = 12 * 60 * 60;
+const RETRY_INTERVAL: i64 = 60 * 60;
+
+// Hours of the past grabbed, so what's on now has its start
+const PAST_SECONDS: i64 = 6 * 60 * 60;
+
+pub trait Grabber: Send + Sync {
+    // Stored in sources to name the grabber
+    fn kind(&self) -> &'static str;
+
+    fn name(&self) -> &'static str;
+
+    // Programmes of `channels` overlapping [from, to); programmes name
+    // their channel by GrabChannel::id
+    fn grab<'a>(
+        &'a self,
+        source: &'a GrabberSource,
+        channels: &'a [GrabChannel],
+        from: i64,
+        to: i64,
+    ) -> BoxFuture<'a, Result<Guide, String>>;
+}
+
+static GRABBERS: &[&dyn Grabber] = &[&json::JsonApiGrabber];
+
+pub fn grabber(kind: &str) -> Result<&'static dyn Grabber, String> {
+    GRABBERS
+        .iter()
+        .copied()
+        .find(|grabber| grabber.kind() == kind)
+        .ok_or_else(|| format!("Unknown guide grabber '{}'", kind))
+}
+
+// A playlist channel asked for, by its tvg-id or else its name
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrabChannel {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GrabberSource {
+    pub id: String,
+    pub name: String,
+    // Grabber::kind
+    pub kind: String,
+    // Service address, as the grabber reads it
+    pub url: String,
+    // The channels of these groups of the playlist are grabbed
+    pub playlist_id: String,
+    pub groups: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub days: Option<u32>,
+}
+
+impl GrabberSource {
+    // EPG store id of the source's guide
+    pub fn source_id(&self) -> String {
+        source_id(&self.id)
+    }
+}
+
+fn source_id(id: &str) -> String {
+    format!("grabber:{}", id)
+}
+
+// The channels of `groups` among a playlist's, each id once
+pub fn group_channels(entries: &[ChannelEntry], groups: &[String]) -> Vec<GrabChannel> {
+    let groups: HashSet<String> = groups.iter().map(|group| group.trim().to_lowercase()).collect();
+    let mut seen = HashSet::new();
+    entries
+        .iter()
+        .filter(|entry| {
+            let group = entry.group_title.as_deref().unwrap_or_default();
+            groups.contains(&group.trim().to_lowercase())
+        })
+        .filter_map(|entry| {
+            let id = entry
+                .tvg_id
+                .as_deref()
+                .map(str::trim)
+                .filter(|id| !id.is_empty())
+                .unwrap_or(entry.name.trim());
+            seen.insert(id.to_string()).then(|| GrabChannel {
+                id: id.to_string(),
+                name: entry.name.trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+pub struct GrabberSources {
+    path: PathBuf,
+    sources: Mutex<Vec<GrabberSource>>,
+}
+
+impl GrabberSources {
+    pub fn open(data_dir: &Path) -> Self {
+        let path = data_dir.join(SOURCES_FILE);
+        let sources = storage::read_json(&path).unwrap_or_else(|e| {
+            log::error!("Failed to load guide grabber sources: {}", e);
+            Vec::new()
+        });
+        Self {
+            path,
+            sources: Mutex::new(sources),
+        }
+    }
+
+    pub fn list(&self) -> Vec<GrabberSource> {
+        self.sources.lock().unwrap().clone()
+    }
+
+    pub fn get(&self, id: &str) -> Result<GrabberSource, String> {
+        self.sources
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|s| s.id == id)
+            .cloned()
+            .ok_or_else(|| format!("Guide grabber source '{}' doesn't exist", id))
+    }
+
+    pub fn upsert(&self, source: GrabberSource) -> Result<(), String> {
+        let mut sources = self.sources.lock().unwrap();
+        match sources.iter_mut().find(|s| s.id == source.id) {
+            Some(existing) => *existing = source,
+            None => sources.push(source),
+        }
+        storage::write_json(&self.path, &*sources)
+    }
+
+    pub fn remove(&self, id: &str) -> Result<bool, String> {
+        let mut sources = self.sources.lock().unwrap();
+        let before = sources.len();
+        sources.retain(|s| s.id != id);
+        if sources.len() == before {
+            return Ok(false);
+        }
+        storage::write_json(&self.path, &*sources)?;
+        Ok(true)
+    }
+}
+
+// Grab a source's guide for its groups' channels and swap it in for the
+// stored one
+async fn import(app: &AppHandle, source: &GrabberSource) -> Result<EpgSource, String> {
+    let grabber = grabber(&source.kind)?;
+    let entries = app
+        .state::<PlaylistStore>()
+        .get(&source.playlist_id)
+        .ok_or_else(|| format!("Playlist '{}' is not loaded", source.playlist_id))?;
+    let channels = group_channels(&entries, &source.groups);
+    if channels.is_empty() {
+        return Err("The chosen groups have no channels".to_string());
+    }
+    let now = unix_now();
+    let days = source.days.unwrap_or(DEFAULT_DAYS).clamp(1, MAX_DAYS);
+    let guide = grabber
+        .grab(source, &channels, now - PAST_SECONDS, now + i64::from(days) * 86_400)
+        .await?;
+    let id = source.source_id();
+    let handle = app.clone();
+    let stored = tauri::async_runtime::spawn_blocking(move || {
+        handle.state::<EpgStore>().replace(&id, guide, unix_now())
+    })
+    .await
+    .map_err(|e| format!("Failed to store guide: {}", e))??;
+    let _ = app.emit("epg-updated", stored.clone());
+    Ok(stored)
+}
+
+// Start the refresh job; runs for the lifetime of the app. Sources whose
+// playlist isn't loaded wait until it is
+pub fn start(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut failed_at: HashMap<String, i64> = HashMap::new();
+        loop {
+            tokio::time::sleep(CHECK_INTERVAL).await;
+            let now = unix_now();
+            for source in app.state::<GrabberSources>().list() {
+                let updated_at = app.state::<EpgStore>().updated_at(&source.source_id());
+                let fresh = updated_at.is_some_and(|at| now - at < REFRESH_INTERVAL);
+                let backing_off = failed_at.get(&source.id).is_some_and(|at| now - at < RETRY_INTERVAL);
+                let loaded = app.state::<PlaylistStore>().get(&source.playlist_id).is_some();
+                if fresh || backing_off || !loaded {
+                    continue;
+                }
+                match import(&app, &source).await {
+                    Ok(_) => {
+                        failed_at.remove(&source.id);
+                    }
+                    Err(e) => {
+                        failed_at.insert(source.id.clone(), now);
+                        log::warn!("Refresh of guide '{}' failed: {}", source.name, e);
+                    }
+                }
+            }
+        }
+    });
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GrabberInfo {
+    pub kind: &'static str,
+    pub name: &'static str,
+}
+
+// Command handler listing the available grabbers
+#[tauri::command]
+pub fn list_epg_grabbers() -> Vec<GrabberInfo> {
+    GRABBERS
+        .iter()
+        .map(|grabber| GrabberInfo {
+            kind: grabber.kind(),
+            name: grabber.name(),
+        })
+        .collect()
+}
+
+// Command handler that saves a grabber source for some of a loaded
+// playlist's groups and grabs its guide; adding the same grabber, address
+// and playlist again changes the groups. A source that fails to grab
+// isn't saved
+#[tauri::command]
+pub async fn add_grabber_source(
+    app: AppHandle,
+    name: String,
+    kind: String,
+    url: String,
+    playlist_id: String,
+    groups: Vec<String>,
+    days: Option<u32>,
+) -> Result<EpgSource, String> {
+    validate_string_length(&url, MAX_URL_LENGTH)?;
+    validate_string_length(&name, MAX_NAME_LENGTH)?;
+    let grabber = grabber(kind.trim())?;
+    let url = url.trim();
+    if url.is_empty() {
+        return Err("Guide URL cannot be empty".to_string());
+    }
+    if groups.len() > MAX_GROUPS {
+        return Err(format!("At most {} groups can be grabbed", MAX_GROUPS));
+    }
+    if days.is_some_and(|days| days == 0 || days > MAX_DAYS) {
+        return Err(format!("Days must be between 1 and {}", MAX_DAYS));
+    }
+    let source = GrabberSource {
+        id: content_hash(format!("{}|{}|{}", grabber.kind(), url, playlist_id).as_bytes()),
+        name: match name.trim() {
+            "" => url.to_string(),
+            name => name.to_string(),
+        },
+        kind: grabber.kind().to_string(),
+        url: url.to_string(),
+        playlist_id,
+        groups,
+        days,
+    };
+    let stored = import(&app, &source).await?;
+    app.state::<GrabberSources>().upsert(source)?;
+    Ok(stored)
+}
+
+// Command handler listing the saved grabber sources
+#[tauri::command]
+pub fn list_grabber_sources(sources: State<'_, GrabberSources>) -> Vec<GrabberSource> {
+    sources.list()
+}
+
+// Command handler grabbing a saved source's guide again
+#[tauri::command]
+pub async fn refresh_grabber_source(app: AppHandle, id: String) -> Result<EpgSource, String> {
+    let source = app.state::<GrabberSources>().get(&id)?;
+    import(&app, &source).await
+}
+
+// Command handler that deletes a grabber source together with its guide
+#[tauri::command]
+pub fn remove_grabber_source(app: AppHandle, sources: State<'_, GrabberSources>, id: String) -> Result<bool, String> {
+    app.state::<EpgStore>().remove(&source_id(&id))?;
+    sources.remove(&id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn test_group_channels_and_sources_persist() {
+        // Test that only the chosen groups' channels are asked for, once each, and sources survive reopening
+        let entry = |name: &str, group: &str, tvg_id: Option<&str>| ChannelEntry {
+            name: name.to_string(),
+            group_title: Some(group.to_string()),
+            tvg_id: tvg_id.map(str::to_string),
+            ..Default::default()
+        };
+        let entries = vec![
+            entry("One HD", "UK", Some("one.uk")),
+            entry("One SD", "uk ", Some("one.uk")),
+            entry("Two", "UK", Some(" ")),
+            entry("Drei", "DE", None),
+        ];
+        let channels = group_channels(&entries, &["UK".to_string()]);
+        let ids: Vec<&str> = channels.iter().map(|channel| channel.id.as_str()).collect();
+        assert_eq!(ids, vec!["one.uk", "Two"]);
+        assert!(grabber("json").is_ok() && grabber("missing").is_err());
+
+        let dir = temp_dir("grabber-sources");
+        let sources = GrabberSources::open(&dir);
+        let source = GrabberSource {
+            id: "g".to_string(),
+            kind: "json".to_string(),
+            groups: vec!["UK".to_string()],
+            ..Default::default()
+        };
+        sources.upsert(source.clone()).unwrap();
+        assert_eq!(GrabberSources::open(&dir).get("g").unwrap(), source);
+        assert_eq!(source.source_id(), "grabber:g");
+        assert!(sources.remove("g").unwrap() && sources.get("g").is_err());
+    }
+}
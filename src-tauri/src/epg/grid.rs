@@ -0,0 +1,166 @@
+// Data for one screen of the timeline grid: a page of playlist channels,
+// each with the guide channel it's matched to and the programmes inside
+// the time window. Descriptions stay behind; the grid only draws titles
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use super::matching::Matcher;
+use super::{merged_window, GuideIndex, Programme, TimeOffsets};
+use crate::playlist::ChannelEntry;
+
+// Most channels in one page
+pub const MAX_ROWS: usize = 200;
+
+// Widest time window, in seconds
+pub const MAX_WINDOW: i64 = 2 * 24 * 60 * 60;
+
+// Rows [offset, offset + limit) of a loaded playlist, in playlist order
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelPage {
+    pub playlist_id: String,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+// Unix seconds, [from, to)
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeWindow {
+    pub from: i64,
+    pub to: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GridRow {
+    pub channel_id: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logo: Option<String>,
+    // None when no guide channel matched
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub epg_channel_id: Option<String>,
+    pub programmes: Vec<Programme>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EpgGrid {
+    pub rows: Vec<GridRow>,
+    pub offset: usize,
+    // Channels in the playlist
+    pub total: usize,
+    pub from: i64,
+    pub to: i64,
+}
+
+pub struct GridSource<'a> {
+    pub guides: &'a [(String, Arc<GuideIndex>)],
+    pub offsets: &'a TimeOffsets,
+    pub matcher: &'a Matcher,
+    // Manual mappings, channel id -> guide channel id
+    pub manual: &'a BTreeMap<String, String>,
+    pub threshold: f64,
+}
+
+pub fn grid(source: &GridSource<'_>, entries: &[ChannelEntry], page: &ChannelPage, window: TimeWindow) -> EpgGrid {
+    let end = page.offset.saturating_add(page.limit.min(MAX_ROWS)).min(entries.len());
+    let start = page.offset.min(end);
+    let to = window.to.min(window.from.saturating_add(MAX_WINDOW));
+    let rows = entries[start..end]
+        .iter()
+        .map(|entry| {
+            let manual = source.manual.get(&entry.channel_id).map(String::as_str);
+            let epg_channel_id = source
+                .matcher
+                .find(entry, manual, source.threshold)
+                .ok()
+                .map(|found| found.epg_channel_id);
+            let programmes = epg_channel_id.as_deref().map_or_else(Vec::new, |channel| {
+                merged_window(source.guides, source.offsets, channel, window.from, to)
+                    .into_iter()
+                    .map(|programme| Programme {
+                        description: None,
+                        ..programme
+                    })
+                    .collect()
+            });
+            GridRow {
+                channel_id: entry.channel_id.clone(),
+                name: entry.name.clone(),
+                logo: entry.tvg_logo.clone(),
+                epg_channel_id,
+                programmes,
+            }
+        })
+        .collect();
+    EpgGrid {
+        rows,
+        offset: start,
+        total: entries.len(),
+        from: window.from,
+        to,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epg::EpgChannel;
+
+    #[test]
+    fn test_grid_page() {
+        // Test that a page holds matched channels with their programmes in the window, without descriptions
+        let guide = GuideIndex::new(
+            (0..10)
+                .map(|hour| Programme {
+                    channel: "one.uk".to_string(),
+                    start: hour * 3600,
+                    stop: (hour + 1) * 3600,
+                    title: format!("Hour {}", hour),
+                    description: Some("Long text".to_string()),
+                    ..Default::default()
+                })
+                .collect(),
+        );
+        let guides = vec![("s".to_string(), Arc::new(guide))];
+        let matcher = Matcher::new(vec![(
+            "s".to_string(),
+            EpgChannel {
+                id: "one.uk".to_string(),
+                names: vec!["One".to_string()],
+                icon: None,
+            },
+        )]);
+        let source = GridSource {
+            guides: &guides,
+            offsets: &TimeOffsets::default(),
+            matcher: &matcher,
+            manual: &BTreeMap::new(),
+            threshold: 0.9,
+        };
+        let entries: Vec<ChannelEntry> = ["Other", "One HD", "Unknown"]
+            .iter()
+            .map(|name| ChannelEntry {
+                channel_id: format!("p:{}", name),
+                name: name.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        let page = ChannelPage {
+            playlist_id: "p".to_string(),
+            offset: 1,
+            limit: 5,
+        };
+
+        let grid = grid(&source, &entries, &page, TimeWindow { from: 5400, to: 9000 });
+        assert_eq!((grid.offset, grid.total, grid.rows.len()), (1, 3, 2));
+        let titles: Vec<_> = grid.rows[0].programmes.iter().map(|p| p.title.as_str()).collect();
+        assert_eq!(titles, vec!["Hour 1", "Hour 2"]);
+        assert_eq!(grid.rows[0].programmes[0].description, None);
+        assert_eq!((grid.rows[1].epg_channel_id.as_deref(), grid.rows[1].programmes.len()), (None, 0));
+    }
+}
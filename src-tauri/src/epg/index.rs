@@ -0,0 +1,83 @@
+// A stored guide held in memory with a channel index: each channel's
+// programmes are one run sorted by start time, found through a hash map,
+// so a window lookup is a map hit plus a binary search even with a week of
+// programmes for thousands of channels
+use std::collections::HashMap;
+use std::ops::Range;
+
+use super::Programme;
+
+#[derive(Debug, Default)]
+pub struct GuideIndex {
+    programmes: Vec<Programme>,
+    channels: HashMap<String, Range<usize>>,
+}
+
+impl GuideIndex {
+    pub fn new(mut programmes: Vec<Programme>) -> Self {
+        programmes.sort_by(|a, b| a.channel.cmp(&b.channel).then(a.start.cmp(&b.start)));
+        let mut channels: HashMap<String, Range<usize>> = HashMap::new();
+        let mut first = 0;
+        for (i, programme) in programmes.iter().enumerate() {
+            if programmes.get(i + 1).map(|next| &next.channel) != Some(&programme.channel) {
+                channels.insert(programme.channel.clone(), first..i + 1);
+                first = i + 1;
+            }
+        }
+        Self { programmes, channels }
+    }
+
+    // All programmes, sorted by channel then start time
+    pub fn programmes(&self) -> &[Programme] {
+        &self.programmes
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.channels.len()
+    }
+
+    // Ids of the channels that have programmes, in no particular order
+    pub fn channel_ids(&self) -> impl Iterator<Item = &str> {
+        self.channels.keys().map(String::as_str)
+    }
+
+    fn run(&self, channel: &str) -> &[Programme] {
+        self.channels
+            .get(channel)
+            .map_or(&[], |range| &self.programmes[range.clone()])
+    }
+
+    // Programmes of one channel overlapping [from, to). A channel's
+    // programmes don't overlap, so their stops ascend with their starts
+    pub fn window(&self, channel: &str, from: i64, to: i64) -> &[Programme] {
+        let run = self.run(channel);
+        let first = run.partition_point(|p| p.stop <= from);
+        let last = run.partition_point(|p| p.start < to).max(first);
+        &run[first..last]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn programme(channel: &str, start: i64) -> Programme {
+        Programme {
+            channel: channel.to_string(),
+            start,
+            stop: start + 100,
+            title: format!("{}@{}", channel, start),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_window() {
+        // Test that lookups only see the channel's programmes around the given times
+        let index = GuideIndex::new(vec![programme("a", 200), programme("b", 100), programme("a", 0), programme("a", 100)]);
+        assert_eq!(index.channel_count(), 2);
+        assert_eq!(index.window("a", 150, 201), &[programme("a", 100), programme("a", 200)]);
+        assert!(index.window("c", 0, 1000).is_empty());
+        assert!(index.window("a", 500, 400).is_empty());
+    }
+}
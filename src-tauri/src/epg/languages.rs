@@ -0,0 +1,188 @@
+// Guides often give a programme's title and description in several
+// languages. All of them are stored; queries pick the one in the user's
+// preferred languages, in order, then the same language of another region
+// (en for en-GB and back), then the guide's first
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+
+use super::{EpgStore, Programme};
+use crate::storage;
+
+const LANGUAGES_FILE: &str = "epg_languages.json";
+
+const MAX_LANGUAGES: usize = 10;
+const MAX_TAG_LENGTH: usize = 35;
+
+// A title or description and its XMLTV lang attribute
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalizedText {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    pub text: String,
+}
+
+// Language tags compare case-insensitively, with "_" read as "-"
+fn normalize_tag(tag: &str) -> String {
+    tag.trim().replace('_', "-").to_ascii_lowercase()
+}
+
+fn primary(tag: &str) -> &str {
+    tag.split('-').next().unwrap_or(tag)
+}
+
+// The text to show for `preferred` (normalized tags), None when no
+// language matches and the guide's first should stay
+fn pick<'a>(texts: &'a [LocalizedText], preferred: &[String]) -> Option<&'a LocalizedText> {
+    let tags: Vec<Option<String>> = texts.iter().map(|text| text.lang.as_deref().map(normalize_tag)).collect();
+    preferred.iter().find_map(|wanted| {
+        let found = |matches: &dyn Fn(&str) -> bool| {
+            tags.iter()
+                .position(|tag| tag.as_deref().is_some_and(matches))
+                .map(|index| &texts[index])
+        };
+        found(&|tag| tag == wanted).or_else(|| found(&|tag| primary(tag) == primary(wanted)))
+    })
+}
+
+fn prefer(programme: &mut Programme, preferred: &[String]) {
+    if let Some(title) = pick(&programme.titles, preferred) {
+        programme.title = title.text.clone();
+    }
+    if let Some(description) = pick(&programme.descriptions, preferred) {
+        programme.description = Some(description.text.clone());
+    }
+}
+
+pub struct EpgLanguages {
+    path: PathBuf,
+    // Normalized tags, most preferred first
+    languages: Mutex<Vec<String>>,
+}
+
+impl EpgLanguages {
+    pub fn open(data_dir: &Path) -> Self {
+        let path = data_dir.join(LANGUAGES_FILE);
+        let languages = storage::read_json(&path).unwrap_or_else(|e| {
+            log::error!("Failed to load EPG languages: {}", e);
+            Vec::new()
+        });
+        Self {
+            path,
+            languages: Mutex::new(languages),
+        }
+    }
+
+    pub fn list(&self) -> Vec<String> {
+        self.languages.lock().unwrap().clone()
+    }
+
+    // Replace the preferred languages; an empty list keeps the guides' order
+    pub fn set(&self, languages: &[String]) -> Result<Vec<String>, String> {
+        let mut tags: Vec<String> = Vec::new();
+        for tag in languages.iter().map(|tag| normalize_tag(tag)).filter(|tag| !tag.is_empty()) {
+            if tag.len() > MAX_TAG_LENGTH || !tag.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+                return Err(format!("'{}' isn't a language tag", tag));
+            }
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        if tags.len() > MAX_LANGUAGES {
+            return Err(format!("At most {} languages can be preferred", MAX_LANGUAGES));
+        }
+        let mut current = self.languages.lock().unwrap();
+        storage::write_json(&self.path, &tags)?;
+        *current = tags.clone();
+        Ok(tags)
+    }
+}
+
+// Show programmes in the preferred languages
+pub fn apply<'a>(app: &AppHandle, programmes: impl IntoIterator<Item = &'a mut Programme>) {
+    let preferred = app.state::<EpgLanguages>().list();
+    if preferred.is_empty() {
+        return;
+    }
+    for programme in programmes {
+        prefer(programme, &preferred);
+    }
+}
+
+// A language the stored guides use and how many titles are in it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GuideLanguage {
+    pub lang: String,
+    pub titles: usize,
+}
+
+// Command handler listing the languages of the stored guides' titles,
+// most used first
+#[tauri::command]
+pub async fn list_epg_languages(app: AppHandle) -> Result<Vec<GuideLanguage>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for (_, guide) in app.state::<EpgStore>().guides() {
+            for title in guide.programmes().iter().flat_map(|programme| &programme.titles) {
+                if let Some(lang) = &title.lang {
+                    *counts.entry(normalize_tag(lang)).or_default() += 1;
+                }
+            }
+        }
+        let mut languages: Vec<GuideLanguage> =
+            counts.into_iter().map(|(lang, titles)| GuideLanguage { lang, titles }).collect();
+        languages.sort_by(|a, b| b.titles.cmp(&a.titles).then_with(|| a.lang.cmp(&b.lang)));
+        languages
+    })
+    .await
+    .map_err(|e| format!("Failed to list guide languages: {}", e))
+}
+
+// Command handler returning the preferred guide languages
+#[tauri::command]
+pub fn get_epg_languages(languages: State<'_, EpgLanguages>) -> Vec<String> {
+    languages.list()
+}
+
+// Command handler setting the preferred guide languages, most preferred
+// first (e.g. ["de-AT", "en"])
+#[tauri::command]
+pub fn set_epg_languages(languages: State<'_, EpgLanguages>, preferred: Vec<String>) -> Result<Vec<String>, String> {
+    languages.set(&preferred)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn test_preferred_language_picked() {
+        // Test that exact tags win over regional matches and unmatched languages keep the guide's first
+        let text = |lang: Option<&str>, text: &str| LocalizedText {
+            lang: lang.map(str::to_string),
+            text: text.to_string(),
+        };
+        let mut programme = Programme {
+            title: "Newyddion".to_string(),
+            titles: vec![text(Some("cy"), "Newyddion"), text(Some("en_GB"), "News"), text(Some("en-US"), "Newscast")],
+            descriptions: vec![text(None, "Penawdau"), text(Some("en"), "Headlines")],
+            ..Default::default()
+        };
+        let languages = EpgLanguages::open(&temp_dir("epg-languages"));
+        assert!(languages.set(&["en gb".to_string()]).is_err());
+        let preferred = languages.set(&["fr".to_string(), "EN-us".to_string(), "en".to_string()]).unwrap();
+        assert_eq!(EpgLanguages::open(languages.path.parent().unwrap()).list(), preferred);
+
+        prefer(&mut programme, &preferred);
+        assert_eq!(programme.title, "Newscast");
+        assert_eq!(programme.description.as_deref(), Some("Headlines"));
+        assert_eq!(pick(&programme.titles, &["en-au".to_string()]).unwrap().text, "News");
+        assert!(pick(&programme.titles, &["de".to_string()]).is_none());
+    }
+}
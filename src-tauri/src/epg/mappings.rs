@@ -0,0 +1,125 @@
+// Guide channels the user picked for channels by hand. They're keyed by
+// channel id, which survives playlist and guide refreshes, and always win
+// over automatic matching
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use tauri::State;
+
+use crate::storage;
+use crate::validate_string_length;
+
+const MAPPINGS_FILE: &str = "epg_mappings.json";
+
+// Limit for ids sent by the UI
+const MAX_ID_LENGTH: usize = 2048;
+
+pub struct EpgMappings {
+    path: PathBuf,
+    // Channel id -> guide channel id
+    mappings: Mutex<BTreeMap<String, String>>,
+}
+
+impl EpgMappings {
+    pub fn open(data_dir: &Path) -> Self {
+        let path = data_dir.join(MAPPINGS_FILE);
+        let mappings = storage::read_json(&path).unwrap_or_else(|e| {
+            log::error!("Failed to load EPG mappings: {}", e);
+            BTreeMap::new()
+        });
+        Self {
+            path,
+            mappings: Mutex::new(mappings),
+        }
+    }
+
+    pub fn list(&self) -> BTreeMap<String, String> {
+        self.mappings.lock().unwrap().clone()
+    }
+
+    pub fn get(&self, channel_id: &str) -> Option<String> {
+        self.mappings.lock().unwrap().get(channel_id).cloned()
+    }
+
+    pub fn set(&self, channel_id: &str, epg_channel_id: &str) -> Result<(), String> {
+        let mut mappings = self.mappings.lock().unwrap();
+        mappings.insert(channel_id.to_string(), epg_channel_id.to_string());
+        storage::write_json(&self.path, &*mappings)
+    }
+
+    pub fn clear(&self, channel_id: &str) -> Result<bool, String> {
+        let mut mappings = self.mappings.lock().unwrap();
+        if mappings.remove(channel_id).is_none() {
+            return Ok(false);
+        }
+        storage::write_json(&self.path, &*mappings)?;
+        Ok(true)
+    }
+
+    // Drop the mappings of every channel of a removed playlist or provider
+    pub fn remove_provider(&self, provider_id: &str) -> Result<(), String> {
+        let prefix = format!("{}:", provider_id);
+        let mut mappings = self.mappings.lock().unwrap();
+        let before = mappings.len();
+        mappings.retain(|channel_id, _| !channel_id.starts_with(&prefix));
+        if mappings.len() == before {
+            return Ok(());
+        }
+        storage::write_json(&self.path, &*mappings)
+    }
+}
+
+// Command handler listing the manual mappings, channel id -> guide channel id
+#[tauri::command]
+pub fn list_epg_mappings(mappings: State<'_, EpgMappings>) -> BTreeMap<String, String> {
+    mappings.list()
+}
+
+// Command handler pinning a channel to a guide channel
+#[tauri::command]
+pub fn set_epg_mapping(
+    mappings: State<'_, EpgMappings>,
+    channel_id: String,
+    epg_channel_id: String,
+) -> Result<(), String> {
+    validate_string_length(&channel_id, MAX_ID_LENGTH)?;
+    validate_string_length(&epg_channel_id, MAX_ID_LENGTH)?;
+    let (channel_id, epg_channel_id) = (channel_id.trim(), epg_channel_id.trim());
+    if channel_id.is_empty() || epg_channel_id.is_empty() {
+        return Err("Channel and guide channel are required".to_string());
+    }
+    mappings.set(channel_id, epg_channel_id)
+}
+
+// Command handler returning a channel to automatic matching
+#[tauri::command]
+pub fn clear_epg_mapping(mappings: State<'_, EpgMappings>, channel_id: String) -> Result<bool, String> {
+    mappings.clear(channel_id.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn test_mappings_persist() {
+        // Test that mappings survive reopening and go away with their playlist
+        let dir = temp_dir("epg-mappings");
+        let mappings = EpgMappings::open(&dir);
+        mappings.set("p:1", "BBCOne.uk").unwrap();
+        mappings.set("p:2", "ITV1.uk").unwrap();
+        mappings.set("q:1", "Arte.fr").unwrap();
+        assert!(mappings.clear("p:2").unwrap());
+        assert!(!mappings.clear("p:2").unwrap());
+
+        let reopened = EpgMappings::open(&dir);
+        assert_eq!(reopened.list().len(), 2);
+        reopened.remove_provider("p").unwrap();
+        assert_eq!(
+            EpgMappings::open(&dir).list().into_keys().collect::<Vec<_>>(),
+            vec!["q:1"]
+        );
+    }
+}
@@ -0,0 +1,292 @@
+// Linking playlist channels to guide channels: by the user's manual
+// mapping, then by tvg-id when a guide has that id, otherwise by name with
+// provider tags and quality suffixes ignored, exactly first and then by
+// Jaro-Winkler similarity
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Serialize;
+
+use super::EpgChannel;
+use crate::playlist::{jaro_winkler, normalize_name, strip_tags, ChannelEntry};
+
+// Fuzzy matches below this similarity are reported as unmatched by default
+pub const DEFAULT_THRESHOLD: f64 = 0.9;
+
+// Confidence of a name that is the same once normalized
+const EXACT_NAME_CONFIDENCE: f64 = 0.95;
+
+// Fuzzy similarities are scaled by this so they rank below exact names
+const FUZZY_WEIGHT: f64 = 0.9;
+
+// Words that say how a channel is encoded rather than which channel it is
+const QUALITY_TAGS: [&str; 16] = [
+    "sd", "hd", "fhd", "uhd", "4k", "8k", "hevc", "h264", "h265", "hdr", "720p", "1080i", "1080p", "50fps", "60fps",
+    "raw",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MatchMethod {
+    Manual,
+    TvgId,
+    Name,
+    Fuzzy,
+}
+
+// A playlist channel linked to a guide channel
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EpgMatch {
+    pub channel_id: String,
+    pub name: String,
+    pub epg_channel_id: String,
+    // Stored guide the channel was found in, the most trusted one; None for
+    // a manual mapping to a channel no stored guide has (yet)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_id: Option<String>,
+    pub method: MatchMethod,
+    // 0.0..=1.0, 1.0 for manual and tvg-id matches
+    pub confidence: f64,
+}
+
+// A playlist channel no guide channel was close enough to
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnmatchedChannel {
+    pub channel_id: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tvg_id: Option<String>,
+    // Best guess below the threshold, for the user to confirm
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub closest: Option<String>,
+    pub similarity: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchReport {
+    pub matched: Vec<EpgMatch>,
+    pub unmatched: Vec<UnmatchedChannel>,
+}
+
+// "UK: BBC One FHD" and "BBC One HD" both become "bbcone"
+pub fn match_name(name: &str) -> String {
+    let stripped = strip_tags(name);
+    let words: String = stripped
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty() && !QUALITY_TAGS.contains(&word.to_lowercase().as_str()))
+        .collect();
+    match normalize_name(&words) {
+        key if key.is_empty() => normalize_name(name),
+        key => key,
+    }
+}
+
+// XMLTV ids often read "BBCOne.uk"; without the country they double as a
+// name for guides that list no display names
+fn id_name(id: &str) -> &str {
+    match id.rsplit_once('.') {
+        Some((name, country)) if country.len() == 2 && !name.is_empty() => name,
+        _ => id,
+    }
+}
+
+// Keeps the earlier of two equally similar candidates
+fn better(best: Option<(usize, f64)>, candidate: (usize, f64)) -> Option<(usize, f64)> {
+    match best {
+        Some(best) if best.1 >= candidate.1 => Some(best),
+        _ => Some(candidate),
+    }
+}
+
+// The guide channels to match against, looked up by id, exact name and
+// first letter of the name for the fuzzy pass
+pub struct Matcher {
+    // (source id, guide channel id)
+    channels: Vec<(String, String)>,
+    ids: HashMap<String, usize>,
+    names: HashMap<String, usize>,
+    buckets: HashMap<char, Vec<(String, usize)>>,
+}
+
+impl Matcher {
+    // Guide channels given with their source, most trusted source first;
+    // the first source with a channel id owns it
+    pub fn new(channels: Vec<(String, EpgChannel)>) -> Self {
+        let mut matcher = Matcher {
+            channels: Vec::new(),
+            ids: HashMap::new(),
+            names: HashMap::new(),
+            buckets: HashMap::new(),
+        };
+        for (source_id, channel) in channels {
+            let id = channel.id.trim().to_lowercase();
+            if id.is_empty() || matcher.ids.contains_key(&id) {
+                continue;
+            }
+            let position = matcher.channels.len();
+            matcher.ids.insert(id, position);
+            let names = channel.names.iter().map(String::as_str).chain([id_name(&channel.id)]);
+            for key in names.map(match_name) {
+                let Some(first) = key.chars().next() else {
+                    continue;
+                };
+                if matcher.names.contains_key(&key) {
+                    continue;
+                }
+                matcher.names.insert(key.clone(), position);
+                matcher.buckets.entry(first).or_default().push((key, position));
+            }
+            matcher.channels.push((source_id, channel.id));
+        }
+        matcher
+    }
+
+    fn linked(&self, entry: &ChannelEntry, position: usize, method: MatchMethod, confidence: f64) -> EpgMatch {
+        let (source_id, epg_channel_id) = &self.channels[position];
+        EpgMatch {
+            channel_id: entry.channel_id.clone(),
+            name: entry.name.clone(),
+            epg_channel_id: epg_channel_id.clone(),
+            source_id: Some(source_id.clone()),
+            method,
+            confidence,
+        }
+    }
+
+    // The closest guide name sharing the key's first letter
+    fn closest(&self, key: &str) -> Option<(usize, f64)> {
+        self.buckets
+            .get(&key.chars().next()?)?
+            .iter()
+            .map(|(name, position)| (*position, jaro_winkler(key, name)))
+            .fold(None, better)
+    }
+
+    pub fn find(
+        &self,
+        entry: &ChannelEntry,
+        manual: Option<&str>,
+        threshold: f64,
+    ) -> Result<EpgMatch, UnmatchedChannel> {
+        if let Some(epg_channel_id) = manual {
+            return Ok(match self.ids.get(&epg_channel_id.to_lowercase()) {
+                Some(&position) => self.linked(entry, position, MatchMethod::Manual, 1.0),
+                None => EpgMatch {
+                    channel_id: entry.channel_id.clone(),
+                    name: entry.name.clone(),
+                    epg_channel_id: epg_channel_id.to_string(),
+                    source_id: None,
+                    method: MatchMethod::Manual,
+                    confidence: 1.0,
+                },
+            });
+        }
+        let tvg_id = entry.tvg_id.as_deref().map(str::trim).filter(|id| !id.is_empty());
+        if let Some(&position) = tvg_id.and_then(|id| self.ids.get(&id.to_lowercase())) {
+            return Ok(self.linked(entry, position, MatchMethod::TvgId, 1.0));
+        }
+
+        let keys: Vec<String> = [Some(entry.name.as_str()), entry.tvg_name.as_deref()]
+            .into_iter()
+            .flatten()
+            .map(match_name)
+            .filter(|key| !key.is_empty())
+            .collect();
+        if let Some(&position) = keys.iter().find_map(|key| self.names.get(key)) {
+            return Ok(self.linked(entry, position, MatchMethod::Name, EXACT_NAME_CONFIDENCE));
+        }
+        let best = keys.iter().filter_map(|key| self.closest(key)).fold(None, better);
+        match best {
+            Some((position, similarity)) if similarity >= threshold => {
+                Ok(self.linked(entry, position, MatchMethod::Fuzzy, similarity * FUZZY_WEIGHT))
+            }
+            _ => Err(UnmatchedChannel {
+                channel_id: entry.channel_id.clone(),
+                name: entry.name.clone(),
+                tvg_id: entry.tvg_id.clone(),
+                closest: best.map(|(position, _)| self.channels[position].1.clone()),
+                similarity: best.map_or(0.0, |(_, similarity)| similarity),
+            }),
+        }
+    }
+
+    // `manual` maps channel ids to the guide channels the user picked
+    pub fn report(&self, entries: &[ChannelEntry], manual: &BTreeMap<String, String>, threshold: f64) -> MatchReport {
+        let mut report = MatchReport::default();
+        for entry in entries {
+            match self.find(entry, manual.get(&entry.channel_id).map(String::as_str), threshold) {
+                Ok(found) => report.matched.push(found),
+                Err(missing) => report.unmatched.push(missing),
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, tvg_id: Option<&str>) -> ChannelEntry {
+        ChannelEntry {
+            channel_id: format!("c-{}", name),
+            name: name.to_string(),
+            tvg_id: tvg_id.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    fn channel(id: &str, names: &[&str]) -> EpgChannel {
+        EpgChannel {
+            id: id.to_string(),
+            names: names.iter().map(|name| name.to_string()).collect(),
+            icon: None,
+        }
+    }
+
+    #[test]
+    fn test_match_by_id_name_and_similarity() {
+        // Test that manual mappings and then tvg-ids win, tags and quality suffixes are ignored and distant names are reported
+        let matcher = Matcher::new(vec![
+            ("xmltv:a".to_string(), channel("BBCOne.uk", &["BBC One"])),
+            ("xmltv:a".to_string(), channel("Discovery.uk", &["Discovery Channel"])),
+            ("xmltv:b".to_string(), channel("bbcone.uk", &["BBC 1"])),
+            ("xmltv:b".to_string(), channel("Eurosport1.de", &[])),
+        ]);
+        let report = matcher.report(
+            &[
+                entry("Anything", Some("BBCONE.uk")),
+                entry("UK: BBC One FHD", None),
+                entry("Discovery Chanel HD", None),
+                entry("EUROSPORT 1 HEVC", None),
+                entry("Dazn 1", None),
+                entry("Sky News", Some("BBCOne.uk")),
+            ],
+            &BTreeMap::from([("c-Sky News".to_string(), "skynews.uk".to_string())]),
+            DEFAULT_THRESHOLD,
+        );
+
+        let found: Vec<_> = report
+            .matched
+            .iter()
+            .map(|m| (m.epg_channel_id.as_str(), m.source_id.as_deref(), m.method))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("BBCOne.uk", Some("xmltv:a"), MatchMethod::TvgId),
+                ("BBCOne.uk", Some("xmltv:a"), MatchMethod::Name),
+                ("Discovery.uk", Some("xmltv:a"), MatchMethod::Fuzzy),
+                ("Eurosport1.de", Some("xmltv:b"), MatchMethod::Name),
+                ("skynews.uk", None, MatchMethod::Manual),
+            ]
+        );
+        assert_eq!(report.matched[0].confidence, 1.0);
+        assert!(report.matched[2].confidence < EXACT_NAME_CONFIDENCE);
+        assert_eq!(report.unmatched.len(), 1);
+        assert_eq!(report.unmatched[0].name, "Dazn 1");
+        assert_eq!(match_name("HD"), "hd");
+    }
+}
@@ -0,0 +1,82 @@
+// Combining one channel's programmes from several guides. Guides come best
+// first: the first guide's programmes all count, later guides only fill the
+// time the earlier ones leave empty
+use serde::Serialize;
+
+use super::Programme;
+
+// What's on a channel now and what follows
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NowNext {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub now: Option<Programme>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next: Option<Programme>,
+}
+
+// Each run sorted by start, as GuideIndex returns them; the result is too.
+// A lower-priority programme is taken only when it fits a gap whole, so
+// the guide never shows two programmes at once
+pub fn merge(runs: &[&[Programme]]) -> Vec<Programme> {
+    let Some((first, rest)) = runs.split_first() else {
+        return Vec::new();
+    };
+    let mut merged = first.to_vec();
+    for run in rest {
+        for programme in run.iter() {
+            let i = merged.partition_point(|taken| taken.start < programme.start);
+            let clashes_before = i > 0 && merged[i - 1].stop > programme.start;
+            let clashes_after = merged.get(i).is_some_and(|taken| taken.start < programme.stop);
+            if !clashes_before && !clashes_after {
+                merged.insert(i, programme.clone());
+            }
+        }
+    }
+    merged
+}
+
+// Now and next among programmes sorted by start that all end after `now`
+pub fn now_next(programmes: &[Programme], now: i64) -> NowNext {
+    let current = programmes.first().filter(|p| p.start <= now);
+    let next = programmes.iter().find(|p| p.start > now);
+    NowNext {
+        now: current.cloned(),
+        next: next.cloned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn programme(title: &str, start: i64, stop: i64) -> Programme {
+        Programme {
+            channel: "c".to_string(),
+            start,
+            stop,
+            title: title.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_lower_priority_fills_gaps() {
+        // Test that later guides only add programmes that fit between the earlier ones
+        let best = [programme("News", 0, 100), programme("Film", 200, 400)];
+        let fallback = [
+            programme("Old news", 0, 100),
+            programme("Quiz", 100, 200),
+            programme("Late", 350, 500),
+            programme("Night", 400, 500),
+        ];
+        let merged = merge(&[&best, &fallback]);
+        let titles: Vec<_> = merged.iter().map(|p| p.title.as_str()).collect();
+        assert_eq!(titles, vec!["News", "Quiz", "Film", "Night"]);
+        assert!(merge(&[]).is_empty());
+
+        let on = now_next(&merged[1..], 150);
+        assert_eq!((on.now.unwrap().title, on.next.unwrap().title), ("Quiz".to_string(), "Film".to_string()));
+        assert_eq!(now_next(&merged[2..], 150).now, None);
+    }
+}
@@ -0,0 +1,927 @@
+// Programme guide data: one stored guide per source (a provider or an
+// XMLTV URL or file). Programmes live in the guide database, which answers
+// queries by channel and time from its index; whole guides are loaded into
+// memory on first use for the views that walk every programme. Queries by
+// channel merge every guide that has the channel, in priority order
+mod archive;
+pub mod artwork;
+mod calendar;
+mod coverage;
+mod database;
+pub mod genres;
+pub mod grabbers;
+mod export;
+mod grid;
+mod index;
+pub mod languages;
+pub mod mappings;
+mod matching;
+mod merge;
+mod notifications;
+mod offsets;
+pub mod parental;
+pub mod reminders;
+pub mod retention;
+mod search;
+pub mod sources;
+pub mod time;
+pub mod watcher;
+pub mod xmltv;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+
+use crate::playlist::PlaylistStore;
+use crate::{storage, unix_now, validate_string_length};
+
+use database::GuideDatabase;
+
+pub use genres::Genre;
+pub use grid::{ChannelPage, EpgGrid, TimeWindow};
+pub use index::GuideIndex;
+pub use languages::LocalizedText;
+pub use matching::MatchReport;
+pub use merge::NowNext;
+pub use offsets::TimeOffsets;
+pub use parental::ContentRating;
+pub use search::{SearchResult, TimeRange};
+
+const INDEX_FILE: &str = "sources.json";
+const PRIORITY_FILE: &str = "priority.json";
+const OFFSETS_FILE: &str = "offsets.json";
+const RETENTION_FILE: &str = "retention.json";
+const GENRES_FILE: &str = "genres.json";
+const DATABASE_FILE: &str = "guide.db";
+
+// How far ahead now/next looks for the next programme, in seconds
+const NEXT_HORIZON: i64 = 24 * 60 * 60;
+
+// Limit for search text sent by the UI
+const MAX_QUERY_LENGTH: usize = 500;
+
+// Limit for file paths sent by the UI
+const MAX_PATH_LENGTH: usize = 4096;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Programme {
+    // XMLTV channel id (tvg-id on playlist channels)
+    pub channel: String,
+    // Unix seconds
+    pub start: i64,
+    pub stop: i64,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    // Genres as the guide names them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<String>,
+    // A new episode or a première
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub premiere: bool,
+    // The categories in the fixed genre set, see genres
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub genres: Vec<Genre>,
+    // Artwork URL; guide queries return the cached copy's asset URL
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ratings: Vec<ContentRating>,
+    // Shared by every episode of a series, when the guide says
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub series_id: Option<String>,
+    // Every language of the title and description when the guide has
+    // several; title and description are the guide's first until queries
+    // pick the preferred language, see languages
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub titles: Vec<LocalizedText>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub descriptions: Vec<LocalizedText>,
+}
+
+// A channel a guide lists
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EpgChannel {
+    // XMLTV channel id, what programmes refer to
+    pub id: String,
+    // Display names, the main one first
+    pub names: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+}
+
+// Everything read from one guide
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Guide {
+    pub channels: Vec<EpgChannel>,
+    pub programmes: Vec<Programme>,
+}
+
+// Guides built from programme listings name no channels of their own
+impl From<Vec<Programme>> for Guide {
+    fn from(programmes: Vec<Programme>) -> Self {
+        Guide {
+            channels: Vec::new(),
+            programmes,
+        }
+    }
+}
+
+// A stored guide
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EpgSource {
+    pub id: String,
+    // Unix seconds of the last successful update
+    pub updated_at: i64,
+    pub programmes: usize,
+    pub channels: usize,
+}
+
+pub struct EpgStore {
+    dir: PathBuf,
+    database: GuideDatabase,
+    sources: Mutex<Vec<EpgSource>>,
+    // Source ids, most trusted first; sources not listed follow in the
+    // order they were stored
+    priority: Mutex<Vec<String>>,
+    offsets: Mutex<TimeOffsets>,
+    // Days of past programmes kept, the default when unset
+    retention_days: Mutex<Option<u32>>,
+    // Category -> genre, replacing the built-in table
+    genre_overrides: Mutex<BTreeMap<String, Genre>>,
+    loaded: Mutex<HashMap<String, Arc<GuideIndex>>>,
+    // Held while any file in the directory is written, so a pruned copy
+    // never overwrites a newer download and compaction never runs halfway
+    // through a write
+    writing: Mutex<()>,
+}
+
+impl EpgStore {
+    pub fn open(dir: PathBuf) -> Self {
+        let sources = storage::read_json(&dir.join(INDEX_FILE)).unwrap_or_else(|e| {
+            log::error!("Failed to load EPG sources: {}", e);
+            Vec::new()
+        });
+        let priority = storage::read_json(&dir.join(PRIORITY_FILE)).unwrap_or_else(|e| {
+            log::error!("Failed to load EPG source priority: {}", e);
+            Vec::new()
+        });
+        let offsets = storage::read_json(&dir.join(OFFSETS_FILE)).unwrap_or_else(|e| {
+            log::error!("Failed to load EPG time offsets: {}", e);
+            TimeOffsets::default()
+        });
+        let retention_days = storage::read_json(&dir.join(RETENTION_FILE)).unwrap_or_else(|e| {
+            log::error!("Failed to load EPG retention: {}", e);
+            None
+        });
+        let genre_overrides = storage::read_json(&dir.join(GENRES_FILE)).unwrap_or_else(|e| {
+            log::error!("Failed to load EPG genre overrides: {}", e);
+            BTreeMap::new()
+        });
+        let store = Self {
+            database: GuideDatabase::open(&dir.join(DATABASE_FILE)),
+            dir,
+            sources: Mutex::new(sources),
+            priority: Mutex::new(priority),
+            offsets: Mutex::new(offsets),
+            retention_days: Mutex::new(retention_days),
+            genre_overrides: Mutex::new(genre_overrides),
+            loaded: Mutex::new(HashMap::new()),
+            writing: Mutex::new(()),
+        };
+        store.import_guide_files();
+        store
+    }
+
+    // Guides were stored as one gzipped JSON file per source before the
+    // guide database; move any still there into it
+    fn import_guide_files(&self) {
+        for source in self.sources() {
+            let path = self.guide_path(&source.id);
+            if !path.is_file() {
+                continue;
+            }
+            let imported = storage::read_json_gz::<Vec<Programme>>(&path)
+                .and_then(|programmes| self.database.replace(&source.id, &programmes));
+            match imported {
+                Ok(()) => {
+                    let _ = fs::remove_file(&path);
+                }
+                Err(e) => log::error!("Failed to import the guide of EPG source '{}': {}", source.id, e),
+            }
+        }
+    }
+
+    // Source ids come from content hashes or provider ids but are hashed
+    // again so any string is a safe file name. Guide files are only read
+    // to import them into the database
+    fn guide_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json.gz", crate::playlist::content_hash(id.as_bytes())))
+    }
+
+    fn channels_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.channels.json", crate::playlist::content_hash(id.as_bytes())))
+    }
+
+    pub fn sources(&self) -> Vec<EpgSource> {
+        self.sources.lock().unwrap().clone()
+    }
+
+    pub fn updated_at(&self, id: &str) -> Option<i64> {
+        self.sources.lock().unwrap().iter().find(|s| s.id == id).map(|s| s.updated_at)
+    }
+
+    // Store a new guide for a source, replacing the previous one; programmes
+    // that ended before the retention window are left out
+    pub fn replace(&self, id: &str, guide: Guide, at: i64) -> Result<EpgSource, String> {
+        let Guide {
+            channels: listed,
+            mut programmes,
+        } = guide;
+        let cutoff = at - i64::from(self.retention_days()) * 24 * 60 * 60;
+        programmes.retain(|programme| programme.stop > cutoff);
+        let overrides = self.genre_overrides();
+        for programme in &mut programmes {
+            programme.genres = genres::normalize(&programme.categories, &overrides);
+        }
+        let _writing = self.writing.lock().unwrap();
+        storage::write_json(&self.channels_path(id), &listed)?;
+        self.store_index(id, GuideIndex::new(programmes), at)
+    }
+
+    // Write a guide's programmes and swap them in; they're replaced in one
+    // transaction, readers keep the previous index until they ask again.
+    // Callers hold `writing`
+    fn store_index(&self, id: &str, index: GuideIndex, at: i64) -> Result<EpgSource, String> {
+        self.database.replace(id, index.programmes())?;
+
+        let source = EpgSource {
+            id: id.to_string(),
+            updated_at: at,
+            programmes: index.programmes().len(),
+            channels: index.channel_count(),
+        };
+        let mut sources = self.sources.lock().unwrap();
+        match sources.iter_mut().find(|s| s.id == id) {
+            Some(existing) => *existing = source.clone(),
+            None => sources.push(source.clone()),
+        }
+        storage::write_json(&self.dir.join(INDEX_FILE), &*sources)?;
+        drop(sources);
+        self.loaded.lock().unwrap().insert(id.to_string(), Arc::new(index));
+        Ok(source)
+    }
+
+    // Mark a source's guide as current without replacing it
+    pub fn touch(&self, id: &str, at: i64) -> Result<EpgSource, String> {
+        let _writing = self.writing.lock().unwrap();
+        let mut sources = self.sources.lock().unwrap();
+        let source = sources
+            .iter_mut()
+            .find(|s| s.id == id)
+            .ok_or_else(|| format!("EPG source '{}' doesn't exist", id))?;
+        source.updated_at = at;
+        let touched = source.clone();
+        storage::write_json(&self.dir.join(INDEX_FILE), &*sources)?;
+        Ok(touched)
+    }
+
+    // The indexed guide of a source
+    pub fn guide(&self, id: &str) -> Result<Arc<GuideIndex>, String> {
+        if let Some(guide) = self.loaded.lock().unwrap().get(id) {
+            return Ok(guide.clone());
+        }
+        if self.updated_at(id).is_none() {
+            return Err(format!("EPG source '{}' doesn't exist", id));
+        }
+        let guide = Arc::new(GuideIndex::new(self.database.programmes(id)?));
+        self.loaded.lock().unwrap().insert(id.to_string(), guide.clone());
+        Ok(guide)
+    }
+
+    // Ids of the stored sources, most trusted first
+    pub fn priority(&self) -> Vec<String> {
+        let priority = self.priority.lock().unwrap();
+        let mut ids: Vec<String> = self.sources().into_iter().map(|source| source.id).collect();
+        ids.sort_by_key(|id| priority.iter().position(|p| p == id).unwrap_or(usize::MAX));
+        ids
+    }
+
+    pub fn set_priority(&self, ids: Vec<String>) -> Result<(), String> {
+        let mut ids: Vec<String> = ids.into_iter().map(|id| id.trim().to_string()).collect();
+        let mut seen = HashSet::new();
+        ids.retain(|id| !id.is_empty() && seen.insert(id.clone()));
+        let _writing = self.writing.lock().unwrap();
+        let mut priority = self.priority.lock().unwrap();
+        storage::write_json(&self.dir.join(PRIORITY_FILE), &ids)?;
+        *priority = ids;
+        Ok(())
+    }
+
+    // Every stored guide with its source id, most trusted first; guides
+    // that fail to load are left out
+    pub fn guides(&self) -> Vec<(String, Arc<GuideIndex>)> {
+        self.priority()
+            .into_iter()
+            .filter_map(|id| {
+                let guide = self
+                    .guide(&id)
+                    .inspect_err(|e| log::warn!("Failed to load EPG source '{}': {}", id, e))
+                    .ok()?;
+                Some((id, guide))
+            })
+            .collect()
+    }
+
+    pub fn offsets(&self) -> TimeOffsets {
+        self.offsets.lock().unwrap().clone()
+    }
+
+    fn update_offsets(&self, update: impl FnOnce(&mut TimeOffsets)) -> Result<TimeOffsets, String> {
+        let _writing = self.writing.lock().unwrap();
+        let mut offsets = self.offsets.lock().unwrap();
+        let mut updated = offsets.clone();
+        update(&mut updated);
+        storage::write_json(&self.dir.join(OFFSETS_FILE), &updated)?;
+        *offsets = updated.clone();
+        Ok(updated)
+    }
+
+    // Shift a source's programmes by `seconds`; 0 removes the correction
+    pub fn set_source_offset(&self, id: &str, seconds: i64) -> Result<TimeOffsets, String> {
+        offsets::check_offset(seconds)?;
+        self.update_offsets(|offsets| {
+            if seconds == 0 {
+                offsets.sources.remove(id);
+            } else {
+                offsets.sources.insert(id.to_string(), seconds);
+            }
+        })
+    }
+
+    // Shift a guide channel by `seconds` whatever its source's offset; None
+    // goes back to the source's
+    pub fn set_channel_offset(&self, channel: &str, seconds: Option<i64>) -> Result<TimeOffsets, String> {
+        if let Some(seconds) = seconds {
+            offsets::check_offset(seconds)?;
+        }
+        self.update_offsets(|offsets| match seconds {
+            Some(seconds) => {
+                offsets.channels.insert(channel.to_string(), seconds);
+            }
+            None => {
+                offsets.channels.remove(channel);
+            }
+        })
+    }
+
+    // Channels the source's guide lists; empty for guides that list none
+    pub fn channels(&self, id: &str) -> Result<Vec<EpgChannel>, String> {
+        if self.updated_at(id).is_none() {
+            return Err(format!("EPG source '{}' doesn't exist", id));
+        }
+        storage::read_json(&self.channels_path(id))
+    }
+
+    // Every channel that has programmes with its source, most trusted
+    // source first; names come from the guide's channel list
+    pub fn guide_channels(&self) -> Vec<(String, EpgChannel)> {
+        let mut found = Vec::new();
+        for id in self.priority() {
+            let Ok(guide) = self.guide(&id) else {
+                continue;
+            };
+            let mut listed: HashMap<String, EpgChannel> = self
+                .channels(&id)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|channel| (channel.id.clone(), channel))
+                .collect();
+            let mut channels: Vec<EpgChannel> = guide
+                .channel_ids()
+                .map(|channel| {
+                    listed.remove(channel).unwrap_or_else(|| EpgChannel {
+                        id: channel.to_string(),
+                        ..Default::default()
+                    })
+                })
+                .collect();
+            channels.sort_by(|a, b| a.id.cmp(&b.id));
+            found.extend(channels.into_iter().map(|channel| (id.clone(), channel)));
+        }
+        found
+    }
+
+    // A guide channel's programmes overlapping [from, to), merged from every
+    // guide and with time offsets applied; read from the database without
+    // loading any guide
+    pub fn programmes(&self, channel: &str, from: i64, to: i64) -> Vec<Programme> {
+        // Stored times are uncorrected, and no offset is larger than MAX_OFFSET
+        let stored = self
+            .database
+            .window(channel, from.saturating_sub(offsets::MAX_OFFSET), to.saturating_add(offsets::MAX_OFFSET))
+            .unwrap_or_else(|e| {
+                log::warn!("Failed to read the guide of channel '{}': {}", channel, e);
+                Vec::new()
+            });
+        self.merged(channel, &stored, from, to)
+    }
+
+    // A channel's programmes as the database returns them, with their
+    // source ids and by start time, corrected and merged; those that end
+    // up outside [from, to) are left out
+    fn merged(&self, channel: &str, stored: &[(String, Programme)], from: i64, to: i64) -> Vec<Programme> {
+        let offsets = self.offsets();
+        let runs: Vec<Vec<Programme>> = self
+            .priority()
+            .into_iter()
+            .map(|id| {
+                let offset = offsets.offset(&id, channel);
+                stored
+                    .iter()
+                    .filter(|(source, programme)| *source == id && programme.channel == channel)
+                    .map(|(_, programme)| offsets::shift(programme, offset))
+                    .filter(|programme| programme.start < to && programme.stop > from)
+                    .collect::<Vec<_>>()
+            })
+            .filter(|run| !run.is_empty())
+            .collect();
+        merge::merge(&runs.iter().map(Vec::as_slice).collect::<Vec<_>>())
+    }
+
+    // A programme by its id, with time offsets applied
+    pub fn find_programme(&self, id: &str) -> Result<Programme, String> {
+        let (channel, start) = parse_programme_id(id).ok_or_else(|| format!("Invalid programme id '{}'", id))?;
+        self.programmes(channel, start, start + 1)
+            .into_iter()
+            .find(|programme| programme.start == start)
+            .ok_or_else(|| "That programme isn't in the guide".to_string())
+    }
+
+    pub fn remove(&self, id: &str) -> Result<(), String> {
+        let _writing = self.writing.lock().unwrap();
+        self.database.remove(id)?;
+        self.loaded.lock().unwrap().remove(id);
+        let mut sources = self.sources.lock().unwrap();
+        let before = sources.len();
+        sources.retain(|s| s.id != id);
+        if sources.len() != before {
+            let _ = fs::remove_file(self.guide_path(id));
+            let _ = fs::remove_file(self.channels_path(id));
+            storage::write_json(&self.dir.join(INDEX_FILE), &*sources)?;
+        }
+        Ok(())
+    }
+}
+
+// A channel's corrected programmes overlapping [from, to) from all guides,
+// merged
+fn merged_window(
+    guides: &[(String, Arc<GuideIndex>)],
+    offsets: &TimeOffsets,
+    channel: &str,
+    from: i64,
+    to: i64,
+) -> Vec<Programme> {
+    let runs: Vec<Vec<Programme>> = guides
+        .iter()
+        .map(|(id, guide)| offsets.window(id, guide, channel, from, to))
+        .filter(|run| !run.is_empty())
+        .collect();
+    merge::merge(&runs.iter().map(Vec::as_slice).collect::<Vec<_>>())
+}
+
+// A programme is known by its guide channel and start time (after time
+// offsets, as queries return it): "<channel>@<start>"
+pub fn programme_id(channel: &str, start: i64) -> String {
+    format!("{}@{}", channel, start)
+}
+
+fn parse_programme_id(id: &str) -> Option<(&str, i64)> {
+    let (channel, start) = id.trim().rsplit_once('@')?;
+    Some((channel, start.parse().ok()?)).filter(|(channel, _)| !channel.is_empty())
+}
+
+// Command handler listing the stored guides
+#[tauri::command]
+pub fn list_epg_sources(store: State<'_, EpgStore>) -> Vec<EpgSource> {
+    store.sources()
+}
+
+// Command handler listing the channels of a stored guide
+#[tauri::command]
+pub fn get_epg_channels(store: State<'_, EpgStore>, source_id: String) -> Result<Vec<EpgChannel>, String> {
+    store.channels(&source_id)
+}
+
+// Command handler returning a channel's programmes between two Unix times
+#[tauri::command]
+pub fn get_epg_programmes(
+    app: AppHandle,
+    store: State<'_, EpgStore>,
+    source_id: String,
+    channel: String,
+    from: i64,
+    to: i64,
+) -> Result<Vec<Programme>, String> {
+    let guide = store.guide(&source_id)?;
+    let mut programmes = store.offsets().window(&source_id, &guide, channel.trim(), from, to);
+    parental::hide(&app, &mut programmes);
+    languages::apply(&app, &mut programmes);
+    artwork::localize(&app, &mut programmes);
+    Ok(programmes)
+}
+
+// What's on a guide channel now and next, as guide queries show it
+pub fn now_next(app: &AppHandle, channel: &str, now: i64) -> NowNext {
+    let mut programmes = app.state::<EpgStore>().programmes(channel, now, now + NEXT_HORIZON);
+    parental::hide(app, &mut programmes);
+    languages::apply(app, &mut programmes);
+    merge::now_next(&programmes, now)
+}
+
+// Command handler answering what's on now and next for each of the given
+// guide channel ids
+#[tauri::command]
+pub fn get_now_next(
+    app: AppHandle,
+    store: State<'_, EpgStore>,
+    channel_ids: Vec<String>,
+    now: Option<i64>,
+) -> HashMap<String, NowNext> {
+    let now = now.unwrap_or_else(unix_now);
+    let mut found: HashMap<String, NowNext> = channel_ids
+        .into_iter()
+        .map(|channel| {
+            let mut programmes = store.programmes(channel.trim(), now, now + NEXT_HORIZON);
+            parental::hide(&app, &mut programmes);
+            languages::apply(&app, &mut programmes);
+            (channel, merge::now_next(&programmes, now))
+        })
+        .collect();
+    artwork::localize(
+        &app,
+        found.values_mut().flat_map(|now_next| now_next.now.iter_mut().chain(now_next.next.iter_mut())),
+    );
+    found
+}
+
+// Command handler returning a guide channel's programmes between two Unix
+// times, merged from every guide that has it
+#[tauri::command]
+pub fn get_programs(
+    app: AppHandle,
+    store: State<'_, EpgStore>,
+    channel_id: String,
+    from: i64,
+    to: i64,
+) -> Vec<Programme> {
+    let mut programmes = store.programmes(channel_id.trim(), from, to);
+    parental::hide(&app, &mut programmes);
+    languages::apply(&app, &mut programmes);
+    artwork::localize(&app, &mut programmes);
+    programmes
+}
+
+// Command handler linking a loaded playlist's channels to guide channels,
+// manual mappings first; threshold is the minimum name similarity for
+// fuzzy matches (0.5 to 1.0)
+#[tauri::command]
+pub async fn match_epg_channels(
+    app: AppHandle,
+    store: State<'_, PlaylistStore>,
+    mappings: State<'_, mappings::EpgMappings>,
+    playlist_id: String,
+    threshold: Option<f64>,
+) -> Result<MatchReport, String> {
+    let entries = store
+        .get(&playlist_id)
+        .ok_or_else(|| format!("Playlist '{}' is not loaded", playlist_id))?;
+    let manual = mappings.list();
+    let threshold = threshold.unwrap_or(matching::DEFAULT_THRESHOLD).clamp(0.5, 1.0);
+    tauri::async_runtime::spawn_blocking(move || {
+        let matcher = matching::Matcher::new(app.state::<EpgStore>().guide_channels());
+        matcher.report(&entries, &manual, threshold)
+    })
+    .await
+    .map_err(|e| format!("EPG matching failed: {}", e))
+}
+
+// Command handler listing the stored sources, most trusted first
+#[tauri::command]
+pub fn get_epg_priority(store: State<'_, EpgStore>) -> Vec<String> {
+    store.priority()
+}
+
+// Command handler ordering the sources, most trusted first; sources left
+// out keep their place after the listed ones
+#[tauri::command]
+pub fn set_epg_priority(store: State<'_, EpgStore>, source_ids: Vec<String>) -> Result<Vec<String>, String> {
+    store.set_priority(source_ids)?;
+    Ok(store.priority())
+}
+
+// Command handler returning one page of the guide grid: the playlist's
+// channels in the page with the guide channels they're matched to and the
+// programmes in the time window
+#[tauri::command]
+pub async fn get_epg_grid(
+    app: AppHandle,
+    store: State<'_, PlaylistStore>,
+    mappings: State<'_, mappings::EpgMappings>,
+    channel_page: ChannelPage,
+    time_window: TimeWindow,
+) -> Result<EpgGrid, String> {
+    if time_window.to <= time_window.from {
+        return Err("The grid's time window is empty".to_string());
+    }
+    let entries = store
+        .get(&channel_page.playlist_id)
+        .ok_or_else(|| format!("Playlist '{}' is not loaded", channel_page.playlist_id))?;
+    let manual = mappings.list();
+    tauri::async_runtime::spawn_blocking(move || {
+        let store = app.state::<EpgStore>();
+        let source = grid::GridSource {
+            guides: &store.guides(),
+            offsets: &store.offsets(),
+            matcher: &matching::Matcher::new(store.guide_channels()),
+            manual: &manual,
+            threshold: matching::DEFAULT_THRESHOLD,
+        };
+        let mut grid = grid::grid(&source, &entries, &channel_page, time_window);
+        for row in &mut grid.rows {
+            parental::hide(&app, &mut row.programmes);
+            languages::apply(&app, &mut row.programmes);
+        }
+        artwork::localize(&app, grid.rows.iter_mut().flat_map(|row| row.programmes.iter_mut()));
+        grid
+    })
+    .await
+    .map_err(|e| format!("Failed to build the guide grid: {}", e))
+}
+
+// Command handler finding programmes whose title or description has words
+// starting with every word of the query, in any of the genres given and on
+// the given guide channels; without a time range it searches what's on
+// from now on
+#[tauri::command]
+pub async fn search_epg(
+    app: AppHandle,
+    query: String,
+    time_range: Option<TimeRange>,
+    genres: Option<Vec<String>>,
+    channel_filter: Option<Vec<String>>,
+) -> Result<Vec<SearchResult>, String> {
+    validate_string_length(&query, MAX_QUERY_LENGTH)?;
+    let range = time_range.unwrap_or_default();
+    let trimmed = |values: Option<Vec<String>>| -> Vec<String> {
+        values
+            .unwrap_or_default()
+            .iter()
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+            .collect()
+    };
+    let query = search::SearchQuery {
+        genres: trimmed(genres),
+        channels: trimmed(channel_filter),
+        ..search::SearchQuery::new(&query, range.from.unwrap_or_else(unix_now), range.to.unwrap_or(i64::MAX))
+    };
+    if query.terms.is_empty() && query.genres.is_empty() {
+        return Err("Enter something to search for or pick a genre".to_string());
+    }
+    tauri::async_runtime::spawn_blocking(move || {
+        let store = app.state::<EpgStore>();
+        let mut listed = HashMap::new();
+        for (_, channel) in store.guide_channels() {
+            listed.entry(channel.id.clone()).or_insert(channel);
+        }
+        let mut found = search::search(&store, &listed, &query)?;
+        let controls = app.state::<parental::ParentalControls>();
+        let now = unix_now();
+        found.retain(|result| !controls.hidden(&result.programme, now));
+        languages::apply(&app, found.iter_mut().map(|result| &mut result.programme));
+        artwork::localize(&app, found.iter_mut().map(|result| &mut result.programme));
+        Ok(found)
+    })
+    .await
+    .map_err(|e| format!("EPG search failed: {}", e))?
+}
+
+// Command handler writing the guide of a loaded playlist's matched
+// channels (all of them when `channel_ids` is empty) to an XMLTV file;
+// returns the number of programmes written
+#[tauri::command]
+pub async fn export_epg(
+    app: AppHandle,
+    store: State<'_, PlaylistStore>,
+    mappings: State<'_, mappings::EpgMappings>,
+    playlist_id: String,
+    channel_ids: Option<Vec<String>>,
+    path: String,
+) -> Result<usize, String> {
+    validate_string_length(&path, MAX_PATH_LENGTH)?;
+    let path = path.trim().to_string();
+    if path.is_empty() {
+        return Err("Export path cannot be empty".to_string());
+    }
+    let entries = store
+        .get(&playlist_id)
+        .ok_or_else(|| format!("Playlist '{}' is not loaded", playlist_id))?;
+    let manual = mappings.list();
+    tauri::async_runtime::spawn_blocking(move || {
+        let store = app.state::<EpgStore>();
+        let matcher = matching::Matcher::new(store.guide_channels());
+        let channels = export::export_channels(
+            &entries,
+            &matcher,
+            &manual,
+            matching::DEFAULT_THRESHOLD,
+            &channel_ids.unwrap_or_default(),
+        );
+        let (xml, total) = export::to_xmltv(&store.guides(), &store.offsets(), &channels);
+        fs::write(&path, xml).map_err(|e| format!("Failed to write {}: {}", path, e))?;
+        Ok(total)
+    })
+    .await
+    .map_err(|e| format!("EPG export failed: {}", e))?
+}
+
+// Command handler listing the past programmes of a loaded playlist's
+// channel that its catch-up archive can still play back, oldest first,
+// with their archive URLs; programmes parental controls restrict aren't
+#[tauri::command]
+pub async fn get_archive_programs(
+    app: AppHandle,
+    store: State<'_, PlaylistStore>,
+    mappings: State<'_, mappings::EpgMappings>,
+    playlist_id: String,
+    channel_id: String,
+) -> Result<Vec<archive::ArchiveProgramme>, String> {
+    let entry = store
+        .get(&playlist_id)
+        .ok_or_else(|| format!("Playlist '{}' is not loaded", playlist_id))?
+        .iter()
+        .find(|entry| entry.channel_id == channel_id.trim())
+        .cloned()
+        .ok_or_else(|| format!("Channel '{}' isn't in the playlist", channel_id))?;
+    let now = unix_now();
+    let (from, to) = archive::archive_range(&entry, now)?;
+    let manual = mappings.list();
+    tauri::async_runtime::spawn_blocking(move || {
+        let store = app.state::<EpgStore>();
+        let matcher = matching::Matcher::new(store.guide_channels());
+        let manual = manual.get(&entry.channel_id).map(String::as_str);
+        let found = matcher
+            .find(&entry, manual, matching::DEFAULT_THRESHOLD)
+            .map_err(|_| format!("Channel '{}' has no guide data", entry.name))?;
+        let mut programmes = store.programmes(&found.epg_channel_id, from, to);
+        parental::block(&app, &mut programmes);
+        languages::apply(&app, &mut programmes);
+        artwork::localize(&app, &mut programmes);
+        Ok(archive::archived(&entry, programmes, now))
+    })
+    .await
+    .map_err(|e| format!("Failed to list archived programmes: {}", e))?
+}
+
+// Command handler reporting, per group of a loaded playlist, how many
+// channels have guide data, how many days of it and from which source
+#[tauri::command]
+pub async fn get_epg_coverage(
+    app: AppHandle,
+    store: State<'_, PlaylistStore>,
+    mappings: State<'_, mappings::EpgMappings>,
+    playlist_id: String,
+) -> Result<Vec<coverage::GroupCoverage>, String> {
+    let entries = store
+        .get(&playlist_id)
+        .ok_or_else(|| format!("Playlist '{}' is not loaded", playlist_id))?;
+    let manual = mappings.list();
+    tauri::async_runtime::spawn_blocking(move || {
+        let store = app.state::<EpgStore>();
+        let matcher = matching::Matcher::new(store.guide_channels());
+        coverage::coverage(&store.guides(), &store.offsets(), &entries, &matcher, &manual, unix_now())
+    })
+    .await
+    .map_err(|e| format!("Failed to work out EPG coverage: {}", e))
+}
+
+// Command handler returning the time corrections by source and channel
+#[tauri::command]
+pub fn get_epg_offsets(store: State<'_, EpgStore>) -> TimeOffsets {
+    store.offsets()
+}
+
+// Command handler shifting every programme of a source by `seconds`, e.g.
+// 10800 for a guide published three hours early; 0 removes the correction
+#[tauri::command]
+pub fn set_epg_source_offset(
+    store: State<'_, EpgStore>,
+    source_id: String,
+    seconds: i64,
+) -> Result<TimeOffsets, String> {
+    store.set_source_offset(source_id.trim(), seconds)
+}
+
+// Command handler shifting one guide channel by `seconds` in place of its
+// source's correction; no seconds removes the override
+#[tauri::command]
+pub fn set_epg_channel_offset(
+    store: State<'_, EpgStore>,
+    channel_id: String,
+    seconds: Option<i64>,
+) -> Result<TimeOffsets, String> {
+    store.set_channel_offset(channel_id.trim(), seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    fn programme(channel: &str, start: i64) -> Programme {
+        Programme {
+            channel: channel.to_string(),
+            start,
+            stop: start + 100,
+            title: format!("{}@{}", channel, start),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_store_roundtrip() {
+        // Test that a stored guide survives reopening and can be removed
+        let dir = temp_dir("epg-store");
+        let store = EpgStore::open(dir.clone());
+        let guide = Guide {
+            channels: vec![EpgChannel {
+                id: "a".to_string(),
+                names: vec!["A".to_string()],
+                icon: None,
+            }],
+            programmes: vec![programme("b", 0), programme("a", 100), programme("a", 0)],
+        };
+        let source = store.replace("xtream:p", guide, 42).unwrap();
+        assert_eq!((source.programmes, source.channels), (3, 2));
+
+        let reopened = EpgStore::open(dir.clone());
+        assert_eq!(reopened.updated_at("xtream:p"), Some(42));
+        let guide = reopened.guide("xtream:p").unwrap();
+        assert_eq!(guide.programmes()[0], programme("a", 0));
+        assert_eq!(reopened.channels("xtream:p").unwrap()[0].names, vec!["A"]);
+
+        reopened.replace("xmltv:x", Guide::default(), 43).unwrap();
+        assert_eq!(reopened.priority(), vec!["xtream:p", "xmltv:x"]);
+        reopened.set_priority(vec![" xmltv:x".to_string(), "xmltv:x".to_string()]).unwrap();
+        assert_eq!(EpgStore::open(dir.clone()).priority(), vec!["xmltv:x", "xtream:p"]);
+        let channels: Vec<_> = reopened.guide_channels().into_iter().map(|(_, c)| (c.id, c.names.len())).collect();
+        assert_eq!(channels, vec![("a".to_string(), 1), ("b".to_string(), 0)]);
+        reopened.set_source_offset("xmltv:x", 3600).unwrap();
+        reopened.set_channel_offset("a", Some(-60)).unwrap();
+        assert!(reopened.set_source_offset("xmltv:x", 2 * offsets::MAX_OFFSET).is_err());
+        let offsets = EpgStore::open(dir.clone()).offsets();
+        assert_eq!((offsets.offset("xmltv:x", "b"), offsets.offset("xmltv:x", "a")), (3600, -60));
+        assert_eq!(reopened.find_programme(&programme_id("a", -60)).unwrap().title, "a@0");
+        assert!(reopened.find_programme("a@0").is_err() && reopened.find_programme("@-60").is_err());
+        reopened.remove("xtream:p").unwrap();
+        assert!(reopened.guide("xtream:p").is_err());
+    }
+
+    #[test]
+    fn test_channel_queries_load_no_guides() {
+        // Test that channel queries are answered from the database without loading guides, also after reopening
+        let dir = temp_dir("epg-channels");
+        let store = EpgStore::open(dir.clone());
+        store.replace("xmltv:a", Guide::from(vec![programme("a", 0)]), 1).unwrap();
+        store.replace("xmltv:b", Guide::from(vec![programme("b", 0), programme("a", 100)]), 1).unwrap();
+        let reopened = EpgStore::open(dir);
+        assert_eq!(reopened.programmes("a", 0, 200).len(), 2);
+        assert!(reopened.programmes("c", 0, 200).is_empty());
+        assert!(reopened.loaded.lock().unwrap().is_empty());
+        reopened.remove("xmltv:b").unwrap();
+        assert_eq!(reopened.programmes("a", 0, 200), vec![programme("a", 0)]);
+    }
+
+    #[test]
+    fn test_guide_files_are_imported() {
+        // Test that a guide stored as a file before the guide database moves into it on opening
+        let dir = temp_dir("epg-import");
+        let store = EpgStore::open(dir.clone());
+        store.replace("xmltv:a", Guide::from(vec![programme("a", 0)]), 1).unwrap();
+        let json = serde_json::to_vec(&[programme("a", 100), programme("b", 0)]).unwrap();
+        storage::write_json_gz(&store.guide_path("xmltv:a"), &json).unwrap();
+        let reopened = EpgStore::open(dir);
+        assert!(!reopened.guide_path("xmltv:a").exists());
+        assert_eq!(reopened.programmes("a", 0, 1000), vec![programme("a", 100)]);
+        assert_eq!(reopened.guide("xmltv:a").unwrap().programmes().len(), 2);
+    }
+}
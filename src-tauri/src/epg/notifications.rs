@@ -0,0 +1,38 @@
+// OS notifications for programmes about to start, raised by the backend so
+// they show while the window is hidden or closed to the tray. The matching
+// event still goes to the webview, which opens the channel when asked
+use tauri::AppHandle;
+use tauri_plugin_notification::NotificationExt;
+
+fn starts_in(start: i64, now: i64) -> String {
+    match (start - now + 59) / 60 {
+        ..=0 => "Starting now".to_string(),
+        1 => "Starts in 1 minute".to_string(),
+        minutes => format!("Starts in {} minutes", minutes),
+    }
+}
+
+// Show a notification about a programme; `detail` follows the start time
+pub fn programme(app: &AppHandle, title: &str, start: i64, now: i64, detail: Option<&str>) {
+    let mut body = starts_in(start, now);
+    if let Some(detail) = detail {
+        body = format!("{} · {}", body, detail);
+    }
+    if let Err(e) = app.notification().builder().title(title).body(body).show() {
+        log::warn!("Failed to show a notification for '{}': {}", title, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_starts_in() {
+        // Test that the start is given in whole minutes, rounded up
+        assert_eq!(starts_in(1000, 1000), "Starting now");
+        assert_eq!(starts_in(1000, 1200), "Starting now");
+        assert_eq!(starts_in(1030, 1000), "Starts in 1 minute");
+        assert_eq!(starts_in(1000 + 5 * 60, 1000), "Starts in 5 minutes");
+    }
+}
@@ -0,0 +1,82 @@
+// Corrections for guides published in the wrong timezone. Stored guides
+// keep the times they were published with; the offset is applied whenever
+// programmes are read, so changing it never needs a re-download
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::{GuideIndex, Programme};
+
+// Largest correction accepted, in seconds either way
+pub const MAX_OFFSET: i64 = 24 * 60 * 60;
+
+// Seconds added to programme times
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TimeOffsets {
+    // By EPG source id
+    pub sources: BTreeMap<String, i64>,
+    // By guide channel id, replacing the source offset in every guide
+    pub channels: BTreeMap<String, i64>,
+}
+
+impl TimeOffsets {
+    pub fn offset(&self, source_id: &str, channel: &str) -> i64 {
+        self.channels
+            .get(channel)
+            .or_else(|| self.sources.get(source_id))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    // A channel's corrected programmes overlapping [from, to) in a guide
+    pub fn window(&self, source_id: &str, guide: &GuideIndex, channel: &str, from: i64, to: i64) -> Vec<Programme> {
+        let offset = self.offset(source_id, channel);
+        guide
+            .window(channel, from.saturating_sub(offset), to.saturating_sub(offset))
+            .iter()
+            .map(|programme| shift(programme, offset))
+            .collect()
+    }
+}
+
+pub fn shift(programme: &Programme, offset: i64) -> Programme {
+    Programme {
+        start: programme.start + offset,
+        stop: programme.stop + offset,
+        ..programme.clone()
+    }
+}
+
+pub fn check_offset(seconds: i64) -> Result<(), String> {
+    if seconds.abs() > MAX_OFFSET {
+        return Err(format!("Time offset must be within {} hours", MAX_OFFSET / 3600));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offsets_shift_windows() {
+        // Test that channel offsets replace source offsets and windows are looked up in guide time
+        let guide = GuideIndex::new(vec![Programme {
+            channel: "a".to_string(),
+            start: 0,
+            stop: 100,
+            title: "News".to_string(),
+            ..Default::default()
+        }]);
+        let mut offsets = TimeOffsets::default();
+        offsets.sources.insert("s".to_string(), 3600);
+        offsets.channels.insert("b".to_string(), 0);
+        assert_eq!((offsets.offset("s", "a"), offsets.offset("s", "b"), offsets.offset("t", "a")), (3600, 0, 0));
+
+        let shifted = offsets.window("s", &guide, "a", 3650, 3700);
+        assert_eq!((shifted[0].start, shifted[0].stop), (3600, 3700));
+        assert!(offsets.window("s", &guide, "a", 50, 100).is_empty());
+        assert!(check_offset(-MAX_OFFSET).is_ok() && check_offset(MAX_OFFSET + 1).is_err());
+    }
+}
@@ -0,0 +1,577 @@
+// Parental controls from guide content ratings. Ratings come in many
+// systems (MPAA, VCHIP, FSK, BBFC, CSA, ...); each is read as the minimum
+// age it names. Programmes rated above the configured age are hidden from
+// the guide or only kept from playing, until the PIN unlocks them. The
+// backend holds the block: stream URLs, native playback and catch-up URLs
+// are refused while a restricted programme is on, and what plays is
+// checked again as programmes change
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use super::mappings::EpgMappings;
+use super::{EpgStore, Programme};
+use crate::playlist::PlaylistStore;
+use crate::{storage, unix_now, validate_string_length};
+
+const PARENTAL_FILE: &str = "parental.json";
+
+const DEFAULT_MAX_AGE: u8 = 12;
+const MAX_AGE: u8 = 21;
+
+// How long a PIN unlocks restricted programmes
+const UNLOCK_DURATION: i64 = 4 * 60 * 60;
+
+// Wrong PINs allowed before entry pauses, and for how long
+const MAX_ATTEMPTS: u32 = 5;
+const LOCKOUT: i64 = 60;
+
+// How often what plays is checked for a restricted programme coming on
+pub const RECHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+// How long a channel without guide data for now counts as showing nothing
+// rated
+const UNKNOWN_ON_AIR: i64 = 60;
+
+const MAX_ID_LENGTH: usize = 2048;
+
+// A rating as the guide gives it, e.g. system "MPAA", value "PG-13"
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContentRating {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+    pub value: String,
+}
+
+// The minimum age a rating names: the first number in it ("PG-13",
+// "FSK 16", "12A", "TV-Y7", "-16") or a known letter rating
+pub fn minimum_age(rating: &ContentRating) -> Option<u8> {
+    let value = rating.value.trim().to_ascii_uppercase();
+    let digits: String = value
+        .chars()
+        .skip_while(|c| !c.is_ascii_digit())
+        .take_while(char::is_ascii_digit)
+        .collect();
+    if let Ok(age) = digits.parse::<u32>() {
+        return Some(age.min(u32::from(MAX_AGE)) as u8);
+    }
+    Some(match value.as_str() {
+        "G" | "U" | "E" | "TV-Y" | "TV-G" | "ALL" | "TP" | "AL" => 0,
+        "PG" | "TV-PG" | "UC" => 8,
+        "M" => 15,
+        "R" | "TV-MA" | "MA" => 17,
+        "X" | "XXX" | "ADULT" | "NC" => 18,
+        _ => return None,
+    })
+}
+
+// The highest minimum age among a programme's ratings
+pub fn programme_age(programme: &Programme) -> Option<u8> {
+    programme.ratings.iter().filter_map(minimum_age).max()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FilterMode {
+    // Restricted programmes leave the guide and can't be played
+    #[default]
+    Hide,
+    // Restricted programmes stay in the guide but can't be played
+    Block,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ParentalSettings {
+    pub enabled: bool,
+    // Oldest minimum age allowed without the PIN
+    pub max_age: u8,
+    pub mode: FilterMode,
+}
+
+impl Default for ParentalSettings {
+    fn default() -> Self {
+        ParentalSettings {
+            enabled: false,
+            max_age: DEFAULT_MAX_AGE,
+            mode: FilterMode::Hide,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct StoredParental {
+    settings: ParentalSettings,
+    // Hex SHA-256 of salt and PIN
+    pin_hash: Option<String>,
+    pin_salt: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParentalStatus {
+    #[serde(flatten)]
+    pub settings: ParentalSettings,
+    pub has_pin: bool,
+    // Unix seconds until which restricted programmes are allowed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unlocked_until: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaybackCheck {
+    pub allowed: bool,
+    // The programme on air and its minimum age, when rated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub age: Option<u8>,
+}
+
+// The rating of what's on a guide channel, and until when it's on
+#[derive(Debug, Clone, Default)]
+struct OnAir {
+    until: i64,
+    title: Option<String>,
+    age: Option<u8>,
+}
+
+#[derive(Default)]
+struct Session {
+    unlocked_until: i64,
+    failed_attempts: u32,
+    retry_at: i64,
+}
+
+fn hash_pin(salt: &str, pin: &str) -> String {
+    Sha256::digest(format!("{}:{}", salt, pin).as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn check_pin_format(pin: &str) -> Result<(), String> {
+    if !(4..=12).contains(&pin.len()) || !pin.bytes().all(|b| b.is_ascii_digit()) {
+        return Err("The PIN must be 4 to 12 digits".to_string());
+    }
+    Ok(())
+}
+
+pub struct ParentalControls {
+    path: PathBuf,
+    stored: Mutex<StoredParental>,
+    session: Mutex<Session>,
+    // What's on the guide channels checked lately, kept until it ends
+    on_air: Mutex<HashMap<String, OnAir>>,
+}
+
+impl ParentalControls {
+    pub fn open(data_dir: &Path) -> Self {
+        let path = data_dir.join(PARENTAL_FILE);
+        let stored = storage::read_json(&path).unwrap_or_else(|e| {
+            log::error!("Failed to load parental controls: {}", e);
+            StoredParental::default()
+        });
+        Self {
+            path,
+            stored: Mutex::new(stored),
+            session: Mutex::new(Session::default()),
+            on_air: Mutex::default(),
+        }
+    }
+
+    pub fn status(&self, now: i64) -> ParentalStatus {
+        let stored = self.stored.lock().unwrap();
+        let unlocked_until = self.session.lock().unwrap().unlocked_until;
+        ParentalStatus {
+            settings: stored.settings.clone(),
+            has_pin: stored.pin_hash.is_some(),
+            unlocked_until: Some(unlocked_until).filter(|until| *until > now),
+        }
+    }
+
+    // Check a PIN, pausing entry after too many wrong ones; passes when no
+    // PIN is set
+    fn verify(&self, pin: Option<&str>, now: i64) -> Result<(), String> {
+        let stored = self.stored.lock().unwrap();
+        let Some(expected) = &stored.pin_hash else {
+            return Ok(());
+        };
+        let mut session = self.session.lock().unwrap();
+        if session.retry_at > now {
+            return Err(format!("Too many wrong PINs, try again in {} seconds", session.retry_at - now));
+        }
+        if pin.is_some_and(|pin| hash_pin(&stored.pin_salt, pin.trim()) == *expected) {
+            session.failed_attempts = 0;
+            return Ok(());
+        }
+        session.failed_attempts += 1;
+        if session.failed_attempts >= MAX_ATTEMPTS {
+            session.failed_attempts = 0;
+            session.retry_at = now + LOCKOUT;
+        }
+        Err("Wrong PIN".to_string())
+    }
+
+    fn save(&self, change: impl FnOnce(&mut StoredParental)) -> Result<(), String> {
+        let mut stored = self.stored.lock().unwrap();
+        let mut updated = stored.clone();
+        change(&mut updated);
+        storage::write_json(&self.path, &updated)?;
+        *stored = updated;
+        Ok(())
+    }
+
+    pub fn set_settings(&self, settings: ParentalSettings, pin: Option<&str>, now: i64) -> Result<(), String> {
+        if settings.max_age > MAX_AGE {
+            return Err(format!("The age limit can be at most {}", MAX_AGE));
+        }
+        if settings.enabled && self.stored.lock().unwrap().pin_hash.is_none() {
+            return Err("Set a PIN before turning on parental controls".to_string());
+        }
+        self.verify(pin, now)?;
+        self.save(|stored| stored.settings = settings)
+    }
+
+    pub fn set_pin(&self, current: Option<&str>, pin: &str, now: i64) -> Result<(), String> {
+        let pin = pin.trim();
+        check_pin_format(pin)?;
+        self.verify(current, now)?;
+        let salt = format!("{:016x}", RandomState::new().build_hasher().finish());
+        self.save(|stored| {
+            stored.pin_hash = Some(hash_pin(&salt, pin));
+            stored.pin_salt = salt;
+        })
+    }
+
+    pub fn unlock(&self, pin: &str, now: i64) -> Result<(), String> {
+        if self.stored.lock().unwrap().pin_hash.is_none() {
+            return Err("No PIN is set".to_string());
+        }
+        self.verify(Some(pin), now)?;
+        self.session.lock().unwrap().unlocked_until = now + UNLOCK_DURATION;
+        Ok(())
+    }
+
+    pub fn lock(&self) {
+        self.session.lock().unwrap().unlocked_until = 0;
+    }
+
+    // Whether anything is held back right now
+    fn active(&self, now: i64) -> bool {
+        self.stored.lock().unwrap().settings.enabled && self.session.lock().unwrap().unlocked_until <= now
+    }
+
+    // Whether a minimum age is above the age limit right now
+    fn blocks(&self, age: Option<u8>, now: i64) -> bool {
+        let max_age = self.stored.lock().unwrap().settings.max_age;
+        self.active(now) && age.is_some_and(|age| age > max_age)
+    }
+
+    // Whether a programme is above the age limit right now
+    pub fn restricted(&self, programme: &Programme, now: i64) -> bool {
+        self.blocks(programme_age(programme), now)
+    }
+
+    fn check(&self, on_air: OnAir, now: i64) -> PlaybackCheck {
+        PlaybackCheck {
+            allowed: !self.blocks(on_air.age, now),
+            title: on_air.title,
+            age: on_air.age,
+        }
+    }
+
+    // Whether a programme is left out of guide results
+    pub fn hidden(&self, programme: &Programme, now: i64) -> bool {
+        let mode = self.stored.lock().unwrap().settings.mode;
+        mode == FilterMode::Hide && self.restricted(programme, now)
+    }
+}
+
+// Drop the programmes parental controls hide from guide results
+pub fn hide(app: &AppHandle, programmes: &mut Vec<Programme>) {
+    let controls = app.state::<ParentalControls>();
+    let now = unix_now();
+    programmes.retain(|programme| !controls.hidden(programme, now));
+}
+
+// Drop the programmes parental controls keep from playing, from lists of
+// programmes to play back
+pub fn block(app: &AppHandle, programmes: &mut Vec<Programme>) {
+    let controls = app.state::<ParentalControls>();
+    let now = unix_now();
+    programmes.retain(|programme| !controls.restricted(programme, now));
+}
+
+// The programme on a guide channel at `at`
+fn airing(store: &EpgStore, epg_channel_id: &str, at: i64) -> OnAir {
+    let programme = store.programmes(epg_channel_id, at, at + 1).into_iter().find(|programme| programme.start <= at);
+    match programme {
+        Some(programme) => OnAir {
+            until: programme.stop,
+            age: programme_age(&programme),
+            title: Some(programme.title),
+        },
+        None => OnAir {
+            until: at + UNKNOWN_ON_AIR,
+            ..Default::default()
+        },
+    }
+}
+
+// What's on a guide channel now, looked up once per programme
+fn on_air(app: &AppHandle, epg_channel_id: &str, now: i64) -> OnAir {
+    let controls = app.state::<ParentalControls>();
+    let known = controls.on_air.lock().unwrap().get(epg_channel_id).cloned();
+    if let Some(known) = known.filter(|known| known.until > now) {
+        return known;
+    }
+    let found = airing(&app.state::<EpgStore>(), epg_channel_id, now);
+    let mut on_air = controls.on_air.lock().unwrap();
+    on_air.retain(|_, known| known.until > now);
+    on_air.insert(epg_channel_id.to_string(), found.clone());
+    found
+}
+
+fn refusal(check: PlaybackCheck) -> Result<(), String> {
+    if check.allowed {
+        return Ok(());
+    }
+    let title = check.title.unwrap_or_default();
+    let age = check.age.unwrap_or_default();
+    Err(format!("'{}' is rated {}+ and needs the parental PIN", title, age))
+}
+
+// The guide channel a stream shows: the one given, else the one mapped to
+// its channel by hand, else the channel's tvg-id
+pub fn guide_channel(
+    app: &AppHandle,
+    epg_channel_id: Option<&str>,
+    channel_id: Option<&str>,
+) -> Result<Option<String>, String> {
+    if let Some(id) = epg_channel_id.map(str::trim).filter(|id| !id.is_empty()) {
+        validate_string_length(id, MAX_ID_LENGTH)?;
+        return Ok(Some(id.to_string()));
+    }
+    let Some(channel_id) = channel_id.map(str::trim).filter(|id| !id.is_empty()) else {
+        return Ok(None);
+    };
+    if let Some(mapped) = app.state::<EpgMappings>().get(channel_id) {
+        return Ok(Some(mapped));
+    }
+    let Some((playlist_id, _)) = channel_id.split_once(':') else {
+        return Ok(None);
+    };
+    let entries = app.state::<PlaylistStore>().get(playlist_id);
+    let entry = entries.as_ref().and_then(|entries| entries.iter().find(|entry| entry.channel_id == channel_id));
+    Ok(entry.and_then(|entry| entry.tvg_id.clone()).filter(|id| !id.is_empty()))
+}
+
+// Err when the programme on a guide channel now may not play
+pub fn enforce(app: &AppHandle, epg_channel_id: &str) -> Result<(), String> {
+    let controls = app.state::<ParentalControls>();
+    let now = unix_now();
+    if !controls.active(now) {
+        return Ok(());
+    }
+    refusal(controls.check(on_air(app, epg_channel_id, now), now))
+}
+
+// enforce, off the async runtime since the guide may have to be loaded
+pub async fn enforce_live(app: &AppHandle, epg_channel_id: &str) -> Result<(), String> {
+    if !app.state::<ParentalControls>().active(unix_now()) {
+        return Ok(());
+    }
+    let (app, id) = (app.clone(), epg_channel_id.to_string());
+    tauri::async_runtime::spawn_blocking(move || enforce(&app, &id))
+        .await
+        .map_err(|e| format!("Parental check failed: {}", e))?
+}
+
+// Err when the programme on a guide channel at `start` may not be played
+// back from the archive
+pub fn enforce_archive(app: &AppHandle, epg_channel_id: &str, start: i64) -> Result<(), String> {
+    let controls = app.state::<ParentalControls>();
+    let now = unix_now();
+    if !controls.active(now) {
+        return Ok(());
+    }
+    refusal(controls.check(airing(&app.state::<EpgStore>(), epg_channel_id, start), now))
+}
+
+// Tell the UI playback stopped for a restricted programme
+pub fn blocked(app: &AppHandle, message: &str) {
+    let _ = app.emit("parental-blocked", message);
+}
+
+// Start the loop checking what plays natively as programmes change,
+// stopping it when a restricted one comes on; runs for the lifetime of the
+// app. Streams through the proxy are checked as they're read
+pub fn start(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            tokio::time::sleep(RECHECK_INTERVAL).await;
+            let Some(guide) = crate::playback::playing_guide(&app) else {
+                continue;
+            };
+            if let Err(message) = enforce_live(&app, &guide).await {
+                blocked(&app, &message);
+                if let Err(e) = crate::playback::stop_playing(&app).await {
+                    log::warn!("Failed to stop a restricted programme: {}", e);
+                }
+            }
+        }
+    });
+}
+
+// Command handler returning the parental control settings
+#[tauri::command]
+pub fn get_parental_controls(controls: State<'_, ParentalControls>) -> ParentalStatus {
+    controls.status(unix_now())
+}
+
+// Command handler changing the parental control settings; needs the PIN
+// once one is set
+#[tauri::command]
+pub fn set_parental_controls(
+    controls: State<'_, ParentalControls>,
+    settings: ParentalSettings,
+    pin: Option<String>,
+) -> Result<ParentalStatus, String> {
+    let now = unix_now();
+    controls.set_settings(settings, pin.as_deref(), now)?;
+    Ok(controls.status(now))
+}
+
+// Command handler setting a new PIN; the current one is needed to change it
+#[tauri::command]
+pub fn set_parental_pin(
+    controls: State<'_, ParentalControls>,
+    current_pin: Option<String>,
+    pin: String,
+) -> Result<ParentalStatus, String> {
+    let now = unix_now();
+    controls.set_pin(current_pin.as_deref(), &pin, now)?;
+    Ok(controls.status(now))
+}
+
+// Command handler allowing restricted programmes for a while
+#[tauri::command]
+pub fn unlock_parental_controls(controls: State<'_, ParentalControls>, pin: String) -> Result<ParentalStatus, String> {
+    let now = unix_now();
+    controls.unlock(&pin, now)?;
+    Ok(controls.status(now))
+}
+
+// Command handler ending an unlock early
+#[tauri::command]
+pub fn lock_parental_controls(controls: State<'_, ParentalControls>) -> ParentalStatus {
+    controls.lock();
+    controls.status(unix_now())
+}
+
+// Command handler the player asks before tuning to a channel: whether the
+// programme on air on its guide channel may play. The backend refuses the
+// stream anyway when it may not
+#[tauri::command]
+pub fn check_playback(app: AppHandle, epg_channel_id: String) -> PlaybackCheck {
+    let now = unix_now();
+    let on_air = on_air(&app, epg_channel_id.trim(), now);
+    app.state::<ParentalControls>().check(on_air, now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn test_ratings_and_pin() {
+        // Test that ratings read as ages and restricted programmes show only after unlocking with the PIN
+        let rating = |value: &str| ContentRating {
+            system: None,
+            value: value.to_string(),
+        };
+        let ages: Vec<_> = ["PG-13", "FSK 16", "TV-MA", "U", "12A", "TV-Y7", "-16", "unrated"]
+            .iter()
+            .map(|value| minimum_age(&rating(value)))
+            .collect();
+        assert_eq!(ages, vec![Some(13), Some(16), Some(17), Some(0), Some(12), Some(7), Some(16), None]);
+
+        let controls = ParentalControls::open(&temp_dir("parental"));
+        let film = Programme {
+            title: "Film".to_string(),
+            ratings: vec![rating("PG"), rating("18")],
+            ..Default::default()
+        };
+        let enabled = ParentalSettings {
+            enabled: true,
+            ..Default::default()
+        };
+        assert!(controls.set_settings(enabled.clone(), None, 0).is_err());
+        assert!(controls.set_pin(None, "12a4", 0).is_err());
+        controls.set_pin(None, "1234", 0).unwrap();
+        assert!(controls.set_settings(enabled.clone(), Some("0000"), 0).is_err());
+        controls.set_settings(enabled, Some("1234"), 0).unwrap();
+        assert!(controls.hidden(&film, 0));
+
+        controls.unlock("1234", 10).unwrap();
+        assert!(!controls.restricted(&film, 20));
+        assert!(controls.restricted(&film, 10 + UNLOCK_DURATION));
+        for _ in 0..MAX_ATTEMPTS {
+            assert!(controls.unlock("0000", 30).is_err());
+        }
+        assert!(controls.unlock("1234", 31).is_err());
+        controls.unlock("1234", 30 + LOCKOUT).unwrap();
+
+        let reopened = ParentalControls::open(controls.path.parent().unwrap());
+        let status = reopened.status(0);
+        assert!(status.settings.enabled && status.has_pin && status.unlocked_until.is_none());
+    }
+
+    #[test]
+    fn test_playback_blocked_on_air() {
+        // Test that the programme on air decides whether a channel may play, until it ends
+        let dir = temp_dir("parental-on-air");
+        let store = EpgStore::open(dir.join("epg"));
+        let film = Programme {
+            channel: "one.uk".to_string(),
+            start: 0,
+            stop: 100,
+            title: "Film".to_string(),
+            ratings: vec![ContentRating {
+                system: Some("MPAA".to_string()),
+                value: "R".to_string(),
+            }],
+            ..Default::default()
+        };
+        store.replace("xmltv:s", crate::epg::Guide::from(vec![film]), 0).unwrap();
+        let controls = ParentalControls::open(&dir);
+        controls.set_pin(None, "1234", 0).unwrap();
+        let enabled = ParentalSettings {
+            enabled: true,
+            ..Default::default()
+        };
+        controls.set_settings(enabled, Some("1234"), 0).unwrap();
+
+        let on_air = airing(&store, "one.uk", 50);
+        assert_eq!((on_air.until, on_air.age), (100, Some(17)));
+        let refused = refusal(controls.check(on_air, 50)).unwrap_err();
+        assert!(refused.contains("'Film' is rated 17+"));
+        let after = airing(&store, "one.uk", 100);
+        assert_eq!((after.until, after.title.as_deref()), (100 + UNKNOWN_ON_AIR, None));
+        assert!(refusal(controls.check(after, 100)).is_ok());
+        controls.unlock("1234", 60).unwrap();
+        assert!(refusal(controls.check(airing(&store, "one.uk", 60), 60)).is_ok());
+    }
+}
@@ -0,0 +1,296 @@
+// Reminders for programmes the user doesn't want to miss. They're kept
+// with a copy of the programme so a guide refresh can't lose them; a
+// background job announces each one a few minutes before it starts and
+// drops it once the programme is over
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use super::{calendar, notifications, programme_id, EpgStore};
+use crate::recording::{RecordingJob, RecordingSchedule};
+use crate::{storage, unix_now, validate_string_length};
+
+const REMINDERS_FILE: &str = "reminders.json";
+
+// Minutes of warning when the user sets nothing
+const DEFAULT_LEAD_MINUTES: u32 = 5;
+const MAX_LEAD_MINUTES: u32 = 24 * 60;
+
+// How often the job looks for reminders that are due
+const CHECK_INTERVAL: Duration = Duration::from_secs(20);
+
+// Limit for ids and paths sent by the UI
+const MAX_ID_LENGTH: usize = 2048;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Reminder {
+    // Programme id, see epg::programme_id
+    pub id: String,
+    // Guide channel id
+    pub channel: String,
+    pub start: i64,
+    pub stop: i64,
+    pub title: String,
+    // Playlist channel to open from the notification
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel_id: Option<String>,
+    // Whether reminder-due went out
+    #[serde(default)]
+    pub notified: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct ReminderState {
+    lead_minutes: Option<u32>,
+    reminders: Vec<Reminder>,
+}
+
+// Payload of the open-channel event
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenChannel {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel_id: Option<String>,
+    pub epg_channel_id: String,
+}
+
+pub struct Reminders {
+    path: PathBuf,
+    state: Mutex<ReminderState>,
+}
+
+impl Reminders {
+    pub fn open(data_dir: &Path) -> Self {
+        let path = data_dir.join(REMINDERS_FILE);
+        let state = storage::read_json(&path).unwrap_or_else(|e| {
+            log::error!("Failed to load reminders: {}", e);
+            ReminderState::default()
+        });
+        Self {
+            path,
+            state: Mutex::new(state),
+        }
+    }
+
+    fn update<T>(&self, change: impl FnOnce(&mut ReminderState) -> T) -> Result<T, String> {
+        let mut state = self.state.lock().unwrap();
+        let mut updated = state.clone();
+        let result = change(&mut updated);
+        if updated != *state {
+            storage::write_json(&self.path, &updated)?;
+            *state = updated;
+        }
+        Ok(result)
+    }
+
+    pub fn list(&self) -> Vec<Reminder> {
+        let mut reminders = self.state.lock().unwrap().reminders.clone();
+        reminders.sort_by_key(|reminder| reminder.start);
+        reminders
+    }
+
+    pub fn get(&self, id: &str) -> Result<Reminder, String> {
+        self.state
+            .lock()
+            .unwrap()
+            .reminders
+            .iter()
+            .find(|reminder| reminder.id == id)
+            .cloned()
+            .ok_or_else(|| format!("There's no reminder for '{}'", id))
+    }
+
+    pub fn lead_minutes(&self) -> u32 {
+        self.state.lock().unwrap().lead_minutes.unwrap_or(DEFAULT_LEAD_MINUTES)
+    }
+
+    pub fn set_lead_minutes(&self, minutes: u32) -> Result<(), String> {
+        if minutes > MAX_LEAD_MINUTES {
+            return Err(format!("Reminders can go out at most {} minutes early", MAX_LEAD_MINUTES));
+        }
+        self.update(|state| state.lead_minutes = Some(minutes))
+    }
+
+    pub fn upsert(&self, reminder: Reminder) -> Result<(), String> {
+        self.update(|state| {
+            state.reminders.retain(|r| r.id != reminder.id);
+            state.reminders.push(reminder);
+        })
+    }
+
+    pub fn remove(&self, id: &str) -> Result<bool, String> {
+        self.update(|state| {
+            let before = state.reminders.len();
+            state.reminders.retain(|r| r.id != id);
+            state.reminders.len() != before
+        })
+    }
+
+    // Reminders to announce at `now`, marked as announced, after dropping
+    // the ones whose programme is over
+    pub fn take_due(&self, now: i64) -> Result<Vec<Reminder>, String> {
+        let lead = i64::from(self.lead_minutes()) * 60;
+        self.update(|state| {
+            state.reminders.retain(|reminder| reminder.stop > now);
+            let mut due = Vec::new();
+            for reminder in state.reminders.iter_mut() {
+                if !reminder.notified && reminder.start - lead <= now {
+                    reminder.notified = true;
+                    due.push(reminder.clone());
+                }
+            }
+            due
+        })
+    }
+}
+
+// Start the reminder loop; runs for the lifetime of the app. Each reminder
+// raises an OS notification and a reminder-due event
+pub fn start(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let now = unix_now();
+            match app.state::<Reminders>().take_due(now) {
+                Ok(due) => {
+                    for reminder in due {
+                        notifications::programme(&app, &reminder.title, reminder.start, now, None);
+                        let _ = app.emit("reminder-due", reminder);
+                    }
+                }
+                Err(e) => log::warn!("Failed to update reminders: {}", e),
+            }
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    });
+}
+
+// Command handler that sets a reminder for a programme; `channel_id` is the
+// playlist channel the notification opens
+#[tauri::command]
+pub fn set_reminder(
+    store: State<'_, EpgStore>,
+    reminders: State<'_, Reminders>,
+    program_id: String,
+    channel_id: Option<String>,
+) -> Result<Reminder, String> {
+    validate_string_length(&program_id, MAX_ID_LENGTH)?;
+    let programme = store.find_programme(&program_id)?;
+    if programme.stop <= unix_now() {
+        return Err("That programme is already over".to_string());
+    }
+    let reminder = Reminder {
+        id: programme_id(&programme.channel, programme.start),
+        channel: programme.channel,
+        start: programme.start,
+        stop: programme.stop,
+        title: programme.title,
+        channel_id: channel_id.map(|id| id.trim().to_string()).filter(|id| !id.is_empty()),
+        notified: false,
+    };
+    reminders.upsert(reminder.clone())?;
+    Ok(reminder)
+}
+
+// Command handler that cancels a reminder
+#[tauri::command]
+pub fn cancel_reminder(reminders: State<'_, Reminders>, program_id: String) -> Result<bool, String> {
+    reminders.remove(program_id.trim())
+}
+
+// Command handler listing the pending reminders, soonest first
+#[tauri::command]
+pub fn list_reminders(reminders: State<'_, Reminders>) -> Vec<Reminder> {
+    reminders.list()
+}
+
+// Command handler setting how many minutes before the start reminders go
+// out
+#[tauri::command]
+pub fn set_reminder_lead_minutes(reminders: State<'_, Reminders>, minutes: u32) -> Result<u32, String> {
+    reminders.set_lead_minutes(minutes)?;
+    Ok(reminders.lead_minutes())
+}
+
+// Command handler for the notification's action: brings the window to the
+// front and asks the UI to tune to the reminder's channel
+#[tauri::command]
+pub fn open_reminder(app: AppHandle, program_id: String) -> Result<Reminder, String> {
+    let reminder = app.state::<Reminders>().get(program_id.trim())?;
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+    let _ = app.emit(
+        "open-channel",
+        OpenChannel {
+            channel_id: reminder.channel_id.clone(),
+            epg_channel_id: reminder.channel.clone(),
+        },
+    );
+    Ok(reminder)
+}
+
+// Command handler writing the pending reminders and recordings to an .ics
+// file for calendar apps; returns the number of events written
+#[tauri::command]
+pub async fn export_reminders_ics(
+    reminders: State<'_, Reminders>,
+    schedule: State<'_, RecordingSchedule>,
+    path: String,
+) -> Result<usize, String> {
+    validate_string_length(&path, MAX_ID_LENGTH)?;
+    let path = path.trim();
+    if path.is_empty() {
+        return Err("Export path cannot be empty".to_string());
+    }
+    let pending = reminders.list();
+    let now = unix_now();
+    let recordings: Vec<RecordingJob> = schedule.list().into_iter().filter(|job| job.stop > now).collect();
+    let ics = calendar::to_ics(&pending, &recordings, reminders.lead_minutes(), now);
+    tokio::fs::write(path, ics)
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+    Ok(pending.len() + recordings.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn test_reminders_fire_once_and_expire() {
+        // Test that reminders go out once within the lead time, persist and are dropped when over
+        let dir = temp_dir("reminders");
+        let reminders = Reminders::open(&dir);
+        reminders.set_lead_minutes(10).unwrap();
+        assert!(reminders.set_lead_minutes(MAX_LEAD_MINUTES + 1).is_err());
+        let id = programme_id("bbc1.uk", 10_000);
+        reminders
+            .upsert(Reminder {
+                id: id.clone(),
+                channel: "bbc1.uk".to_string(),
+                start: 10_000,
+                stop: 13_600,
+                title: "Final".to_string(),
+                channel_id: None,
+                notified: false,
+            })
+            .unwrap();
+
+        assert!(reminders.take_due(10_000 - 601).unwrap().is_empty());
+        assert_eq!(reminders.take_due(10_000 - 600).unwrap().len(), 1);
+        assert!(reminders.take_due(10_000).unwrap().is_empty());
+        let reopened = Reminders::open(&dir);
+        assert!(reopened.get(&id).unwrap().notified);
+        assert_eq!(reopened.lead_minutes(), 10);
+        reopened.take_due(13_600).unwrap();
+        assert!(reopened.list().is_empty());
+    }
+}
@@ -0,0 +1,269 @@
+// Keeping the guide store from growing without bound: programmes that
+// ended before the retention window are pruned from every stored guide,
+// and files of sources no longer stored are deleted
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager, State};
+
+use super::{EpgStore, GuideIndex, RETENTION_FILE};
+use crate::{storage, unix_now};
+
+// Days of past programmes kept when the user sets nothing
+pub const DEFAULT_RETENTION_DAYS: u32 = 7;
+pub const MAX_RETENTION_DAYS: u32 = 90;
+
+const DAY: i64 = 24 * 60 * 60;
+
+// How often the store is pruned and compacted; the first run waits one
+// interval so it stays out of the way of startup
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+// Disk use of one stored guide
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceStorage {
+    pub id: String,
+    pub updated_at: i64,
+    pub programmes: usize,
+    pub channels: usize,
+    // Programme data in the guide database and the channel list file
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EpgStorageStats {
+    pub sources: Vec<SourceStorage>,
+    pub programmes: usize,
+    // Everything in the guide directory
+    pub bytes: u64,
+    pub retention_days: u32,
+}
+
+// What one maintenance pass did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Maintenance {
+    pub pruned_programmes: usize,
+    pub removed_files: usize,
+    pub freed_bytes: u64,
+}
+
+fn file_size(path: &Path) -> u64 {
+    fs::metadata(path).map_or(0, |metadata| metadata.len())
+}
+
+// Whether a file is some source's guide or channel list, named as
+// EpgStore::guide_path and channels_path name them
+fn is_source_file(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    let hash = name.strip_suffix(".json.gz").or_else(|| name.strip_suffix(".channels.json"));
+    hash.is_some_and(|hash| hash.len() == 16 && hash.bytes().all(|byte| byte.is_ascii_hexdigit()))
+}
+
+impl EpgStore {
+    pub fn retention_days(&self) -> u32 {
+        self.retention_days.lock().unwrap().unwrap_or(DEFAULT_RETENTION_DAYS)
+    }
+
+    pub fn set_retention_days(&self, days: u32) -> Result<(), String> {
+        if !(1..=MAX_RETENTION_DAYS).contains(&days) {
+            return Err(format!("Keep between 1 and {} days of past programmes", MAX_RETENTION_DAYS));
+        }
+        let _writing = self.writing.lock().unwrap();
+        let mut retention_days = self.retention_days.lock().unwrap();
+        storage::write_json(&self.dir.join(RETENTION_FILE), &days)?;
+        *retention_days = Some(days);
+        Ok(())
+    }
+
+    // Drop programmes that ended before `before` from every stored guide;
+    // returns how many were dropped
+    pub fn prune(&self, before: i64) -> Result<usize, String> {
+        let mut pruned = 0;
+        for source in self.sources() {
+            let guide = match self.guide(&source.id) {
+                Ok(guide) => guide,
+                Err(e) => {
+                    log::warn!("Skipping EPG source '{}' while pruning: {}", source.id, e);
+                    continue;
+                }
+            };
+            let kept: Vec<_> = guide.programmes().iter().filter(|p| p.stop > before).cloned().collect();
+            if kept.len() == guide.programmes().len() {
+                continue;
+            }
+            let _writing = self.writing.lock().unwrap();
+            // A new download replaced the guide meanwhile
+            let current = self.loaded.lock().unwrap().get(&source.id).cloned();
+            if !current.is_some_and(|current| Arc::ptr_eq(&current, &guide)) {
+                continue;
+            }
+            pruned += guide.programmes().len() - kept.len();
+            self.store_index(&source.id, GuideIndex::new(kept), source.updated_at)?;
+        }
+        Ok(pruned)
+    }
+
+    // Delete the guide and channel list files of sources that are no
+    // longer stored; anything else in the directory is left alone
+    pub fn compact(&self) -> Result<(usize, u64), String> {
+        let _writing = self.writing.lock().unwrap();
+        let owned: HashSet<PathBuf> = self
+            .sources()
+            .iter()
+            .flat_map(|source| [self.guide_path(&source.id), self.channels_path(&source.id)])
+            .collect();
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((0, 0)),
+            Err(e) => return Err(format!("Failed to read {}: {}", self.dir.display(), e)),
+        };
+        let (mut removed, mut freed) = (0, 0);
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if !is_source_file(&path) || owned.contains(&path) || !path.is_file() {
+                continue;
+            }
+            let size = file_size(&path);
+            match fs::remove_file(&path) {
+                Ok(()) => {
+                    removed += 1;
+                    freed += size;
+                }
+                Err(e) => log::warn!("Failed to remove {}: {}", path.display(), e),
+            }
+        }
+        Ok((removed, freed))
+    }
+
+    // Prune past the retention window, then compact
+    pub fn maintain(&self, now: i64) -> Result<Maintenance, String> {
+        let pruned_programmes = self.prune(now - i64::from(self.retention_days()) * DAY)?;
+        let (removed_files, freed_bytes) = self.compact()?;
+        Ok(Maintenance {
+            pruned_programmes,
+            removed_files,
+            freed_bytes,
+        })
+    }
+
+    pub fn storage_stats(&self) -> EpgStorageStats {
+        let sources: Vec<SourceStorage> = self
+            .sources()
+            .into_iter()
+            .map(|source| SourceStorage {
+                bytes: self.database.size(&source.id) + file_size(&self.channels_path(&source.id)),
+                id: source.id,
+                updated_at: source.updated_at,
+                programmes: source.programmes,
+                channels: source.channels,
+            })
+            .collect();
+        let bytes = fs::read_dir(&self.dir)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .filter_map(|entry| entry.metadata().ok())
+                    .filter(|metadata| metadata.is_file())
+                    .map(|metadata| metadata.len())
+                    .sum()
+            })
+            .unwrap_or(0);
+        EpgStorageStats {
+            programmes: sources.iter().map(|source| source.programmes).sum(),
+            sources,
+            bytes,
+            retention_days: self.retention_days(),
+        }
+    }
+}
+
+async fn maintain(app: &AppHandle) -> Result<Maintenance, String> {
+    let handle = app.clone();
+    tauri::async_runtime::spawn_blocking(move || handle.state::<EpgStore>().maintain(unix_now()))
+        .await
+        .map_err(|e| format!("EPG maintenance failed: {}", e))?
+}
+
+// Start the maintenance loop; runs for the lifetime of the app
+pub fn start(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            tokio::time::sleep(MAINTENANCE_INTERVAL).await;
+            match maintain(&app).await {
+                Ok(done) if done.pruned_programmes > 0 || done.removed_files > 0 => log::info!(
+                    "Pruned {} past programmes and {} stale guide files ({} bytes)",
+                    done.pruned_programmes,
+                    done.removed_files,
+                    done.freed_bytes
+                ),
+                Ok(_) => {}
+                Err(e) => log::warn!("{}", e),
+            }
+        }
+    });
+}
+
+// Command handler reporting how much the stored guides take up
+#[tauri::command]
+pub fn get_epg_storage_stats(store: State<'_, EpgStore>) -> EpgStorageStats {
+    store.storage_stats()
+}
+
+// Command handler setting how many days of past programmes are kept and
+// pruning right away
+#[tauri::command]
+pub async fn set_epg_retention_days(app: AppHandle, days: u32) -> Result<Maintenance, String> {
+    app.state::<EpgStore>().set_retention_days(days)?;
+    maintain(&app).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epg::{Guide, Programme};
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn test_prune_and_compact() {
+        // Test that past programmes are pruned, files of removed sources deleted and stats add up
+        let dir = temp_dir("epg-retention");
+        let store = EpgStore::open(dir.clone());
+        let now = 100 * DAY;
+        let programmes: Vec<Programme> = [now - 10 * DAY, now - 2 * DAY, now]
+            .into_iter()
+            .map(|start| Programme {
+                channel: "a".to_string(),
+                start,
+                stop: start + 3600,
+                title: "Show".to_string(),
+                ..Default::default()
+            })
+            .collect();
+        // Stored long ago, so the import itself keeps everything
+        store.replace("xmltv:s", Guide::from(programmes), 0).unwrap();
+        let orphan = dir.join(format!("{}.json.gz", crate::playlist::content_hash(b"xmltv:gone")));
+        fs::write(&orphan, b"removed").unwrap();
+        fs::write(dir.join("other.json.gz"), b"kept").unwrap();
+
+        store.set_retention_days(3).unwrap();
+        assert!(store.set_retention_days(0).is_err());
+        let done = store.maintain(now).unwrap();
+        assert_eq!((done.pruned_programmes, done.removed_files, done.freed_bytes), (1, 1, 7));
+        assert!(!orphan.exists() && dir.join("other.json.gz").exists());
+
+        let reopened = EpgStore::open(dir);
+        assert_eq!(reopened.guide("xmltv:s").unwrap().programmes().len(), 2);
+        let stats = reopened.storage_stats();
+        assert_eq!((stats.programmes, stats.retention_days, stats.sources[0].updated_at), (2, 3, 0));
+        assert!(stats.bytes >= stats.sources[0].bytes && stats.sources[0].bytes > 0);
+    }
+}
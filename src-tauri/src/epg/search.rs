@@ -0,0 +1,195 @@
+// Finding programmes across every stored guide. The guide database's
+// full-text index finds the programmes with the words or genres asked for,
+// which are then time-corrected and merged channel by channel as guide
+// queries show them
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use super::{offsets, EpgChannel, EpgStore, Programme};
+
+// Most results one search returns, earliest first
+pub const MAX_RESULTS: usize = 500;
+
+// Unix seconds; a missing bound is open
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TimeRange {
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchQuery {
+    // Words the title or description must have words starting with
+    pub terms: Vec<String>,
+    pub from: i64,
+    pub to: i64,
+    // Any one of these must be among the programme's categories or genres
+    pub genres: Vec<String>,
+    // Guide channel ids to search; empty searches them all
+    pub channels: Vec<String>,
+}
+
+impl SearchQuery {
+    pub fn new(query: &str, from: i64, to: i64) -> Self {
+        SearchQuery {
+            terms: query.split_whitespace().map(str::to_lowercase).collect(),
+            from,
+            to,
+            ..Default::default()
+        }
+    }
+
+    // The full-text query: every term as a word prefix, and any one of the
+    // genres as a phrase
+    fn expression(&self) -> String {
+        let quoted = |text: &str| format!("\"{}\"", text.replace('"', "\"\""));
+        let mut parts = Vec::new();
+        if !self.terms.is_empty() {
+            let terms: Vec<String> = self.terms.iter().map(|term| quoted(term) + "*").collect();
+            parts.push(format!("words : ({})", terms.join(" ")));
+        }
+        if !self.genres.is_empty() {
+            let genres: Vec<String> = self.genres.iter().map(|genre| quoted(genre)).collect();
+            parts.push(format!("genres : ({})", genres.join(" OR ")));
+        }
+        parts.join(" AND ")
+    }
+
+    // The full-text index matches genres by their words; a genre has to
+    // be one of the programme's whole
+    fn in_genres(&self, programme: &Programme) -> bool {
+        self.genres.is_empty()
+            || programme
+                .categories
+                .iter()
+                .map(String::as_str)
+                .chain(programme.genres.iter().map(|genre| genre.as_str()))
+                .any(|category| self.genres.iter().any(|genre| genre.eq_ignore_ascii_case(category)))
+    }
+}
+
+// A programme found, with the guide channel it's on
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResult {
+    pub channel: EpgChannel,
+    pub programme: Programme,
+}
+
+// `listed` holds the channels the guides list, for names and icons
+pub fn search(
+    store: &EpgStore,
+    listed: &HashMap<String, EpgChannel>,
+    query: &SearchQuery,
+) -> Result<Vec<SearchResult>, String> {
+    if query.terms.is_empty() && query.genres.is_empty() {
+        return Ok(Vec::new());
+    }
+    // Stored times are uncorrected, and no offset is larger than MAX_OFFSET
+    let from = query.from.saturating_sub(offsets::MAX_OFFSET);
+    let to = query.to.saturating_add(offsets::MAX_OFFSET);
+    let mut channels: BTreeMap<String, Vec<(String, Programme)>> = BTreeMap::new();
+    for (source, programme) in store.database.search(&query.expression(), from, to)? {
+        if query.channels.is_empty() || query.channels.contains(&programme.channel) {
+            channels.entry(programme.channel.clone()).or_default().push((source, programme));
+        }
+    }
+    let mut found: Vec<SearchResult> = Vec::new();
+    for (channel, stored) in channels {
+        let mut programmes = store.merged(&channel, &stored, query.from, query.to);
+        programmes.retain(|programme| query.in_genres(programme));
+        if programmes.is_empty() {
+            continue;
+        }
+        let info = listed.get(&channel).cloned().unwrap_or_else(|| EpgChannel {
+            id: channel.clone(),
+            ..Default::default()
+        });
+        found.extend(programmes.into_iter().map(|programme| SearchResult {
+            channel: info.clone(),
+            programme,
+        }));
+    }
+    found.sort_by(|a, b| {
+        a.programme
+            .start
+            .cmp(&b.programme.start)
+            .then_with(|| a.channel.id.cmp(&b.channel.id))
+    });
+    found.truncate(MAX_RESULTS);
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epg::Guide;
+    use crate::test_support::temp_dir;
+
+    fn programme(channel: &str, start: i64, title: &str, categories: &[&str]) -> Programme {
+        Programme {
+            channel: channel.to_string(),
+            start,
+            stop: start + 100,
+            title: title.to_string(),
+            categories: categories.iter().map(|c| c.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_search_terms_genres_and_channels() {
+        // Test that all words must match as word prefixes, genres and channels narrow the search and duplicates are merged
+        let store = EpgStore::open(temp_dir("epg-search"));
+        let first = vec![
+            programme("a", 0, "UEFA Champions League Live", &["Sports"]),
+            programme("a", 100, "Champions of the Sea", &["Documentary"]),
+            programme("b", 500, "Champions League Highlights", &["sports"]),
+        ];
+        store.replace("s1", Guide::from(first), 0).unwrap();
+        store.replace("s2", Guide::from(vec![programme("a", 0, "Champions League", &["Sports"])]), 0).unwrap();
+        let listed = HashMap::from([(
+            "a".to_string(),
+            EpgChannel {
+                id: "a".to_string(),
+                names: vec!["Sport 1".to_string()],
+                icon: None,
+            },
+        )]);
+
+        let query = SearchQuery::new("champ LEAGUE", 0, 1000);
+        let titles: Vec<_> = search(&store, &listed, &query)
+            .unwrap()
+            .into_iter()
+            .map(|found| (found.channel.names.len(), found.programme.title))
+            .collect();
+        assert_eq!(
+            titles,
+            vec![
+                (1, "UEFA Champions League Live".to_string()),
+                (0, "Champions League Highlights".to_string())
+            ]
+        );
+
+        let query = SearchQuery {
+            genres: vec!["documentary".to_string()],
+            ..SearchQuery::new("champions", 0, 1000)
+        };
+        assert_eq!(search(&store, &listed, &query).unwrap().len(), 1);
+        assert!(search(&store, &listed, &SearchQuery::new("ampions", 0, 1000)).unwrap().is_empty());
+        let query = SearchQuery {
+            genres: vec!["Sports".to_string()],
+            channels: vec!["b".to_string()],
+            ..SearchQuery::new("", 0, 400)
+        };
+        assert!(search(&store, &listed, &query).unwrap().is_empty());
+        store.set_source_offset("s1", 100).unwrap();
+        let query = SearchQuery {
+            channels: vec!["b".to_string()],
+            ..SearchQuery::new("highlights", 0, 601)
+        };
+        assert_eq!(search(&store, &listed, &query).unwrap()[0].programme.start, 600);
+    }
+}
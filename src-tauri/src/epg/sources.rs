@@ -0,0 +1,435 @@
+// XMLTV guides the user added by URL or file path, downloaded (gzip, xz
+// and zstd are unpacked as they stream in) and stored in the EPG store.
+// A background job refreshes them, asking servers only for changed guides
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, UNIX_EPOCH};
+
+use futures_util::TryStreamExt;
+use reqwest::header::{CONTENT_ENCODING, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::io::{AsyncBufRead, BufReader};
+use tokio_util::io::StreamReader;
+
+use super::{xmltv, EpgSource, EpgStore};
+use crate::compression;
+use crate::http;
+use crate::playlist::{content_hash, is_remote, CacheEntry};
+use crate::storage;
+use crate::{unix_now, validate_string_length};
+
+const SOURCES_FILE: &str = "xmltv_sources.json";
+
+// Limits for user-entered source details
+const MAX_URL_LENGTH: usize = 2048;
+const MAX_NAME_LENGTH: usize = 200;
+
+// Read buffer used for local guide files
+const FILE_BUFFER_SIZE: usize = 64 * 1024;
+
+// How often the refresh job looks for sources that are due
+const CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+// The job's first check waits up to this long so a restart doesn't hit
+// every guide server at once
+const START_JITTER: Duration = Duration::from_secs(5 * 60);
+
+// Hours between refreshes of a source that sets none
+const DEFAULT_REFRESH_HOURS: u32 = 12;
+
+// Seconds before a failed refresh is tried again
+const RETRY_INTERVAL: i64 = 60 * 60;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct XmltvSource {
+    pub id: String,
+    pub name: String,
+    // http(s) URL or local path
+    pub url: String,
+    // Hours between background refreshes, 12 when unset; 0 turns them off
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_interval_hours: Option<u32>,
+    // ETag / Last-Modified of the stored guide
+    #[serde(default, skip_serializing_if = "CacheEntry::is_empty")]
+    pub validators: CacheEntry,
+}
+
+impl XmltvSource {
+    // EPG store id of the source's guide
+    pub fn source_id(&self) -> String {
+        source_id(&self.id)
+    }
+
+    // Seconds between refreshes, None when they're off
+    fn refresh_interval(&self) -> Option<i64> {
+        match self.refresh_interval_hours.unwrap_or(DEFAULT_REFRESH_HOURS) {
+            0 => None,
+            hours => Some(i64::from(hours) * 3600),
+        }
+    }
+}
+
+fn source_id(id: &str) -> String {
+    format!("xmltv:{}", id)
+}
+
+pub struct XmltvSources {
+    path: PathBuf,
+    sources: Mutex<Vec<XmltvSource>>,
+}
+
+impl XmltvSources {
+    pub fn open(data_dir: &Path) -> Self {
+        let path = data_dir.join(SOURCES_FILE);
+        let sources = storage::read_json(&path).unwrap_or_else(|e| {
+            log::error!("Failed to load XMLTV sources: {}", e);
+            Vec::new()
+        });
+        Self {
+            path,
+            sources: Mutex::new(sources),
+        }
+    }
+
+    pub fn list(&self) -> Vec<XmltvSource> {
+        self.sources.lock().unwrap().clone()
+    }
+
+    pub fn get(&self, id: &str) -> Result<XmltvSource, String> {
+        self.sources
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|s| s.id == id)
+            .cloned()
+            .ok_or_else(|| format!("XMLTV source '{}' doesn't exist", id))
+    }
+
+    pub fn upsert(&self, source: XmltvSource) -> Result<(), String> {
+        let mut sources = self.sources.lock().unwrap();
+        match sources.iter_mut().find(|s| s.id == source.id) {
+            Some(existing) => *existing = source,
+            None => sources.push(source),
+        }
+        storage::write_json(&self.path, &*sources)
+    }
+
+    pub fn remove(&self, id: &str) -> Result<bool, String> {
+        let mut sources = self.sources.lock().unwrap();
+        let before = sources.len();
+        sources.retain(|s| s.id != id);
+        if sources.len() == before {
+            return Ok(false);
+        }
+        storage::write_json(&self.path, &*sources)?;
+        Ok(true)
+    }
+}
+
+type GuideReader = Box<dyn AsyncBufRead + Unpin + Send>;
+
+// The copy of a guide already stored, for conditional refreshes
+struct Stored<'a> {
+    validators: &'a CacheEntry,
+    updated_at: i64,
+}
+
+// Open a guide, or None when it hasn't changed since `stored`: servers
+// are asked with the stored validators, local files compare their
+// modification time
+async fn open(url: &str, stored: Option<Stored<'_>>) -> Result<Option<(GuideReader, CacheEntry)>, String> {
+    if !is_remote(url) {
+        let path = url.strip_prefix("file://").unwrap_or(url);
+        let file = tokio::fs::File::open(path)
+            .await
+            .map_err(|e| format!("Failed to read guide file: {}", e))?;
+        if let Some(stored) = stored {
+            let modified = file.metadata().await.and_then(|metadata| metadata.modified());
+            let modified = modified.ok().and_then(|at| at.duration_since(UNIX_EPOCH).ok());
+            if modified.is_some_and(|at| at.as_secs() as i64 <= stored.updated_at) {
+                return Ok(None);
+            }
+        }
+        let (reader, _) = compression::decompress(Box::new(BufReader::with_capacity(FILE_BUFFER_SIZE, file)), None)
+            .await
+            .map_err(|e| format!("Failed to read guide file: {}", e))?;
+        return Ok(Some((reader, CacheEntry::default())));
+    }
+
+    let mut request = http::get(url, None);
+    let validators = stored.as_ref().map(|stored| stored.validators).filter(|v| !v.is_empty());
+    if let Some(validators) = validators {
+        if let Some(etag) = &validators.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+    let response = request
+        .send()
+        .await
+        .map_err(|e| format!("Guide download failed: {}", e.without_url()))?;
+    if response.status() == StatusCode::NOT_MODIFIED && validators.is_some() {
+        return Ok(None);
+    }
+    if !response.status().is_success() {
+        return Err(format!("Guide download failed with HTTP {}", response.status().as_u16()));
+    }
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    let encoding = header(CONTENT_ENCODING);
+    let validators = CacheEntry {
+        etag: header(ETAG),
+        last_modified: header(LAST_MODIFIED),
+    };
+    let body = StreamReader::new(response.bytes_stream().map_err(std::io::Error::other));
+    let (reader, _) = compression::decompress(Box::new(body), encoding.as_deref())
+        .await
+        .map_err(|e| format!("Guide download failed: {}", e))?;
+    Ok(Some((reader, validators)))
+}
+
+// Download a source's guide, parsing it as it streams in, and swap it in
+// for the stored one; the validators of the new guide come back with it.
+// A conditional refresh of an unchanged guide only marks the stored one as
+// current
+async fn import(
+    app: &AppHandle,
+    source: &XmltvSource,
+    conditional: bool,
+) -> Result<(EpgSource, Option<CacheEntry>), String> {
+    let id = source.source_id();
+    let store = app.state::<EpgStore>();
+    let stored = store
+        .updated_at(&id)
+        .filter(|_| conditional)
+        .map(|updated_at| Stored {
+            validators: &source.validators,
+            updated_at,
+        });
+    let Some((reader, validators)) = open(&source.url, stored).await? else {
+        return Ok((store.touch(&id, unix_now())?, None));
+    };
+    let guide = xmltv::read_guide(reader).await?;
+    let handle = app.clone();
+    let stored = tauri::async_runtime::spawn_blocking(move || {
+        handle.state::<EpgStore>().replace(&id, guide, unix_now())
+    })
+    .await
+    .map_err(|e| format!("Failed to store guide: {}", e))??;
+    let _ = app.emit("epg-updated", stored.clone());
+    Ok((stored, Some(validators)))
+}
+
+// Import a saved source's guide and remember its validators
+async fn refresh(app: &AppHandle, source: &XmltvSource, conditional: bool) -> Result<EpgSource, String> {
+    let (stored, validators) = import(app, source, conditional).await?;
+    let sources = app.state::<XmltvSources>();
+    // A source removed while it downloaded stays removed
+    if let (Some(validators), Ok(mut current)) = (validators, sources.get(&source.id)) {
+        current.validators = validators;
+        sources.upsert(current)?;
+    }
+    Ok(stored)
+}
+
+fn jitter(max: Duration) -> Duration {
+    let random = RandomState::new().build_hasher().finish();
+    Duration::from_secs(random % max.as_secs().max(1))
+}
+
+// Start the refresh job; runs for the lifetime of the app
+pub fn start(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(jitter(START_JITTER)).await;
+        let mut failed_at: HashMap<String, i64> = HashMap::new();
+        loop {
+            let now = unix_now();
+            for source in app.state::<XmltvSources>().list() {
+                let Some(interval) = source.refresh_interval() else {
+                    continue;
+                };
+                let updated_at = app.state::<EpgStore>().updated_at(&source.source_id());
+                let fresh = updated_at.is_some_and(|at| now - at < interval);
+                let backing_off = failed_at.get(&source.id).is_some_and(|at| now - at < RETRY_INTERVAL);
+                if fresh || backing_off {
+                    continue;
+                }
+                match refresh(&app, &source, true).await {
+                    Ok(_) => {
+                        failed_at.remove(&source.id);
+                    }
+                    Err(e) => {
+                        failed_at.insert(source.id.clone(), now);
+                        log::warn!("Refresh of guide '{}' failed: {}", source.name, e);
+                    }
+                }
+            }
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    });
+}
+
+// Save an XMLTV source and import its guide; a source that fails to import
+// isn't saved
+pub async fn add(
+    app: &AppHandle,
+    name: &str,
+    url: &str,
+    refresh_interval_hours: Option<u32>,
+) -> Result<EpgSource, String> {
+    validate_string_length(url, MAX_URL_LENGTH)?;
+    validate_string_length(name, MAX_NAME_LENGTH)?;
+    let url = url.trim();
+    if url.is_empty() {
+        return Err("Guide URL cannot be empty".to_string());
+    }
+    let mut source = XmltvSource {
+        id: content_hash(format!("xmltv:{}", url).as_bytes()),
+        name: match name.trim() {
+            "" => url.to_string(),
+            name => name.to_string(),
+        },
+        url: url.to_string(),
+        refresh_interval_hours,
+        validators: CacheEntry::default(),
+    };
+    let (stored, validators) = import(app, &source, false).await?;
+    source.validators = validators.unwrap_or_default();
+    app.state::<XmltvSources>().upsert(source)?;
+    Ok(stored)
+}
+
+// Command handler that saves an XMLTV source and imports its guide
+#[tauri::command]
+pub async fn add_xmltv_source(
+    app: AppHandle,
+    name: String,
+    url: String,
+    refresh_interval_hours: Option<u32>,
+) -> Result<EpgSource, String> {
+    add(&app, &name, &url, refresh_interval_hours).await
+}
+
+// Command handler listing the saved XMLTV sources
+#[tauri::command]
+pub fn list_xmltv_sources(sources: State<'_, XmltvSources>) -> Vec<XmltvSource> {
+    sources.list()
+}
+
+// Command handler downloading a saved source's guide again, whether or not
+// it changed
+#[tauri::command]
+pub async fn refresh_xmltv_source(
+    app: AppHandle,
+    sources: State<'_, XmltvSources>,
+    id: String,
+) -> Result<EpgSource, String> {
+    let source = sources.get(&id)?;
+    refresh(&app, &source, false).await
+}
+
+// Command handler setting how many hours pass between background refreshes
+// of a source; 0 turns them off, no value goes back to the default
+#[tauri::command]
+pub fn set_xmltv_refresh_interval(
+    sources: State<'_, XmltvSources>,
+    id: String,
+    refresh_interval_hours: Option<u32>,
+) -> Result<XmltvSource, String> {
+    let mut source = sources.get(&id)?;
+    source.refresh_interval_hours = refresh_interval_hours;
+    sources.upsert(source.clone())?;
+    Ok(source)
+}
+
+// Command handler that deletes a source together with its stored guide
+#[tauri::command]
+pub fn remove_xmltv_source(app: AppHandle, sources: State<'_, XmltvSources>, id: String) -> Result<bool, String> {
+    app.state::<EpgStore>().remove(&source_id(&id))?;
+    sources.remove(&id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{response, serve, temp_dir};
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    #[test]
+    fn test_fetch_compressed_file_and_sources_persist() {
+        // Test that a gzipped guide file is read and saved sources survive reopening
+        let dir = temp_dir("xmltv-sources");
+        let guide = r#"<tv><channel id="one"><display-name>One</display-name></channel>
+            <programme start="20240101100000 +0000" stop="20240101110000 +0000" channel="one"><title>News</title></programme></tv>"#;
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(guide.as_bytes()).unwrap();
+        let path = dir.join("guide.xml.gz");
+        std::fs::write(&path, encoder.finish().unwrap()).unwrap();
+
+        let fetch = |url: String| {
+            tauri::async_runtime::block_on(async move {
+                let (reader, _) = open(&url, None).await?.unwrap();
+                xmltv::read_guide(reader).await
+            })
+        };
+        let guide = fetch(path.to_string_lossy().to_string()).unwrap();
+        assert_eq!((guide.channels.len(), guide.programmes.len()), (1, 1));
+        assert!(fetch(dir.join("missing.xml").to_string_lossy().to_string()).is_err());
+
+        let sources = XmltvSources::open(&dir);
+        let source = XmltvSource {
+            id: "s".to_string(),
+            name: "Guide".to_string(),
+            url: path.to_string_lossy().to_string(),
+            refresh_interval_hours: Some(0),
+            validators: CacheEntry::default(),
+        };
+        assert_eq!(source.refresh_interval(), None);
+        sources.upsert(source.clone()).unwrap();
+        assert_eq!(XmltvSources::open(&dir).get("s").unwrap(), source);
+        assert_eq!(source.source_id(), "xmltv:s");
+        assert!(sources.remove("s").unwrap());
+    }
+
+    #[test]
+    fn test_conditional_open() {
+        // Test that stored validators are sent and unchanged guides and files aren't read again
+        let (base, requests) = serve(vec![
+            response("200 OK", &[("ETag", "\"v1\"")], "<tv></tv>"),
+            response("304 Not Modified", &[], ""),
+        ]);
+        let url = format!("{}/guide.xml", base);
+        let (_, validators) = tauri::async_runtime::block_on(open(&url, None)).unwrap().unwrap();
+        assert_eq!(validators.etag.as_deref(), Some("\"v1\""));
+        let stored = Stored {
+            validators: &validators,
+            updated_at: 0,
+        };
+        assert!(tauri::async_runtime::block_on(open(&url, Some(stored))).unwrap().is_none());
+        assert!(requests.lock().unwrap()[1].contains("if-none-match: \"v1\""));
+
+        let path = temp_dir("xmltv-conditional").join("guide.xml");
+        std::fs::write(&path, "<tv></tv>").unwrap();
+        let path = path.to_string_lossy();
+        let stored = |updated_at| Stored {
+            validators: &validators,
+            updated_at,
+        };
+        assert!(tauri::async_runtime::block_on(open(&path, Some(stored(i64::MAX)))).unwrap().is_none());
+        assert!(tauri::async_runtime::block_on(open(&path, Some(stored(0)))).unwrap().is_some());
+    }
+}
@@ -0,0 +1,141 @@
+// Calendar times to Unix seconds without pulling in a date library
+// Days since 1970-01-01 of a proleptic Gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+// Proleptic Gregorian date of a day count since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: i64,
+    pub day: i64,
+    pub hour: i64,
+    pub minute: i64,
+    pub second: i64,
+}
+
+// Calendar fields of Unix seconds, in UTC
+pub fn utc_datetime(timestamp: i64) -> DateTime {
+    let (year, month, day) = civil_from_days(timestamp.div_euclid(86_400));
+    let seconds = timestamp.rem_euclid(86_400);
+    DateTime {
+        year,
+        month,
+        day,
+        hour: seconds / 3600,
+        minute: seconds % 3600 / 60,
+        second: seconds % 60,
+    }
+}
+
+// Unix seconds of a UTC date and time; None when a field is out of range
+pub fn unix_time(year: i64, month: i64, day: i64, hour: i64, minute: i64, second: i64) -> Option<i64> {
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    if [hour, minute, second].iter().any(|field| *field < 0) {
+        return None;
+    }
+    Some(days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second)
+}
+
+// Unix seconds of "YYYY-MM-DD HH:MM:SS" (or ISO "T"-separated), as UTC
+pub fn parse_datetime(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let (date, time) = value.split_once([' ', 'T']).unwrap_or((value, "00:00:00"));
+    let date: Vec<i64> = date.split('-').map(str::parse).collect::<Result<_, _>>().ok()?;
+    let time: Vec<i64> = time.split(':').map(str::parse).collect::<Result<_, _>>().ok()?;
+    match (date.as_slice(), time.as_slice()) {
+        ([year, month, day], [hour, minute, second]) => unix_time(*year, *month, *day, *hour, *minute, *second),
+        ([year, month, day], [hour, minute]) => unix_time(*year, *month, *day, *hour, *minute, 0),
+        _ => None,
+    }
+}
+
+// Unix seconds of an XMLTV time: "YYYYMMDDhhmmss +hhmm", where seconds and
+// the offset may be missing (no offset means UTC)
+pub fn parse_xmltv(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let digits = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (stamp, rest) = value.split_at(digits);
+    if stamp.len() < 12 {
+        return None;
+    }
+    let field = |range: std::ops::Range<usize>| stamp.get(range).and_then(|s| s.parse::<i64>().ok());
+    let second = if stamp.len() >= 14 { field(12..14)? } else { 0 };
+    let local = unix_time(field(0..4)?, field(4..6)?, field(6..8)?, field(8..10)?, field(10..12)?, second)?;
+
+    let offset = rest.trim();
+    if offset.is_empty() || offset.eq_ignore_ascii_case("utc") || offset.eq_ignore_ascii_case("z") {
+        return Some(local);
+    }
+    let (sign, offset) = match offset.as_bytes()[0] {
+        b'+' => (1, &offset[1..]),
+        b'-' => (-1, &offset[1..]),
+        _ => return None,
+    };
+    let offset = offset.replace(':', "");
+    if offset.len() != 4 || !offset.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i64 = offset[..2].parse().ok()?;
+    let minutes: i64 = offset[2..].parse().ok()?;
+    Some(local - sign * (hours * 3600 + minutes * 60))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_datetime() {
+        // Test plain date strings, leap days and range checks
+        assert_eq!(parse_datetime("1970-01-01 00:00:00"), Some(0));
+        assert_eq!(parse_datetime("2024-02-29 12:30:15"), Some(1_709_209_815));
+        assert_eq!(parse_datetime("2024-02-29T12:30"), Some(1_709_209_800));
+        assert_eq!(parse_datetime("2024-13-01 00:00:00"), None);
+        assert_eq!(parse_datetime("soon"), None);
+    }
+
+    #[test]
+    fn test_utc_datetime_roundtrip() {
+        // Test that calendar fields convert back to the same timestamp
+        let parts = utc_datetime(1_709_209_815);
+        assert_eq!((parts.year, parts.month, parts.day), (2024, 2, 29));
+        assert_eq!((parts.hour, parts.minute, parts.second), (12, 30, 15));
+        for timestamp in [0, 951_782_400, 1_709_251_199, 4_102_444_800, -86_400] {
+            let p = utc_datetime(timestamp);
+            assert_eq!(unix_time(p.year, p.month, p.day, p.hour, p.minute, p.second), Some(timestamp));
+        }
+    }
+
+    #[test]
+    fn test_parse_xmltv() {
+        // Test XMLTV times with and without seconds and timezone offsets
+        assert_eq!(parse_xmltv("20240229123015 +0000"), Some(1_709_209_815));
+        assert_eq!(parse_xmltv("20240229143015 +0200"), Some(1_709_209_815));
+        assert_eq!(parse_xmltv("20240229073015 -05:00"), Some(1_709_209_815));
+        assert_eq!(parse_xmltv("202402291230"), Some(1_709_209_800));
+        assert_eq!(parse_xmltv("20240229123015 EST"), None);
+        assert_eq!(parse_xmltv("2024"), None);
+    }
+}
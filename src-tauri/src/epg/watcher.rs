@@ -0,0 +1,305 @@
+// "Starting soon" alerts without per-programme reminders: when enabled, a
+// background job looks at what starts in the next few minutes on the
+// channels the UI marks as favourites and for the saved keyword searches,
+// and announces each programme once
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use super::search::{search, SearchQuery};
+use super::{notifications, programme_id, EpgStore, Programme};
+use crate::{storage, unix_now, validate_string_length};
+
+const WATCH_FILE: &str = "epg_watch.json";
+
+const DEFAULT_LEAD_MINUTES: u32 = 10;
+const MAX_LEAD_MINUTES: u32 = 3 * 60;
+const MAX_CHANNELS: usize = 1000;
+const MAX_KEYWORDS: usize = 100;
+const MAX_FIELD_LENGTH: usize = 2048;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchedChannel {
+    pub epg_channel_id: String,
+    // Playlist channel to open from the notification
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct WatchSettings {
+    pub enabled: bool,
+    // Favourite channels
+    pub channels: Vec<WatchedChannel>,
+    // Saved searches, matched like search_epg queries on every channel
+    pub keywords: Vec<String>,
+    // Only new episodes and premières, so a favourite's reruns stay quiet
+    pub new_only: bool,
+    pub lead_minutes: u32,
+}
+
+impl Default for WatchSettings {
+    fn default() -> Self {
+        WatchSettings {
+            enabled: false,
+            channels: Vec::new(),
+            keywords: Vec::new(),
+            new_only: true,
+            lead_minutes: DEFAULT_LEAD_MINUTES,
+        }
+    }
+}
+
+// Payload of the programme-starting event
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartingSoon {
+    // Usable with set_reminder
+    pub id: String,
+    pub programme: Programme,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel_id: Option<String>,
+    // The saved search that found it; None for a favourite channel
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keyword: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct WatchState {
+    settings: WatchSettings,
+    // Programme ids already announced -> programme stop, kept until it ends
+    announced: BTreeMap<String, i64>,
+}
+
+// Programmes starting in [now, now + lead] that the settings pick out,
+// soonest first; a favourite channel wins over a keyword for the same one
+pub fn starting_soon(store: &EpgStore, settings: &WatchSettings, now: i64) -> Result<Vec<StartingSoon>, String> {
+    let to = now + i64::from(settings.lead_minutes) * 60;
+    let wanted =
+        |programme: &Programme| (now..=to).contains(&programme.start) && (programme.premiere || !settings.new_only);
+    let mut found: BTreeMap<String, StartingSoon> = BTreeMap::new();
+    for channel in &settings.channels {
+        for programme in store.programmes(&channel.epg_channel_id, now, to + 1) {
+            if wanted(&programme) {
+                let id = programme_id(&programme.channel, programme.start);
+                found.entry(id.clone()).or_insert(StartingSoon {
+                    id,
+                    programme,
+                    channel_id: channel.channel_id.clone(),
+                    keyword: None,
+                });
+            }
+        }
+    }
+    for keyword in &settings.keywords {
+        let query = SearchQuery::new(keyword, now, to + 1);
+        for result in search(store, &HashMap::new(), &query)? {
+            if wanted(&result.programme) {
+                let id = programme_id(&result.programme.channel, result.programme.start);
+                found.entry(id.clone()).or_insert(StartingSoon {
+                    id,
+                    programme: result.programme,
+                    channel_id: None,
+                    keyword: Some(keyword.clone()),
+                });
+            }
+        }
+    }
+    let mut found: Vec<StartingSoon> = found.into_values().collect();
+    found.sort_by_key(|soon| soon.programme.start);
+    Ok(found)
+}
+
+pub struct EpgWatch {
+    path: PathBuf,
+    state: Mutex<WatchState>,
+}
+
+impl EpgWatch {
+    pub fn open(data_dir: &Path) -> Self {
+        let path = data_dir.join(WATCH_FILE);
+        let state = storage::read_json(&path).unwrap_or_else(|e| {
+            log::error!("Failed to load EPG watch settings: {}", e);
+            WatchState::default()
+        });
+        Self {
+            path,
+            state: Mutex::new(state),
+        }
+    }
+
+    pub fn settings(&self) -> WatchSettings {
+        self.state.lock().unwrap().settings.clone()
+    }
+
+    pub fn set_settings(&self, settings: WatchSettings) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        let mut updated = state.clone();
+        updated.settings = settings;
+        storage::write_json(&self.path, &updated)?;
+        *state = updated;
+        Ok(())
+    }
+
+    // What to announce at `now`, leaving out what already was
+    pub fn check(&self, store: &EpgStore, now: i64) -> Result<Vec<StartingSoon>, String> {
+        let mut state = self.state.lock().unwrap();
+        if !state.settings.enabled {
+            return Ok(Vec::new());
+        }
+        let mut updated = state.clone();
+        updated.announced.retain(|_, stop| *stop > now);
+        let mut due = starting_soon(store, &updated.settings, now)?;
+        due.retain(|soon| updated.announced.insert(soon.id.clone(), soon.programme.stop).is_none());
+        if updated != *state {
+            storage::write_json(&self.path, &updated)?;
+            *state = updated;
+        }
+        Ok(due)
+    }
+}
+
+fn check_settings(settings: WatchSettings) -> Result<WatchSettings, String> {
+    if settings.lead_minutes == 0 || settings.lead_minutes > MAX_LEAD_MINUTES {
+        return Err(format!("Look between 1 and {} minutes ahead", MAX_LEAD_MINUTES));
+    }
+    if settings.channels.len() > MAX_CHANNELS || settings.keywords.len() > MAX_KEYWORDS {
+        return Err(format!("Watch at most {} channels and {} searches", MAX_CHANNELS, MAX_KEYWORDS));
+    }
+    let mut channels = Vec::with_capacity(settings.channels.len());
+    for channel in settings.channels {
+        validate_string_length(&channel.epg_channel_id, MAX_FIELD_LENGTH)?;
+        validate_string_length(channel.channel_id.as_deref().unwrap_or_default(), MAX_FIELD_LENGTH)?;
+        let epg_channel_id = channel.epg_channel_id.trim().to_string();
+        if !epg_channel_id.is_empty() {
+            channels.push(WatchedChannel {
+                epg_channel_id,
+                channel_id: channel.channel_id.map(|id| id.trim().to_string()).filter(|id| !id.is_empty()),
+            });
+        }
+    }
+    let mut keywords: Vec<String> = Vec::with_capacity(settings.keywords.len());
+    for keyword in settings.keywords {
+        validate_string_length(&keyword, MAX_FIELD_LENGTH)?;
+        let keyword = keyword.trim();
+        if !keyword.is_empty() && !keywords.iter().any(|k| k == keyword) {
+            keywords.push(keyword.to_string());
+        }
+    }
+    Ok(WatchSettings {
+        channels,
+        keywords,
+        ..settings
+    })
+}
+
+// Start the watch loop; runs for the lifetime of the app. Each programme
+// raises an OS notification and a programme-starting event
+pub fn start(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let store = app.state::<EpgStore>();
+            let watch = app.state::<EpgWatch>();
+            let now = unix_now();
+            match watch.check(&store, now) {
+                Ok(due) => {
+                    for soon in due {
+                        let programme = &soon.programme;
+                        let search = soon.keyword.as_ref().map(|keyword| format!("Matches \"{}\"", keyword));
+                        notifications::programme(&app, &programme.title, programme.start, now, search.as_deref());
+                        let _ = app.emit("programme-starting", soon);
+                    }
+                }
+                Err(e) => log::warn!("Failed to check for programmes starting soon: {}", e),
+            }
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    });
+}
+
+// Command handler returning the "starting soon" settings
+#[tauri::command]
+pub fn get_epg_watch(watch: State<'_, EpgWatch>) -> WatchSettings {
+    watch.settings()
+}
+
+// Command handler replacing the "starting soon" settings; the UI sends its
+// favourites' guide channels here whenever they change
+#[tauri::command]
+pub fn set_epg_watch(watch: State<'_, EpgWatch>, settings: WatchSettings) -> Result<WatchSettings, String> {
+    watch.set_settings(check_settings(settings)?)?;
+    Ok(watch.settings())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epg::Guide;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn test_starting_soon_announced_once() {
+        // Test that new programmes on favourites and keyword matches starting soon are announced once
+        let programme = |channel: &str, start: i64, title: &str, premiere: bool| Programme {
+            channel: channel.to_string(),
+            start,
+            stop: start + 1800,
+            title: title.to_string(),
+            premiere,
+            ..Default::default()
+        };
+        let store = EpgStore::open(temp_dir("epg-watch-guide"));
+        let guide = vec![
+            programme("fav", 0, "Earlier", true),
+            programme("fav", 1800, "Rerun", false),
+            programme("fav", 3600, "Season Finale", true),
+            programme("other", 3000, "Cup Final Live", false),
+            programme("other", 9000, "Cup Final Replay", false),
+        ];
+        store.replace("s", Guide::from(guide), 0).unwrap();
+        let watch = EpgWatch::open(&temp_dir("epg-watch"));
+        let settings = check_settings(WatchSettings {
+            enabled: true,
+            channels: vec![WatchedChannel {
+                epg_channel_id: " fav ".to_string(),
+                channel_id: Some("p:fav".to_string()),
+            }],
+            keywords: vec!["final".to_string(), " final".to_string()],
+            lead_minutes: 60,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(settings.keywords.len(), 1);
+        assert!(check_settings(WatchSettings {
+            lead_minutes: 0,
+            ..Default::default()
+        })
+        .is_err());
+
+        watch.set_settings(settings.clone()).unwrap();
+        let due = watch.check(&store, 1000).unwrap();
+        let titles: Vec<_> = due.iter().map(|soon| soon.programme.title.as_str()).collect();
+        assert_eq!(titles, vec!["Season Finale"]);
+        assert_eq!((due[0].id.as_str(), due[0].channel_id.as_deref()), ("fav@3600", Some("p:fav")));
+        assert!(watch.check(&store, 1100).unwrap().is_empty());
+
+        watch
+            .set_settings(WatchSettings {
+                new_only: false,
+                ..settings
+            })
+            .unwrap();
+        let due = watch.check(&store, 1500).unwrap();
+        let found: Vec<_> = due.iter().map(|soon| (soon.programme.title.as_str(), soon.keyword.as_deref())).collect();
+        assert_eq!(found, vec![("Rerun", None), ("Cup Final Live", Some("final"))]);
+    }
+}
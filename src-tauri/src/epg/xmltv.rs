@@ -0,0 +1,288 @@
+// Streaming reader for the <channel> and <programme> elements of an XMLTV
+// guide
+use std::collections::HashMap;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use tokio::io::AsyncBufRead;
+
+use super::{time, ContentRating, EpgChannel, Guide, LocalizedText, Programme};
+use crate::xml::append_text;
+
+#[derive(Default)]
+struct Pending {
+    channel: String,
+    start: Option<i64>,
+    stop: Option<i64>,
+    titles: Vec<LocalizedText>,
+    descriptions: Vec<LocalizedText>,
+    categories: Vec<String>,
+    premiere: bool,
+    icon: Option<String>,
+    ratings: Vec<ContentRating>,
+    series_id: Option<String>,
+}
+
+// <new/> marks a first showing, <premiere> a film or series première
+fn is_premiere(name: &[u8]) -> bool {
+    matches!(name, b"new" | b"premiere")
+}
+
+// dd_progid episode numbers look like "EP01234567.0005": the kind (EP, SH,
+// MV, SP), the series number all its episodes share, then the episode
+fn series_id(progid: &str) -> Option<String> {
+    let series = progid.split('.').next()?.trim();
+    let (kind, number) = (series.get(..2)?, series.get(2..)?);
+    if !kind.bytes().all(|byte| byte.is_ascii_alphabetic()) || number.is_empty() {
+        return None;
+    }
+    Some(number.to_string())
+}
+
+fn attribute(element: &BytesStart<'_>, name: &[u8]) -> Option<String> {
+    element
+        .try_get_attribute(name)
+        .ok()
+        .flatten()
+        .and_then(|attr| attr.unescape_value().ok())
+        .map(|value| value.trim().to_string())
+}
+
+// Read every channel and programme; a programme without a stop time ends
+// where the next one on its channel starts, and programmes lacking a
+// channel, title or valid times are skipped
+pub async fn read_guide<R>(reader: R) -> Result<Guide, String>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut xml = Reader::from_reader(reader);
+    let mut buf = Vec::new();
+    let mut channels: Vec<EpgChannel> = Vec::new();
+    let mut channel: Option<EpgChannel> = None;
+    let mut pending: Vec<Pending> = Vec::new();
+    let mut current: Option<Pending> = None;
+    let mut field: Option<Vec<u8>> = None;
+    let mut text = String::new();
+    // System of the programme <rating> being read
+    let mut rating: Option<Option<String>> = None;
+    // System of the programme <episode-num> being read
+    let mut episode_system: Option<String> = None;
+    // Language of the <title> or <desc> being read
+    let mut lang: Option<String> = None;
+
+    loop {
+        let event = xml
+            .read_event_into_async(&mut buf)
+            .await
+            .map_err(|e| format!("Failed to parse XMLTV guide: {}", e))?;
+        match &event {
+            Event::Start(start) => {
+                let name = start.local_name();
+                if name.as_ref() == b"channel" {
+                    channel = Some(EpgChannel {
+                        id: attribute(start, b"id").unwrap_or_default(),
+                        ..Default::default()
+                    });
+                } else if name.as_ref() == b"programme" {
+                    current = Some(Pending {
+                        channel: attribute(start, b"channel").unwrap_or_default(),
+                        start: attribute(start, b"start").and_then(|t| time::parse_xmltv(&t)),
+                        stop: attribute(start, b"stop").and_then(|t| time::parse_xmltv(&t)),
+                        ..Default::default()
+                    });
+                } else if let Some(programme) = current.as_mut().filter(|_| is_premiere(name.as_ref())) {
+                    programme.premiere = true;
+                } else if current.is_some() && name.as_ref() == b"rating" {
+                    rating = Some(attribute(start, b"system").filter(|system| !system.is_empty()));
+                } else if current.is_some() && name.as_ref() == b"episode-num" {
+                    episode_system = attribute(start, b"system");
+                    field = Some(name.as_ref().to_vec());
+                    text.clear();
+                } else if current.is_some() && matches!(name.as_ref(), b"title" | b"desc" | b"category")
+                    || rating.is_some() && name.as_ref() == b"value"
+                    || channel.is_some() && name.as_ref() == b"display-name"
+                {
+                    lang = attribute(start, b"lang").filter(|lang| !lang.is_empty());
+                    field = Some(name.as_ref().to_vec());
+                    text.clear();
+                }
+            }
+            // <icon src="..."/> of a channel or programme, <new/> of a programme
+            Event::Empty(empty) => {
+                let name = empty.local_name();
+                if let Some(programme) = current.as_mut() {
+                    if is_premiere(name.as_ref()) {
+                        programme.premiere = true;
+                    } else if name.as_ref() == b"icon" && programme.icon.is_none() {
+                        programme.icon = attribute(empty, b"src").filter(|src| !src.is_empty());
+                    }
+                }
+                if let Some(channel) = channel.as_mut().filter(|_| name.as_ref() == b"icon") {
+                    if channel.icon.is_none() {
+                        channel.icon = attribute(empty, b"src").filter(|src| !src.is_empty());
+                    }
+                }
+            }
+            Event::End(end) => {
+                let name = end.local_name();
+                if name.as_ref() == b"channel" {
+                    channels.extend(channel.take().filter(|channel| !channel.id.is_empty()));
+                } else if name.as_ref() == b"programme" {
+                    pending.extend(current.take());
+                } else if name.as_ref() == b"rating" {
+                    rating = None;
+                } else if name.as_ref() == b"value" && field.as_deref() == Some(b"value") {
+                    if let (Some(programme), Some(system)) = (current.as_mut(), rating.as_ref()) {
+                        let value = text.trim();
+                        if !value.is_empty() {
+                            programme.ratings.push(ContentRating {
+                                system: system.clone(),
+                                value: value.to_string(),
+                            });
+                        }
+                    }
+                    field = None;
+                } else if name.as_ref() == b"episode-num" && field.as_deref() == Some(b"episode-num") {
+                    let progid = episode_system.as_deref() == Some("dd_progid");
+                    if let Some(programme) = current.as_mut().filter(|p| progid && p.series_id.is_none()) {
+                        programme.series_id = series_id(&text);
+                    }
+                    field = None;
+                } else if name.as_ref() == b"display-name" && field.as_deref() == Some(b"display-name") {
+                    if let Some(channel) = channel.as_mut() {
+                        let value = text.trim();
+                        if !value.is_empty() && !channel.names.iter().any(|name| name == value) {
+                            channel.names.push(value.to_string());
+                        }
+                    }
+                    field = None;
+                } else if field.as_deref() == Some(name.as_ref()) {
+                    if let Some(programme) = current.as_mut().filter(|_| name.as_ref() == b"category") {
+                        let value = text.trim();
+                        if !value.is_empty() && !programme.categories.iter().any(|category| category == value) {
+                            programme.categories.push(value.to_string());
+                        }
+                    } else if let Some(programme) = current.as_mut() {
+                        let value = text.trim();
+                        let texts = match name.as_ref() {
+                            b"title" => &mut programme.titles,
+                            _ => &mut programme.descriptions,
+                        };
+                        if !value.is_empty() && !texts.iter().any(|text| text.lang == lang && text.text == value) {
+                            texts.push(LocalizedText {
+                                lang: lang.clone(),
+                                text: value.to_string(),
+                            });
+                        }
+                    }
+                    field = None;
+                }
+            }
+            Event::Eof => break,
+            event => {
+                if field.is_some() {
+                    append_text(&mut text, event);
+                }
+            }
+        }
+        buf.clear();
+    }
+
+    Ok(Guide {
+        channels,
+        programmes: finish(pending),
+    })
+}
+
+// The guide's first text, usually the main language, and all of them when
+// there are several
+fn languages(texts: Vec<LocalizedText>) -> (Option<String>, Vec<LocalizedText>) {
+    let first = texts.first().map(|text| text.text.clone());
+    (first, if texts.len() > 1 { texts } else { Vec::new() })
+}
+
+fn finish(mut pending: Vec<Pending>) -> Vec<Programme> {
+    pending.retain(|p| !p.channel.is_empty() && p.start.is_some() && !p.titles.is_empty());
+    pending.sort_by(|a, b| a.channel.cmp(&b.channel).then(a.start.cmp(&b.start)));
+
+    let mut next_start: HashMap<String, i64> = HashMap::new();
+    let mut programmes = Vec::with_capacity(pending.len());
+    for p in pending.into_iter().rev() {
+        let start = p.start.unwrap_or_default();
+        let stop = p.stop.or_else(|| next_start.get(&p.channel).copied());
+        next_start.insert(p.channel.clone(), start);
+        if let Some(stop) = stop.filter(|stop| *stop > start) {
+            let (title, titles) = languages(p.titles);
+            let (description, descriptions) = languages(p.descriptions);
+            programmes.push(Programme {
+                channel: p.channel,
+                start,
+                stop,
+                title: title.unwrap_or_default(),
+                description,
+                categories: p.categories,
+                premiere: p.premiere,
+                genres: Vec::new(),
+                icon: p.icon,
+                ratings: p.ratings,
+                series_id: p.series_id,
+                titles,
+                descriptions,
+            });
+        }
+    }
+    programmes.reverse();
+    programmes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_programmes() {
+        // Test channels, times with offsets, entities, missing stops and skipped entries
+        let guide = r#"<?xml version="1.0" encoding="UTF-8"?>
+<tv>
+  <channel id="bbc1"><display-name>BBC One</display-name><display-name>BBC 1</display-name>
+    <icon src="http://logos/bbc1.png" /></channel>
+  <channel id=""><display-name>No id</display-name></channel>
+  <programme start="20240101110000 +0100" stop="20240101120000 +0100" channel="bbc1">
+    <title lang="en">News &amp; Weather</title><title lang="cy">Newyddion</title>
+    <desc>Headlines</desc><category>News</category><category>News</category><category>Weather</category>
+  </programme>
+  <programme start="20240101120000 +0100" channel="bbc1"><title>Film</title><premiere>First on TV</premiere>
+    <rating system="MPAA"><value>PG-13</value></rating><star-rating><value>3/5</value></star-rating></programme>
+  <programme start="20240101130000 +0100" stop="20240101140000 +0100" channel="bbc1"><title>Quiz</title>
+    <icon src="http://art/quiz.jpg" /><episode-num system="dd_progid">EP01234567.0005</episode-num></programme>
+  <programme start="20240101130000 +0000" stop="20240101140000 +0000" channel="bbc2"></programme>
+  <programme start="garbage" channel="bbc2"><title>Broken</title></programme>
+</tv>"#;
+        let guide = tauri::async_runtime::block_on(read_guide(guide.as_bytes())).unwrap();
+        assert_eq!(guide.channels.len(), 1);
+        assert_eq!(guide.channels[0].names, vec!["BBC One", "BBC 1"]);
+        assert_eq!(guide.channels[0].icon.as_deref(), Some("http://logos/bbc1.png"));
+
+        let programmes = guide.programmes;
+
+        let titles: Vec<_> = programmes.iter().map(|p| p.title.as_str()).collect();
+        assert_eq!(titles, vec!["News & Weather", "Film", "Quiz"]);
+        assert_eq!(programmes[0].start, 1_704_103_200);
+        assert_eq!(programmes[0].description.as_deref(), Some("Headlines"));
+        let langs: Vec<_> = programmes[0].titles.iter().map(|title| title.lang.as_deref()).collect();
+        assert_eq!(langs, vec![Some("en"), Some("cy")]);
+        assert!(programmes[0].descriptions.is_empty() && programmes[1].titles.is_empty());
+        assert_eq!(programmes[0].categories, vec!["News", "Weather"]);
+        assert_eq!(programmes[1].stop, programmes[2].start);
+        assert_eq!((programmes[0].premiere, programmes[1].premiere), (false, true));
+        assert_eq!(programmes[2].icon.as_deref(), Some("http://art/quiz.jpg"));
+        assert_eq!((programmes[0].series_id.as_deref(), programmes[2].series_id.as_deref()), (None, Some("01234567")));
+        assert_eq!(
+            programmes[1].ratings,
+            vec![ContentRating {
+                system: Some("MPAA".to_string()),
+                value: "PG-13".to_string()
+            }]
+        );
+    }
+}
@@ -0,0 +1,113 @@
+// HTTP client for an HDHomeRun tuner's discover.json and lineup.json
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::model::{DeviceInfo, LineupChannel};
+use crate::http;
+
+// Address of a tuner on the local network
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HdhomerunTuner {
+    pub url: String,
+}
+
+// Accept "192.168.1.20", "hdhomerun.local" or a pasted
+// "http://192.168.1.20/discover.json", keeping only the device part
+pub fn normalize_device(device: &str) -> Result<Url, String> {
+    let device = device.trim();
+    if device.is_empty() {
+        return Err("Tuner address cannot be empty".to_string());
+    }
+    let with_scheme = if device.contains("://") {
+        device.to_string()
+    } else {
+        format!("http://{}", device)
+    };
+    let mut url = Url::parse(&with_scheme).map_err(|e| format!("Invalid tuner address '{}': {}", device, e))?;
+    if !matches!(url.scheme(), "http" | "https") || !url.has_host() {
+        return Err(format!("Invalid tuner address '{}'", device));
+    }
+    url.set_path("/");
+    url.set_query(None);
+    url.set_fragment(None);
+    Ok(url)
+}
+
+pub struct HdhomerunClient {
+    base: Url,
+}
+
+impl HdhomerunClient {
+    pub fn new(tuner: &HdhomerunTuner) -> Result<Self, String> {
+        Ok(Self {
+            base: normalize_device(&tuner.url)?,
+        })
+    }
+
+    pub fn server(&self) -> &str {
+        self.base.as_str().trim_end_matches('/')
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T, String> {
+        let response = http::get(url, None)
+            .send()
+            .await
+            .map_err(|e| format!("Couldn't reach the tuner at {}: {}", self.server(), e.without_url()))?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!("The tuner at {} answered HTTP {}", self.server(), status.as_u16()));
+        }
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| format!("Couldn't read the tuner's answer: {}", e.without_url()))?;
+        serde_json::from_slice(&body).map_err(|e| format!("Invalid answer from the tuner at {}: {}", self.server(), e))
+    }
+
+    pub async fn device(&self) -> Result<DeviceInfo, String> {
+        let url = self.base.join("discover.json").expect("valid relative URL");
+        self.get_json(url.as_str()).await
+    }
+
+    // The channels the tuner found in its last channel scan; devices name
+    // their lineup URL, older ones just serve lineup.json
+    pub async fn lineup(&self, device: &DeviceInfo) -> Result<Vec<LineupChannel>, String> {
+        let url = match device.lineup_url.trim() {
+            "" => self.base.join("lineup.json").expect("valid relative URL").to_string(),
+            url => url.to_string(),
+        };
+        let mut channels: Vec<LineupChannel> = self.get_json(&url).await?;
+        channels.retain(|channel| !channel.url.is_empty());
+        Ok(channels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{response, serve};
+
+    #[test]
+    fn test_device_and_lineup() {
+        // Test that older devices' lineup.json is read and entries without a stream dropped
+        let lineup = r#"[{"GuideNumber": "5.1", "GuideName": "KING", "URL": "http://t:5004/auto/v5.1"}, {"GuideNumber": "9.1"}]"#;
+        let device = r#"{"DeviceID": "1050A5C2", "TunerCount": 2}"#;
+        let (base, requests) = serve(vec![response("200 OK", &[], device), response("200 OK", &[], lineup)]);
+        let client = HdhomerunClient::new(&HdhomerunTuner {
+            url: format!("{}/discover.json", base),
+        })
+        .unwrap();
+
+        let info = tauri::async_runtime::block_on(client.device()).unwrap();
+        assert_eq!(info.tuner_count, 2);
+        let channels = tauri::async_runtime::block_on(client.lineup(&info)).unwrap();
+        assert_eq!(channels.len(), 1);
+        let requests = requests.lock().unwrap();
+        assert!(requests[0].starts_with("GET /discover.json "));
+        assert!(requests[1].starts_with("GET /lineup.json "));
+        assert!(normalize_device("").is_err());
+        assert_eq!(normalize_device("192.168.1.20").unwrap().as_str(), "http://192.168.1.20/");
+    }
+}
@@ -0,0 +1,194 @@
+// Finding tuners on the local network with the HDHomeRun discovery
+// protocol: a broadcast request on UDP port 65001 that every device
+// answers with its id and HTTP address
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+const DISCOVERY_PORT: u16 = 65001;
+
+const TYPE_DISCOVER_REQUEST: u16 = 0x0002;
+const TYPE_DISCOVER_REPLY: u16 = 0x0003;
+
+const TAG_DEVICE_TYPE: u8 = 0x01;
+const TAG_DEVICE_ID: u8 = 0x02;
+const TAG_TUNER_COUNT: u8 = 0x10;
+const TAG_BASE_URL: u8 = 0x2A;
+
+const DEVICE_TYPE_TUNER: u32 = 0x0000_0001;
+const WILDCARD: u32 = 0xFFFF_FFFF;
+
+// A device that answered a discovery request
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Discovered {
+    pub device_id: String,
+    // Where its HTTP API lives; older firmware leaves this out, so it's
+    // derived from the answer's address then
+    pub base_url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tuner_count: Option<u32>,
+}
+
+// Ethernet CRC-32, which the protocol appends little-endian
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in bytes {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+// Type, payload length, payload, CRC
+fn frame(packet_type: u16, payload: &[u8]) -> Vec<u8> {
+    let length = u16::try_from(payload.len()).expect("discovery payloads are small");
+    let mut packet = Vec::with_capacity(payload.len() + 8);
+    packet.extend_from_slice(&packet_type.to_be_bytes());
+    packet.extend_from_slice(&length.to_be_bytes());
+    packet.extend_from_slice(payload);
+    let crc = crc32(&packet);
+    packet.extend_from_slice(&crc.to_le_bytes());
+    packet
+}
+
+// Tag, length (one byte below 128, two above), value
+fn push_tag(payload: &mut Vec<u8>, tag: u8, value: &[u8]) {
+    payload.push(tag);
+    match u8::try_from(value.len()) {
+        Ok(length) if length < 0x80 => payload.push(length),
+        _ => {
+            payload.push(0x80 | (value.len() & 0x7F) as u8);
+            payload.push((value.len() >> 7) as u8);
+        }
+    }
+    payload.extend_from_slice(value);
+}
+
+fn request() -> Vec<u8> {
+    let mut payload = Vec::new();
+    push_tag(&mut payload, TAG_DEVICE_TYPE, &DEVICE_TYPE_TUNER.to_be_bytes());
+    push_tag(&mut payload, TAG_DEVICE_ID, &WILDCARD.to_be_bytes());
+    frame(TYPE_DISCOVER_REQUEST, &payload)
+}
+
+// The tags of a reply, None for anything that isn't an intact reply
+fn parse_reply(packet: &[u8]) -> Option<Vec<(u8, &[u8])>> {
+    if packet.len() < 8 {
+        return None;
+    }
+    let (body, crc) = packet.split_at(packet.len() - 4);
+    if crc32(body).to_le_bytes() != crc {
+        return None;
+    }
+    let packet_type = u16::from_be_bytes([body[0], body[1]]);
+    let length = usize::from(u16::from_be_bytes([body[2], body[3]]));
+    let mut payload = body.get(4..)?;
+    if packet_type != TYPE_DISCOVER_REPLY || payload.len() != length {
+        return None;
+    }
+    let mut tags = Vec::new();
+    while let [tag, first, rest @ ..] = payload {
+        let (length, rest) = if first & 0x80 == 0 {
+            (usize::from(*first), rest)
+        } else {
+            let (second, rest) = rest.split_first()?;
+            (usize::from(first & 0x7F) | (usize::from(*second) << 7), rest)
+        };
+        if rest.len() < length {
+            return None;
+        }
+        let (value, rest) = rest.split_at(length);
+        tags.push((*tag, value));
+        payload = rest;
+    }
+    Some(tags)
+}
+
+fn discovered(packet: &[u8], from: SocketAddr) -> Option<Discovered> {
+    let tags = parse_reply(packet)?;
+    let value = |wanted: u8| tags.iter().find(|(tag, _)| *tag == wanted).map(|(_, value)| *value);
+    let device_type = value(TAG_DEVICE_TYPE).and_then(|v| <[u8; 4]>::try_from(v).ok()).map(u32::from_be_bytes);
+    if device_type != Some(DEVICE_TYPE_TUNER) {
+        return None;
+    }
+    let device_id = u32::from_be_bytes(value(TAG_DEVICE_ID)?.try_into().ok()?);
+    let base_url = value(TAG_BASE_URL)
+        .map(|url| String::from_utf8_lossy(url).trim_end_matches('\0').to_string())
+        .filter(|url| !url.is_empty())
+        .unwrap_or_else(|| format!("http://{}", from.ip()));
+    Some(Discovered {
+        device_id: format!("{:08X}", device_id),
+        base_url,
+        tuner_count: value(TAG_TUNER_COUNT).and_then(|v| v.first()).map(|count| u32::from(*count)),
+    })
+}
+
+// Broadcast a discovery request and collect answers until the timeout;
+// blocking, so it runs off the async runtime
+pub fn discover(timeout: Duration) -> Result<Vec<Discovered>, String> {
+    let error = |e: std::io::Error| format!("HDHomeRun discovery failed: {}", e);
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).map_err(error)?;
+    socket.set_broadcast(true).map_err(error)?;
+    socket
+        .send_to(&request(), (Ipv4Addr::BROADCAST, DISCOVERY_PORT))
+        .map_err(error)?;
+
+    let deadline = Instant::now() + timeout;
+    let mut devices: Vec<Discovered> = Vec::new();
+    let mut buffer = [0u8; 1500];
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        socket.set_read_timeout(Some(left)).map_err(error)?;
+        match socket.recv_from(&mut buffer) {
+            Ok((size, from)) => {
+                let Some(device) = discovered(&buffer[..size], from) else {
+                    continue;
+                };
+                if !devices.iter().any(|known| known.device_id == device.device_id) {
+                    devices.push(device);
+                }
+            }
+            Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => break,
+            Err(e) => return Err(error(e)),
+        }
+    }
+    Ok(devices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packets() {
+        // Test the checksum, the request layout and reading a device's reply
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        let packet = request();
+        assert_eq!(&packet[..4], &[0x00, 0x02, 0x00, 0x0C]);
+        assert_eq!(&packet[4..10], &[TAG_DEVICE_TYPE, 4, 0, 0, 0, 1]);
+
+        let mut payload = Vec::new();
+        push_tag(&mut payload, TAG_DEVICE_TYPE, &DEVICE_TYPE_TUNER.to_be_bytes());
+        push_tag(&mut payload, TAG_DEVICE_ID, &0x1050_A5C2u32.to_be_bytes());
+        push_tag(&mut payload, TAG_TUNER_COUNT, &[4]);
+        push_tag(&mut payload, 0x7F, &[b'x'; 200]);
+        let reply = frame(TYPE_DISCOVER_REPLY, &payload);
+        let from: SocketAddr = "192.168.1.20:65001".parse().unwrap();
+        let device = discovered(&reply, from).unwrap();
+        assert_eq!(device.device_id, "1050A5C2");
+        assert_eq!(device.base_url, "http://192.168.1.20");
+        assert_eq!(device.tuner_count, Some(4));
+
+        let mut corrupted = reply.clone();
+        corrupted[5] ^= 1;
+        assert!(discovered(&corrupted, from).is_none());
+        assert!(discovered(&packet, from).is_none());
+    }
+}
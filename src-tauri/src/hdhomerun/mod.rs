@@ -0,0 +1,240 @@
+// HDHomeRun network tuners: discovery on the local network, saved devices
+// and their channel lineups, streamed straight from the tuner
+mod client;
+mod discovery;
+mod model;
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use crate::identity;
+use crate::playlist::content_hash;
+use crate::storage;
+use crate::validate_string_length;
+
+pub use client::{HdhomerunClient, HdhomerunTuner};
+pub use model::{DeviceInfo, LineupChannel};
+
+const PROVIDERS_FILE: &str = "hdhomerun_providers.json";
+
+// Limits for user-entered tuner details
+const MAX_FIELD_LENGTH: usize = 2048;
+const MAX_NAME_LENGTH: usize = 200;
+
+// How long discovery waits for devices to answer
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+// A saved tuner
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HdhomerunProvider {
+    pub id: String,
+    pub name: String,
+    #[serde(flatten)]
+    pub tuner: HdhomerunTuner,
+}
+
+// Same device address always maps to the same provider
+fn provider_id(client: &HdhomerunClient) -> String {
+    content_hash(format!("hdhomerun:{}", client.server()).as_bytes())
+}
+
+impl HdhomerunProvider {
+    // A provider with a normalized address; an empty name becomes the
+    // address
+    pub fn new(client: &HdhomerunClient, name: &str) -> Self {
+        HdhomerunProvider {
+            id: provider_id(client),
+            name: match name.trim() {
+                "" => client.server().to_string(),
+                name => name.to_string(),
+            },
+            tuner: HdhomerunTuner {
+                url: client.server().to_string(),
+            },
+        }
+    }
+}
+
+pub struct HdhomerunProviders {
+    path: PathBuf,
+    providers: Mutex<Vec<HdhomerunProvider>>,
+}
+
+impl HdhomerunProviders {
+    pub fn open(data_dir: &Path) -> Self {
+        let path = data_dir.join(PROVIDERS_FILE);
+        let providers = storage::read_json(&path).unwrap_or_else(|e| {
+            log::error!("Failed to load HDHomeRun tuners: {}", e);
+            Vec::new()
+        });
+        Self {
+            path,
+            providers: Mutex::new(providers),
+        }
+    }
+
+    pub fn list(&self) -> Vec<HdhomerunProvider> {
+        self.providers.lock().unwrap().clone()
+    }
+
+    pub fn get(&self, id: &str) -> Result<HdhomerunProvider, String> {
+        self.providers
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|p| p.id == id)
+            .cloned()
+            .ok_or_else(|| format!("HDHomeRun tuner '{}' doesn't exist", id))
+    }
+
+    pub fn upsert(&self, provider: HdhomerunProvider) -> Result<(), String> {
+        let mut providers = self.providers.lock().unwrap();
+        match providers.iter_mut().find(|p| p.id == provider.id) {
+            Some(existing) => *existing = provider,
+            None => providers.push(provider),
+        }
+        storage::write_json(&self.path, &*providers)
+    }
+
+    pub fn remove(&self, id: &str) -> Result<bool, String> {
+        let mut providers = self.providers.lock().unwrap();
+        let before = providers.len();
+        providers.retain(|p| p.id != id);
+        if providers.len() == before {
+            return Ok(false);
+        }
+        storage::write_json(&self.path, &*providers)?;
+        Ok(true)
+    }
+}
+
+pub fn check_tuner(tuner: &HdhomerunTuner) -> Result<(), String> {
+    validate_string_length(&tuner.url, MAX_FIELD_LENGTH)
+}
+
+fn provider_client(providers: &HdhomerunProviders, provider_id: &str) -> Result<HdhomerunClient, String> {
+    HdhomerunClient::new(&providers.get(provider_id)?.tuner)
+}
+
+// A tuner found on the network, with its details when it answered them
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveredTuner {
+    pub device_id: String,
+    pub base_url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tuner_count: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<DeviceInfo>,
+}
+
+// Command handler looking for tuners on the local network
+#[tauri::command]
+pub async fn hdhomerun_discover() -> Result<Vec<DiscoveredTuner>, String> {
+    let found = tauri::async_runtime::spawn_blocking(|| discovery::discover(DISCOVERY_TIMEOUT))
+        .await
+        .map_err(|e| format!("HDHomeRun discovery failed: {}", e))??;
+    let mut tuners = Vec::new();
+    for device in found {
+        let tuner = HdhomerunTuner {
+            url: device.base_url.clone(),
+        };
+        let info = match HdhomerunClient::new(&tuner) {
+            Ok(client) => client.device().await,
+            Err(e) => Err(e),
+        };
+        let info = info
+            .inspect_err(|e| log::info!("No details for tuner {}: {}", device.device_id, e))
+            .ok();
+        tuners.push(DiscoveredTuner {
+            device_id: device.device_id,
+            base_url: device.base_url,
+            tuner_count: info.as_ref().map(|info| info.tuner_count).or(device.tuner_count),
+            device: info,
+        });
+    }
+    Ok(tuners)
+}
+
+// Command handler that checks a tuner answers and saves it as a provider
+#[tauri::command]
+pub async fn hdhomerun_add_provider(
+    providers: State<'_, HdhomerunProviders>,
+    name: String,
+    tuner: HdhomerunTuner,
+) -> Result<HdhomerunProvider, String> {
+    check_tuner(&tuner)?;
+    validate_string_length(&name, MAX_NAME_LENGTH)?;
+    let client = HdhomerunClient::new(&tuner)?;
+    let device = client.device().await?;
+
+    let name = match name.trim() {
+        "" => device.friendly_name.as_str(),
+        name => name,
+    };
+    let provider = HdhomerunProvider::new(&client, name);
+    providers.upsert(provider.clone())?;
+    Ok(provider)
+}
+
+// Command handler listing the saved tuners
+#[tauri::command]
+pub fn hdhomerun_list_providers(providers: State<'_, HdhomerunProviders>) -> Vec<HdhomerunProvider> {
+    providers.list()
+}
+
+// Command handler that deletes a saved tuner
+#[tauri::command]
+pub fn hdhomerun_remove_provider(providers: State<'_, HdhomerunProviders>, provider_id: String) -> Result<bool, String> {
+    providers.remove(&provider_id)
+}
+
+// Command handler describing a saved tuner: model, firmware, tuner count
+#[tauri::command]
+pub async fn hdhomerun_get_device(
+    providers: State<'_, HdhomerunProviders>,
+    provider_id: String,
+) -> Result<DeviceInfo, String> {
+    provider_client(&providers, &provider_id)?.device().await
+}
+
+// Command handler listing a tuner's channels with their stream URLs
+#[tauri::command]
+pub async fn hdhomerun_get_lineup(
+    providers: State<'_, HdhomerunProviders>,
+    provider_id: String,
+) -> Result<Vec<LineupChannel>, String> {
+    let client = provider_client(&providers, &provider_id)?;
+    let mut channels = client.lineup(&client.device().await?).await?;
+    for channel in &mut channels {
+        channel.channel_id = identity::channel_id(&provider_id, &identity::hdhomerun_key(&channel.guide_number));
+    }
+    Ok(channels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn test_providers_persist() {
+        // Test that saved tuners survive reopening and ids ignore address spelling
+        let client = |url: &str| HdhomerunClient::new(&HdhomerunTuner { url: url.to_string() }).unwrap();
+        assert_eq!(provider_id(&client("192.168.1.20")), provider_id(&client("http://192.168.1.20/discover.json")));
+
+        let dir = temp_dir("hdhomerun-providers");
+        let providers = HdhomerunProviders::open(&dir);
+        let provider = HdhomerunProvider::new(&client("192.168.1.20"), " ");
+        assert_eq!(provider.name, "http://192.168.1.20");
+        providers.upsert(provider.clone()).unwrap();
+        assert_eq!(HdhomerunProviders::open(&dir).get(&provider.id).unwrap(), provider);
+        assert!(providers.remove(&provider.id).unwrap());
+        assert!(providers.get(&provider.id).is_err());
+    }
+}
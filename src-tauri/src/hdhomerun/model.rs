@@ -0,0 +1,75 @@
+// Typed views of the HDHomeRun HTTP API (discover.json and lineup.json)
+use serde::{Deserialize, Serialize};
+
+use crate::de;
+
+// Answer of discover.json
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all(serialize = "camelCase"))]
+pub struct DeviceInfo {
+    #[serde(rename(deserialize = "DeviceID"), deserialize_with = "de::string")]
+    pub device_id: String,
+    #[serde(rename(deserialize = "FriendlyName"), deserialize_with = "de::string")]
+    pub friendly_name: String,
+    #[serde(rename(deserialize = "ModelNumber"), deserialize_with = "de::string")]
+    pub model_number: String,
+    #[serde(rename(deserialize = "FirmwareVersion"), deserialize_with = "de::string")]
+    pub firmware_version: String,
+    #[serde(rename(deserialize = "BaseURL"), deserialize_with = "de::string")]
+    pub base_url: String,
+    #[serde(rename(deserialize = "LineupURL"), deserialize_with = "de::string")]
+    pub lineup_url: String,
+    // Streams the device can serve at once
+    #[serde(rename(deserialize = "TunerCount"), deserialize_with = "de::u32")]
+    pub tuner_count: u32,
+}
+
+// Item of lineup.json
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all(serialize = "camelCase"))]
+pub struct LineupChannel {
+    // Virtual channel number such as "5.1"
+    #[serde(rename(deserialize = "GuideNumber"), deserialize_with = "de::string")]
+    pub guide_number: String,
+    #[serde(rename(deserialize = "GuideName"), deserialize_with = "de::string")]
+    pub guide_name: String,
+    #[serde(rename(deserialize = "URL"), deserialize_with = "de::string")]
+    pub url: String,
+    #[serde(rename(deserialize = "VideoCodec"), deserialize_with = "de::opt_string")]
+    pub video_codec: Option<String>,
+    #[serde(rename(deserialize = "AudioCodec"), deserialize_with = "de::opt_string")]
+    pub audio_codec: Option<String>,
+    #[serde(rename(deserialize = "HD"), deserialize_with = "de::bool")]
+    pub hd: bool,
+    #[serde(rename(deserialize = "Favorite"), deserialize_with = "de::bool")]
+    pub favorite: bool,
+    // Copy-protected; most players can't decode these
+    #[serde(rename(deserialize = "DRM"), deserialize_with = "de::bool")]
+    pub drm: bool,
+    // Stable identity (see crate::identity), filled in per provider
+    #[serde(skip_deserializing)]
+    pub channel_id: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_and_lineup_shapes() {
+        // Test the device's capitalized field names and flag values
+        let device: DeviceInfo = serde_json::from_str(
+            r#"{"FriendlyName": "HDHomeRun CONNECT", "DeviceID": "1050A5C2", "TunerCount": 2,
+                "BaseURL": "http://192.168.1.20", "LineupURL": "http://192.168.1.20/lineup.json"}"#,
+        )
+        .unwrap();
+        assert_eq!((device.device_id.as_str(), device.tuner_count), ("1050A5C2", 2));
+
+        let channel: LineupChannel = serde_json::from_str(
+            r#"{"GuideNumber": "5.1", "GuideName": "KING-HD", "HD": 1, "URL": "http://192.168.1.20:5004/auto/v5.1"}"#,
+        )
+        .unwrap();
+        assert!(channel.hd && !channel.drm);
+        assert_eq!(serde_json::to_value(&channel).unwrap()["guideNumber"], "5.1");
+    }
+}
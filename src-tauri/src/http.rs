@@ -0,0 +1,219 @@
+// Shared HTTP client and per-request options
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use reqwest::header::{HeaderName, HeaderValue, REFERER, USER_AGENT};
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+
+// User agent sent when neither the channel nor the provider sets one
+const DEFAULT_USER_AGENT: &str = concat!("TIPTV/", env!("CARGO_PKG_VERSION"));
+
+// HTTP options a stream or playlist needs to be fetched successfully,
+// collected from #EXTVLCOPT, #KODIPROP, #EXTHTTP and "url|Header=value"
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub referrer: Option<String>,
+    // Additional headers (Origin, Cookie, ...) keyed by header name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+}
+
+impl HttpOptions {
+    pub fn is_empty(&self) -> bool {
+        self.user_agent.is_none() && self.referrer.is_none() && self.headers.is_empty()
+    }
+
+    // Set a header by name, routing User-Agent and Referer to their fields
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        let name = name.trim();
+        let value = value.trim();
+        if name.is_empty() || value.is_empty() {
+            return;
+        }
+        match name.to_ascii_lowercase().as_str() {
+            "user-agent" => self.user_agent = Some(value.to_string()),
+            "referer" | "referrer" => self.referrer = Some(value.to_string()),
+            _ => {
+                self.headers.insert(name.to_string(), value.to_string());
+            }
+        }
+    }
+
+    // Parse "Header=value&Other=value" lists (values may be URL-encoded)
+    pub fn merge_query(&mut self, query: &str) {
+        for (name, value) in url::form_urlencoded::parse(query.as_bytes()) {
+            self.set_header(&name, &value);
+        }
+    }
+
+    // Just the user agent and referrer, which can go along with a stream to
+    // any host
+    pub fn public(&self) -> HttpOptions {
+        HttpOptions {
+            user_agent: self.user_agent.clone(),
+            referrer: self.referrer.clone(),
+            headers: BTreeMap::new(),
+        }
+    }
+
+    // Take every option set in another set of options, overriding ours
+    pub fn extend(&mut self, other: HttpOptions) {
+        if other.user_agent.is_some() {
+            self.user_agent = other.user_agent;
+        }
+        if other.referrer.is_some() {
+            self.referrer = other.referrer;
+        }
+        self.headers.extend(other.headers);
+    }
+}
+
+// Credentials sent with HTTP basic authentication
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BasicAuth {
+    pub username: String,
+    #[serde(default)]
+    pub password: String,
+}
+
+impl BasicAuth {
+    // Value of the Authorization header for these credentials
+    pub fn header_value(&self) -> String {
+        let credentials = format!("{}:{}", self.username, self.password);
+        format!("Basic {}", STANDARD.encode(credentials))
+    }
+}
+
+// Split a Kodi-style "http://host/stream|User-Agent=x&Referer=y" URL
+pub fn split_url_options(url: &str) -> (String, Option<HttpOptions>) {
+    match url.split_once('|') {
+        Some((base, query)) => {
+            let mut options = HttpOptions::default();
+            options.merge_query(query);
+            let options = if options.is_empty() { None } else { Some(options) };
+            (base.trim().to_string(), options)
+        }
+        None => (url.to_string(), None),
+    }
+}
+
+// Shared client so connections are pooled across requests
+pub fn client() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        Client::builder()
+            .user_agent(DEFAULT_USER_AGENT)
+            .build()
+            .expect("failed to build HTTP client")
+    })
+}
+
+// Client that never follows redirects, for inspecting redirect chains
+pub fn no_redirect_client() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        Client::builder()
+            .user_agent(DEFAULT_USER_AGENT)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("failed to build HTTP client")
+    })
+}
+
+// Start a GET request with the given options applied
+pub fn get(url: &str, options: Option<&HttpOptions>) -> RequestBuilder {
+    apply_options(client().get(url), options)
+}
+
+// Apply user agent, referrer and extra headers to a request, skipping
+// values that aren't valid HTTP headers
+pub fn apply_options(mut request: RequestBuilder, options: Option<&HttpOptions>) -> RequestBuilder {
+    let Some(options) = options else {
+        return request;
+    };
+
+    if let Some(value) = options.user_agent.as_deref().and_then(header_value) {
+        request = request.header(USER_AGENT, value);
+    }
+    if let Some(value) = options.referrer.as_deref().and_then(header_value) {
+        request = request.header(REFERER, value);
+    }
+    for (name, value) in &options.headers {
+        if let (Ok(name), Some(value)) = (HeaderName::from_bytes(name.as_bytes()), header_value(value)) {
+            request = request.header(name, value);
+        }
+    }
+    request
+}
+
+fn header_value(value: &str) -> Option<HeaderValue> {
+    HeaderValue::from_str(value).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_header_routes_known_names() {
+        // Test that User-Agent and Referer go to their dedicated fields
+        let mut options = HttpOptions::default();
+        options.set_header("user-agent", "VLC/3.0");
+        options.set_header("Referrer", "http://portal/");
+        options.set_header("Origin", "http://portal");
+        options.set_header("Cookie", "");
+
+        assert_eq!(options.user_agent.as_deref(), Some("VLC/3.0"));
+        assert_eq!(options.referrer.as_deref(), Some("http://portal/"));
+        assert_eq!(options.headers.len(), 1);
+        assert_eq!(options.headers["Origin"], "http://portal");
+    }
+
+    #[test]
+    fn test_basic_auth_header_value() {
+        // Test the RFC 7617 encoding of user:password
+        let auth = BasicAuth {
+            username: "Aladdin".to_string(),
+            password: "open sesame".to_string(),
+        };
+        assert_eq!(auth.header_value(), "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ==");
+    }
+
+    #[test]
+    fn test_split_url_options() {
+        // Test that Kodi-style header suffixes are removed from the URL
+        let (url, options) =
+            split_url_options("http://host/live.m3u8|User-Agent=Mozilla%2F5.0&Referer=http%3A%2F%2Fsite%2F");
+        let options = options.unwrap();
+
+        assert_eq!(url, "http://host/live.m3u8");
+        assert_eq!(options.user_agent.as_deref(), Some("Mozilla/5.0"));
+        assert_eq!(options.referrer.as_deref(), Some("http://site/"));
+
+        let (plain, none) = split_url_options("http://host/live.ts");
+        assert_eq!(plain, "http://host/live.ts");
+        assert!(none.is_none());
+    }
+
+    #[test]
+    fn test_extend_overrides_set_values() {
+        // Test that extending only replaces options the other side sets
+        let mut base = HttpOptions::default();
+        base.set_header("User-Agent", "base");
+        base.set_header("Referer", "http://base/");
+        let mut suffix = HttpOptions::default();
+        suffix.set_header("User-Agent", "suffix");
+
+        base.extend(suffix);
+        assert_eq!(base.user_agent.as_deref(), Some("suffix"));
+        assert_eq!(base.referrer.as_deref(), Some("http://base/"));
+    }
+}
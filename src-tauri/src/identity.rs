@@ -0,0 +1,142 @@
+// Stable channel identities: "<provider id>:<stream key>", where the stream
+// key hashes what identifies a stream within its provider. Favorites,
+// history, overrides and EPG mappings keyed this way survive refreshes, and
+// two providers that both have a "stream 1234" never collide
+use url::Url;
+
+use crate::playlist::{content_hash, xtream_parts};
+
+// Query parameters providers use for rotating tokens and signatures; the
+// rest of a query (play.php?id=1) tells streams apart
+const TOKEN_PARAMS: [&str; 16] = [
+    "token", "auth", "auth_key", "e", "st", "exp", "expires", "hash", "md5", "sig", "signature", "wmsauthsign",
+    "hdnts", "hdnea", "validfrom", "validto",
+];
+
+fn origin(url: &Url) -> String {
+    url.origin().ascii_serialization()
+}
+
+// Origin of the Xtream panel a playlist was downloaded from, when its source
+// is the panel's get.php or player_api.php with a login
+pub fn xtream_server(source: &str) -> Option<String> {
+    let url = Url::parse(source.trim()).ok()?;
+    let script = url.path_segments().and_then(|mut segments| segments.next_back()).unwrap_or_default();
+    let login = url.query_pairs().any(|(key, _)| key == "username");
+    (matches!(script, "get.php" | "player_api.php") && login).then(|| origin(&url))
+}
+
+pub fn channel_id(provider_id: &str, stream_key: &str) -> String {
+    format!("{}:{}", provider_id, stream_key)
+}
+
+// Stream of an Xtream server, by the id the panel assigned it
+pub fn xtream_key(stream_id: &str) -> String {
+    content_hash(format!("xtream:live:{}", stream_id.trim()).as_bytes())
+}
+
+// Channel of a Stalker portal, by the portal's channel id
+pub fn stalker_key(channel_id: &str) -> String {
+    content_hash(format!("stalker:{}", channel_id.trim()).as_bytes())
+}
+
+// Channel of a TVHeadend server, by the uuid the server gave it
+pub fn tvheadend_key(uuid: &str) -> String {
+    content_hash(format!("tvheadend:{}", uuid.trim()).as_bytes())
+}
+
+// Channel of an HDHomeRun tuner, by its virtual channel number
+pub fn hdhomerun_key(guide_number: &str) -> String {
+    content_hash(format!("hdhomerun:{}", guide_number.trim()).as_bytes())
+}
+
+// Channel of a SAT>IP server, by its tuning parameters: the server's
+// address can change, and servers list them in no fixed order
+pub fn satip_key(url: &str) -> String {
+    let query = url.trim().split_once('?').map_or("", |(_, query)| query);
+    let mut params: Vec<String> = query
+        .split('&')
+        .filter(|param| !param.is_empty())
+        .map(|param| match param.split_once('=') {
+            Some((key, value)) => format!("{}={}", key.to_ascii_lowercase(), value),
+            None => param.to_ascii_lowercase(),
+        })
+        .collect();
+    params.sort();
+    content_hash(format!("satip:{}", params.join("&")).as_bytes())
+}
+
+// Playlist entry, by its stream URL and the Xtream panel its playlist came
+// from (see xtream_server). Xtream URLs (/live/user/pass/id, or /user/pass/id
+// on that panel) carry the login in the path, so they're keyed by server and
+// stream id and survive a password change; other URLs drop only the token
+// parameters of their query. Names, groups and EPG ids are exactly what
+// users override, so they can't be part of it
+pub fn url_key(url: &str, xtream_server: Option<&str>) -> String {
+    let url = url.trim();
+    if let Some((parsed, _, _, stream_id)) = xtream_parts(url) {
+        let live = parsed.path_segments().and_then(|mut segments| segments.next()) == Some("live");
+        if live || xtream_server == Some(origin(&parsed).as_str()) {
+            return content_hash(format!("xtream:{}:{}", origin(&parsed), stream_id).as_bytes());
+        }
+    }
+    let url = url.split('#').next().unwrap_or(url);
+    let (stream, query) = url.split_once('?').unwrap_or((url, ""));
+    let mut params: Vec<&str> = query
+        .split('&')
+        .filter(|param| {
+            let name = param.split('=').next().unwrap_or(param);
+            !name.is_empty() && !TOKEN_PARAMS.iter().any(|token| token.eq_ignore_ascii_case(name))
+        })
+        .collect();
+    if params.is_empty() {
+        return content_hash(format!("url:{}", stream).as_bytes());
+    }
+    params.sort_unstable();
+    content_hash(format!("url:{}?{}", stream, params.join("&")).as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_ignore_tokens_and_logins() {
+        // Test that rotating tokens and changed passwords keep the key
+        assert_eq!(url_key(" http://a/hls/1.m3u8?token=abc ", None), url_key("http://a/hls/1.m3u8#x", None));
+        assert_ne!(url_key("http://a/hls/1.m3u8", None), url_key("http://a/hls/2.m3u8", None));
+        assert_eq!(url_key("http://a/hls/1.m3u8?st=1&e=2", None), url_key("http://a/hls/1.m3u8", None));
+        assert_eq!(
+            url_key("http://x/live/user/old/1234.ts", None),
+            url_key("http://x/live/user/new/1234.m3u8", None)
+        );
+        let server = xtream_server("http://panel.test:8080/get.php?username=u&password=p&type=m3u_plus");
+        assert_eq!(server.as_deref(), Some("http://panel.test:8080"));
+        assert_eq!(
+            url_key("http://panel.test:8080/user/old/1234", server.as_deref()),
+            url_key("http://panel.test:8080/live/u/p/1234.ts", None)
+        );
+        assert_ne!(
+            url_key("http://panel.test:8080/user/old/1234", None),
+            url_key("http://panel.test:8080/user/new/1234", None)
+        );
+        assert_eq!(xtream_server("http://panel.test/list.m3u"), None);
+        assert_ne!(xtream_key("1234"), stalker_key("1234"));
+        assert_ne!(stalker_key("1234"), tvheadend_key("1234"));
+        assert_eq!(hdhomerun_key("5.1 "), hdhomerun_key("5.1"));
+        assert_eq!(satip_key("rtsp://a/?src=1&freq=11494"), satip_key("rtsp://b:554/?freq=11494&SRC=1"));
+        assert_ne!(channel_id("a", &xtream_key("1")), channel_id("b", &xtream_key("1")));
+    }
+
+    #[test]
+    fn test_url_keys_dont_collide() {
+        // Test that streams on other hosts, paths or query ids keep separate keys
+        let key = |url: &str| url_key(url, Some("http://a"));
+        assert_ne!(key("http://cdn/news/720/1.m3u8"), key("http://cdn/sport/720/1.m3u8"));
+        assert_ne!(key("http://cdn/news/720/1.m3u8"), key("http://cdn/news/1080/1.m3u8"));
+        assert_ne!(key("http://a/x/y/1.ts"), key("http://b/p/q/1.ts"));
+        assert_ne!(key("http://a/live/u/p/1.ts"), key("http://b/live/u/p/1.ts"));
+        assert_ne!(key("http://a/play.php?id=1"), key("http://a/play.php?id=2"));
+        assert_eq!(key("http://a/play.php?token=x&id=1"), key("http://a/play.php?id=1&token=y"));
+    }
+}
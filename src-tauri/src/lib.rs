@@ -1,3 +1,28 @@
+mod compression;
+mod de;
+mod dropped;
+mod epg;
+mod hdhomerun;
+mod http;
+mod identity;
+mod playback;
+mod playlist;
+mod providers;
+mod proxy;
+mod recording;
+mod resolve;
+mod satip;
+mod stalker;
+mod storage;
+#[cfg(test)]
+mod test_support;
+mod tray;
+mod tvheadend;
+mod xml;
+mod xtream;
+
+use tauri::Manager;
+
 // Input validation helper functions
 fn validate_string_length(input: &str, max_length: usize) -> Result<(), String> {
     if input.len() > max_length {
@@ -13,6 +38,14 @@ fn sanitize_string(input: &str) -> String {
         .collect()
 }
 
+// Current time as Unix seconds
+fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
 // Basic command handler for platform information
 #[tauri::command]
 fn get_platform_info() -> Result<String, String> {
@@ -49,7 +82,81 @@ pub fn run() {
   tauri::Builder::default()
     .plugin(tauri_plugin_store::Builder::default().build())
     .plugin(tauri_plugin_process::init())
+    .plugin(tauri_plugin_notification::init())
+    .manage(playlist::PlaylistStore::default())
+    .manage(providers::Failover::default())
+    .manage(providers::ConnectionTracker::default())
+    .manage(tray::TrayState::default())
+    .manage(proxy::StreamProxy::default())
+    .on_window_event(dropped::on_window_event)
+    .on_page_load(providers::on_page_load)
     .setup(|app| {
+      let cache_dir = app.path().app_cache_dir()?;
+      app.manage(playlist::DownloadCache::open(cache_dir.join("playlists")));
+      let artwork = epg::artwork::ArtworkCache::open(cache_dir.join("epg-artwork"), epg::artwork::MAX_CACHE_BYTES);
+      if let Err(e) = app.asset_protocol_scope().allow_directory(artwork.dir(), true) {
+        log::error!("Failed to allow the artwork cache in the asset scope: {}", e);
+      }
+      app.manage(artwork);
+      let data_dir = app.path().app_data_dir()?;
+      app.manage(playlist::PlaylistLibrary::open(data_dir.join("playlists")));
+      app.manage(playlist::PlaylistHistory::open(data_dir.join("playlists").join("history")));
+      app.manage(playlist::PlaylistWatcher::start(app.handle().clone()));
+      app.manage(playlist::watch_folder::WatchFolder::open(&data_dir));
+      app.manage(playlist::ChannelRules::open(&data_dir));
+      let overrides = playlist::ChannelOverrides::open(&data_dir, &app.state::<playlist::PlaylistLibrary>());
+      app.manage(overrides);
+      app.manage(xtream::XtreamProviders::open(&data_dir));
+      app.manage(xtream::XtreamCache::default());
+      app.manage(xtream::AccountRotation::default());
+      app.manage(stalker::StalkerProviders::open(&data_dir));
+      app.manage(stalker::StalkerSessions::default());
+      app.manage(tvheadend::TvheadendProviders::open(&data_dir));
+      app.manage(hdhomerun::HdhomerunProviders::open(&data_dir));
+      app.manage(satip::SatipProviders::open(&data_dir));
+      app.manage(satip::SatipStreams::default());
+      app.manage(providers::ProviderRegistry::open(&data_dir));
+      app.manage(epg::EpgStore::open(data_dir.join("epg")));
+      app.manage(epg::sources::XmltvSources::open(&data_dir));
+      app.manage(epg::grabbers::GrabberSources::open(&data_dir));
+      app.manage(epg::mappings::EpgMappings::open(&data_dir));
+      app.manage(epg::reminders::Reminders::open(&data_dir));
+      app.manage(epg::watcher::EpgWatch::open(&data_dir));
+      app.manage(epg::parental::ParentalControls::open(&data_dir));
+      app.manage(epg::languages::EpgLanguages::open(&data_dir));
+      app.manage(recording::RecordingSchedule::open(&data_dir));
+      app.manage(proxy::transcode::Transcoding::open(&data_dir));
+      app.manage(proxy::profiles::TranscodeProfiles::open(&data_dir));
+      app.manage(proxy::quality::StreamQualities::open(&data_dir));
+      app.manage(proxy::multicast::Multicast::open(&data_dir));
+      app.manage(proxy::scte35::SpliceMarkers::open(&data_dir));
+      let thumbnails = proxy::thumbnails::Thumbnails::open(&data_dir, cache_dir.join("thumbnails"));
+      if let Err(e) = app.asset_protocol_scope().allow_directory(thumbnails.dir(), true) {
+        log::error!("Failed to allow the thumbnail cache in the asset scope: {}", e);
+      }
+      app.manage(thumbnails);
+      app.manage(proxy::timeshift::Timeshift::open(&data_dir, cache_dir.join("timeshift")));
+      app.manage(playback::Playback::open(&data_dir));
+      if let Err(e) = providers::sync(app.handle()) {
+        log::error!("Failed to sync providers: {}", e);
+      }
+      playlist::scheduler::start(app.handle().clone());
+      xtream::guide::start(app.handle().clone());
+      epg::sources::start(app.handle().clone());
+      epg::grabbers::start(app.handle().clone());
+      epg::retention::start(app.handle().clone());
+      epg::reminders::start(app.handle().clone());
+      epg::watcher::start(app.handle().clone());
+      epg::parental::start(app.handle().clone());
+      recording::series::start(app.handle().clone());
+      proxy::start(app.handle().clone());
+      proxy::thumbnails::start(app.handle().clone());
+      match tray::create(app.handle()) {
+        Ok(()) => tray::start(app.handle().clone()),
+        Err(e) => log::error!("Failed to create the tray icon: {}", e),
+      }
+      playlist::watch_folder::activate(app.handle());
+
       if cfg!(debug_assertions) {
         app.handle().plugin(
           tauri_plugin_log::Builder::default()
@@ -62,10 +169,216 @@ pub fn run() {
     .invoke_handler(tauri::generate_handler![
       get_platform_info,
       get_app_version,
-      greet
+      greet,
+      playlist::parse_playlist,
+      playlist::load_playlist,
+      playlist::refresh_playlist,
+      playlist::get_playlist_page,
+      playlist::get_playlist_diff,
+      playlist::bulk_edit_channels,
+      playlist::get_channel_overrides,
+      playlist::set_channel_override,
+      playlist::get_channel_numbering,
+      playlist::find_channel_by_number,
+      playlist::renumber_channels,
+      playlist::resequence_group,
+      playlist::resolve_number_conflicts,
+      playlist::get_catchup_url,
+      playlist::find_duplicate_channels,
+      playlist::merge_playlists,
+      playlist::sanitize_playlist,
+      playlist::export_playlist,
+      playlist::import_enigma2,
+      playlist::import_channels_csv,
+      playlist::export_channels_csv,
+      dropped::handle_dropped_file,
+      resolve::resolve_url,
+      playlist::watch_folder::get_watch_folder,
+      playlist::watch_folder::set_watch_folder,
+      playlist::channel_rules::get_channel_rules,
+      playlist::channel_rules::set_channel_rules,
+      playlist::list_playlists,
+      playlist::add_playlist,
+      playlist::update_playlist,
+      playlist::set_playlist_http,
+      playlist::remove_playlist,
+      playlist::list_playlist_versions,
+      playlist::rollback_playlist,
+      xtream::xtream_login,
+      xtream::xtream_add_provider,
+      xtream::xtream_open_stream,
+      xtream::xtream_rotate_account,
+      xtream::xtream_close_stream,
+      xtream::xtream_get_account_usage,
+      xtream::xtream_set_accounts,
+      xtream::xtream_list_providers,
+      xtream::xtream_remove_provider,
+      xtream::xtream_get_live_categories,
+      xtream::xtream_get_live_streams,
+      xtream::xtream_get_vod_categories,
+      xtream::xtream_get_vod_streams,
+      xtream::xtream_get_vod_info,
+      xtream::xtream_get_series_categories,
+      xtream::xtream_get_series,
+      xtream::xtream_get_series_info,
+      xtream::xtream_get_short_epg,
+      xtream::xtream_get_account_info,
+      xtream::xtream_refresh_epg,
+      stalker::stalker_login,
+      stalker::stalker_add_provider,
+      stalker::stalker_list_providers,
+      stalker::stalker_remove_provider,
+      stalker::stalker_get_genres,
+      stalker::stalker_get_channels,
+      stalker::stalker_create_link,
+      stalker::stalker_get_short_epg,
+      tvheadend::tvheadend_login,
+      tvheadend::tvheadend_add_provider,
+      tvheadend::tvheadend_list_providers,
+      tvheadend::tvheadend_remove_provider,
+      tvheadend::tvheadend_get_tags,
+      tvheadend::tvheadend_get_channels,
+      tvheadend::tvheadend_get_epg,
+      tvheadend::tvheadend_refresh_epg,
+      tvheadend::tvheadend_stream_url,
+      hdhomerun::hdhomerun_discover,
+      hdhomerun::hdhomerun_add_provider,
+      hdhomerun::hdhomerun_list_providers,
+      hdhomerun::hdhomerun_remove_provider,
+      hdhomerun::hdhomerun_get_device,
+      hdhomerun::hdhomerun_get_lineup,
+      satip::satip_discover,
+      satip::satip_add_provider,
+      satip::satip_list_providers,
+      satip::satip_remove_provider,
+      satip::satip_get_server,
+      satip::satip_get_channels,
+      satip::satip_open_stream,
+      satip::satip_close_stream,
+      providers::list_providers,
+      providers::test_provider,
+      providers::add_provider,
+      providers::update_provider,
+      providers::delete_provider,
+      providers::reorder_providers,
+      providers::list_logical_channels,
+      providers::select_channel_source,
+      providers::open_connection,
+      providers::close_connection,
+      providers::list_connections,
+      epg::list_epg_sources,
+      epg::get_epg_channels,
+      epg::get_epg_programmes,
+      epg::get_now_next,
+      epg::get_programs,
+      epg::get_epg_grid,
+      epg::search_epg,
+      epg::match_epg_channels,
+      epg::get_epg_priority,
+      epg::set_epg_priority,
+      epg::export_epg,
+      epg::get_epg_coverage,
+      epg::get_archive_programs,
+      epg::artwork::clear_epg_artwork,
+      epg::parental::get_parental_controls,
+      epg::parental::set_parental_controls,
+      epg::parental::set_parental_pin,
+      epg::parental::unlock_parental_controls,
+      epg::parental::lock_parental_controls,
+      epg::parental::check_playback,
+      recording::schedule_recording,
+      recording::list_recordings,
+      recording::cancel_recording,
+      recording::get_recording_settings,
+      recording::set_recording_settings,
+      recording::get_recording_chapters,
+      recording::series::list_series_candidates,
+      recording::series::add_series_rule,
+      recording::series::list_series_rules,
+      recording::series::remove_series_rule,
+      epg::get_epg_offsets,
+      epg::set_epg_source_offset,
+      epg::set_epg_channel_offset,
+      epg::retention::get_epg_storage_stats,
+      epg::retention::set_epg_retention_days,
+      epg::genres::list_epg_categories,
+      epg::genres::get_epg_genre_overrides,
+      epg::genres::set_epg_genre_override,
+      epg::languages::list_epg_languages,
+      epg::languages::get_epg_languages,
+      epg::languages::set_epg_languages,
+      epg::reminders::set_reminder,
+      epg::reminders::cancel_reminder,
+      epg::reminders::list_reminders,
+      epg::reminders::set_reminder_lead_minutes,
+      epg::reminders::open_reminder,
+      epg::reminders::export_reminders_ics,
+      epg::watcher::get_epg_watch,
+      epg::watcher::set_epg_watch,
+      epg::mappings::list_epg_mappings,
+      epg::mappings::set_epg_mapping,
+      epg::mappings::clear_epg_mapping,
+      epg::sources::add_xmltv_source,
+      epg::sources::list_xmltv_sources,
+      epg::sources::refresh_xmltv_source,
+      epg::sources::set_xmltv_refresh_interval,
+      epg::sources::remove_xmltv_source,
+      epg::grabbers::list_epg_grabbers,
+      epg::grabbers::add_grabber_source,
+      epg::grabbers::list_grabber_sources,
+      epg::grabbers::refresh_grabber_source,
+      epg::grabbers::remove_grabber_source,
+      proxy::get_proxy_url,
+      proxy::get_subtitle_url,
+      proxy::get_teletext_url,
+      proxy::get_captions_url,
+      proxy::backups::get_stream_source,
+      proxy::id3::get_stream_metadata,
+      proxy::scte35::list_splice_markers,
+      proxy::screenshot::capture_screenshot,
+      proxy::thumbnails::get_channel_thumbnails,
+      proxy::thumbnails::get_thumbnail_settings,
+      proxy::thumbnails::set_thumbnail_settings,
+      proxy::timeshift::get_timeshift_status,
+      proxy::timeshift::seek_timeshift,
+      proxy::timeshift::seek_back,
+      proxy::timeshift::get_timeshift_settings,
+      proxy::timeshift::set_timeshift_settings,
+      proxy::transcode::get_transcode_settings,
+      proxy::transcode::set_transcode_settings,
+      proxy::hwaccel::get_hw_accel_info,
+      proxy::probe::probe_stream,
+      proxy::profiles::list_transcode_profiles,
+      proxy::profiles::save_transcode_profile,
+      proxy::profiles::delete_transcode_profile,
+      proxy::profiles::set_transcode_profile_choice,
+      proxy::quality::get_stream_qualities,
+      proxy::quality::set_stream_quality,
+      proxy::multicast::get_multicast_settings,
+      proxy::multicast::set_multicast_settings,
+      playback::list_playback_engines,
+      playback::set_playback_engine,
+      playback::player_load,
+      playback::player_set_paused,
+      playback::player_seek,
+      playback::player_set_track,
+      playback::player_set_volume,
+      playback::player_stop,
+      playback::player_set_bounds,
+      playback::player_audio_tracks,
+      playback::get_audio_preference,
+      playback::set_audio_preference,
+      tray::set_now_playing,
+      tray::clear_now_playing
     ])
-    .run(tauri::generate_context!())
-    .expect("error while running tauri application");
+    .build(tauri::generate_context!())
+    .expect("error while running tauri application")
+    .run(|app, event| {
+      if let tauri::RunEvent::Exit = event {
+        proxy::stop(app);
+        playback::stop(app);
+      }
+    });
 }
 
 #[cfg(test)]
@@ -0,0 +1,179 @@
+// Audio tracks of the stream playing and the track chosen per channel.
+// Engines report tracks as mpv's track-list; picking an audio track on a
+// channel saves its language (and title, to tell apart two tracks in one
+// language), and the next time the channel plays the saved track is
+// selected as soon as the tracks are known
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::storage;
+use crate::validate_string_length;
+
+const PREFERENCES_FILE: &str = "audio_preferences.json";
+
+const MAX_FIELD_LENGTH: usize = 200;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioTrack {
+    // The engine's track id, for player_set_track
+    pub id: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub codec: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channels: Option<u32>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub selected: bool,
+}
+
+// The audio a channel plays with
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioPreference {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+impl AudioPreference {
+    pub fn check(&self) -> Result<(), String> {
+        self.language.iter().chain(&self.title).try_for_each(|field| validate_string_length(field, MAX_FIELD_LENGTH))
+    }
+}
+
+// The audio entries of a track-list
+pub fn audio_tracks(track_list: &Value) -> Vec<AudioTrack> {
+    let Some(tracks) = track_list.as_array() else {
+        return Vec::new();
+    };
+    let text = |track: &Value, name: &str| {
+        track.get(name).and_then(Value::as_str).filter(|value| !value.is_empty()).map(str::to_string)
+    };
+    tracks
+        .iter()
+        .filter(|track| track.get("type").and_then(Value::as_str) == Some("audio"))
+        .filter_map(|track| {
+            Some(AudioTrack {
+                id: track.get("id")?.as_i64()?,
+                language: text(track, "lang"),
+                title: text(track, "title"),
+                codec: text(track, "codec"),
+                channels: track.get("demux-channel-count").and_then(Value::as_u64).map(|count| count as u32),
+                selected: track.get("selected").and_then(Value::as_bool).unwrap_or(false),
+            })
+        })
+        .collect()
+}
+
+// The track matching a preference best: same language and title, else the
+// first in the language
+pub fn preferred(tracks: &[AudioTrack], preference: &AudioPreference) -> Option<i64> {
+    let language = preference.language.as_deref()?;
+    let same_language = |track: &&AudioTrack| {
+        track.language.as_deref().is_some_and(|track| track.eq_ignore_ascii_case(language))
+    };
+    let mut candidates = tracks.iter().filter(same_language);
+    let first = candidates.clone().next();
+    candidates.find(|track| track.title == preference.title).or(first).map(|track| track.id)
+}
+
+pub struct AudioPreferences {
+    path: PathBuf,
+    channels: Mutex<BTreeMap<String, AudioPreference>>,
+}
+
+impl AudioPreferences {
+    pub fn open(data_dir: &Path) -> Self {
+        let path = data_dir.join(PREFERENCES_FILE);
+        let channels = storage::read_json(&path).unwrap_or_else(|e| {
+            log::error!("Failed to load audio preferences: {}", e);
+            BTreeMap::new()
+        });
+        Self {
+            path,
+            channels: Mutex::new(channels),
+        }
+    }
+
+    pub fn get(&self, channel_id: &str) -> Option<AudioPreference> {
+        self.channels.lock().unwrap().get(channel_id).cloned()
+    }
+
+    // Save a channel's preference, or forget it with None
+    pub fn set(&self, channel_id: &str, preference: Option<AudioPreference>) -> Result<(), String> {
+        let mut channels = self.channels.lock().unwrap();
+        let mut updated = channels.clone();
+        match preference.filter(|preference| preference.language.is_some()) {
+            Some(preference) => updated.insert(channel_id.to_string(), preference),
+            None => updated.remove(channel_id),
+        };
+        storage::write_json(&self.path, &updated)?;
+        *channels = updated;
+        Ok(())
+    }
+}
+
+// What's known of the stream playing
+#[derive(Debug, Default)]
+pub struct Current {
+    pub channel_id: Option<String>,
+    // The guide channel parental controls check while it plays
+    pub guide: Option<String>,
+    pub tracks: Vec<AudioTrack>,
+    // The channel's preference was applied, or there was none to apply
+    pub settled: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+    use serde_json::json;
+
+    #[test]
+    fn test_preferred_audio_track() {
+        // Test that the saved language and title pick the track and survive a reopen
+        let track_list = json!([
+            {"id": 1, "type": "video", "selected": true},
+            {"id": 1, "type": "audio", "lang": "tur", "codec": "aac", "selected": true},
+            {"id": 2, "type": "audio", "lang": "eng", "title": "Commentary", "demux-channel-count": 2},
+            {"id": 3, "type": "audio", "lang": "ENG", "title": "Original"},
+            {"id": 1, "type": "sub", "lang": "eng"}
+        ]);
+        let tracks = audio_tracks(&track_list);
+        assert_eq!(tracks.iter().map(|track| track.id).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert!(tracks[0].selected && tracks[1].channels == Some(2));
+
+        let original = AudioPreference {
+            language: Some("eng".to_string()),
+            title: Some("Original".to_string()),
+        };
+        assert_eq!(preferred(&tracks, &original), Some(3));
+        let english = AudioPreference {
+            language: Some("eng".to_string()),
+            title: None,
+        };
+        assert_eq!(preferred(&tracks, &english), Some(2));
+        let german = AudioPreference {
+            language: Some("ger".to_string()),
+            title: None,
+        };
+        assert_eq!(preferred(&tracks, &german), None);
+
+        let dir = temp_dir("audio_preferences");
+        AudioPreferences::open(&dir).set("c1", Some(original.clone())).unwrap();
+        let reopened = AudioPreferences::open(&dir);
+        assert_eq!(reopened.get("c1"), Some(original));
+        reopened.set("c1", None).unwrap();
+        assert_eq!(AudioPreferences::open(&dir).get("c1"), None);
+    }
+}
@@ -0,0 +1,313 @@
+// GStreamer as a playback engine, for platforms where mpv is awkward to
+// ship. Each stream gets a playbin; its bus is watched on a thread that
+// reports mpv-style properties, and its video goes into the video surface
+// through the video overlay where the platform has one
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures_util::future::{self, BoxFuture};
+use futures_util::FutureExt;
+use gstreamer as gst;
+use gstreamer::glib;
+use gstreamer::prelude::*;
+use gstreamer_video::prelude::*;
+use serde_json::{json, Value};
+use tauri::AppHandle;
+
+use super::{EngineKind, Media, PlaybackEngine, TrackKind};
+
+// How often time-pos is reported while playing
+const POSITION_INTERVAL: Duration = Duration::from_millis(500);
+
+struct Pipeline {
+    playbin: gst::Element,
+    // Paused by the user rather than for buffering
+    paused: Arc<AtomicBool>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl Pipeline {
+    fn stop(self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        let _ = self.playbin.set_state(gst::State::Null);
+    }
+}
+
+#[derive(Default)]
+pub struct GstPlayer {
+    pipeline: Mutex<Option<Pipeline>>,
+}
+
+fn seconds(time: gst::ClockTime) -> f64 {
+    time.nseconds() as f64 / 1e9
+}
+
+fn clock_time(seconds: f64) -> gst::ClockTime {
+    gst::ClockTime::from_nseconds((seconds.max(0.0) * 1e9) as u64)
+}
+
+// Turn one of playbin's flags (video, audio, text) on or off
+fn set_flag(playbin: &gst::Element, nick: &str, on: bool) -> Result<(), String> {
+    let flags = playbin.property_value("flags");
+    let class = glib::FlagsClass::with_type(flags.type_()).ok_or("playbin has no flags")?;
+    let builder = class.builder_with_value(flags).ok_or("playbin has no flags")?;
+    let builder = if on { builder.set_by_nick(nick) } else { builder.unset_by_nick(nick) };
+    let flags = builder.build().ok_or("Invalid playbin flag")?;
+    playbin.set_property_from_value("flags", &flags);
+    Ok(())
+}
+
+// playbin's streams in mpv's track-list shape; ids count from 1 per type
+fn track_list(playbin: &gst::Element) -> Value {
+    let kinds = [
+        ("video", "n-video", "current-video", "get-video-tags"),
+        ("audio", "n-audio", "current-audio", "get-audio-tags"),
+        ("sub", "n-text", "current-text", "get-text-tags"),
+    ];
+    let mut tracks = Vec::new();
+    for (kind, count, current, tags) in kinds {
+        let current = playbin.property::<i32>(current);
+        for index in 0..playbin.property::<i32>(count) {
+            let mut track = json!({ "id": index + 1, "type": kind, "selected": index == current });
+            if let Some(tags) = playbin.emit_by_name::<Option<gst::TagList>>(tags, &[&index]) {
+                if let Some(language) = tags.get::<gst::tags::LanguageCode>() {
+                    track["lang"] = json!(language.get());
+                }
+                if let Some(title) = tags.get::<gst::tags::Title>() {
+                    track["title"] = json!(title.get());
+                }
+            }
+            tracks.push(track);
+        }
+    }
+    Value::Array(tracks)
+}
+
+// Report what the playbin does until it's stopped
+fn watch(app: AppHandle, playbin: gst::Element, paused: Arc<AtomicBool>, stopped: Arc<AtomicBool>, start: Option<f64>) {
+    let Some(bus) = playbin.bus() else {
+        return;
+    };
+    let mut loaded = false;
+    let mut reported = Instant::now();
+    while !stopped.load(Ordering::Relaxed) {
+        if reported.elapsed() >= POSITION_INTERVAL {
+            reported = Instant::now();
+            if let Some(position) = playbin.query_position::<gst::ClockTime>() {
+                super::emit_property(&app, "time-pos", json!(seconds(position)));
+            }
+        }
+        let Some(message) = bus.timed_pop(gst::ClockTime::from_mseconds(100)) else {
+            continue;
+        };
+        match message.view() {
+            gst::MessageView::AsyncDone(_) if !loaded => {
+                loaded = true;
+                if let Some(start) = start.filter(|start| *start > 0.0) {
+                    let flags = gst::SeekFlags::FLUSH | gst::SeekFlags::KEY_UNIT;
+                    let _ = playbin.seek_simple(flags, clock_time(start));
+                }
+                super::emit_event(&app, "file-loaded", json!({}));
+                super::emit_property(&app, "track-list", track_list(&playbin));
+                let duration = playbin.query_duration::<gst::ClockTime>().map(seconds);
+                super::emit_property(&app, "duration", json!(duration));
+            }
+            gst::MessageView::DurationChanged(_) => {
+                let duration = playbin.query_duration::<gst::ClockTime>().map(seconds);
+                super::emit_property(&app, "duration", json!(duration));
+            }
+            gst::MessageView::StreamStart(_) if loaded => {
+                super::emit_property(&app, "track-list", track_list(&playbin));
+            }
+            gst::MessageView::StateChanged(change)
+                if change.src().is_some_and(|src| src == playbin.upcast_ref::<gst::Object>()) =>
+            {
+                match change.current() {
+                    gst::State::Playing => super::emit_property(&app, "pause", json!(false)),
+                    gst::State::Paused => super::emit_property(&app, "pause", json!(true)),
+                    _ => {}
+                }
+            }
+            // Network streams are paused while their buffer fills
+            gst::MessageView::Buffering(buffering) => {
+                let filling = buffering.percent() < 100;
+                let state = if filling || paused.load(Ordering::Relaxed) {
+                    gst::State::Paused
+                } else {
+                    gst::State::Playing
+                };
+                let _ = playbin.set_state(state);
+                super::emit_property(&app, "paused-for-cache", json!(filling));
+            }
+            gst::MessageView::Eos(_) => {
+                super::emit_property(&app, "eof-reached", json!(true));
+                super::emit_event(&app, "end-file", json!({ "reason": "eof" }));
+            }
+            gst::MessageView::Error(error) => {
+                log::warn!("GStreamer playback failed: {} ({:?})", error.error(), error.debug());
+                let fields = json!({ "reason": "error", "error": error.error().to_string() });
+                super::emit_event(&app, "end-file", fields);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl GstPlayer {
+    fn playbin(&self) -> Result<gst::Element, String> {
+        let pipeline = self.pipeline.lock().unwrap();
+        let pipeline = pipeline.as_ref().ok_or("GStreamer isn't playing")?;
+        Ok(pipeline.playbin.clone())
+    }
+
+    fn play(&self, app: &AppHandle, media: Media, surface: Option<i64>) -> Result<(), String> {
+        gst::init().map_err(|e| format!("Failed to start GStreamer: {}", e))?;
+        if let Some(previous) = self.pipeline.lock().unwrap().take() {
+            previous.stop();
+        }
+        let playbin = gst::ElementFactory::make("playbin")
+            .property("uri", &media.url)
+            .build()
+            .map_err(|e| format!("Failed to create a playbin: {}", e))?;
+
+        // HTTP sources (souphttpsrc) take the channel's headers
+        let http = media.http;
+        playbin.connect("source-setup", false, move |values| {
+            let source = values.get(1)?.get::<gst::Element>().ok()?;
+            if let Some(user_agent) = &http.user_agent {
+                if source.find_property("user-agent").is_some() {
+                    source.set_property("user-agent", user_agent);
+                }
+            }
+            if source.find_property("extra-headers").is_some() {
+                let mut headers = gst::Structure::builder("extra-headers");
+                if let Some(referrer) = &http.referrer {
+                    headers = headers.field("Referer", referrer);
+                }
+                for (name, value) in &http.headers {
+                    headers = headers.field(name.as_str(), value);
+                }
+                source.set_property("extra-headers", headers.build());
+            }
+            None
+        });
+
+        let bus = playbin.bus().ok_or("The playbin has no bus")?;
+        if let Some(handle) = surface {
+            bus.set_sync_handler(move |_, message| {
+                if gstreamer_video::is_video_overlay_prepare_window_handle_message(message) {
+                    if let Some(overlay) = message
+                        .src()
+                        .and_then(|src| src.dynamic_cast_ref::<gstreamer_video::VideoOverlay>())
+                    {
+                        // Safety: the video surface is never destroyed
+                        // while the app runs
+                        unsafe { overlay.set_window_handle(handle as usize) };
+                    }
+                }
+                gst::BusSyncReply::Pass
+            });
+        }
+
+        playbin
+            .set_state(gst::State::Playing)
+            .map_err(|_| "GStreamer can't play the stream".to_string())?;
+        let paused = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let (app, watched, user_paused, watch_stopped) =
+            (app.clone(), playbin.clone(), paused.clone(), stopped.clone());
+        std::thread::spawn(move || watch(app, watched, user_paused, watch_stopped, media.start));
+        *self.pipeline.lock().unwrap() = Some(Pipeline {
+            playbin,
+            paused,
+            stopped,
+        });
+        Ok(())
+    }
+
+    fn pause(&self, paused: bool) -> Result<(), String> {
+        let pipeline = self.pipeline.lock().unwrap();
+        let pipeline = pipeline.as_ref().ok_or("GStreamer isn't playing")?;
+        pipeline.paused.store(paused, Ordering::Relaxed);
+        let state = if paused { gst::State::Paused } else { gst::State::Playing };
+        pipeline
+            .playbin
+            .set_state(state)
+            .map(|_| ())
+            .map_err(|_| "Failed to change the playback state".to_string())
+    }
+
+    fn seek_to(&self, position: f64, relative: bool) -> Result<(), String> {
+        let playbin = self.playbin()?;
+        let position = match relative {
+            true => playbin.query_position::<gst::ClockTime>().map_or(0.0, seconds) + position,
+            false => position,
+        };
+        playbin
+            .seek_simple(gst::SeekFlags::FLUSH | gst::SeekFlags::KEY_UNIT, clock_time(position))
+            .map_err(|e| format!("Failed to seek: {}", e))
+    }
+
+    fn select_track(&self, kind: TrackKind, id: Option<i64>) -> Result<(), String> {
+        let playbin = self.playbin()?;
+        let (flag, current) = match kind {
+            TrackKind::Video => ("video", "current-video"),
+            TrackKind::Audio => ("audio", "current-audio"),
+            TrackKind::Subtitle => ("text", "current-text"),
+        };
+        set_flag(&playbin, flag, id.is_some())?;
+        if let Some(id) = id {
+            let index = i32::try_from(id - 1).map_err(|_| "Invalid track id".to_string())?;
+            playbin.set_property(current, index);
+        }
+        Ok(())
+    }
+}
+
+impl PlaybackEngine for GstPlayer {
+    fn kind(&self) -> EngineKind {
+        EngineKind::Gstreamer
+    }
+
+    fn available(&self) -> bool {
+        gst::init().is_ok() && gst::ElementFactory::find("playbin").is_some()
+    }
+
+    fn load<'a>(&'a self, app: &'a AppHandle, media: Media) -> BoxFuture<'a, Result<(), String>> {
+        async move {
+            let surface = super::video_surface(app).await;
+            self.play(app, media, surface)
+        }
+        .boxed()
+    }
+
+    fn set_paused(&self, paused: bool) -> BoxFuture<'_, Result<(), String>> {
+        future::ready(self.pause(paused)).boxed()
+    }
+
+    fn seek(&self, position: f64, relative: bool) -> BoxFuture<'_, Result<(), String>> {
+        future::ready(self.seek_to(position, relative)).boxed()
+    }
+
+    fn set_track(&self, kind: TrackKind, id: Option<i64>) -> BoxFuture<'_, Result<(), String>> {
+        future::ready(self.select_track(kind, id)).boxed()
+    }
+
+    // playbin's volume is linear with 1.0 as full
+    fn set_volume(&self, volume: f64) -> BoxFuture<'_, Result<(), String>> {
+        let result = self.playbin().map(|playbin| playbin.set_property("volume", volume / 100.0));
+        future::ready(result).boxed()
+    }
+
+    fn stop(&self) -> BoxFuture<'_, Result<(), String>> {
+        self.kill();
+        future::ready(Ok(())).boxed()
+    }
+
+    fn kill(&self) {
+        if let Some(pipeline) = self.pipeline.lock().unwrap().take() {
+            pipeline.stop();
+        }
+    }
+}
@@ -0,0 +1,468 @@
+// Native playback engines for streams the webview can't play. Each engine
+// implements PlaybackEngine and reports through the same events:
+// "playback-property" with mpv's property names (pause, time-pos, duration,
+// volume, track-list, eof-reached, paused-for-cache) and "playback-event".
+// The user picks an engine or leaves the choice automatic; either way a
+// load that fails falls back to the next available engine. Engines draw
+// into the video surface where the platform has one. The audio track
+// picked on a channel is selected again the next time it plays
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Emitter, Manager, State};
+use url::Url;
+
+use crate::epg::parental;
+use crate::http::HttpOptions;
+use crate::playlist::check_http_options;
+use crate::storage;
+use crate::validate_string_length;
+
+use audio::{AudioPreference, AudioPreferences, AudioTrack, Current};
+use surface::{Bounds, VideoSurface};
+
+mod audio;
+#[cfg(feature = "gstreamer")]
+mod gstreamer;
+mod mpv;
+mod surface;
+
+const PLAYBACK_FILE: &str = "playback.json";
+
+const MAX_CHANNEL_ID_LENGTH: usize = 512;
+const MAX_URL_LENGTH: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EngineKind {
+    Mpv,
+    Gstreamer,
+}
+
+// Tried in this order when the choice is automatic
+const ENGINES: [EngineKind; 2] = [EngineKind::Mpv, EngineKind::Gstreamer];
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TrackKind {
+    Video,
+    Audio,
+    Subtitle,
+}
+
+// What to play
+#[derive(Debug, Clone, Default)]
+pub struct Media {
+    pub url: String,
+    pub http: HttpOptions,
+    // Position in seconds, for recordings and VOD
+    pub start: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct PropertyChange {
+    name: String,
+    data: Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct PlayerEvent {
+    event: String,
+    fields: Value,
+}
+
+pub trait PlaybackEngine: Send + Sync {
+    fn kind(&self) -> EngineKind;
+    // Whether the engine can run here
+    fn available(&self) -> bool;
+    fn load<'a>(&'a self, app: &'a AppHandle, media: Media) -> BoxFuture<'a, Result<(), String>>;
+    fn set_paused(&self, paused: bool) -> BoxFuture<'_, Result<(), String>>;
+    fn seek(&self, position: f64, relative: bool) -> BoxFuture<'_, Result<(), String>>;
+    // Select a track by its id in track-list, or turn the kind off
+    fn set_track(&self, kind: TrackKind, id: Option<i64>) -> BoxFuture<'_, Result<(), String>>;
+    fn set_volume(&self, volume: f64) -> BoxFuture<'_, Result<(), String>>;
+    fn stop(&self) -> BoxFuture<'_, Result<(), String>>;
+    // Stop at once, when the app exits
+    fn kill(&self);
+}
+
+fn emit_property(app: &AppHandle, name: &str, data: Value) {
+    if name == "track-list" {
+        tracks_changed(app, &data);
+    }
+    let _ = app.emit(
+        "playback-property",
+        PropertyChange {
+            name: name.to_string(),
+            data,
+        },
+    );
+}
+
+// Keep the stream's audio tracks, and select the channel's saved track the
+// first time they're known
+fn tracks_changed(app: &AppHandle, track_list: &Value) {
+    let playback = app.state::<Playback>();
+    let mut current = playback.current.lock().unwrap();
+    current.tracks = audio::audio_tracks(track_list);
+    if current.settled || current.tracks.is_empty() {
+        return;
+    }
+    current.settled = true;
+    let preference = current.channel_id.as_deref().and_then(|id| playback.audio.get(id));
+    let Some(id) = preference.and_then(|preference| audio::preferred(&current.tracks, &preference)) else {
+        return;
+    };
+    if current.tracks.iter().any(|track| track.id == id && track.selected) {
+        return;
+    }
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let playback = app.state::<Playback>();
+        let selected = match playback.active() {
+            Ok(engine) => engine.set_track(TrackKind::Audio, Some(id)).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = selected {
+            log::warn!("Failed to select the preferred audio track: {}", e);
+        }
+    });
+}
+
+fn emit_event(app: &AppHandle, event: &str, fields: Value) {
+    let _ = app.emit(
+        "playback-event",
+        PlayerEvent {
+            event: event.to_string(),
+            fields,
+        },
+    );
+}
+
+// The native handle of the video surface, shown, on platforms engines can
+// draw into
+async fn video_surface(app: &AppHandle) -> Option<i64> {
+    surface::show(app).await
+}
+
+// Engines open far more than network streams (mpv reads local files, lavfi
+// graphs and EDL lists), so only stream URLs get through
+fn check_url(url: &str) -> Result<(), String> {
+    validate_string_length(url, MAX_URL_LENGTH)?;
+    let parsed = Url::parse(url.trim()).map_err(|e| format!("Invalid URL '{}': {}", url, e))?;
+    match parsed.scheme() {
+        "http" | "https" | "rtsp" | "rtsps" | "rtmp" | "rtmps" | "udp" | "rtp" if parsed.has_host() => Ok(()),
+        scheme => Err(format!("Only network streams can be played, not {}://", scheme)),
+    }
+}
+
+// Whether a program can be found on PATH
+fn on_path(program: &str) -> bool {
+    let Some(paths) = std::env::var_os("PATH") else {
+        return false;
+    };
+    let file = format!("{}{}", program, std::env::consts::EXE_SUFFIX);
+    std::env::split_paths(&paths).any(|directory| directory.join(&file).is_file())
+}
+
+// Engines to try: the chosen one first, then the rest in ENGINES order
+fn order(choice: Option<EngineKind>) -> Vec<EngineKind> {
+    let mut order: Vec<EngineKind> = choice.into_iter().collect();
+    order.extend(ENGINES.iter().filter(|kind| Some(**kind) != choice));
+    order
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlaybackSettings {
+    // None to pick automatically
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    engine: Option<EngineKind>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EngineInfo {
+    pub kind: EngineKind,
+    pub available: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub chosen: bool,
+    // Playing the current stream
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub active: bool,
+}
+
+pub struct Playback {
+    path: PathBuf,
+    settings: Mutex<PlaybackSettings>,
+    engines: Vec<Box<dyn PlaybackEngine>>,
+    active: Mutex<Option<EngineKind>>,
+    audio: AudioPreferences,
+    current: Mutex<Current>,
+    surface: VideoSurface,
+}
+
+impl Playback {
+    pub fn open(data_dir: &Path) -> Self {
+        let path = data_dir.join(PLAYBACK_FILE);
+        let settings = storage::read_json(&path).unwrap_or_else(|e| {
+            log::error!("Failed to load playback settings: {}", e);
+            PlaybackSettings::default()
+        });
+        let engines: Vec<Box<dyn PlaybackEngine>> = vec![
+            Box::<mpv::MpvPlayer>::default(),
+            #[cfg(feature = "gstreamer")]
+            Box::<gstreamer::GstPlayer>::default(),
+        ];
+        Self {
+            path,
+            settings: Mutex::new(settings),
+            engines,
+            active: Mutex::new(None),
+            audio: AudioPreferences::open(data_dir),
+            current: Mutex::default(),
+            surface: VideoSurface::default(),
+        }
+    }
+
+    fn engine(&self, kind: EngineKind) -> Option<&dyn PlaybackEngine> {
+        self.engines.iter().find(|engine| engine.kind() == kind).map(|engine| engine.as_ref())
+    }
+
+    fn active(&self) -> Result<&dyn PlaybackEngine, String> {
+        let active = *self.active.lock().unwrap();
+        active.and_then(|kind| self.engine(kind)).ok_or_else(|| "Nothing is playing".to_string())
+    }
+
+    pub fn engines(&self) -> Vec<EngineInfo> {
+        let chosen = self.settings.lock().unwrap().engine;
+        let active = *self.active.lock().unwrap();
+        ENGINES
+            .iter()
+            .map(|kind| EngineInfo {
+                kind: *kind,
+                available: self.engine(*kind).is_some_and(|engine| engine.available()),
+                chosen: chosen == Some(*kind),
+                active: active == Some(*kind),
+            })
+            .collect()
+    }
+
+    pub fn choose(&self, engine: Option<EngineKind>) -> Result<(), String> {
+        let mut settings = self.settings.lock().unwrap();
+        let chosen = PlaybackSettings { engine };
+        storage::write_json(&self.path, &chosen)?;
+        *settings = chosen;
+        Ok(())
+    }
+
+    pub async fn load(&self, app: &AppHandle, media: Media) -> Result<EngineKind, String> {
+        let choice = self.settings.lock().unwrap().engine;
+        let mut error = None;
+        for kind in order(choice) {
+            let Some(engine) = self.engine(kind).filter(|engine| engine.available()) else {
+                continue;
+            };
+            match engine.load(app, media.clone()).await {
+                Ok(()) => {
+                    let previous = self.active.lock().unwrap().replace(kind);
+                    if let Some(previous) = previous.filter(|previous| *previous != kind) {
+                        if let Some(previous) = self.engine(previous) {
+                            let _ = previous.stop().await;
+                        }
+                    }
+                    return Ok(kind);
+                }
+                Err(e) => {
+                    log::warn!("{:?} failed to play {}: {}", kind, media.url, e);
+                    error = Some(e);
+                }
+            }
+        }
+        Err(error.unwrap_or_else(|| "No playback engine is available".to_string()))
+    }
+
+    async fn stop_active(&self, app: &AppHandle) -> Result<(), String> {
+        let active = self.active.lock().unwrap().take();
+        *self.current.lock().unwrap() = Current::default();
+        surface::hide(app).await;
+        match active.and_then(|kind| self.engine(kind)) {
+            Some(engine) => engine.stop().await,
+            None => Ok(()),
+        }
+    }
+}
+
+// The guide channel of what plays natively, for parental controls
+pub fn playing_guide(app: &AppHandle) -> Option<String> {
+    app.state::<Playback>().current.lock().unwrap().guide.clone()
+}
+
+// Stop what plays natively, as player_stop does
+pub async fn stop_playing(app: &AppHandle) -> Result<(), String> {
+    app.state::<Playback>().stop_active(app).await
+}
+
+// Stop every engine when the app exits
+pub fn stop(app: &AppHandle) {
+    for engine in &app.state::<Playback>().engines {
+        engine.kill();
+    }
+}
+
+// Command handler listing the engines and which can run here
+#[tauri::command]
+pub fn list_playback_engines(playback: State<'_, Playback>) -> Vec<EngineInfo> {
+    playback.engines()
+}
+
+// Command handler choosing the engine to try first, None for automatic
+#[tauri::command]
+pub fn set_playback_engine(playback: State<'_, Playback>, engine: Option<EngineKind>) -> Result<(), String> {
+    playback.choose(engine)
+}
+
+// Command handler playing a stream natively, returning the engine playing
+// it; with the channel's id the audio track saved for it is selected.
+// Refused while parental controls restrict the programme on the channel's
+// guide channel (`epg_channel_id`, else the channel's)
+#[tauri::command]
+pub async fn player_load(
+    app: AppHandle,
+    playback: State<'_, Playback>,
+    url: String,
+    http: Option<HttpOptions>,
+    start: Option<f64>,
+    channel_id: Option<String>,
+    epg_channel_id: Option<String>,
+) -> Result<EngineKind, String> {
+    check_url(&url)?;
+    if let Some(http) = &http {
+        check_http_options(http)?;
+    }
+    if let Some(id) = &channel_id {
+        validate_string_length(id, MAX_CHANNEL_ID_LENGTH)?;
+    }
+    let guide = parental::guide_channel(&app, epg_channel_id.as_deref(), channel_id.as_deref())?;
+    if let Some(guide) = &guide {
+        parental::enforce_live(&app, guide).await?;
+    }
+    *playback.current.lock().unwrap() = Current {
+        channel_id,
+        guide,
+        ..Default::default()
+    };
+    let media = Media {
+        url: url.trim().to_string(),
+        http: http.unwrap_or_default(),
+        start,
+    };
+    playback.load(&app, media).await
+}
+
+#[tauri::command]
+pub async fn player_set_paused(playback: State<'_, Playback>, paused: bool) -> Result<(), String> {
+    playback.active()?.set_paused(paused).await
+}
+
+// Seek to a position in seconds, or by an offset with `relative`
+#[tauri::command]
+pub async fn player_seek(playback: State<'_, Playback>, position: f64, relative: Option<bool>) -> Result<(), String> {
+    playback.active()?.seek(position, relative.unwrap_or(false)).await
+}
+
+// Command handler selecting a track; an audio track picked is saved for
+// the channel playing
+#[tauri::command]
+pub async fn player_set_track(
+    playback: State<'_, Playback>,
+    kind: TrackKind,
+    id: Option<i64>,
+) -> Result<(), String> {
+    playback.active()?.set_track(kind, id).await?;
+    let Some(id) = id.filter(|_| matches!(kind, TrackKind::Audio)) else {
+        return Ok(());
+    };
+    let chosen = {
+        let current = playback.current.lock().unwrap();
+        let track = current.tracks.iter().find(|track| track.id == id);
+        current.channel_id.clone().zip(track.map(|track| AudioPreference {
+            language: track.language.clone(),
+            title: track.title.clone(),
+        }))
+    };
+    match chosen {
+        Some((channel_id, preference)) => playback.audio.set(&channel_id, Some(preference)),
+        None => Ok(()),
+    }
+}
+
+// Command handler listing the audio tracks of the stream playing
+#[tauri::command]
+pub fn player_audio_tracks(playback: State<'_, Playback>) -> Vec<AudioTrack> {
+    playback.current.lock().unwrap().tracks.clone()
+}
+
+// Command handler returning the audio saved for a channel, for players
+// outside the backend too
+#[tauri::command]
+pub fn get_audio_preference(playback: State<'_, Playback>, channel_id: String) -> Option<AudioPreference> {
+    playback.audio.get(&channel_id)
+}
+
+// Command handler saving a channel's audio, or forgetting it with None
+#[tauri::command]
+pub fn set_audio_preference(
+    playback: State<'_, Playback>,
+    channel_id: String,
+    preference: Option<AudioPreference>,
+) -> Result<(), String> {
+    validate_string_length(&channel_id, MAX_CHANNEL_ID_LENGTH)?;
+    if let Some(preference) = &preference {
+        preference.check()?;
+    }
+    playback.audio.set(&channel_id, preference)
+}
+
+#[tauri::command]
+pub async fn player_set_volume(playback: State<'_, Playback>, volume: f64) -> Result<(), String> {
+    playback.active()?.set_volume(volume.clamp(0.0, 100.0)).await
+}
+
+#[tauri::command]
+pub async fn player_stop(app: AppHandle, playback: State<'_, Playback>) -> Result<(), String> {
+    playback.stop_active(&app).await
+}
+
+// Command handler placing the video surface over the player area, in
+// logical pixels of the main window
+#[tauri::command]
+pub async fn player_set_bounds(app: AppHandle, bounds: Bounds) -> Result<(), String> {
+    surface::set_bounds(&app, bounds).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_engine_order() {
+        // Test that the chosen engine is tried first and the others remain as fallbacks
+        assert_eq!(order(None), vec![EngineKind::Mpv, EngineKind::Gstreamer]);
+        assert_eq!(order(Some(EngineKind::Gstreamer)), vec![EngineKind::Gstreamer, EngineKind::Mpv]);
+    }
+
+    #[test]
+    fn test_only_stream_urls_play() {
+        // Test that network streams load and local files or mpv protocols are refused
+        assert!(check_url(" http://host/live/1.ts ").is_ok());
+        assert!(check_url("rtsp://host:554/stream").is_ok());
+        assert!(check_url("file:///etc/passwd").is_err());
+        assert!(check_url("lavfi://sine").is_err());
+        assert!(check_url("/home/user/video.mkv").is_err());
+        assert!(check_url(&format!("http://host/{}", "a".repeat(MAX_URL_LENGTH))).is_err());
+    }
+}
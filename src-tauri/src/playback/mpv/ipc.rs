@@ -0,0 +1,76 @@
+// mpv's JSON IPC protocol: one JSON object per line each way. Commands go
+// out as {"command": [...], "request_id": n}; replies, property changes
+// and events come back
+use serde_json::{json, Value};
+
+// A command line to send
+pub fn command(request_id: u64, arguments: &[Value]) -> String {
+    let mut line = json!({ "command": arguments, "request_id": request_id }).to_string();
+    line.push('\n');
+    line
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    // The reply to a command; error is "success" when it worked
+    Reply { request_id: u64, error: String },
+    // An observed property changed
+    Property { name: String, data: Value },
+    // Any other event ("file-loaded", "end-file", ...) with its fields
+    Event { name: String, fields: Value },
+}
+
+pub fn parse(line: &str) -> Option<Message> {
+    let mut value: Value = serde_json::from_str(line.trim()).ok()?;
+    let object = value.as_object_mut()?;
+    if let Some(event) = object.remove("event") {
+        let name = event.as_str()?.to_string();
+        if name == "property-change" {
+            return Some(Message::Property {
+                name: object.get("name")?.as_str()?.to_string(),
+                data: object.remove("data").unwrap_or(Value::Null),
+            });
+        }
+        return Some(Message::Event { name, fields: value });
+    }
+    Some(Message::Reply {
+        request_id: object.get("request_id")?.as_u64()?,
+        error: object.get("error")?.as_str()?.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ipc_messages() {
+        // Test that commands are single JSON lines and replies, property changes and events are told apart
+        assert_eq!(
+            command(3, &[json!("loadfile"), json!("http://host/1.ts"), json!("replace")]),
+            "{\"command\":[\"loadfile\",\"http://host/1.ts\",\"replace\"],\"request_id\":3}\n"
+        );
+        assert_eq!(
+            parse(r#"{"request_id":3,"error":"success","data":null}"#),
+            Some(Message::Reply {
+                request_id: 3,
+                error: "success".to_string()
+            })
+        );
+        assert_eq!(
+            parse(r#"{"event":"property-change","id":1,"name":"time-pos","data":12.5}"#),
+            Some(Message::Property {
+                name: "time-pos".to_string(),
+                data: json!(12.5)
+            })
+        );
+        assert_eq!(
+            parse(r#"{"event":"end-file","reason":"eof"}"#),
+            Some(Message::Event {
+                name: "end-file".to_string(),
+                fields: json!({"reason": "eof"})
+            })
+        );
+        assert_eq!(parse("not json"), None);
+    }
+}
@@ -0,0 +1,260 @@
+// mpv as a playback engine. An mpv process is started on first use and
+// driven over its JSON IPC socket (a named pipe on Windows) under a random
+// name, in a directory only the user can open on Unix; on Windows and X11
+// its video goes into the video surface through --wid, elsewhere it opens
+// its own window
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use serde_json::{json, Value};
+use tauri::AppHandle;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::{mpsc, Mutex};
+
+use super::{EngineKind, Media, PlaybackEngine, TrackKind};
+
+mod ipc;
+
+use ipc::Message;
+
+const PROGRAM: &str = "mpv";
+
+// Properties reported through "playback-property" events
+const OBSERVED: [&str; 7] = [
+    "pause",
+    "time-pos",
+    "duration",
+    "volume",
+    "track-list",
+    "eof-reached",
+    "paused-for-cache",
+];
+
+// How long mpv gets to open its IPC socket
+const CONNECT_ATTEMPTS: u32 = 50;
+const CONNECT_INTERVAL: Duration = Duration::from_millis(100);
+
+struct Session {
+    child: Child,
+    commands: mpsc::UnboundedSender<String>,
+}
+
+#[derive(Default)]
+pub struct MpvPlayer {
+    session: Mutex<Option<Session>>,
+    next_request: AtomicU64,
+}
+
+fn track_property(kind: TrackKind) -> &'static str {
+    match kind {
+        TrackKind::Video => "vid",
+        TrackKind::Audio => "aid",
+        TrackKind::Subtitle => "sid",
+    }
+}
+
+// A name other local processes can't guess; RandomState is seeded from the OS
+fn random_name() -> String {
+    format!("{:016x}", RandomState::new().build_hasher().finish())
+}
+
+// In the app's cache directory, which only the user can open
+#[cfg(unix)]
+fn socket_path(app: &AppHandle) -> Result<String, String> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+    use tauri::Manager;
+
+    let dir = app.path().app_cache_dir().map_err(|e| e.to_string())?.join("mpv");
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&dir)
+        .and_then(|_| std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700)))
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let path = dir.join(format!("{}.sock", random_name()));
+    Ok(path.to_string_lossy().into_owned())
+}
+
+#[cfg(windows)]
+fn socket_path(_app: &AppHandle) -> Result<String, String> {
+    Ok(format!(r"\\.\pipe\tiptv-mpv-{}", random_name()))
+}
+
+impl MpvPlayer {
+    fn send(&self, session: &Session, arguments: &[Value]) -> Result<(), String> {
+        let id = self.next_request.fetch_add(1, Ordering::Relaxed);
+        session
+            .commands
+            .send(ipc::command(id, arguments))
+            .map_err(|_| "mpv has exited".to_string())
+    }
+
+    // Send a command to the running mpv
+    async fn command(&self, arguments: &[Value]) -> Result<(), String> {
+        let session = self.session.lock().await;
+        let session = session.as_ref().ok_or("mpv isn't running")?;
+        self.send(session, arguments)
+    }
+
+    async fn set_property(&self, name: &str, value: Value) -> Result<(), String> {
+        self.command(&[json!("set_property"), json!(name), value]).await
+    }
+
+    // Start mpv unless it's running
+    async fn ensure_started(&self, app: &AppHandle) -> Result<(), String> {
+        let mut session = self.session.lock().await;
+        if session.as_mut().is_some_and(|session| matches!(session.child.try_wait(), Ok(None))) {
+            return Ok(());
+        }
+        let path = socket_path(app)?;
+        let mut command = Command::new(PROGRAM);
+        command
+            .args(["--idle=yes", "--force-window=yes", "--keep-open=yes", "--no-terminal"])
+            .arg("--hwdec=auto-safe")
+            .arg(format!("--input-ipc-server={}", path))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true);
+        if let Some(id) = super::video_surface(app).await {
+            command.arg(format!("--wid={}", id));
+        }
+        let child = command.spawn().map_err(|e| format!("Failed to start mpv: {}", e))?;
+        let (commands, receiver) = mpsc::unbounded_channel();
+        let started = Session { child, commands };
+        let mut attempts = 0;
+        loop {
+            match connect(&path).await {
+                Ok(stream) => {
+                    tauri::async_runtime::spawn(serve(app.clone(), stream, receiver));
+                    break;
+                }
+                Err(e) if attempts >= CONNECT_ATTEMPTS => {
+                    return Err(format!("Failed to connect to mpv: {}", e));
+                }
+                Err(_) => {
+                    attempts += 1;
+                    tokio::time::sleep(CONNECT_INTERVAL).await;
+                }
+            }
+        }
+        for (index, name) in OBSERVED.iter().enumerate() {
+            self.send(&started, &[json!("observe_property"), json!(index + 1), json!(name)])?;
+        }
+        *session = Some(started);
+        Ok(())
+    }
+
+    async fn play(&self, app: &AppHandle, media: Media) -> Result<(), String> {
+        self.ensure_started(app).await?;
+        // Set every time so one channel's headers don't carry over to the next
+        let headers: Vec<String> = media
+            .http
+            .headers
+            .iter()
+            .map(|(name, value)| format!("{}: {}", name, value))
+            .collect();
+        let user_agent = media.http.user_agent.unwrap_or_default();
+        self.set_property("user-agent", json!(user_agent)).await?;
+        self.set_property("referrer", json!(media.http.referrer.unwrap_or_default())).await?;
+        self.set_property("http-header-fields", json!(headers)).await?;
+        let start = media.start.map_or("none".to_string(), |start| start.to_string());
+        self.set_property("start", json!(start)).await?;
+        self.set_property("pause", json!(false)).await?;
+        self.command(&[json!("loadfile"), json!(media.url), json!("replace")]).await
+    }
+}
+
+impl PlaybackEngine for MpvPlayer {
+    fn kind(&self) -> EngineKind {
+        EngineKind::Mpv
+    }
+
+    fn available(&self) -> bool {
+        super::on_path(PROGRAM)
+    }
+
+    fn load<'a>(&'a self, app: &'a AppHandle, media: Media) -> BoxFuture<'a, Result<(), String>> {
+        self.play(app, media).boxed()
+    }
+
+    fn set_paused(&self, paused: bool) -> BoxFuture<'_, Result<(), String>> {
+        self.set_property("pause", json!(paused)).boxed()
+    }
+
+    fn seek(&self, position: f64, relative: bool) -> BoxFuture<'_, Result<(), String>> {
+        let mode = if relative { "relative" } else { "absolute" };
+        async move { self.command(&[json!("seek"), json!(position), json!(mode)]).await }.boxed()
+    }
+
+    fn set_track(&self, kind: TrackKind, id: Option<i64>) -> BoxFuture<'_, Result<(), String>> {
+        let value = id.map_or(json!("no"), |id| json!(id));
+        self.set_property(track_property(kind), value).boxed()
+    }
+
+    fn set_volume(&self, volume: f64) -> BoxFuture<'_, Result<(), String>> {
+        self.set_property("volume", json!(volume)).boxed()
+    }
+
+    fn stop(&self) -> BoxFuture<'_, Result<(), String>> {
+        async move {
+            if let Some(mut session) = self.session.lock().await.take() {
+                let _ = session.child.start_kill();
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn kill(&self) {
+        if let Ok(mut session) = self.session.try_lock() {
+            if let Some(mut session) = session.take() {
+                let _ = session.child.start_kill();
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn connect(path: &str) -> std::io::Result<tokio::net::UnixStream> {
+    tokio::net::UnixStream::connect(path).await
+}
+
+#[cfg(windows)]
+async fn connect(path: &str) -> std::io::Result<tokio::net::windows::named_pipe::NamedPipeClient> {
+    tokio::net::windows::named_pipe::ClientOptions::new().open(path)
+}
+
+// Write queued commands and emit what mpv reports until it exits
+async fn serve<S>(app: AppHandle, stream: S, mut commands: mpsc::UnboundedReceiver<String>)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+    let writing = tauri::async_runtime::spawn(async move {
+        while let Some(line) = commands.recv().await {
+            if writer.write_all(line.as_bytes()).await.is_err() {
+                break;
+            }
+        }
+    });
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        match ipc::parse(&line) {
+            Some(Message::Reply { error, .. }) if error != "success" => {
+                log::warn!("mpv command failed: {}", error);
+            }
+            Some(Message::Property { name, data }) => super::emit_property(&app, &name, data),
+            Some(Message::Event { name, fields }) => super::emit_event(&app, &name, fields),
+            _ => {}
+        }
+    }
+    writing.abort();
+    super::emit_event(&app, "shutdown", json!({}));
+}
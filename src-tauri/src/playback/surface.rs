@@ -0,0 +1,254 @@
+// A native child window of the main window for engines to draw video into.
+// It sits over the player area the frontend reports (player_set_bounds), so
+// video covers that area instead of the whole webview, and is hidden while
+// nothing plays. Windows and X11 only: macOS and Wayland give no window
+// handle an engine can draw into, so there engines open their own window.
+// Native windows are only created and changed on the main thread
+use std::sync::Mutex;
+
+use raw_window_handle::HasWindowHandle;
+use serde::Deserialize;
+use tauri::{AppHandle, Manager, WebviewWindow};
+
+#[cfg(any(windows, target_os = "linux"))]
+use raw_window_handle::RawWindowHandle;
+#[cfg(target_os = "linux")]
+use raw_window_handle::{HasDisplayHandle, RawDisplayHandle};
+#[cfg(windows)]
+use windows_sys::Win32::Foundation::HWND;
+#[cfg(windows)]
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, MoveWindow, SetWindowPos, ShowWindow, HWND_TOP, SWP_NOMOVE, SWP_NOSIZE, SWP_SHOWWINDOW,
+    SW_HIDE, WS_CHILD, WS_CLIPSIBLINGS,
+};
+#[cfg(target_os = "linux")]
+use x11::xlib;
+
+use super::Playback;
+
+// Where the video goes, in logical pixels of the main window's client area
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bounds {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Bounds {
+    pub fn check(&self) -> Result<(), String> {
+        let values = [self.x, self.y, self.width, self.height];
+        if values.iter().any(|value| !value.is_finite()) || self.width < 0.0 || self.height < 0.0 {
+            return Err("Invalid video bounds".to_string());
+        }
+        Ok(())
+    }
+
+    // In physical pixels; native windows are at least 1x1
+    fn physical(&self, scale: f64) -> (i32, i32, u32, u32) {
+        let scaled = |value: f64| (value * scale).round().clamp(0.0, i32::MAX as f64);
+        let (width, height) = (scaled(self.width).max(1.0), scaled(self.height).max(1.0));
+        (scaled(self.x) as i32, scaled(self.y) as i32, width as u32, height as u32)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Native {
+    #[cfg(windows)]
+    Win32(isize),
+    // The display is the one GDK opened for the main window
+    #[cfg(target_os = "linux")]
+    Xlib { display: usize, window: u64 },
+}
+
+impl Native {
+    #[cfg_attr(not(any(windows, target_os = "linux")), allow(unused_variables))]
+    fn create(window: &WebviewWindow, bounds: Bounds) -> Option<Native> {
+        let scale = window.scale_factor().ok()?;
+        let (x, y, width, height) = bounds.physical(scale);
+        match window.window_handle().ok()?.as_raw() {
+            #[cfg(windows)]
+            RawWindowHandle::Win32(parent) => {
+                let class: Vec<u16> = "STATIC\0".encode_utf16().collect();
+                // Safety: the parent is the main window, alive on this thread
+                let hwnd = unsafe {
+                    CreateWindowExW(
+                        0,
+                        class.as_ptr(),
+                        std::ptr::null(),
+                        WS_CHILD | WS_CLIPSIBLINGS,
+                        x,
+                        y,
+                        width as i32,
+                        height as i32,
+                        parent.hwnd.get() as HWND,
+                        std::ptr::null_mut(),
+                        std::ptr::null_mut(),
+                        std::ptr::null(),
+                    )
+                };
+                (!hwnd.is_null()).then_some(Native::Win32(hwnd as isize))
+            }
+            #[cfg(target_os = "linux")]
+            RawWindowHandle::Xlib(parent) => {
+                let RawDisplayHandle::Xlib(display) = window.display_handle().ok()?.as_raw() else {
+                    return None;
+                };
+                let display = display.display?.as_ptr() as *mut xlib::Display;
+                // Safety: the display and parent are the main window's, on
+                // the thread GDK uses them from
+                let child = unsafe { xlib::XCreateSimpleWindow(display, parent.window, x, y, width, height, 0, 0, 0) };
+                (child != 0).then_some(Native::Xlib {
+                    display: display as usize,
+                    window: child,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn id(self) -> i64 {
+        match self {
+            #[cfg(windows)]
+            Native::Win32(hwnd) => hwnd as i64,
+            #[cfg(target_os = "linux")]
+            Native::Xlib { window, .. } => window as i64,
+        }
+    }
+
+    // Safety for the calls below: the window was created by create() and is
+    // never destroyed while the app runs; they run on the main thread
+    fn show(self) {
+        match self {
+            #[cfg(windows)]
+            Native::Win32(hwnd) => unsafe {
+                SetWindowPos(hwnd as HWND, HWND_TOP, 0, 0, 0, 0, SWP_NOMOVE | SWP_NOSIZE | SWP_SHOWWINDOW);
+            },
+            #[cfg(target_os = "linux")]
+            Native::Xlib { display, window } => unsafe {
+                xlib::XMapRaised(display as *mut xlib::Display, window);
+                xlib::XFlush(display as *mut xlib::Display);
+            },
+        }
+    }
+
+    fn hide(self) {
+        match self {
+            #[cfg(windows)]
+            Native::Win32(hwnd) => unsafe {
+                ShowWindow(hwnd as HWND, SW_HIDE);
+            },
+            #[cfg(target_os = "linux")]
+            Native::Xlib { display, window } => unsafe {
+                xlib::XUnmapWindow(display as *mut xlib::Display, window);
+                xlib::XFlush(display as *mut xlib::Display);
+            },
+        }
+    }
+
+    #[cfg_attr(not(any(windows, target_os = "linux")), allow(unused_variables))]
+    fn place(self, bounds: Bounds, scale: f64) {
+        let (x, y, width, height) = bounds.physical(scale);
+        match self {
+            #[cfg(windows)]
+            Native::Win32(hwnd) => unsafe {
+                MoveWindow(hwnd as HWND, x, y, width as i32, height as i32, 1);
+            },
+            #[cfg(target_os = "linux")]
+            Native::Xlib { display, window } => unsafe {
+                xlib::XMoveResizeWindow(display as *mut xlib::Display, window, x, y, width, height);
+                xlib::XFlush(display as *mut xlib::Display);
+            },
+        }
+    }
+}
+
+#[derive(Default)]
+struct SurfaceState {
+    native: Option<Native>,
+    bounds: Bounds,
+}
+
+#[derive(Default)]
+pub struct VideoSurface {
+    state: Mutex<SurfaceState>,
+}
+
+// Run `f` on the main thread and wait for its result
+async fn on_main<T, F>(app: &AppHandle, f: F) -> Option<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&AppHandle, &VideoSurface) -> T + Send + 'static,
+{
+    let (sender, receiver) = tokio::sync::oneshot::channel();
+    let handle = app.clone();
+    let run = app.run_on_main_thread(move || {
+        let _ = sender.send(f(&handle, &handle.state::<Playback>().surface));
+    });
+    if let Err(e) = run {
+        log::warn!("Failed to reach the main thread: {}", e);
+        return None;
+    }
+    receiver.await.ok()
+}
+
+// Show the surface, creating it on first use, and return its native handle
+// for an engine to draw into
+pub async fn show(app: &AppHandle) -> Option<i64> {
+    on_main(app, |app, surface| {
+        let mut state = surface.state.lock().unwrap();
+        if state.native.is_none() {
+            let window = app.get_webview_window("main")?;
+            state.native = Native::create(&window, state.bounds);
+        }
+        let native = state.native?;
+        native.show();
+        Some(native.id())
+    })
+    .await
+    .flatten()
+}
+
+pub async fn hide(app: &AppHandle) {
+    on_main(app, |_, surface| {
+        if let Some(native) = surface.state.lock().unwrap().native {
+            native.hide();
+        }
+    })
+    .await;
+}
+
+pub async fn set_bounds(app: &AppHandle, bounds: Bounds) -> Result<(), String> {
+    bounds.check()?;
+    on_main(app, move |app, surface| {
+        let mut state = surface.state.lock().unwrap();
+        state.bounds = bounds;
+        let scale = app.get_webview_window("main").and_then(|window| window.scale_factor().ok());
+        if let Some((native, scale)) = state.native.zip(scale) {
+            native.place(bounds, scale);
+        }
+    })
+    .await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bounds_scale_to_physical_pixels() {
+        // Test that bounds scale, round and never collapse below 1x1
+        let bounds = Bounds {
+            x: 10.0,
+            y: 20.4,
+            width: 640.0,
+            height: 0.0,
+        };
+        assert_eq!(bounds.physical(1.5), (15, 31, 960, 1));
+        assert!(Bounds { width: -1.0, ..bounds }.check().is_err());
+        assert!(Bounds { x: f64::NAN, ..bounds }.check().is_err());
+        assert!(bounds.check().is_ok());
+    }
+}
@@ -0,0 +1,286 @@
+// Batched manual edits of channels, applied all-or-nothing
+use std::collections::HashSet;
+
+use serde::Deserialize;
+
+use super::overrides::ChannelOverride;
+use super::{ChannelEntry, MAX_NAME_LENGTH, MAX_SOURCE_LENGTH};
+use crate::validate_string_length;
+
+// Largest batch accepted in one call
+pub const MAX_EDITS: usize = 10_000;
+
+// One change to the channels of a playlist; channels are identified by
+// their stream URL, and every entry with that URL is affected
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "edit", rename_all = "camelCase")]
+pub enum ChannelEdit {
+    Rename {
+        url: String,
+        name: String,
+    },
+    // None removes the group
+    SetGroup {
+        urls: Vec<String>,
+        group: Option<String>,
+    },
+    SetHidden {
+        urls: Vec<String>,
+        hidden: bool,
+    },
+    // None removes the logo
+    SetLogo {
+        urls: Vec<String>,
+        logo: Option<String>,
+    },
+    // Move the channels, keeping their relative order, so the first one ends
+    // up at `position` among the channels that weren't moved
+    Move {
+        urls: Vec<String>,
+        position: usize,
+    },
+}
+
+// Indices of the entries with one of the URLs, failing on unknown URLs so a
+// stale UI can't silently edit nothing
+fn select<'a>(entries: &[ChannelEntry], urls: impl IntoIterator<Item = &'a String>) -> Result<Vec<usize>, String> {
+    let urls: HashSet<&str> = urls.into_iter().map(String::as_str).collect();
+    let selected: Vec<usize> = entries
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| urls.contains(entry.url.as_str()))
+        .map(|(i, _)| i)
+        .collect();
+    let found: HashSet<&str> = selected.iter().map(|&i| entries[i].url.as_str()).collect();
+    match urls.iter().find(|url| !found.contains(*url)) {
+        Some(missing) => Err(format!("Channel '{}' is not in the playlist", missing)),
+        None => Ok(selected),
+    }
+}
+
+fn non_empty(value: &Option<String>) -> Option<String> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty()).map(str::to_string)
+}
+
+// Hold names and groups to the limit of playlist names, URLs and logos to
+// that of playlist sources
+fn check(edit: &ChannelEdit) -> Result<(), String> {
+    let (urls, text, limit) = match edit {
+        ChannelEdit::Rename { url, name } => (std::slice::from_ref(url), Some(name), MAX_NAME_LENGTH),
+        ChannelEdit::SetGroup { urls, group } => (urls.as_slice(), group.as_ref(), MAX_NAME_LENGTH),
+        ChannelEdit::SetLogo { urls, logo } => (urls.as_slice(), logo.as_ref(), MAX_SOURCE_LENGTH),
+        ChannelEdit::SetHidden { urls, .. } | ChannelEdit::Move { urls, .. } => (urls.as_slice(), None, 0),
+    };
+    for url in urls {
+        validate_string_length(url, MAX_SOURCE_LENGTH)?;
+    }
+    match text {
+        Some(text) => validate_string_length(text, limit),
+        None => Ok(()),
+    }
+}
+
+fn apply_edit(entries: &mut Vec<ChannelEntry>, edit: &ChannelEdit) -> Result<(), String> {
+    check(edit)?;
+    match edit {
+        ChannelEdit::Rename { url, name } => {
+            let name = name.trim();
+            if name.is_empty() {
+                return Err("Channel name cannot be empty".to_string());
+            }
+            for i in select(entries, [url])? {
+                entries[i].name = name.to_string();
+            }
+        }
+        ChannelEdit::SetGroup { urls, group } => {
+            for i in select(entries, urls)? {
+                entries[i].group_title = non_empty(group);
+            }
+        }
+        ChannelEdit::SetHidden { urls, hidden } => {
+            for i in select(entries, urls)? {
+                entries[i].hidden = *hidden;
+            }
+        }
+        ChannelEdit::SetLogo { urls, logo } => {
+            for i in select(entries, urls)? {
+                entries[i].tvg_logo = non_empty(logo);
+            }
+        }
+        ChannelEdit::Move { urls, position } => {
+            let selected: HashSet<usize> = select(entries, urls)?.into_iter().collect();
+            let (moved, mut rest): (Vec<_>, Vec<_>) = std::mem::take(entries)
+                .into_iter()
+                .enumerate()
+                .partition(|(i, _)| selected.contains(i));
+            let position = (*position).min(rest.len());
+            rest.splice(position..position, moved);
+            *entries = rest.into_iter().map(|(_, entry)| entry).collect();
+        }
+    }
+    Ok(())
+}
+
+// The persistent overrides an edit amounts to, so it survives refreshes;
+// reordering isn't kept
+pub fn as_overrides(edit: &ChannelEdit) -> Vec<(String, ChannelOverride)> {
+    let each = |urls: &[String], change: ChannelOverride| {
+        urls.iter().map(|url| (url.clone(), change.clone())).collect()
+    };
+    match edit {
+        ChannelEdit::Rename { url, name } => vec![(
+            url.clone(),
+            ChannelOverride {
+                name: Some(name.trim().to_string()),
+                ..Default::default()
+            },
+        )],
+        ChannelEdit::SetGroup { urls, group } => each(
+            urls,
+            ChannelOverride {
+                group: Some(non_empty(group).unwrap_or_default()),
+                ..Default::default()
+            },
+        ),
+        ChannelEdit::SetHidden { urls, hidden } => each(
+            urls,
+            ChannelOverride {
+                hidden: Some(*hidden),
+                ..Default::default()
+            },
+        ),
+        ChannelEdit::SetLogo { urls, logo } => each(
+            urls,
+            ChannelOverride {
+                logo: Some(non_empty(logo).unwrap_or_default()),
+                ..Default::default()
+            },
+        ),
+        ChannelEdit::Move { .. } => Vec::new(),
+    }
+}
+
+// Apply the edits in order to a copy of the entries; on error nothing is
+// changed
+pub fn apply(entries: &[ChannelEntry], edits: &[ChannelEdit]) -> Result<Vec<ChannelEntry>, String> {
+    let mut edited = entries.to_vec();
+    for (index, edit) in edits.iter().enumerate() {
+        apply_edit(&mut edited, edit).map_err(|e| format!("Edit {}: {}", index + 1, e))?;
+    }
+    Ok(edited)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<ChannelEntry> {
+        ["a", "b", "c", "d"]
+            .iter()
+            .map(|name| ChannelEntry {
+                name: name.to_string(),
+                url: format!("http://x/{}", name),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn urls(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| format!("http://x/{}", name)).collect()
+    }
+
+    #[test]
+    fn test_apply_edits() {
+        // Test rename, regroup, hide and logo edits in one batch
+        let edits = vec![
+            ChannelEdit::Rename {
+                url: "http://x/a".to_string(),
+                name: " Alpha ".to_string(),
+            },
+            ChannelEdit::SetGroup {
+                urls: urls(&["a", "b"]),
+                group: Some("Sports".to_string()),
+            },
+            ChannelEdit::SetHidden {
+                urls: urls(&["c"]),
+                hidden: true,
+            },
+            ChannelEdit::SetLogo {
+                urls: urls(&["d"]),
+                logo: Some("http://logo/d.png".to_string()),
+            },
+        ];
+        let edited = apply(&entries(), &edits).unwrap();
+
+        assert_eq!(edited[0].name, "Alpha");
+        assert_eq!(edited[1].group_title.as_deref(), Some("Sports"));
+        assert!(edited[2].hidden);
+        assert_eq!(edited[3].tvg_logo.as_deref(), Some("http://logo/d.png"));
+    }
+
+    #[test]
+    fn test_move_keeps_relative_order() {
+        // Test that moved channels land together at the target position
+        let edits = vec![ChannelEdit::Move {
+            urls: urls(&["d", "b"]),
+            position: 0,
+        }];
+        let names: Vec<String> = apply(&entries(), &edits).unwrap().into_iter().map(|e| e.name).collect();
+        assert_eq!(names, vec!["b", "d", "a", "c"]);
+
+        let edits = vec![ChannelEdit::Move {
+            urls: urls(&["a"]),
+            position: 99,
+        }];
+        let names: Vec<String> = apply(&entries(), &edits).unwrap().into_iter().map(|e| e.name).collect();
+        assert_eq!(names, vec!["b", "c", "d", "a"]);
+    }
+
+    #[test]
+    fn test_failed_batch_is_rejected() {
+        // Test that an unknown channel fails the whole batch with its index
+        let edits = vec![
+            ChannelEdit::SetHidden {
+                urls: urls(&["a"]),
+                hidden: true,
+            },
+            ChannelEdit::SetGroup {
+                urls: urls(&["missing"]),
+                group: None,
+            },
+        ];
+        let error = apply(&entries(), &edits).unwrap_err();
+        assert_eq!(error, "Edit 2: Channel 'http://x/missing' is not in the playlist");
+
+        // Text over the playlist limits is refused before anything applies
+        let rename = ChannelEdit::Rename {
+            url: "http://x/a".to_string(),
+            name: "a".repeat(MAX_NAME_LENGTH + 1),
+        };
+        let group = ChannelEdit::SetGroup {
+            urls: urls(&["a"]),
+            group: Some("g".repeat(MAX_NAME_LENGTH + 1)),
+        };
+        let logo = ChannelEdit::SetLogo {
+            urls: urls(&["a"]),
+            logo: Some(format!("http://logo/{}", "l".repeat(MAX_SOURCE_LENGTH))),
+        };
+        for edit in [rename, group, logo] {
+            assert!(apply(&entries(), &[edit]).unwrap_err().starts_with("Edit 1: "));
+        }
+    }
+
+    #[test]
+    fn test_as_overrides() {
+        // Test that edits become overrides and clearing is kept as empty
+        let edit = ChannelEdit::SetGroup {
+            urls: urls(&["a", "b"]),
+            group: None,
+        };
+        let changes = as_overrides(&edit);
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[1].0, "http://x/b");
+        assert_eq!(changes[1].1.group.as_deref(), Some(""));
+        assert!(as_overrides(&ChannelEdit::Move { urls: urls(&["a"]), position: 0 }).is_empty());
+    }
+}
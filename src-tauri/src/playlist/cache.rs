@@ -0,0 +1,147 @@
+// Conditional-request cache for remote playlists (ETag / Last-Modified)
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use super::playlist_id;
+use crate::storage;
+
+const INDEX_FILE: &str = "index.json";
+
+// Validators returned by the server for one playlist URL
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+impl CacheEntry {
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+// Raw playlist bodies plus their validators, kept in the app cache dir
+pub struct DownloadCache {
+    dir: PathBuf,
+    entries: Mutex<HashMap<String, CacheEntry>>,
+}
+
+impl DownloadCache {
+    pub fn open(dir: PathBuf) -> Self {
+        let entries = storage::read_json(&dir.join(INDEX_FILE)).unwrap_or_else(|e| {
+            log::warn!("Discarding playlist cache index: {}", e);
+            HashMap::new()
+        });
+        Self {
+            dir,
+            entries: Mutex::new(entries),
+        }
+    }
+
+    // Validators for a URL, only when its cached body is still on disk
+    pub fn entry(&self, url: &str) -> Option<CacheEntry> {
+        let entry = self.entries.lock().unwrap().get(url).cloned()?;
+        self.body_path(url).exists().then_some(entry)
+    }
+
+    pub fn body_path(&self, url: &str) -> PathBuf {
+        self.dir.join(format!("{}.body", playlist_id(url)))
+    }
+
+    // Where a download is written while it is still in progress
+    pub fn partial_path(&self, url: &str) -> PathBuf {
+        self.dir.join(format!("{}.partial", playlist_id(url)))
+    }
+
+    pub fn ensure_dir(&self) -> Result<(), String> {
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("Failed to create {}: {}", self.dir.display(), e))
+    }
+
+    // Promote a fully downloaded body and remember its validators
+    pub fn commit(&self, url: &str, entry: CacheEntry) -> Result<(), String> {
+        let partial = self.partial_path(url);
+        let body = self.body_path(url);
+        fs::rename(&partial, &body).map_err(|e| format!("Failed to store {}: {}", body.display(), e))?;
+
+        let mut entries = self.entries.lock().unwrap();
+        if entry.is_empty() {
+            // Nothing to validate against next time
+            entries.remove(url);
+        } else {
+            entries.insert(url.to_string(), entry);
+        }
+        storage::write_json(&self.dir.join(INDEX_FILE), &*entries)
+    }
+
+    // Throw away an interrupted download
+    pub fn discard(&self, url: &str) {
+        let _ = fs::remove_file(self.partial_path(url));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    fn entry(etag: &str) -> CacheEntry {
+        CacheEntry {
+            etag: Some(etag.to_string()),
+            last_modified: None,
+        }
+    }
+
+    #[test]
+    fn test_commit_and_reopen() {
+        // Test that committed validators survive reopening the cache
+        let dir = temp_dir("playlist-cache");
+        let cache = DownloadCache::open(dir.clone());
+        let url = "http://example.com/list.m3u";
+
+        cache.ensure_dir().unwrap();
+        fs::write(cache.partial_path(url), b"#EXTM3U\n").unwrap();
+        cache.commit(url, entry("\"v1\"")).unwrap();
+
+        let reopened = DownloadCache::open(dir);
+        assert_eq!(reopened.entry(url), Some(entry("\"v1\"")));
+        assert!(reopened.body_path(url).exists());
+        assert!(!reopened.partial_path(url).exists());
+    }
+
+    #[test]
+    fn test_entry_requires_body() {
+        // Test that validators are ignored once the body file is gone
+        let dir = temp_dir("playlist-cache-body");
+        let cache = DownloadCache::open(dir);
+        let url = "http://example.com/list.m3u";
+
+        cache.ensure_dir().unwrap();
+        fs::write(cache.partial_path(url), b"#EXTM3U\n").unwrap();
+        cache.commit(url, entry("\"v1\"")).unwrap();
+        fs::remove_file(cache.body_path(url)).unwrap();
+
+        assert!(cache.entry(url).is_none());
+    }
+
+    #[test]
+    fn test_commit_without_validators_forgets_url() {
+        // Test that a body without ETag/Last-Modified isn't revalidated
+        let dir = temp_dir("playlist-cache-empty");
+        let cache = DownloadCache::open(dir);
+        let url = "http://example.com/list.m3u";
+
+        cache.ensure_dir().unwrap();
+        fs::write(cache.partial_path(url), b"#EXTM3U\n").unwrap();
+        cache.commit(url, CacheEntry::default()).unwrap();
+
+        assert!(cache.entry(url).is_none());
+    }
+}
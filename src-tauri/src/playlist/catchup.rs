@@ -0,0 +1,291 @@
+// Archive (catch-up) URLs for a past programme, following the catchup modes
+// and catchup-source placeholders of Kodi's IPTV Simple client
+use std::sync::OnceLock;
+
+use regex::{Captures, Regex};
+use url::Url;
+
+use super::ChannelEntry;
+use crate::epg::time::{utc_datetime, DateTime};
+
+// Archive depth assumed when a channel supports catch-up without saying how far back
+const DEFAULT_ARCHIVE_DAYS: u32 = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CatchupMode {
+    // catchup-source is the whole archive URL
+    Default,
+    // catchup-source is appended to the stream URL
+    Append,
+    // utc/lutc query parameters on the stream URL
+    Shift,
+    Flussonic,
+    Xtream,
+}
+
+impl CatchupMode {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "default" => Some(Self::Default),
+            "append" => Some(Self::Append),
+            "shift" | "timeshift" => Some(Self::Shift),
+            "flussonic" | "flussonic-hls" | "flussonic-ts" | "fs" => Some(Self::Flussonic),
+            "xc" | "xtream" => Some(Self::Xtream),
+            _ => None,
+        }
+    }
+}
+
+// A programme to play back: start and end in Unix seconds, and the current
+// time for the {lutc} and {offset} placeholders
+#[derive(Debug, Clone, Copy)]
+pub struct ArchiveWindow {
+    pub start: i64,
+    pub end: i64,
+    pub now: i64,
+}
+
+// The channel's catch-up mode; channels that only announce an archive
+// depth get Xtream mode when their URL has the Xtream layout
+pub fn mode(entry: &ChannelEntry) -> Option<CatchupMode> {
+    if let Some(mode) = entry.catchup.as_deref().and_then(CatchupMode::parse) {
+        return Some(mode);
+    }
+    let announced = entry.timeshift.or(entry.catchup_days).is_some_and(|days| days > 0);
+    (announced && xtream_parts(&entry.url).is_some()).then_some(CatchupMode::Xtream)
+}
+
+pub fn archive_days(entry: &ChannelEntry) -> u32 {
+    entry
+        .catchup_days
+        .or(entry.timeshift)
+        .filter(|days| *days > 0)
+        .unwrap_or(DEFAULT_ARCHIVE_DAYS)
+}
+
+pub fn archive_url(entry: &ChannelEntry, window: ArchiveWindow) -> Result<String, String> {
+    let mode = mode(entry).ok_or_else(|| format!("Channel '{}' doesn't support catch-up", entry.name))?;
+    if window.end <= window.start {
+        return Err("The programme must end after it starts".to_string());
+    }
+    if window.start > window.now {
+        return Err("The programme hasn't started yet".to_string());
+    }
+    let days = archive_days(entry);
+    if window.now - window.start > i64::from(days) * 86_400 {
+        return Err(format!("The programme is older than the channel's {}-day archive", days));
+    }
+
+    let source = entry.catchup_source.as_deref().map(str::trim).filter(|s| !s.is_empty());
+    let template = match (mode, source) {
+        (CatchupMode::Append, Some(source)) => format!("{}{}", entry.url, source),
+        (CatchupMode::Append, None) => return Err("Append catch-up needs a catchup-source".to_string()),
+        (CatchupMode::Default, None) => return Err("Default catch-up needs a catchup-source".to_string()),
+        (_, Some(source)) => source.to_string(),
+        (CatchupMode::Shift, None) => {
+            let separator = if entry.url.contains('?') { '&' } else { '?' };
+            format!("{}{}utc={{utc}}&lutc={{lutc}}", entry.url, separator)
+        }
+        (CatchupMode::Flussonic, None) => flussonic_template(&entry.url)?,
+        (CatchupMode::Xtream, None) => xtream_template(&entry.url)?,
+    };
+    Ok(fill(&template, window))
+}
+
+// Flussonic: .../name.m3u8 → .../name-{utc}-{duration}.m3u8 and
+// .../mpegts → .../timeshift_abs-{utc}.ts
+fn flussonic_template(stream: &str) -> Result<String, String> {
+    let mut url = Url::parse(stream).map_err(|_| format!("Can't build a Flussonic archive URL from '{}'", stream))?;
+    let last = url.path_segments().and_then(|mut segments| segments.next_back()).unwrap_or_default();
+    let file = match last.strip_suffix(".m3u8") {
+        Some(name) if !name.is_empty() => format!("{}-{{utc}}-{{duration}}.m3u8", name),
+        _ if last == "mpegts" || last.ends_with(".ts") => "timeshift_abs-{utc}.ts".to_string(),
+        _ => return Err(format!("Can't build a Flussonic archive URL from '{}'", stream)),
+    };
+    url.path_segments_mut()
+        .map_err(|_| format!("Can't build a Flussonic archive URL from '{}'", stream))?
+        .pop()
+        .push(&file);
+    // The placeholders must survive percent-encoding of the path
+    Ok(url.to_string().replace("%7B", "{").replace("%7D", "}"))
+}
+
+// Server, username, password and stream id of an Xtream stream URL, which
+// is /live/user/pass/id.ext or /user/pass/id[.ext]
+pub fn xtream_parts(stream: &str) -> Option<(Url, String, String, String)> {
+    let url = Url::parse(stream).ok()?;
+    let segments: Vec<&str> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+    let [user, pass, file] = match segments.as_slice() {
+        ["live", user, pass, file] => [*user, *pass, *file],
+        [user, pass, file] => [*user, *pass, *file],
+        _ => return None,
+    };
+    let id = file.split('.').next().unwrap_or(file);
+    if id.is_empty() || !id.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let decode = |s: &str| percent_encoding::percent_decode_str(s).decode_utf8_lossy().into_owned();
+    Some((url.clone(), decode(user), decode(pass), id.to_string()))
+}
+
+// Xtream: /streaming/timeshift.php with the length in minutes. Panels read
+// the start as server time, which is UTC on nearly all of them; others need
+// an explicit catchup-source
+fn xtream_template(stream: &str) -> Result<String, String> {
+    let (mut url, user, pass, id) =
+        xtream_parts(stream).ok_or_else(|| format!("'{}' isn't an Xtream stream URL", stream))?;
+    url.set_path("/streaming/timeshift.php");
+    url.set_fragment(None);
+    url.query_pairs_mut()
+        .clear()
+        .append_pair("username", &user)
+        .append_pair("password", &pass)
+        .append_pair("stream", &id);
+    Ok(format!("{}&start={{Y}}-{{m}}-{{d}}:{{H}}-{{M}}&duration={{duration:60}}", url))
+}
+
+fn placeholder_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"\$\{([a-z_-]+)\}|\{([a-zA-Z_]+)(?::([^}]*))?\}").unwrap())
+}
+
+// "YmdHMS"-style format letters, e.g. {utc:Y-m-d H:M:S}
+fn format_time(format: &str, time: DateTime) -> String {
+    let mut out = String::new();
+    for ch in format.chars() {
+        match ch {
+            'Y' => out.push_str(&format!("{:04}", time.year)),
+            'm' => out.push_str(&format!("{:02}", time.month)),
+            'd' => out.push_str(&format!("{:02}", time.day)),
+            'H' => out.push_str(&format!("{:02}", time.hour)),
+            'M' => out.push_str(&format!("{:02}", time.minute)),
+            'S' => out.push_str(&format!("{:02}", time.second)),
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+// Replace the catchup-source placeholders; unknown ones are left as they are
+pub fn fill(template: &str, window: ArchiveWindow) -> String {
+    let start = utc_datetime(window.start);
+    let duration = window.end - window.start;
+    let offset = window.now - window.start;
+    placeholder_pattern()
+        .replace_all(template, |caps: &Captures<'_>| {
+            let (name, argument) = match caps.get(1) {
+                Some(name) => (name.as_str(), None),
+                None => (&caps[2], caps.get(3).map(|m| m.as_str())),
+            };
+            let divided = |value: i64| {
+                let divisor = argument.and_then(|a| a.parse::<i64>().ok()).filter(|d| *d > 0).unwrap_or(1);
+                // Round up so the whole programme is requested
+                ((value + divisor - 1) / divisor).to_string()
+            };
+            let time = |timestamp: i64| match argument {
+                Some(format) => format_time(format, utc_datetime(timestamp)),
+                None => timestamp.to_string(),
+            };
+            match name {
+                "utc" | "start" => time(window.start),
+                "utcend" | "end" => time(window.end),
+                "lutc" | "now" | "timestamp" => time(window.now),
+                "duration" => divided(duration),
+                "offset" => divided(offset),
+                "Y" => format!("{:04}", start.year),
+                "m" => format!("{:02}", start.month),
+                "d" => format!("{:02}", start.day),
+                "H" => format!("{:02}", start.hour),
+                "M" => format!("{:02}", start.minute),
+                "S" => format!("{:02}", start.second),
+                _ => caps[0].to_string(),
+            }
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-01-01 10:00:00 UTC to 11:30, watched at 12:00
+    const WINDOW: ArchiveWindow = ArchiveWindow {
+        start: 1_704_103_200,
+        end: 1_704_108_600,
+        now: 1_704_110_400,
+    };
+
+    fn channel(url: &str, catchup: Option<&str>, source: Option<&str>) -> ChannelEntry {
+        ChannelEntry {
+            name: "News".to_string(),
+            url: url.to_string(),
+            catchup: catchup.map(str::to_string),
+            catchup_source: source.map(str::to_string),
+            catchup_days: Some(3),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_template_modes() {
+        // Test default, append and shift URLs and the placeholder set
+        let default = channel(
+            "http://a/live.ts",
+            Some("default"),
+            Some("http://a/arch?s={utc}&e=${end}&d={duration:60}&o={offset}&t={utc:Y-m-d_H:M}&x={unknown}"),
+        );
+        assert_eq!(
+            archive_url(&default, WINDOW).unwrap(),
+            "http://a/arch?s=1704103200&e=1704108600&d=90&o=7200&t=2024-01-01_10:00&x={unknown}"
+        );
+
+        let append = channel("http://a/live.m3u8?token=1", Some("append"), Some("&start={utc}"));
+        assert_eq!(archive_url(&append, WINDOW).unwrap(), "http://a/live.m3u8?token=1&start=1704103200");
+
+        let shift = channel("http://a/live.m3u8", Some("shift"), None);
+        assert_eq!(archive_url(&shift, WINDOW).unwrap(), "http://a/live.m3u8?utc=1704103200&lutc=1704110400");
+    }
+
+    #[test]
+    fn test_flussonic_and_xtream() {
+        // Test URL arithmetic for Flussonic HLS/MPEG-TS and Xtream streams
+        let hls = channel("http://f:8080/325/index.m3u8?token=s", Some("flussonic"), None);
+        assert_eq!(
+            archive_url(&hls, WINDOW).unwrap(),
+            "http://f:8080/325/index-1704103200-5400.m3u8?token=s"
+        );
+        let ts = channel("http://f:8080/325/mpegts?token=s", Some("fs"), None);
+        assert_eq!(archive_url(&ts, WINDOW).unwrap(), "http://f:8080/325/timeshift_abs-1704103200.ts?token=s");
+
+        // No catchup attribute, but an archive depth and an Xtream URL
+        let mut xtream = channel("http://x:80/live/us%40r/pass/1234.ts", None, None);
+        xtream.catchup_days = None;
+        xtream.timeshift = Some(2);
+        assert_eq!(mode(&xtream), Some(CatchupMode::Xtream));
+        assert_eq!(
+            archive_url(&xtream, WINDOW).unwrap(),
+            "http://x/streaming/timeshift.php?username=us%40r&password=pass&stream=1234&start=2024-01-01:10-00&duration=90"
+        );
+    }
+
+    #[test]
+    fn test_archive_limits() {
+        // Test that unsupported channels and out-of-range programmes fail
+        assert!(archive_url(&channel("http://a/1.ts", None, None), WINDOW).is_err());
+        let shift = channel("http://a/1.ts", Some("shift"), None);
+        let old = ArchiveWindow {
+            now: WINDOW.start + 4 * 86_400,
+            ..WINDOW
+        };
+        assert_eq!(
+            archive_url(&shift, old).unwrap_err(),
+            "The programme is older than the channel's 3-day archive"
+        );
+        let future = ArchiveWindow {
+            now: WINDOW.start - 1,
+            ..WINDOW
+        };
+        assert!(archive_url(&shift, future).is_err());
+        assert!(archive_url(&channel("http://a/1.ts", Some("default"), None), WINDOW).is_err());
+    }
+}
@@ -0,0 +1,276 @@
+// User-defined regex rules that rewrite channels every time a playlist is
+// parsed (regroup, rename, renumber or hide)
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use super::{ChannelEntry, NUMBER_ATTRIBUTE};
+use crate::storage;
+
+const RULES_FILE: &str = "channel_rules.json";
+
+const MAX_RULES: usize = 500;
+const MAX_PATTERN_LENGTH: usize = 1000;
+
+// Compiled size cap so a pathological pattern can't eat memory
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RuleField {
+    Name,
+    Group,
+    Url,
+}
+
+// What happens to a matching channel; names and groups may refer to capture
+// groups of the pattern ("$1", "${name}")
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "camelCase")]
+pub enum RuleAction {
+    SetGroup { group: String },
+    Rename { name: String },
+    SetNumber { number: u32 },
+    Hide,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelRule {
+    // Only apply to this playlist, None applies to all of them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub playlist_id: Option<String>,
+    pub field: RuleField,
+    pub pattern: String,
+    #[serde(default)]
+    pub case_sensitive: bool,
+    #[serde(flatten)]
+    pub action: RuleAction,
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+impl ChannelRule {
+    fn compile(&self) -> Result<Regex, String> {
+        if self.pattern.len() > MAX_PATTERN_LENGTH {
+            return Err(format!("Pattern exceeds maximum length of {} characters", MAX_PATTERN_LENGTH));
+        }
+        RegexBuilder::new(&self.pattern)
+            .case_insensitive(!self.case_sensitive)
+            .size_limit(REGEX_SIZE_LIMIT)
+            .build()
+            .map_err(|e| format!("Invalid pattern '{}': {}", self.pattern, e))
+    }
+
+    fn applies_to(&self, playlist_id: &str) -> bool {
+        self.enabled && self.playlist_id.as_deref().map_or(true, |id| id == playlist_id)
+    }
+}
+
+// Run the rules in order over the entries; later rules see the changes made
+// by earlier ones
+fn apply_rules(rules: &[(ChannelRule, Regex)], playlist_id: &str, entries: &mut [ChannelEntry]) {
+    let rules: Vec<&(ChannelRule, Regex)> = rules.iter().filter(|(rule, _)| rule.applies_to(playlist_id)).collect();
+    if rules.is_empty() {
+        return;
+    }
+
+    for entry in entries {
+        for (rule, regex) in &rules {
+            let value = match rule.field {
+                RuleField::Name => entry.name.as_str(),
+                RuleField::Group => entry.group_title.as_deref().unwrap_or_default(),
+                RuleField::Url => entry.url.as_str(),
+            };
+            let Some(captures) = regex.captures(value) else {
+                continue;
+            };
+            let expand = |template: &str| {
+                let mut out = String::new();
+                captures.expand(template, &mut out);
+                out.trim().to_string()
+            };
+
+            match &rule.action {
+                RuleAction::SetGroup { group } => {
+                    let group = expand(group);
+                    entry.group_title = (!group.is_empty()).then_some(group);
+                }
+                RuleAction::Rename { name } => {
+                    let name = expand(name);
+                    if !name.is_empty() {
+                        entry.name = name;
+                    }
+                }
+                RuleAction::SetNumber { number } => {
+                    entry.attributes.insert(NUMBER_ATTRIBUTE.to_string(), number.to_string());
+                }
+                RuleAction::Hide => entry.hidden = true,
+            }
+        }
+    }
+}
+
+fn compile_all(rules: Vec<ChannelRule>) -> Result<Vec<(ChannelRule, Regex)>, String> {
+    rules
+        .into_iter()
+        .enumerate()
+        .map(|(index, rule)| {
+            let regex = rule.compile().map_err(|e| format!("Rule {}: {}", index + 1, e))?;
+            Ok((rule, regex))
+        })
+        .collect()
+}
+
+pub struct ChannelRules {
+    path: PathBuf,
+    rules: Mutex<Vec<(ChannelRule, Regex)>>,
+}
+
+impl ChannelRules {
+    pub fn open(data_dir: &Path) -> Self {
+        let path = data_dir.join(RULES_FILE);
+        let saved: Vec<ChannelRule> = storage::read_json(&path).unwrap_or_else(|e| {
+            log::error!("Failed to load channel rules: {}", e);
+            Vec::new()
+        });
+        // A rule that no longer compiles (e.g. after a regex crate change)
+        // is dropped instead of disabling every rule
+        let rules = saved
+            .into_iter()
+            .filter_map(|rule| match rule.compile() {
+                Ok(regex) => Some((rule, regex)),
+                Err(e) => {
+                    log::warn!("Skipping channel rule: {}", e);
+                    None
+                }
+            })
+            .collect();
+        Self {
+            path,
+            rules: Mutex::new(rules),
+        }
+    }
+
+    pub fn list(&self) -> Vec<ChannelRule> {
+        self.rules.lock().unwrap().iter().map(|(rule, _)| rule.clone()).collect()
+    }
+
+    fn replace(&self, rules: Vec<ChannelRule>) -> Result<(), String> {
+        if rules.len() > MAX_RULES {
+            return Err(format!("Too many rules (maximum is {})", MAX_RULES));
+        }
+        let compiled = compile_all(rules)?;
+        let mut current = self.rules.lock().unwrap();
+        let list: Vec<&ChannelRule> = compiled.iter().map(|(rule, _)| rule).collect();
+        storage::write_json(&self.path, &list)?;
+        *current = compiled;
+        Ok(())
+    }
+
+    pub fn apply(&self, playlist_id: &str, entries: &mut [ChannelEntry]) {
+        apply_rules(&self.rules.lock().unwrap(), playlist_id, entries);
+    }
+}
+
+// Command handler returning the configured rules in the order they run
+#[tauri::command]
+pub fn get_channel_rules(rules: State<'_, ChannelRules>) -> Vec<ChannelRule> {
+    rules.list()
+}
+
+// Command handler that replaces the rule list; takes effect on the next
+// load or refresh of each playlist
+#[tauri::command]
+pub fn set_channel_rules(rules: State<'_, ChannelRules>, list: Vec<ChannelRule>) -> Result<Vec<ChannelRule>, String> {
+    rules.replace(list)?;
+    Ok(rules.list())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    fn rule(field: RuleField, pattern: &str, action: RuleAction) -> ChannelRule {
+        ChannelRule {
+            playlist_id: None,
+            field,
+            pattern: pattern.to_string(),
+            case_sensitive: false,
+            action,
+            enabled: true,
+        }
+    }
+
+    fn entry(name: &str, group: &str) -> ChannelEntry {
+        ChannelEntry {
+            name: name.to_string(),
+            url: format!("http://example.com/{}.ts", name.len()),
+            group_title: Some(group.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_apply_rules_in_order() {
+        // Test that actions use captures and later rules see earlier changes
+        let rules = compile_all(vec![
+            rule(RuleField::Group, r"^\|(\w+)\|\s*(.*)$", RuleAction::SetGroup { group: "$1 / $2".to_string() }),
+            rule(RuleField::Group, "^uk / sports", RuleAction::SetNumber { number: 500 }),
+            rule(RuleField::Name, r"\s*\[adult\]", RuleAction::Hide),
+            rule(RuleField::Name, r"^(.*?)\s+FHD$", RuleAction::Rename { name: "$1 HD".to_string() }),
+        ])
+        .unwrap();
+        let mut entries = vec![entry("Sky Sports FHD", "|UK| Sports"), entry("Late [ADULT]", "Other")];
+        apply_rules(&rules, "p", &mut entries);
+
+        assert_eq!(entries[0].group_title.as_deref(), Some("UK / Sports"));
+        assert_eq!(entries[0].name, "Sky Sports HD");
+        assert_eq!(entries[0].attributes.get(NUMBER_ATTRIBUTE).map(String::as_str), Some("500"));
+        assert!(!entries[0].hidden);
+        assert!(entries[1].hidden);
+        assert_eq!(entries[1].group_title.as_deref(), Some("Other"));
+    }
+
+    #[test]
+    fn test_rules_scoped_and_disabled() {
+        // Test that playlist-scoped and disabled rules are skipped
+        let mut scoped = rule(RuleField::Name, ".", RuleAction::Hide);
+        scoped.playlist_id = Some("other".to_string());
+        let mut disabled = rule(RuleField::Name, ".", RuleAction::Hide);
+        disabled.enabled = false;
+        let rules = compile_all(vec![scoped, disabled]).unwrap();
+
+        let mut entries = vec![entry("News", "News")];
+        apply_rules(&rules, "p", &mut entries);
+        assert!(!entries[0].hidden);
+        apply_rules(&rules, "other", &mut entries);
+        assert!(entries[0].hidden);
+    }
+
+    #[test]
+    fn test_rules_persist_and_validate() {
+        // Test that invalid patterns are rejected and valid rules survive reopening
+        let dir = temp_dir("channel-rules");
+        let rules = ChannelRules::open(&dir);
+        let error = rules
+            .replace(vec![rule(RuleField::Url, "(", RuleAction::Hide)])
+            .unwrap_err();
+        assert!(error.starts_with("Rule 1:"));
+
+        let list = vec![rule(RuleField::Url, r"\.mp4$", RuleAction::SetGroup { group: "VOD".to_string() })];
+        rules.replace(list.clone()).unwrap();
+        assert_eq!(ChannelRules::open(&dir).list(), list);
+
+        let json = serde_json::to_string(&list[0]).unwrap();
+        assert!(json.contains(r#""action":"setGroup","group":"VOD""#));
+    }
+}
@@ -0,0 +1,174 @@
+// CSV import/export of channel lists for editing in spreadsheets
+//
+// Column layout (a header row is written on export and optional on import;
+// with a header the columns may come in any order):
+//
+//   name,url,group,tvg-id,logo,number
+//
+// `name` and `url` are required; `number` is the channel number (tvg-chno).
+use std::path::Path;
+
+use super::{ChannelEntry, NUMBER_ATTRIBUTE};
+
+pub const COLUMNS: [&str; 6] = ["name", "url", "group", "tvg-id", "logo", "number"];
+
+pub fn write(path: &Path, entries: &[ChannelEntry]) -> Result<(), String> {
+    let mut writer = csv::Writer::from_path(path).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    write_records(&mut writer, entries)?;
+    writer.flush().map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+fn write_records<W: std::io::Write>(writer: &mut csv::Writer<W>, entries: &[ChannelEntry]) -> Result<(), String> {
+    let error = |e: csv::Error| format!("Failed to write CSV: {}", e);
+    writer.write_record(COLUMNS).map_err(error)?;
+    for entry in entries {
+        let text = |value: &Option<String>| value.clone().unwrap_or_default();
+        writer
+            .write_record([
+                entry.name.clone(),
+                entry.url.clone(),
+                text(&entry.group_title),
+                text(&entry.tvg_id),
+                text(&entry.tvg_logo),
+                entry.attributes.get(NUMBER_ATTRIBUTE).cloned().unwrap_or_default(),
+            ])
+            .map_err(error)?;
+    }
+    Ok(())
+}
+
+pub fn read(path: &Path) -> Result<Vec<ChannelEntry>, String> {
+    let reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_path(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    read_records(reader)
+}
+
+fn read_records<R: std::io::Read>(mut reader: csv::Reader<R>) -> Result<Vec<ChannelEntry>, String> {
+    // Position of each known column, by default the documented order
+    let mut positions: Vec<Option<usize>> = (0..COLUMNS.len()).map(Some).collect();
+    let mut entries = Vec::new();
+
+    for (line, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| format!("Invalid CSV: {}", e))?;
+        if line == 0 && is_header(&record) {
+            positions = COLUMNS
+                .iter()
+                .map(|column| record.iter().position(|field| normalize_column(field) == *column))
+                .collect();
+            if positions[0].is_none() || positions[1].is_none() {
+                return Err("CSV header must contain 'name' and 'url' columns".to_string());
+            }
+            continue;
+        }
+
+        let field = |column: usize| {
+            positions[column]
+                .and_then(|index| record.get(index))
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+        let Some(url) = field(1) else {
+            // Blank rows and rows without a stream are skipped
+            continue;
+        };
+
+        let mut entry = ChannelEntry {
+            name: field(0).unwrap_or_else(|| url.clone()),
+            url,
+            group_title: field(2),
+            tvg_id: field(3),
+            tvg_logo: field(4),
+            ..Default::default()
+        };
+        if let Some(number) = field(5) {
+            entry.attributes.insert(NUMBER_ATTRIBUTE.to_string(), number);
+        }
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+// Spreadsheets often change case or use "tvg_id" / "Group Title"
+fn normalize_column(field: &str) -> String {
+    let column = field.trim().trim_start_matches('\u{feff}').to_ascii_lowercase().replace(['_', ' '], "-");
+    match column.as_str() {
+        "group-title" => "group".to_string(),
+        "tvg-logo" => "logo".to_string(),
+        "tvg-chno" | "chno" | "channel-number" => "number".to_string(),
+        _ => column,
+    }
+}
+
+fn is_header(record: &csv::StringRecord) -> bool {
+    record.iter().any(|field| {
+        let column = normalize_column(field);
+        column == "name" || column == "url"
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> Result<Vec<ChannelEntry>, String> {
+        let reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_reader(content.as_bytes());
+        read_records(reader)
+    }
+
+    fn entry() -> ChannelEntry {
+        let mut entry = ChannelEntry {
+            name: "News, HD".to_string(),
+            url: "http://a/news.ts".to_string(),
+            group_title: Some("News".to_string()),
+            tvg_id: Some("news.uk".to_string()),
+            tvg_logo: Some("http://logo/news.png".to_string()),
+            ..Default::default()
+        };
+        entry.attributes.insert(NUMBER_ATTRIBUTE.to_string(), "101".to_string());
+        entry
+    }
+
+    #[test]
+    fn test_csv_roundtrip() {
+        // Test that exported CSV imports back to the same entries
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        write_records(&mut writer, &[entry()]).unwrap();
+        let content = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+
+        assert!(content.starts_with("name,url,group,tvg-id,logo,number\n"));
+        assert_eq!(parse(&content).unwrap(), vec![entry()]);
+    }
+
+    #[test]
+    fn test_csv_header_in_any_order() {
+        // Test that header names map columns regardless of order and spelling
+        let content = "URL,Tvg_ID,Name\nhttp://a/1.ts,one.uk,One\n,,\n";
+        let entries = parse(content).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "One");
+        assert_eq!(entries[0].tvg_id.as_deref(), Some("one.uk"));
+    }
+
+    #[test]
+    fn test_csv_without_header() {
+        // Test that header-less files use the documented column order
+        let entries = parse("One,http://a/1.ts,Group\nhttp-only,\n").unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].group_title.as_deref(), Some("Group"));
+    }
+
+    #[test]
+    fn test_csv_header_requires_url() {
+        // Test that a header without a url column is rejected
+        assert!(parse("name,group\nOne,News\n").is_err());
+    }
+}
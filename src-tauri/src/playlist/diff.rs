@@ -0,0 +1,168 @@
+// Channel-level differences between two versions of a playlist
+use std::collections::{HashMap, VecDeque};
+
+use serde::Serialize;
+
+use super::ChannelEntry;
+use crate::unix_now;
+
+// A channel that kept its stream (or EPG id) but changed its name
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelRename {
+    pub old_name: String,
+    pub new_name: String,
+    pub url: String,
+}
+
+// What changed between the previous and the current download
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistDiff {
+    pub playlist_id: String,
+    pub added: Vec<ChannelEntry>,
+    pub removed: Vec<ChannelEntry>,
+    pub renamed: Vec<ChannelRename>,
+    // Unix seconds when the new version was parsed
+    pub computed_at: i64,
+}
+
+// Match channels by stream URL first, then by tvg-id so providers that
+// rotate tokens in their URLs don't show up as a full remove + add
+pub fn diff_entries(playlist_id: &str, old: &[ChannelEntry], new: &[ChannelEntry]) -> PlaylistDiff {
+    let mut renamed = Vec::new();
+
+    let mut old_by_url: HashMap<&str, VecDeque<usize>> = HashMap::new();
+    for (index, entry) in old.iter().enumerate() {
+        old_by_url.entry(entry.url.as_str()).or_default().push_back(index);
+    }
+
+    let mut matched_old = vec![false; old.len()];
+    let mut unmatched_new = Vec::new();
+    for entry in new {
+        match old_by_url.get_mut(entry.url.as_str()).and_then(VecDeque::pop_front) {
+            Some(index) => {
+                matched_old[index] = true;
+                if old[index].name != entry.name {
+                    renamed.push(rename(&old[index], entry));
+                }
+            }
+            None => unmatched_new.push(entry),
+        }
+    }
+
+    let mut old_by_tvg_id: HashMap<&str, VecDeque<usize>> = HashMap::new();
+    for (index, entry) in old.iter().enumerate() {
+        if matched_old[index] {
+            continue;
+        }
+        if let Some(tvg_id) = entry.tvg_id.as_deref().filter(|id| !id.is_empty()) {
+            old_by_tvg_id.entry(tvg_id).or_default().push_back(index);
+        }
+    }
+
+    let mut added = Vec::new();
+    for entry in unmatched_new {
+        let index = entry
+            .tvg_id
+            .as_deref()
+            .and_then(|id| old_by_tvg_id.get_mut(id))
+            .and_then(VecDeque::pop_front);
+        match index {
+            Some(index) => {
+                matched_old[index] = true;
+                if old[index].name != entry.name {
+                    renamed.push(rename(&old[index], entry));
+                }
+            }
+            None => added.push(entry.clone()),
+        }
+    }
+
+    let removed = old
+        .iter()
+        .zip(matched_old)
+        .filter(|(_, matched)| !matched)
+        .map(|(entry, _)| entry.clone())
+        .collect();
+
+    PlaylistDiff {
+        playlist_id: playlist_id.to_string(),
+        added,
+        removed,
+        renamed,
+        computed_at: unix_now(),
+    }
+}
+
+fn rename(old: &ChannelEntry, new: &ChannelEntry) -> ChannelRename {
+    ChannelRename {
+        old_name: old.name.clone(),
+        new_name: new.name.clone(),
+        url: new.url.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, url: &str, tvg_id: Option<&str>) -> ChannelEntry {
+        ChannelEntry {
+            name: name.to_string(),
+            url: url.to_string(),
+            tvg_id: tvg_id.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_diff_added_removed_renamed() {
+        // Test that URL matches detect renames and the rest is added/removed
+        let old = vec![
+            entry("News", "http://a/1", None),
+            entry("Sport", "http://a/2", None),
+            entry("Movies", "http://a/3", None),
+        ];
+        let new = vec![
+            entry("News HD", "http://a/1", None),
+            entry("Movies", "http://a/3", None),
+            entry("Kids", "http://a/4", None),
+        ];
+
+        let diff = diff_entries("p", &old, &new);
+        assert_eq!(diff.added, vec![entry("Kids", "http://a/4", None)]);
+        assert_eq!(diff.removed, vec![entry("Sport", "http://a/2", None)]);
+        assert_eq!(
+            diff.renamed,
+            vec![ChannelRename {
+                old_name: "News".to_string(),
+                new_name: "News HD".to_string(),
+                url: "http://a/1".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_diff_matches_rotated_urls_by_tvg_id() {
+        // Test that a changed URL with the same tvg-id is not reported as churn
+        let old = vec![entry("News", "http://a/1?token=old", Some("news.uk"))];
+        let new = vec![entry("News", "http://a/1?token=new", Some("news.uk"))];
+
+        let diff = diff_entries("p", &old, &new);
+        assert!(diff.added.is_empty());
+        assert!(diff.removed.is_empty());
+        assert!(diff.renamed.is_empty());
+    }
+
+    #[test]
+    fn test_diff_handles_duplicate_urls() {
+        // Test that duplicate URLs are matched one-to-one
+        let old = vec![entry("A", "http://a/1", None), entry("B", "http://a/1", None)];
+        let new = vec![entry("A", "http://a/1", None)];
+
+        let diff = diff_entries("p", &old, &new);
+        assert_eq!(diff.removed, vec![entry("B", "http://a/1", None)]);
+        assert!(diff.added.is_empty());
+    }
+}
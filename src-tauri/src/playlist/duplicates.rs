@@ -0,0 +1,310 @@
+// Probable duplicate channels within and across playlists, found by stream
+// URL and by fuzzy (Jaro-Winkler) name similarity. Names only match when
+// their numbers do, so "Sky Sports 1" and "Sky Sports 2" stay apart
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use super::merge::normalize_name;
+use super::ChannelEntry;
+
+// Names closer than this are considered the same channel by default
+pub const DEFAULT_THRESHOLD: f64 = 0.92;
+
+// Each name is compared with this many following names in sorted order;
+// Jaro-Winkler favours shared prefixes, so near matches sort close together
+const WINDOW: usize = 8;
+
+// One channel flagged as part of a duplicate group
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateMember {
+    pub playlist_id: String,
+    // Position of the entry in its playlist
+    pub index: usize,
+    pub name: String,
+    pub url: String,
+}
+
+// Channels that are probably the same
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateGroup {
+    pub members: Vec<DuplicateMember>,
+    // Whether some members share a stream URL
+    pub same_url: bool,
+    // Lowest name similarity that linked members, 1.0 for exact matches
+    pub similarity: f64,
+}
+
+// Jaro-Winkler similarity in 0.0..=1.0
+pub fn jaro_winkler(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+
+    let range = (a.len().max(b.len()) / 2).saturating_sub(1);
+    let mut a_matched = vec![false; a.len()];
+    let mut b_matched = vec![false; b.len()];
+    let mut matches = 0usize;
+    for (i, ca) in a.iter().enumerate() {
+        let start = i.saturating_sub(range);
+        let end = (i + range + 1).min(b.len());
+        for j in start..end {
+            if !b_matched[j] && b[j] == *ca {
+                a_matched[i] = true;
+                b_matched[j] = true;
+                matches += 1;
+                break;
+            }
+        }
+    }
+    if matches == 0 {
+        return 0.0;
+    }
+
+    let mut transpositions = 0usize;
+    let mut j = 0;
+    for (i, ca) in a.iter().enumerate() {
+        if !a_matched[i] {
+            continue;
+        }
+        while !b_matched[j] {
+            j += 1;
+        }
+        if *ca != b[j] {
+            transpositions += 1;
+        }
+        j += 1;
+    }
+
+    let m = matches as f64;
+    let jaro = (m / a.len() as f64 + m / b.len() as f64 + (m - transpositions as f64 / 2.0) / m) / 3.0;
+    let prefix = a.iter().zip(&b).take(4).take_while(|(x, y)| x == y).count();
+    jaro + prefix as f64 * 0.1 * (1.0 - jaro)
+}
+
+// The digit runs of a normalized name, "skysports1hd" -> ["1"]
+fn numbers(name: &str) -> Vec<&str> {
+    name.split(|c: char| !c.is_ascii_digit()).filter(|run| !run.is_empty()).collect()
+}
+
+// Similarity of two normalized names, 0.0 unless their numbers are the same
+fn similarity(a: &str, b: &str) -> f64 {
+    if a == b {
+        1.0
+    } else if numbers(a) != numbers(b) {
+        0.0
+    } else {
+        jaro_winkler(a, b)
+    }
+}
+
+// Union-find over all entries, remembering why sets were joined and the
+// first member, which names of later members are compared with
+struct Groups {
+    parent: Vec<usize>,
+    same_url: Vec<bool>,
+    similarity: Vec<f64>,
+    first: Vec<usize>,
+}
+
+impl Groups {
+    fn new(len: usize) -> Self {
+        Self {
+            parent: (0..len).collect(),
+            same_url: vec![false; len],
+            similarity: vec![1.0; len],
+            first: (0..len).collect(),
+        }
+    }
+
+    fn first(&mut self, i: usize) -> usize {
+        let root = self.find(i);
+        self.first[root]
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    fn join(&mut self, a: usize, b: usize, same_url: bool, similarity: f64) {
+        let (a, root) = (self.find(a), self.find(b));
+        if a != root {
+            self.parent[a] = root;
+            self.first[root] = self.first[a];
+            self.same_url[root] |= self.same_url[a];
+            self.similarity[root] = self.similarity[root].min(self.similarity[a]);
+        }
+        self.same_url[root] |= same_url;
+        self.similarity[root] = self.similarity[root].min(similarity);
+    }
+}
+
+// Group probable duplicates, largest groups first
+pub fn find(playlists: &[(String, Arc<Vec<ChannelEntry>>)], threshold: f64) -> Vec<DuplicateGroup> {
+    let entries: Vec<(&str, usize, &ChannelEntry)> = playlists
+        .iter()
+        .flat_map(|(id, entries)| entries.iter().enumerate().map(move |(index, entry)| (id.as_str(), index, entry)))
+        .collect();
+    let mut groups = Groups::new(entries.len());
+
+    let mut by_url: HashMap<&str, usize> = HashMap::new();
+    for (i, (_, _, entry)) in entries.iter().enumerate() {
+        let url = entry.url.trim();
+        if url.is_empty() {
+            continue;
+        }
+        match by_url.get(url) {
+            Some(&first) => groups.join(first, i, true, 1.0),
+            None => {
+                by_url.insert(url, i);
+            }
+        }
+    }
+
+    let normalized: Vec<String> = entries.iter().map(|(_, _, entry)| normalize_name(&entry.name)).collect();
+    let mut names: Vec<(&str, usize)> = normalized
+        .iter()
+        .enumerate()
+        .map(|(i, name)| (name.as_str(), i))
+        .filter(|(name, _)| !name.is_empty())
+        .collect();
+    names.sort();
+    for (position, (name, i)) in names.iter().enumerate() {
+        for (other, j) in names.iter().skip(position + 1).take(WINDOW) {
+            let pair = similarity(name, other);
+            if pair < threshold {
+                continue;
+            }
+            // Against the groups' first members too, so a chain of close
+            // names doesn't pull in ones far from where it started
+            let first = Some(normalized[groups.first(*i)].as_str()).filter(|first| !first.is_empty());
+            let other_first = Some(normalized[groups.first(*j)].as_str()).filter(|first| !first.is_empty());
+            let linked = similarity(first.unwrap_or(name), other_first.unwrap_or(other));
+            if linked >= threshold {
+                groups.join(*i, *j, false, pair.min(linked));
+            }
+        }
+    }
+
+    let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..entries.len() {
+        let root = groups.find(i);
+        members.entry(root).or_default().push(i);
+    }
+    let mut result: Vec<DuplicateGroup> = members
+        .into_iter()
+        .filter(|(_, members)| members.len() > 1)
+        .map(|(root, mut indices)| {
+            indices.sort_unstable();
+            DuplicateGroup {
+                members: indices
+                    .into_iter()
+                    .map(|i| {
+                        let (playlist_id, index, entry) = entries[i];
+                        DuplicateMember {
+                            playlist_id: playlist_id.to_string(),
+                            index,
+                            name: entry.name.clone(),
+                            url: entry.url.clone(),
+                        }
+                    })
+                    .collect(),
+                same_url: groups.same_url[root],
+                similarity: groups.similarity[root],
+            }
+        })
+        .collect();
+    result.sort_by(|a, b| {
+        b.members
+            .len()
+            .cmp(&a.members.len())
+            .then_with(|| a.members[0].name.cmp(&b.members[0].name))
+    });
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playlist(id: &str, channels: &[(&str, &str)]) -> (String, Arc<Vec<ChannelEntry>>) {
+        let entries = channels
+            .iter()
+            .map(|(name, url)| ChannelEntry {
+                name: name.to_string(),
+                url: url.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        (id.to_string(), Arc::new(entries))
+    }
+
+    #[test]
+    fn test_jaro_winkler() {
+        // Test the similarity against known reference values
+        assert!((jaro_winkler("martha", "marhta") - 0.9611).abs() < 0.001);
+        assert!((jaro_winkler("dixon", "dicksonx") - 0.8133).abs() < 0.001);
+        assert_eq!(jaro_winkler("abc", "abc"), 1.0);
+        assert_eq!(jaro_winkler("abc", ""), 0.0);
+    }
+
+    #[test]
+    fn test_find_groups_by_url_and_name() {
+        // Test that shared URLs and similar names group across playlists
+        let playlists = vec![
+            playlist("a", &[("BBC One HD", "http://a/1"), ("CNN", "http://a/2"), ("Film4", "http://a/3")]),
+            playlist("b", &[("BBC One", "http://b/1"), ("CNN International", "http://a/2")]),
+        ];
+        let groups = find(&playlists, DEFAULT_THRESHOLD);
+
+        assert_eq!(groups.len(), 2);
+        let names = |group: &DuplicateGroup| group.members.iter().map(|m| m.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(&groups[0]), vec!["BBC One HD", "BBC One"]);
+        assert!(!groups[0].same_url);
+        assert!(groups[0].similarity >= DEFAULT_THRESHOLD && groups[0].similarity < 1.0);
+        assert_eq!(names(&groups[1]), vec!["CNN", "CNN International"]);
+        assert!(groups[1].same_url);
+        assert_eq!(groups[1].members[1].playlist_id, "b");
+    }
+
+    #[test]
+    fn test_find_respects_threshold() {
+        // Test that a strict threshold only keeps exact name matches
+        let playlists = vec![playlist("a", &[("Sky News", "http://1"), ("Sky Newz", "http://2"), ("sky-news", "http://3")])];
+        let groups = find(&playlists, 1.0);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].members.len(), 2);
+        assert_eq!(groups[0].similarity, 1.0);
+    }
+
+    #[test]
+    fn test_numbered_channels_stay_apart() {
+        // Test that names differing only in their number never group, and chains don't join whole families
+        let playlists = vec![
+            playlist("a", &[("Sky Sports 1", "http://1"), ("Sky Sports 2", "http://2"), ("Sky Sports 3", "http://3")]),
+            playlist("b", &[("ESPN", "http://4"), ("ESPN2", "http://5"), ("Sky Sports 1 HD", "http://6")]),
+        ];
+        let groups = find(&playlists, DEFAULT_THRESHOLD);
+        assert_eq!(groups.len(), 1);
+        let names: Vec<&str> = groups[0].members.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, vec!["Sky Sports 1", "Sky Sports 1 HD"]);
+
+        let chain = [("abcdefgh", "http://1"), ("abcdefgx", "http://2"), ("abcdefyx", "http://3")];
+        let chain = vec![playlist("a", &chain)];
+        let groups = find(&chain, 0.94);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].members.len(), 2);
+    }
+}
@@ -0,0 +1,362 @@
+// Enigma2 bouquet (userbouquet.*.tv) and lamedb import
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use percent_encoding::percent_decode_str;
+
+use super::ChannelEntry;
+
+// Service reference flag marking a bouquet separator ("--- News ---")
+const FLAG_MARKER: u32 = 64;
+
+// Service reference flags marking a link to another bouquet
+const FLAG_DIRECTORY: u32 = 7;
+
+// Identifies a DVB service: SID, TSID, ONID and DVB namespace
+type ServiceKey = (u32, u32, u32, u32);
+
+// Service names from an Enigma2 lamedb (version 4 or 5)
+#[derive(Debug, Default)]
+pub struct Lamedb {
+    names: HashMap<ServiceKey, String>,
+}
+
+impl Lamedb {
+    pub fn parse(content: &str) -> Self {
+        let mut names = HashMap::new();
+        let mut lines = content.lines();
+
+        while let Some(line) = lines.next() {
+            let line = line.trim();
+            // lamedb5: s:sid:ns:tsid:onid:type:number,"Name",p:Provider
+            if let Some(rest) = line.strip_prefix("s:") {
+                let Some((key, tail)) = rest.split_once(',') else {
+                    continue;
+                };
+                let name = tail.trim_start_matches('"').split('"').next().unwrap_or("");
+                if let Some(key) = lamedb_key(key) {
+                    names.insert(key, name.to_string());
+                }
+                continue;
+            }
+            // lamedb4: "services" section with a key line followed by the name
+            if line == "services" {
+                while let Some(key_line) = lines.next() {
+                    if key_line.trim() == "end" {
+                        break;
+                    }
+                    let name = lines.next().unwrap_or("").trim();
+                    // Provider line
+                    lines.next();
+                    if let Some(key) = lamedb_key(key_line.trim()) {
+                        names.insert(key, name.to_string());
+                    }
+                }
+            }
+        }
+
+        Self { names }
+    }
+
+    fn name(&self, service: &ServiceRef) -> Option<&str> {
+        self.names
+            .get(&(service.sid, service.tsid, service.onid, service.namespace))
+            .map(String::as_str)
+            .filter(|name| !name.is_empty())
+    }
+}
+
+// Parse "sid:namespace:tsid:onid:..." (hex) into a service key
+fn lamedb_key(line: &str) -> Option<ServiceKey> {
+    let mut fields = line.split(':').map(|f| u32::from_str_radix(f, 16));
+    let sid = fields.next()?.ok()?;
+    let namespace = fields.next()?.ok()?;
+    let tsid = fields.next()?.ok()?;
+    let onid = fields.next()?.ok()?;
+    Some((sid, tsid, onid, namespace))
+}
+
+// A parsed "1:0:1:445D:453:1:C00000:0:0:0:" style service reference; type
+// and flags are decimal, the remaining ids hexadecimal
+#[derive(Debug, PartialEq)]
+struct ServiceRef {
+    kind: u32,
+    flags: u32,
+    sid: u32,
+    tsid: u32,
+    onid: u32,
+    namespace: u32,
+    // Stream URL for IPTV references (4097, 5001, 5002, ...)
+    url: Option<String>,
+    // Name embedded after the URL
+    name: Option<String>,
+    // The reference without URL and name, as used by the box's own streaming
+    dvb_ref: String,
+}
+
+fn parse_service_ref(reference: &str) -> Option<ServiceRef> {
+    let fields: Vec<&str> = reference.trim().splitn(12, ':').collect();
+    if fields.len() < 10 {
+        return None;
+    }
+    let hex = |index: usize| u32::from_str_radix(fields.get(index).copied().unwrap_or("0"), 16).ok();
+    let kind = fields[0].parse().ok()?;
+    let decode = |index: usize| {
+        fields
+            .get(index)
+            .map(|f| percent_decode_str(f).decode_utf8_lossy().trim().to_string())
+            .filter(|f| !f.is_empty())
+    };
+
+    Some(ServiceRef {
+        kind,
+        flags: fields[1].parse().ok()?,
+        sid: hex(3)?,
+        tsid: hex(4)?,
+        onid: hex(5)?,
+        namespace: hex(6)?,
+        url: decode(10),
+        name: decode(11),
+        dvb_ref: format!("{}:", fields[..10].join(":")),
+    })
+}
+
+// Bouquet file names listed in bouquets.tv, in display order
+pub fn bouquet_files(bouquets_tv: &str) -> Vec<String> {
+    bouquets_tv
+        .lines()
+        .filter_map(|line| {
+            let (_, rest) = line.split_once("FROM BOUQUET \"")?;
+            rest.split('"').next().map(str::to_string)
+        })
+        .collect()
+}
+
+// What the last #SERVICE line produced; a following #DESCRIPTION names it
+enum Previous {
+    Entry,
+    Marker,
+    Other,
+}
+
+// Parse one userbouquet into entries grouped by the bouquet name (and any
+// separators inside it). DVB services become streams from `box_url`, the
+// receiver's streaming port (e.g. http://192.168.1.10:8001); without it
+// only IPTV references can be played and DVB services are skipped.
+pub fn parse_bouquet(content: &str, lamedb: Option<&Lamedb>, box_url: Option<&str>) -> Vec<ChannelEntry> {
+    let mut bouquet_name = String::new();
+    let mut section: Option<String> = None;
+    let mut entries: Vec<ChannelEntry> = Vec::new();
+    let mut previous = Previous::Other;
+
+    for line in content.lines() {
+        let line = line.trim_start_matches('\u{feff}').trim();
+        if let Some(name) = line.strip_prefix("#NAME ") {
+            bouquet_name = name.trim().to_string();
+            continue;
+        }
+        if let Some(description) = line.strip_prefix("#DESCRIPTION") {
+            let description = description.trim_start_matches(':').trim();
+            if !description.is_empty() {
+                match previous {
+                    Previous::Entry => {
+                        if let Some(entry) = entries.last_mut() {
+                            entry.name = description.to_string();
+                        }
+                    }
+                    Previous::Marker => section = Some(description.to_string()),
+                    Previous::Other => {}
+                }
+            }
+            previous = Previous::Other;
+            continue;
+        }
+        let Some(reference) = line.strip_prefix("#SERVICE") else {
+            continue;
+        };
+        previous = Previous::Other;
+        let Some(service) = parse_service_ref(reference.trim_start_matches(':')) else {
+            continue;
+        };
+
+        if service.flags & FLAG_MARKER != 0 {
+            section = service.name;
+            previous = Previous::Marker;
+            continue;
+        }
+        if service.flags & FLAG_DIRECTORY == FLAG_DIRECTORY {
+            continue;
+        }
+
+        let url = match (&service.url, box_url) {
+            (Some(url), _) => url.clone(),
+            (None, Some(box_url)) => format!("{}/{}", box_url.trim_end_matches('/'), service.dvb_ref),
+            (None, None) => continue,
+        };
+        let name = service
+            .name
+            .clone()
+            .or_else(|| lamedb.and_then(|db| db.name(&service)).map(str::to_string))
+            .unwrap_or_else(|| url.clone());
+
+        let mut entry = ChannelEntry {
+            name,
+            url,
+            group_title: group_name(&bouquet_name, section.as_deref()),
+            ..Default::default()
+        };
+        // Kept so EPG sources keyed by service reference can be matched
+        entry.attributes.insert("service-ref".to_string(), service.dvb_ref.clone());
+        if service.kind == 1 {
+            entry.tvg_id = Some(service.dvb_ref);
+        }
+        entries.push(entry);
+        previous = Previous::Entry;
+    }
+
+    entries
+}
+
+fn group_name(bouquet: &str, section: Option<&str>) -> Option<String> {
+    match (bouquet.is_empty(), section.filter(|s| !s.is_empty())) {
+        (true, None) => None,
+        (true, Some(section)) => Some(section.to_string()),
+        (false, None) => Some(bouquet.to_string()),
+        (false, Some(section)) => Some(format!("{} / {}", bouquet, section)),
+    }
+}
+
+// Import a single bouquet file, or a copied /etc/enigma2 directory where
+// bouquets.tv decides the order and lamedb provides service names
+pub fn import(path: &Path, box_url: Option<&str>) -> Result<Vec<ChannelEntry>, String> {
+    let read = |path: &Path| {
+        fs::read(path)
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+    };
+
+    let (dir, files) = if path.is_dir() {
+        let index = path.join("bouquets.tv");
+        let files = if index.exists() {
+            bouquet_files(&read(&index)?)
+        } else {
+            let mut files: Vec<String> = fs::read_dir(path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+                .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                .filter(|name| name.starts_with("userbouquet.") && name.ends_with(".tv"))
+                .collect();
+            files.sort();
+            files
+        };
+        (path.to_path_buf(), files)
+    } else {
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let file = path.file_name().map(|f| f.to_string_lossy().into_owned()).unwrap_or_default();
+        (dir, vec![file])
+    };
+
+    let lamedb_path = [dir.join("lamedb5"), dir.join("lamedb")].into_iter().find(|p| p.exists());
+    let lamedb = match lamedb_path {
+        Some(path) => Some(Lamedb::parse(&read(&path)?)),
+        None => None,
+    };
+
+    let mut entries = Vec::new();
+    for file in files {
+        let bouquet = dir.join(&file);
+        match read(&bouquet) {
+            Ok(content) => entries.extend(parse_bouquet(&content, lamedb.as_ref(), box_url)),
+            // A stale bouquets.tv may list files that no longer exist
+            Err(e) => log::warn!("Skipping bouquet: {}", e),
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    const BOUQUET: &str = "#NAME Favourites (TV)\n\
+#SERVICE 1:64:0:0:0:0:0:0:0:0::News\n\
+#SERVICE 1:0:19:445D:453:1:C00000:0:0:0:\n\
+#SERVICE 4097:0:1:0:0:0:0:0:0:0:http%3a//example.com/cnn.m3u8:CNN International\n\
+#SERVICE 1:64:1:0:0:0:0:0:0:0:\n\
+#DESCRIPTION Sport\n\
+#SERVICE 1:0:19:1234:453:1:C00000:0:0:0:\n\
+#DESCRIPTION Sport One\n";
+
+    const LAMEDB4: &str = "eDVB services /4/\ntransponders\nend\nservices\n445d:00c00000:0453:0001:25:0\nBBC One HD\np:BBC\nend\n";
+
+    #[test]
+    fn test_parse_service_ref() {
+        // Test that DVB and IPTV references are split into their parts
+        let dvb = parse_service_ref("1:0:19:445D:453:1:C00000:0:0:0:").unwrap();
+        assert_eq!((dvb.sid, dvb.tsid, dvb.onid, dvb.namespace), (0x445d, 0x453, 1, 0xc00000));
+        assert_eq!(dvb.url, None);
+
+        let iptv = parse_service_ref("4097:0:1:0:0:0:0:0:0:0:http%3a//a/b.ts?x=1%3a2:Name: With Colon").unwrap();
+        assert_eq!(iptv.url.as_deref(), Some("http://a/b.ts?x=1:2"));
+        assert_eq!(iptv.name.as_deref(), Some("Name: With Colon"));
+        assert!(parse_service_ref("garbage").is_none());
+    }
+
+    #[test]
+    fn test_parse_lamedb_4_and_5() {
+        // Test that service names are keyed by SID/TSID/ONID/namespace
+        let service = parse_service_ref("1:0:19:445D:453:1:C00000:0:0:0:").unwrap();
+        assert_eq!(Lamedb::parse(LAMEDB4).name(&service), Some("BBC One HD"));
+
+        let lamedb5 = "eDVB services /5/\ns:445d:00c00000:0453:0001:25:0,\"BBC One HD\",p:BBC\n";
+        assert_eq!(Lamedb::parse(lamedb5).name(&service), Some("BBC One HD"));
+    }
+
+    #[test]
+    fn test_parse_bouquet_with_box_url() {
+        // Test that DVB services stream from the box and markers become groups
+        let lamedb = Lamedb::parse(LAMEDB4);
+        let entries = parse_bouquet(BOUQUET, Some(&lamedb), Some("http://box:8001/"));
+
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].name, "BBC One HD");
+        assert_eq!(entries[0].url, "http://box:8001/1:0:19:445D:453:1:C00000:0:0:0:");
+        assert_eq!(entries[0].group_title.as_deref(), Some("Favourites (TV) / News"));
+        assert_eq!(entries[1].name, "CNN International");
+        assert_eq!(entries[1].url, "http://example.com/cnn.m3u8");
+        assert_eq!(entries[2].name, "Sport One");
+        assert_eq!(entries[2].group_title.as_deref(), Some("Favourites (TV) / Sport"));
+    }
+
+    #[test]
+    fn test_parse_bouquet_without_box_url() {
+        // Test that only IPTV references are kept when no box is configured
+        let entries = parse_bouquet(BOUQUET, None, None);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "CNN International");
+    }
+
+    #[test]
+    fn test_import_directory() {
+        // Test that bouquets.tv order and lamedb names are used for a directory
+        let dir = temp_dir("enigma2");
+        fs::write(
+            dir.join("bouquets.tv"),
+            "#NAME Bouquets (TV)\n#SERVICE 1:7:1:0:0:0:0:0:0:0:FROM BOUQUET \"userbouquet.b.tv\" ORDER BY bouquet\n#SERVICE 1:7:1:0:0:0:0:0:0:0:FROM BOUQUET \"userbouquet.a.tv\" ORDER BY bouquet\n",
+        )
+        .unwrap();
+        fs::write(dir.join("userbouquet.a.tv"), "#NAME A\n#SERVICE 1:0:19:445D:453:1:C00000:0:0:0:\n").unwrap();
+        fs::write(
+            dir.join("userbouquet.b.tv"),
+            "#NAME B\n#SERVICE 4097:0:1:0:0:0:0:0:0:0:http%3a//x/1.ts:One\n",
+        )
+        .unwrap();
+        fs::write(dir.join("lamedb"), LAMEDB4).unwrap();
+
+        let entries = import(&dir, Some("http://box:8001")).unwrap();
+        let groups: Vec<_> = entries.iter().map(|e| e.group_title.clone().unwrap()).collect();
+        assert_eq!(groups, vec!["B", "A"]);
+        assert_eq!(entries[1].name, "BBC One HD");
+    }
+}
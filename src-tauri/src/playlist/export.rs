@@ -0,0 +1,142 @@
+// Write channel lists back out as M3U Plus or JSON
+use serde::Deserialize;
+
+use super::ChannelEntry;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    M3u,
+    Json,
+}
+
+// Render entries as an M3U Plus playlist our own parser reads back losslessly
+pub fn to_m3u(entries: &[ChannelEntry]) -> String {
+    let mut out = String::from("#EXTM3U\n");
+
+    for entry in entries {
+        let duration = match entry.duration {
+            Some(duration) => duration.to_string(),
+            None => "-1".to_string(),
+        };
+        out.push_str("#EXTINF:");
+        out.push_str(&duration);
+
+        let known = [
+            ("tvg-id", entry.tvg_id.clone()),
+            ("tvg-name", entry.tvg_name.clone()),
+            ("tvg-logo", entry.tvg_logo.clone()),
+            ("group-title", entry.group_title.clone()),
+            ("catchup", entry.catchup.clone()),
+            ("catchup-days", entry.catchup_days.map(|d| d.to_string())),
+            ("catchup-source", entry.catchup_source.clone()),
+            ("timeshift", entry.timeshift.map(|t| t.to_string())),
+        ];
+        let attributes = known
+            .iter()
+            .filter_map(|(key, value)| value.as_deref().map(|value| (*key, value)))
+            .chain(entry.attributes.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+        for (key, value) in attributes {
+            out.push_str(&format!(" {}=\"{}\"", key, attribute_value(value)));
+        }
+        out.push(',');
+        out.push_str(&single_line(&entry.name));
+        out.push('\n');
+
+        let http = &entry.http;
+        if let Some(user_agent) = &http.user_agent {
+            out.push_str(&format!("#EXTVLCOPT:http-user-agent={}\n", single_line(user_agent)));
+        }
+        if let Some(referrer) = &http.referrer {
+            out.push_str(&format!("#EXTVLCOPT:http-referrer={}\n", single_line(referrer)));
+        }
+        if !http.headers.is_empty() {
+            if let Ok(json) = serde_json::to_string(&http.headers) {
+                out.push_str(&format!("#EXTHTTP:{}\n", json));
+            }
+        }
+
+        out.push_str(&single_line(&entry.url));
+        out.push('\n');
+    }
+
+    out
+}
+
+pub fn to_json(entries: &[ChannelEntry]) -> Result<String, String> {
+    serde_json::to_string_pretty(entries).map_err(|e| format!("Failed to serialize playlist: {}", e))
+}
+
+// Attribute values are always double quoted, so inner quotes can't survive
+fn attribute_value(value: &str) -> String {
+    single_line(value).replace('"', "'")
+}
+
+fn single_line(value: &str) -> String {
+    value.replace(['\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::playlist::read_entries;
+    use std::collections::BTreeMap;
+
+    fn sample() -> Vec<ChannelEntry> {
+        let mut full = ChannelEntry {
+            name: "BBC One, HD".to_string(),
+            url: "http://example.com/bbc1.ts".to_string(),
+            tvg_id: Some("bbc1.uk".to_string()),
+            tvg_name: Some("BBC One".to_string()),
+            tvg_logo: Some("http://logo/bbc1.png".to_string()),
+            group_title: Some("UK, News".to_string()),
+            catchup: Some("default".to_string()),
+            catchup_days: Some(7),
+            catchup_source: Some("?utc={utc}".to_string()),
+            timeshift: Some(3),
+            attributes: BTreeMap::from([("tvg-chno".to_string(), "101".to_string())]),
+            ..Default::default()
+        };
+        full.http.set_header("User-Agent", "Player/1.0");
+        full.http.set_header("Referer", "http://example.com/");
+        full.http.set_header("Cookie", "a=b");
+
+        let movie = ChannelEntry {
+            name: "Movie".to_string(),
+            url: "http://example.com/movie.mp4".to_string(),
+            duration: Some(125.5),
+            ..Default::default()
+        };
+        vec![full, movie]
+    }
+
+    #[test]
+    fn test_m3u_roundtrip() {
+        // Test that exported M3U parses back to the same entries
+        let entries = sample();
+        let m3u = to_m3u(&entries);
+        let parsed = tauri::async_runtime::block_on(read_entries(m3u.as_bytes(), None, |_, _| {})).unwrap();
+        assert_eq!(parsed, entries);
+    }
+
+    #[test]
+    fn test_m3u_escapes_quotes_and_newlines() {
+        // Test that values can't break out of their attribute or line
+        let entries = vec![ChannelEntry {
+            name: "Bad\nName".to_string(),
+            url: "http://example.com/x.ts".to_string(),
+            group_title: Some("Say \"hi\"".to_string()),
+            ..Default::default()
+        }];
+        let m3u = to_m3u(&entries);
+        assert!(m3u.contains("group-title=\"Say 'hi'\",Bad Name\n"));
+    }
+
+    #[test]
+    fn test_json_roundtrip() {
+        // Test that the JSON export deserializes back to the entries
+        let entries = sample();
+        let parsed: Vec<ChannelEntry> = serde_json::from_str(&to_json(&entries).unwrap()).unwrap();
+        assert_eq!(parsed, entries);
+    }
+}
@@ -0,0 +1,74 @@
+// Playlist format detection and the interface shared by line-based parsers
+use super::ChannelEntry;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaylistFormat {
+    M3u,
+    Xspf,
+    Pls,
+}
+
+impl PlaylistFormat {
+    // Guess the format from a file name or URL path
+    pub fn from_extension(source: &str) -> Option<Self> {
+        let path = source.split(['?', '#']).next().unwrap_or(source);
+        let extension = path.rsplit_once('.')?.1.to_ascii_lowercase();
+        match extension.as_str() {
+            "m3u" | "m3u8" => Some(PlaylistFormat::M3u),
+            "xspf" => Some(PlaylistFormat::Xspf),
+            "pls" => Some(PlaylistFormat::Pls),
+            _ => None,
+        }
+    }
+
+    // Sniff the start of the content; the extension hint only decides
+    // when the content itself isn't conclusive
+    pub fn detect(head: &[u8], hint: Option<Self>) -> Self {
+        let head = head.strip_prefix(b"\xef\xbb\xbf").unwrap_or(head);
+        let start = head.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(head.len());
+        let head = &head[start..];
+
+        if head.starts_with(b"<") {
+            PlaylistFormat::Xspf
+        } else if head.len() >= 10 && head[..10].eq_ignore_ascii_case(b"[playlist]") {
+            PlaylistFormat::Pls
+        } else if head.starts_with(b"#EXTM3U") {
+            PlaylistFormat::M3u
+        } else {
+            hint.unwrap_or(PlaylistFormat::M3u)
+        }
+    }
+}
+
+// A parser fed one line at a time
+pub trait LineParser {
+    // Feed one line, returning an entry as soon as it is complete
+    fn push_line(&mut self, line: &str) -> Option<ChannelEntry>;
+
+    // Entries that can only be emitted once the whole input was read
+    fn finish(self) -> Vec<ChannelEntry>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_extension() {
+        // Test that the extension is read from the path, ignoring the query
+        assert_eq!(PlaylistFormat::from_extension("/tmp/list.XSPF"), Some(PlaylistFormat::Xspf));
+        assert_eq!(PlaylistFormat::from_extension("http://a/radio.pls?x=1.m3u"), Some(PlaylistFormat::Pls));
+        assert_eq!(PlaylistFormat::from_extension("http://a/get.php?type=m3u"), None);
+    }
+
+    #[test]
+    fn test_detect() {
+        // Test that content wins over the extension hint
+        let pls = Some(PlaylistFormat::Pls);
+        assert_eq!(PlaylistFormat::detect(b"\xef\xbb\xbf <?xml version", None), PlaylistFormat::Xspf);
+        assert_eq!(PlaylistFormat::detect(b"[Playlist]\nFile1=x", None), PlaylistFormat::Pls);
+        assert_eq!(PlaylistFormat::detect(b"#EXTM3U\n", pls), PlaylistFormat::M3u);
+        assert_eq!(PlaylistFormat::detect(b"File1=http://a", pls), PlaylistFormat::Pls);
+        assert_eq!(PlaylistFormat::detect(b"http://a/1.ts", None), PlaylistFormat::M3u);
+    }
+}
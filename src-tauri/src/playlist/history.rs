@@ -0,0 +1,133 @@
+// Gzip-compressed snapshots of the last few versions of each playlist, so a
+// broken update from a provider can be rolled back
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use super::{content_hash, ChannelEntry};
+use crate::storage;
+
+const INDEX_FILE: &str = "versions.json";
+
+// Versions kept per playlist, older ones are deleted
+pub const MAX_VERSIONS: usize = 10;
+
+// One stored version of a playlist
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistVersion {
+    pub version: u32,
+    // Unix seconds when the version was downloaded
+    pub saved_at: i64,
+    pub total: usize,
+    // Hash of the channel list, used to skip unchanged downloads
+    pub hash: String,
+}
+
+pub struct PlaylistHistory {
+    dir: PathBuf,
+}
+
+impl PlaylistHistory {
+    pub fn open(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn playlist_dir(&self, id: &str) -> PathBuf {
+        self.dir.join(id)
+    }
+
+    fn version_path(&self, id: &str, version: u32) -> PathBuf {
+        self.playlist_dir(id).join(format!("{}.json.gz", version))
+    }
+
+    // Stored versions, newest first
+    pub fn versions(&self, id: &str) -> Result<Vec<PlaylistVersion>, String> {
+        storage::read_json(&self.playlist_dir(id).join(INDEX_FILE))
+    }
+
+    // Store a new version unless it matches the newest one; returns the
+    // version that now holds these channels
+    pub fn record(&self, id: &str, entries: &[ChannelEntry], at: i64) -> Result<PlaylistVersion, String> {
+        let json = serde_json::to_vec(entries).map_err(|e| format!("Failed to serialize playlist: {}", e))?;
+        let hash = content_hash(&json);
+        let mut versions = self.versions(id)?;
+        if let Some(latest) = versions.first().filter(|latest| latest.hash == hash) {
+            return Ok(latest.clone());
+        }
+
+        let version = PlaylistVersion {
+            version: versions.first().map(|latest| latest.version + 1).unwrap_or(1),
+            saved_at: at,
+            total: entries.len(),
+            hash,
+        };
+        storage::write_json_gz(&self.version_path(id, version.version), &json)?;
+
+        versions.insert(0, version.clone());
+        for dropped in versions.drain(MAX_VERSIONS.min(versions.len())..) {
+            let _ = fs::remove_file(self.version_path(id, dropped.version));
+        }
+        storage::write_json(&self.playlist_dir(id).join(INDEX_FILE), &versions)?;
+        Ok(version)
+    }
+
+    pub fn load(&self, id: &str, version: u32) -> Result<Vec<ChannelEntry>, String> {
+        if !self.versions(id)?.iter().any(|v| v.version == version) {
+            return Err(format!("Version {} of playlist '{}' doesn't exist", version, id));
+        }
+        storage::read_json_gz(&self.version_path(id, version))
+    }
+
+    pub fn remove(&self, id: &str) {
+        let _ = fs::remove_dir_all(self.playlist_dir(id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    fn entries(names: &[&str]) -> Vec<ChannelEntry> {
+        names
+            .iter()
+            .map(|name| ChannelEntry {
+                name: name.to_string(),
+                url: format!("http://example.com/{}.ts", name),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_record_and_load() {
+        // Test that versions load back and unchanged lists aren't stored twice
+        let history = PlaylistHistory::open(temp_dir("history-record"));
+        assert_eq!(history.record("p", &entries(&["a"]), 10).unwrap().version, 1);
+        assert_eq!(history.record("p", &entries(&["a"]), 20).unwrap().version, 1);
+        assert_eq!(history.record("p", &entries(&["a", "b"]), 30).unwrap().version, 2);
+
+        let versions = history.versions("p").unwrap();
+        assert_eq!(versions.iter().map(|v| v.version).collect::<Vec<_>>(), vec![2, 1]);
+        assert_eq!(versions[1].saved_at, 10);
+        assert_eq!(history.load("p", 1).unwrap(), entries(&["a"]));
+        assert!(history.load("p", 3).is_err());
+    }
+
+    #[test]
+    fn test_record_prunes_old_versions() {
+        // Test that only the newest MAX_VERSIONS versions are kept
+        let history = PlaylistHistory::open(temp_dir("history-prune"));
+        for i in 0..MAX_VERSIONS + 2 {
+            history.record("p", &entries(&[&i.to_string()]), i as i64).unwrap();
+        }
+
+        let versions = history.versions("p").unwrap();
+        assert_eq!(versions.len(), MAX_VERSIONS);
+        assert_eq!(versions.last().unwrap().version, 3);
+        assert!(history.load("p", 2).is_err());
+        assert!(!history.version_path("p", 1).exists());
+    }
+}
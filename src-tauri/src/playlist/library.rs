@@ -0,0 +1,301 @@
+// Configured playlists and their last parsed channels, persisted on disk
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::ChannelEntry;
+use crate::http::{BasicAuth, HttpOptions};
+use crate::storage;
+
+const CONFIG_FILE: &str = "playlists.json";
+
+// A playlist the user added to the app
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistConfig {
+    pub id: String,
+    pub name: String,
+    pub source: String,
+    // Hours between automatic refreshes, None disables auto-refresh
+    #[serde(default)]
+    pub refresh_interval_hours: Option<u32>,
+    // Unix seconds of the last successful refresh
+    #[serde(default)]
+    pub last_refreshed: Option<i64>,
+    // Headers sent when fetching the playlist and streams on its server;
+    // channels elsewhere only get the user agent and referrer
+    #[serde(default, skip_serializing_if = "HttpOptions::is_empty")]
+    pub http: HttpOptions,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<BasicAuth>,
+}
+
+impl PlaylistConfig {
+    // Options for requests to this playlist's server, None when nothing is set
+    pub fn request_options(&self) -> Option<HttpOptions> {
+        let mut options = self.http.clone();
+        if let Some(auth) = &self.auth {
+            options.headers.insert("Authorization".to_string(), auth.header_value());
+        }
+        (!options.is_empty()).then_some(options)
+    }
+
+    // Whether a URL is on the server the playlist is downloaded from
+    fn serves(&self, url: &Url) -> bool {
+        Url::parse(&self.source).is_ok_and(|source| source.origin() == url.origin())
+    }
+
+    // Whether the playlist should be refreshed at the given time
+    pub fn is_due(&self, now: i64) -> bool {
+        match (self.refresh_interval_hours, self.last_refreshed) {
+            (None, _) | (Some(0), _) => false,
+            (Some(_), None) => true,
+            (Some(hours), Some(last)) => now - last >= i64::from(hours) * 3600,
+        }
+    }
+}
+
+pub struct PlaylistLibrary {
+    dir: PathBuf,
+    playlists: Mutex<Vec<PlaylistConfig>>,
+    refreshing: Mutex<HashSet<String>>,
+}
+
+impl PlaylistLibrary {
+    pub fn open(dir: PathBuf) -> Self {
+        let playlists: Vec<PlaylistConfig> = storage::read_json(&dir.join(CONFIG_FILE)).unwrap_or_else(|e| {
+            log::error!("Failed to load playlists: {}", e);
+            Vec::new()
+        });
+        Self {
+            dir,
+            playlists: Mutex::new(playlists),
+            refreshing: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn list(&self) -> Vec<PlaylistConfig> {
+        self.playlists.lock().unwrap().clone()
+    }
+
+    pub fn get(&self, id: &str) -> Option<PlaylistConfig> {
+        self.playlists.lock().unwrap().iter().find(|p| p.id == id).cloned()
+    }
+
+    // Add a playlist or replace the one with the same id
+    pub fn upsert(&self, config: PlaylistConfig) -> Result<(), String> {
+        let mut playlists = self.playlists.lock().unwrap();
+        match playlists.iter_mut().find(|p| p.id == config.id) {
+            Some(existing) => *existing = config,
+            None => playlists.push(config),
+        }
+        storage::write_json(&self.dir.join(CONFIG_FILE), &*playlists)
+    }
+
+    pub fn remove(&self, id: &str) -> Result<bool, String> {
+        let mut playlists = self.playlists.lock().unwrap();
+        let before = playlists.len();
+        playlists.retain(|p| p.id != id);
+        if playlists.len() == before {
+            return Ok(false);
+        }
+        storage::write_json(&self.dir.join(CONFIG_FILE), &*playlists)?;
+        let _ = std::fs::remove_file(self.channels_path(id));
+        Ok(true)
+    }
+
+    pub fn mark_refreshed(&self, id: &str, at: i64) -> Result<(), String> {
+        let mut playlists = self.playlists.lock().unwrap();
+        if let Some(playlist) = playlists.iter_mut().find(|p| p.id == id) {
+            playlist.last_refreshed = Some(at);
+        }
+        storage::write_json(&self.dir.join(CONFIG_FILE), &*playlists)
+    }
+
+    // Playlists whose refresh interval has elapsed
+    pub fn due(&self, now: i64) -> Vec<PlaylistConfig> {
+        self.playlists
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|p| p.is_due(now))
+            .cloned()
+            .collect()
+    }
+
+    // Claim a playlist for refreshing; false if a refresh is already running
+    pub fn begin_refresh(&self, id: &str) -> bool {
+        self.refreshing.lock().unwrap().insert(id.to_string())
+    }
+
+    pub fn end_refresh(&self, id: &str) {
+        self.refreshing.lock().unwrap().remove(id);
+    }
+
+    // Options of the playlist whose server `url` is on, None when it isn't on
+    // one or the playlist sets none
+    pub fn server_options(&self, url: &str) -> Option<HttpOptions> {
+        let url = Url::parse(url).ok()?;
+        let playlists = self.playlists.lock().unwrap();
+        playlists
+            .iter()
+            .filter(|playlist| playlist.serves(&url))
+            .find_map(PlaylistConfig::request_options)
+    }
+
+    fn channels_path(&self, id: &str) -> PathBuf {
+        self.dir.join("channels").join(format!("{}.json", id))
+    }
+
+    pub fn save_channels(&self, id: &str, entries: &[ChannelEntry]) -> Result<(), String> {
+        storage::write_json(&self.channels_path(id), entries)
+    }
+
+    // Channels from the last refresh, None if the playlist was never fetched
+    pub fn load_channels(&self, id: &str) -> Result<Option<Vec<ChannelEntry>>, String> {
+        let path = self.channels_path(id);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let mut entries: Vec<ChannelEntry> = storage::read_json(&path)?;
+        // Earlier versions copied the playlist's login and custom headers
+        // into every channel. Drop the copies whatever their value, which may
+        // be from older settings; logins are never a channel's own
+        let copied = self.get(id).map(|config| config.http.headers).unwrap_or_default();
+        for entry in &mut entries {
+            entry.http.headers.retain(|name, _| {
+                let copy = copied.keys().any(|copy| copy.eq_ignore_ascii_case(name));
+                !copy && !name.eq_ignore_ascii_case("Authorization")
+            });
+        }
+        Ok(Some(entries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    fn config(id: &str, interval: Option<u32>, last: Option<i64>) -> PlaylistConfig {
+        PlaylistConfig {
+            id: id.to_string(),
+            name: id.to_string(),
+            source: format!("http://example.com/{}.m3u", id),
+            refresh_interval_hours: interval,
+            last_refreshed: last,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_is_due() {
+        // Test that only playlists past their interval are due
+        let now = 100_000;
+        assert!(!config("a", None, None).is_due(now));
+        assert!(!config("a", Some(0), None).is_due(now));
+        assert!(config("a", Some(12), None).is_due(now));
+        assert!(!config("a", Some(12), Some(now - 3600)).is_due(now));
+        assert!(config("a", Some(12), Some(now - 12 * 3600)).is_due(now));
+    }
+
+    #[test]
+    fn test_request_options_include_auth() {
+        // Test that credentials become an Authorization header next to custom headers
+        let mut playlist = config("a", None, None);
+        assert!(playlist.request_options().is_none());
+
+        playlist.http.set_header("X-Forwarded-For", "1.2.3.4");
+        playlist.auth = Some(BasicAuth {
+            username: "user".to_string(),
+            password: "pass".to_string(),
+        });
+        let options = playlist.request_options().unwrap();
+        assert_eq!(options.headers["X-Forwarded-For"], "1.2.3.4");
+        assert_eq!(options.headers["Authorization"], "Basic dXNlcjpwYXNz");
+        assert!(options.public().headers.is_empty());
+    }
+
+    #[test]
+    fn test_server_options_stay_on_the_server() {
+        // Test that a playlist's login goes only to URLs on the server it's downloaded from
+        let library = PlaylistLibrary::open(temp_dir("playlist-server-options"));
+        let mut playlist = config("a", None, None);
+        playlist.source = "https://panel.test/get.php?username=u".to_string();
+        playlist.auth = Some(BasicAuth {
+            username: "user".to_string(),
+            password: "pass".to_string(),
+        });
+        library.upsert(playlist).unwrap();
+        let options = library.server_options("https://panel.test/live/1.ts").unwrap();
+        assert_eq!(options.headers["Authorization"], "Basic dXNlcjpwYXNz");
+        assert!(library.server_options("https://cdn.test/live/1.ts").is_none());
+        assert!(library.server_options("http://panel.test/live/1.ts").is_none());
+    }
+
+    #[test]
+    fn test_upsert_remove_and_persist() {
+        // Test that configs are replaced by id and survive reopening
+        let dir = temp_dir("library");
+        let library = PlaylistLibrary::open(dir.clone());
+        library.upsert(config("a", Some(12), None)).unwrap();
+        library.upsert(config("b", None, None)).unwrap();
+        library.upsert(config("a", Some(6), None)).unwrap();
+
+        let reopened = PlaylistLibrary::open(dir);
+        assert_eq!(reopened.list().len(), 2);
+        assert_eq!(reopened.get("a").unwrap().refresh_interval_hours, Some(6));
+        assert!(reopened.remove("a").unwrap());
+        assert!(!reopened.remove("a").unwrap());
+        assert_eq!(reopened.list().len(), 1);
+    }
+
+    #[test]
+    fn test_channels_roundtrip() {
+        // Test that saved channels load back and missing ones are None
+        let library = PlaylistLibrary::open(temp_dir("library-channels"));
+        let entries = vec![ChannelEntry {
+            name: "News".to_string(),
+            url: "http://example.com/news.ts".to_string(),
+            ..Default::default()
+        }];
+
+        assert!(library.load_channels("a").unwrap().is_none());
+        library.save_channels("a", &entries).unwrap();
+        assert_eq!(library.load_channels("a").unwrap(), Some(entries));
+    }
+
+    #[test]
+    fn test_load_channels_drops_copied_headers() {
+        // Test that logins and the playlist's custom headers copied into channels are dropped
+        let library = PlaylistLibrary::open(temp_dir("library-copied-headers"));
+        let mut playlist = config("a", None, None);
+        playlist.http.set_header("X-Token", "new");
+        library.upsert(playlist).unwrap();
+        let mut entry = ChannelEntry {
+            url: "http://cdn.example.com/1.ts".to_string(),
+            ..Default::default()
+        };
+        entry.http.set_header("Authorization", "Basic b2xkOm9sZA==");
+        entry.http.set_header("x-token", "old");
+        entry.http.set_header("Origin", "http://example.com");
+        library.save_channels("a", &[entry]).unwrap();
+
+        let entries = library.load_channels("a").unwrap().unwrap();
+        let headers: Vec<&str> = entries[0].http.headers.keys().map(String::as_str).collect();
+        assert_eq!(headers, ["Origin"]);
+    }
+
+    #[test]
+    fn test_begin_refresh_is_exclusive() {
+        // Test that a playlist can't be refreshed twice at the same time
+        let library = PlaylistLibrary::open(temp_dir("library-refresh"));
+        assert!(library.begin_refresh("a"));
+        assert!(!library.begin_refresh("a"));
+        library.end_refresh("a");
+        assert!(library.begin_refresh("a"));
+    }
+}
@@ -0,0 +1,350 @@
+// Incremental M3U/M3U8 parser
+use super::format::LineParser;
+use super::ChannelEntry;
+use crate::http::{split_url_options, HttpOptions};
+
+// Line-by-line parser so large playlists never have to be held in memory
+// as a single string
+#[derive(Debug, Default)]
+pub struct M3uParser {
+    pending: Option<ChannelEntry>,
+}
+
+impl M3uParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn pending_entry(&mut self) -> &mut ChannelEntry {
+        self.pending.get_or_insert_with(ChannelEntry::default)
+    }
+}
+
+impl LineParser for M3uParser {
+    // Feed one line of the playlist, returning an entry once its URL is seen
+    fn push_line(&mut self, line: &str) -> Option<ChannelEntry> {
+        let line = line.trim_start_matches('\u{feff}').trim();
+        if line.is_empty() {
+            return None;
+        }
+
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            let mut entry = parse_extinf(info);
+            // Directives seen before #EXTINF still belong to this channel
+            if let Some(previous) = self.pending.take() {
+                entry.http = previous.http;
+                if entry.group_title.is_none() {
+                    entry.group_title = previous.group_title;
+                }
+            }
+            self.pending = Some(entry);
+            return None;
+        }
+        if let Some(group) = line.strip_prefix("#EXTGRP:") {
+            // #EXTGRP only applies when #EXTINF carried no group-title
+            let entry = self.pending_entry();
+            if entry.group_title.is_none() && !group.trim().is_empty() {
+                entry.group_title = Some(group.trim().to_string());
+            }
+            return None;
+        }
+        if let Some(option) = line.strip_prefix("#EXTVLCOPT:") {
+            apply_vlc_option(&mut self.pending_entry().http, option);
+            return None;
+        }
+        if let Some(property) = line.strip_prefix("#KODIPROP:") {
+            apply_kodi_property(&mut self.pending_entry().http, property);
+            return None;
+        }
+        if let Some(json) = line.strip_prefix("#EXTHTTP:") {
+            apply_exthttp(&mut self.pending_entry().http, json);
+            return None;
+        }
+        if line.starts_with('#') {
+            // Other directives (#EXTM3U, ...) are not needed yet
+            return None;
+        }
+
+        let mut entry = self.pending.take().unwrap_or_default();
+        let (url, url_options) = split_url_options(line);
+        if let Some(options) = url_options {
+            entry.http.extend(options);
+        }
+        if entry.name.is_empty() {
+            entry.name = entry.tvg_name.clone().unwrap_or_else(|| url.clone());
+        }
+        entry.url = url;
+        Some(entry)
+    }
+
+    // A trailing #EXTINF without a URL is not a channel
+    fn finish(self) -> Vec<ChannelEntry> {
+        Vec::new()
+    }
+}
+
+// Parse the part after "#EXTINF:" (duration, attributes and title)
+fn parse_extinf(info: &str) -> ChannelEntry {
+    let (head, title) = match find_title_separator(info) {
+        Some(idx) => (&info[..idx], info[idx + 1..].trim()),
+        None => (info, ""),
+    };
+
+    let head = head.trim();
+    let (duration, attributes) = match head.find(char::is_whitespace) {
+        Some(idx) => (&head[..idx], &head[idx..]),
+        None => (head, ""),
+    };
+
+    let mut entry = ChannelEntry {
+        name: title.to_string(),
+        duration: duration.parse::<f64>().ok().filter(|d| *d >= 0.0),
+        ..Default::default()
+    };
+
+    for (key, value) in parse_attributes(attributes) {
+        if value.is_empty() {
+            continue;
+        }
+        match key.as_str() {
+            "tvg-id" => entry.tvg_id = Some(value),
+            "tvg-name" => entry.tvg_name = Some(value),
+            "tvg-logo" => entry.tvg_logo = Some(value),
+            "group-title" => entry.group_title = Some(value),
+            "catchup" | "catchup-type" => entry.catchup = Some(value),
+            "catchup-days" => entry.catchup_days = value.parse().ok(),
+            "catchup-source" => entry.catchup_source = Some(value),
+            "timeshift" | "tvg-rec" => entry.timeshift = value.parse().ok(),
+            _ => {
+                entry.attributes.insert(key, value);
+            }
+        }
+    }
+
+    entry
+}
+
+// Apply an "#EXTVLCOPT:http-user-agent=..." style option
+pub(super) fn apply_vlc_option(http: &mut HttpOptions, option: &str) {
+    let Some((key, value)) = option.split_once('=') else {
+        return;
+    };
+    match key.trim().to_ascii_lowercase().as_str() {
+        "http-user-agent" => http.set_header("User-Agent", value),
+        "http-referrer" | "http-referer" => http.set_header("Referer", value),
+        "http-origin" => http.set_header("Origin", value),
+        "http-cookie" => http.set_header("Cookie", value),
+        _ => {}
+    }
+}
+
+// Apply "#KODIPROP:inputstream.adaptive.stream_headers=User-Agent=..&Referer=.."
+fn apply_kodi_property(http: &mut HttpOptions, property: &str) {
+    let Some((key, value)) = property.split_once('=') else {
+        return;
+    };
+    match key.trim() {
+        "inputstream.adaptive.stream_headers" | "inputstream.adaptive.manifest_headers" => {
+            http.merge_query(value)
+        }
+        _ => {}
+    }
+}
+
+// Apply "#EXTHTTP:{\"User-Agent\":\"...\",\"cookie\":\"...\"}"
+fn apply_exthttp(http: &mut HttpOptions, json: &str) {
+    let Ok(headers) = serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(json) else {
+        return;
+    };
+    for (name, value) in headers {
+        if let Some(value) = value.as_str() {
+            http.set_header(&name, value);
+        }
+    }
+}
+
+// Parse key="value" pairs (quotes optional), lowercasing the keys
+fn parse_attributes(input: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let mut rest = input.trim_start();
+
+    while let Some(eq) = rest.find('=') {
+        let key = rest[..eq].trim();
+        // Keys never contain spaces; skip stray words before the key
+        let key = key.rsplit(char::is_whitespace).next().unwrap_or(key);
+        rest = &rest[eq + 1..];
+
+        let value;
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            value = &quoted[..end];
+            rest = quoted.get(end + 1..).unwrap_or("");
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            value = &rest[..end];
+            rest = &rest[end..];
+        }
+
+        if !key.is_empty() {
+            attributes.push((key.to_ascii_lowercase(), value.trim().to_string()));
+        }
+        rest = rest.trim_start();
+    }
+
+    attributes
+}
+
+// Find the comma that separates attributes from the title, ignoring
+// commas inside quoted attribute values
+fn find_title_separator(info: &str) -> Option<usize> {
+    let mut in_quotes = false;
+    for (idx, c) in info.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => return Some(idx),
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_m3u(content: &str) -> Vec<ChannelEntry> {
+        let mut parser = M3uParser::new();
+        content.lines().filter_map(|line| parser.push_line(line)).collect()
+    }
+
+    #[test]
+    fn test_parse_m3u_basic() {
+        // Test that a simple playlist yields one entry per URL
+        let content = "#EXTM3U\n#EXTINF:-1,Channel One\nhttp://example.com/1.ts\n#EXTINF:-1,Channel Two\nhttp://example.com/2.ts\n";
+        let entries = parse_m3u(content);
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name, "Channel One");
+        assert_eq!(entries[0].url, "http://example.com/1.ts");
+        assert_eq!(entries[0].duration, None);
+        assert_eq!(entries[1].name, "Channel Two");
+    }
+
+    #[test]
+    fn test_parse_m3u_duration() {
+        // Test that positive durations are kept and -1 means live
+        let content = "#EXTINF:125.5,Movie\nhttp://example.com/movie.mp4\n#EXTINF:-1,Live\nhttp://example.com/live.m3u8";
+        let entries = parse_m3u(content);
+
+        assert_eq!(entries[0].duration, Some(125.5));
+        assert_eq!(entries[1].duration, None);
+    }
+
+    #[test]
+    fn test_parse_m3u_quoted_commas() {
+        // Test that commas inside attribute values don't split the title
+        let content = "#EXTINF:-1 group-title=\"News, World\",World News\nhttp://example.com/news.ts";
+        let entries = parse_m3u(content);
+
+        assert_eq!(entries[0].name, "World News");
+    }
+
+    #[test]
+    fn test_parse_m3u_without_extinf() {
+        // Test that bare URL lists use the URL as the name
+        let content = "\u{feff}http://example.com/a.ts\r\n\r\nhttp://example.com/b.ts\r\n";
+        let entries = parse_m3u(content);
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name, "http://example.com/a.ts");
+        assert_eq!(entries[1].url, "http://example.com/b.ts");
+    }
+
+    #[test]
+    fn test_parse_m3u_empty() {
+        // Test that an empty or header-only playlist has no entries
+        assert!(parse_m3u("").is_empty());
+        assert!(parse_m3u("#EXTM3U\n").is_empty());
+    }
+
+    #[test]
+    fn test_parser_push_line() {
+        // Test that the incremental parser only emits on URL lines
+        let mut parser = M3uParser::new();
+        assert!(parser.push_line("#EXTM3U").is_none());
+        assert!(parser.push_line("#EXTINF:-1,News").is_none());
+
+        let entry = parser.push_line("http://example.com/news.ts").unwrap();
+        assert_eq!(entry.name, "News");
+    }
+
+    #[test]
+    fn test_parse_m3u_plus_attributes() {
+        // Test that all M3U Plus attributes land in their typed fields
+        let content = "#EXTINF:-1 tvg-id=\"bbc1.uk\" tvg-name=\"BBC One\" tvg-logo=\"http://logo/bbc1.png\" group-title=\"UK\" catchup=\"default\" catchup-days=\"7\" catchup-source=\"?utc={utc}\" timeshift=\"3\" tvg-chno=\"101\",BBC One HD\nhttp://example.com/bbc1.ts";
+        let entry = &parse_m3u(content)[0];
+
+        assert_eq!(entry.name, "BBC One HD");
+        assert_eq!(entry.tvg_id.as_deref(), Some("bbc1.uk"));
+        assert_eq!(entry.tvg_name.as_deref(), Some("BBC One"));
+        assert_eq!(entry.tvg_logo.as_deref(), Some("http://logo/bbc1.png"));
+        assert_eq!(entry.group_title.as_deref(), Some("UK"));
+        assert_eq!(entry.catchup.as_deref(), Some("default"));
+        assert_eq!(entry.catchup_days, Some(7));
+        assert_eq!(entry.catchup_source.as_deref(), Some("?utc={utc}"));
+        assert_eq!(entry.timeshift, Some(3));
+        assert_eq!(entry.attributes.get("tvg-chno").map(String::as_str), Some("101"));
+    }
+
+    #[test]
+    fn test_parse_m3u_unquoted_and_mixed_case_attributes() {
+        // Test that unquoted values and upper-case keys are accepted
+        let content = "#EXTINF:-1 TVG-ID=news.us Group-Title=\"News\",CNN\nhttp://example.com/cnn.ts";
+        let entry = &parse_m3u(content)[0];
+
+        assert_eq!(entry.tvg_id.as_deref(), Some("news.us"));
+        assert_eq!(entry.group_title.as_deref(), Some("News"));
+    }
+
+    #[test]
+    fn test_parse_m3u_extgrp_and_tvg_name_fallback() {
+        // Test that #EXTGRP fills a missing group and tvg-name a missing title
+        let content = "#EXTINF:-1 tvg-name=\"Sport 1\",\n#EXTGRP:Sports\nhttp://example.com/s1.ts";
+        let entry = &parse_m3u(content)[0];
+
+        assert_eq!(entry.name, "Sport 1");
+        assert_eq!(entry.group_title.as_deref(), Some("Sports"));
+    }
+
+    #[test]
+    fn test_parse_m3u_vlc_and_kodi_options() {
+        // Test that #EXTVLCOPT and #KODIPROP lines become HTTP options
+        let content = "#EXTINF:-1,Channel\n#EXTVLCOPT:http-user-agent=VLC/3.0\n#EXTVLCOPT:http-referrer=http://site/\n#KODIPROP:inputstream.adaptive.stream_headers=Origin=http%3A%2F%2Fsite\nhttp://example.com/c.m3u8";
+        let entry = &parse_m3u(content)[0];
+
+        assert_eq!(entry.http.user_agent.as_deref(), Some("VLC/3.0"));
+        assert_eq!(entry.http.referrer.as_deref(), Some("http://site/"));
+        assert_eq!(entry.http.headers["Origin"], "http://site");
+    }
+
+    #[test]
+    fn test_parse_m3u_options_before_extinf() {
+        // Test that options placed before #EXTINF are not lost
+        let content = "#EXTVLCOPT:http-user-agent=Early\n#EXTINF:-1,Channel\nhttp://example.com/c.ts\nhttp://example.com/next.ts";
+        let entries = parse_m3u(content);
+
+        assert_eq!(entries[0].http.user_agent.as_deref(), Some("Early"));
+        assert!(entries[1].http.is_empty());
+    }
+
+    #[test]
+    fn test_parse_m3u_exthttp_and_url_suffix() {
+        // Test that #EXTHTTP JSON and "url|Header=value" suffixes are honored
+        let content = "#EXTINF:-1,Channel\n#EXTHTTP:{\"cookie\":\"token=abc\"}\nhttp://example.com/c.ts|User-Agent=Kodi";
+        let entry = &parse_m3u(content)[0];
+
+        assert_eq!(entry.url, "http://example.com/c.ts");
+        assert_eq!(entry.http.user_agent.as_deref(), Some("Kodi"));
+        assert_eq!(entry.http.headers["cookie"], "token=abc");
+    }
+}
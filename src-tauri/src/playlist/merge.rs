@@ -0,0 +1,136 @@
+// Combine several playlists into one list without duplicate channels
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use super::ChannelEntry;
+
+// Which copy of a duplicated channel survives the merge
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "strategy", rename_all = "camelCase")]
+pub enum MergeStrategy {
+    // The first playlist (in the order given) that has the channel wins
+    #[default]
+    KeepFirst,
+    // Playlists with a lower priority value win; missing ids come last
+    #[serde(rename_all = "camelCase")]
+    KeepByPriority { priorities: HashMap<String, i32> },
+}
+
+// Lowercase alphanumerics only, so "BBC One", "bbc-one" and "BBC ONE "
+// compare equal
+pub fn normalize_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+// Merge playlists, treating channels with the same normalized name or the
+// same stream URL as duplicates; returns the entries and the number dropped.
+// The streams of dropped duplicates become backups of the copy kept
+pub fn merge(
+    playlists: &[(String, Arc<Vec<ChannelEntry>>)],
+    strategy: &MergeStrategy,
+) -> (Vec<ChannelEntry>, usize) {
+    let mut ordered: Vec<&(String, Arc<Vec<ChannelEntry>>)> = playlists.iter().collect();
+    if let MergeStrategy::KeepByPriority { priorities } = strategy {
+        // Stable, so equal priorities keep the order given
+        ordered.sort_by_key(|(id, _)| priorities.get(id).copied().unwrap_or(i32::MAX));
+    }
+
+    // Normalized name -> position in `merged`
+    let mut names: HashMap<String, usize> = HashMap::new();
+    let mut urls = HashSet::new();
+    let mut merged = Vec::new();
+    let mut duplicates = 0;
+
+    for (_, entries) in ordered {
+        for entry in entries.iter() {
+            let name = normalize_name(&entry.name);
+            let url = entry.url.as_str();
+            let kept = names.get(&name).copied().filter(|_| !name.is_empty());
+            if kept.is_some() || urls.contains(url) {
+                duplicates += 1;
+                if let Some(kept) = kept.filter(|_| !urls.contains(url)) {
+                    let kept: &mut ChannelEntry = &mut merged[kept];
+                    kept.backup_urls.push(entry.url.clone());
+                    urls.insert(url);
+                }
+                continue;
+            }
+            if !name.is_empty() {
+                names.insert(name, merged.len());
+            }
+            urls.insert(url);
+            merged.push(entry.clone());
+        }
+    }
+
+    (merged, duplicates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, url: &str) -> ChannelEntry {
+        ChannelEntry {
+            name: name.to_string(),
+            url: url.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn playlists() -> Vec<(String, Arc<Vec<ChannelEntry>>)> {
+        vec![
+            (
+                "a".to_string(),
+                Arc::new(vec![entry("BBC One", "http://a/1"), entry("CNN", "http://a/2")]),
+            ),
+            (
+                "b".to_string(),
+                Arc::new(vec![entry("bbc-one", "http://b/1"), entry("Sky", "http://a/2")]),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_normalize_name() {
+        // Test that case, spacing and punctuation are ignored
+        assert_eq!(normalize_name(" BBC-One "), "bbcone");
+        assert_eq!(normalize_name("Ärte 1"), "ärte1");
+    }
+
+    #[test]
+    fn test_merge_keep_first() {
+        // Test that duplicates by name or URL keep the first playlist's copy
+        let (merged, duplicates) = merge(&playlists(), &MergeStrategy::KeepFirst);
+        let mut bbc = entry("BBC One", "http://a/1");
+        bbc.backup_urls = vec!["http://b/1".to_string()];
+        assert_eq!(merged, vec![bbc, entry("CNN", "http://a/2")]);
+        assert_eq!(duplicates, 2);
+    }
+
+    #[test]
+    fn test_merge_keep_by_priority() {
+        // Test that the playlist with the lowest priority value wins
+        let strategy = MergeStrategy::KeepByPriority {
+            priorities: HashMap::from([("b".to_string(), 1), ("a".to_string(), 2)]),
+        };
+        let (merged, duplicates) = merge(&playlists(), &strategy);
+        let mut bbc = entry("bbc-one", "http://b/1");
+        bbc.backup_urls = vec!["http://a/1".to_string()];
+        assert_eq!(merged, vec![bbc, entry("Sky", "http://a/2")]);
+        assert_eq!(duplicates, 2);
+    }
+
+    #[test]
+    fn test_strategy_deserializes() {
+        // Test the JSON shape the frontend sends
+        let strategy: MergeStrategy =
+            serde_json::from_str(r#"{"strategy":"keepByPriority","priorities":{"a":1}}"#).unwrap();
+        assert!(matches!(strategy, MergeStrategy::KeepByPriority { .. }));
+    }
+}
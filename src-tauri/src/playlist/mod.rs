@@ -0,0 +1,1255 @@
+// Playlist loading, parsing and paging
+mod bulk_edit;
+mod cache;
+pub mod catchup;
+pub mod channel_rules;
+mod csv_io;
+mod diff;
+mod duplicates;
+mod enigma2;
+mod export;
+mod format;
+mod history;
+mod library;
+mod m3u;
+mod merge;
+mod numbering;
+mod overrides;
+mod pls;
+mod sanitize;
+pub mod scheduler;
+mod source;
+pub mod watch_folder;
+mod watcher;
+mod xspf;
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+use crate::epg::mappings::EpgMappings;
+use crate::epg::parental;
+use crate::http::{BasicAuth, HttpOptions};
+use crate::identity;
+use crate::{unix_now, validate_string_length};
+
+use bulk_edit::ChannelEdit;
+use duplicates::DuplicateGroup;
+use export::ExportFormat;
+use format::LineParser;
+use history::PlaylistVersion;
+use m3u::M3uParser;
+use merge::MergeStrategy;
+use numbering::NumberConflict;
+use overrides::ChannelOverride;
+use pls::PlsParser;
+use sanitize::{SanitizeOptions, SanitizeStats};
+use source::{Opened, SourceReader};
+
+pub use cache::{CacheEntry, DownloadCache};
+pub use catchup::xtream_parts;
+pub use channel_rules::ChannelRules;
+pub use format::PlaylistFormat;
+pub use history::PlaylistHistory;
+pub use diff::PlaylistDiff;
+pub use duplicates::jaro_winkler;
+pub use library::{PlaylistConfig, PlaylistLibrary};
+pub use merge::normalize_name;
+pub use overrides::ChannelOverrides;
+pub use sanitize::strip_tags;
+pub use source::is_remote;
+pub use watcher::PlaylistWatcher;
+
+// Maximum accepted length for a playlist URL or file path
+const MAX_SOURCE_LENGTH: usize = 2048;
+
+// Number of parsed entries between two progress events
+const PROGRESS_INTERVAL: usize = 2000;
+
+// Largest page the frontend may request at once
+const MAX_PAGE_SIZE: usize = 5000;
+
+// Maximum accepted length for a playlist display name
+const MAX_NAME_LENGTH: usize = 200;
+
+// Limits for user-configured request headers
+const MAX_HEADERS: usize = 32;
+const MAX_HEADER_LENGTH: usize = 4096;
+
+// Attribute holding the channel number
+const NUMBER_ATTRIBUTE: &str = "tvg-chno";
+
+// A single channel entry parsed from a playlist, including the
+// M3U Plus attributes used for EPG mapping, logos and catch-up
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelEntry {
+    // Stable identity (see crate::identity), set when the playlist is stored
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub channel_id: String,
+    pub name: String,
+    pub url: String,
+    // Duration in seconds, None for live streams (#EXTINF:-1)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tvg_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tvg_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tvg_logo: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group_title: Option<String>,
+    // Catch-up mode (default, append, shift, flussonic, xc, ...)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub catchup: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub catchup_days: Option<u32>,
+    // URL template used to build archive URLs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub catchup_source: Option<String>,
+    // Archive depth in days announced via timeshift / tvg-rec
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeshift: Option<u32>,
+    // Per-channel HTTP options from #EXTVLCOPT / #KODIPROP / #EXTHTTP
+    #[serde(default, skip_serializing_if = "HttpOptions::is_empty")]
+    pub http: HttpOptions,
+    // Any other #EXTINF attributes, keyed by lowercase name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, String>,
+    // Hidden by a channel rule; kept so the UI can still show it on demand
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hidden: bool,
+    // Other streams of the channel, from merged duplicates or entered by
+    // hand; the proxy fails over to them in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub backup_urls: Vec<String>,
+}
+
+// Payload of the playlist-parse-progress event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParseProgress {
+    pub playlist_id: String,
+    pub entries: usize,
+    pub bytes_read: u64,
+    pub total_bytes: Option<u64>,
+    pub done: bool,
+}
+
+// Result of loading a playlist into the backend
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistSummary {
+    pub id: String,
+    pub total: usize,
+}
+
+// Result of merging several playlists into one
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeResult {
+    pub summary: PlaylistSummary,
+    // Channels dropped because an earlier or higher-priority copy was kept
+    pub duplicates: usize,
+}
+
+// Result of a cleanup pass over a loaded playlist
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SanitizeResult {
+    pub summary: PlaylistSummary,
+    #[serde(flatten)]
+    pub stats: SanitizeStats,
+}
+
+// Result of refreshing a playlist with a conditional request
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum RefreshOutcome {
+    // The server still has the same version; nothing was downloaded
+    NotModified { summary: PlaylistSummary },
+    Updated { summary: PlaylistSummary },
+}
+
+impl RefreshOutcome {
+    pub fn summary(&self) -> &PlaylistSummary {
+        match self {
+            RefreshOutcome::NotModified { summary } | RefreshOutcome::Updated { summary } => summary,
+        }
+    }
+}
+
+// One page of a loaded playlist
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistPage {
+    pub entries: Vec<ChannelEntry>,
+    pub offset: usize,
+    pub total: usize,
+}
+
+// Parsed playlists kept in memory so they can be served in pages, plus
+// the diff produced by their last re-download and the Xtream panel each one
+// came from (see identity::xtream_server)
+#[derive(Default)]
+pub struct PlaylistStore {
+    playlists: Mutex<HashMap<String, Arc<Vec<ChannelEntry>>>>,
+    diffs: Mutex<HashMap<String, Arc<PlaylistDiff>>>,
+    xtream_servers: Mutex<HashMap<String, String>>,
+}
+
+impl PlaylistStore {
+    pub fn insert(&self, id: &str, xtream_server: Option<&str>, mut entries: Vec<ChannelEntry>) -> usize {
+        let total = entries.len();
+        for entry in &mut entries {
+            entry.channel_id = identity::channel_id(id, &identity::url_key(&entry.url, xtream_server));
+        }
+        let mut servers = self.xtream_servers.lock().unwrap();
+        match xtream_server {
+            Some(server) => servers.insert(id.to_string(), server.to_string()),
+            None => servers.remove(id),
+        };
+        self.playlists
+            .lock()
+            .unwrap()
+            .insert(id.to_string(), Arc::new(entries));
+        total
+    }
+
+    // Xtream panel the loaded playlist was keyed with, for edits that re-key it
+    pub fn xtream_server(&self, id: &str) -> Option<String> {
+        self.xtream_servers.lock().unwrap().get(id).cloned()
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<Vec<ChannelEntry>>> {
+        self.playlists.lock().unwrap().get(id).cloned()
+    }
+
+    pub fn remove(&self, id: &str) {
+        self.playlists.lock().unwrap().remove(id);
+        self.diffs.lock().unwrap().remove(id);
+        self.xtream_servers.lock().unwrap().remove(id);
+    }
+
+    pub fn set_diff(&self, diff: PlaylistDiff) {
+        self.diffs
+            .lock()
+            .unwrap()
+            .insert(diff.playlist_id.clone(), Arc::new(diff));
+    }
+
+    pub fn diff(&self, id: &str) -> Option<Arc<PlaylistDiff>> {
+        self.diffs.lock().unwrap().get(id).cloned()
+    }
+
+    pub fn page(&self, id: &str, offset: usize, limit: usize) -> Option<PlaylistPage> {
+        let entries = self.get(id)?;
+        let end = offset.saturating_add(limit).min(entries.len());
+        let start = offset.min(end);
+        Some(PlaylistPage {
+            entries: entries[start..end].to_vec(),
+            offset: start,
+            total: entries.len(),
+        })
+    }
+}
+
+// Derive a stable playlist id from its source so reloading the same
+// URL or file replaces the previous copy (FNV-1a, hex encoded)
+pub fn playlist_id(source: &str) -> String {
+    content_hash(source.trim().as_bytes())
+}
+
+// FNV-1a hash of arbitrary bytes, hex encoded
+pub fn content_hash(bytes: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+// Parse a playlist (M3U, XSPF or PLS, sniffed from the content) as it
+// streams in, reporting (entries, bytes read) as it goes
+pub async fn read_entries<R>(
+    mut reader: R,
+    hint: Option<PlaylistFormat>,
+    on_progress: impl FnMut(usize, u64),
+) -> Result<Vec<ChannelEntry>, String>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let head = reader
+        .fill_buf()
+        .await
+        .map_err(|e| format!("Failed to read playlist: {}", e))?;
+    match PlaylistFormat::detect(head, hint) {
+        PlaylistFormat::M3u => read_lines(reader, M3uParser::new(), on_progress).await,
+        PlaylistFormat::Pls => read_lines(reader, PlsParser::new(), on_progress).await,
+        PlaylistFormat::Xspf => xspf::read_entries(reader, on_progress).await,
+    }
+}
+
+// Drive a line-based parser over the whole input
+async fn read_lines<R, P>(
+    mut reader: R,
+    mut parser: P,
+    mut on_progress: impl FnMut(usize, u64),
+) -> Result<Vec<ChannelEntry>, String>
+where
+    R: AsyncBufRead + Unpin,
+    P: LineParser,
+{
+    let mut entries = Vec::new();
+    let mut bytes_read: u64 = 0;
+    let mut line = Vec::with_capacity(1024);
+
+    loop {
+        line.clear();
+        let read = reader
+            .read_until(b'\n', &mut line)
+            .await
+            .map_err(|e| format!("Failed to read playlist: {}", e))?;
+        if read == 0 {
+            break;
+        }
+        bytes_read += read as u64;
+
+        if let Some(entry) = parser.push_line(&String::from_utf8_lossy(&line)) {
+            entries.push(entry);
+            if entries.len() % PROGRESS_INTERVAL == 0 {
+                on_progress(entries.len(), bytes_read);
+            }
+        }
+    }
+
+    entries.extend(parser.finish());
+    on_progress(entries.len(), bytes_read);
+    Ok(entries)
+}
+
+// Validate and normalize a playlist source argument
+fn check_source(source: &str) -> Result<&str, String> {
+    validate_string_length(source, MAX_SOURCE_LENGTH)?;
+    let source = source.trim();
+    if source.is_empty() {
+        return Err("Playlist source cannot be empty".to_string());
+    }
+    Ok(source)
+}
+
+// Reject header sets that are unreasonably large, or that would smuggle in
+// more headers where they're joined into one string (ffmpeg's -headers, mpv's
+// http-header-fields)
+pub fn check_http_options(http: &HttpOptions) -> Result<(), String> {
+    if http.headers.len() > MAX_HEADERS {
+        return Err(format!("At most {} custom headers are allowed", MAX_HEADERS));
+    }
+    let values = http.user_agent.iter().chain(http.referrer.iter());
+    for value in values.chain(http.headers.keys()).chain(http.headers.values()) {
+        validate_string_length(value, MAX_HEADER_LENGTH)?;
+        if value.chars().any(char::is_control) {
+            return Err("Headers cannot contain control characters".to_string());
+        }
+    }
+    if http.headers.keys().any(|name| name.contains(':')) {
+        return Err("Header names cannot contain ':'".to_string());
+    }
+    Ok(())
+}
+
+pub fn check_auth(auth: Option<&BasicAuth>) -> Result<(), String> {
+    if let Some(auth) = auth {
+        validate_string_length(&auth.username, MAX_HEADER_LENGTH)?;
+        validate_string_length(&auth.password, MAX_HEADER_LENGTH)?;
+        if auth.username.contains(':') {
+            return Err("Username cannot contain ':'".to_string());
+        }
+    }
+    Ok(())
+}
+
+// Validate and normalize a playlist display name
+fn check_name(name: &str) -> Result<&str, String> {
+    validate_string_length(name, MAX_NAME_LENGTH)?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Playlist name cannot be empty".to_string());
+    }
+    Ok(name)
+}
+
+// Command handler that downloads or reads a playlist and returns its channels
+#[tauri::command]
+pub async fn parse_playlist(source: String) -> Result<Vec<ChannelEntry>, String> {
+    read_source(check_source(&source)?, None).await
+}
+
+// Download or read a whole playlist without touching the store
+pub async fn read_source(source: &str, options: Option<&HttpOptions>) -> Result<Vec<ChannelEntry>, String> {
+    match source::open(source, options, None, false).await? {
+        Opened::Body(opened) => read_entries(opened.reader, opened.format_hint, |_, _| {}).await,
+        Opened::NotModified => Err("Unexpected 304 response for an unconditional request".to_string()),
+    }
+}
+
+// Parse a playlist body into the store, emitting playlist-parse-progress;
+// the playlist's user agent and referrer become the defaults of every channel
+async fn parse_into_store(
+    app: &AppHandle,
+    store: &PlaylistStore,
+    id: &str,
+    xtream_server: Option<&str>,
+    opened: SourceReader,
+    options: Option<&HttpOptions>,
+) -> Result<PlaylistSummary, String> {
+    let total_bytes = opened.total_bytes;
+    let mut last_bytes = 0;
+
+    let mut entries = read_entries(opened.reader, opened.format_hint, |entries, bytes_read| {
+        last_bytes = bytes_read;
+        let _ = app.emit(
+            "playlist-parse-progress",
+            ParseProgress {
+                playlist_id: id.to_string(),
+                entries,
+                bytes_read,
+                total_bytes,
+                done: false,
+            },
+        );
+    })
+    .await?;
+
+    // Logins and custom headers stay out of the channels, which are saved,
+    // exported and played from other hosts; see stream_options
+    if let Some(options) = options.map(HttpOptions::public).filter(|options| !options.is_empty()) {
+        for entry in &mut entries {
+            let mut http = options.clone();
+            http.extend(std::mem::take(&mut entry.http));
+            entry.http = http;
+        }
+    }
+    app.state::<ChannelRules>().apply(id, &mut entries);
+    // Manual fixes win over rules
+    app.state::<ChannelOverrides>().apply(id, xtream_server, &mut entries);
+
+    let total = store.insert(id, xtream_server, entries);
+    let _ = app.emit(
+        "playlist-parse-progress",
+        ParseProgress {
+            playlist_id: id.to_string(),
+            entries: total,
+            bytes_read: last_bytes,
+            total_bytes,
+            done: true,
+        },
+    );
+
+    Ok(PlaylistSummary {
+        id: id.to_string(),
+        total,
+    })
+}
+
+// Fetch a playlist into the store, optionally revalidating the cached copy
+pub async fn fetch_into_store(
+    app: &AppHandle,
+    store: &PlaylistStore,
+    cache: &DownloadCache,
+    source: &str,
+    options: Option<&HttpOptions>,
+    conditional: bool,
+) -> Result<RefreshOutcome, String> {
+    let id = playlist_id(source);
+    let server = identity::xtream_server(source);
+
+    match source::open(source, options, Some(cache), conditional).await? {
+        Opened::NotModified => {
+            let summary = match store.get(&id) {
+                Some(entries) => PlaylistSummary {
+                    id,
+                    total: entries.len(),
+                },
+                // Not parsed in this session yet: use the copy on disk
+                None => {
+                    let mut opened = source::open_file(&cache.body_path(source)).await?;
+                    opened.format_hint = PlaylistFormat::from_extension(source);
+                    parse_into_store(app, store, &id, server.as_deref(), opened, options).await?
+                }
+            };
+            Ok(RefreshOutcome::NotModified { summary })
+        }
+        Opened::Body(mut opened) => {
+            let previous = store.get(&id);
+            let pending_cache = opened.pending_cache.take();
+            let result = parse_into_store(app, store, &id, server.as_deref(), opened, options).await;
+            if let Some(pending) = pending_cache {
+                pending.finish(cache, source, result.is_ok());
+            }
+            let summary = result?;
+
+            if let (Some(previous), Some(current)) = (previous, store.get(&id)) {
+                store.set_diff(diff::diff_entries(&id, &previous, &current));
+            }
+            Ok(RefreshOutcome::Updated { summary })
+        }
+    }
+}
+
+// Command handler that streams a (possibly huge) playlist into the backend,
+// emitting playlist-parse-progress events; entries are then fetched in pages
+#[tauri::command]
+pub async fn load_playlist(
+    app: AppHandle,
+    store: State<'_, PlaylistStore>,
+    cache: State<'_, DownloadCache>,
+    watcher: State<'_, PlaylistWatcher>,
+    source: String,
+) -> Result<PlaylistSummary, String> {
+    let source = check_source(&source)?;
+    let outcome = fetch_into_store(&app, &store, &cache, source, None, false).await?;
+    // Local files are re-parsed whenever they change on disk
+    watcher.watch(source);
+    Ok(outcome.summary().clone())
+}
+
+// Refresh a configured playlist with a conditional request, persisting the
+// channels and emitting playlist-updated when the content changed
+pub async fn refresh_configured(app: &AppHandle, id: &str) -> Result<RefreshOutcome, String> {
+    let library = app.state::<PlaylistLibrary>();
+    let config = library
+        .get(id)
+        .ok_or_else(|| format!("Playlist '{}' is not configured", id))?;
+    if !library.begin_refresh(id) {
+        return Err(format!("Playlist '{}' is already refreshing", config.name));
+    }
+
+    let store = app.state::<PlaylistStore>();
+    let cache = app.state::<DownloadCache>();
+    let options = config.request_options();
+    let result = fetch_into_store(app, &store, &cache, &config.source, options.as_ref(), true).await;
+    library.end_refresh(id);
+    let outcome = result?;
+
+    if let RefreshOutcome::Updated { summary } = &outcome {
+        if let Some(entries) = store.get(id) {
+            library.save_channels(id, &entries)?;
+            if let Err(e) = app.state::<PlaylistHistory>().record(id, &entries, unix_now()) {
+                log::warn!("Failed to store a version of {}: {}", config.name, e);
+            }
+        }
+        let _ = app.emit("playlist-updated", summary.clone());
+    }
+    library.mark_refreshed(id, unix_now())?;
+    Ok(outcome)
+}
+
+// Re-read a playlist whose source changed, persisting it when it is part of
+// the library, and emit playlist-updated
+pub async fn reload_source(app: &AppHandle, source: &str) -> Result<(), String> {
+    let id = playlist_id(source);
+    if app.state::<PlaylistLibrary>().get(&id).is_some() {
+        return refresh_configured(app, &id).await.map(|_| ());
+    }
+
+    let store = app.state::<PlaylistStore>();
+    let cache = app.state::<DownloadCache>();
+    if let RefreshOutcome::Updated { summary } = fetch_into_store(app, &store, &cache, source, None, false).await? {
+        let _ = app.emit("playlist-updated", summary);
+    }
+    Ok(())
+}
+
+// The options of a request to a stream: its own, on top of the login and
+// headers of the playlist on the same server
+pub fn stream_options(app: &AppHandle, url: &str, http: &HttpOptions) -> HttpOptions {
+    let mut options = app.state::<PlaylistLibrary>().server_options(url).unwrap_or_default();
+    options.extend(http.clone());
+    options
+}
+
+// A channel's stream URL as the playlist has it after fetching it again,
+// for playlists whose URLs carry tokens that expire
+pub async fn renew_stream_url(app: &AppHandle, playlist_id: &str, channel_id: &str) -> Result<String, String> {
+    refresh_configured(app, playlist_id).await?;
+    let entries = loaded(&app.state::<PlaylistStore>(), playlist_id)?;
+    let entry = entries.iter().find(|entry| entry.channel_id == channel_id);
+    entry
+        .map(|entry| entry.url.clone())
+        .ok_or_else(|| format!("Channel '{}' is no longer in the playlist", channel_id))
+}
+
+// Command handler that re-fetches a configured playlist with If-None-Match /
+// If-Modified-Since, skipping the download when the server reports 304
+#[tauri::command]
+pub async fn refresh_playlist(app: AppHandle, playlist_id: String) -> Result<RefreshOutcome, String> {
+    refresh_configured(&app, &playlist_id).await
+}
+
+// Command handler returning the channels added, removed and renamed by the
+// last re-download of a playlist, None until it has been fetched twice
+#[tauri::command]
+pub fn get_playlist_diff(
+    store: State<'_, PlaylistStore>,
+    playlist_id: String,
+) -> Result<Option<PlaylistDiff>, String> {
+    Ok(store.diff(&playlist_id).map(|diff| (*diff).clone()))
+}
+
+// Command handler that runs the cleanup rules over a loaded playlist in
+// place, persisting the result for library playlists
+#[tauri::command]
+pub async fn sanitize_playlist(
+    app: AppHandle,
+    store: State<'_, PlaylistStore>,
+    library: State<'_, PlaylistLibrary>,
+    playlist_id: String,
+    options: Option<SanitizeOptions>,
+) -> Result<SanitizeResult, String> {
+    let previous = store
+        .get(&playlist_id)
+        .ok_or_else(|| format!("Playlist '{}' is not loaded", playlist_id))?;
+    let options = options.unwrap_or_default();
+    let entries = previous.as_ref().clone();
+    let (entries, stats) = tauri::async_runtime::spawn_blocking(move || sanitize::sanitize(entries, &options))
+        .await
+        .map_err(|e| format!("Cleanup failed: {}", e))?;
+
+    if library.get(&playlist_id).is_some() {
+        library.save_channels(&playlist_id, &entries)?;
+    }
+    store.set_diff(diff::diff_entries(&playlist_id, &previous, &entries));
+    let summary = PlaylistSummary {
+        total: store.insert(&playlist_id, xtream_server(&app, &playlist_id).as_deref(), entries),
+        id: playlist_id,
+    };
+    let _ = app.emit("playlist-updated", summary.clone());
+    Ok(SanitizeResult { summary, stats })
+}
+
+// Xtream panel a playlist's stream URLs are keyed with, see identity::url_key
+fn xtream_server(app: &AppHandle, playlist_id: &str) -> Option<String> {
+    let config = app.state::<PlaylistLibrary>().get(playlist_id);
+    config
+        .and_then(|config| identity::xtream_server(&config.source))
+        .or_else(|| app.state::<PlaylistStore>().xtream_server(playlist_id))
+}
+
+// Swap in edited entries for a loaded playlist, saving them for library
+// playlists and emitting playlist-updated
+fn replace_entries(app: &AppHandle, playlist_id: &str, entries: Vec<ChannelEntry>) -> Result<PlaylistSummary, String> {
+    let library = app.state::<PlaylistLibrary>();
+    if library.get(playlist_id).is_some() {
+        library.save_channels(playlist_id, &entries)?;
+    }
+    let summary = PlaylistSummary {
+        id: playlist_id.to_string(),
+        total: app.state::<PlaylistStore>().insert(playlist_id, xtream_server(app, playlist_id).as_deref(), entries),
+    };
+    let _ = app.emit("playlist-updated", summary.clone());
+    Ok(summary)
+}
+
+// Command handler applying a batch of channel edits at once; either every
+// edit applies or none does
+#[tauri::command]
+pub async fn bulk_edit_channels(
+    app: AppHandle,
+    store: State<'_, PlaylistStore>,
+    playlist_id: String,
+    edits: Vec<ChannelEdit>,
+) -> Result<PlaylistSummary, String> {
+    if edits.len() > bulk_edit::MAX_EDITS {
+        return Err(format!("Too many edits (maximum is {})", bulk_edit::MAX_EDITS));
+    }
+    let entries = store
+        .get(&playlist_id)
+        .ok_or_else(|| format!("Playlist '{}' is not loaded", playlist_id))?;
+    let changes: Vec<(String, ChannelOverride)> = edits.iter().flat_map(bulk_edit::as_overrides).collect();
+    let entries = tauri::async_runtime::spawn_blocking(move || bulk_edit::apply(&entries, &edits))
+        .await
+        .map_err(|e| format!("Edit failed: {}", e))??;
+
+    let server = xtream_server(&app, &playlist_id);
+    app.state::<ChannelOverrides>().record(&playlist_id, server.as_deref(), changes)?;
+    replace_entries(&app, &playlist_id, entries)
+}
+
+// Command handler returning the user overrides of a playlist, keyed by
+// channel identity
+#[tauri::command]
+pub fn get_channel_overrides(
+    overrides: State<'_, ChannelOverrides>,
+    playlist_id: String,
+) -> BTreeMap<String, ChannelOverride> {
+    overrides.list(&playlist_id)
+}
+
+// Command handler that replaces the override of one channel and applies it
+// to the loaded playlist; fields dropped from an override only revert to the
+// provider value on the next refresh
+#[tauri::command]
+pub fn set_channel_override(
+    app: AppHandle,
+    overrides: State<'_, ChannelOverrides>,
+    store: State<'_, PlaylistStore>,
+    playlist_id: String,
+    url: String,
+    value: ChannelOverride,
+) -> Result<(), String> {
+    validate_string_length(&url, MAX_SOURCE_LENGTH)?;
+    for backup in value.backup_urls.iter().flatten() {
+        validate_string_length(backup, MAX_SOURCE_LENGTH)?;
+    }
+    let server = xtream_server(&app, &playlist_id);
+    overrides.set(&playlist_id, server.as_deref(), &url, value)?;
+
+    let Some(entries) = store.get(&playlist_id) else {
+        return Ok(());
+    };
+    let mut entries = entries.as_ref().clone();
+    overrides.apply(&playlist_id, server.as_deref(), &mut entries);
+    replace_entries(&app, &playlist_id, entries).map(|_| ())
+}
+
+// Numbering state of a loaded playlist
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NumberingReport {
+    pub numbered: usize,
+    pub unnumbered: usize,
+    pub conflicts: Vec<NumberConflict>,
+}
+
+fn loaded(store: &PlaylistStore, playlist_id: &str) -> Result<Arc<Vec<ChannelEntry>>, String> {
+    store
+        .get(playlist_id)
+        .ok_or_else(|| format!("Playlist '{}' is not loaded", playlist_id))
+}
+
+// Persist channel numbers as overrides and apply them to the loaded playlist
+fn assign_numbers(app: &AppHandle, playlist_id: &str, numbers: Vec<(String, u32)>) -> Result<PlaylistSummary, String> {
+    let entries = loaded(&app.state::<PlaylistStore>(), playlist_id)?;
+    let overrides = app.state::<ChannelOverrides>();
+    let changes = numbers
+        .into_iter()
+        .map(|(url, number)| {
+            let change = ChannelOverride {
+                number: Some(number),
+                ..Default::default()
+            };
+            (url, change)
+        })
+        .collect();
+    let server = xtream_server(app, playlist_id);
+    overrides.record(playlist_id, server.as_deref(), changes)?;
+
+    let mut entries = entries.as_ref().clone();
+    overrides.apply(playlist_id, server.as_deref(), &mut entries);
+    replace_entries(app, playlist_id, entries)
+}
+
+// Command handler reporting channel numbers in use and their conflicts
+#[tauri::command]
+pub fn get_channel_numbering(store: State<'_, PlaylistStore>, playlist_id: String) -> Result<NumberingReport, String> {
+    let entries = loaded(&store, &playlist_id)?;
+    let numbered = entries.iter().filter(|entry| numbering::number(entry).is_some()).count();
+    Ok(NumberingReport {
+        numbered,
+        unnumbered: entries.len() - numbered,
+        conflicts: numbering::conflicts(&entries),
+    })
+}
+
+// Command handler returning the channel a typed number leads to
+#[tauri::command]
+pub fn find_channel_by_number(
+    store: State<'_, PlaylistStore>,
+    playlist_id: String,
+    number: u32,
+) -> Result<Option<ChannelEntry>, String> {
+    let entries = loaded(&store, &playlist_id)?;
+    Ok(numbering::find(&entries, number).cloned())
+}
+
+// Command handler building the archive URL of a past programme on a
+// channel; refused while parental controls restrict that programme
+#[tauri::command]
+pub fn get_catchup_url(
+    app: AppHandle,
+    store: State<'_, PlaylistStore>,
+    playlist_id: String,
+    url: String,
+    start: i64,
+    end: i64,
+) -> Result<String, String> {
+    let entries = loaded(&store, &playlist_id)?;
+    let entry = entries
+        .iter()
+        .find(|entry| entry.url == url)
+        .ok_or_else(|| format!("Channel '{}' is not in playlist '{}'", url, playlist_id))?;
+    if let Some(guide) = parental::guide_channel(&app, None, Some(&entry.channel_id))? {
+        parental::enforce_archive(&app, &guide, start)?;
+    }
+    catchup::archive_url(entry, catchup::ArchiveWindow { start, end, now: unix_now() })
+}
+
+// Command handler numbering the given channels consecutively from `start`
+#[tauri::command]
+pub fn renumber_channels(
+    app: AppHandle,
+    playlist_id: String,
+    urls: Vec<String>,
+    start: u32,
+) -> Result<PlaylistSummary, String> {
+    let numbers = numbering::sequence(&urls, start)?;
+    assign_numbers(&app, &playlist_id, numbers)
+}
+
+// Command handler numbering a group's channels consecutively, in playlist
+// order, from `start`
+#[tauri::command]
+pub fn resequence_group(
+    app: AppHandle,
+    store: State<'_, PlaylistStore>,
+    playlist_id: String,
+    group: String,
+    start: u32,
+) -> Result<PlaylistSummary, String> {
+    let urls = numbering::group_urls(&loaded(&store, &playlist_id)?, &group);
+    if urls.is_empty() {
+        return Err(format!("Group '{}' has no channels", group));
+    }
+    let numbers = numbering::sequence(&urls, start)?;
+    assign_numbers(&app, &playlist_id, numbers)
+}
+
+// Command handler moving every channel with a contested number to the next
+// free one, keeping the first holder in place
+#[tauri::command]
+pub fn resolve_number_conflicts(
+    app: AppHandle,
+    store: State<'_, PlaylistStore>,
+    playlist_id: String,
+) -> Result<PlaylistSummary, String> {
+    let numbers = numbering::resolve_conflicts(&loaded(&store, &playlist_id)?);
+    assign_numbers(&app, &playlist_id, numbers)
+}
+
+// Command handler that flags probable duplicate channels within and across
+// loaded playlists; threshold is the minimum name similarity (0.5 to 1.0)
+#[tauri::command]
+pub async fn find_duplicate_channels(
+    store: State<'_, PlaylistStore>,
+    ids: Vec<String>,
+    threshold: Option<f64>,
+) -> Result<Vec<DuplicateGroup>, String> {
+    if ids.is_empty() {
+        return Err("Select at least one playlist to analyze".to_string());
+    }
+    let playlists = ids
+        .iter()
+        .map(|id| {
+            store
+                .get(id)
+                .map(|entries| (id.clone(), entries))
+                .ok_or_else(|| format!("Playlist '{}' is not loaded", id))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let threshold = threshold.unwrap_or(duplicates::DEFAULT_THRESHOLD).clamp(0.5, 1.0);
+    tauri::async_runtime::spawn_blocking(move || duplicates::find(&playlists, threshold))
+        .await
+        .map_err(|e| format!("Duplicate analysis failed: {}", e))
+}
+
+// Command handler that combines loaded playlists into a new deduplicated
+// one, stored under an id derived from the merged ids
+#[tauri::command]
+pub async fn merge_playlists(
+    store: State<'_, PlaylistStore>,
+    ids: Vec<String>,
+    strategy: Option<MergeStrategy>,
+) -> Result<MergeResult, String> {
+    if ids.is_empty() {
+        return Err("Select at least one playlist to merge".to_string());
+    }
+    let playlists = ids
+        .iter()
+        .map(|id| {
+            store
+                .get(id)
+                .map(|entries| (id.clone(), entries))
+                .ok_or_else(|| format!("Playlist '{}' is not loaded", id))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let strategy = strategy.unwrap_or_default();
+    let (entries, duplicates) =
+        tauri::async_runtime::spawn_blocking(move || merge::merge(&playlists, &strategy))
+            .await
+            .map_err(|e| format!("Merge failed: {}", e))?;
+
+    let id = playlist_id(&format!("merged:{}", ids.join(",")));
+    let total = store.insert(&id, None, entries);
+    Ok(MergeResult {
+        summary: PlaylistSummary { id, total },
+        duplicates,
+    })
+}
+
+// Command handler that writes a loaded (possibly merged) playlist to disk
+// as M3U Plus or JSON; returns the number of channels written
+#[tauri::command]
+pub async fn export_playlist(
+    store: State<'_, PlaylistStore>,
+    playlist_id: String,
+    format: ExportFormat,
+    path: String,
+) -> Result<usize, String> {
+    validate_string_length(&path, MAX_SOURCE_LENGTH)?;
+    if path.trim().is_empty() {
+        return Err("Export path cannot be empty".to_string());
+    }
+    let entries = store
+        .get(&playlist_id)
+        .ok_or_else(|| format!("Playlist '{}' is not loaded", playlist_id))?;
+
+    let content = match format {
+        ExportFormat::M3u => export::to_m3u(&entries),
+        ExportFormat::Json => export::to_json(&entries)?,
+    };
+    tokio::fs::write(path.trim(), content)
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", path.trim(), e))?;
+    Ok(entries.len())
+}
+
+// Command handler importing an Enigma2 bouquet file or a copied
+// /etc/enigma2 directory; DVB services play through `box_url`
+#[tauri::command]
+pub async fn import_enigma2(
+    store: State<'_, PlaylistStore>,
+    path: String,
+    box_url: Option<String>,
+) -> Result<PlaylistSummary, String> {
+    let path = check_source(&path)?.to_string();
+    let box_url = match box_url.as_deref().map(str::trim).filter(|url| !url.is_empty()) {
+        Some(url) => {
+            validate_string_length(url, MAX_SOURCE_LENGTH)?;
+            if !source::is_remote(url) {
+                return Err("Receiver URL must start with http:// or https://".to_string());
+            }
+            Some(url.to_string())
+        }
+        None => None,
+    };
+
+    let id = playlist_id(&path);
+    let entries = tauri::async_runtime::spawn_blocking(move || {
+        enigma2::import(std::path::Path::new(&path), box_url.as_deref())
+    })
+    .await
+    .map_err(|e| format!("Import failed: {}", e))??;
+
+    let total = store.insert(&id, None, entries);
+    Ok(PlaylistSummary { id, total })
+}
+
+// Import a CSV channel list (see csv_io for the column layout) into the store
+pub async fn import_csv(store: &PlaylistStore, path: &str) -> Result<PlaylistSummary, String> {
+    let path = check_source(path)?.to_string();
+    let id = playlist_id(&path);
+    let entries = tauri::async_runtime::spawn_blocking(move || csv_io::read(std::path::Path::new(&path)))
+        .await
+        .map_err(|e| format!("Import failed: {}", e))??;
+
+    let total = store.insert(&id, None, entries);
+    Ok(PlaylistSummary { id, total })
+}
+
+// Command handler importing a channel list from CSV
+#[tauri::command]
+pub async fn import_channels_csv(store: State<'_, PlaylistStore>, path: String) -> Result<PlaylistSummary, String> {
+    import_csv(&store, &path).await
+}
+
+// Command handler writing a loaded playlist as CSV; returns the row count
+#[tauri::command]
+pub async fn export_channels_csv(
+    store: State<'_, PlaylistStore>,
+    playlist_id: String,
+    path: String,
+) -> Result<usize, String> {
+    let path = check_source(&path)?.to_string();
+    let entries = store
+        .get(&playlist_id)
+        .ok_or_else(|| format!("Playlist '{}' is not loaded", playlist_id))?;
+
+    let total = entries.len();
+    tauri::async_runtime::spawn_blocking(move || csv_io::write(std::path::Path::new(&path), &entries))
+        .await
+        .map_err(|e| format!("Export failed: {}", e))??;
+    Ok(total)
+}
+
+// Command handler listing the configured playlists
+#[tauri::command]
+pub fn list_playlists(library: State<'_, PlaylistLibrary>) -> Vec<PlaylistConfig> {
+    library.list()
+}
+
+// Command handler that adds a playlist to the library; the scheduler
+// fetches it on its next pass when an interval is set
+#[tauri::command]
+pub fn add_playlist(
+    library: State<'_, PlaylistLibrary>,
+    watcher: State<'_, PlaylistWatcher>,
+    name: String,
+    source: String,
+    refresh_interval_hours: Option<u32>,
+) -> Result<PlaylistConfig, String> {
+    let name = check_name(&name)?;
+    let source = check_source(&source)?;
+    let id = playlist_id(source);
+    let existing = library.get(&id).unwrap_or_default();
+    let config = PlaylistConfig {
+        id,
+        name: name.to_string(),
+        source: source.to_string(),
+        refresh_interval_hours,
+        ..existing
+    };
+    library.upsert(config.clone())?;
+    watcher.watch(&config.source);
+    Ok(config)
+}
+
+// Command handler that renames a playlist or changes its refresh interval
+#[tauri::command]
+pub fn update_playlist(
+    library: State<'_, PlaylistLibrary>,
+    playlist_id: String,
+    name: String,
+    refresh_interval_hours: Option<u32>,
+) -> Result<PlaylistConfig, String> {
+    let name = check_name(&name)?;
+    let mut config = library
+        .get(&playlist_id)
+        .ok_or_else(|| format!("Playlist '{}' is not configured", playlist_id))?;
+    config.name = name.to_string();
+    config.refresh_interval_hours = refresh_interval_hours;
+    library.upsert(config.clone())?;
+    Ok(config)
+}
+
+// Command handler storing the headers and basic-auth credentials used for
+// a playlist and its streams; applied from the next refresh on
+#[tauri::command]
+pub fn set_playlist_http(
+    library: State<'_, PlaylistLibrary>,
+    playlist_id: String,
+    http: HttpOptions,
+    auth: Option<BasicAuth>,
+) -> Result<PlaylistConfig, String> {
+    check_http_options(&http)?;
+    check_auth(auth.as_ref())?;
+
+    let mut config = library
+        .get(&playlist_id)
+        .ok_or_else(|| format!("Playlist '{}' is not configured", playlist_id))?;
+    config.http = http;
+    config.auth = auth.filter(|auth| !auth.username.is_empty());
+    library.upsert(config.clone())?;
+    Ok(config)
+}
+
+// Remove a configured playlist with its saved channels, versions,
+// overrides and EPG mappings
+pub fn forget_playlist(app: &AppHandle, playlist_id: &str) -> Result<bool, String> {
+    let library = app.state::<PlaylistLibrary>();
+    let Some(config) = library.get(playlist_id) else {
+        return Ok(false);
+    };
+    let removed = library.remove(playlist_id)?;
+    if removed {
+        app.state::<PlaylistStore>().remove(playlist_id);
+        app.state::<PlaylistHistory>().remove(playlist_id);
+        app.state::<ChannelOverrides>().remove_playlist(playlist_id)?;
+        app.state::<EpgMappings>().remove_provider(playlist_id)?;
+        app.state::<PlaylistWatcher>().unwatch(&config.source);
+    }
+    Ok(removed)
+}
+
+// Command handler that removes a playlist and its saved channels
+#[tauri::command]
+pub fn remove_playlist(app: AppHandle, playlist_id: String) -> Result<bool, String> {
+    forget_playlist(&app, &playlist_id)
+}
+
+// Command handler listing the stored versions of a playlist, newest first
+#[tauri::command]
+pub fn list_playlist_versions(
+    library: State<'_, PlaylistLibrary>,
+    history: State<'_, PlaylistHistory>,
+    playlist_id: String,
+) -> Result<Vec<PlaylistVersion>, String> {
+    if library.get(&playlist_id).is_none() {
+        return Err(format!("Playlist '{}' is not configured", playlist_id));
+    }
+    history.versions(&playlist_id)
+}
+
+// Command handler that restores a stored version of a configured playlist
+// until its next successful refresh
+#[tauri::command]
+pub fn rollback_playlist(
+    app: AppHandle,
+    library: State<'_, PlaylistLibrary>,
+    history: State<'_, PlaylistHistory>,
+    store: State<'_, PlaylistStore>,
+    playlist_id: String,
+    version: u32,
+) -> Result<PlaylistSummary, String> {
+    if library.get(&playlist_id).is_none() {
+        return Err(format!("Playlist '{}' is not configured", playlist_id));
+    }
+    let entries = history.load(&playlist_id, version)?;
+    library.save_channels(&playlist_id, &entries)?;
+    if let Some(previous) = store.get(&playlist_id) {
+        store.set_diff(diff::diff_entries(&playlist_id, &previous, &entries));
+    }
+
+    let summary = PlaylistSummary {
+        total: store.insert(&playlist_id, xtream_server(&app, &playlist_id).as_deref(), entries),
+        id: playlist_id,
+    };
+    let _ = app.emit("playlist-updated", summary.clone());
+    Ok(summary)
+}
+
+// Command handler returning a slice of a previously loaded playlist
+#[tauri::command]
+pub fn get_playlist_page(
+    store: State<'_, PlaylistStore>,
+    playlist_id: String,
+    offset: usize,
+    limit: usize,
+) -> Result<PlaylistPage, String> {
+    store
+        .page(&playlist_id, offset, limit.min(MAX_PAGE_SIZE))
+        .ok_or_else(|| format!("Playlist '{}' is not loaded", playlist_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str) -> ChannelEntry {
+        ChannelEntry {
+            name: name.to_string(),
+            url: format!("http://example.com/{}.ts", name),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_playlist_id_is_stable() {
+        // Test that the id only depends on the trimmed source
+        assert_eq!(playlist_id("http://a/list.m3u"), playlist_id(" http://a/list.m3u "));
+        assert_ne!(playlist_id("http://a/list.m3u"), playlist_id("http://b/list.m3u"));
+        assert_eq!(playlist_id("x").len(), 16);
+    }
+
+    #[test]
+    fn test_store_pages() {
+        // Test that pages are clamped to the playlist bounds
+        let store = PlaylistStore::default();
+        let total = store.insert("p", None, (0..10).map(|i| entry(&i.to_string())).collect());
+        assert_eq!(total, 10);
+
+        let page = store.page("p", 8, 5).unwrap();
+        assert_eq!(page.entries.len(), 2);
+        assert_eq!(page.offset, 8);
+        assert_eq!(page.total, 10);
+
+        let past_end = store.page("p", 50, 5).unwrap();
+        assert!(past_end.entries.is_empty());
+        assert!(store.page("missing", 0, 5).is_none());
+    }
+
+    #[test]
+    fn test_check_http_options() {
+        // Test that oversized header sets and line breaks in headers are rejected
+        let mut http = HttpOptions::default();
+        http.set_header("X-Forwarded-For", "1.2.3.4");
+        assert!(check_http_options(&http).is_ok());
+
+        http.set_header("User-Agent", &"a".repeat(MAX_HEADER_LENGTH + 1));
+        assert!(check_http_options(&http).is_err());
+
+        let mut many = HttpOptions::default();
+        for i in 0..=MAX_HEADERS {
+            many.set_header(&format!("X-{}", i), "1");
+        }
+        assert!(check_http_options(&many).is_err());
+
+        let mut injected = HttpOptions::default();
+        injected.set_header("Referer", "http://a/\r\nX-Injected: 1");
+        assert!(check_http_options(&injected).is_err());
+        let mut injected = HttpOptions::default();
+        injected.set_header("X-Token", "a\nb");
+        assert!(check_http_options(&injected).is_err());
+        let mut named = HttpOptions::default();
+        named.set_header("X-Injected: 1", "2");
+        assert!(check_http_options(&named).is_err());
+    }
+
+    #[test]
+    fn test_check_name() {
+        // Test that names are trimmed and empty or oversized ones rejected
+        assert_eq!(check_name("  My list "), Ok("My list"));
+        assert!(check_name("   ").is_err());
+        assert!(check_name(&"a".repeat(MAX_NAME_LENGTH + 1)).is_err());
+    }
+
+    #[test]
+    fn test_read_entries_reports_progress() {
+        // Test that the streaming reader parses every entry and reports bytes
+        let mut content = String::from("#EXTM3U\n");
+        for i in 0..(PROGRESS_INTERVAL + 5) {
+            content.push_str(&format!("#EXTINF:-1,Channel {}\nhttp://example.com/{}.ts\n", i, i));
+        }
+
+        let mut reports = Vec::new();
+        let entries = tauri::async_runtime::block_on(read_entries(content.as_bytes(), None, |n, bytes| {
+            reports.push((n, bytes))
+        }))
+        .unwrap();
+
+        assert_eq!(entries.len(), PROGRESS_INTERVAL + 5);
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].0, PROGRESS_INTERVAL);
+        assert_eq!(reports[1], (entries.len(), content.len() as u64));
+    }
+
+    #[test]
+    fn test_read_entries_detects_format() {
+        // Test that PLS and XSPF content is routed to the matching parser
+        let pls = "[playlist]\nFile1=http://radio/a\nTitle1=Radio A\n";
+        let xspf = "<playlist><trackList><track><location>http://radio/b</location></track></trackList></playlist>";
+
+        let read = |content: &str| {
+            tauri::async_runtime::block_on(read_entries(content.as_bytes(), None, |_, _| {})).unwrap()
+        };
+        assert_eq!(read(pls)[0].name, "Radio A");
+        assert_eq!(read(xspf)[0].url, "http://radio/b");
+    }
+}
@@ -0,0 +1,170 @@
+// Logical channel numbers (tvg-chno / LCN) for jumping to a channel by
+// typing its number
+use std::collections::{BTreeMap, HashSet};
+
+use serde::Serialize;
+
+use super::{ChannelEntry, NUMBER_ATTRIBUTE};
+
+// Attributes providers use for the channel number, in order of preference
+const NUMBER_ATTRIBUTES: [&str; 4] = [NUMBER_ATTRIBUTE, "lcn", "tvg-num", "channel-number"];
+
+// Highest number a remote-style number entry is expected to handle
+pub const MAX_CHANNEL_NUMBER: u32 = 99_999;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NumberedChannel {
+    pub index: usize,
+    pub name: String,
+    pub url: String,
+}
+
+// Several channels claiming the same number
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NumberConflict {
+    pub number: u32,
+    pub channels: Vec<NumberedChannel>,
+}
+
+pub fn number(entry: &ChannelEntry) -> Option<u32> {
+    NUMBER_ATTRIBUTES
+        .iter()
+        .filter_map(|name| entry.attributes.get(*name))
+        .find_map(|value| value.trim().parse().ok())
+        .filter(|number| (1..=MAX_CHANNEL_NUMBER).contains(number))
+}
+
+// Numbers held by more than one channel, lowest number first
+pub fn conflicts(entries: &[ChannelEntry]) -> Vec<NumberConflict> {
+    let mut holders: BTreeMap<u32, Vec<NumberedChannel>> = BTreeMap::new();
+    for (index, entry) in entries.iter().enumerate() {
+        if let Some(number) = number(entry) {
+            holders.entry(number).or_default().push(NumberedChannel {
+                index,
+                name: entry.name.clone(),
+                url: entry.url.clone(),
+            });
+        }
+    }
+    holders
+        .into_iter()
+        .filter(|(_, channels)| channels.len() > 1)
+        .map(|(number, channels)| NumberConflict { number, channels })
+        .collect()
+}
+
+pub fn find(entries: &[ChannelEntry], wanted: u32) -> Option<&ChannelEntry> {
+    entries.iter().find(|entry| number(entry) == Some(wanted))
+}
+
+// Consecutive numbers from `start` for the given URLs, in order
+pub fn sequence(urls: &[String], start: u32) -> Result<Vec<(String, u32)>, String> {
+    if start == 0 {
+        return Err("Channel numbers start at 1".to_string());
+    }
+    let last = u64::from(start) + urls.len().saturating_sub(1) as u64;
+    if last > u64::from(MAX_CHANNEL_NUMBER) {
+        return Err(format!("Channel numbers can't exceed {}", MAX_CHANNEL_NUMBER));
+    }
+    Ok(urls.iter().cloned().zip(start..).collect())
+}
+
+// URLs of a group's channels in playlist order, skipping repeated streams
+pub fn group_urls(entries: &[ChannelEntry], group: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    entries
+        .iter()
+        .filter(|entry| entry.group_title.as_deref() == Some(group))
+        .filter(|entry| seen.insert(entry.url.as_str()))
+        .map(|entry| entry.url.clone())
+        .collect()
+}
+
+// New numbers that settle every conflict: the first channel in playlist
+// order keeps a contested number and the others move to the next free one
+pub fn resolve_conflicts(entries: &[ChannelEntry]) -> Vec<(String, u32)> {
+    let mut used: HashSet<u32> = entries.iter().filter_map(number).collect();
+    let mut claimed = HashSet::new();
+    let mut changes = Vec::new();
+    for entry in entries {
+        let Some(wanted) = number(entry) else {
+            continue;
+        };
+        if claimed.insert(wanted) {
+            continue;
+        }
+        let Some(free) = (wanted + 1..=MAX_CHANNEL_NUMBER).find(|n| !used.contains(n)) else {
+            continue;
+        };
+        used.insert(free);
+        claimed.insert(free);
+        changes.push((entry.url.clone(), free));
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, attribute: Option<(&str, &str)>) -> ChannelEntry {
+        let mut entry = ChannelEntry {
+            name: name.to_string(),
+            url: format!("http://x/{}", name),
+            group_title: Some("G".to_string()),
+            ..Default::default()
+        };
+        if let Some((key, value)) = attribute {
+            entry.attributes.insert(key.to_string(), value.to_string());
+        }
+        entry
+    }
+
+    #[test]
+    fn test_number_attributes() {
+        // Test that tvg-chno wins over lcn and junk values are ignored
+        let mut both = entry("a", Some(("lcn", "7")));
+        assert_eq!(number(&both), Some(7));
+        both.attributes.insert(NUMBER_ATTRIBUTE.to_string(), "101".to_string());
+        assert_eq!(number(&both), Some(101));
+        assert_eq!(number(&entry("b", Some(("tvg-chno", "abc")))), None);
+        assert_eq!(number(&entry("c", Some(("tvg-chno", "0")))), None);
+    }
+
+    #[test]
+    fn test_conflicts_and_resolution() {
+        // Test that duplicate numbers are reported and moved to free numbers
+        let entries = vec![
+            entry("a", Some(("tvg-chno", "1"))),
+            entry("b", Some(("tvg-chno", "1"))),
+            entry("c", Some(("tvg-chno", "2"))),
+            entry("d", Some(("lcn", "1"))),
+            entry("e", None),
+        ];
+        let found = conflicts(&entries);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].number, 1);
+        assert_eq!(found[0].channels.iter().map(|c| c.index).collect::<Vec<_>>(), vec![0, 1, 3]);
+
+        let changes = resolve_conflicts(&entries);
+        assert_eq!(
+            changes,
+            vec![("http://x/b".to_string(), 3), ("http://x/d".to_string(), 4)]
+        );
+        assert_eq!(find(&entries, 2).map(|e| e.name.as_str()), Some("c"));
+    }
+
+    #[test]
+    fn test_sequence_bounds() {
+        // Test consecutive numbering and its limits
+        let urls = vec!["u1".to_string(), "u2".to_string()];
+        assert_eq!(sequence(&urls, 10).unwrap(), vec![("u1".to_string(), 10), ("u2".to_string(), 11)]);
+        assert!(sequence(&urls, 0).is_err());
+        assert!(sequence(&urls, MAX_CHANNEL_NUMBER).is_err());
+
+        let entries = vec![entry("a", None), entry("a", None), entry("b", None)];
+        assert_eq!(group_urls(&entries, "G"), vec!["http://x/a", "http://x/b"]);
+    }
+}
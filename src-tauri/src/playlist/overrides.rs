@@ -0,0 +1,288 @@
+// User fixes to individual channels, kept apart from the provider data and
+// re-applied every time a playlist is parsed
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use super::{ChannelEntry, PlaylistLibrary, NUMBER_ATTRIBUTE};
+use crate::identity;
+use crate::storage;
+
+const OVERRIDES_FILE: &str = "channel_overrides.json";
+
+// Fields the user replaced; None keeps the provider value. An empty group
+// or logo removes the provider's one
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelOverride {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logo: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tvg_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hidden: Option<bool>,
+    // Channel number, stored as the tvg-chno attribute
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub number: Option<u32>,
+    // Replaces the channel's backup streams
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup_urls: Option<Vec<String>>,
+}
+
+impl ChannelOverride {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    // Take every field set in `other`
+    pub fn merge(&mut self, other: ChannelOverride) {
+        let ChannelOverride {
+            name,
+            logo,
+            tvg_id,
+            group,
+            hidden,
+            number,
+            backup_urls,
+        } = other;
+        self.name = name.or(self.name.take());
+        self.logo = logo.or(self.logo.take());
+        self.tvg_id = tvg_id.or(self.tvg_id.take());
+        self.group = group.or(self.group.take());
+        self.hidden = hidden.or(self.hidden.take());
+        self.number = number.or(self.number.take());
+        self.backup_urls = backup_urls.or(self.backup_urls.take());
+    }
+
+    fn apply(&self, entry: &mut ChannelEntry) {
+        let optional = |value: &str| (!value.is_empty()).then(|| value.to_string());
+        if let Some(name) = self.name.as_deref().filter(|name| !name.is_empty()) {
+            entry.name = name.to_string();
+        }
+        if let Some(logo) = &self.logo {
+            entry.tvg_logo = optional(logo);
+        }
+        if let Some(tvg_id) = &self.tvg_id {
+            entry.tvg_id = optional(tvg_id);
+        }
+        if let Some(group) = &self.group {
+            entry.group_title = optional(group);
+        }
+        if let Some(hidden) = self.hidden {
+            entry.hidden = hidden;
+        }
+        if let Some(number) = self.number {
+            entry.attributes.insert(NUMBER_ATTRIBUTE.to_string(), number.to_string());
+        }
+        if let Some(backup_urls) = &self.backup_urls {
+            let urls = backup_urls.iter().map(|url| url.trim()).filter(|url| !url.is_empty() && *url != entry.url);
+            entry.backup_urls = urls.map(str::to_string).collect();
+        }
+    }
+}
+
+type PlaylistOverrides = HashMap<String, BTreeMap<String, ChannelOverride>>;
+
+// Older files keyed channels by their URL without the query string
+fn migrate(overrides: &mut PlaylistOverrides, library: &PlaylistLibrary) {
+    for (playlist_id, playlist) in overrides.iter_mut() {
+        if playlist.keys().any(|key| key.contains("://")) {
+            let server = library
+                .get(playlist_id)
+                .and_then(|config| identity::xtream_server(&config.source));
+            *playlist = std::mem::take(playlist)
+                .into_iter()
+                .map(|(key, value)| {
+                    let key = if key.contains("://") { identity::url_key(&key, server.as_deref()) } else { key };
+                    (key, value)
+                })
+                .collect();
+        }
+    }
+}
+
+pub struct ChannelOverrides {
+    path: PathBuf,
+    // Playlist id -> stream key -> override
+    overrides: Mutex<PlaylistOverrides>,
+}
+
+impl ChannelOverrides {
+    pub fn open(data_dir: &Path, library: &PlaylistLibrary) -> Self {
+        let path = data_dir.join(OVERRIDES_FILE);
+        let mut overrides = storage::read_json(&path).unwrap_or_else(|e| {
+            log::error!("Failed to load channel overrides: {}", e);
+            HashMap::new()
+        });
+        migrate(&mut overrides, library);
+        Self {
+            path,
+            overrides: Mutex::new(overrides),
+        }
+    }
+
+    // Overrides of a playlist keyed by channel id
+    pub fn list(&self, playlist_id: &str) -> BTreeMap<String, ChannelOverride> {
+        let overrides = self.overrides.lock().unwrap();
+        let Some(playlist) = overrides.get(playlist_id) else {
+            return BTreeMap::new();
+        };
+        playlist
+            .iter()
+            .map(|(key, value)| (identity::channel_id(playlist_id, key), value.clone()))
+            .collect()
+    }
+
+    // Merge changes into the overrides of the given channels. Stream URLs are
+    // keyed with the playlist's Xtream panel, see identity::url_key
+    pub fn record(
+        &self,
+        playlist_id: &str,
+        xtream_server: Option<&str>,
+        changes: Vec<(String, ChannelOverride)>,
+    ) -> Result<(), String> {
+        let mut overrides = self.overrides.lock().unwrap();
+        let playlist = overrides.entry(playlist_id.to_string()).or_default();
+        for (url, change) in changes {
+            playlist.entry(identity::url_key(&url, xtream_server)).or_default().merge(change);
+        }
+        storage::write_json(&self.path, &*overrides)
+    }
+
+    // Replace the override of one channel, an empty one removes it
+    pub fn set(
+        &self,
+        playlist_id: &str,
+        xtream_server: Option<&str>,
+        url: &str,
+        value: ChannelOverride,
+    ) -> Result<(), String> {
+        if value.is_empty() {
+            return self.clear(playlist_id, xtream_server, url).map(|_| ());
+        }
+        let mut overrides = self.overrides.lock().unwrap();
+        overrides
+            .entry(playlist_id.to_string())
+            .or_default()
+            .insert(identity::url_key(url, xtream_server), value);
+        storage::write_json(&self.path, &*overrides)
+    }
+
+    // Drop the override of one channel; false if it had none
+    pub fn clear(&self, playlist_id: &str, xtream_server: Option<&str>, url: &str) -> Result<bool, String> {
+        let mut overrides = self.overrides.lock().unwrap();
+        let Some(playlist) = overrides.get_mut(playlist_id) else {
+            return Ok(false);
+        };
+        let removed = playlist.remove(&identity::url_key(url, xtream_server)).is_some();
+        if playlist.is_empty() {
+            overrides.remove(playlist_id);
+        }
+        if removed {
+            storage::write_json(&self.path, &*overrides)?;
+        }
+        Ok(removed)
+    }
+
+    pub fn remove_playlist(&self, playlist_id: &str) -> Result<(), String> {
+        let mut overrides = self.overrides.lock().unwrap();
+        if overrides.remove(playlist_id).is_some() {
+            storage::write_json(&self.path, &*overrides)?;
+        }
+        Ok(())
+    }
+
+    pub fn apply(&self, playlist_id: &str, xtream_server: Option<&str>, entries: &mut [ChannelEntry]) {
+        let overrides = self.overrides.lock().unwrap();
+        let Some(playlist) = overrides.get(playlist_id) else {
+            return;
+        };
+        for entry in entries {
+            if let Some(channel) = playlist.get(&identity::url_key(&entry.url, xtream_server)) {
+                channel.apply(entry);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    fn library(dir: &Path) -> PlaylistLibrary {
+        PlaylistLibrary::open(dir.join("playlists"))
+    }
+
+    fn entry(url: &str) -> ChannelEntry {
+        ChannelEntry {
+            name: "Provider Name".to_string(),
+            url: url.to_string(),
+            tvg_logo: Some("http://logo/provider.png".to_string()),
+            group_title: Some("Junk".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_url_keys_migrate() {
+        // Test that overrides saved under URL keys move to stream keys
+        let dir = temp_dir("channel-overrides-migrate");
+        let hidden = r#"{"p": {"http://a/1.ts": {"hidden": true}}}"#;
+        std::fs::write(dir.join(OVERRIDES_FILE), hidden).unwrap();
+        let overrides = ChannelOverrides::open(&dir, &library(&dir));
+        let id = identity::channel_id("p", &identity::url_key("http://a/1.ts", None));
+        assert_eq!(overrides.list("p")[&id].hidden, Some(true));
+    }
+
+    #[test]
+    fn test_overrides_apply_after_refresh() {
+        // Test that recorded overrides survive reopening and reapply to fresh data
+        let dir = temp_dir("channel-overrides");
+        let overrides = ChannelOverrides::open(&dir, &library(&dir));
+        let change = |name: Option<&str>, group: Option<&str>| ChannelOverride {
+            name: name.map(str::to_string),
+            group: group.map(str::to_string),
+            ..Default::default()
+        };
+        overrides
+            .record("p", None, vec![("http://a/1.ts?token=1".to_string(), change(Some("BBC One"), None))])
+            .unwrap();
+        overrides
+            .record("p", None, vec![("http://a/1.ts?token=2".to_string(), change(None, Some("")))])
+            .unwrap();
+
+        let reopened = ChannelOverrides::open(&dir, &library(&dir));
+        let mut entries = vec![entry("http://a/1.ts?token=3"), entry("http://a/2.ts")];
+        reopened.apply("p", None, &mut entries);
+        assert_eq!(entries[0].name, "BBC One");
+        assert_eq!(entries[0].group_title, None);
+        assert_eq!(entries[0].tvg_logo.as_deref(), Some("http://logo/provider.png"));
+        assert_eq!(entries[1], entry("http://a/2.ts"));
+
+        let mut other = vec![entry("http://a/1.ts")];
+        reopened.apply("q", None, &mut other);
+        assert_eq!(other[0].name, "Provider Name");
+    }
+
+    #[test]
+    fn test_clear_override() {
+        // Test that clearing removes the override and reports whether one existed
+        let dir = temp_dir("channel-overrides-clear");
+        let overrides = ChannelOverrides::open(&dir, &library(&dir));
+        let change = ChannelOverride {
+            hidden: Some(true),
+            ..Default::default()
+        };
+        overrides.record("p", None, vec![("http://a/1.ts".to_string(), change)]).unwrap();
+        assert!(overrides.clear("p", None, "http://a/1.ts?token=x").unwrap());
+        assert!(!overrides.clear("p", None, "http://a/1.ts").unwrap());
+        assert!(overrides.list("p").is_empty());
+    }
+}
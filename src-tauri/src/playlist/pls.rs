@@ -0,0 +1,86 @@
+// PLS (Winamp / SHOUTcast) playlist parser
+use std::collections::BTreeMap;
+
+use super::format::LineParser;
+use super::ChannelEntry;
+
+// PLS entries are spread over numbered FileN / TitleN / LengthN keys that
+// may come in any order, so they are collected and emitted at the end
+#[derive(Debug, Default)]
+pub struct PlsParser {
+    entries: BTreeMap<u32, ChannelEntry>,
+}
+
+impl PlsParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl LineParser for PlsParser {
+    fn push_line(&mut self, line: &str) -> Option<ChannelEntry> {
+        let line = line.trim_start_matches('\u{feff}').trim();
+        let (key, value) = line.split_once('=')?;
+        let key = key.trim().to_ascii_lowercase();
+        let value = value.trim();
+
+        let split = key.find(|c: char| c.is_ascii_digit())?;
+        let index: u32 = key[split..].parse().ok()?;
+        let entry = self.entries.entry(index).or_default();
+        match &key[..split] {
+            "file" => entry.url = value.to_string(),
+            "title" => entry.name = value.to_string(),
+            "length" => entry.duration = value.parse().ok().filter(|d: &f64| *d >= 0.0),
+            _ => {}
+        }
+        None
+    }
+
+    fn finish(self) -> Vec<ChannelEntry> {
+        self.entries
+            .into_values()
+            .filter(|entry| !entry.url.is_empty())
+            .map(|mut entry| {
+                if entry.name.is_empty() {
+                    entry.name = entry.url.clone();
+                }
+                entry
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_pls(content: &str) -> Vec<ChannelEntry> {
+        let mut parser = PlsParser::new();
+        for line in content.lines() {
+            parser.push_line(line);
+        }
+        parser.finish()
+    }
+
+    #[test]
+    fn test_parse_pls() {
+        // Test that numbered keys are grouped per entry, in any order
+        let content = "[playlist]\nTitle2=Jazz\nFile1=http://radio/rock\nTitle1=Rock FM\nLength1=-1\nFile2=http://radio/jazz\nLength2=300\nNumberOfEntries=2\nVersion=2\n";
+        let entries = parse_pls(content);
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name, "Rock FM");
+        assert_eq!(entries[0].url, "http://radio/rock");
+        assert_eq!(entries[0].duration, None);
+        assert_eq!(entries[1].name, "Jazz");
+        assert_eq!(entries[1].duration, Some(300.0));
+    }
+
+    #[test]
+    fn test_parse_pls_without_title_or_file() {
+        // Test that untitled entries use the URL and entries without a file are dropped
+        let entries = parse_pls("[playlist]\nFile1=http://radio/a\nTitle2=Orphan\n");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "http://radio/a");
+    }
+}
@@ -0,0 +1,287 @@
+// Cleanup rules for messy provider playlists (prefixes, junk entries,
+// inconsistent whitespace and casing)
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::ChannelEntry;
+
+// Characters providers use to separate group title levels
+const GROUP_SEPARATORS: [char; 4] = ['|', '/', ';', '>'];
+
+// Longest token treated as a provider prefix ("UK", "FHD", "VIP4K")
+const MAX_PREFIX_LENGTH: usize = 6;
+
+// How channel names are recased
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NameCasing {
+    // "BBC ONE HD" -> "Bbc One HD": two-letter all-caps words are kept
+    Title,
+    Upper,
+    Lower,
+}
+
+// Which rules run; every field is optional in JSON and defaults to the
+// values below
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SanitizeOptions {
+    // Strip country/provider tags like "UK:", "|US|", "[DE]" or "FR - "
+    pub strip_prefixes: bool,
+    // Extra literal prefixes to strip (case-insensitive)
+    pub prefixes: Vec<String>,
+    pub drop_invalid_urls: bool,
+    pub normalize_whitespace: bool,
+    pub casing: Option<NameCasing>,
+    pub collapse_group_separators: bool,
+}
+
+impl Default for SanitizeOptions {
+    fn default() -> Self {
+        Self {
+            strip_prefixes: true,
+            prefixes: Vec::new(),
+            drop_invalid_urls: true,
+            normalize_whitespace: true,
+            casing: None,
+            collapse_group_separators: true,
+        }
+    }
+}
+
+// What a cleanup pass changed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SanitizeStats {
+    // Entries dropped for an empty or invalid URL
+    pub removed: usize,
+    // Entries whose name or group was rewritten
+    pub changed: usize,
+}
+
+fn collapse_whitespace(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// A leading "UK:", "UK |", "UK - ", "|UK|" or "[UK]" tag
+fn strip_tag(name: &str) -> Option<&str> {
+    let is_tag = |tag: &str| {
+        let tag = tag.trim();
+        !tag.is_empty()
+            && tag.len() <= MAX_PREFIX_LENGTH
+            && tag.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+            && tag.chars().any(|c| c.is_ascii_uppercase())
+    };
+
+    for (open, close) in [('[', ']'), ('|', '|'), ('(', ')')] {
+        if let Some(rest) = name.strip_prefix(open) {
+            if let Some((tag, rest)) = rest.split_once(close) {
+                if is_tag(tag) {
+                    return Some(rest.trim_start_matches([':', '-', '|', ' ']));
+                }
+            }
+        }
+    }
+    let end = name.find([':', '|', '-'])?;
+    let (tag, rest) = name.split_at(end);
+    // "UK-1" style names aren't prefixes, a separator needs a space or ':'
+    let spaced = tag.ends_with(' ') || rest.starts_with(':') || rest[1..].starts_with(' ');
+    (is_tag(tag) && spaced).then(|| rest[1..].trim_start())
+}
+
+fn strip_prefixes(name: &str, options: &SanitizeOptions) -> String {
+    let mut name = name.trim();
+    for prefix in &options.prefixes {
+        let prefix = prefix.trim();
+        if !prefix.is_empty() && name.len() >= prefix.len() && name.is_char_boundary(prefix.len()) {
+            let (head, rest) = name.split_at(prefix.len());
+            if head.eq_ignore_ascii_case(prefix) {
+                name = rest.trim_start();
+            }
+        }
+    }
+    if options.strip_prefixes {
+        // Stacked tags such as "|UK| VIP: News"
+        while let Some(rest) = strip_tag(name).filter(|rest| !rest.trim().is_empty()) {
+            name = rest.trim_start();
+        }
+    }
+    name.to_string()
+}
+
+// A name without its leading country/provider tags
+pub fn strip_tags(name: &str) -> String {
+    strip_prefixes(name, &SanitizeOptions::default())
+}
+
+fn recase(name: &str, casing: NameCasing) -> String {
+    match casing {
+        NameCasing::Upper => name.to_uppercase(),
+        NameCasing::Lower => name.to_lowercase(),
+        NameCasing::Title => name
+            .split(' ')
+            .map(|word| {
+                let all_caps = word.chars().all(|c| !c.is_lowercase());
+                if all_caps && word.chars().count() <= 2 {
+                    return word.to_string();
+                }
+                let mut chars = word.chars();
+                match chars.next() {
+                    Some(first) => first.to_uppercase().chain(chars.flat_map(char::to_lowercase)).collect(),
+                    None => String::new(),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+// Repeated separators become one ("News || UK //  Sport" -> "News | UK / Sport");
+// single ones such as "24/7" are left alone
+fn collapse_separators(group: &str) -> String {
+    let is_separator = |c: &char| GROUP_SEPARATORS.contains(c);
+    let chars: Vec<char> = group.chars().collect();
+    let mut out = String::with_capacity(group.len());
+    let mut i = 0;
+    while i < chars.len() {
+        if !is_separator(&chars[i]) {
+            out.push(chars[i]);
+            i += 1;
+            continue;
+        }
+        let mut end = i;
+        let mut count = 0;
+        while end < chars.len() && (is_separator(&chars[end]) || chars[end].is_whitespace()) {
+            count += usize::from(is_separator(&chars[end]));
+            end += 1;
+        }
+        if count > 1 {
+            out.truncate(out.trim_end().len());
+            out.push(' ');
+            out.push(chars[i]);
+            out.push(' ');
+        } else {
+            out.extend(&chars[i..end]);
+        }
+        i = end;
+    }
+    out.trim_matches(|c: char| c.is_whitespace() || is_separator(&c)).to_string()
+}
+
+fn is_valid_url(url: &str) -> bool {
+    let url = url.trim();
+    if url.is_empty() {
+        return false;
+    }
+    match Url::parse(url) {
+        Ok(parsed) => parsed.has_host() || parsed.scheme() == "file",
+        // Local paths are playable too
+        Err(_) => std::path::Path::new(url).is_absolute(),
+    }
+}
+
+// Apply the cleanup rules to every entry, returning what changed
+pub fn sanitize(entries: Vec<ChannelEntry>, options: &SanitizeOptions) -> (Vec<ChannelEntry>, SanitizeStats) {
+    let mut stats = SanitizeStats::default();
+    let mut cleaned = Vec::with_capacity(entries.len());
+
+    for mut entry in entries {
+        if options.drop_invalid_urls && !is_valid_url(&entry.url) {
+            stats.removed += 1;
+            continue;
+        }
+
+        let mut name = entry.name.clone();
+        if options.normalize_whitespace {
+            name = collapse_whitespace(&name);
+        }
+        name = strip_prefixes(&name, options);
+        if let Some(casing) = options.casing {
+            name = recase(&name, casing);
+        }
+
+        let mut group = entry.group_title.clone();
+        if let Some(title) = &mut group {
+            if options.normalize_whitespace {
+                *title = collapse_whitespace(title);
+            }
+            if options.collapse_group_separators {
+                *title = collapse_separators(title);
+            }
+        }
+        let group = group.filter(|title| !title.is_empty());
+
+        // Entries whose whole name was junk keep their original name
+        if (!name.is_empty() && name != entry.name) || group != entry.group_title {
+            stats.changed += 1;
+        }
+        if !name.is_empty() {
+            entry.name = name;
+        }
+        entry.group_title = group;
+        entry.url = entry.url.trim().to_string();
+        cleaned.push(entry);
+    }
+
+    (cleaned, stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, url: &str, group: Option<&str>) -> ChannelEntry {
+        ChannelEntry {
+            name: name.to_string(),
+            url: url.to_string(),
+            group_title: group.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_strip_prefixes() {
+        // Test that provider tags are stripped but ordinary names are kept
+        let options = SanitizeOptions::default();
+        assert_eq!(strip_prefixes("UK: BBC One", &options), "BBC One");
+        assert_eq!(strip_prefixes("|US| VIP: CNN", &options), "CNN");
+        assert_eq!(strip_prefixes("[DE] ARD", &options), "ARD");
+        assert_eq!(strip_prefixes("FR - TF1", &options), "TF1");
+        assert_eq!(strip_prefixes("BBC-1", &options), "BBC-1");
+        assert_eq!(strip_prefixes("Sky Sports: News", &options), "Sky Sports: News");
+
+        let options = SanitizeOptions {
+            prefixes: vec!["provider.tv".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(strip_prefixes("PROVIDER.TV  Movies", &options), "Movies");
+    }
+
+    #[test]
+    fn test_collapse_separators_and_casing() {
+        // Test group separator cleanup and the casing modes
+        assert_eq!(collapse_separators("News || UK //  Sport|"), "News | UK / Sport");
+        assert_eq!(collapse_separators("| 24/7 Movies"), "24/7 Movies");
+        assert_eq!(recase("BBC ONE HD", NameCasing::Title), "Bbc One HD");
+        assert_eq!(recase("discovery channel", NameCasing::Title), "Discovery Channel");
+        assert_eq!(recase("Cnn", NameCasing::Upper), "CNN");
+    }
+
+    #[test]
+    fn test_sanitize_entries() {
+        // Test that junk entries are dropped and changes are counted
+        let entries = vec![
+            entry("  UK:  BBC   One ", "http://a/1.ts", Some("UK ||  News")),
+            entry("Empty", "  ", None),
+            entry("Broken", "not a url", None),
+            entry("Local", "/media/movie.mkv", Some("Films")),
+            entry("UK:", "rtp://@239.0.0.1:1234", None),
+        ];
+        let (cleaned, stats) = sanitize(entries, &SanitizeOptions::default());
+
+        assert_eq!(stats, SanitizeStats { removed: 2, changed: 1 });
+        let names: Vec<&str> = cleaned.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["BBC One", "Local", "UK:"]);
+        assert_eq!(cleaned[0].group_title.as_deref(), Some("UK | News"));
+    }
+}
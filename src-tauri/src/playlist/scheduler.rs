@@ -0,0 +1,50 @@
+// Background auto-refresh of configured playlists
+use std::time::Duration;
+
+use tauri::{AppHandle, Manager};
+
+use super::{refresh_configured, PlaylistLibrary, PlaylistStore, PlaylistWatcher};
+use crate::identity;
+use crate::unix_now;
+
+// How often the scheduler looks for playlists that are due
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+// Start the refresh loop; runs for the lifetime of the app
+pub fn start(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        restore_persisted(&app);
+
+        loop {
+            let due = app.state::<PlaylistLibrary>().due(unix_now());
+            for playlist in due {
+                if let Err(e) = refresh_configured(&app, &playlist.id).await {
+                    log::warn!("Auto-refresh of playlist '{}' failed: {}", playlist.name, e);
+                }
+            }
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    });
+}
+
+// Load the channels saved by earlier refreshes so the app has data
+// before any network request completes, and watch local playlist files
+fn restore_persisted(app: &AppHandle) {
+    let library = app.state::<PlaylistLibrary>();
+    let store = app.state::<PlaylistStore>();
+    let watcher = app.state::<PlaylistWatcher>();
+
+    for playlist in library.list() {
+        watcher.watch(&playlist.source);
+        if store.get(&playlist.id).is_some() {
+            continue;
+        }
+        match library.load_channels(&playlist.id) {
+            Ok(Some(entries)) => {
+                store.insert(&playlist.id, identity::xtream_server(&playlist.source).as_deref(), entries);
+            }
+            Ok(None) => {}
+            Err(e) => log::warn!("Failed to restore playlist '{}': {}", playlist.name, e),
+        }
+    }
+}
@@ -0,0 +1,244 @@
+// Opening playlist sources (http(s) URLs or local files) as buffered readers
+use std::io::Write;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures_util::{Stream, TryStreamExt};
+use reqwest::header::{CONTENT_ENCODING, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+use tokio::io::{AsyncBufRead, BufReader};
+use tokio_util::io::StreamReader;
+
+use super::cache::{CacheEntry, DownloadCache};
+use super::format::PlaylistFormat;
+use crate::compression::{self, Compression};
+use crate::http::{self, HttpOptions};
+
+// Read buffer used for local playlist files
+const FILE_BUFFER_SIZE: usize = 64 * 1024;
+
+type ByteStream = Pin<Box<dyn Stream<Item = std::io::Result<Bytes>> + Send>>;
+
+// A playlist body that can be consumed incrementally
+pub struct SourceReader {
+    pub reader: Box<dyn AsyncBufRead + Unpin + Send>,
+    // Size of the body in bytes when known up front
+    pub total_bytes: Option<u64>,
+    // Set when the body is being copied into the download cache
+    pub pending_cache: Option<PendingCache>,
+    // Format suggested by the file extension, used when sniffing is inconclusive
+    pub format_hint: Option<PlaylistFormat>,
+}
+
+// A download being copied into the cache while it is parsed
+pub struct PendingCache {
+    pub entry: CacheEntry,
+    write_failed: Arc<AtomicBool>,
+}
+
+impl PendingCache {
+    // Keep the copy only if it was written completely
+    pub fn finish(self, cache: &DownloadCache, url: &str, parsed: bool) {
+        if !parsed || self.write_failed.load(Ordering::SeqCst) {
+            cache.discard(url);
+            return;
+        }
+        if let Err(e) = cache.commit(url, self.entry) {
+            log::warn!("Failed to cache playlist {}: {}", url, e);
+            cache.discard(url);
+        }
+    }
+}
+
+pub enum Opened {
+    // The server confirmed the cached copy is still current
+    NotModified,
+    Body(SourceReader),
+}
+
+// Check whether a source refers to a remote playlist
+pub fn is_remote(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
+}
+
+// Open a playlist source without reading it into memory; gzip, zstd and
+// xz compressed playlists are decompressed on the fly. With a cache, remote
+// bodies are copied to disk as they stream in, and `conditional` requests
+// send the stored ETag / Last-Modified validators.
+pub async fn open(
+    source: &str,
+    options: Option<&HttpOptions>,
+    cache: Option<&DownloadCache>,
+    conditional: bool,
+) -> Result<Opened, String> {
+    if !is_remote(source) {
+        let path = source.strip_prefix("file://").unwrap_or(source);
+        return open_file(Path::new(path)).await.map(Opened::Body);
+    }
+
+    let cached = cache
+        .filter(|_| conditional)
+        .and_then(|cache| cache.entry(source));
+    let mut request = http::get(source, options);
+    if let Some(entry) = &cached {
+        if let Some(etag) = &entry.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &entry.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let response = request
+        .send()
+        .await
+        .map_err(|e| format!("Failed to download playlist: {}", e))?;
+    if response.status() == StatusCode::NOT_MODIFIED && cached.is_some() {
+        return Ok(Opened::NotModified);
+    }
+    if !response.status().is_success() {
+        return Err(format!("Playlist download failed with status {}", response.status()));
+    }
+
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    let encoding = header(CONTENT_ENCODING);
+    let entry = CacheEntry {
+        etag: header(ETAG),
+        last_modified: header(LAST_MODIFIED),
+    };
+    let total_bytes = response.content_length();
+
+    let mut stream: ByteStream = Box::pin(response.bytes_stream().map_err(std::io::Error::other));
+    let mut pending_cache = None;
+    if let Some(cache) = cache {
+        let write_failed = Arc::new(AtomicBool::new(false));
+        let file = cache
+            .ensure_dir()
+            .and_then(|_| std::fs::File::create(cache.partial_path(source)).map_err(|e| e.to_string()));
+        match file {
+            Ok(mut file) => {
+                let failed = write_failed.clone();
+                stream = Box::pin(stream.inspect_ok(move |chunk| {
+                    if file.write_all(chunk).is_err() {
+                        failed.store(true, Ordering::SeqCst);
+                    }
+                }));
+                pending_cache = Some(PendingCache { entry, write_failed });
+            }
+            Err(e) => log::warn!("Playlist cache unavailable: {}", e),
+        }
+    }
+
+    let reader = decompressed(Box::new(StreamReader::new(stream)), encoding.as_deref(), total_bytes).await?;
+    Ok(Opened::Body(SourceReader {
+        pending_cache,
+        format_hint: PlaylistFormat::from_extension(source),
+        ..reader
+    }))
+}
+
+// Open a local playlist file
+pub async fn open_file(path: &Path) -> Result<SourceReader, String> {
+    let file = tokio::fs::File::open(path)
+        .await
+        .map_err(|e| format!("Failed to read playlist file: {}", e))?;
+    let total_bytes = file.metadata().await.ok().map(|m| m.len());
+    let reader = decompressed(Box::new(BufReader::with_capacity(FILE_BUFFER_SIZE, file)), None, total_bytes).await?;
+    Ok(SourceReader {
+        format_hint: PlaylistFormat::from_extension(&path.to_string_lossy()),
+        ..reader
+    })
+}
+
+async fn decompressed(
+    reader: Box<dyn AsyncBufRead + Unpin + Send>,
+    encoding: Option<&str>,
+    total_bytes: Option<u64>,
+) -> Result<SourceReader, String> {
+    let (reader, compression) = compression::decompress(reader, encoding)
+        .await
+        .map_err(|e| format!("Failed to read playlist: {}", e))?;
+
+    Ok(SourceReader {
+        reader,
+        // The compressed size says nothing about how much text will come out
+        total_bytes: if compression == Compression::None { total_bytes } else { None },
+        pending_cache: None,
+        format_hint: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{response, serve, temp_dir};
+    use tokio::io::AsyncReadExt;
+
+    const BODY: &str = "#EXTM3U\n#EXTINF:-1,News\nhttp://example.com/news.ts\n";
+
+    #[test]
+    fn test_conditional_request_returns_not_modified() {
+        // Test that a cached body is revalidated with If-None-Match and a 304
+        // short-circuits the download
+        let (base, requests) = serve(vec![
+            response("200 OK", &[("ETag", "\"v1\"")], BODY),
+            response("304 Not Modified", &[], ""),
+        ]);
+        let url = format!("{}/list.m3u", base);
+        let cache = DownloadCache::open(temp_dir("source-conditional"));
+
+        tauri::async_runtime::block_on(async {
+            let Opened::Body(mut opened) = open(&url, None, Some(&cache), true).await.unwrap() else {
+                panic!("first request should download the body");
+            };
+            let mut text = String::new();
+            opened.reader.read_to_string(&mut text).await.unwrap();
+            drop(opened.reader);
+            assert_eq!(text, BODY);
+            opened.pending_cache.take().unwrap().finish(&cache, &url, true);
+
+            let second = open(&url, None, Some(&cache), true).await.unwrap();
+            assert!(matches!(second, Opened::NotModified));
+        });
+
+        let requests = requests.lock().unwrap();
+        assert!(!requests[0].to_ascii_lowercase().contains("if-none-match"));
+        assert!(requests[1].to_ascii_lowercase().contains("if-none-match: \"v1\""));
+        assert_eq!(std::fs::read_to_string(cache.body_path(&url)).unwrap(), BODY);
+    }
+
+    #[test]
+    fn test_unconditional_request_skips_validators() {
+        // Test that a plain load never sends validators even when cached
+        let (base, requests) = serve(vec![
+            response("200 OK", &[("ETag", "\"v1\"")], BODY),
+            response("200 OK", &[("ETag", "\"v2\"")], BODY),
+        ]);
+        let url = format!("{}/list.m3u", base);
+        let cache = DownloadCache::open(temp_dir("source-unconditional"));
+
+        tauri::async_runtime::block_on(async {
+            for _ in 0..2 {
+                let Opened::Body(mut opened) = open(&url, None, Some(&cache), false).await.unwrap() else {
+                    panic!("unconditional requests always return a body");
+                };
+                let mut text = String::new();
+                opened.reader.read_to_string(&mut text).await.unwrap();
+                drop(opened.reader);
+                opened.pending_cache.take().unwrap().finish(&cache, &url, true);
+            }
+        });
+
+        let requests = requests.lock().unwrap();
+        assert!(!requests[1].to_ascii_lowercase().contains("if-none-match"));
+    }
+}
@@ -0,0 +1,229 @@
+// Auto-import of playlists dropped into a monitored directory
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use super::watcher::debounced;
+use super::{content_hash, playlist_id, refresh_configured, PlaylistConfig, PlaylistLibrary, PlaylistWatcher};
+use crate::storage;
+
+const STATE_FILE: &str = "watch_folder.json";
+
+// Downloader scripts may write a new file in several chunks
+const SETTLE_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FolderState {
+    #[serde(default)]
+    dir: Option<String>,
+    // Content hash -> playlist id of every file imported so far, so the same
+    // list dropped again under another name isn't added twice
+    #[serde(default)]
+    imported: HashMap<String, String>,
+}
+
+pub struct WatchFolder {
+    path: PathBuf,
+    state: Mutex<FolderState>,
+    watcher: Mutex<Option<RecommendedWatcher>>,
+}
+
+impl WatchFolder {
+    pub fn open(data_dir: &Path) -> Self {
+        let path = data_dir.join(STATE_FILE);
+        let state = storage::read_json(&path).unwrap_or_else(|e| {
+            log::error!("Failed to load watch folder settings: {}", e);
+            FolderState::default()
+        });
+        Self {
+            path,
+            state: Mutex::new(state),
+            watcher: Mutex::new(None),
+        }
+    }
+
+    pub fn dir(&self) -> Option<String> {
+        self.state.lock().unwrap().dir.clone()
+    }
+
+    fn set_dir(&self, dir: Option<String>) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        state.dir = dir;
+        storage::write_json(&self.path, &*state)
+    }
+
+    fn imported(&self, hash: &str) -> Option<String> {
+        self.state.lock().unwrap().imported.get(hash).cloned()
+    }
+
+    fn record(&self, hash: String, id: String) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        state.imported.insert(hash, id);
+        storage::write_json(&self.path, &*state)
+    }
+}
+
+fn is_playlist_file(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .map(|ext| ext.eq_ignore_ascii_case("m3u") || ext.eq_ignore_ascii_case("m3u8"))
+            .unwrap_or(false)
+}
+
+// Import one file from the folder as a new library playlist; None when it
+// is already known (same path or identical content)
+async fn import_file(app: &AppHandle, path: &Path) -> Result<Option<PlaylistConfig>, String> {
+    let content = tokio::fs::read(path)
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let hash = content_hash(&content);
+    let source = path.to_string_lossy().into_owned();
+    let id = playlist_id(&source);
+
+    let folder = app.state::<WatchFolder>();
+    let library = app.state::<PlaylistLibrary>();
+    let duplicate = folder.imported(&hash).is_some_and(|known| library.get(&known).is_some());
+    if duplicate || library.get(&id).is_some() {
+        // Changes to files already in the library are picked up by the
+        // playlist file watcher
+        return Ok(None);
+    }
+
+    let name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| source.clone());
+    let config = PlaylistConfig {
+        id: id.clone(),
+        name,
+        source: source.clone(),
+        ..Default::default()
+    };
+    library.upsert(config.clone())?;
+    folder.record(hash, id.clone())?;
+    app.state::<PlaylistWatcher>().watch(&source);
+    refresh_configured(app, &id).await?;
+
+    let _ = app.emit("watch-folder-imported", config.clone());
+    Ok(Some(config))
+}
+
+// Import every playlist file currently in the folder
+async fn scan(app: &AppHandle, dir: &Path) {
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::warn!("Failed to read watch folder {}: {}", dir.display(), e);
+            return;
+        }
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        if !is_playlist_file(&path) {
+            continue;
+        }
+        if let Err(e) = import_file(app, &path).await {
+            log::warn!("Failed to import {}: {}", path.display(), e);
+        }
+    }
+}
+
+// (Re)start monitoring the configured folder and import what is already
+// there, catching files added while the app was closed
+pub fn activate(app: &AppHandle) {
+    let folder = app.state::<WatchFolder>();
+    let mut slot = folder.watcher.lock().unwrap();
+    *slot = None;
+    let Some(dir) = folder.dir().map(PathBuf::from) else {
+        return;
+    };
+
+    let handle = app.clone();
+    let watcher = debounced(SETTLE_DELAY, move |path| {
+        let app = handle.clone();
+        async move {
+            if !is_playlist_file(&path) {
+                return;
+            }
+            if let Err(e) = import_file(&app, &path).await {
+                log::warn!("Failed to import {}: {}", path.display(), e);
+            }
+        }
+    });
+    let Some(mut watcher) = watcher else {
+        return;
+    };
+    if let Err(e) = watcher.watch(&dir, RecursiveMode::NonRecursive) {
+        log::warn!("Failed to watch {}: {}", dir.display(), e);
+        return;
+    }
+    *slot = Some(watcher);
+
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move { scan(&app, &dir).await });
+}
+
+// Command handler returning the monitored folder, if any
+#[tauri::command]
+pub fn get_watch_folder(folder: State<'_, WatchFolder>) -> Option<String> {
+    folder.dir()
+}
+
+// Command handler that sets (or with None, clears) the monitored folder
+#[tauri::command]
+pub fn set_watch_folder(app: AppHandle, path: Option<String>) -> Result<Option<String>, String> {
+    let dir = match path.as_deref().map(str::trim).filter(|p| !p.is_empty()) {
+        Some(path) => {
+            crate::validate_string_length(path, super::MAX_SOURCE_LENGTH)?;
+            let dir = std::fs::canonicalize(path).map_err(|e| format!("Invalid folder {}: {}", path, e))?;
+            if !dir.is_dir() {
+                return Err(format!("{} is not a folder", path));
+            }
+            Some(dir.to_string_lossy().into_owned())
+        }
+        None => None,
+    };
+
+    app.state::<WatchFolder>().set_dir(dir.clone())?;
+    activate(&app);
+    Ok(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn test_is_playlist_file() {
+        // Test that only existing .m3u/.m3u8 files are picked up
+        let dir = temp_dir("watch-folder-files");
+        for name in ["a.m3u", "b.M3U8", "c.txt"] {
+            std::fs::write(dir.join(name), "#EXTM3U\n").unwrap();
+        }
+        assert!(is_playlist_file(&dir.join("a.m3u")));
+        assert!(is_playlist_file(&dir.join("b.M3U8")));
+        assert!(!is_playlist_file(&dir.join("c.txt")));
+        assert!(!is_playlist_file(&dir.join("missing.m3u")));
+    }
+
+    #[test]
+    fn test_state_persists() {
+        // Test that the folder and imported hashes survive reopening
+        let dir = temp_dir("watch-folder-state");
+        let folder = WatchFolder::open(&dir);
+        folder.set_dir(Some("/lists".to_string())).unwrap();
+        folder.record("hash".to_string(), "id".to_string()).unwrap();
+
+        let reopened = WatchFolder::open(&dir);
+        assert_eq!(reopened.dir().as_deref(), Some("/lists"));
+        assert_eq!(reopened.imported("hash").as_deref(), Some("id"));
+    }
+}
@@ -0,0 +1,186 @@
+// Hot-reload of playlists loaded from local files
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tauri::AppHandle;
+use tokio::sync::mpsc;
+
+use super::reload_source;
+
+// Scripts and editors touch a file several times while saving it
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+#[derive(Default)]
+struct Watched {
+    // Watched file -> playlist source it was loaded from
+    files: HashMap<PathBuf, String>,
+    // Directory -> number of watched files inside it
+    dirs: HashMap<PathBuf, usize>,
+}
+
+// A notify watcher whose create/modify events are coalesced per path and
+// handed to `on_change` once the path has been quiet for `delay`
+pub fn debounced<F, Fut>(delay: Duration, on_change: F) -> Option<RecommendedWatcher>
+where
+    F: Fn(PathBuf) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let (tx, mut rx) = mpsc::unbounded_channel::<PathBuf>();
+    let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+            for path in event.paths {
+                let _ = tx.send(path);
+            }
+        }
+    })
+    .map_err(|e| log::warn!("File watching unavailable: {}", e))
+    .ok()?;
+
+    let on_change = Arc::new(on_change);
+    tauri::async_runtime::spawn(async move {
+        // Bumped on every event so only the last one of a burst fires
+        let generations: Arc<Mutex<HashMap<PathBuf, u64>>> = Arc::default();
+        while let Some(path) = rx.recv().await {
+            let generation = {
+                let mut generations = generations.lock().unwrap();
+                let generation = generations.entry(path.clone()).or_default();
+                *generation += 1;
+                *generation
+            };
+
+            let generations = generations.clone();
+            let on_change = on_change.clone();
+            tauri::async_runtime::spawn(async move {
+                tokio::time::sleep(delay).await;
+                {
+                    let mut generations = generations.lock().unwrap();
+                    if generations.get(&path) != Some(&generation) {
+                        return;
+                    }
+                    generations.remove(&path);
+                }
+                on_change(path).await;
+            });
+        }
+    });
+
+    Some(watcher)
+}
+
+pub struct PlaylistWatcher {
+    watcher: Mutex<Option<RecommendedWatcher>>,
+    watched: Arc<Mutex<Watched>>,
+}
+
+impl PlaylistWatcher {
+    // Create the watcher that reloads changed playlist files
+    pub fn start(app: AppHandle) -> Self {
+        let watched = Arc::new(Mutex::new(Watched::default()));
+        let state = watched.clone();
+        let watcher = debounced(DEBOUNCE, move |path| {
+            let app = app.clone();
+            let source = state.lock().unwrap().files.get(&path).cloned();
+            async move {
+                let Some(source) = source else {
+                    return;
+                };
+                if let Err(e) = reload_source(&app, &source).await {
+                    log::warn!("Failed to reload {}: {}", path.display(), e);
+                }
+            }
+        });
+
+        Self {
+            watcher: Mutex::new(watcher),
+            watched,
+        }
+    }
+
+    // Start watching the file behind a local playlist source
+    pub fn watch(&self, source: &str) {
+        let Some(path) = local_path(source) else {
+            return;
+        };
+        let Some(dir) = path.parent().map(Path::to_path_buf) else {
+            return;
+        };
+
+        let mut watched = self.watched.lock().unwrap();
+        if watched.files.contains_key(&path) {
+            return;
+        }
+        if !watched.dirs.contains_key(&dir) {
+            // Watch the directory so files replaced via rename keep working
+            let mut watcher = self.watcher.lock().unwrap();
+            let Some(watcher) = watcher.as_mut() else {
+                return;
+            };
+            if let Err(e) = watcher.watch(&dir, RecursiveMode::NonRecursive) {
+                log::warn!("Failed to watch {}: {}", dir.display(), e);
+                return;
+            }
+        }
+        *watched.dirs.entry(dir).or_default() += 1;
+        watched.files.insert(path, source.to_string());
+    }
+
+    pub fn unwatch(&self, source: &str) {
+        let Some(path) = local_path(source) else {
+            return;
+        };
+        let mut watched = self.watched.lock().unwrap();
+        if watched.files.remove(&path).is_none() {
+            return;
+        }
+
+        let Some(dir) = path.parent().map(Path::to_path_buf) else {
+            return;
+        };
+        let remaining = watched.dirs.get_mut(&dir).map(|count| {
+            *count -= 1;
+            *count
+        });
+        if remaining == Some(0) {
+            watched.dirs.remove(&dir);
+            if let Some(watcher) = self.watcher.lock().unwrap().as_mut() {
+                let _ = watcher.unwatch(&dir);
+            }
+        }
+    }
+}
+
+// Absolute path of a local playlist source, None for URLs or missing files
+fn local_path(source: &str) -> Option<PathBuf> {
+    if super::source::is_remote(source) {
+        return None;
+    }
+    let path = source.strip_prefix("file://").unwrap_or(source);
+    std::fs::canonicalize(path).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn test_local_path() {
+        // Test that URLs are ignored and files resolve to absolute paths
+        let dir = temp_dir("watcher-path");
+        let file = dir.join("list.m3u");
+        std::fs::write(&file, "#EXTM3U\n").unwrap();
+
+        assert!(local_path("http://example.com/list.m3u").is_none());
+        assert!(local_path(&dir.join("missing.m3u").to_string_lossy()).is_none());
+        let resolved = local_path(&format!("file://{}", file.display())).unwrap();
+        assert!(resolved.is_absolute());
+        assert!(resolved.ends_with("list.m3u"));
+    }
+}
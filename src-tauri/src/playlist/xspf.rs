@@ -0,0 +1,164 @@
+// XSPF ("spiff") playlist parser
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use tokio::io::AsyncBufRead;
+
+use super::m3u::apply_vlc_option;
+use super::{ChannelEntry, PROGRESS_INTERVAL};
+use crate::xml::append_text;
+
+// Track child elements whose text we keep
+const TRACK_FIELDS: &[&[u8]] = &[b"location", b"title", b"creator", b"image", b"duration", b"album", b"option"];
+
+// Stream <track> elements into entries, reporting (entries, bytes read)
+pub async fn read_entries<R>(
+    reader: R,
+    mut on_progress: impl FnMut(usize, u64),
+) -> Result<Vec<ChannelEntry>, String>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut xml = Reader::from_reader(reader);
+    let mut buf = Vec::new();
+    let mut entries = Vec::new();
+    let mut track: Option<Track> = None;
+    let mut field: Option<Vec<u8>> = None;
+    let mut text = String::new();
+
+    loop {
+        let event = xml
+            .read_event_into_async(&mut buf)
+            .await
+            .map_err(|e| format!("Failed to parse XSPF playlist: {}", e))?;
+        match &event {
+            Event::Start(start) => {
+                let name = start.local_name();
+                if name.as_ref() == b"track" {
+                    track = Some(Track::default());
+                } else if track.is_some() && TRACK_FIELDS.contains(&name.as_ref()) {
+                    field = Some(name.as_ref().to_vec());
+                    text.clear();
+                }
+            }
+            Event::End(end) => {
+                let name = end.local_name();
+                if name.as_ref() == b"track" {
+                    if let Some(entry) = track.take().and_then(Track::into_entry) {
+                        entries.push(entry);
+                        if entries.len() % PROGRESS_INTERVAL == 0 {
+                            on_progress(entries.len(), xml.buffer_position());
+                        }
+                    }
+                } else if field.as_deref() == Some(name.as_ref()) {
+                    if let Some(track) = track.as_mut() {
+                        track.set(name.as_ref(), text.trim());
+                    }
+                    field = None;
+                }
+            }
+            Event::Eof => break,
+            event => {
+                if field.is_some() {
+                    append_text(&mut text, event);
+                }
+            }
+        }
+        buf.clear();
+    }
+
+    on_progress(entries.len(), xml.buffer_position());
+    Ok(entries)
+}
+
+#[derive(Debug, Default)]
+struct Track {
+    entry: ChannelEntry,
+    title: Option<String>,
+    creator: Option<String>,
+}
+
+impl Track {
+    fn set(&mut self, field: &[u8], value: &str) {
+        if value.is_empty() {
+            return;
+        }
+        let entry = &mut self.entry;
+        match field {
+            // Only the first location is played; the rest are alternatives
+            b"location" if entry.url.is_empty() => entry.url = value.to_string(),
+            b"title" => self.title = Some(value.to_string()),
+            b"creator" => self.creator = Some(value.to_string()),
+            b"image" => entry.tvg_logo = Some(value.to_string()),
+            // XSPF durations are in milliseconds
+            b"duration" => entry.duration = value.parse::<f64>().ok().map(|ms| ms / 1000.0),
+            b"album" => entry.group_title = Some(value.to_string()),
+            // VLC's <vlc:option> mirrors #EXTVLCOPT
+            b"option" => apply_vlc_option(&mut entry.http, value),
+            _ => {}
+        }
+    }
+
+    fn into_entry(self) -> Option<ChannelEntry> {
+        let mut entry = self.entry;
+        if entry.url.is_empty() {
+            return None;
+        }
+        entry.name = self.title.or(self.creator).unwrap_or_else(|| entry.url.clone());
+        Some(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_xspf(content: &str) -> Vec<ChannelEntry> {
+        tauri::async_runtime::block_on(read_entries(content.as_bytes(), |_, _| {})).unwrap()
+    }
+
+    #[test]
+    fn test_parse_xspf_tracks() {
+        // Test that track fields map onto channel entries
+        let content = r#"<?xml version="1.0" encoding="UTF-8"?>
+<playlist version="1" xmlns="http://xspf.org/ns/0/" xmlns:vlc="http://www.videolan.org/vlc/playlist/ns/0/">
+  <title>Radios</title>
+  <trackList>
+    <track>
+      <location>http://radio/rock?a=1&amp;b=2</location>
+      <title>Rock FM</title>
+      <image>http://logo/rock.png</image>
+      <album>Music</album>
+      <duration>90500</duration>
+      <extension application="http://www.videolan.org/vlc/playlist/0">
+        <vlc:option>http-user-agent=Radio/1.0</vlc:option>
+      </extension>
+    </track>
+    <track>
+      <location>http://radio/jazz</location>
+      <creator>Jazz Station</creator>
+    </track>
+    <track><title>No location</title></track>
+  </trackList>
+</playlist>"#;
+        let entries = parse_xspf(content);
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name, "Rock FM");
+        assert_eq!(entries[0].url, "http://radio/rock?a=1&b=2");
+        assert_eq!(entries[0].tvg_logo.as_deref(), Some("http://logo/rock.png"));
+        assert_eq!(entries[0].group_title.as_deref(), Some("Music"));
+        assert_eq!(entries[0].duration, Some(90.5));
+        assert_eq!(entries[0].http.user_agent.as_deref(), Some("Radio/1.0"));
+        assert_eq!(entries[1].name, "Jazz Station");
+    }
+
+    #[test]
+    fn test_parse_xspf_invalid() {
+        // Test that malformed XML is reported as an error
+        let result = tauri::async_runtime::block_on(read_entries(
+            "<playlist><trackList></playlist>".as_bytes(),
+            |_, _| {},
+        ));
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,179 @@
+// Setup-time check of a provider: can the server be reached, do the
+// credentials work, and how many channels does it offer
+use std::time::Instant;
+
+use serde::Serialize;
+
+use super::ProviderSource;
+use crate::epg::time;
+use crate::hdhomerun::HdhomerunClient;
+use crate::http::{self, HttpOptions};
+use crate::playlist::{self, PlaylistConfig};
+use crate::satip::SatipClient;
+use crate::stalker::StalkerClient;
+use crate::tvheadend::TvheadendClient;
+use crate::xtream::{AccountStatus, XtreamClient};
+
+// What a check found; checks run in order and stop at the first failure,
+// which is described in `error`
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderReport {
+    pub reachable: bool,
+    // None for kinds without a login
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub authenticated: Option<bool>,
+    // Milliseconds until the server answered, None for local files
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel_count: Option<usize>,
+    // Unix seconds the subscription ends, when the provider says
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+    // Xtream subscription details
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account: Option<AccountStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// Time a request until the response head arrives; any HTTP answer,
+// errors included, shows the server is there
+async fn probe(url: &str, options: Option<&HttpOptions>) -> Result<u64, String> {
+    let started = Instant::now();
+    http::get(url, options)
+        .send()
+        .await
+        .map_err(|e| format!("Couldn't reach {}: {}", url, e.without_url()))?;
+    Ok(u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX))
+}
+
+async fn run(source: &ProviderSource, now: i64, report: &mut ProviderReport) -> Result<(), String> {
+    match source {
+        ProviderSource::M3uUrl { url, http, auth } => {
+            let config = PlaylistConfig {
+                http: http.clone(),
+                auth: auth.clone(),
+                ..Default::default()
+            };
+            let options = config.request_options();
+            report.latency_ms = Some(probe(url, options.as_ref()).await?);
+            report.reachable = true;
+            report.channel_count = Some(playlist::read_source(url, options.as_ref()).await?.len());
+        }
+        ProviderSource::M3uFile { path } => {
+            tokio::fs::metadata(path)
+                .await
+                .map_err(|e| format!("Couldn't open {}: {}", path, e))?;
+            report.reachable = true;
+            report.channel_count = Some(playlist::read_source(path, None).await?.len());
+        }
+        ProviderSource::Xtream(credentials) => {
+            let client = XtreamClient::new(credentials)?;
+            report.latency_ms = Some(probe(client.server(), None).await?);
+            report.reachable = true;
+            report.authenticated = Some(false);
+            let info = client.authenticate().await?;
+            report.authenticated = Some(true);
+            let account = AccountStatus::new(info.user_info, now);
+            report.expires_at = account.expires_at;
+            report.account = Some(account);
+            report.channel_count = Some(client.live_streams(None).await?.len());
+        }
+        ProviderSource::Stalker(portal) => {
+            let client = StalkerClient::new(portal)?;
+            report.latency_ms = Some(probe(client.api(), None).await?);
+            report.reachable = true;
+            report.authenticated = Some(false);
+            let profile = client.connect().await?;
+            report.authenticated = Some(true);
+            report.expires_at = profile.expire_billing_date.as_deref().and_then(time::parse_datetime);
+            report.channel_count = Some(client.channels().await?.len());
+        }
+        ProviderSource::Tvheadend(server) => {
+            let client = TvheadendClient::new(server)?;
+            report.latency_ms = Some(probe(client.server(), None).await?);
+            report.reachable = true;
+            report.authenticated = Some(false);
+            client.server_info().await?;
+            report.authenticated = Some(true);
+            report.channel_count = Some(client.channels().await?.len());
+        }
+        ProviderSource::Hdhomerun(tuner) => {
+            let client = HdhomerunClient::new(tuner)?;
+            report.latency_ms = Some(probe(client.server(), None).await?);
+            report.reachable = true;
+            let device = client.device().await?;
+            report.channel_count = Some(client.lineup(&device).await?.len());
+        }
+        ProviderSource::Satip(server) => {
+            let client = SatipClient::new(server)?;
+            report.latency_ms = Some(probe(client.server(), None).await?);
+            report.reachable = true;
+            let info = client.describe().await?;
+            report.channel_count = Some(client.channels(&info, "").await?.len());
+        }
+    }
+    Ok(())
+}
+
+pub async fn check(source: &ProviderSource, now: i64) -> ProviderReport {
+    let mut report = ProviderReport::default();
+    if let Err(e) = run(source, now, &mut report).await {
+        report.error = Some(e);
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{response, serve};
+    use crate::xtream::XtreamCredentials;
+
+    #[test]
+    fn test_check_playlist_url() {
+        // Test that a reachable playlist reports its channel count with headers sent
+        let playlist = "#EXTM3U\n#EXTINF:-1,One\nhttp://s/1\n#EXTINF:-1,Two\nhttp://s/2\n";
+        let (base, requests) = serve(vec![response("200 OK", &[], playlist), response("200 OK", &[], playlist)]);
+        let mut http = HttpOptions::default();
+        http.set_header("X-Token", "abc");
+        let source = ProviderSource::M3uUrl {
+            url: format!("{}/list.m3u", base),
+            http,
+            auth: None,
+        };
+
+        let report = tauri::async_runtime::block_on(check(&source, 0));
+        assert_eq!(report.error, None);
+        assert!(report.reachable && report.latency_ms.is_some());
+        assert_eq!((report.authenticated, report.channel_count), (None, Some(2)));
+        assert!(requests.lock().unwrap()[1].to_ascii_lowercase().contains("x-token: abc"));
+    }
+
+    #[test]
+    fn test_check_xtream_login() {
+        // Test that a refused login stops the checks after reachability
+        let refused = r#"{"user_info": {"auth": 0}}"#;
+        let (base, _) = serve(vec![response("404 Not Found", &[], ""), response("200 OK", &[], refused)]);
+        let source = ProviderSource::Xtream(XtreamCredentials {
+            server: base,
+            username: "user".to_string(),
+            password: "wrong".to_string(),
+        });
+
+        let report = tauri::async_runtime::block_on(check(&source, 0));
+        assert!(report.reachable);
+        assert_eq!(report.authenticated, Some(false));
+        assert_eq!(report.channel_count, None);
+        assert!(report.error.is_some());
+
+        let unreachable = ProviderSource::M3uFile {
+            path: "/nonexistent/list.m3u".to_string(),
+        };
+        let report = tauri::async_runtime::block_on(check(&unreachable, 0));
+        assert!(!report.reachable);
+        assert!(report.error.unwrap().starts_with("Couldn't open"));
+    }
+}
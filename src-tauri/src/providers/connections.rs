@@ -0,0 +1,374 @@
+// Streams and recordings open per provider. Providers cap how many
+// connections an account may hold and drop the oldest one when a new one
+// goes over, so an action that would exceed the cap is refused or waits
+// for a connection to close instead. The webview holds the connections it
+// opens until it closes them or reloads; the proxy claims one per stream
+// it relays for as long as it reads from the provider, sharing the
+// webview's connection for the same stream
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectionKind {
+    Stream,
+    Recording,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Connection {
+    pub id: u64,
+    pub provider_id: String,
+    pub kind: ConnectionKind,
+    // What it's for, e.g. a channel name, shown when the limit is hit
+    pub label: String,
+    // Unix seconds it was opened, or queued while waiting
+    pub opened_at: i64,
+    // The stream it carries, usually a channel id
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<String>,
+    // Whether the webview holds it, and how many of the proxy's relays do
+    #[serde(skip)]
+    webview: bool,
+    #[serde(skip)]
+    claims: usize,
+}
+
+impl Connection {
+    fn is_held(&self) -> bool {
+        self.webview || self.claims > 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum Grant {
+    Open(Connection),
+    // Opens when enough connections close; position 1 is next
+    Queued {
+        #[serde(flatten)]
+        connection: Connection,
+        position: usize,
+    },
+}
+
+// Why a connection wasn't opened, tagged so the UI can tell a full
+// provider from other failures
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "code", rename_all = "camelCase")]
+pub enum ConnectionError {
+    #[serde(rename_all = "camelCase")]
+    LimitReached {
+        provider_id: String,
+        limit: u32,
+        // The connections holding the provider, oldest first
+        open: Vec<Connection>,
+    },
+    Failed {
+        message: String,
+    },
+}
+
+impl From<String> for ConnectionError {
+    fn from(message: String) -> Self {
+        ConnectionError::Failed { message }
+    }
+}
+
+// Open and queued connections, oldest first
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionList {
+    pub open: Vec<Connection>,
+    pub queued: Vec<Connection>,
+}
+
+#[derive(Default)]
+struct Connections {
+    next_id: u64,
+    open: Vec<Connection>,
+    queued: Vec<Connection>,
+    // Limit each provider had when last asked, to promote queued ones
+    limits: HashMap<String, u32>,
+}
+
+impl Connections {
+    fn open_count(&self, provider_id: &str) -> usize {
+        self.open.iter().filter(|c| c.provider_id == provider_id).count()
+    }
+
+    fn has_room(&self, provider_id: &str) -> bool {
+        match self.limits.get(provider_id) {
+            Some(&limit) => self.open_count(provider_id) < limit as usize,
+            None => true,
+        }
+    }
+
+    fn set_limit(&mut self, provider_id: &str, limit: Option<u32>) {
+        match limit {
+            Some(limit) => self.limits.insert(provider_id.to_string(), limit),
+            None => self.limits.remove(provider_id),
+        };
+    }
+
+    fn connection(&mut self, provider_id: &str, kind: ConnectionKind, label: &str, now: i64) -> Connection {
+        self.next_id += 1;
+        Connection {
+            id: self.next_id,
+            provider_id: provider_id.to_string(),
+            kind,
+            label: label.trim().to_string(),
+            opened_at: now,
+            stream: None,
+            webview: true,
+            claims: 0,
+        }
+    }
+
+    // Drop an open connection nothing holds any more and open queued ones
+    // in its place
+    fn drop_unheld(&mut self, index: usize, now: i64) -> Vec<Connection> {
+        if self.open[index].is_held() {
+            return Vec::new();
+        }
+        let provider_id = self.open.remove(index).provider_id;
+        self.promote(&provider_id, now)
+    }
+
+    fn promote(&mut self, provider_id: &str, now: i64) -> Vec<Connection> {
+        let mut promoted = Vec::new();
+        while self.has_room(provider_id) {
+            let Some(index) = self.queued.iter().position(|c| c.provider_id == provider_id) else {
+                break;
+            };
+            let mut connection = self.queued.remove(index);
+            connection.opened_at = now;
+            self.open.push(connection.clone());
+            promoted.push(connection);
+        }
+        promoted
+    }
+}
+
+#[derive(Default)]
+pub struct ConnectionTracker {
+    inner: Mutex<Connections>,
+}
+
+impl ConnectionTracker {
+    // Open a connection if the provider has room under `limit` (None for
+    // no limit); otherwise queue it or refuse
+    pub fn open(
+        &self,
+        provider_id: &str,
+        limit: Option<u32>,
+        kind: ConnectionKind,
+        label: &str,
+        queue: bool,
+        now: i64,
+    ) -> Result<Grant, ConnectionError> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.set_limit(provider_id, limit);
+        let connection = inner.connection(provider_id, kind, label, now);
+        // Queued connections go first, so a new one can't jump the line
+        let waiting = inner.queued.iter().any(|c| c.provider_id == provider_id);
+        if !waiting && inner.has_room(provider_id) {
+            inner.open.push(connection.clone());
+            return Ok(Grant::Open(connection));
+        }
+        if !queue {
+            return Err(ConnectionError::LimitReached {
+                provider_id: provider_id.to_string(),
+                limit: limit.unwrap_or_default(),
+                open: inner.open.iter().filter(|c| c.provider_id == provider_id).cloned().collect(),
+            });
+        }
+        inner.queued.push(connection.clone());
+        let position = inner.queued.iter().filter(|c| c.provider_id == provider_id).count();
+        Ok(Grant::Queued { connection, position })
+    }
+
+    // Close an open or queued connection for the webview; one the proxy
+    // still relays stays open until it's done. Returns the queued
+    // connections that opened in its place
+    pub fn close(&self, id: u64, now: i64) -> Option<Vec<Connection>> {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(index) = inner.open.iter().position(|c| c.id == id) {
+            inner.open[index].webview = false;
+            return Some(inner.drop_unheld(index, now));
+        }
+        let index = inner.queued.iter().position(|c| c.id == id)?;
+        inner.queued.remove(index);
+        Some(Vec::new())
+    }
+
+    // Mark the stream an open or queued connection is for, so the proxy's
+    // relays of that stream share it
+    pub fn carry(&self, id: u64, stream: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        let Connections { open, queued, .. } = &mut *inner;
+        if let Some(connection) = open.iter_mut().chain(queued.iter_mut()).find(|c| c.id == id) {
+            connection.stream = Some(stream.to_string());
+        }
+    }
+
+    // The connection a stream already holds, claimed once more
+    pub fn join(&self, provider_id: &str, stream: &str) -> Option<u64> {
+        let mut inner = self.inner.lock().unwrap();
+        let connection = inner
+            .open
+            .iter_mut()
+            .find(|c| c.provider_id == provider_id && c.stream.as_deref() == Some(stream))?;
+        connection.claims += 1;
+        Some(connection.id)
+    }
+
+    // Claim a connection for a stream the proxy relays: the one the stream
+    // holds, or else a new one if the provider has room. Never queues, since
+    // a player waiting on the request would time out first
+    pub fn claim(
+        &self,
+        provider_id: &str,
+        limit: Option<u32>,
+        stream: &str,
+        label: &str,
+        now: i64,
+    ) -> Result<u64, ConnectionError> {
+        if let Some(id) = self.join(provider_id, stream) {
+            return Ok(id);
+        }
+        let mut inner = self.inner.lock().unwrap();
+        inner.set_limit(provider_id, limit);
+        if !inner.has_room(provider_id) || inner.queued.iter().any(|c| c.provider_id == provider_id) {
+            return Err(ConnectionError::LimitReached {
+                provider_id: provider_id.to_string(),
+                limit: limit.unwrap_or_default(),
+                open: inner.open.iter().filter(|c| c.provider_id == provider_id).cloned().collect(),
+            });
+        }
+        let mut connection = inner.connection(provider_id, ConnectionKind::Stream, label, now);
+        connection.stream = Some(stream.to_string());
+        (connection.webview, connection.claims) = (false, 1);
+        let id = connection.id;
+        inner.open.push(connection);
+        Ok(id)
+    }
+
+    // Give back a claim; the connection closes once nothing holds it.
+    // Returns the queued connections that opened in its place
+    pub fn release(&self, id: u64, now: i64) -> Vec<Connection> {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(index) = inner.open.iter().position(|c| c.id == id) else {
+            return Vec::new();
+        };
+        inner.open[index].claims = inner.open[index].claims.saturating_sub(1);
+        inner.drop_unheld(index, now)
+    }
+
+    // Let go of everything the webview held, since it reloaded and won't
+    // close what it opened before. Only its own connections were queued,
+    // so none open in their place
+    pub fn release_webview(&self, now: i64) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.queued.clear();
+        for index in (0..inner.open.len()).rev() {
+            inner.open[index].webview = false;
+            inner.drop_unheld(index, now);
+        }
+    }
+
+    pub fn list(&self) -> ConnectionList {
+        let inner = self.inner.lock().unwrap();
+        ConnectionList {
+            open: inner.open.clone(),
+            queued: inner.queued.clone(),
+        }
+    }
+
+    // Drop everything about a deleted provider
+    pub fn forget(&self, provider_id: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.open.retain(|c| c.provider_id != provider_id);
+        inner.queued.retain(|c| c.provider_id != provider_id);
+        inner.limits.remove(provider_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(grant: &Grant) -> u64 {
+        match grant {
+            Grant::Open(connection) | Grant::Queued { connection, .. } => connection.id,
+        }
+    }
+
+    #[test]
+    fn test_limit_refuses_or_queues() {
+        // Test that a full provider refuses or queues, and closing promotes
+        let tracker = ConnectionTracker::default();
+        let first = tracker.open("p", Some(1), ConnectionKind::Stream, "News", false, 0).unwrap();
+        assert!(matches!(first, Grant::Open(_)));
+        let refused = tracker.open("p", Some(1), ConnectionKind::Recording, "Film", false, 1);
+        let Err(ConnectionError::LimitReached { limit, open, .. }) = refused else {
+            panic!("expected the limit to be reached: {:?}", refused);
+        };
+        assert_eq!((limit, open[0].label.as_str()), (1, "News"));
+        assert!(matches!(tracker.open("q", Some(1), ConnectionKind::Stream, "", false, 1), Ok(Grant::Open(_))));
+
+        let queued = tracker.open("p", Some(1), ConnectionKind::Recording, "Film", true, 2).unwrap();
+        assert!(matches!(queued, Grant::Queued { position: 1, .. }));
+        // Room doesn't let a newcomer pass the queue
+        let later = tracker.open("p", Some(1), ConnectionKind::Stream, "Sport", true, 3).unwrap();
+        assert!(matches!(later, Grant::Queued { position: 2, .. }));
+
+        let promoted = tracker.close(id(&first), 10).unwrap();
+        assert_eq!(promoted.iter().map(|c| c.id).collect::<Vec<_>>(), vec![id(&queued)]);
+        assert_eq!(promoted[0].opened_at, 10);
+        assert_eq!(tracker.close(id(&later), 11), Some(Vec::new()));
+        assert_eq!(tracker.close(id(&later), 11), None);
+
+        tracker.forget("p");
+        let list = tracker.list();
+        assert_eq!((list.open.len(), list.queued.len()), (1, 0));
+        assert!(matches!(tracker.open("p", None, ConnectionKind::Stream, "", false, 0), Ok(Grant::Open(_))));
+    }
+
+    #[test]
+    fn test_proxy_claims_hold_connections() {
+        // Test that the proxy's relays count against the limit and keep a connection open until released
+        let tracker = ConnectionTracker::default();
+        let relayed = tracker.claim("p", Some(2), "stream-a", "a", 0).unwrap();
+        assert_eq!(tracker.claim("p", Some(2), "stream-a", "a", 1), Ok(relayed));
+        let Ok(Grant::Open(player)) = tracker.open("p", Some(2), ConnectionKind::Stream, "B", false, 2) else {
+            panic!("expected room for the player");
+        };
+        assert!(tracker.claim("p", Some(2), "stream-c", "c", 3).is_err());
+
+        // The webview closing leaves the relay holding its stream
+        tracker.carry(player.id, "stream-b");
+        let joined = tracker.join("p", "stream-b").unwrap();
+        assert_eq!(tracker.close(joined, 4), Some(Vec::new()));
+        assert_eq!(tracker.list().open.len(), 2);
+        tracker.release(joined, 5);
+        assert_eq!(tracker.list().open.len(), 1);
+
+        tracker.release(relayed, 6);
+        assert_eq!(tracker.list().open.len(), 1);
+        tracker.release(relayed, 7);
+        assert!(tracker.list().open.is_empty());
+
+        // A reload drops what the webview held but not what the proxy does
+        tracker.open("p", Some(2), ConnectionKind::Stream, "D", false, 8).unwrap();
+        tracker.open("p", Some(2), ConnectionKind::Stream, "E", true, 8).unwrap();
+        let held = tracker.claim("p", Some(3), "stream-f", "f", 9).unwrap();
+        tracker.release_webview(10);
+        let list = tracker.list();
+        assert_eq!(list.open.iter().map(|c| c.id).collect::<Vec<_>>(), vec![held]);
+        assert!(list.queued.is_empty());
+    }
+}
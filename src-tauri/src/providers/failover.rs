@@ -0,0 +1,294 @@
+// Channels several providers carry, presented as one logical channel with
+// its sources in provider priority order. Playback asks for a source and
+// gets the first one that answers, with recently failed ones tried last
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+use tauri::AppHandle;
+
+use super::ConnectionError;
+use crate::http::{self, HttpOptions};
+use crate::playlist::{content_hash, normalize_name, ChannelEntry, PlaylistLibrary};
+use crate::xtream::LiveStream;
+
+// Sources that failed are tried after the others for this long
+const FAILURE_COOLDOWN: i64 = 10 * 60;
+
+// One provider's stream of a logical channel
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelSource {
+    pub provider_id: String,
+    pub channel_id: String,
+    pub name: String,
+    pub url: String,
+    #[serde(skip_serializing_if = "HttpOptions::is_empty")]
+    pub http: HttpOptions,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogicalChannel {
+    pub id: String,
+    // Name, logo and group come from the highest-priority source that has them
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logo: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tvg_id: Option<String>,
+    pub sources: Vec<ChannelSource>,
+}
+
+// A provider channel reduced to what grouping needs
+pub struct Candidate {
+    source: ChannelSource,
+    tvg_id: Option<String>,
+    logo: Option<String>,
+    group: Option<String>,
+}
+
+impl Candidate {
+    pub fn from_entry(provider_id: &str, entry: &ChannelEntry) -> Self {
+        Candidate {
+            source: ChannelSource {
+                provider_id: provider_id.to_string(),
+                channel_id: entry.channel_id.clone(),
+                name: entry.name.clone(),
+                url: entry.url.clone(),
+                http: entry.http.clone(),
+            },
+            tvg_id: entry.tvg_id.clone(),
+            logo: entry.tvg_logo.clone(),
+            group: entry.group_title.clone(),
+        }
+    }
+
+    pub fn from_live_stream(provider_id: &str, stream: &LiveStream) -> Self {
+        Candidate {
+            source: ChannelSource {
+                provider_id: provider_id.to_string(),
+                channel_id: stream.channel_id.clone(),
+                name: stream.name.clone(),
+                url: stream.url.clone(),
+                http: HttpOptions::default(),
+            },
+            tvg_id: stream.epg_channel_id.clone(),
+            logo: stream.stream_icon.clone(),
+            group: None,
+        }
+    }
+
+    // Channels with the same EPG id, or lacking one the same normalized
+    // name, are the same logical channel
+    fn match_key(&self) -> Option<String> {
+        let tvg_id = self.tvg_id.as_deref().map(str::trim).filter(|id| !id.is_empty());
+        if let Some(tvg_id) = tvg_id {
+            return Some(format!("tvg:{}", tvg_id.to_lowercase()));
+        }
+        let name = normalize_name(&self.source.name);
+        (!name.is_empty()).then(|| format!("name:{}", name))
+    }
+}
+
+// Group candidates given in provider priority order
+pub fn group(candidates: Vec<Candidate>) -> Vec<LogicalChannel> {
+    let mut channels: Vec<LogicalChannel> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for candidate in candidates {
+        let Some(key) = candidate.match_key() else {
+            continue;
+        };
+        let Some(&position) = index.get(&key) else {
+            index.insert(key.clone(), channels.len());
+            channels.push(LogicalChannel {
+                id: content_hash(key.as_bytes()),
+                name: candidate.source.name.clone(),
+                logo: candidate.logo,
+                group: candidate.group,
+                tvg_id: candidate.tvg_id,
+                sources: vec![candidate.source],
+            });
+            continue;
+        };
+        let channel = &mut channels[position];
+        if channel.sources.iter().any(|s| s.channel_id == candidate.source.channel_id) {
+            continue;
+        }
+        channel.logo = channel.logo.take().or(candidate.logo);
+        channel.group = channel.group.take().or(candidate.group);
+        channel.sources.push(candidate.source);
+    }
+    channels
+}
+
+// Sources of the last listed logical channels and recent failures
+#[derive(Default)]
+pub struct Failover {
+    channels: Mutex<HashMap<String, Vec<ChannelSource>>>,
+    // Channel id -> unix seconds of the last failure
+    failed: Mutex<HashMap<String, i64>>,
+}
+
+impl Failover {
+    pub fn remember(&self, channels: &[LogicalChannel]) {
+        *self.channels.lock().unwrap() = channels
+            .iter()
+            .map(|channel| (channel.id.clone(), channel.sources.clone()))
+            .collect();
+    }
+
+    pub fn mark_failed(&self, channel_id: &str, now: i64) {
+        self.failed.lock().unwrap().insert(channel_id.to_string(), now);
+    }
+
+    fn mark_working(&self, channel_id: &str) {
+        self.failed.lock().unwrap().remove(channel_id);
+    }
+
+    // Sources in the order to try: priority order, recent failures last
+    fn ordered(&self, id: &str, now: i64) -> Option<Vec<ChannelSource>> {
+        let mut sources = self.channels.lock().unwrap().get(id)?.clone();
+        let failed = self.failed.lock().unwrap();
+        // Stable, so each half keeps the priority order
+        sources.sort_by_key(|source| failed.get(&source.channel_id).is_some_and(|at| now - at < FAILURE_COOLDOWN));
+        Some(sources)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceFailure {
+    pub provider_id: String,
+    pub channel_id: String,
+    pub error: String,
+}
+
+// The source to play and the ones skipped on the way
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceChoice {
+    #[serde(flatten)]
+    pub source: ChannelSource,
+    pub skipped: Vec<SourceFailure>,
+}
+
+// Whether a stream answers; the body is dropped unread. Streams on a
+// playlist's server are asked with the playlist's login
+async fn responds(source: &ChannelSource, library: &PlaylistLibrary) -> Result<(), String> {
+    let mut options = library.server_options(&source.url).unwrap_or_default();
+    options.extend(source.http.clone());
+    let options = (!options.is_empty()).then_some(&options);
+    let response = http::get(&source.url, options)
+        .send()
+        .await
+        .map_err(|e| e.without_url().to_string())?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("HTTP {}", status.as_u16()));
+    }
+    Ok(())
+}
+
+// The first source that answers; with `app`, each check holds one of its
+// provider's connections, and a full provider counts as failing
+pub async fn select(
+    failover: &Failover,
+    library: &PlaylistLibrary,
+    app: Option<&AppHandle>,
+    id: &str,
+    now: i64,
+) -> Result<SourceChoice, String> {
+    let sources = failover
+        .ordered(id, now)
+        .ok_or_else(|| format!("Channel '{}' isn't listed; load the channel list again", id))?;
+    let mut skipped = Vec::new();
+    for source in sources {
+        let claimed = match app {
+            Some(app) => super::claim(app, &source.provider_id, &source.channel_id, &source.name).await,
+            None => Ok(None),
+        };
+        let result = match claimed {
+            Ok(_claim) => responds(&source, library).await,
+            Err(ConnectionError::LimitReached { limit, .. }) => Err(format!("All {} connections are in use", limit)),
+            Err(ConnectionError::Failed { message }) => Err(message),
+        };
+        match result {
+            Ok(()) => {
+                failover.mark_working(&source.channel_id);
+                return Ok(SourceChoice { source, skipped });
+            }
+            Err(error) => {
+                failover.mark_failed(&source.channel_id, now);
+                skipped.push(SourceFailure {
+                    provider_id: source.provider_id,
+                    channel_id: source.channel_id,
+                    error,
+                });
+            }
+        }
+    }
+    let errors: Vec<String> = skipped.iter().map(|f| format!("{}: {}", f.provider_id, f.error)).collect();
+    Err(format!("No source of this channel is working ({})", errors.join(", ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{response, serve, temp_dir};
+
+    fn entry(provider: &str, name: &str, tvg_id: Option<&str>, url: &str) -> Candidate {
+        let entry = ChannelEntry {
+            channel_id: format!("{}:{}", provider, url),
+            name: name.to_string(),
+            url: url.to_string(),
+            tvg_id: tvg_id.map(str::to_string),
+            tvg_logo: (provider == "b").then(|| "http://logo/b.png".to_string()),
+            ..Default::default()
+        };
+        Candidate::from_entry(provider, &entry)
+    }
+
+    #[test]
+    fn test_group_by_epg_id_then_name() {
+        // Test that sources merge by EPG id or name and keep priority order
+        let channels = group(vec![
+            entry("a", "BBC One HD", Some("bbc1.uk"), "http://a/1"),
+            entry("a", "News", None, "http://a/2"),
+            entry("b", "BBC 1", Some("BBC1.uk"), "http://b/1"),
+            entry("b", "news", None, "http://b/2"),
+            entry("b", "", None, "http://b/3"),
+        ]);
+        assert_eq!(channels.len(), 2);
+        assert_eq!(channels[0].name, "BBC One HD");
+        assert_eq!(channels[0].logo.as_deref(), Some("http://logo/b.png"));
+        let providers: Vec<&str> = channels[0].sources.iter().map(|s| s.provider_id.as_str()).collect();
+        assert_eq!(providers, vec!["a", "b"]);
+        assert_eq!(channels[1].sources.len(), 2);
+    }
+
+    #[test]
+    fn test_select_fails_over() {
+        // Test that a failing source is skipped and tried last afterwards
+        let (base, _) = serve(vec![response("503 Service Unavailable", &[], ""), response("200 OK", &[], "ts")]);
+        let channels = group(vec![
+            entry("a", "News", None, &format!("{}/a", base)),
+            entry("b", "News", None, &format!("{}/b", base)),
+        ]);
+        let failover = Failover::default();
+        failover.remember(&channels);
+        let library = PlaylistLibrary::open(temp_dir("failover"));
+
+        let choice = tauri::async_runtime::block_on(select(&failover, &library, None, &channels[0].id, 1000)).unwrap();
+        assert_eq!(choice.source.provider_id, "b");
+        assert_eq!(choice.skipped[0].error, "HTTP 503");
+
+        let order = |now| failover.ordered(&channels[0].id, now).unwrap()[0].provider_id.clone();
+        assert_eq!(order(1000 + 60), "b");
+        assert_eq!(order(1000 + FAILURE_COOLDOWN), "a");
+        assert!(tauri::async_runtime::block_on(select(&failover, &library, None, "missing", 0)).is_err());
+    }
+}
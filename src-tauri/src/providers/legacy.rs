@@ -0,0 +1,109 @@
+// Xtream logins the web UI kept as loose keys in the store plugin file: the
+// current login plus the saved profiles, in both the old host + port shape
+// and the newer protocol + host one
+use std::path::Path;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::de;
+use crate::storage;
+use crate::xtream::XtreamCredentials;
+
+// Store plugin file name, relative to the app data directory
+pub const STORE_FILE: &str = ".xtream.creds.json";
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct StoredLogin {
+    #[serde(deserialize_with = "de::string")]
+    protocol: String,
+    #[serde(deserialize_with = "de::string")]
+    host: String,
+    #[serde(deserialize_with = "de::string")]
+    port: String,
+    #[serde(deserialize_with = "de::string")]
+    user: String,
+    #[serde(deserialize_with = "de::string")]
+    pass: String,
+}
+
+impl StoredLogin {
+    fn into_credentials(self) -> Option<XtreamCredentials> {
+        let (host, port) = (self.host.trim(), self.port.trim());
+        if host.is_empty() || self.user.trim().is_empty() {
+            return None;
+        }
+        let host = if port.is_empty() || host.contains(':') {
+            host.to_string()
+        } else {
+            format!("{}:{}", host, port)
+        };
+        let server = match self.protocol.trim() {
+            _ if host.contains("://") => host,
+            "" => format!("http://{}", host),
+            protocol => format!("{}://{}", protocol, host),
+        };
+        Some(XtreamCredentials {
+            server,
+            username: self.user.trim().to_string(),
+            password: self.pass,
+        })
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct StoredProfile {
+    #[serde(deserialize_with = "de::string")]
+    name: String,
+    #[serde(deserialize_with = "de::object")]
+    credentials: StoredLogin,
+}
+
+// (name, credentials) of every login in the store file, profiles first; the
+// current login usually repeats one of them and comes out with no name
+pub fn logins(data_dir: &Path) -> Vec<(String, XtreamCredentials)> {
+    let mut store: Value = storage::read_json(&data_dir.join(STORE_FILE)).unwrap_or_else(|e| {
+        log::warn!("Failed to read the saved logins: {}", e);
+        Value::Null
+    });
+    let profiles = store.get_mut("profiles").map(Value::take).unwrap_or_default();
+    let mut logins: Vec<(String, XtreamCredentials)> = de::list::<StoredProfile>(profiles)
+        .into_iter()
+        .filter_map(|profile| Some((profile.name, profile.credentials.into_credentials()?)))
+        .collect();
+    if let Some(current) = de::object_value::<StoredLogin>(store).into_credentials() {
+        logins.push((String::new(), current));
+    }
+    logins
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn test_logins_from_store_file() {
+        // Test both profile shapes, the current login and a missing file
+        let dir = temp_dir("providers-legacy");
+        assert!(logins(&dir).is_empty());
+
+        let store = r#"{
+            "protocol": "https", "host": "tv.example.com", "user": "me", "pass": "secret",
+            "profiles": [
+                {"id": "1", "name": "Home", "credentials": {"host": "old.example.com", "port": "8080", "user": "a", "pass": "b"}},
+                {"id": "2", "name": "Empty", "credentials": {"host": "", "user": ""}}
+            ]
+        }"#;
+        std::fs::write(dir.join(STORE_FILE), store).unwrap();
+        let logins = logins(&dir);
+        assert_eq!(logins.len(), 2);
+        assert_eq!(logins[0].0, "Home");
+        assert_eq!(logins[0].1.server, "http://old.example.com:8080");
+        assert_eq!(logins[1].0, "");
+        assert_eq!(logins[1].1.server, "https://tv.example.com");
+        assert_eq!(logins[1].1.password, "secret");
+    }
+}
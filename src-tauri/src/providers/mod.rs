@@ -0,0 +1,884 @@
+// Every configured channel source, whatever its kind, behind one set of
+// commands. Each kind keeps its details where its own features read them
+// (the playlist library, the Xtream, Stalker, TVHeadend, HDHomeRun and
+// SAT>IP providers); the registry adds what they share and lists them all
+// under their backend ids
+mod check;
+mod connections;
+mod failover;
+mod legacy;
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::webview::{PageLoadEvent, PageLoadPayload};
+use tauri::{AppHandle, Emitter, Manager, State, Webview};
+use url::Url;
+
+use crate::epg::mappings::EpgMappings;
+use crate::hdhomerun::{self, HdhomerunClient, HdhomerunProvider, HdhomerunProviders, HdhomerunTuner};
+use crate::http::{BasicAuth, HttpOptions};
+use crate::playlist::{self, PlaylistConfig, PlaylistLibrary, PlaylistStore, PlaylistWatcher};
+use crate::satip::{self, SatipClient, SatipProvider, SatipProviders, SatipServer};
+use crate::stalker::{self, StalkerClient, StalkerPortal, StalkerProvider, StalkerProviders, StalkerSessions};
+use crate::storage;
+use crate::tvheadend::{self, TvheadendClient, TvheadendProvider, TvheadendProviders, TvheadendServer};
+use crate::xtream::{self, XtreamCache, XtreamClient, XtreamCredentials, XtreamProvider, XtreamProviders};
+use crate::{unix_now, validate_string_length};
+
+pub use check::ProviderReport;
+pub use connections::{ConnectionError, ConnectionKind, ConnectionList, ConnectionTracker, Grant};
+pub use failover::{Failover, LogicalChannel, SourceChoice};
+
+use failover::Candidate;
+
+const REGISTRY_FILE: &str = "providers.json";
+
+// Limits for user-entered provider details
+const MAX_NAME_LENGTH: usize = 200;
+const MAX_FIELD_LENGTH: usize = 2048;
+
+// How long a claim outlives the request it was made for: HLS players ask
+// for a segment every few seconds, and the stream keeps its connection in
+// between
+const RELEASE_DELAY: Duration = Duration::from_secs(15);
+
+// Where a provider's channels come from, with that kind's credentials and
+// options
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum ProviderSource {
+    M3uUrl {
+        url: String,
+        #[serde(default, skip_serializing_if = "HttpOptions::is_empty")]
+        http: HttpOptions,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        auth: Option<BasicAuth>,
+    },
+    M3uFile {
+        path: String,
+    },
+    Xtream(XtreamCredentials),
+    Stalker(StalkerPortal),
+    Tvheadend(TvheadendServer),
+    Hdhomerun(HdhomerunTuner),
+    Satip(SatipServer),
+}
+
+// Store that holds the details of a source kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    Playlists,
+    Xtream,
+    Stalker,
+    Tvheadend,
+    Hdhomerun,
+    Satip,
+}
+
+impl ProviderSource {
+    fn from_playlist(config: &PlaylistConfig) -> Self {
+        if playlist::is_remote(&config.source) {
+            ProviderSource::M3uUrl {
+                url: config.source.clone(),
+                http: config.http.clone(),
+                auth: config.auth.clone(),
+            }
+        } else {
+            ProviderSource::M3uFile {
+                path: config.source.clone(),
+            }
+        }
+    }
+
+    fn backend(&self) -> Backend {
+        match self {
+            ProviderSource::M3uUrl { .. } | ProviderSource::M3uFile { .. } => Backend::Playlists,
+            ProviderSource::Xtream(_) => Backend::Xtream,
+            ProviderSource::Stalker(_) => Backend::Stalker,
+            ProviderSource::Tvheadend(_) => Backend::Tvheadend,
+            ProviderSource::Hdhomerun(_) => Backend::Hdhomerun,
+            ProviderSource::Satip(_) => Backend::Satip,
+        }
+    }
+}
+
+// What the user enters for a provider
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub source: ProviderSource,
+    // Hours between automatic refreshes: the channel list for playlists
+    // (None disables it), the guide for Xtream (None keeps the default,
+    // 0 disables it). Stalker portals, TVHeadend servers, HDHomeRun tuners
+    // and SAT>IP servers are always queried live
+    #[serde(default)]
+    pub refresh_interval_hours: Option<u32>,
+    // Streams and recordings the provider allows at once. None takes what
+    // an Xtream server reports or the tuner count of an HDHomeRun or SAT>IP
+    // server, and means no limit for other kinds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_connections: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Provider {
+    pub id: String,
+    pub created_at: i64,
+    #[serde(flatten)]
+    pub config: ProviderConfig,
+}
+
+// What the registry itself keeps per provider; everything else is read
+// from the backend stores
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProviderEntry {
+    id: String,
+    created_at: i64,
+    // Playlists keep their interval in the library
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    refresh_interval_hours: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_connections: Option<u32>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct RegistryFile {
+    // Whether the store plugin's logins were imported
+    legacy_imported: bool,
+    providers: Vec<ProviderEntry>,
+}
+
+pub struct ProviderRegistry {
+    dir: PathBuf,
+    data: Mutex<RegistryFile>,
+}
+
+impl ProviderRegistry {
+    pub fn open(data_dir: &Path) -> Self {
+        let data = storage::read_json(&data_dir.join(REGISTRY_FILE)).unwrap_or_else(|e| {
+            log::error!("Failed to load providers: {}", e);
+            RegistryFile::default()
+        });
+        Self {
+            dir: data_dir.to_path_buf(),
+            data: Mutex::new(data),
+        }
+    }
+
+    fn save(&self, data: &RegistryFile) -> Result<(), String> {
+        storage::write_json(&self.dir.join(REGISTRY_FILE), data)
+    }
+
+    // Configured guide refresh interval of a non-playlist provider
+    pub fn refresh_interval(&self, id: &str) -> Option<u32> {
+        let data = self.data.lock().unwrap();
+        data.providers.iter().find(|p| p.id == id)?.refresh_interval_hours
+    }
+
+    fn created_at(&self, id: &str) -> Option<i64> {
+        let data = self.data.lock().unwrap();
+        data.providers.iter().find(|p| p.id == id).map(|p| p.created_at)
+    }
+
+    fn upsert(&self, entry: ProviderEntry) -> Result<(), String> {
+        let mut data = self.data.lock().unwrap();
+        match data.providers.iter_mut().find(|p| p.id == entry.id) {
+            Some(existing) => *existing = entry,
+            None => data.providers.push(entry),
+        }
+        self.save(&data)
+    }
+
+    fn remove(&self, id: &str) -> Result<bool, String> {
+        let mut data = self.data.lock().unwrap();
+        let before = data.providers.len();
+        data.providers.retain(|p| p.id != id);
+        if data.providers.len() == before {
+            return Ok(false);
+        }
+        self.save(&data)?;
+        Ok(true)
+    }
+
+    // Match the entries to the sources the backends hold: sources added by
+    // the kind-specific commands get an entry, entries whose source is gone
+    // are dropped. Returns the entries in the order they were added
+    fn reconcile(&self, ids: &[String], now: i64) -> Result<Vec<ProviderEntry>, String> {
+        let mut data = self.data.lock().unwrap();
+        let before = data.providers.len();
+        data.providers.retain(|entry| ids.contains(&entry.id));
+        let mut changed = data.providers.len() != before;
+        for id in ids {
+            if !data.providers.iter().any(|entry| &entry.id == id) {
+                data.providers.push(ProviderEntry {
+                    id: id.clone(),
+                    created_at: now,
+                    refresh_interval_hours: None,
+                    max_connections: None,
+                });
+                changed = true;
+            }
+        }
+        if changed {
+            self.save(&data)?;
+        }
+        Ok(data.providers.clone())
+    }
+
+    // Put the given providers first, in that order; the rest keep theirs
+    fn reorder(&self, ids: &[String]) -> Result<(), String> {
+        let mut data = self.data.lock().unwrap();
+        let rank = |entry: &ProviderEntry| ids.iter().position(|id| *id == entry.id).unwrap_or(ids.len());
+        data.providers.sort_by_key(rank);
+        self.save(&data)
+    }
+
+    // Import the store plugin's logins once; later edits happen here
+    fn take_legacy_logins(&self) -> Result<Vec<(String, XtreamCredentials)>, String> {
+        let mut data = self.data.lock().unwrap();
+        if data.legacy_imported {
+            return Ok(Vec::new());
+        }
+        let logins = legacy::logins(&self.dir);
+        data.legacy_imported = true;
+        self.save(&data)?;
+        Ok(logins)
+    }
+}
+
+// Every source the backends hold, with the playlist refresh intervals
+fn configured(app: &AppHandle) -> Vec<Provider> {
+    let playlists = app.state::<PlaylistLibrary>().list().into_iter().map(|config| Provider {
+        created_at: 0,
+        config: ProviderConfig {
+            source: ProviderSource::from_playlist(&config),
+            name: config.name,
+            refresh_interval_hours: config.refresh_interval_hours,
+            max_connections: None,
+        },
+        id: config.id,
+    });
+    let xtream = app.state::<XtreamProviders>().list().into_iter().map(|provider| Provider {
+        id: provider.id,
+        created_at: 0,
+        config: ProviderConfig {
+            name: provider.name,
+            source: ProviderSource::Xtream(provider.credentials),
+            refresh_interval_hours: None,
+            max_connections: None,
+        },
+    });
+    let stalker = app.state::<StalkerProviders>().list().into_iter().map(|provider| Provider {
+        id: provider.id,
+        created_at: 0,
+        config: ProviderConfig {
+            name: provider.name,
+            source: ProviderSource::Stalker(provider.portal),
+            refresh_interval_hours: None,
+            max_connections: None,
+        },
+    });
+    let tvheadend = app.state::<TvheadendProviders>().list().into_iter().map(|provider| Provider {
+        id: provider.id,
+        created_at: 0,
+        config: ProviderConfig {
+            name: provider.name,
+            source: ProviderSource::Tvheadend(provider.server),
+            refresh_interval_hours: None,
+            max_connections: None,
+        },
+    });
+    let hdhomerun = app.state::<HdhomerunProviders>().list().into_iter().map(|provider| Provider {
+        id: provider.id,
+        created_at: 0,
+        config: ProviderConfig {
+            name: provider.name,
+            source: ProviderSource::Hdhomerun(provider.tuner),
+            refresh_interval_hours: None,
+            max_connections: None,
+        },
+    });
+    let satip = app.state::<SatipProviders>().list().into_iter().map(|provider| Provider {
+        id: provider.id,
+        created_at: 0,
+        config: ProviderConfig {
+            name: provider.name,
+            source: ProviderSource::Satip(provider.server),
+            refresh_interval_hours: None,
+            max_connections: None,
+        },
+    });
+    playlists.chain(xtream).chain(stalker).chain(tvheadend).chain(hdhomerun).chain(satip).collect()
+}
+
+// Registry entries joined with their sources, in registry order
+fn join(entries: Vec<ProviderEntry>, mut sources: Vec<Provider>) -> Vec<Provider> {
+    entries
+        .into_iter()
+        .filter_map(|entry| {
+            let index = sources.iter().position(|source| source.id == entry.id)?;
+            let mut provider = sources.swap_remove(index);
+            provider.created_at = entry.created_at;
+            provider.config.max_connections = entry.max_connections;
+            if provider.config.source.backend() != Backend::Playlists {
+                provider.config.refresh_interval_hours = entry.refresh_interval_hours;
+            }
+            Some(provider)
+        })
+        .collect()
+}
+
+// Save the logins the web UI kept in the store plugin as Xtream providers
+fn import_legacy(app: &AppHandle) -> Result<(), String> {
+    let xtream = app.state::<XtreamProviders>();
+    for (name, credentials) in app.state::<ProviderRegistry>().take_legacy_logins()? {
+        let client = match XtreamClient::new(&credentials) {
+            Ok(client) => client,
+            Err(e) => {
+                log::warn!("Skipping saved login for '{}': {}", credentials.server, e);
+                continue;
+            }
+        };
+        let provider = XtreamProvider::new(&client, &name, &credentials);
+        if xtream.get(&provider.id).is_err() {
+            xtream.upsert(provider)?;
+        }
+    }
+    Ok(())
+}
+
+// All providers, bringing the registry in line with the backends first
+pub fn sync(app: &AppHandle) -> Result<Vec<Provider>, String> {
+    import_legacy(app)?;
+    let sources = configured(app);
+    let ids: Vec<String> = sources.iter().map(|source| source.id.clone()).collect();
+    let entries = app.state::<ProviderRegistry>().reconcile(&ids, unix_now())?;
+    Ok(join(entries, sources))
+}
+
+// Validate and normalize a provider, returning the id it's saved under
+// when new
+fn prepare(config: ProviderConfig) -> Result<(String, ProviderConfig), String> {
+    validate_string_length(&config.name, MAX_NAME_LENGTH)?;
+    let (id, label, source) = match config.source {
+        ProviderSource::M3uUrl { url, http, auth } => {
+            validate_string_length(&url, MAX_FIELD_LENGTH)?;
+            let url = url.trim().to_string();
+            if !Url::parse(&url).is_ok_and(|u| matches!(u.scheme(), "http" | "https")) {
+                return Err(format!("'{}' isn't an http(s) playlist address", url));
+            }
+            playlist::check_http_options(&http)?;
+            playlist::check_auth(auth.as_ref())?;
+            let auth = auth.filter(|auth| !auth.username.is_empty());
+            (playlist::playlist_id(&url), url.clone(), ProviderSource::M3uUrl { url, http, auth })
+        }
+        ProviderSource::M3uFile { path } => {
+            validate_string_length(&path, MAX_FIELD_LENGTH)?;
+            let path = path.trim().to_string();
+            if path.is_empty() {
+                return Err("Playlist file path cannot be empty".to_string());
+            }
+            (playlist::playlist_id(&path), path.clone(), ProviderSource::M3uFile { path })
+        }
+        ProviderSource::Xtream(credentials) => {
+            xtream::check_credentials(&credentials)?;
+            let provider = XtreamProvider::new(&XtreamClient::new(&credentials)?, "", &credentials);
+            (provider.id, provider.name, ProviderSource::Xtream(provider.credentials))
+        }
+        ProviderSource::Stalker(portal) => {
+            stalker::check_portal(&portal)?;
+            let provider = StalkerProvider::new(&StalkerClient::new(&portal)?, "", &portal);
+            (provider.id, provider.name, ProviderSource::Stalker(provider.portal))
+        }
+        ProviderSource::Tvheadend(server) => {
+            tvheadend::check_server(&server)?;
+            let provider = TvheadendProvider::new(&TvheadendClient::new(&server)?, "", &server);
+            (provider.id, provider.name, ProviderSource::Tvheadend(provider.server))
+        }
+        ProviderSource::Hdhomerun(tuner) => {
+            hdhomerun::check_tuner(&tuner)?;
+            let provider = HdhomerunProvider::new(&HdhomerunClient::new(&tuner)?, "");
+            (provider.id, provider.name, ProviderSource::Hdhomerun(provider.tuner))
+        }
+        ProviderSource::Satip(server) => {
+            satip::check_server(&server)?;
+            let provider = SatipProvider::new(&SatipClient::new(&server)?, "");
+            (provider.id, provider.name, ProviderSource::Satip(provider.server))
+        }
+    };
+    let name = match config.name.trim() {
+        "" => label,
+        name => name.to_string(),
+    };
+    let config = ProviderConfig {
+        name,
+        source,
+        refresh_interval_hours: config.refresh_interval_hours,
+        max_connections: config.max_connections.filter(|max| *max > 0),
+    };
+    Ok((id, config))
+}
+
+// Write a provider's details to its backend store
+fn attach(app: &AppHandle, id: &str, config: &ProviderConfig) -> Result<(), String> {
+    let (source, http, auth) = match &config.source {
+        ProviderSource::M3uUrl { url, http, auth } => (url, http.clone(), auth.clone()),
+        ProviderSource::M3uFile { path } => (path, HttpOptions::default(), None),
+        ProviderSource::Xtream(credentials) => {
+            let providers = app.state::<XtreamProviders>();
+            // Extra accounts are managed separately and stay as they were
+            let extra_accounts = providers.get(id).map(|p| p.extra_accounts).unwrap_or_default();
+            providers.upsert(XtreamProvider {
+                id: id.to_string(),
+                name: config.name.clone(),
+                credentials: credentials.clone(),
+                extra_accounts,
+            })?;
+            // A changed password makes cached stream URLs stale
+            app.state::<XtreamCache>().invalidate(id);
+            return Ok(());
+        }
+        ProviderSource::Stalker(portal) => {
+            app.state::<StalkerProviders>().upsert(StalkerProvider {
+                id: id.to_string(),
+                name: config.name.clone(),
+                portal: portal.clone(),
+            })?;
+            app.state::<StalkerSessions>().invalidate(id);
+            return Ok(());
+        }
+        ProviderSource::Tvheadend(server) => {
+            return app.state::<TvheadendProviders>().upsert(TvheadendProvider {
+                id: id.to_string(),
+                name: config.name.clone(),
+                server: server.clone(),
+            });
+        }
+        ProviderSource::Hdhomerun(tuner) => {
+            return app.state::<HdhomerunProviders>().upsert(HdhomerunProvider {
+                id: id.to_string(),
+                name: config.name.clone(),
+                tuner: tuner.clone(),
+            });
+        }
+        ProviderSource::Satip(server) => {
+            return app.state::<SatipProviders>().upsert(SatipProvider {
+                id: id.to_string(),
+                name: config.name.clone(),
+                server: server.clone(),
+            });
+        }
+    };
+
+    let library = app.state::<PlaylistLibrary>();
+    let watcher = app.state::<PlaylistWatcher>();
+    let existing = library.get(id).unwrap_or_default();
+    if !existing.source.is_empty() && existing.source != *source {
+        watcher.unwatch(&existing.source);
+    }
+    library.upsert(PlaylistConfig {
+        id: id.to_string(),
+        name: config.name.clone(),
+        source: source.clone(),
+        refresh_interval_hours: config.refresh_interval_hours,
+        http,
+        auth,
+        ..existing
+    })?;
+    watcher.watch(source);
+    Ok(())
+}
+
+// Remove a provider from its backend store with everything derived from it
+fn detach(app: &AppHandle, provider: &Provider) -> Result<bool, String> {
+    match provider.config.source.backend() {
+        Backend::Playlists => playlist::forget_playlist(app, &provider.id),
+        Backend::Xtream => xtream::forget_provider(app, &provider.id),
+        Backend::Stalker => stalker::forget_provider(app, &provider.id),
+        Backend::Tvheadend => tvheadend::forget_provider(app, &provider.id),
+        Backend::Hdhomerun => app.state::<HdhomerunProviders>().remove(&provider.id),
+        Backend::Satip => satip::forget_provider(app, &provider.id),
+    }
+}
+
+fn find(app: &AppHandle, id: &str) -> Result<Provider, String> {
+    sync(app)?
+        .into_iter()
+        .find(|provider| provider.id == id)
+        .ok_or_else(|| format!("Provider '{}' doesn't exist", id))
+}
+
+fn save(app: &AppHandle, id: String, config: ProviderConfig) -> Result<Provider, String> {
+    attach(app, &id, &config)?;
+    let registry = app.state::<ProviderRegistry>();
+    let is_playlist = config.source.backend() == Backend::Playlists;
+    registry.upsert(ProviderEntry {
+        created_at: registry.created_at(&id).unwrap_or_else(unix_now),
+        refresh_interval_hours: config.refresh_interval_hours.filter(|_| !is_playlist),
+        max_connections: config.max_connections,
+        id: id.clone(),
+    })?;
+    find(app, &id)
+}
+
+// Command handler listing every configured provider
+#[tauri::command]
+pub fn list_providers(app: AppHandle) -> Result<Vec<Provider>, String> {
+    sync(&app)
+}
+
+// Command handler that checks a provider's details without saving them:
+// reachability, login and channel count
+#[tauri::command]
+pub async fn test_provider(config: ProviderConfig) -> Result<ProviderReport, String> {
+    let (_, config) = prepare(config)?;
+    Ok(check::check(&config.source, unix_now()).await)
+}
+
+// Command handler that saves a new provider; adding the same source again
+// updates the existing one
+#[tauri::command]
+pub fn add_provider(app: AppHandle, config: ProviderConfig) -> Result<Provider, String> {
+    let (id, config) = prepare(config)?;
+    save(&app, id, config)
+}
+
+// Command handler that changes a provider's details while keeping its id,
+// so anything keyed on it survives a new address or password
+#[tauri::command]
+pub fn update_provider(app: AppHandle, provider_id: String, config: ProviderConfig) -> Result<Provider, String> {
+    let existing = find(&app, &provider_id)?;
+    let (_, config) = prepare(config)?;
+    if existing.config.source.backend() != config.source.backend() {
+        detach(&app, &existing)?;
+    }
+    save(&app, provider_id, config)
+}
+
+// Command handler that deletes a provider and the data kept for it
+#[tauri::command]
+pub fn delete_provider(app: AppHandle, provider_id: String) -> Result<bool, String> {
+    let Some(existing) = sync(&app)?.into_iter().find(|provider| provider.id == provider_id) else {
+        return Ok(false);
+    };
+    detach(&app, &existing)?;
+    app.state::<EpgMappings>().remove_provider(&provider_id)?;
+    app.state::<ConnectionTracker>().forget(&provider_id);
+    app.state::<ProviderRegistry>().remove(&provider_id)
+}
+
+// Command handler that ranks providers; listed first means preferred when
+// several carry the same channel
+#[tauri::command]
+pub fn reorder_providers(app: AppHandle, provider_ids: Vec<String>) -> Result<Vec<Provider>, String> {
+    app.state::<ProviderRegistry>().reorder(&provider_ids)?;
+    sync(&app)
+}
+
+// Command handler merging the loaded channels of every provider into
+// logical channels. Playlists take part once loaded, Xtream providers once
+// their full live stream list was fetched; Stalker, TVHeadend, HDHomeRun
+// and SAT>IP channels are only known by asking the server, so they're left
+// out
+#[tauri::command]
+pub fn list_logical_channels(app: AppHandle) -> Result<Vec<LogicalChannel>, String> {
+    let store = app.state::<PlaylistStore>();
+    let cache = app.state::<XtreamCache>();
+    let mut candidates = Vec::new();
+    for provider in sync(&app)? {
+        match provider.config.source.backend() {
+            Backend::Playlists => {
+                if let Some(entries) = store.get(&provider.id) {
+                    let visible = entries.iter().filter(|entry| !entry.hidden);
+                    candidates.extend(visible.map(|entry| Candidate::from_entry(&provider.id, entry)));
+                }
+            }
+            Backend::Xtream => {
+                if let Some(streams) = cache.all_live_streams(&provider.id) {
+                    candidates.extend(streams.iter().map(|stream| Candidate::from_live_stream(&provider.id, stream)));
+                }
+            }
+            Backend::Stalker | Backend::Tvheadend | Backend::Hdhomerun | Backend::Satip => {}
+        }
+    }
+    let channels = failover::group(candidates);
+    app.state::<Failover>().remember(&channels);
+    Ok(channels)
+}
+
+// Command handler picking the stream to play for a logical channel: the
+// preferred source that answers. Pass the source that just stopped working
+// to have it tried last for a while
+#[tauri::command]
+pub async fn select_channel_source(
+    app: AppHandle,
+    failover: State<'_, Failover>,
+    library: State<'_, PlaylistLibrary>,
+    logical_channel_id: String,
+    failed_channel_id: Option<String>,
+) -> Result<SourceChoice, String> {
+    let now = unix_now();
+    if let Some(failed) = failed_channel_id {
+        failover.mark_failed(&failed, now);
+    }
+    failover::select(&failover, &library, Some(&app), &logical_channel_id, now).await
+}
+
+// Connection limit of a provider: the configured one, else for Xtream
+// what the server reports (every account of the provider brings its own
+// connections) and for HDHomeRun and SAT>IP the number of tuners
+async fn connection_limit(app: &AppHandle, provider: &Provider) -> Result<Option<u32>, String> {
+    if provider.config.max_connections.is_some() {
+        return Ok(provider.config.max_connections);
+    }
+    match &provider.config.source {
+        ProviderSource::Xtream(_) => {
+            let providers = app.state::<XtreamProviders>();
+            let info = xtream::account_info(&providers, &app.state::<XtreamCache>(), &provider.id).await?;
+            let reported = info.user_info.max_connections;
+            let accounts = providers.get(&provider.id)?.logins().len();
+            Ok((reported > 0).then(|| reported.saturating_mul(u32::try_from(accounts).unwrap_or(u32::MAX))))
+        }
+        ProviderSource::Hdhomerun(tuner) => {
+            let tuners = HdhomerunClient::new(tuner)?.device().await?.tuner_count;
+            Ok((tuners > 0).then_some(tuners))
+        }
+        ProviderSource::Satip(server) => {
+            let tuners = SatipClient::new(server)?.describe().await?.tuner_count();
+            Ok((tuners > 0).then_some(tuners))
+        }
+        _ => Ok(None),
+    }
+}
+
+// A connection the backend holds while it reads a stream from a provider,
+// given back a little after it's dropped
+pub struct ConnectionClaim {
+    app: AppHandle,
+    id: u64,
+}
+
+impl Drop for ConnectionClaim {
+    fn drop(&mut self) {
+        let (app, id) = (self.app.clone(), self.id);
+        tauri::async_runtime::spawn(async move {
+            tokio::time::sleep(RELEASE_DELAY).await;
+            for connection in app.state::<ConnectionTracker>().release(id, unix_now()) {
+                let _ = app.emit("connection-opened", connection);
+            }
+        });
+    }
+}
+
+// Claim a connection of a provider for a stream the backend reads, shared
+// with whatever else holds the same stream; None when there's no such
+// provider. A full provider fails with `limitReached`
+pub async fn claim(
+    app: &AppHandle,
+    provider_id: &str,
+    stream: &str,
+    label: &str,
+) -> Result<Option<ConnectionClaim>, ConnectionError> {
+    let tracker = app.state::<ConnectionTracker>();
+    if let Some(id) = tracker.join(provider_id, stream) {
+        return Ok(Some(ConnectionClaim { app: app.clone(), id }));
+    }
+    let Ok(provider) = find(app, provider_id) else {
+        return Ok(None);
+    };
+    // Streams aren't held up by an account that can't be asked
+    let limit = connection_limit(app, &provider).await.unwrap_or_else(|e| {
+        log::debug!("No connection limit for provider '{}': {}", provider_id, e);
+        provider.config.max_connections
+    });
+    let id = tracker.claim(provider_id, limit, stream, label, unix_now())?;
+    Ok(Some(ConnectionClaim { app: app.clone(), id }))
+}
+
+// The webview's connections end with the page it opened them from
+pub fn on_page_load(webview: &Webview, payload: &PageLoadPayload<'_>) {
+    if payload.event() == PageLoadEvent::Started {
+        webview.state::<ConnectionTracker>().release_webview(unix_now());
+    }
+}
+
+// Command handler claiming one of a provider's connections before a stream
+// or recording starts; `stream_id` (the channel id) lets the proxy's relays
+// of the stream share it. A full provider fails with `limitReached`, or
+// with `queue` set puts it in line; a queued connection is announced with
+// a "connection-opened" event when its turn comes
+#[tauri::command]
+pub async fn open_connection(
+    app: AppHandle,
+    provider_id: String,
+    kind: ConnectionKind,
+    label: String,
+    queue: Option<bool>,
+    stream_id: Option<String>,
+) -> Result<Grant, ConnectionError> {
+    validate_string_length(&label, MAX_NAME_LENGTH)?;
+    let provider = find(&app, &provider_id)?;
+    let limit = connection_limit(&app, &provider).await?;
+    let tracker = app.state::<ConnectionTracker>();
+    let grant = tracker.open(&provider_id, limit, kind, &label, queue.unwrap_or(false), unix_now())?;
+    if let Some(stream) = stream_id.as_deref().map(str::trim).filter(|stream| !stream.is_empty()) {
+        validate_string_length(stream, MAX_FIELD_LENGTH)?;
+        let (Grant::Open(connection) | Grant::Queued { connection, .. }) = &grant;
+        tracker.carry(connection.id, stream);
+    }
+    Ok(grant)
+}
+
+// Command handler for a stream or recording that ended, or a queued one
+// given up on
+#[tauri::command]
+pub fn close_connection(app: AppHandle, connection_id: u64) -> bool {
+    let Some(promoted) = app.state::<ConnectionTracker>().close(connection_id, unix_now()) else {
+        return false;
+    };
+    for connection in promoted {
+        let _ = app.emit("connection-opened", connection);
+    }
+    true
+}
+
+// Command handler listing open and queued connections of every provider
+#[tauri::command]
+pub fn list_connections(tracker: State<'_, ConnectionTracker>) -> ConnectionList {
+    tracker.list()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    fn source(id: &str, source: ProviderSource) -> Provider {
+        Provider {
+            id: id.to_string(),
+            created_at: 0,
+            config: ProviderConfig {
+                name: id.to_string(),
+                source,
+                refresh_interval_hours: Some(6),
+                max_connections: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_config_shape() {
+        // Test that the kind tag and the kind's fields sit next to the shared ones
+        let config: ProviderConfig = serde_json::from_str(
+            r#"{"name": "Home", "kind": "xtream", "server": "http://tv", "username": "u", "password": "p",
+                "refreshIntervalHours": 12}"#,
+        )
+        .unwrap();
+        assert_eq!(config.refresh_interval_hours, Some(12));
+        assert!(matches!(&config.source, ProviderSource::Xtream(c) if c.username == "u"));
+
+        let file = ProviderSource::M3uFile {
+            path: "/tmp/list.m3u".to_string(),
+        };
+        let json = serde_json::to_value(source("a", file)).unwrap();
+        assert_eq!(json["kind"], "m3uFile");
+        assert_eq!(json["path"], "/tmp/list.m3u");
+        assert_eq!(json["createdAt"], 0);
+    }
+
+    #[test]
+    fn test_prepare_normalizes() {
+        // Test that ids follow the backends' schemes and names default to the source
+        let config = |source| ProviderConfig {
+            name: " ".to_string(),
+            source,
+            refresh_interval_hours: None,
+            max_connections: Some(0),
+        };
+        let (id, prepared) = prepare(config(ProviderSource::M3uUrl {
+            url: " http://example.com/list.m3u ".to_string(),
+            http: HttpOptions::default(),
+            auth: Some(BasicAuth {
+                username: String::new(),
+                password: "unused".to_string(),
+            }),
+        }))
+        .unwrap();
+        assert_eq!(id, playlist::playlist_id("http://example.com/list.m3u"));
+        assert_eq!(prepared.name, "http://example.com/list.m3u");
+        assert_eq!(prepared.max_connections, None);
+        assert!(matches!(prepared.source, ProviderSource::M3uUrl { auth: None, .. }));
+
+        let m3u = |url: &str| {
+            config(ProviderSource::M3uUrl {
+                url: url.to_string(),
+                http: HttpOptions::default(),
+                auth: None,
+            })
+        };
+        assert!(prepare(m3u("ftp://example.com/list.m3u")).is_err());
+        assert!(prepare(config(ProviderSource::M3uFile { path: "".to_string() })).is_err());
+
+        let (_, prepared) = prepare(config(ProviderSource::Stalker(StalkerPortal {
+            url: "example.com/c/".to_string(),
+            mac: "00-1a-79-00-00-01".to_string(),
+            ..Default::default()
+        })))
+        .unwrap();
+        assert!(matches!(prepared.source, ProviderSource::Stalker(p) if p.mac == "00:1A:79:00:00:01"));
+    }
+
+    #[test]
+    fn test_reorder() {
+        // Test that ranked providers move to the front in the given order
+        let registry = ProviderRegistry::open(&temp_dir("providers-reorder"));
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+        registry.reconcile(&ids(&["a", "b", "c", "d"]), 0).unwrap();
+        registry.reorder(&ids(&["c", "missing", "a"])).unwrap();
+        let order = registry.reconcile(&ids(&["a", "b", "c", "d"]), 0).unwrap();
+        assert_eq!(order.iter().map(|e| e.id.as_str()).collect::<Vec<_>>(), vec!["c", "a", "b", "d"]);
+    }
+
+    #[test]
+    fn test_reconcile_and_join() {
+        // Test that entries follow the backends and keep their order and dates
+        let dir = temp_dir("providers-registry");
+        let registry = ProviderRegistry::open(&dir);
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+        registry.reconcile(&ids(&["b", "a"]), 100).unwrap();
+        registry
+            .upsert(ProviderEntry {
+                id: "a".to_string(),
+                created_at: 100,
+                refresh_interval_hours: Some(24),
+                max_connections: Some(2),
+            })
+            .unwrap();
+
+        let reopened = ProviderRegistry::open(&dir);
+        let entries = reopened.reconcile(&ids(&["a", "c"]), 200).unwrap();
+        assert_eq!(entries.iter().map(|e| e.id.as_str()).collect::<Vec<_>>(), vec!["a", "c"]);
+        assert_eq!(entries[1].created_at, 200);
+        assert_eq!(reopened.refresh_interval("a"), Some(24));
+
+        let playlist = ProviderSource::M3uFile {
+            path: "/tmp/c.m3u".to_string(),
+        };
+        let xtream = ProviderSource::Xtream(XtreamCredentials::default());
+        let joined = join(entries, vec![source("c", playlist), source("a", xtream)]);
+        assert_eq!(joined.iter().map(|p| p.id.as_str()).collect::<Vec<_>>(), vec!["a", "c"]);
+        // Playlists keep the library's interval, other kinds the registry's
+        assert_eq!(joined[0].config.refresh_interval_hours, Some(24));
+        assert_eq!(joined[1].config.refresh_interval_hours, Some(6));
+        assert_eq!(joined[0].created_at, 100);
+        assert_eq!((joined[0].config.max_connections, joined[1].config.max_connections), (Some(2), None));
+    }
+}
@@ -0,0 +1,241 @@
+// HLS variant selection in the proxy. A master playlist fetched for an
+// adaptive stream is served with a single variant pointing back at the
+// proxy; every time the player reloads that media playlist the proxy picks
+// the variant the throughput measured on recent segment downloads can
+// sustain, and serves its playlist. Variants of a master are meant to have
+// aligned segments, so switching between reloads plays on seamlessly
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use url::Url;
+
+use super::hls::attribute;
+
+// Streams tracked before the least recently used is dropped
+const MAX_STREAMS: usize = 32;
+
+// Assumed throughput in bits/s before any download is measured
+const START_ESTIMATE: f64 = 2_000_000.0;
+
+// Share of the estimate a variant may use when picked, and the share the
+// current variant may use before it's switched down from
+const SAFETY: f64 = 0.7;
+const KEEP: f64 = 0.9;
+
+// Weight of the newest download in the estimate
+const SMOOTHING: f64 = 0.3;
+
+// Downloads too small to measure throughput by
+const MIN_SAMPLE_BYTES: u64 = 16 * 1024;
+const MIN_SAMPLE_TIME: Duration = Duration::from_millis(5);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    pub url: String,
+    // AVERAGE-BANDWIDTH when given, else BANDWIDTH, in bits/s
+    pub bandwidth: u64,
+    // From RESOLUTION
+    pub height: Option<u32>,
+    // The #EXT-X-STREAM-INF line
+    pub info: String,
+}
+
+// The variants of a master playlist, with their URIs resolved
+pub fn variants(master: &str, base: &Url) -> Vec<Variant> {
+    let mut variants = Vec::new();
+    let mut info: Option<&str> = None;
+    for line in master.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        if line.starts_with("#EXT-X-STREAM-INF:") {
+            info = Some(line);
+        } else if line.starts_with('#') {
+            continue;
+        } else if let Some(info) = info.take() {
+            let bandwidth = attribute(info, "AVERAGE-BANDWIDTH")
+                .or_else(|| attribute(info, "BANDWIDTH"))
+                .and_then(|bandwidth| bandwidth.parse().ok());
+            let url = base.join(line).ok().filter(|url| matches!(url.scheme(), "http" | "https"));
+            if let (Some(bandwidth), Some(url)) = (bandwidth, url) {
+                variants.push(Variant {
+                    url: url.to_string(),
+                    bandwidth,
+                    height: attribute(info, "RESOLUTION")
+                        .and_then(|resolution| resolution.split_once(['x', 'X']))
+                        .and_then(|(_, height)| height.parse().ok()),
+                    info: info.to_string(),
+                });
+            }
+        }
+    }
+    variants
+}
+
+// The master without its variants (renditions and session tags stay), for
+// hls::rewrite to relink before the proxy's single variant is added
+pub fn without_variants(master: &str) -> String {
+    let mut kept = String::with_capacity(master.len());
+    let mut skip_uri = false;
+    for line in master.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("#EXT-X-STREAM-INF:") {
+            skip_uri = true;
+        } else if trimmed.starts_with("#EXT-X-I-FRAME-STREAM-INF:") {
+            continue;
+        } else if skip_uri && !trimmed.is_empty() && !trimmed.starts_with('#') {
+            skip_uri = false;
+        } else {
+            kept.push_str(line);
+            kept.push('\n');
+        }
+    }
+    kept
+}
+
+// The variant to play: with no estimate the current one, or the best
+// START_ESTIMATE allows; then the best the estimate allows once it can
+// carry more than the current one, or when the current can't be kept up
+pub fn choose(variants: &[Variant], current: Option<usize>, estimate: Option<f64>) -> usize {
+    let best = |limit: f64| {
+        let fitting = variants.iter().enumerate().filter(|(_, variant)| variant.bandwidth as f64 <= limit);
+        let lowest = variants.iter().enumerate().min_by_key(|(_, variant)| variant.bandwidth);
+        fitting.max_by_key(|(_, variant)| variant.bandwidth).or(lowest).map_or(0, |(index, _)| index)
+    };
+    let current = current.filter(|current| *current < variants.len());
+    let Some(estimate) = estimate else {
+        return current.unwrap_or_else(|| best(START_ESTIMATE * SAFETY));
+    };
+    let best = best(estimate * SAFETY);
+    match current {
+        Some(current)
+            if variants[current].bandwidth as f64 <= estimate * KEEP
+                && variants[best].bandwidth <= variants[current].bandwidth =>
+        {
+            current
+        }
+        _ => best,
+    }
+}
+
+struct Stream {
+    variants: Vec<Variant>,
+    current: Option<usize>,
+    // Smoothed throughput in bits/s
+    estimate: Option<f64>,
+    used: Instant,
+}
+
+// Adaptive streams by id
+#[derive(Default)]
+pub struct Adaptive {
+    streams: Mutex<HashMap<String, Stream>>,
+}
+
+impl Adaptive {
+    // Track a master's variants, keeping what's been measured for it
+    pub fn register(&self, id: &str, variants: Vec<Variant>) {
+        let mut streams = self.streams.lock().unwrap();
+        if let Some(stream) = streams.get_mut(id) {
+            stream.variants = variants;
+            stream.current = None;
+            stream.used = Instant::now();
+            return;
+        }
+        if streams.len() >= MAX_STREAMS {
+            let oldest = streams.iter().min_by_key(|(_, stream)| stream.used).map(|(id, _)| id.clone());
+            if let Some(oldest) = oldest {
+                streams.remove(&oldest);
+            }
+        }
+        streams.insert(
+            id.to_string(),
+            Stream {
+                variants,
+                current: None,
+                estimate: None,
+                used: Instant::now(),
+            },
+        );
+    }
+
+    // The URL of the variant to serve next for a stream
+    pub fn select(&self, id: &str) -> Option<String> {
+        let mut streams = self.streams.lock().unwrap();
+        let stream = streams.get_mut(id)?;
+        let chosen = choose(&stream.variants, stream.current, stream.estimate);
+        if stream.current.is_some_and(|current| current != chosen) {
+            log::info!(
+                "Switching to the {} bit/s variant at {:.0} bit/s measured",
+                stream.variants[chosen].bandwidth,
+                stream.estimate.unwrap_or_default()
+            );
+        }
+        stream.current = Some(chosen);
+        stream.used = Instant::now();
+        Some(stream.variants[chosen].url.clone())
+    }
+
+    // Measure a segment download of a stream
+    pub fn record(&self, id: &str, bytes: u64, elapsed: Duration) {
+        if bytes < MIN_SAMPLE_BYTES || elapsed < MIN_SAMPLE_TIME {
+            return;
+        }
+        let sample = bytes as f64 * 8.0 / elapsed.as_secs_f64();
+        if let Some(stream) = self.streams.lock().unwrap().get_mut(id) {
+            stream.estimate = Some(match stream.estimate {
+                Some(estimate) => estimate * (1.0 - SMOOTHING) + sample * SMOOTHING,
+                None => sample,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_variant_selection() {
+        // Test that variants are parsed and switched between as the measured throughput changes
+        let master = "#EXTM3U\n#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"a\",URI=\"audio.m3u8\"\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=6000000,RESOLUTION=1920x1080\nhd.m3u8\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=900000,AVERAGE-BANDWIDTH=800000\nsd.m3u8\n\
+            #EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=100000,URI=\"iframes.m3u8\"\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=3000000\nhttps://cdn/mid.m3u8\n";
+        let base = Url::parse("http://host/live/master.m3u8").unwrap();
+        let found = variants(master, &base);
+        let summary: Vec<(&str, u64, Option<u32>)> = found
+            .iter()
+            .map(|variant| (variant.url.as_str(), variant.bandwidth, variant.height))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("http://host/live/hd.m3u8", 6_000_000, Some(1080)),
+                ("http://host/live/sd.m3u8", 800_000, None),
+                ("https://cdn/mid.m3u8", 3_000_000, None),
+            ]
+        );
+        assert_eq!(
+            without_variants(master),
+            "#EXTM3U\n#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"a\",URI=\"audio.m3u8\"\n"
+        );
+
+        // Nothing measured: the best START_ESTIMATE allows
+        assert_eq!(choose(&found, None, None), 1);
+        // Enough for the middle variant but not the top one
+        assert_eq!(choose(&found, Some(1), Some(5_000_000.0)), 2);
+        // Kept while it fits within KEEP, though not SAFETY
+        assert_eq!(choose(&found, Some(2), Some(3_500_000.0)), 2);
+        assert_eq!(choose(&found, Some(2), Some(3_000_000.0)), 1);
+        assert_eq!(choose(&found, Some(2), Some(10_000_000.0)), 0);
+        // Below every variant: the lowest
+        assert_eq!(choose(&found, Some(0), Some(100_000.0)), 1);
+
+        let adaptive = Adaptive::default();
+        adaptive.register("s", found);
+        assert_eq!(adaptive.select("s").as_deref(), Some("http://host/live/sd.m3u8"));
+        adaptive.record("s", 625_000, Duration::from_secs(1));
+        assert_eq!(adaptive.select("s").as_deref(), Some("https://cdn/mid.m3u8"));
+        assert_eq!(adaptive.select("missing"), None);
+    }
+}
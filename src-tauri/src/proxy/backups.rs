@@ -0,0 +1,153 @@
+// Channels with backup streams, from merged duplicates or entered by hand.
+// The proxy fetches the channel's active source; after FAILOVER_AFTER
+// failed requests in a row it moves on to the next one, wrapping around
+// to the first, and the request is made again there. The app hears
+// "stream-source-changed" on every switch and can ask which source is on
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use reqwest::StatusCode;
+use serde::Serialize;
+use tauri::State;
+
+use super::StreamProxy;
+
+// Consecutive failures before switching to the next source
+const FAILOVER_AFTER: u32 = 3;
+
+// Channels tracked before the least recently used is dropped
+const MAX_CHANNELS: usize = 32;
+
+struct Channel {
+    urls: Vec<String>,
+    active: usize,
+    errors: u32,
+    used: Instant,
+}
+
+// The source a channel plays from
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActiveSource {
+    pub channel_id: String,
+    pub url: String,
+    // 0 for the main stream, then the backups in order
+    pub index: usize,
+    pub sources: usize,
+}
+
+// Whether a response counts against the source
+pub fn failed(status: StatusCode) -> bool {
+    status.is_server_error() || (status.is_client_error() && status != StatusCode::RANGE_NOT_SATISFIABLE)
+}
+
+#[derive(Default)]
+pub struct Sources {
+    channels: Mutex<HashMap<String, Channel>>,
+}
+
+impl Sources {
+    // Track a channel's streams, main one first, keeping the active source
+    // while they stay the same
+    pub fn register(&self, id: &str, urls: Vec<String>) {
+        let mut channels = self.channels.lock().unwrap();
+        if let Some(channel) = channels.get_mut(id).filter(|channel| channel.urls == urls) {
+            channel.used = Instant::now();
+            return;
+        }
+        if channels.len() >= MAX_CHANNELS && !channels.contains_key(id) {
+            let oldest = channels.iter().min_by_key(|(_, channel)| channel.used).map(|(id, _)| id.clone());
+            if let Some(oldest) = oldest {
+                channels.remove(&oldest);
+            }
+        }
+        let channel = Channel {
+            urls,
+            active: 0,
+            errors: 0,
+            used: Instant::now(),
+        };
+        channels.insert(id.to_string(), channel);
+    }
+
+    // The URL to fetch for a channel now
+    pub fn active(&self, id: &str) -> Option<String> {
+        let mut channels = self.channels.lock().unwrap();
+        let channel = channels.get_mut(id)?;
+        channel.used = Instant::now();
+        Some(channel.urls[channel.active].clone())
+    }
+
+    pub fn succeeded(&self, id: &str) {
+        if let Some(channel) = self.channels.lock().unwrap().get_mut(id) {
+            channel.errors = 0;
+        }
+    }
+
+    // Count a failure of the active source; the source switched to once
+    // there have been enough in a row
+    pub fn failed(&self, id: &str) -> Option<ActiveSource> {
+        let mut channels = self.channels.lock().unwrap();
+        let channel = channels.get_mut(id)?;
+        channel.errors += 1;
+        if channel.errors < FAILOVER_AFTER || channel.urls.len() < 2 {
+            return None;
+        }
+        channel.errors = 0;
+        channel.active = (channel.active + 1) % channel.urls.len();
+        log::info!("Switching {} to source {}: {}", id, channel.active, channel.urls[channel.active]);
+        Some(source(id, channel))
+    }
+
+    pub fn get(&self, id: &str) -> Option<ActiveSource> {
+        self.channels.lock().unwrap().get(id).map(|channel| source(id, channel))
+    }
+}
+
+fn source(id: &str, channel: &Channel) -> ActiveSource {
+    ActiveSource {
+        channel_id: id.to_string(),
+        url: channel.urls[channel.active].clone(),
+        index: channel.active,
+        sources: channel.urls.len(),
+    }
+}
+
+// Command handler returning the source a channel played through the proxy
+// is on, None for channels without backups or not played yet
+#[tauri::command]
+pub fn get_stream_source(proxy: State<'_, StreamProxy>, channel_id: String) -> Option<ActiveSource> {
+    proxy.sources.get(&channel_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failover_after_consecutive_errors() {
+        // Test that a channel switches source after enough failures in a row and wraps around to the first
+        let sources = Sources::default();
+        let urls = vec!["http://a/1".to_string(), "http://b/1".to_string()];
+        sources.register("bbc", urls.clone());
+        assert_eq!(sources.active("bbc").as_deref(), Some("http://a/1"));
+        assert_eq!(sources.failed("bbc"), None);
+        sources.succeeded("bbc");
+        assert_eq!(sources.failed("bbc"), None);
+        assert_eq!(sources.failed("bbc"), None);
+        let switched = sources.failed("bbc").unwrap();
+        assert_eq!((switched.index, switched.url.as_str()), (1, "http://b/1"));
+        assert_eq!(sources.active("bbc").as_deref(), Some("http://b/1"));
+
+        // Registering the same streams again keeps the active source
+        sources.register("bbc", urls);
+        assert_eq!(sources.get("bbc").map(|source| source.index), Some(1));
+        for _ in 0..FAILOVER_AFTER {
+            sources.failed("bbc");
+        }
+        assert_eq!(sources.active("bbc").as_deref(), Some("http://a/1"));
+        assert!(failed(StatusCode::NOT_FOUND) && !failed(StatusCode::RANGE_NOT_SATISFIABLE));
+        assert!(!failed(StatusCode::PARTIAL_CONTENT));
+    }
+}
@@ -0,0 +1,884 @@
+// Closed captions of North American channels (CEA-608 and CEA-708 in ATSC
+// A/53 user data), served as WebVTT. Captions ride along in the video
+// stream: in SEI messages of H.264 and HEVC, in the user data of MPEG-2
+// pictures. The caption byte pairs are taken from the video frames, put
+// back in presentation order and decoded for the chosen channel (CC1 to
+// CC4) the way a caption decoder draws them; a pop-on caption is a cue,
+// roll-up captions make a new cue each time a line is added. A CEA-708
+// service is decoded into its windows' text, what the visible windows show
+// making the cues; pen and window styles, positions and priorities are
+// left out
+use std::collections::BTreeMap;
+
+use super::ts::{self, Clock, Demuxer, ElementaryStream};
+use super::webvtt::{self, Cue};
+
+// Video stream types captions are read from
+const MPEG2_VIDEO: u8 = 0x02;
+const H264_VIDEO: u8 = 0x1B;
+const HEVC_VIDEO: u8 = 0x24;
+
+// Frames held back to put captions in presentation order
+const REORDER_DEPTH: usize = 8;
+
+const COLUMNS: usize = 32;
+const BOTTOM_ROW: u8 = 15;
+
+// Special characters, 0x11 0x30 to 0x3F (0x39 is a transparent space)
+const SPECIAL: [char; 16] = ['®', '°', '½', '¿', '™', '¢', '£', '♪', 'à', ' ', 'è', 'â', 'ê', 'î', 'ô', 'û'];
+
+// Extended characters, 0x12 and 0x13 followed by 0x20 to 0x3F; each
+// replaces the basic character sent before it for older decoders
+const EXTENDED: [[char; 32]; 2] = [
+    [
+        'Á', 'É', 'Ó', 'Ú', 'Ü', 'ü', '‘', '¡', '*', '’', '—', '©', '℠', '•', '“', '”', 'À', 'Â', 'Ç', 'È', 'Ê', 'Ë',
+        'ë', 'Î', 'Ï', 'ï', 'Ô', 'Ù', 'ù', 'Û', '«', '»',
+    ],
+    [
+        'Ã', 'ã', 'Í', 'Ì', 'ì', 'Ò', 'ò', 'Õ', 'õ', '{', '}', '\\', '^', '_', '|', '~', 'Ä', 'ä', 'Ö', 'ö', 'ß', '¥',
+        '¤', '¦', 'Å', 'å', 'Ø', 'ø', '┌', '┐', '└', '┘',
+    ],
+];
+
+// CEA-708 characters of the G2 set, after EXT1; the rest aren't drawn
+fn g2(code: u8) -> Option<char> {
+    let character = match code {
+        0x20 | 0x21 => ' ',
+        0x25 => '…',
+        0x2A => 'Š',
+        0x2C => 'Œ',
+        0x30 => '█',
+        0x31 => '‘',
+        0x32 => '’',
+        0x33 => '“',
+        0x34 => '”',
+        0x35 => '•',
+        0x39 => '™',
+        0x3A => 'š',
+        0x3C => 'œ',
+        0x3D => '℠',
+        0x3F => 'Ÿ',
+        0x76 => '⅛',
+        0x77 => '⅜',
+        0x78 => '⅝',
+        0x79 => '⅞',
+        0x7A => '│',
+        0x7B => '┐',
+        0x7C => '└',
+        0x7D => '─',
+        0x7E => '┘',
+        0x7F => '┌',
+        _ => return None,
+    };
+    Some(character)
+}
+
+// Parameter bytes of a CEA-708 C0 or C1 command
+fn parameters(command: u8) -> usize {
+    match command {
+        0x10..=0x17 | 0x88..=0x8D => 1,
+        0x18..=0x1F | 0x90 | 0x92 => 2,
+        0x91 => 3,
+        0x97 => 4,
+        0x98..=0x9F => 6,
+        _ => 0,
+    }
+}
+
+// Bytes after EXT1 and the extended code that a C2 or C3 command takes
+fn extended_parameters(code: u8, rest: &[u8]) -> usize {
+    match code {
+        0x08..=0x0F => 1,
+        0x10..=0x17 => 2,
+        0x18..=0x1F => 3,
+        0x80..=0x87 => 4,
+        0x88..=0x8F => 5,
+        // Variable length, the size in the next byte
+        0x90..=0x9F => rest.first().map_or(0, |size| 1 + (size & 0x1F) as usize),
+        _ => 0,
+    }
+}
+
+// The rows of the preamble address codes 0x10 to 0x17; the second byte's
+// 0x20 bit moves to the row below
+const PAC_ROWS: [u8; 8] = [11, 1, 3, 12, 14, 5, 7, 9];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Codec {
+    Mpeg2,
+    H264,
+    Hevc,
+}
+
+fn character(code: u8) -> char {
+    match code {
+        0x2A => 'á',
+        0x5C => 'é',
+        0x5E => 'í',
+        0x5F => 'ó',
+        0x60 => 'ú',
+        0x7B => 'ç',
+        0x7C => '÷',
+        0x7D => 'Ñ',
+        0x7E => 'ñ',
+        0x7F => '█',
+        code => code as char,
+    }
+}
+
+// The valid caption data of an ATSC user data payload ("GA94", type 3):
+// the cc_type (0 and 1 for the 608 fields, 2 and 3 for CEA-708) and the
+// byte pair
+fn cc_data(user_data: &[u8], pairs: &mut Vec<[u8; 3]>) {
+    let Some(data) = user_data.strip_prefix(b"GA94\x03") else {
+        return;
+    };
+    // process_cc_data_flag
+    if !data.first().is_some_and(|flags| flags & 0x40 != 0) {
+        return;
+    }
+    let count = (data[0] & 0x1F) as usize;
+    for triple in data.get(2..).unwrap_or_default().chunks_exact(3).take(count) {
+        if triple[0] & 0x04 != 0 {
+            pairs.push([triple[0] & 0x03, triple[1], triple[2]]);
+        }
+    }
+}
+
+// A NAL unit's payload without emulation prevention bytes
+fn unescape(nal: &[u8]) -> Vec<u8> {
+    let mut rbsp = Vec::with_capacity(nal.len());
+    let mut zeros = 0;
+    for &byte in nal {
+        if zeros >= 2 && byte == 0x03 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        rbsp.push(byte);
+    }
+    rbsp
+}
+
+// An SEI message's type or size: 0xFF bytes that add up, then the last
+fn sei_number(rest: &mut &[u8]) -> Option<usize> {
+    let mut value = 0;
+    while let Some((&byte, tail)) = rest.split_first() {
+        *rest = tail;
+        value += byte as usize;
+        if byte != 0xFF {
+            return Some(value);
+        }
+    }
+    None
+}
+
+// Caption pairs of the user_data_registered_itu_t_t35 messages of an SEI
+fn sei(rbsp: &[u8], pairs: &mut Vec<[u8; 3]>) {
+    let mut rest = rbsp;
+    while rest.first().is_some_and(|byte| *byte != 0x80) {
+        let (Some(kind), Some(size)) = (sei_number(&mut rest), sei_number(&mut rest)) else {
+            return;
+        };
+        let Some(message) = rest.get(..size) else {
+            return;
+        };
+        rest = &rest[size..];
+        // United States, ATSC
+        if let Some(user_data) = message.strip_prefix(&[0xB5, 0x00, 0x31]).filter(|_| kind == 4) {
+            cc_data(user_data, pairs);
+        }
+    }
+}
+
+// The caption data in a PES payload of video
+fn caption_pairs(codec: Codec, payload: &[u8]) -> Vec<[u8; 3]> {
+    let starts: Vec<usize> =
+        payload.windows(3).enumerate().filter(|(_, code)| *code == [0, 0, 1]).map(|(at, _)| at + 3).collect();
+    let mut pairs = Vec::new();
+    for (index, &start) in starts.iter().enumerate() {
+        let end = starts.get(index + 1).map_or(payload.len(), |next| next - 3);
+        let Some(unit) = payload.get(start..end).filter(|unit| !unit.is_empty()) else {
+            continue;
+        };
+        match codec {
+            Codec::Mpeg2 if unit[0] == 0xB2 => cc_data(&unit[1..], &mut pairs),
+            Codec::H264 if unit[0] & 0x1F == 6 => sei(&unescape(&unit[1..]), &mut pairs),
+            Codec::Hevc if (unit[0] >> 1) & 0x3F == 39 && unit.len() > 2 => sei(&unescape(&unit[2..]), &mut pairs),
+            _ => {}
+        }
+    }
+    pairs
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    PopOn,
+    // Rows in the window
+    RollUp(u8),
+    PaintOn,
+    // Text service, not captions
+    Text,
+}
+
+// Rows of the screen, or of the caption being loaded
+type Memory = BTreeMap<u8, Vec<char>>;
+
+// The caption screen of one channel
+struct Screen {
+    // 0 or 1, the data channel within the field
+    channel: u8,
+    // The data channel the last control code was for
+    current: Option<u8>,
+    mode: Mode,
+    displayed: Memory,
+    loading: Memory,
+    row: u8,
+    column: usize,
+    // Control codes come twice; the first was just seen
+    repeated: Option<[u8; 2]>,
+    changed: bool,
+}
+
+impl Screen {
+    fn new(channel: u8) -> Self {
+        Screen {
+            channel,
+            current: None,
+            mode: Mode::PopOn,
+            displayed: Memory::new(),
+            loading: Memory::new(),
+            row: BOTTOM_ROW,
+            column: 0,
+            repeated: None,
+            changed: false,
+        }
+    }
+
+    fn text(&self) -> String {
+        let rows: Vec<String> = self
+            .displayed
+            .values()
+            .map(|row| row.iter().collect::<String>().trim().to_string())
+            .filter(|row| !row.is_empty())
+            .collect();
+        rows.join("\n")
+    }
+
+    fn memory(&mut self) -> &mut Memory {
+        match self.mode {
+            Mode::PopOn => &mut self.loading,
+            _ => &mut self.displayed,
+        }
+    }
+
+    fn write(&mut self, character: char) {
+        if self.mode == Mode::Text {
+            return;
+        }
+        let (row, column) = (self.row, self.column);
+        let cells = self.memory().entry(row).or_insert_with(|| vec![' '; COLUMNS]);
+        if let Some(cell) = cells.get_mut(column) {
+            *cell = character;
+        }
+        self.column = (column + 1).min(COLUMNS - 1);
+        self.changed |= self.mode == Mode::PaintOn;
+    }
+
+    fn backspace(&mut self) {
+        self.column = self.column.saturating_sub(1);
+        let (row, column) = (self.row, self.column);
+        if let Some(cell) = self.memory().get_mut(&row).and_then(|cells| cells.get_mut(column)) {
+            *cell = ' ';
+        }
+    }
+
+    fn pair(&mut self, pair: [u8; 2]) {
+        let [first, second] = pair;
+        if first == 0 && second == 0 {
+            return;
+        }
+        if !(0x10..=0x1F).contains(&first) {
+            self.repeated = None;
+            if self.current == Some(self.channel) {
+                self.write(character(first));
+                if second >= 0x20 {
+                    self.write(character(second));
+                }
+            }
+            return;
+        }
+        if self.repeated.take() == Some(pair) {
+            return;
+        }
+        self.repeated = Some(pair);
+        self.current = Some((first >> 3) & 0x01);
+        if self.current != Some(self.channel) {
+            return;
+        }
+        let code = first & 0xF7;
+        match (code, second) {
+            (_, 0x40..=0x7F) => self.preamble(code, second),
+            // Mid-row style codes take a space
+            (0x11, 0x20..=0x2F) => self.write(' '),
+            (0x11, 0x30..=0x3F) => self.write(SPECIAL[(second - 0x30) as usize]),
+            (0x12 | 0x13, 0x20..=0x3F) => {
+                self.backspace();
+                self.write(EXTENDED[(code - 0x12) as usize][(second - 0x20) as usize]);
+            }
+            (0x14 | 0x15, 0x20..=0x2F) => self.command(second),
+            (0x17, 0x21..=0x23) => self.column = (self.column + (second - 0x20) as usize).min(COLUMNS - 1),
+            _ => {}
+        }
+    }
+
+    fn preamble(&mut self, code: u8, second: u8) {
+        let row = (PAC_ROWS[(code & 0x07) as usize] + u8::from(second & 0x20 != 0)).min(BOTTOM_ROW);
+        if let Mode::RollUp(_) = self.mode {
+            // The window moves to the new base row
+            let offset = row as i16 - self.row as i16;
+            let rows = std::mem::take(&mut self.displayed);
+            self.displayed = rows
+                .into_iter()
+                .filter_map(|(at, cells)| u8::try_from(at as i16 + offset).ok().map(|at| (at, cells)))
+                .filter(|(at, _)| (1..=BOTTOM_ROW).contains(at))
+                .collect();
+        }
+        self.row = row;
+        self.column = match second & 0x10 != 0 {
+            true => ((second & 0x0E) >> 1) as usize * 4,
+            false => 0,
+        };
+    }
+
+    fn command(&mut self, command: u8) {
+        match command {
+            // Resume caption loading
+            0x20 => self.mode = Mode::PopOn,
+            0x21 => self.backspace(),
+            // Delete to end of row
+            0x24 => {
+                let (row, column) = (self.row, self.column);
+                if let Some(cells) = self.memory().get_mut(&row) {
+                    cells[column..].fill(' ');
+                }
+            }
+            0x25..=0x27 => {
+                if !matches!(self.mode, Mode::RollUp(_)) {
+                    self.changed |= !self.displayed.is_empty();
+                    self.displayed.clear();
+                    self.loading.clear();
+                    self.row = BOTTOM_ROW;
+                }
+                self.mode = Mode::RollUp(command - 0x23);
+                self.column = 0;
+            }
+            // Resume direct captioning
+            0x29 => self.mode = Mode::PaintOn,
+            0x2A | 0x2B => self.mode = Mode::Text,
+            // Erase displayed memory
+            0x2C => {
+                self.displayed.clear();
+                self.changed = true;
+            }
+            // Carriage return
+            0x2D => {
+                if let Mode::RollUp(rows) = self.mode {
+                    let top = self.row.saturating_sub(rows - 1);
+                    let rows = std::mem::take(&mut self.displayed);
+                    self.displayed = rows
+                        .into_iter()
+                        .filter(|(at, _)| *at > top && *at <= self.row)
+                        .map(|(at, cells)| (at - 1, cells))
+                        .collect();
+                    self.changed = true;
+                }
+                self.column = 0;
+            }
+            // Erase non-displayed memory
+            0x2E => self.loading.clear(),
+            // End of caption: the loaded caption goes on screen
+            0x2F => {
+                std::mem::swap(&mut self.displayed, &mut self.loading);
+                self.mode = Mode::PopOn;
+                self.changed = true;
+            }
+            _ => {}
+        }
+    }
+}
+
+// A CEA-708 window: its rows of text and the pen's place in them
+struct Window {
+    visible: bool,
+    rows: u8,
+    columns: usize,
+    text: Memory,
+    row: u8,
+    column: usize,
+}
+
+impl Window {
+    fn carriage_return(&mut self) {
+        self.column = 0;
+        if self.row + 1 < self.rows {
+            self.row += 1;
+            return;
+        }
+        // The window scrolls up at its last row
+        let rows = std::mem::take(&mut self.text);
+        self.text = rows.into_iter().filter(|(at, _)| *at > 0).map(|(at, cells)| (at - 1, cells)).collect();
+    }
+}
+
+// One caption service of the CEA-708 (DTVCC) channel
+struct Service {
+    // 1 to 63
+    number: u8,
+    // The packet being put together from the caption data
+    packet: Vec<u8>,
+    windows: [Option<Window>; 8],
+    current: usize,
+    changed: bool,
+}
+
+impl Service {
+    fn new(number: u8) -> Self {
+        Service {
+            number,
+            packet: Vec::new(),
+            windows: Default::default(),
+            current: 0,
+            changed: false,
+        }
+    }
+
+    fn text(&self) -> String {
+        let rows: Vec<String> = self
+            .windows
+            .iter()
+            .flatten()
+            .filter(|window| window.visible)
+            .flat_map(|window| window.text.values())
+            .map(|row| row.iter().collect::<String>().trim().to_string())
+            .filter(|row| !row.is_empty())
+            .collect();
+        rows.join("\n")
+    }
+
+    // A byte pair of cc_type 2 or 3, the latter starting a packet
+    fn data(&mut self, start: bool, pair: [u8; 2]) {
+        if start {
+            // A packet cut short ends here
+            let packet = std::mem::take(&mut self.packet);
+            self.blocks(packet.get(1..).unwrap_or_default());
+        } else if self.packet.is_empty() {
+            return;
+        }
+        self.packet.extend(pair);
+        let size = match self.packet[0] & 0x3F {
+            0 => 128,
+            code => code as usize * 2,
+        };
+        if self.packet.len() >= size {
+            let packet = std::mem::take(&mut self.packet);
+            self.blocks(&packet[1..size]);
+        }
+    }
+
+    // The service blocks of a packet, decoding the ones of this service
+    fn blocks(&mut self, packet: &[u8]) {
+        let mut rest = packet;
+        while let Some((&header, tail)) = rest.split_first() {
+            rest = tail;
+            let (mut number, size) = (header >> 5, (header & 0x1F) as usize);
+            // Padding ends the packet
+            if number == 0 {
+                return;
+            }
+            if number == 7 {
+                let Some((&extended, tail)) = rest.split_first() else {
+                    return;
+                };
+                (number, rest) = (extended & 0x3F, tail);
+            }
+            let (block, tail) = rest.split_at(size.min(rest.len()));
+            rest = tail;
+            if number == self.number {
+                self.commands(block);
+            }
+        }
+    }
+
+    fn commands(&mut self, block: &[u8]) {
+        let mut at = 0;
+        while let Some(&code) = block.get(at) {
+            if code == 0x10 {
+                let Some(&extended) = block.get(at + 1) else {
+                    return;
+                };
+                if let Some(character) = g2(extended) {
+                    self.write(character);
+                }
+                at += 2 + extended_parameters(extended, &block[at + 2..]);
+                continue;
+            }
+            let Some(parameters) = block.get(at + 1..at + 1 + parameters(code)) else {
+                return;
+            };
+            at += 1 + parameters.len();
+            match code {
+                0x20..=0x7E => self.write(code as char),
+                0x7F => self.write('♪'),
+                // Latin-1
+                0xA0..=0xFF => self.write(code as char),
+                _ => self.command(code, parameters),
+            }
+        }
+    }
+
+    fn window(&mut self) -> Option<&mut Window> {
+        self.windows[self.current].as_mut()
+    }
+
+    // Text shows when a line ends or a window changes, not a letter at a
+    // time
+    fn touched(&mut self) {
+        self.changed |= self.window().is_some_and(|window| window.visible);
+    }
+
+    fn write(&mut self, character: char) {
+        let Some(window) = self.window() else {
+            return;
+        };
+        if window.column >= window.columns {
+            window.carriage_return();
+        }
+        let (row, column) = (window.row, window.column);
+        let cells = window.text.entry(row).or_default();
+        if cells.len() <= column {
+            cells.resize(column + 1, ' ');
+        }
+        cells[column] = character;
+        window.column += 1;
+    }
+
+    // Apply `change` to the windows of a bitmap
+    fn each(&mut self, bitmap: u8, change: impl Fn(&mut Option<Window>)) {
+        for (_, window) in self.windows.iter_mut().enumerate().filter(|(index, _)| bitmap & (1 << index) != 0) {
+            let shown = window.as_ref().is_some_and(|window| window.visible);
+            change(window);
+            self.changed |= shown || window.as_ref().is_some_and(|window| window.visible);
+        }
+    }
+
+    fn command(&mut self, code: u8, parameters: &[u8]) {
+        match code {
+            // End of text
+            0x03 => self.touched(),
+            // Backspace
+            0x08 => {
+                if let Some(window) = self.window() {
+                    window.column = window.column.saturating_sub(1);
+                    let (row, column) = (window.row, window.column);
+                    if let Some(cell) = window.text.get_mut(&row).and_then(|cells| cells.get_mut(column)) {
+                        *cell = ' ';
+                    }
+                }
+                self.touched();
+            }
+            // Form feed: the window is cleared
+            0x0C => {
+                if let Some(window) = self.window() {
+                    window.text.clear();
+                    (window.row, window.column) = (0, 0);
+                }
+                self.touched();
+            }
+            0x0D => {
+                if let Some(window) = self.window() {
+                    window.carriage_return();
+                }
+                self.touched();
+            }
+            // Horizontal carriage return: the row is cleared
+            0x0E => {
+                if let Some(window) = self.window() {
+                    let row = window.row;
+                    window.text.remove(&row);
+                    window.column = 0;
+                }
+                self.touched();
+            }
+            0x80..=0x87 => self.current = (code - 0x80) as usize,
+            0x88 => self.each(parameters[0], |window| {
+                if let Some(window) = window {
+                    window.text.clear();
+                }
+            }),
+            0x89..=0x8B => self.each(parameters[0], |window| {
+                if let Some(window) = window {
+                    window.visible = match code {
+                        0x89 => true,
+                        0x8A => false,
+                        _ => !window.visible,
+                    };
+                }
+            }),
+            0x8C => self.each(parameters[0], |window| *window = None),
+            // Reset
+            0x8F => self.each(0xFF, |window| *window = None),
+            // Pen location
+            0x92 => {
+                if let Some(window) = self.window() {
+                    window.row = (parameters[0] & 0x0F).min(window.rows - 1);
+                    window.column = (parameters[1] & 0x3F) as usize;
+                }
+            }
+            // Define window, which also makes it the current one
+            0x98..=0x9F => {
+                self.current = (code - 0x98) as usize;
+                let visible = parameters[0] & 0x20 != 0;
+                let (rows, columns) = ((parameters[3] & 0x0F) + 1, (parameters[4] & 0x3F) as usize + 1);
+                let window = self.windows[self.current].get_or_insert_with(|| Window {
+                    visible,
+                    rows,
+                    columns,
+                    text: Memory::new(),
+                    row: 0,
+                    column: 0,
+                });
+                self.changed |= window.visible != visible && !window.text.is_empty();
+                (window.visible, window.rows, window.columns) = (visible, rows, columns);
+                window.row = window.row.min(rows - 1);
+            }
+            // Delays, pen and window styles
+            _ => {}
+        }
+    }
+}
+
+pub struct Decoder {
+    // 0 or 1
+    field: u8,
+    codec: Option<Codec>,
+    demuxer: Demuxer,
+    clock: Clock,
+    time: u64,
+    // Caption data of frames in decode order, with their times
+    frames: Vec<(u64, Vec<[u8; 3]>)>,
+    screen: Screen,
+    // The CEA-708 service decoded instead of a 608 channel
+    service: Option<Service>,
+    shown: Option<(u64, String)>,
+}
+
+impl Decoder {
+    // A decoder of channel CC1 to CC4
+    pub fn new(channel: u8) -> Self {
+        let channel = channel.clamp(1, 4) - 1;
+        Decoder {
+            field: channel / 2,
+            codec: None,
+            demuxer: Demuxer::default(),
+            clock: Clock::default(),
+            time: 0,
+            frames: Vec::new(),
+            screen: Screen::new(channel % 2),
+            service: None,
+            shown: None,
+        }
+    }
+
+    // A decoder of CEA-708 service 1 to 63
+    pub fn service(number: u8) -> Self {
+        Decoder {
+            service: Some(Service::new(number.clamp(1, 63))),
+            ..Decoder::new(1)
+        }
+    }
+
+    // Decode the earliest frame held back
+    fn frame(&mut self, cues: &mut Vec<Cue>) {
+        let Some((index, _)) = self.frames.iter().enumerate().min_by_key(|(_, (time, _))| *time) else {
+            return;
+        };
+        let (time, pairs) = self.frames.remove(index);
+        for [kind, first, second] in pairs {
+            match &mut self.service {
+                Some(service) if kind >= 2 => service.data(kind == 3, [first, second]),
+                None if kind == self.field => self.screen.pair([first & 0x7F, second & 0x7F]),
+                _ => {}
+            }
+        }
+        let changed = match &mut self.service {
+            Some(service) => std::mem::take(&mut service.changed),
+            None => std::mem::take(&mut self.screen.changed),
+        };
+        if !changed {
+            return;
+        }
+        let text = self.service.as_ref().map_or_else(|| self.screen.text(), Service::text);
+        if self.shown.as_ref().is_some_and(|(_, shown)| *shown == text) {
+            return;
+        }
+        if let Some((start, shown)) = self.shown.take() {
+            if time > start {
+                cues.push(Cue {
+                    start,
+                    end: time,
+                    text: shown,
+                });
+            }
+        }
+        self.shown = (!text.is_empty()).then_some((time, text));
+    }
+}
+
+fn select(codec: &mut Option<Codec>, streams: &[ElementaryStream]) -> Option<u16> {
+    streams.iter().find_map(|stream| {
+        *codec = match stream.stream_type {
+            MPEG2_VIDEO => Some(Codec::Mpeg2),
+            H264_VIDEO => Some(Codec::H264),
+            HEVC_VIDEO => Some(Codec::Hevc),
+            _ => None,
+        };
+        codec.map(|_| stream.pid)
+    })
+}
+
+impl webvtt::Decoder for Decoder {
+    fn feed(&mut self, data: &[u8]) -> Vec<Cue> {
+        let codec = &mut self.codec;
+        let packets = self.demuxer.feed(data, &mut |streams| select(codec, streams));
+        let mut cues = Vec::new();
+        for packet in packets {
+            let (Some(pes), Some(codec)) = (ts::pes(&packet), self.codec) else {
+                continue;
+            };
+            if let Some(pts) = pes.pts {
+                self.time = self.clock.ms(pts);
+            }
+            let pairs = caption_pairs(codec, pes.payload);
+            if !pairs.is_empty() {
+                self.frames.push((self.time, pairs));
+            }
+            if self.frames.len() > REORDER_DEPTH {
+                self.frame(&mut cues);
+            }
+        }
+        cues
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::ts::tests::{pes_packet, tables};
+    use webvtt::Decoder as _;
+
+    // An H.264 access unit with an SEI carrying the caption data, with
+    // emulation prevention where the data has zeros
+    fn access_unit(data: &[[u8; 3]]) -> Vec<u8> {
+        let mut message = vec![0xB5, 0x00, 0x31, b'G', b'A', b'9', b'4', 0x03, 0x40 | data.len() as u8, 0xFF];
+        message.extend(data.iter().flatten());
+        message.push(0xFF);
+        let mut unit = vec![0, 0, 0, 1, 0x09, 0xF0, 0, 0, 1, 0x06, 0x04, message.len() as u8];
+        for byte in message {
+            if unit.ends_with(&[0, 0]) && byte <= 3 {
+                unit.push(0x03);
+            }
+            unit.push(byte);
+        }
+        unit.extend([0x80, 0, 0, 1, 0x65, 0x88]);
+        unit
+    }
+
+    // With odd parity, as sent
+    fn parity(byte: u8) -> u8 {
+        match byte.count_ones() % 2 {
+            0 => byte | 0x80,
+            _ => byte,
+        }
+    }
+
+    #[test]
+    fn test_decode_pop_on_captions() {
+        // Test that a pop-on caption of CC1 becomes a cue from end of caption to erase, in presentation order
+        let text = b"HELLO, W\x5CRLD";
+        let mut pairs: Vec<[u8; 2]> = vec![[0x14, 0x20], [0x14, 0x20], [0x14, 0x2E], [0x14, 0x2E]];
+        // Row 15, indent 4
+        pairs.extend([[0x14, 0x72], [0x14, 0x72]]);
+        pairs.extend(text.chunks(2).map(|chunk| [chunk[0], *chunk.get(1).unwrap_or(&0)]));
+        // CC2 text isn't shown
+        pairs.extend([[0x1C, 0x20], [0x1C, 0x20], [b'N', b'O']]);
+        pairs.extend([[0x14, 0x2F], [0x14, 0x2F], [0, 0], [0x14, 0x2C], [0x14, 0x2C]]);
+        pairs.extend(std::iter::repeat([0, 0]).take(REORDER_DEPTH));
+
+        // The frame with the last letters is sent after the next one, as a
+        // B-frame would be; shown first, the letters are still for CC1
+        let mut order: Vec<usize> = (0..pairs.len()).collect();
+        order.swap(11, 12);
+        let mut stream = tables(H264_VIDEO, 0x100, &[]);
+        for frame in order {
+            let payload = access_unit(&[[0xFC, parity(pairs[frame][0]), parity(pairs[frame][1])]]);
+            stream.extend(pes_packet(0x100, 0xE0, 900_000 + frame as u64 * 3003, &payload));
+        }
+
+        let mut decoder = Decoder::new(1);
+        let cues = decoder.feed(&stream);
+        // End of caption on frame 15, erase on 18
+        let cue = Cue {
+            start: 15 * 3003 / 90,
+            end: 18 * 3003 / 90,
+            text: "HELLO, WéRLD".to_string(),
+        };
+        assert_eq!(cues, vec![cue]);
+        assert_eq!(decoder.screen.loading, Memory::new());
+    }
+
+    // The caption data of a DTVCC packet of service blocks
+    fn dtvcc(blocks: &[(u8, &[u8])]) -> Vec<[u8; 3]> {
+        let mut packet = vec![0];
+        for (service, data) in blocks {
+            packet.push(service << 5 | data.len() as u8);
+            packet.extend(*data);
+        }
+        if packet.len() % 2 != 0 {
+            packet.push(0);
+        }
+        packet[0] = (packet.len() / 2) as u8;
+        let pairs = packet.chunks(2).enumerate();
+        pairs.map(|(index, pair)| [if index == 0 { 0xFF } else { 0xFE }, pair[0], pair[1]]).collect()
+    }
+
+    #[test]
+    fn test_decode_cea708_service() {
+        // Test that CEA-708 service 1 text shows from when its window is displayed until it's deleted
+        let mut text = vec![0x98, 0x00, 0x00, 0x00, 0x02, 0x1F, 0x00];
+        text.extend(b"HI ");
+        text.extend([0xE9, 0x0D]);
+        text.extend(b"THERE");
+        text.extend([0x10, 0x35, 0x89, 0x01]);
+        let mut frames = vec![dtvcc(&[(1, &text)])];
+        // The packet comes over two frames, and other services are left out
+        let rest = frames[0].split_off(3);
+        frames.push(rest);
+        frames.push(dtvcc(&[(2, b"\x98\x20\x00\x00\x02\x1FNO\x0D"), (1, b"\x80")]));
+        frames.extend(std::iter::repeat(vec![[0xFC, 0x80, 0x80]]).take(2));
+        frames.push(dtvcc(&[(1, &[0x8C, 0x01])]));
+        frames.extend(std::iter::repeat(vec![[0xFC, 0x80, 0x80]]).take(REORDER_DEPTH));
+
+        let mut stream = tables(H264_VIDEO, 0x100, &[]);
+        for (frame, data) in frames.iter().enumerate() {
+            stream.extend(pes_packet(0x100, 0xE0, 900_000 + frame as u64 * 3003, &access_unit(data)));
+        }
+        let mut decoder = Decoder::service(1);
+        let cue = Cue {
+            start: 3003 / 90,
+            end: 5 * 3003 / 90,
+            text: "HI é\nTHERE•".to_string(),
+        };
+        assert_eq!(decoder.feed(&stream), vec![cue]);
+    }
+}
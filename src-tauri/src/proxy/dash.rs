@@ -0,0 +1,151 @@
+// MPEG-DASH manifests fetched through the proxy. Players build segment URLs
+// from BaseURL elements and SegmentTemplate/SegmentURL attributes, often
+// templates ($Number$, $Time$) only the player can fill in, so each is
+// resolved against the manifest and split: the directory goes through the
+// proxy as a base the rest of the path is resolved against, and the file
+// part is left for the player to complete
+use quick_xml::events::{BytesStart, BytesText, Event};
+use quick_xml::{Reader, Writer};
+use url::Url;
+
+use crate::xml::append_text;
+
+// Attributes holding URLs relative to their element's base
+const URL_ATTRIBUTES: [&[u8]; 4] = [b"media", b"initialization", b"sourceURL", b"index"];
+
+// Proxied base URL, ending in "/", for an upstream directory
+pub type BaseLink<'a> = &'a dyn Fn(&str) -> String;
+
+pub fn is_manifest(content_type: Option<&str>, url: &Url) -> bool {
+    let content_type = content_type.unwrap_or_default().to_ascii_lowercase();
+    content_type.contains("dash+xml") || url.path().to_ascii_lowercase().ends_with(".mpd")
+}
+
+// A URL reference resolved against `base`, as a proxied directory and the
+// (possibly templated) rest
+fn relink(reference: &str, base: &Url, link: BaseLink) -> Option<String> {
+    let reference = reference.trim();
+    let template = reference.find(['$', '?']).unwrap_or(reference.len());
+    let split = reference[..template].rfind('/').map_or(0, |index| index + 1);
+    let (directory, rest) = reference.split_at(split);
+    let directory = base.join(if directory.is_empty() { "./" } else { directory }).ok()?;
+    matches!(directory.scheme(), "http" | "https").then(|| format!("{}{}", link(directory.as_str()), rest))
+}
+
+fn relink_attributes(start: &BytesStart, base: &Url, link: BaseLink) -> BytesStart<'static> {
+    let mut rewritten = BytesStart::new(String::from_utf8_lossy(start.name().as_ref()).into_owned());
+    for attribute in start.attributes().flatten() {
+        if URL_ATTRIBUTES.contains(&attribute.key.local_name().as_ref()) {
+            let linked = attribute
+                .unescape_value()
+                .ok()
+                .and_then(|value| relink(&value, base, link));
+            if let Some(linked) = linked {
+                let key = String::from_utf8_lossy(attribute.key.as_ref()).into_owned();
+                rewritten.push_attribute((key.as_str(), linked.as_str()));
+                continue;
+            }
+        }
+        rewritten.push_attribute(attribute);
+    }
+    rewritten
+}
+
+// Point the BaseURL and Location elements and the URL attributes of a
+// manifest at the proxy; `base` is the manifest's own URL
+pub fn rewrite(manifest: &str, base: &Url, link: BaseLink) -> Result<String, String> {
+    let mut reader = Reader::from_str(manifest);
+    let mut writer = Writer::new(Vec::with_capacity(manifest.len() * 2));
+    // The base of each open element
+    let mut bases = vec![base.clone()];
+    // Text of an open BaseURL (true) or Location (false) element
+    let mut url_element: Option<(bool, String)> = None;
+    loop {
+        let event = reader.read_event().map_err(|e| format!("Invalid DASH manifest: {}", e))?;
+        let event = match event {
+            Event::Eof => break,
+            Event::Start(start) if matches!(start.local_name().as_ref(), b"BaseURL" | b"Location") => {
+                url_element = Some((start.local_name().as_ref() == b"BaseURL", String::new()));
+                Event::Start(start)
+            }
+            Event::End(end) if url_element.is_some() => {
+                let (is_base, text) = url_element.take().unwrap_or_default();
+                let parent = bases.last_mut().expect("the manifest base is never popped");
+                let linked = relink(&text, parent, link).unwrap_or_else(|| text.clone());
+                if is_base {
+                    if let Ok(resolved) = parent.join(text.trim()) {
+                        *parent = resolved;
+                    }
+                }
+                writer
+                    .write_event(Event::Text(BytesText::new(&linked)))
+                    .map_err(|e| e.to_string())?;
+                Event::End(end)
+            }
+            event if url_element.is_some() => {
+                if let Some((_, text)) = &mut url_element {
+                    append_text(text, &event);
+                }
+                continue;
+            }
+            Event::Start(start) => {
+                let parent = bases.last().expect("the manifest base is never popped").clone();
+                let start = relink_attributes(&start, &parent, link);
+                bases.push(parent);
+                Event::Start(start)
+            }
+            Event::Empty(start) => {
+                let parent = bases.last().expect("the manifest base is never popped");
+                Event::Empty(relink_attributes(&start, parent, link))
+            }
+            Event::End(end) => {
+                if bases.len() > 1 {
+                    bases.pop();
+                }
+                Event::End(end)
+            }
+            event => event,
+        };
+        writer.write_event(event).map_err(|e| e.to_string())?;
+    }
+    String::from_utf8(writer.into_inner()).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rewrite_manifest() {
+        // Test that base URLs, templates and segment URLs resolve against their bases and go through the proxy
+        let manifest = r#"<?xml version="1.0"?>
+<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static">
+  <Location>https://host/vod/manifest.mpd?token=a&amp;b=1</Location>
+  <Period>
+    <BaseURL>https://cdn.example/vod/</BaseURL>
+    <AdaptationSet mimeType="video/mp4">
+      <SegmentTemplate initialization="video/init-$RepresentationID$.mp4" media="video/$Number%05d$.m4s?t=1"/>
+      <Representation id="720p" bandwidth="3000000"/>
+    </AdaptationSet>
+    <AdaptationSet mimeType="audio/mp4">
+      <Representation id="en">
+        <BaseURL>audio/en.mp4</BaseURL>
+        <SegmentBase indexRange="0-100"><Initialization sourceURL="../init.mp4"/></SegmentBase>
+      </Representation>
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+        let base = Url::parse("https://host/vod/manifest.mpd").unwrap();
+        let link = |directory: &str| format!("P[{}]/", directory);
+        let rewritten = rewrite(manifest, &base, &link).unwrap();
+        assert!(rewritten.contains("<Location>P[https://host/vod/]/manifest.mpd?token=a&amp;b=1</Location>"));
+        assert!(rewritten.contains("<BaseURL>P[https://cdn.example/vod/]/</BaseURL>"));
+        let initialization = r#"initialization="P[https://cdn.example/vod/video/]/init-$RepresentationID$.mp4""#;
+        assert!(rewritten.contains(initialization));
+        assert!(rewritten.contains(r#"media="P[https://cdn.example/vod/video/]/$Number%05d$.m4s?t=1""#));
+        assert!(rewritten.contains("<BaseURL>P[https://cdn.example/vod/audio/]/en.mp4</BaseURL>"));
+        assert!(rewritten.contains(r#"<Initialization sourceURL="P[https://cdn.example/vod/]/init.mp4"/>"#));
+        assert!(rewritten.contains(r#"<Representation id="720p" bandwidth="3000000"/>"#));
+        assert!(is_manifest(Some("application/dash+xml"), &Url::parse("https://host/live").unwrap()));
+    }
+}
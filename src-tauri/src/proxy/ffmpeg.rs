@@ -0,0 +1,352 @@
+// ffmpeg runs for streams the webview can't play as they are: MPEG-TS (and
+// HLS with TS segments) is remuxed into fragmented MP4 the <video> element
+// plays progressively, with video copied and audio converted to AAC since
+// broadcast MP2 and AC-3 audio rarely plays in browsers; streams in codecs
+// the platform can't decode are transcoded with the configured arguments.
+// RTSP (IP cameras, SAT>IP servers) and RTMP streams always go through
+// ffmpeg, which speaks both and serves them like any other channel. Text
+// subtitle tracks (SRT, ASS, teletext) are converted to WebVTT for the web
+// player; bitmap subtitles like DVB's would need OCR and are left out.
+// Screenshots and channel thumbnails are one frame of a stream written to
+// an image file.
+// Every ffmpeg started is tracked and killed once the player drops the
+// response, or when the app exits
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use reqwest::Body;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::{Child, Command};
+use tokio_util::io::ReaderStream;
+
+use crate::http::HttpOptions;
+
+// Codec arguments of a remux
+pub const REMUX_ARGUMENTS: [&str; 4] = ["-c:v", "copy", "-c:a", "aac"];
+
+const GLOBAL_ARGUMENTS: [&str; 4] = ["-hide_banner", "-loglevel", "error", "-nostdin"];
+
+// Long enough for a live stream to reach its next keyframe
+const SCREENSHOT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Teletext subtitle pages are decoded to text rather than bitmaps
+const SUBTITLE_DECODING: [&str; 2] = ["-txt_format", "text"];
+
+// librtmp's URL options ("rtmp://host/app/stream live=1 swfUrl=..."), as
+// old playlists write them, and ffmpeg's options for them
+const RTMP_OPTIONS: [(&str, &str); 9] = [
+    ("app", "-rtmp_app"),
+    ("playpath", "-rtmp_playpath"),
+    ("swfUrl", "-rtmp_swfurl"),
+    ("swfVfy", "-rtmp_swfverify"),
+    ("pageUrl", "-rtmp_pageurl"),
+    ("tcUrl", "-rtmp_tcurl"),
+    ("flashVer", "-rtmp_flashver"),
+    ("conn", "-rtmp_conn"),
+    ("live", "-rtmp_live"),
+];
+
+fn scheme(url: &str) -> String {
+    url.split_once("://").map_or("", |(scheme, _)| scheme).to_ascii_lowercase()
+}
+
+pub fn is_rtsp(url: &str) -> bool {
+    matches!(scheme(url).as_str(), "rtsp" | "rtsps")
+}
+
+pub fn is_rtmp(url: &str) -> bool {
+    matches!(scheme(url).as_str(), "rtmp" | "rtmps" | "rtmpe" | "rtmpt" | "rtmpte" | "rtmpts")
+}
+
+// An RTMP URL's options as ffmpeg arguments, then the URL without them
+fn rtmp_input(url: &str, http: &HttpOptions) -> Vec<String> {
+    let mut parts = url.split_whitespace();
+    let location = parts.next().unwrap_or_default().to_string();
+    let mut options: Vec<(&str, &str)> = Vec::new();
+    for (name, value) in parts.filter_map(|part| part.split_once('=')) {
+        match RTMP_OPTIONS.iter().find(|(known, _)| known.eq_ignore_ascii_case(name)) {
+            Some((_, option)) => options.push((option, value)),
+            None => log::debug!("Ignoring RTMP option {}", name),
+        }
+    }
+    let enabled = |value: &str| matches!(value, "1" | "true");
+    let swf = options.iter().find(|(option, _)| *option == "-rtmp_swfurl").map(|(_, value)| *value);
+    let mut arguments = Vec::new();
+    for (option, value) in &options {
+        let value = match *option {
+            "-rtmp_live" if enabled(value) => "live",
+            "-rtmp_live" => "recorded",
+            // librtmp takes swfVfy=1 with the URL in swfUrl, ffmpeg the URL
+            "-rtmp_swfverify" if value.contains("://") => value,
+            "-rtmp_swfverify" => match swf.filter(|_| enabled(value)) {
+                Some(swf) => swf,
+                None => continue,
+            },
+            _ => value,
+        };
+        arguments.extend([option.to_string(), value.to_string()]);
+    }
+    let page = options.iter().any(|(option, _)| *option == "-rtmp_pageurl");
+    if let Some(referrer) = http.referrer.as_ref().filter(|_| !page) {
+        arguments.extend(["-rtmp_pageurl".to_string(), referrer.clone()]);
+    }
+    arguments.extend(["-i".to_string(), location]);
+    arguments
+}
+
+fn arguments(url: &str, http: &HttpOptions, codecs: &[String]) -> Vec<String> {
+    let mut arguments: Vec<String> = GLOBAL_ARGUMENTS.map(str::to_string).to_vec();
+    arguments.extend(input(url, http));
+    arguments.extend(map_and_output(codecs));
+    arguments
+}
+
+// A stream's `track`th subtitle track as WebVTT
+fn subtitle_arguments(url: &str, http: &HttpOptions, track: usize) -> Vec<String> {
+    let mut arguments: Vec<String> =
+        GLOBAL_ARGUMENTS.iter().chain(&SUBTITLE_DECODING).map(|argument| argument.to_string()).collect();
+    arguments.extend(input(url, http));
+    arguments.extend(["-map".to_string(), format!("0:s:{}", track)]);
+    arguments.extend(["-c:s", "webvtt", "-f", "webvtt", "pipe:1"].map(str::to_string));
+    arguments
+}
+
+// The stream's first video frame as an image, its format by extension,
+// scaled down to `width` if given
+fn screenshot_arguments(url: &str, http: &HttpOptions, path: &Path, width: Option<u32>) -> Vec<String> {
+    let mut arguments: Vec<String> = GLOBAL_ARGUMENTS.map(str::to_string).to_vec();
+    arguments.extend(input(url, http));
+    arguments.extend(["-map", "0:v:0", "-frames:v", "1", "-update", "1"].map(str::to_string));
+    if let Some(width) = width {
+        arguments.extend(["-vf".to_string(), format!("scale={}:-2", width)]);
+    }
+    let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default();
+    if !extension.eq_ignore_ascii_case("png") {
+        arguments.extend(["-q:v", "2"].map(str::to_string));
+    }
+    arguments.extend(["-y".to_string(), path.to_string_lossy().into_owned()]);
+    arguments
+}
+
+// The input options for a stream's HTTP options, and the input; ffprobe
+// takes the same
+pub fn input(url: &str, http: &HttpOptions) -> Vec<String> {
+    if is_rtmp(url) {
+        // RTMP has no User-Agent or headers; the Referer is its page URL
+        return rtmp_input(url, http);
+    }
+    let mut arguments = Vec::new();
+    if let Some(user_agent) = &http.user_agent {
+        arguments.extend(["-user_agent".to_string(), user_agent.clone()]);
+    }
+    if is_rtsp(url) {
+        // Interleaved over the RTSP connection where the server allows it,
+        // which gets through NAT and firewalls; UDP otherwise. RTSP has no
+        // Referer or extra headers
+        arguments.extend(["-rtsp_flags", "prefer_tcp", "-i", url].map(str::to_string));
+        return arguments;
+    }
+    if let Some(referrer) = &http.referrer {
+        arguments.extend(["-referer".to_string(), referrer.clone()]);
+    }
+    if !http.headers.is_empty() {
+        let headers: String = http
+            .headers
+            .iter()
+            .map(|(name, value)| format!("{}: {}\r\n", name, value))
+            .collect();
+        arguments.extend(["-headers".to_string(), headers]);
+    }
+    // Dropped connections are made again, as the proxy does for streams
+    // it relays itself
+    arguments.extend(["-reconnect", "1", "-reconnect_streamed", "1", "-reconnect_delay_max", "8"].map(str::to_string));
+    arguments.extend(["-i".to_string(), url.to_string()]);
+    arguments
+}
+
+// The streams kept, the codecs and the fragmented MP4 output
+fn map_and_output(codecs: &[String]) -> Vec<String> {
+    let mut arguments: Vec<String> = ["-map", "0:v:0?", "-map", "0:a:0?"].map(str::to_string).to_vec();
+    arguments.extend(codecs.iter().cloned());
+    let output = ["-f", "mp4", "-movflags", "frag_keyframe+empty_moov+default_base_moof", "pipe:1"];
+    arguments.extend(output.map(str::to_string));
+    arguments
+}
+
+// The running ffmpeg processes
+#[derive(Default)]
+pub struct Processes {
+    children: Mutex<HashMap<u64, Child>>,
+    next_id: AtomicU64,
+}
+
+impl Processes {
+    pub fn kill_all(&self) {
+        for (_, mut child) in self.children.lock().unwrap().drain() {
+            let _ = child.start_kill();
+        }
+    }
+}
+
+// Kills its ffmpeg when dropped along with the response stream
+struct Running {
+    processes: Arc<Processes>,
+    id: u64,
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        if let Some(mut child) = self.processes.children.lock().unwrap().remove(&self.id) {
+            let _ = child.start_kill();
+        }
+    }
+}
+
+// Start ffmpeg on a stream, with `codecs` between the input and the MP4
+// output, and stream out what it writes
+pub fn run(
+    processes: &Arc<Processes>,
+    program: &str,
+    url: &str,
+    http: &HttpOptions,
+    codecs: &[String],
+) -> Result<Body, String> {
+    spawn(processes, program, arguments(url, http, codecs))
+}
+
+// Start ffmpeg converting a stream's `track`th subtitle track to WebVTT,
+// and stream out the cues as they come
+pub fn webvtt(
+    processes: &Arc<Processes>,
+    program: &str,
+    url: &str,
+    http: &HttpOptions,
+    track: usize,
+) -> Result<Body, String> {
+    spawn(processes, program, subtitle_arguments(url, http, track))
+}
+
+// Run ffmpeg writing a frame of a stream to `path`
+pub async fn screenshot(
+    program: &str,
+    url: &str,
+    http: &HttpOptions,
+    path: &Path,
+    width: Option<u32>,
+) -> Result<(), String> {
+    let output = Command::new(program)
+        .args(screenshot_arguments(url, http, path, width))
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::time::timeout(SCREENSHOT_TIMEOUT, output)
+        .await
+        .map_err(|_| format!("{} timed out", program))?
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(format!("Failed to capture a frame: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(())
+}
+
+fn spawn(processes: &Arc<Processes>, program: &str, arguments: Vec<String>) -> Result<Body, String> {
+    let mut child = Command::new(program)
+        .args(arguments)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Failed to start {}: {}", program, e))?;
+    let stdout = child.stdout.take().ok_or("ffmpeg has no output")?;
+    if let Some(stderr) = child.stderr.take() {
+        tauri::async_runtime::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                log::warn!("ffmpeg: {}", line);
+            }
+        });
+    }
+    let id = processes.next_id.fetch_add(1, Ordering::Relaxed);
+    processes.children.lock().unwrap().insert(id, child);
+    let running = Running {
+        processes: processes.clone(),
+        id,
+    };
+    let stream = ReaderStream::new(stdout).map(move |chunk| {
+        // The stream owns the process
+        let _ = &running;
+        chunk
+    });
+    Ok(Body::wrap_stream(stream))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ffmpeg_arguments() {
+        // Test that the stream's HTTP options go ahead of the input and the codecs ahead of the output
+        let mut http = HttpOptions::default();
+        http.set_header("User-Agent", "VLC/3.0");
+        http.set_header("Cookie", "a=1");
+        http.set_header("Origin", "http://portal");
+        let codecs = REMUX_ARGUMENTS.map(str::to_string);
+        let arguments = arguments("http://host/live/1.ts", &http, &codecs);
+        let input = arguments.iter().position(|argument| argument == "-i").unwrap();
+        assert_eq!(arguments[input + 1], "http://host/live/1.ts");
+        let user_agent = arguments.iter().position(|argument| argument == "-user_agent").unwrap();
+        assert!(user_agent < input);
+        assert_eq!(arguments[user_agent + 1], "VLC/3.0");
+        let headers = arguments.iter().position(|argument| argument == "-headers").unwrap();
+        assert_eq!(arguments[headers + 1], "Cookie: a=1\r\nOrigin: http://portal\r\n");
+        let codec = arguments.iter().position(|argument| argument == "-c:v").unwrap();
+        assert!(codec > input && arguments[codec + 1] == "copy");
+        assert_eq!(arguments.last().map(String::as_str), Some("pipe:1"));
+
+        let rtsp = super::arguments("rtsp://camera:554/stream1", &http, &codecs);
+        assert!(is_rtsp("RTSP://camera/") && !is_rtsp("http://host/rtsp"));
+        assert!(rtsp.contains(&"prefer_tcp".to_string()));
+        assert!(rtsp.contains(&"-user_agent".to_string()) && !rtsp.contains(&"-headers".to_string()));
+        assert_eq!(rtsp.last().map(String::as_str), Some("pipe:1"));
+
+        http.referrer = Some("http://portal/".to_string());
+        let url = "rtmp://host/live/stream live=1 swfUrl=http://host/player.swf swfVfy=1 foo=bar";
+        let rtmp = super::arguments(url, &http, &codecs);
+        let input = rtmp.iter().position(|argument| argument == "-i").unwrap();
+        assert_eq!(rtmp[input + 1], "rtmp://host/live/stream");
+        assert_eq!(
+            rtmp[4..input],
+            [
+                "-rtmp_live",
+                "live",
+                "-rtmp_swfurl",
+                "http://host/player.swf",
+                "-rtmp_swfverify",
+                "http://host/player.swf",
+                "-rtmp_pageurl",
+                "http://portal/"
+            ]
+        );
+        assert!(is_rtmp("RTMPE://host/app") && !rtmp.contains(&"-user_agent".to_string()));
+
+        let subtitles = subtitle_arguments("http://host/movie.mkv", &HttpOptions::default(), 1);
+        let input = subtitles.iter().position(|argument| argument == "-i").unwrap();
+        assert!(subtitles[..input].contains(&"-txt_format".to_string()));
+        let output = ["http://host/movie.mkv", "-map", "0:s:1", "-c:s", "webvtt", "-f", "webvtt", "pipe:1"];
+        assert_eq!(subtitles[input + 1..], output);
+
+        let screenshot = screenshot_arguments("http://host/live/1.ts", &http, Path::new("/tmp/shot.jpg"), Some(320));
+        let input = screenshot.iter().position(|argument| argument == "-i").unwrap();
+        let output = ["-frames:v", "1", "-update", "1", "-vf", "scale=320:-2", "-q:v", "2", "-y", "/tmp/shot.jpg"];
+        assert_eq!(screenshot[input + 4..], output);
+        let png = screenshot_arguments("http://host/", &http, Path::new("shot.PNG"), None);
+        assert!(!png.contains(&"-q:v".to_string()) && !png.contains(&"-vf".to_string()));
+    }
+}
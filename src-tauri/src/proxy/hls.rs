@@ -0,0 +1,259 @@
+// HLS manifests fetched through the proxy: every URI in them (variant and
+// rendition playlists, segments, keys, init sections) is pointed back at
+// the proxy, so each request carries the channel's headers. Cookies the
+// manifest response sets are added to them, since providers often hand
+// out session or CDN tokens that way and check them on every segment
+use reqwest::header::{HeaderMap, SET_COOKIE};
+use reqwest::Response;
+use url::Url;
+
+use super::keys::SegmentKey;
+use crate::http::HttpOptions;
+
+// Manifests are read whole to rewrite their URIs
+const MAX_PLAYLIST_BYTES: usize = 10 * 1024 * 1024;
+
+pub fn is_playlist(content_type: Option<&str>, url: &Url) -> bool {
+    let content_type = content_type.unwrap_or_default().to_ascii_lowercase();
+    let path = url.path().to_ascii_lowercase();
+    content_type.contains("mpegurl") || path.ends_with(".m3u8") || path.ends_with(".m3u")
+}
+
+// Read a whole HLS or DASH manifest
+pub async fn read(response: &mut Response) -> Result<String, String> {
+    let mut playlist = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| format!("Failed to read the manifest: {}", e))?
+    {
+        playlist.extend_from_slice(&chunk);
+        if playlist.len() > MAX_PLAYLIST_BYTES {
+            return Err(format!("Manifest is larger than {} bytes", MAX_PLAYLIST_BYTES));
+        }
+    }
+    Ok(String::from_utf8_lossy(&playlist).into_owned())
+}
+
+// Add the cookies set by a response to the Cookie header, replacing ones
+// of the same name
+pub fn add_cookies(http: &mut HttpOptions, headers: &HeaderMap) {
+    let set: Vec<(&str, &str)> = headers
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .filter_map(|value| value.split(';').next()?.split_once('='))
+        .map(|(name, value)| (name.trim(), value.trim()))
+        .filter(|(name, _)| !name.is_empty())
+        .collect();
+    if set.is_empty() {
+        return;
+    }
+    let key = http.headers.keys().find(|name| name.eq_ignore_ascii_case("cookie")).cloned();
+    let current = key.and_then(|key| http.headers.remove(&key)).unwrap_or_default();
+    let mut cookies: Vec<(String, String)> = current
+        .split(';')
+        .filter_map(|cookie| cookie.split_once('='))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+    for (name, value) in set {
+        match cookies.iter_mut().find(|(existing, _)| existing == name) {
+            Some(cookie) => cookie.1 = value.to_string(),
+            None => cookies.push((name.to_string(), value.to_string())),
+        }
+    }
+    let header: Vec<String> = cookies.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+    http.set_header("Cookie", &header.join("; "));
+}
+
+// A playlist URL with the LL-HLS delivery directives (_HLS_msn, _HLS_part,
+// _HLS_skip) of the player's reload, so the origin holds the request until
+// the part asked for exists instead of the player polling a stale playlist
+pub fn with_directives(url: &str, query: Option<&str>) -> String {
+    let directives: Vec<(String, String)> = url::form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+        .filter(|(name, _)| name.starts_with("_HLS_"))
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+    let Some(mut url) = Url::parse(url).ok().filter(|_| !directives.is_empty()) else {
+        return url.to_string();
+    };
+    let kept: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(name, _)| !name.starts_with("_HLS_"))
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+    url.query_pairs_mut().clear().extend_pairs(kept).extend_pairs(directives);
+    url.to_string()
+}
+
+// LL-HLS partial segments, which can't be decrypted on their own: they're
+// dropped from encrypted playlists, leaving the player whole segments
+fn is_part_tag(tag: &str) -> bool {
+    tag.starts_with("#EXT-X-PART:")
+        || tag.starts_with("#EXT-X-PART-INF:")
+        || tag.starts_with("#EXT-X-PRELOAD-HINT:") && attribute(tag, "TYPE") == Some("PART")
+}
+
+// Proxy URL for a manifest URI and the key to decrypt what it points at
+pub type Link<'a> = &'a dyn Fn(&str, Option<SegmentKey>) -> String;
+
+fn resolve(uri: &str, base: &Url) -> Option<Url> {
+    let url = base.join(uri.trim()).ok()?;
+    matches!(url.scheme(), "http" | "https").then_some(url)
+}
+
+// http(s) URI resolved against the manifest's URL, then through the proxy
+fn relink(uri: &str, base: &Url, link: Link, key: Option<SegmentKey>) -> Option<String> {
+    resolve(uri, base).map(|url| link(url.as_str(), key))
+}
+
+// Value of an attribute of a tag, unquoted
+pub fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let (_, mut rest) = tag.split_once(':')?;
+    while !rest.is_empty() {
+        let (key, value) = rest.split_once('=')?;
+        let (value, next) = match value.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"')?;
+                (&quoted[..end], quoted[end + 1..].trim_start_matches(','))
+            }
+            None => value.split_once(',').unwrap_or((value, "")),
+        };
+        if key.trim().eq_ignore_ascii_case(name) {
+            return Some(value.trim());
+        }
+        rest = next;
+    }
+    None
+}
+
+// An AES-128 key tag's key URL and IV; None for other methods, which are
+// left to the player
+fn aes_key(tag: &str, base: &Url) -> Option<(String, Option<String>)> {
+    let identity = attribute(tag, "KEYFORMAT").map_or(true, |format| format == "identity");
+    if attribute(tag, "METHOD") != Some("AES-128") || !identity {
+        return None;
+    }
+    let url = resolve(attribute(tag, "URI")?, base)?;
+    Some((url.to_string(), attribute(tag, "IV").map(str::to_string)))
+}
+
+// Rewrite the URI lines and URI="..." attributes of a manifest; other
+// schemes (skd://, data:) are left alone. With `decrypt`, AES-128 key tags
+// are dropped and each segment is linked with its key instead. Partial
+// segments, preload hints and rendition reports are relinked like the rest
+pub fn rewrite(playlist: &str, base: &Url, decrypt: bool, link: Link) -> String {
+    let encrypted = decrypt
+        && playlist
+            .lines()
+            .any(|line| line.trim().starts_with("#EXT-X-KEY:") && aes_key(line.trim(), base).is_some());
+    let mut rewritten = String::with_capacity(playlist.len() * 2);
+    let mut sequence: u64 = 0;
+    let mut key: Option<(String, Option<String>)> = None;
+    for line in playlist.lines() {
+        let trimmed = line.trim();
+        if let Some(first) = trimmed.strip_prefix("#EXT-X-MEDIA-SEQUENCE:") {
+            sequence = first.trim().parse().unwrap_or(0);
+        }
+        if decrypt && trimmed.starts_with("#EXT-X-KEY:") {
+            key = aes_key(trimmed, base);
+            if key.is_some() || attribute(trimmed, "METHOD") == Some("NONE") {
+                continue;
+            }
+        }
+        if encrypted && is_part_tag(trimmed) {
+            continue;
+        }
+        if trimmed.starts_with('#') {
+            let mut rest = line;
+            while let Some(index) = rest.find("URI=\"") {
+                let (head, tail) = rest.split_at(index + 5);
+                rewritten.push_str(head);
+                let Some(end) = tail.find('"') else {
+                    rest = tail;
+                    break;
+                };
+                let uri = &tail[..end];
+                rewritten.push_str(&relink(uri, base, link, None).unwrap_or_else(|| uri.to_string()));
+                rest = &tail[end..];
+            }
+            rewritten.push_str(rest);
+        } else if !trimmed.is_empty() {
+            let segment_key = key
+                .as_ref()
+                .and_then(|(url, iv)| SegmentKey::new(url, iv.as_deref(), sequence));
+            rewritten.push_str(&relink(trimmed, base, link, segment_key).unwrap_or_else(|| line.to_string()));
+            sequence += 1;
+        }
+        rewritten.push('\n');
+    }
+    rewritten
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_rewrite_playlist() {
+        // Test that segment and attribute URIs resolve against the manifest and go through the proxy
+        let playlist = "#EXTM3U\n#EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\",IV=0x1\n\
+            #EXT-X-MAP:URI=\"skd://drm\"\n#EXTINF:6,\nseg1.ts\n\n#EXTINF:6,\nhttps://cdn/seg2.ts\n";
+        let base = Url::parse("http://host/live/index.m3u8").unwrap();
+        let link = |url: &str, key: Option<SegmentKey>| match key {
+            Some(key) => format!("P[{} {} {}]", url, key.url, key.iv.trim_start_matches('0')),
+            None => format!("P[{}]", url),
+        };
+        assert_eq!(
+            rewrite(playlist, &base, false, &link),
+            "#EXTM3U\n#EXT-X-KEY:METHOD=AES-128,URI=\"P[http://host/live/key.bin]\",IV=0x1\n\
+            #EXT-X-MAP:URI=\"skd://drm\"\n#EXTINF:6,\nP[http://host/live/seg1.ts]\n\n\
+            #EXTINF:6,\nP[https://cdn/seg2.ts]\n"
+        );
+        assert_eq!(
+            rewrite(&format!("#EXT-X-MEDIA-SEQUENCE:7\n{}", playlist), &base, true, &link),
+            "#EXT-X-MEDIA-SEQUENCE:7\n#EXTM3U\n#EXT-X-MAP:URI=\"skd://drm\"\n#EXTINF:6,\n\
+            P[http://host/live/seg1.ts http://host/live/key.bin 1]\n\n#EXTINF:6,\n\
+            P[https://cdn/seg2.ts http://host/live/key.bin 1]\n"
+        );
+        assert!(is_playlist(Some("application/vnd.apple.mpegurl"), &Url::parse("http://host/live").unwrap()));
+        assert!(!is_playlist(Some("video/mp2t"), &Url::parse("http://host/seg1.ts").unwrap()));
+
+        let mut http = HttpOptions::default();
+        http.set_header("cookie", "a=1; token=old");
+        let mut headers = HeaderMap::new();
+        headers.append(SET_COOKIE, HeaderValue::from_static("token=new; Path=/; HttpOnly"));
+        headers.append(SET_COOKIE, HeaderValue::from_static("cdn=x"));
+        add_cookies(&mut http, &headers);
+        assert_eq!(http.headers.len(), 1);
+        assert_eq!(http.headers["Cookie"], "a=1; token=new; cdn=x");
+    }
+
+    #[test]
+    fn test_low_latency_playlist() {
+        // Test that reload directives reach the origin and parts are relinked, or dropped when decrypting
+        assert_eq!(
+            with_directives("http://host/low.m3u8?token=a&_HLS_msn=1", Some("_HLS_msn=42&_HLS_part=3&cb=9")),
+            "http://host/low.m3u8?token=a&_HLS_msn=42&_HLS_part=3"
+        );
+        assert_eq!(with_directives("http://host/low.m3u8?token=a", Some("cb=9")), "http://host/low.m3u8?token=a");
+
+        let playlist = "#EXTM3U\n#EXT-X-PART-INF:PART-TARGET=1.0\n#EXTINF:4,\nseg1.ts\n\
+            #EXT-X-PART:DURATION=1.0,URI=\"seg2.0.ts\"\n\
+            #EXT-X-PRELOAD-HINT:TYPE=PART,URI=\"seg2.1.ts\"\n\
+            #EXT-X-RENDITION-REPORT:URI=\"../hd/low.m3u8\",LAST-MSN=2\n";
+        let base = Url::parse("http://host/sd/low.m3u8").unwrap();
+        let link = |url: &str, _: Option<SegmentKey>| format!("P[{}]", url);
+        let rewritten = rewrite(playlist, &base, true, &link);
+        assert!(rewritten.contains("URI=\"P[http://host/sd/seg2.0.ts]\""));
+        assert!(rewritten.contains("TYPE=PART,URI=\"P[http://host/sd/seg2.1.ts]\""));
+        assert!(rewritten.contains("URI=\"P[http://host/hd/low.m3u8]\""));
+
+        let encrypted = format!("#EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\"\n{}", playlist);
+        let rewritten = rewrite(&encrypted, &base, true, &link);
+        assert!(!rewritten.contains("#EXT-X-PART"));
+        assert!(!rewritten.contains("PRELOAD-HINT"));
+        assert!(rewritten.contains("#EXT-X-RENDITION-REPORT"));
+    }
+}
@@ -0,0 +1,196 @@
+// Which hardware encoders the configured ffmpeg can use for transcodes.
+// An encoder listed by `ffmpeg -encoders` is only built in, not backed by a
+// GPU or driver, so each candidate is confirmed by encoding a frame with
+// the codec arguments a transcode would use
+use std::process::Stdio;
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::State;
+use tokio::process::Command;
+
+use super::transcode::Transcoding;
+
+// How long one ffmpeg probe may take
+const PROBE_TIMEOUT: Duration = Duration::from_secs(15);
+
+const TEST_INPUT: [&str; 4] = ["-f", "lavfi", "-i", "color=black:s=320x240:d=0.2"];
+
+const AUDIO_ARGUMENTS: [&str; 4] = ["-c:a", "aac", "-b:a", "160k"];
+
+struct Accelerator {
+    name: &'static str,
+    encoder: &'static str,
+    // Video arguments of a transcode
+    arguments: &'static [&'static str],
+}
+
+// Most preferred first
+const ACCELERATORS: [Accelerator; 5] = [
+    Accelerator {
+        name: "nvenc",
+        encoder: "h264_nvenc",
+        arguments: &["-c:v", "h264_nvenc", "-preset", "p4"],
+    },
+    Accelerator {
+        name: "qsv",
+        encoder: "h264_qsv",
+        arguments: &["-c:v", "h264_qsv", "-preset", "veryfast"],
+    },
+    Accelerator {
+        name: "videotoolbox",
+        encoder: "h264_videotoolbox",
+        arguments: &["-c:v", "h264_videotoolbox", "-b:v", "6M"],
+    },
+    Accelerator {
+        name: "amf",
+        encoder: "h264_amf",
+        arguments: &["-c:v", "h264_amf", "-quality", "speed"],
+    },
+    Accelerator {
+        name: "vaapi",
+        encoder: "h264_vaapi",
+        arguments: &[
+            "-vaapi_device",
+            "/dev/dri/renderD128",
+            "-vf",
+            "format=nv12,hwupload",
+            "-c:v",
+            "h264_vaapi",
+        ],
+    },
+];
+
+pub fn is_accelerator(name: &str) -> bool {
+    ACCELERATORS.iter().any(|accelerator| accelerator.name == name)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HwAccel {
+    pub name: String,
+    // The accelerator's encoders built into ffmpeg
+    pub encoders: Vec<String>,
+    // Whether encoding with it works on this machine
+    pub usable: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HwAccelInfo {
+    pub ffmpeg: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    pub accelerators: Vec<HwAccel>,
+    // Transcode arguments for the most preferred usable accelerator
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recommended: Option<Vec<String>>,
+}
+
+// "ffmpeg version 6.1.1-3ubuntu5 Copyright ..." -> "6.1.1-3ubuntu5"
+fn version(output: &str) -> Option<String> {
+    let line = output.lines().next()?;
+    let rest = line.split_once(" version ")?.1;
+    rest.split_whitespace().next().map(str::to_string)
+}
+
+// Names of the video encoders in `ffmpeg -encoders` output
+fn video_encoders(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (flags, name) = (fields.next()?, fields.next()?);
+            (flags.len() == 6 && flags.starts_with('V') && name != "=").then(|| name.to_string())
+        })
+        .collect()
+}
+
+fn arguments(accelerator: &Accelerator) -> Vec<String> {
+    accelerator
+        .arguments
+        .iter()
+        .chain(&AUDIO_ARGUMENTS)
+        .map(|argument| argument.to_string())
+        .collect()
+}
+
+// Run ffmpeg with `arguments` to completion; its stdout if it succeeded
+async fn probe(ffmpeg: &str, arguments: &[String]) -> Result<String, String> {
+    let output = Command::new(ffmpeg)
+        .args(["-hide_banner", "-nostdin"])
+        .args(arguments)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::time::timeout(PROBE_TIMEOUT, output)
+        .await
+        .map_err(|_| format!("{} timed out", ffmpeg))?
+        .map_err(|e| format!("Failed to run {}: {}", ffmpeg, e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// Command handler reporting the hardware encoders the transcode settings'
+// ffmpeg is built with and whether they work here, with the transcode
+// arguments for the best of them
+#[tauri::command]
+pub async fn get_hw_accel_info(transcoding: State<'_, Transcoding>) -> Result<HwAccelInfo, String> {
+    let ffmpeg = transcoding.get().ffmpeg;
+    let banner = probe(&ffmpeg, &["-version".to_string()]).await?;
+    let encoders = video_encoders(&probe(&ffmpeg, &["-encoders".to_string()]).await?);
+    let mut info = HwAccelInfo {
+        version: version(&banner),
+        ffmpeg,
+        accelerators: Vec::new(),
+        recommended: None,
+    };
+    for accelerator in &ACCELERATORS {
+        let suffix = format!("_{}", accelerator.name);
+        let built: Vec<String> = encoders.iter().filter(|name| name.ends_with(&suffix)).cloned().collect();
+        let mut usable = false;
+        if built.iter().any(|name| name == accelerator.encoder) {
+            let mut test: Vec<String> = ["-loglevel", "error"]
+                .iter()
+                .chain(&TEST_INPUT)
+                .map(|argument| argument.to_string())
+                .collect();
+            test.extend(arguments(accelerator));
+            test.extend(["-frames:v", "1", "-f", "null", "-"].map(str::to_string));
+            match probe(&info.ffmpeg, &test).await {
+                Ok(_) => usable = true,
+                Err(e) => log::debug!("{} isn't usable: {}", accelerator.encoder, e),
+            }
+        }
+        if usable && info.recommended.is_none() {
+            info.recommended = Some(arguments(accelerator));
+        }
+        info.accelerators.push(HwAccel {
+            name: accelerator.name.to_string(),
+            encoders: built,
+            usable,
+        });
+    }
+    Ok(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ffmpeg_output() {
+        // Test that the version and the video encoders are read from ffmpeg's output
+        let banner = "ffmpeg version 6.1.1-3ubuntu5 Copyright (c) 2000-2023 the FFmpeg developers\nbuilt with gcc";
+        assert_eq!(version(banner).as_deref(), Some("6.1.1-3ubuntu5"));
+        let encoders = "Encoders:\n V..... = Video\n A..... = Audio\n ------\n\
+            V....D libx264              libx264 H.264 / AVC (codec h264)\n\
+            V....D h264_nvenc           NVIDIA NVENC H.264 encoder (codec h264)\n\
+            A....D aac                  AAC (Advanced Audio Coding)\n\
+            V..... h264_vaapi           H.264/AVC (VAAPI) (codec h264)\n";
+        assert_eq!(video_encoders(encoders), vec!["libx264", "h264_nvenc", "h264_vaapi"]);
+        assert_eq!(arguments(&ACCELERATORS[0]), ["-c:v", "h264_nvenc", "-preset", "p4", "-c:a", "aac", "-b:a", "160k"]);
+    }
+}
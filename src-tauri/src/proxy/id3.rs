@@ -0,0 +1,306 @@
+// Timed metadata of HLS streams: ID3 tags at the start of packed audio
+// segments (AAC, MP3) and in the ID3 PES stream of TS segments. Radio
+// streams announce the song playing that way, others mark programmes.
+// Segments are read for tags as they pass through the proxy and the app
+// hears "stream-metadata" whenever a stream's text frames change; the
+// tags come with the segment download, a little ahead of playback
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use futures_util::{Stream, StreamExt};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use super::ts::{self, Demuxer, ElementaryStream};
+use super::StreamProxy;
+
+const HEADER_SIZE: usize = 10;
+
+// Tags bigger than this (cover art) aren't read in full
+const MAX_TAG_SIZE: usize = 64 * 1024;
+
+// Metadata in PES, the stream type of ID3 in transport streams
+const METADATA_STREAM: u8 = 0x15;
+
+// Streams remembered before the oldest is dropped
+const MAX_STREAMS: usize = 64;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimedMetadata {
+    pub stream_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    // Every text frame by id, user defined ones as "TXXX:description"
+    pub frames: BTreeMap<String, String>,
+}
+
+fn syncsafe(bytes: &[u8]) -> usize {
+    bytes.iter().fold(0, |size, byte| size << 7 | (byte & 0x7F) as usize)
+}
+
+fn utf16(bytes: &[u8], big_endian: bool) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| match big_endian {
+            true => u16::from_be_bytes([pair[0], pair[1]]),
+            false => u16::from_le_bytes([pair[0], pair[1]]),
+        })
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+// The strings of a text frame, by its encoding byte; several are joined
+fn text(encoding: u8, bytes: &[u8]) -> Vec<String> {
+    let strings: Vec<String> = match encoding {
+        0 => bytes.split(|byte| *byte == 0).map(|string| string.iter().map(|byte| *byte as char).collect()).collect(),
+        1 | 2 => {
+            let units: Vec<&[u8]> = bytes.chunks(2).collect();
+            let strings = units.split(|unit| *unit == [0, 0]);
+            strings
+                .map(|string| {
+                    let bytes = string.concat();
+                    match bytes.get(..2) {
+                        Some([0xFF, 0xFE]) => utf16(&bytes[2..], false),
+                        Some([0xFE, 0xFF]) => utf16(&bytes[2..], true),
+                        _ => utf16(&bytes, encoding == 2),
+                    }
+                })
+                .collect()
+        }
+        _ => bytes.split(|byte| *byte == 0).map(|string| String::from_utf8_lossy(string).into_owned()).collect(),
+    };
+    strings.into_iter().map(|string| string.trim().to_string()).filter(|string| !string.is_empty()).collect()
+}
+
+// The text frames of an ID3v2.3 or v2.4 tag
+fn parse(tag: &[u8]) -> Option<BTreeMap<String, String>> {
+    if tag.len() < HEADER_SIZE || !tag.starts_with(b"ID3") {
+        return None;
+    }
+    let (version, flags) = (tag[3], tag[5]);
+    if !(3..=4).contains(&version) {
+        return None;
+    }
+    let end = (HEADER_SIZE + syncsafe(&tag[6..10])).min(tag.len());
+    let mut offset = HEADER_SIZE;
+    if flags & 0x40 != 0 {
+        let size = tag.get(offset..offset + 4)?;
+        offset += match version {
+            3 => 4 + u32::from_be_bytes([size[0], size[1], size[2], size[3]]) as usize,
+            _ => syncsafe(size),
+        };
+    }
+    let mut frames = BTreeMap::new();
+    while offset + HEADER_SIZE <= end && tag[offset] != 0 {
+        let id = String::from_utf8_lossy(&tag[offset..offset + 4]).into_owned();
+        let size = &tag[offset + 4..offset + 8];
+        let size = match version {
+            3 => u32::from_be_bytes([size[0], size[1], size[2], size[3]]) as usize,
+            _ => syncsafe(size),
+        };
+        let Some(body) = tag.get(offset + HEADER_SIZE..offset + HEADER_SIZE + size).filter(|body| !body.is_empty())
+        else {
+            break;
+        };
+        offset += HEADER_SIZE + size;
+        if !id.starts_with('T') {
+            continue;
+        }
+        let mut strings = text(body[0], &body[1..]);
+        if id == "TXXX" && !strings.is_empty() {
+            let description = strings.remove(0);
+            frames.insert(format!("TXXX:{}", description), strings.join(" / "));
+        } else if !strings.is_empty() {
+            frames.insert(id, strings.join(" / "));
+        }
+    }
+    Some(frames)
+}
+
+// The latest metadata of each stream
+#[derive(Default)]
+pub struct Metadata {
+    streams: Mutex<HashMap<String, (TimedMetadata, Instant)>>,
+}
+
+impl Metadata {
+    // Keep a stream's metadata; false when it's what the stream had
+    fn update(&self, metadata: &TimedMetadata) -> bool {
+        let mut streams = self.streams.lock().unwrap();
+        if let Some((known, used)) = streams.get_mut(&metadata.stream_id) {
+            *used = Instant::now();
+            if known == metadata {
+                return false;
+            }
+        }
+        if streams.len() >= MAX_STREAMS && !streams.contains_key(&metadata.stream_id) {
+            let oldest = streams.iter().min_by_key(|(_, (_, used))| *used).map(|(id, _)| id.clone());
+            if let Some(oldest) = oldest {
+                streams.remove(&oldest);
+            }
+        }
+        streams.insert(metadata.stream_id.clone(), (metadata.clone(), Instant::now()));
+        true
+    }
+
+    pub fn get(&self, id: &str) -> Option<TimedMetadata> {
+        self.streams.lock().unwrap().get(id).map(|(metadata, _)| metadata.clone())
+    }
+}
+
+enum Format {
+    // Not known until the first bytes
+    Unknown,
+    PackedAudio,
+    TransportStream(Box<Demuxer>),
+    None,
+}
+
+// Reads one segment of a stream for tags
+pub struct Scanner {
+    app: AppHandle,
+    id: String,
+    format: Format,
+    // The tag at the start of packed audio, as it comes
+    tag: Vec<u8>,
+}
+
+impl Scanner {
+    pub fn new(app: &AppHandle, id: &str) -> Self {
+        Scanner {
+            app: app.clone(),
+            id: id.to_string(),
+            format: Format::Unknown,
+            tag: Vec::new(),
+        }
+    }
+
+    pub fn feed(&mut self, data: &[u8]) {
+        for frames in self.frames(data) {
+            let metadata = TimedMetadata {
+                stream_id: self.id.clone(),
+                title: frames.get("TIT2").cloned(),
+                artist: frames.get("TPE1").cloned(),
+                frames,
+            };
+            if self.app.state::<StreamProxy>().metadata.update(&metadata) {
+                let _ = self.app.emit("stream-metadata", metadata);
+            }
+        }
+    }
+
+    // The text frames of the tags the next bytes complete
+    fn frames(&mut self, data: &[u8]) -> Vec<BTreeMap<String, String>> {
+        if let Format::Unknown = self.format {
+            self.tag.extend_from_slice(data);
+            if self.tag.len() < 3 {
+                return Vec::new();
+            }
+            self.format = if self.tag.starts_with(b"ID3") {
+                Format::PackedAudio
+            } else if self.tag[0] == 0x47 {
+                Format::TransportStream(Box::default())
+            } else {
+                Format::None
+            };
+            let data = std::mem::take(&mut self.tag);
+            return self.frames(&data);
+        }
+        match &mut self.format {
+            Format::PackedAudio => {
+                self.tag.extend_from_slice(data);
+                let Some(size) = self.tag.get(6..HEADER_SIZE).map(|size| HEADER_SIZE + syncsafe(size)) else {
+                    return Vec::new();
+                };
+                if self.tag.len() < size.min(MAX_TAG_SIZE) {
+                    return Vec::new();
+                }
+                self.format = Format::None;
+                parse(&self.tag).into_iter().filter(|frames| !frames.is_empty()).collect()
+            }
+            Format::TransportStream(demuxer) => {
+                let mut select = |streams: &[ElementaryStream]| {
+                    streams.iter().find(|stream| stream.stream_type == METADATA_STREAM).map(|stream| stream.pid)
+                };
+                demuxer
+                    .feed(data, &mut select)
+                    .iter()
+                    .filter_map(|packet| parse(ts::pes(packet)?.payload))
+                    .filter(|frames| !frames.is_empty())
+                    .collect()
+            }
+            Format::Unknown | Format::None => Vec::new(),
+        }
+    }
+}
+
+// A segment body read for tags
+pub fn scanned<S, T, E>(scanner: Option<Scanner>, chunks: S) -> impl Stream<Item = Result<T, E>>
+where
+    S: Stream<Item = Result<T, E>>,
+    T: AsRef<[u8]>,
+{
+    let mut scanner = scanner;
+    chunks.inspect(move |chunk| {
+        if let (Some(scanner), Ok(chunk)) = (&mut scanner, chunk) {
+            scanner.feed(chunk.as_ref());
+        }
+    })
+}
+
+// Command handler returning the latest timed metadata of a stream played
+// through the proxy, by channel id
+#[tauri::command]
+pub fn get_stream_metadata(proxy: State<'_, StreamProxy>, stream_id: String) -> Option<TimedMetadata> {
+    proxy.metadata.get(&stream_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(id: &str, body: &[u8]) -> Vec<u8> {
+        let mut frame = id.as_bytes().to_vec();
+        frame.extend((body.len() as u32).to_be_bytes());
+        frame.extend([0, 0]);
+        frame.extend_from_slice(body);
+        frame
+    }
+
+    #[test]
+    fn test_parse_id3_text_frames() {
+        // Test that text frames of every encoding are read and other frames skipped
+        let mut frames = frame("PRIV", b"com.apple.streaming.transportStreamTimestamp\0\0\0\0\0\0\0\0\0");
+        frames.extend(frame("TIT2", b"\x03Bohemian Rhapsody\0"));
+        let queen = "Queen".encode_utf16().flat_map(u16::to_le_bytes);
+        let artist: Vec<u8> = [1, 0xFF, 0xFE].into_iter().chain(queen).collect();
+        frames.extend(frame("TPE1", &artist));
+        frames.extend(frame("TXXX", b"\x00programme\0Morning Show"));
+        // Padding
+        frames.extend([0; 16]);
+        let mut tag = b"ID3\x03\x00\x00".to_vec();
+        let size = frames.len();
+        tag.extend([(size >> 21) as u8 & 0x7F, (size >> 14) as u8 & 0x7F, (size >> 7) as u8 & 0x7F, size as u8 & 0x7F]);
+        tag.extend(frames);
+
+        let parsed = parse(&tag).unwrap();
+        assert_eq!(parsed.get("TIT2").map(String::as_str), Some("Bohemian Rhapsody"));
+        assert_eq!(parsed.get("TPE1").map(String::as_str), Some("Queen"));
+        assert_eq!(parsed.get("TXXX:programme").map(String::as_str), Some("Morning Show"));
+        assert_eq!(parsed.len(), 3);
+        assert_eq!(parse(b"ID3\x02\x00\x00\x00\x00\x00\x00"), None);
+
+        let metadata = Metadata::default();
+        let song = TimedMetadata {
+            stream_id: "radio".to_string(),
+            title: Some("Bohemian Rhapsody".to_string()),
+            ..Default::default()
+        };
+        assert!(metadata.update(&song) && !metadata.update(&song));
+        assert_eq!(metadata.get("radio"), Some(song));
+    }
+}
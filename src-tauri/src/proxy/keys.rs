@@ -0,0 +1,134 @@
+// AES-128 encrypted HLS segments decrypted by the proxy. Key endpoints
+// often refuse browsers (no CORS, a cookie or token header they can't
+// send), so when asked to, the proxy drops the #EXT-X-KEY tags from the
+// manifest and serves every segment it links to in the clear instead
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use aes::cipher::block_padding::Pkcs7;
+use aes::cipher::{BlockDecryptMut, KeyIvInit};
+use reqwest::Response;
+use serde::{Deserialize, Serialize};
+
+use crate::http::{self, HttpOptions};
+
+type Aes128CbcDec = cbc::Decryptor<aes::Aes128>;
+
+// Keys kept before the cache starts over
+const MAX_KEYS: usize = 64;
+
+const MAX_SEGMENT_BYTES: usize = 64 * 1024 * 1024;
+
+// The key a segment is encrypted with and its IV, as 32 hex digits
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SegmentKey {
+    pub url: String,
+    pub iv: String,
+}
+
+impl SegmentKey {
+    // The IV is the tag's IV attribute ("0x" and up to 32 hex digits) or,
+    // without one, the segment's media sequence number
+    pub fn new(url: &str, iv: Option<&str>, sequence: u64) -> Option<Self> {
+        let iv = match iv {
+            Some(iv) => {
+                let digits = iv.trim().trim_start_matches("0x").trim_start_matches("0X");
+                if digits.is_empty() || digits.len() > 32 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return None;
+                }
+                format!("{:0>32}", digits.to_ascii_lowercase())
+            }
+            None => format!("{:032x}", sequence),
+        };
+        Some(Self {
+            url: url.to_string(),
+            iv,
+        })
+    }
+
+    fn iv_bytes(&self) -> Option<[u8; 16]> {
+        let mut bytes = [0; 16];
+        for (index, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(self.iv.get(index * 2..index * 2 + 2)?, 16).ok()?;
+        }
+        Some(bytes)
+    }
+}
+
+// Keys by URL, so a playlist's key is fetched once rather than per segment
+#[derive(Default)]
+pub struct KeyCache {
+    keys: Mutex<HashMap<String, [u8; 16]>>,
+}
+
+impl KeyCache {
+    pub async fn get(&self, url: &str, options: &HttpOptions) -> Result<[u8; 16], String> {
+        if let Some(key) = self.keys.lock().unwrap().get(url) {
+            return Ok(*key);
+        }
+        let response = http::get(url, Some(options))
+            .send()
+            .await
+            .map_err(|e| format!("Key request failed: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("Key request failed with HTTP {}", response.status().as_u16()));
+        }
+        let body = response.bytes().await.map_err(|e| format!("Failed to read the key: {}", e))?;
+        let key: [u8; 16] = body[..]
+            .try_into()
+            .map_err(|_| format!("Key is {} bytes instead of 16", body.len()))?;
+        let mut keys = self.keys.lock().unwrap();
+        if keys.len() >= MAX_KEYS {
+            keys.clear();
+        }
+        keys.insert(url.to_string(), key);
+        Ok(key)
+    }
+}
+
+pub async fn read_segment(response: &mut Response) -> Result<Vec<u8>, String> {
+    let mut segment = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| format!("Failed to read the segment: {}", e))?
+    {
+        segment.extend_from_slice(&chunk);
+        if segment.len() > MAX_SEGMENT_BYTES {
+            return Err(format!("Segment is larger than {} bytes", MAX_SEGMENT_BYTES));
+        }
+    }
+    Ok(segment)
+}
+
+// AES-128-CBC with PKCS#7 padding, as HLS specifies
+pub fn decrypt(segment: &[u8], key: &[u8; 16], segment_key: &SegmentKey) -> Result<Vec<u8>, String> {
+    let iv = segment_key.iv_bytes().ok_or("Invalid IV")?;
+    Aes128CbcDec::new(key.into(), &iv.into())
+        .decrypt_padded_vec_mut::<Pkcs7>(segment)
+        .map_err(|_| "Segment doesn't decrypt with its key".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes::cipher::BlockEncryptMut;
+
+    #[test]
+    fn test_decrypt_segment() {
+        // Test that segments decrypt with explicit and media sequence IVs and bad IVs are refused
+        let key = [3; 16];
+        let explicit = SegmentKey::new("http://host/key", Some("0x1F"), 9).unwrap();
+        assert_eq!(explicit.iv, "0000000000000000000000000000001f");
+        let sequence = SegmentKey::new("http://host/key", None, 258).unwrap();
+        assert_eq!(sequence.iv, "00000000000000000000000000000102");
+        assert!(SegmentKey::new("http://host/key", Some("0xZZ"), 0).is_none());
+
+        let plain = b"\x47 transport stream packet".to_vec();
+        let iv = sequence.iv_bytes().unwrap();
+        let encrypted =
+            cbc::Encryptor::<aes::Aes128>::new(&key.into(), &iv.into()).encrypt_padded_vec_mut::<Pkcs7>(&plain);
+        assert_eq!(decrypt(&encrypted, &key, &sequence).unwrap(), plain);
+        assert!(decrypt(&encrypted, &[4; 16], &sequence).is_err());
+    }
+}
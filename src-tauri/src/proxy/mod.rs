@@ -0,0 +1,1123 @@
+// Local HTTP proxy the web player loads streams through. The webview can't
+// set a User-Agent, Referer or Cookie, and providers rarely send CORS
+// headers or serve https, so streams are fetched here with the channel's
+// HTTP options and relayed from 127.0.0.1. Proxy URLs carry the upstream
+// URL and options, signed with a key made at startup so nothing but URLs
+// handed out by get_proxy_url, or linked from manifests fetched through
+// them, can be fetched through the proxy
+mod adaptive;
+pub mod backups;
+mod captions;
+mod dash;
+mod ffmpeg;
+mod hls;
+pub mod hwaccel;
+pub mod id3;
+mod keys;
+pub mod multicast;
+pub mod probe;
+pub mod profiles;
+pub mod quality;
+mod reconnect;
+mod renew;
+pub mod scte35;
+pub mod screenshot;
+mod stats;
+mod teletext;
+pub mod thumbnails;
+pub mod timeshift;
+pub mod transcode;
+mod ts;
+mod webvtt;
+
+use std::convert::Infallible;
+use std::net::Ipv4Addr;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use futures_util::StreamExt;
+use hmac::{Hmac, Mac};
+use http_body_util::BodyDataStream;
+use hyper::body::Incoming;
+use hyper::header::{
+    HeaderValue, ACCEPT_RANGES, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_ORIGIN, CACHE_CONTROL,
+    CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, RANGE,
+};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use reqwest::Body;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::net::TcpListener;
+use url::Url;
+
+use crate::epg::parental;
+use crate::http::{self, HttpOptions};
+use crate::playlist::{self, check_http_options};
+use crate::providers::{self, ConnectionClaim, ConnectionError};
+use crate::validate_string_length;
+
+use adaptive::Adaptive;
+use backups::Sources;
+use ffmpeg::Processes;
+use id3::{Metadata, Scanner};
+use keys::{KeyCache, SegmentKey};
+use multicast::Multicast;
+use profiles::TranscodeProfiles;
+use quality::{QualityPin, StreamQualities};
+use renew::{Renewal, Renewed};
+use screenshot::Playing;
+use stats::{Counter, Stats};
+use transcode::Transcoding;
+
+const MAX_URL_LENGTH: usize = 4096;
+
+const WEBVTT: &str = "text/vtt; charset=utf-8";
+
+// Upstream response headers passed on to the player
+const RELAYED_HEADERS: [hyper::header::HeaderName; 4] = [CONTENT_TYPE, CONTENT_RANGE, ACCEPT_RANGES, CACHE_CONTROL];
+
+// What a proxy URL fetches
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct Target {
+    url: String,
+    #[serde(default, skip_serializing_if = "HttpOptions::is_empty")]
+    http: HttpOptions,
+    // Decrypt AES-128 segments of the manifests fetched
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    decrypt: bool,
+    // Set on segments that are decrypted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key: Option<SegmentKey>,
+    // Remux MPEG-TS into fragmented MP4 with ffmpeg
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    remux: bool,
+    // Transcode with ffmpeg, with the configured codec arguments
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    transcode: bool,
+    // Transcode with this profile's arguments instead
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    profile: Option<String>,
+    // The URL is a base the rest of the proxy URL's path is resolved
+    // against, for DASH segment templates
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    base: bool,
+    // Serve a master playlist's variants through one media playlist, the
+    // variant picked by measured throughput
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    adaptive: bool,
+    // The adaptive stream the media playlist or segment belongs to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stream: Option<String>,
+    // The channel's pinned quality
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    quality: Option<QualityPin>,
+    // The channel whose active source is fetched in place of the URL
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sources: Option<String>,
+    // Where to get the URL again when its token expires
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    renewal: Option<Renewal>,
+    // The stream whose statistics the request counts towards
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stats: Option<String>,
+    // Linked from a manifest, timed as a segment download
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    segment: bool,
+    // Serve this subtitle track of the stream as WebVTT
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    subtitles: Option<usize>,
+    // Serve this teletext page of the stream as WebVTT, 0 for the first
+    // subtitle page the stream announces
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    teletext: Option<u16>,
+    // Serve this closed caption channel (CC1 to CC4) of the stream as WebVTT
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    captions: Option<u8>,
+    // Or this CEA-708 caption service
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    caption_service: Option<u8>,
+    // Play through the channel's timeshift buffer, this many seconds
+    // behind live
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timeshift: Option<u64>,
+    // The provider whose connection the request holds while it streams
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    provider: Option<String>,
+    // The guide channel parental controls check while the stream plays
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    guide: Option<String>,
+}
+
+pub struct StreamProxy {
+    key: [u8; 32],
+    // Set once the server is listening
+    port: OnceLock<u16>,
+    keys: KeyCache,
+    processes: Arc<Processes>,
+    adaptive: Arc<Adaptive>,
+    sources: Sources,
+    renewed: Renewed,
+    stats: Arc<Stats>,
+    metadata: Metadata,
+    playing: Playing,
+}
+
+impl Default for StreamProxy {
+    fn default() -> Self {
+        let mut key = [0; 32];
+        getrandom::getrandom(&mut key).expect("The OS random number generator is unavailable");
+        Self {
+            key,
+            port: OnceLock::new(),
+            keys: KeyCache::default(),
+            processes: Arc::default(),
+            adaptive: Arc::default(),
+            sources: Sources::default(),
+            renewed: Renewed::default(),
+            stats: Arc::default(),
+            metadata: Metadata::default(),
+            playing: Playing::default(),
+        }
+    }
+}
+
+// Signatures are HMAC-SHA256 cut to 128 bits, which keeps URLs short
+const SIGNATURE_BYTES: usize = 16;
+
+fn mac(key: &[u8; 32], payload: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+fn sign(key: &[u8; 32], payload: &str) -> String {
+    let tag = mac(key, payload).finalize().into_bytes();
+    tag[..SIGNATURE_BYTES].iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Whether a signature is the payload's, compared in constant time
+fn verify(key: &[u8; 32], payload: &str, signature: &str) -> bool {
+    if signature.len() != SIGNATURE_BYTES * 2 {
+        return false;
+    }
+    let tag: Option<Vec<u8>> = (0..signature.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(signature.get(i..i + 2)?, 16).ok())
+        .collect();
+    tag.is_some_and(|tag| mac(key, payload).verify_truncated_left(&tag).is_ok())
+}
+
+// The upstream URL's file name, kept at the end of the proxy URL for
+// players that go by extension
+fn file_name(url: &str) -> String {
+    let name = Url::parse(url)
+        .ok()
+        .and_then(|url| url.path_segments()?.next_back().map(str::to_string))
+        .unwrap_or_default();
+    let name: String = name
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+        .take(100)
+        .collect();
+    if name.is_empty() {
+        "stream".to_string()
+    } else {
+        name
+    }
+}
+
+// Proxy URL for a target: /<signature>/<target>/<file name>, or for a base
+// /<signature>/<target>/ with the path to resolve to follow
+fn encode(key: &[u8; 32], port: u16, target: &Target) -> String {
+    let json = serde_json::to_string(target).unwrap_or_default();
+    let payload = URL_SAFE_NO_PAD.encode(json);
+    let name = if target.base { String::new() } else { file_name(&target.url) };
+    format!("http://127.0.0.1:{}/{}/{}/{}", port, sign(key, &payload), payload, name)
+}
+
+// The target of a proxy URL path and the rest of the path
+fn decode<'a>(key: &[u8; 32], path: &'a str) -> Option<(Target, &'a str)> {
+    let mut parts = path.trim_start_matches('/').splitn(3, '/');
+    let (signature, payload) = (parts.next()?, parts.next()?);
+    if !verify(key, payload, signature) {
+        return None;
+    }
+    let json = URL_SAFE_NO_PAD.decode(payload).ok()?;
+    Some((serde_json::from_slice(&json).ok()?, parts.next().unwrap_or_default()))
+}
+
+// The upstream URL a base target's proxy URL stands for, kept within the
+// base's directory
+fn resolve_base(base: &str, rest: &str, query: Option<&str>) -> Option<String> {
+    let reference = match query {
+        Some(query) => format!("{}?{}", rest, query),
+        None => rest.to_string(),
+    };
+    let base = Url::parse(base).ok()?;
+    let resolved = base.join(&reference).ok()?;
+    resolved
+        .as_str()
+        .starts_with(base.join("./").ok()?.as_str())
+        .then(|| resolved.to_string())
+}
+
+fn response(status: StatusCode, body: impl Into<Body>) -> Response<Body> {
+    let mut response = Response::new(body.into());
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
+    response
+}
+
+// A segment fetched and decrypted as a whole
+async fn decrypted(app: &AppHandle, target: &Target, segment_key: &SegmentKey) -> Result<Response<Body>, String> {
+    let proxy = app.state::<StreamProxy>();
+    let key_http = playlist::stream_options(app, &segment_key.url, &target.http);
+    let key = proxy.keys.get(&segment_key.url, &key_http).await?;
+    let started = Instant::now();
+    let mut counter = target.stats.as_deref().map(|id| Counter::new(app, &proxy.stats, id, true));
+    let http = playlist::stream_options(app, &target.url, &target.http);
+    let upstream = http::get(&target.url, Some(&http)).send().await;
+    let failed = upstream.as_ref().map_or(true, |upstream| !upstream.status().is_success());
+    if let Some(id) = target.stats.as_deref().filter(|_| failed) {
+        stats::error(app, &proxy.stats, id);
+    }
+    let mut upstream = upstream.map_err(|e| format!("Request failed: {}", e))?;
+    if !upstream.status().is_success() {
+        return Ok(response(upstream.status(), ""));
+    }
+    let content_type = upstream.headers().get(CONTENT_TYPE).cloned();
+    let segment = keys::read_segment(&mut upstream).await?;
+    if let Some(counter) = &mut counter {
+        counter.add(segment.len());
+    }
+    if let Some(stream) = &target.stream {
+        proxy.adaptive.record(stream, segment.len() as u64, started.elapsed());
+    }
+    let segment = keys::decrypt(&segment, &key, segment_key)?;
+    if let Some(id) = &target.stats {
+        Scanner::new(app, id).feed(&segment);
+        scte35::Scanner::new(app, id).feed(&segment);
+    }
+    let mut relayed = response(StatusCode::OK, segment);
+    if let Some(content_type) = content_type {
+        relayed.headers_mut().insert(CONTENT_TYPE, content_type);
+    }
+    Ok(relayed)
+}
+
+// A body read for timed metadata and splice markers
+fn scanned<S, T, E>(
+    scanner: Option<Scanner>,
+    markers: Option<scte35::Scanner>,
+    chunks: S,
+) -> impl futures_util::Stream<Item = Result<T, E>>
+where
+    S: futures_util::Stream<Item = Result<T, E>>,
+    T: AsRef<[u8]>,
+{
+    scte35::scanned(markers, id3::scanned(scanner, chunks))
+}
+
+// Records a segment download of an adaptive stream once its body is done
+struct Measured {
+    adaptive: Arc<Adaptive>,
+    stream: String,
+    bytes: u64,
+    started: Instant,
+}
+
+impl Measured {
+    fn add(&mut self, bytes: usize) {
+        self.bytes += bytes as u64;
+    }
+}
+
+impl Drop for Measured {
+    fn drop(&mut self) {
+        self.adaptive.record(&self.stream, self.bytes, self.started.elapsed());
+    }
+}
+
+// The upstream response for a target. A refused URL that can be renewed is
+// renewed and requested again; for a channel with backups a failed request
+// counts against its source, and once that switches source the request is
+// made again there
+async fn fetch(
+    app: &AppHandle,
+    target: &mut Target,
+    query: Option<&str>,
+    range: Option<&HeaderValue>,
+) -> reqwest::Result<reqwest::Response> {
+    let proxy = app.state::<StreamProxy>();
+    let mut renewed = false;
+    loop {
+        let url = match target.base {
+            true => target.url.clone(),
+            false => hls::with_directives(&target.url, query),
+        };
+        let http = playlist::stream_options(app, &url, &target.http);
+        let mut upstream = http::get(&url, Some(&http));
+        if let Some(range) = range {
+            upstream = upstream.header(RANGE, range.clone());
+        }
+        let result = upstream.send().await;
+        let failed = result.as_ref().map_or(true, |upstream| backups::failed(upstream.status()));
+        if let Some(id) = target.stats.as_deref().filter(|_| failed) {
+            stats::error(app, &proxy.stats, id);
+        }
+        let refused = result.as_ref().is_ok_and(|upstream| renew::expired(upstream.status()));
+        if let Some(renewal) = target.renewal.as_ref().filter(|_| refused && !renewed) {
+            renewed = true;
+            if let Some(url) = renew::renew(app, renewal).await {
+                target.url = url;
+                continue;
+            }
+        }
+        let Some(channel) = &target.sources else {
+            return result;
+        };
+        if !failed {
+            proxy.sources.succeeded(channel);
+            return result;
+        }
+        match proxy.sources.failed(channel) {
+            Some(source) => {
+                // Backups aren't where the renewal gets a URL for
+                target.url = source.url.clone();
+                target.renewal = None;
+                let _ = app.emit("stream-source-changed", source);
+            }
+            None => return result,
+        }
+    }
+}
+
+fn typed(mut response: Response<Body>, content_type: &'static str) -> Response<Body> {
+    response.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+    response
+}
+
+fn remuxed(response: Response<Body>) -> Response<Body> {
+    typed(response, "video/mp4")
+}
+
+// Where a target is read from over HTTP: multicast goes through the proxy,
+// which joins the group on the configured interface
+fn http_input(app: &AppHandle, target: &Target) -> (String, HttpOptions) {
+    let proxy = app.state::<StreamProxy>();
+    match multicast::is_multicast(&target.url) {
+        true => {
+            let raw = Target {
+                url: target.url.clone(),
+                ..Default::default()
+            };
+            (encode(&proxy.key, proxy.port.get().copied().unwrap_or_default(), &raw), HttpOptions::default())
+        }
+        false => (target.url.clone(), playlist::stream_options(app, &target.url, &target.http)),
+    }
+}
+
+fn ffmpeg_response(body: Result<Body, String>, content_type: &'static str) -> Response<Body> {
+    match body {
+        Ok(body) => typed(response(StatusCode::OK, body), content_type),
+        Err(e) => {
+            log::error!("{}", e);
+            response(StatusCode::BAD_GATEWAY, e)
+        }
+    }
+}
+
+async fn relay(app: AppHandle, request: Request<Incoming>) -> Response<Body> {
+    if request.method() == Method::OPTIONS {
+        let mut preflight = response(StatusCode::NO_CONTENT, "");
+        preflight
+            .headers_mut()
+            .insert(ACCESS_CONTROL_ALLOW_HEADERS, HeaderValue::from_static("*"));
+        return preflight;
+    }
+    if !matches!(*request.method(), Method::GET | Method::HEAD) {
+        return response(StatusCode::METHOD_NOT_ALLOWED, "");
+    }
+    let proxy = app.state::<StreamProxy>();
+    let Some((mut target, rest)) = decode(&proxy.key, request.uri().path()) else {
+        return response(StatusCode::NOT_FOUND, "Unknown stream");
+    };
+    if target.base {
+        match resolve_base(&target.url, rest, request.uri().query()) {
+            Some(url) => target.url = url,
+            None => return response(StatusCode::NOT_FOUND, "Unknown stream"),
+        }
+    }
+    // The adaptive stream's media playlist: the variant to play now
+    if let (true, Some(stream)) = (target.adaptive, &target.stream) {
+        match proxy.adaptive.select(stream) {
+            Some(url) => target.url = url,
+            None => return response(StatusCode::NOT_FOUND, "Unknown stream"),
+        }
+        target.adaptive = false;
+    }
+    let backup = target.sources.as_deref().and_then(|channel| proxy.sources.active(channel));
+    match backup.filter(|url| *url != target.url) {
+        Some(url) => {
+            target.url = url;
+            target.renewal = None;
+        }
+        None => {
+            if let Some(url) = target.renewal.as_ref().and_then(|renewal| proxy.renewed.get(renewal)) {
+                target.url = url;
+            }
+        }
+    }
+    if request.method() == Method::HEAD {
+        return respond(app, request, target).await;
+    }
+    // Refused while parental controls restrict the programme on air
+    let guide = target.guide.clone();
+    if let Some(guide) = &guide {
+        if let Err(e) = parental::enforce_live(&app, guide).await {
+            return response(StatusCode::FORBIDDEN, e);
+        }
+    }
+    match claim(&app, &proxy.key, &target).await {
+        Ok(claim) => guarded(&app, held(respond(app.clone(), request, target).await, claim), guide),
+        Err(ConnectionError::LimitReached { limit, .. }) => {
+            let message = format!("All {} connections of the provider are in use", limit);
+            response(StatusCode::TOO_MANY_REQUESTS, message)
+        }
+        Err(ConnectionError::Failed { message }) => response(StatusCode::BAD_GATEWAY, message),
+    }
+}
+
+// The provider connection a request holds while its body is read: one per
+// stream, however many requests its player makes
+async fn claim(app: &AppHandle, key: &[u8; 32], target: &Target) -> Result<Option<ConnectionClaim>, ConnectionError> {
+    let Some(provider_id) = target.provider.as_deref() else {
+        return Ok(None);
+    };
+    let stream = target.stats.clone().unwrap_or_else(|| sign(key, &target.url));
+    providers::claim(app, provider_id, &stream, &stream).await
+}
+
+// A response whose body holds a claim until it's sent or dropped
+fn held(response: Response<Body>, claim: Option<ConnectionClaim>) -> Response<Body> {
+    let Some(claim) = claim else {
+        return response;
+    };
+    let (parts, body) = response.into_parts();
+    let chunks = BodyDataStream::new(body).inspect(move |_| {
+        let _ = &claim;
+    });
+    Response::from_parts(parts, Body::wrap_stream(chunks))
+}
+
+// A response whose body ends once parental controls restrict the programme
+// coming on its guide channel, checked every little while as it's read
+fn guarded(app: &AppHandle, response: Response<Body>, guide: Option<String>) -> Response<Body> {
+    let Some(guide) = guide else {
+        return response;
+    };
+    let app = app.clone();
+    let mut due = Instant::now() + parental::RECHECK_INTERVAL;
+    let (parts, body) = response.into_parts();
+    let chunks = BodyDataStream::new(body).take_while(move |_| {
+        let check = (Instant::now() >= due).then(|| (app.clone(), guide.clone()));
+        if check.is_some() {
+            due = Instant::now() + parental::RECHECK_INTERVAL;
+        }
+        async move {
+            let Some((app, guide)) = check else {
+                return true;
+            };
+            match parental::enforce_live(&app, &guide).await {
+                Ok(()) => true,
+                Err(e) => {
+                    parental::blocked(&app, &e);
+                    false
+                }
+            }
+        }
+    });
+    Response::from_parts(parts, Body::wrap_stream(chunks))
+}
+
+async fn respond(app: AppHandle, request: Request<Incoming>, mut target: Target) -> Response<Body> {
+    let proxy = app.state::<StreamProxy>();
+    // Timeshifted streams play from the channel's buffer
+    if let Some(behind) = target.timeshift.take().filter(|_| request.method() == Method::GET) {
+        match timeshift::attach(&app, &target).await {
+            Ok(Some(_)) if target.remux || target.transcode || target.profile.is_some() => {
+                // ffmpeg reads the buffer through the proxy
+                let buffered = Target {
+                    url: target.url.clone(),
+                    stats: target.stats.clone(),
+                    timeshift: Some(behind),
+                    ..Default::default()
+                };
+                target.url = encode(&proxy.key, proxy.port.get().copied().unwrap_or_default(), &buffered);
+                target.http = HttpOptions::default();
+                (target.sources, target.renewal) = (None, None);
+            }
+            Ok(Some(buffer)) => {
+                let id = target.stats.as_deref().unwrap_or_default();
+                let body = Body::wrap_stream(timeshift::read(&app, id, buffer, behind));
+                return typed(response(StatusCode::OK, body), "video/mp2t");
+            }
+            // Not a continuous stream; played as it is
+            Ok(None) => {}
+            Err(e) => {
+                log::debug!("Failed to timeshift {}: {}", target.url, e);
+                return response(StatusCode::BAD_GATEWAY, e);
+            }
+        }
+    }
+    // Cues of a live stream come as it plays, so the body doesn't end
+    if let Some(track) = target.subtitles {
+        if request.method() == Method::HEAD {
+            return typed(response(StatusCode::OK, ""), WEBVTT);
+        }
+        let settings = app.state::<Transcoding>().get();
+        let (input, input_http) = http_input(&app, &target);
+        let body = ffmpeg::webvtt(&proxy.processes, &settings.ffmpeg, &input, &input_http, track);
+        return ffmpeg_response(body, WEBVTT);
+    }
+    // Teletext and closed captions are decoded here as the stream comes
+    let decoder: Option<Box<dyn webvtt::Decoder>> = match (target.teletext, target.captions, target.caption_service) {
+        (Some(page), _, _) => Some(Box::new(teletext::Decoder::new((page != 0).then_some(page)))),
+        (None, _, Some(service)) => Some(Box::new(captions::Decoder::service(service))),
+        (None, Some(channel), None) => Some(Box::new(captions::Decoder::new(channel))),
+        (None, None, None) => None,
+    };
+    if let Some(decoder) = decoder {
+        if request.method() == Method::HEAD {
+            return typed(response(StatusCode::OK, ""), WEBVTT);
+        }
+        (target.url, target.http) = http_input(&app, &target);
+        let upstream = match fetch(&app, &mut target, None, None).await {
+            Ok(upstream) if upstream.status().is_success() => upstream,
+            Ok(upstream) => {
+                return response(StatusCode::BAD_GATEWAY, format!("Upstream answered {}", upstream.status()))
+            }
+            Err(e) => return response(StatusCode::BAD_GATEWAY, format!("Request failed: {}", e)),
+        };
+        let cues = webvtt::document(decoder, upstream.bytes_stream());
+        return typed(response(StatusCode::OK, Body::wrap_stream(cues)), WEBVTT);
+    }
+    let native = ffmpeg::is_rtsp(&target.url) || ffmpeg::is_rtmp(&target.url);
+    if target.remux || target.transcode || target.profile.is_some() || native {
+        if request.method() == Method::HEAD {
+            return remuxed(response(StatusCode::OK, ""));
+        }
+        let settings = app.state::<Transcoding>().get();
+        let profile = target.profile.as_deref().and_then(|id| app.state::<TranscodeProfiles>().get(id));
+        let codecs = match profile {
+            Some(profile) => profile.arguments(),
+            None if target.transcode || target.profile.is_some() => settings.arguments,
+            None => ffmpeg::REMUX_ARGUMENTS.map(str::to_string).to_vec(),
+        };
+        let (input, input_http) = http_input(&app, &target);
+        let body = ffmpeg::run(&proxy.processes, &settings.ffmpeg, &input, &input_http, &codecs);
+        return ffmpeg_response(body, "video/mp4");
+    }
+    if multicast::is_multicast(&target.url) {
+        let mut relayed = match request.method() == Method::HEAD {
+            true => response(StatusCode::OK, ""),
+            false => match multicast::open(&target.url, &app.state::<Multicast>().get()) {
+                Ok(body) => response(StatusCode::OK, body),
+                Err(e) => {
+                    log::error!("{}", e);
+                    return response(StatusCode::BAD_GATEWAY, e);
+                }
+            },
+        };
+        relayed.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static("video/mp2t"));
+        return relayed;
+    }
+    if let Some(segment_key) = target.key.as_ref().filter(|_| request.method() == Method::GET) {
+        return decrypted(&app, &target, segment_key).await.unwrap_or_else(|e| {
+            log::debug!("Failed to decrypt {}: {}", target.url, e);
+            response(StatusCode::BAD_GATEWAY, e)
+        });
+    }
+
+    let mut upstream = match fetch(&app, &mut target, request.uri().query(), request.headers().get(RANGE)).await {
+        Ok(upstream) => upstream,
+        Err(e) => {
+            log::debug!("Proxied request to {} failed: {}", target.url, e);
+            return response(StatusCode::BAD_GATEWAY, format!("Request failed: {}", e));
+        }
+    };
+    let status = upstream.status();
+    let headers = upstream.headers().clone();
+    let content_type = headers.get(CONTENT_TYPE).and_then(|value| value.to_str().ok());
+    let mut relayed = if request.method() == Method::HEAD {
+        response(status, "")
+    } else if status.is_success() && dash::is_manifest(content_type, upstream.url()) {
+        let base = upstream.url().clone();
+        let manifest = match hls::read(&mut upstream).await {
+            Ok(manifest) => manifest,
+            Err(e) => return response(StatusCode::BAD_GATEWAY, e),
+        };
+        let mut http = target.http.clone();
+        hls::add_cookies(&mut http, &headers);
+        let port = proxy.port.get().copied().unwrap_or_default();
+        let link = |directory: &str| {
+            let linked = Target {
+                url: directory.to_string(),
+                http: http.clone(),
+                base: true,
+                stats: target.stats.clone(),
+                segment: true,
+                provider: target.provider.clone(),
+                guide: target.guide.clone(),
+                ..Default::default()
+            };
+            encode(&proxy.key, port, &linked)
+        };
+        match dash::rewrite(&manifest, &base, &link) {
+            Ok(manifest) => response(status, manifest),
+            Err(e) => return response(StatusCode::BAD_GATEWAY, e),
+        }
+    } else if status.is_success() && hls::is_playlist(content_type, upstream.url()) {
+        // Relative URIs resolve against where redirects ended up
+        let base = upstream.url().clone();
+        let playlist = match hls::read(&mut upstream).await {
+            Ok(playlist) => playlist,
+            Err(e) => return response(StatusCode::BAD_GATEWAY, e),
+        };
+        let mut http = target.http.clone();
+        hls::add_cookies(&mut http, &headers);
+        let port = proxy.port.get().copied().unwrap_or_default();
+        let link = |url: &str, key: Option<SegmentKey>| {
+            let linked = Target {
+                url: url.to_string(),
+                http: http.clone(),
+                decrypt: target.decrypt,
+                key,
+                stream: target.stream.clone(),
+                stats: target.stats.clone(),
+                segment: true,
+                provider: target.provider.clone(),
+                guide: target.guide.clone(),
+                ..Default::default()
+            };
+            encode(&proxy.key, port, &linked)
+        };
+        let variants = match target.adaptive || target.quality.is_some() {
+            true => quality::allowed(adaptive::variants(&playlist, &base), target.quality),
+            false => Vec::new(),
+        };
+        if target.quality.is_some() && !variants.is_empty() && (!target.adaptive || variants.len() == 1) {
+            // Pinned: only the best variant the pin allows
+            let mut master = hls::rewrite(&adaptive::without_variants(&playlist), &base, target.decrypt, &link);
+            let pinned = &variants[0];
+            master.push_str(&format!("{}\n{}\n", pinned.info, link(&pinned.url, None)));
+            response(status, master)
+        } else if target.adaptive && variants.len() > 1 {
+            // One variant, the proxy choosing which one it really is; it
+            // keeps the best's attributes so players don't cap the quality
+            let stream = sign(&proxy.key, &target.url);
+            let best = variants[0].info.clone();
+            let selected = Target {
+                url: target.url.clone(),
+                http: http.clone(),
+                decrypt: target.decrypt,
+                adaptive: true,
+                stream: Some(stream.clone()),
+                stats: target.stats.clone(),
+                provider: target.provider.clone(),
+                guide: target.guide.clone(),
+                ..Default::default()
+            };
+            proxy.adaptive.register(&stream, variants);
+            let mut master = hls::rewrite(&adaptive::without_variants(&playlist), &base, target.decrypt, &link);
+            master.push_str(&format!("{}\n{}\n", best, encode(&proxy.key, port, &selected)));
+            response(status, master)
+        } else {
+            response(status, hls::rewrite(&playlist, &base, target.decrypt, &link))
+        }
+    } else {
+        let counter = target.stats.as_deref().map(|id| Counter::new(&app, &proxy.stats, id, target.segment));
+        // Segments are read for timed metadata, streams and segments for
+        // splice markers
+        let scanner = target.stats.as_deref().filter(|_| target.segment && status.is_success());
+        let scanner = scanner.map(|id| Scanner::new(&app, id));
+        let markers = target.stats.as_deref().filter(|_| status.is_success());
+        let markers = markers.map(|id| scte35::Scanner::new(&app, id));
+        let body = match target.stream.clone().filter(|_| status.is_success()) {
+            Some(stream) => {
+                let mut measured = Measured {
+                    adaptive: proxy.adaptive.clone(),
+                    stream,
+                    bytes: 0,
+                    started: Instant::now(),
+                };
+                let chunks = upstream.bytes_stream().inspect(move |chunk| {
+                    if let Ok(chunk) = chunk {
+                        measured.add(chunk.len());
+                    }
+                });
+                Body::wrap_stream(stats::counted(counter, scanned(scanner, markers, chunks)))
+            }
+            None => {
+                let range = request.headers().get(RANGE).and_then(|range| range.to_str().ok());
+                match reconnect::range_start(range).filter(|_| status.is_success()) {
+                    Some(start) => {
+                        let relayed = reconnect::relay(app.clone(), &target.url, &target.http, upstream, start);
+                        Body::wrap_stream(stats::counted(counter, scanned(scanner, markers, relayed)))
+                    }
+                    None => {
+                        let chunks = upstream.bytes_stream();
+                        Body::wrap_stream(stats::counted(counter, scanned(scanner, markers, chunks)))
+                    }
+                }
+            }
+        };
+        let mut streamed = response(status, body);
+        if let Some(length) = headers.get(CONTENT_LENGTH) {
+            streamed.headers_mut().insert(CONTENT_LENGTH, length.clone());
+        }
+        streamed
+    };
+    for name in RELAYED_HEADERS {
+        if let Some(value) = headers.get(&name) {
+            relayed.headers_mut().insert(name, value.clone());
+        }
+    }
+    relayed
+}
+
+// Start the proxy on a free port; runs for the lifetime of the app
+pub fn start(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let listener = match TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await {
+            Ok(listener) => listener,
+            Err(e) => return log::error!("Failed to start the stream proxy: {}", e),
+        };
+        let port = match listener.local_addr() {
+            Ok(address) => address.port(),
+            Err(e) => return log::error!("Failed to start the stream proxy: {}", e),
+        };
+        let _ = app.state::<StreamProxy>().port.set(port);
+        log::info!("Stream proxy listening on 127.0.0.1:{}", port);
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    log::debug!("Stream proxy failed to accept a connection: {}", e);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                let service = service_fn(move |request| {
+                    let app = app.clone();
+                    async move { Ok::<_, Infallible>(relay(app, request).await) }
+                });
+                if let Err(e) = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await {
+                    log::debug!("Stream proxy connection failed: {}", e);
+                }
+            });
+        }
+    });
+}
+
+// Kill the running ffmpeg processes; called as the app exits
+pub fn stop(app: &AppHandle) {
+    app.state::<StreamProxy>().processes.kill_all();
+}
+
+// How get_proxy_url's stream is served
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ProxyOptions {
+    // Serve AES-128 HLS segments decrypted, for key servers the player
+    // can't reach
+    pub decrypt: bool,
+    // Serve MPEG-TS as fragmented MP4
+    pub remux: bool,
+    // Transcode with the transcode settings, for codecs the platform can't
+    // decode
+    pub transcode: bool,
+    // Transcode with this profile, or else the one chosen for the channel
+    // or its provider
+    pub profile_id: Option<String>,
+    pub provider_id: Option<String>,
+    pub channel_id: Option<String>,
+    // Pick the variant of an HLS master playlist by measured throughput,
+    // switching as it changes; a quality pinned for the channel is kept to
+    pub adaptive: bool,
+    // Streams to fail over to, in order, when the URL keeps failing
+    pub backup_urls: Vec<String>,
+    // Where the URL came from, to get it again when its token expires
+    pub renewal: Option<Renewal>,
+    // Keep the last minutes of the stream on disk, so it can be paused
+    // and rewound
+    pub timeshift: bool,
+    // The guide channel the stream shows, for parental controls; found
+    // from the channel id without
+    pub epg_channel_id: Option<String>,
+}
+
+// A stream URL and its HTTP options, checked, with any options suffix of
+// the URL moved into them
+fn stream_location(url: String, http: Option<HttpOptions>) -> Result<(String, HttpOptions), String> {
+    validate_string_length(&url, MAX_URL_LENGTH)?;
+    let (url, suffix) = http::split_url_options(url.trim());
+    // RTMP URLs may carry librtmp options after the address
+    let location = match ffmpeg::is_rtmp(&url) {
+        true => url.split_whitespace().next().unwrap_or_default(),
+        false => &url,
+    };
+    let parsed = Url::parse(location).map_err(|e| format!("Invalid URL '{}': {}", url, e))?;
+    if !matches!(parsed.scheme(), "http" | "https" | "rtsp" | "rtsps" | "udp" | "rtp") && !ffmpeg::is_rtmp(&url) {
+        return Err(format!(
+            "Only http(s), rtsp, rtmp and multicast streams can be proxied, not {}://",
+            parsed.scheme()
+        ));
+    }
+    if multicast::is_multicast(&url) {
+        multicast::parse(&url)?;
+    }
+    let mut http = http.unwrap_or_default();
+    http.extend(suffix.unwrap_or_default());
+    check_http_options(&http)?;
+    Ok((url, http))
+}
+
+// The provider a channel id belongs to, see identity::channel_id
+fn provider_of(channel_id: &str) -> Option<&str> {
+    channel_id.split_once(':').map(|(provider_id, _)| provider_id)
+}
+
+// Command handler returning the URL the player should load a stream from;
+// the stream is fetched with its HTTP options, including any Kodi-style
+// "|Header=value" suffix of the URL. Refused while parental controls
+// restrict the programme on the stream's guide channel
+#[tauri::command]
+pub fn get_proxy_url(
+    app: AppHandle,
+    proxy: State<'_, StreamProxy>,
+    profiles: State<'_, TranscodeProfiles>,
+    qualities: State<'_, StreamQualities>,
+    url: String,
+    http: Option<HttpOptions>,
+    options: Option<ProxyOptions>,
+) -> Result<String, String> {
+    let (url, http) = stream_location(url, http)?;
+    let options = options.unwrap_or_default();
+    if let Some(renewal) = &options.renewal {
+        renewal.check()?;
+    }
+    let guide = parental::guide_channel(&app, options.epg_channel_id.as_deref(), options.channel_id.as_deref())?;
+    if let Some(guide) = &guide {
+        parental::enforce(&app, guide)?;
+    }
+    let profile = match options.profile_id.as_deref() {
+        Some(id) => Some(profiles.get(id).ok_or_else(|| format!("Unknown profile '{}'", id))?),
+        None => profiles.resolve(options.provider_id.as_deref(), options.channel_id.as_deref()),
+    };
+    let port = *proxy.port.get().ok_or("The stream proxy isn't running")?;
+    // Statistics and sources are tracked per channel, or per stream without
+    // a channel id
+    let stream_id = options.channel_id.clone().unwrap_or_else(|| sign(&proxy.key, &url));
+    let mut backups = Vec::new();
+    for backup in options.backup_urls.iter().map(|backup| backup.trim()).filter(|backup| !backup.is_empty()) {
+        validate_string_length(backup, MAX_URL_LENGTH)?;
+        Url::parse(backup).map_err(|e| format!("Invalid URL '{}': {}", backup, e))?;
+        if backup != url && !backups.iter().any(|known| known == backup) {
+            backups.push(backup.to_string());
+        }
+    }
+    let sources = (!backups.is_empty()).then(|| {
+        proxy.sources.register(&stream_id, std::iter::once(url.clone()).chain(backups).collect());
+        stream_id.clone()
+    });
+    let target = Target {
+        url,
+        http,
+        decrypt: options.decrypt,
+        remux: options.remux,
+        transcode: options.transcode,
+        profile: profile.map(|profile| profile.id),
+        adaptive: options.adaptive,
+        quality: options.channel_id.as_deref().and_then(|id| qualities.get(id)),
+        sources,
+        renewal: options.renewal,
+        stats: Some(stream_id.clone()),
+        timeshift: options.timeshift.then_some(0),
+        provider: options
+            .provider_id
+            .or_else(|| options.channel_id.as_deref().and_then(provider_of).map(str::to_string)),
+        guide,
+        ..Default::default()
+    };
+    proxy.playing.remember(&stream_id, &target);
+    Ok(encode(&proxy.key, port, &target))
+}
+
+// The stream a text track belongs to, given as in ProxyOptions, so the
+// track's requests share the stream's provider connection and statistics
+// and are checked by parental controls like the stream
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TrackOptions {
+    pub provider_id: Option<String>,
+    pub channel_id: Option<String>,
+    pub epg_channel_id: Option<String>,
+}
+
+impl TrackOptions {
+    // The target of a track of `url`; refused while parental controls
+    // restrict the programme on the stream's guide channel
+    fn target(self, app: &AppHandle, key: &[u8; 32], url: String, http: HttpOptions) -> Result<Target, String> {
+        let guide = parental::guide_channel(app, self.epg_channel_id.as_deref(), self.channel_id.as_deref())?;
+        if let Some(guide) = &guide {
+            parental::enforce(app, guide)?;
+        }
+        let stream_id = self.channel_id.clone().unwrap_or_else(|| sign(key, &url));
+        Ok(Target {
+            url,
+            http,
+            stats: Some(stream_id),
+            provider: self
+                .provider_id
+                .or_else(|| self.channel_id.as_deref().and_then(provider_of).map(str::to_string)),
+            guide,
+            ..Default::default()
+        })
+    }
+}
+
+// Command handler returning the URL of a stream's subtitle track as
+// WebVTT, for the player's text tracks; `track` counts the stream's
+// subtitle tracks as probe_stream lists them, and only text ones convert
+#[tauri::command]
+pub fn get_subtitle_url(
+    app: AppHandle,
+    proxy: State<'_, StreamProxy>,
+    url: String,
+    http: Option<HttpOptions>,
+    track: usize,
+    options: Option<TrackOptions>,
+) -> Result<String, String> {
+    let (url, http) = stream_location(url, http)?;
+    let port = *proxy.port.get().ok_or("The stream proxy isn't running")?;
+    let target = Target {
+        subtitles: Some(track),
+        ..options.unwrap_or_default().target(&app, &proxy.key, url, http)?
+    };
+    Ok(encode(&proxy.key, port, &target))
+}
+
+// Command handler returning the URL of a teletext subtitle page of a
+// transport stream as WebVTT, decoded without ffmpeg; `page` as shown on
+// screen (888), else the first subtitle page the stream announces
+#[tauri::command]
+pub fn get_teletext_url(
+    app: AppHandle,
+    proxy: State<'_, StreamProxy>,
+    url: String,
+    http: Option<HttpOptions>,
+    page: Option<u16>,
+    options: Option<TrackOptions>,
+) -> Result<String, String> {
+    let (url, http) = stream_location(url, http)?;
+    let page = match page {
+        Some(page) => teletext::page_number(page).ok_or_else(|| format!("Invalid teletext page {}", page))?,
+        None => 0,
+    };
+    let port = *proxy.port.get().ok_or("The stream proxy isn't running")?;
+    let target = Target {
+        teletext: Some(page),
+        ..options.unwrap_or_default().target(&app, &proxy.key, url, http)?
+    };
+    Ok(encode(&proxy.key, port, &target))
+}
+
+// Command handler returning the URL of a closed caption channel of a
+// transport stream as WebVTT; `channel` 1 to 4 for CC1 to CC4, CC1 when
+// not given, or `service` 1 to 63 for that CEA-708 service instead.
+// probe_stream tells which video tracks carry captions
+#[tauri::command]
+pub fn get_captions_url(
+    app: AppHandle,
+    proxy: State<'_, StreamProxy>,
+    url: String,
+    http: Option<HttpOptions>,
+    channel: Option<u8>,
+    service: Option<u8>,
+    options: Option<TrackOptions>,
+) -> Result<String, String> {
+    let (url, http) = stream_location(url, http)?;
+    let channel = channel.unwrap_or(1);
+    if !(1..=4).contains(&channel) {
+        return Err(format!("Invalid caption channel CC{}", channel));
+    }
+    if let Some(service) = service.filter(|service| !(1..=63).contains(service)) {
+        return Err(format!("Invalid caption service {}", service));
+    }
+    let port = *proxy.port.get().ok_or("The stream proxy isn't running")?;
+    let target = Target {
+        captions: Some(channel),
+        caption_service: service,
+        ..options.unwrap_or_default().target(&app, &proxy.key, url, http)?
+    };
+    Ok(encode(&proxy.key, port, &target))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proxy_urls_are_signed() {
+        // Test that proxy URLs decode back to their target and altered ones are refused
+        let key = [7; 32];
+        let mut http = HttpOptions::default();
+        http.set_header("User-Agent", "VLC/3.0");
+        let target = Target {
+            url: "http://host/live/index.m3u8?token=a".to_string(),
+            http,
+            decrypt: true,
+            ..Default::default()
+        };
+        let url = encode(&key, 8080, &target);
+        assert!(url.starts_with("http://127.0.0.1:8080/"));
+        assert!(url.ends_with("/index.m3u8"));
+        let path = url.trim_start_matches("http://127.0.0.1:8080");
+        assert_eq!(decode(&key, path), Some((target, "index.m3u8")));
+        assert_eq!(decode(&[8; 32], path), None);
+        let other = Target {
+            url: "http://other/".to_string(),
+            ..Default::default()
+        };
+        let forged = encode(&key, 8080, &other);
+        let forged_payload = forged.split('/').nth(4).unwrap();
+        let mut parts: Vec<&str> = path.split('/').collect();
+        parts[2] = forged_payload;
+        assert_eq!(decode(&key, &parts.join("/")), None);
+        let signature = parts[1].to_string();
+        parts[2] = path.split('/').nth(2).unwrap();
+        let flipped = format!("{}{}", &signature[..31], if signature.ends_with('0') { '1' } else { '0' });
+        parts[1] = &flipped;
+        assert_eq!(decode(&key, &parts.join("/")), None);
+        parts[1] = &signature[..30];
+        assert_eq!(decode(&key, &parts.join("/")), None);
+
+        assert_eq!(
+            resolve_base("https://cdn/vod/", "video/1.m4s", Some("t=1")).as_deref(),
+            Some("https://cdn/vod/video/1.m4s?t=1")
+        );
+        assert_eq!(resolve_base("https://cdn/vod/en.mp4", "", None).as_deref(), Some("https://cdn/vod/en.mp4"));
+        assert_eq!(resolve_base("https://cdn/vod/", "../secret", None), None);
+    }
+}
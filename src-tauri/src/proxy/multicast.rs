@@ -0,0 +1,210 @@
+// UDP and RTP multicast (udp://@239.0.0.1:1234, rtp://@239.0.0.1:5000 or
+// udp://source@232.0.0.1:1234 for source-specific multicast), the way ISP
+// IPTV lineups are delivered. The proxy joins the group on the configured
+// interface and serves the MPEG-TS it receives over HTTP, leaving the
+// group when the player lets go of the response
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use bytes::Bytes;
+use reqwest::Body;
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+use tauri::State;
+use tokio::net::UdpSocket;
+use url::Url;
+
+use crate::storage;
+
+const MULTICAST_FILE: &str = "multicast.json";
+
+// VLC's default port
+const DEFAULT_PORT: u16 = 1234;
+
+// Room for bursts while the player reads slowly
+const RECEIVE_BUFFER: usize = 4 * 1024 * 1024;
+
+// The stream ends when the group stays silent this long
+const SILENCE: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MulticastSettings {
+    // Address of the interface groups are joined on, None to let the
+    // system route it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interface: Option<Ipv4Addr>,
+}
+
+pub struct Multicast {
+    path: PathBuf,
+    settings: Mutex<MulticastSettings>,
+}
+
+impl Multicast {
+    pub fn open(data_dir: &Path) -> Self {
+        let path = data_dir.join(MULTICAST_FILE);
+        let settings = storage::read_json(&path).unwrap_or_else(|e| {
+            log::error!("Failed to load multicast settings: {}", e);
+            MulticastSettings::default()
+        });
+        Self {
+            path,
+            settings: Mutex::new(settings),
+        }
+    }
+
+    pub fn get(&self) -> MulticastSettings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    pub fn set(&self, settings: MulticastSettings) -> Result<MulticastSettings, String> {
+        if settings.interface.is_some_and(|interface| interface.is_multicast() || interface.is_broadcast()) {
+            return Err("The interface must be a local address".to_string());
+        }
+        let mut current = self.settings.lock().unwrap();
+        storage::write_json(&self.path, &settings)?;
+        *current = settings.clone();
+        Ok(settings)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Group {
+    // The sender, for source-specific multicast
+    pub source: Option<Ipv4Addr>,
+    pub address: Ipv4Addr,
+    pub port: u16,
+}
+
+pub fn is_multicast(url: &str) -> bool {
+    let scheme = url.split_once("://").map_or("", |(scheme, _)| scheme);
+    scheme.eq_ignore_ascii_case("udp") || scheme.eq_ignore_ascii_case("rtp")
+}
+
+pub fn parse(url: &str) -> Result<Group, String> {
+    let invalid = || format!("Invalid multicast URL '{}'", url);
+    let parsed = Url::parse(url).map_err(|_| invalid())?;
+    let address: Ipv4Addr = parsed.host_str().ok_or_else(invalid)?.parse().map_err(|_| invalid())?;
+    if !address.is_multicast() {
+        return Err(format!("{} isn't a multicast group", address));
+    }
+    let source = match parsed.username() {
+        "" => None,
+        source => Some(source.parse().map_err(|_| invalid())?),
+    };
+    Ok(Group {
+        source,
+        address,
+        port: parsed.port().unwrap_or(DEFAULT_PORT),
+    })
+}
+
+// The MPEG-TS in a packet: the packet itself, or an RTP packet's payload
+fn payload(packet: &[u8]) -> &[u8] {
+    if packet.first() == Some(&0x47) || packet.len() < 12 || packet[0] >> 6 != 2 {
+        return packet;
+    }
+    let mut start = 12 + 4 * (packet[0] & 0x0f) as usize;
+    if packet[0] & 0x10 != 0 {
+        let Some(length) = packet.get(start + 2..start + 4) else {
+            return &[];
+        };
+        start += 4 + 4 * u16::from_be_bytes([length[0], length[1]]) as usize;
+    }
+    let padding = match packet[0] & 0x20 != 0 {
+        true => packet.last().copied().unwrap_or(0) as usize,
+        false => 0,
+    };
+    packet.get(start..packet.len().saturating_sub(padding)).unwrap_or(&[])
+}
+
+fn join(group: &Group, interface: Option<Ipv4Addr>) -> std::io::Result<UdpSocket> {
+    let interface = interface.unwrap_or(Ipv4Addr::UNSPECIFIED);
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    // Other viewers and apps may be on the same group
+    socket.set_reuse_address(true)?;
+    let _ = socket.set_recv_buffer_size(RECEIVE_BUFFER);
+    // Bound to the group so only its packets arrive; Windows can only bind
+    // to a local address
+    let bound = if cfg!(windows) { Ipv4Addr::UNSPECIFIED } else { group.address };
+    socket.bind(&SocketAddrV4::new(bound, group.port).into())?;
+    match group.source {
+        Some(source) => socket.join_ssm_v4(&source, &group.address, &interface)?,
+        None => socket.join_multicast_v4(&group.address, &interface)?,
+    }
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket.into())
+}
+
+// Join a group and stream what it carries; dropping the body leaves it
+pub fn open(url: &str, settings: &MulticastSettings) -> Result<Body, String> {
+    let group = parse(url)?;
+    let socket = join(&group, settings.interface).map_err(|e| format!("Failed to join {}: {}", group.address, e))?;
+    // The stream ends after its first error
+    let packets = futures_util::stream::unfold((Some(socket), vec![0; 65_536]), |(socket, mut buffer)| async move {
+        let socket = socket?;
+        match tokio::time::timeout(SILENCE, socket.recv(&mut buffer)).await {
+            Ok(Ok(length)) => {
+                let packet = Bytes::copy_from_slice(payload(&buffer[..length]));
+                Some((Ok(packet), (Some(socket), buffer)))
+            }
+            Ok(Err(e)) => Some((Err(e), (None, buffer))),
+            Err(_) => {
+                let silent = std::io::Error::new(std::io::ErrorKind::TimedOut, "No multicast data");
+                Some((Err(silent), (None, buffer)))
+            }
+        }
+    });
+    Ok(Body::wrap_stream(packets))
+}
+
+// Command handler returning the multicast settings
+#[tauri::command]
+pub fn get_multicast_settings(multicast: State<'_, Multicast>) -> MulticastSettings {
+    multicast.get()
+}
+
+// Command handler choosing the interface multicast groups are joined on
+#[tauri::command]
+pub fn set_multicast_settings(
+    multicast: State<'_, Multicast>,
+    settings: MulticastSettings,
+) -> Result<MulticastSettings, String> {
+    multicast.set(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multicast_groups() {
+        // Test that group URLs parse, with a source for SSM, and RTP headers come off the MPEG-TS
+        assert_eq!(
+            parse("udp://@239.1.2.3:5000").unwrap(),
+            Group {
+                source: None,
+                address: Ipv4Addr::new(239, 1, 2, 3),
+                port: 5000
+            }
+        );
+        let ssm = parse("rtp://10.0.0.1@232.1.1.1").unwrap();
+        assert_eq!((ssm.source, ssm.port), (Some(Ipv4Addr::new(10, 0, 0, 1)), DEFAULT_PORT));
+        assert!(parse("udp://@192.168.1.1:1234").is_err());
+        assert!(is_multicast("UDP://@239.1.2.3:1234") && !is_multicast("http://host/udp"));
+
+        let ts = [0x47, 0x00, 0x11, 0x10];
+        assert_eq!(payload(&ts), &ts);
+        let mut rtp = vec![0x80, 33, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0];
+        rtp.extend_from_slice(&ts);
+        assert_eq!(payload(&rtp), &ts);
+        // One CSRC, a one-word extension and two bytes of padding
+        let mut extended = vec![0xb1, 33, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 9, 9, 9, 9, 0, 0, 0, 1, 5, 5, 5, 5];
+        extended.extend_from_slice(&ts);
+        extended.extend_from_slice(&[0, 2]);
+        assert_eq!(payload(&extended), &ts);
+    }
+}
@@ -0,0 +1,353 @@
+// What a stream holds, as ffprobe sees it: the container, the video, audio
+// and subtitle tracks, and how the webview could play it. ffprobe is taken
+// from next to the configured ffmpeg and reads the stream with the same
+// input options a remux would
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tokio::process::Command;
+
+use super::ffmpeg;
+use super::transcode::Transcoding;
+use crate::de;
+use crate::http::{self, HttpOptions};
+use crate::playlist::{self, check_http_options, PlaylistLibrary, PlaylistStore};
+use crate::validate_string_length;
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(20);
+
+const MAX_INPUT_LENGTH: usize = 4096;
+
+// Read at most this much of the stream (bytes, and microseconds of it)
+const PROBE_SIZE: &str = "5000000";
+
+// Codecs and containers the webview plays as they are
+const VIDEO_CODECS: [&str; 4] = ["h264", "vp8", "vp9", "av1"];
+const AUDIO_CODECS: [&str; 5] = ["aac", "mp3", "opus", "vorbis", "flac"];
+const CONTAINERS: [&str; 6] = ["hls", "dash", "mov", "mp4", "webm", "mp3"];
+
+// Subtitles drawn as pictures, which get_subtitle_url can't make text of
+const BITMAP_SUBTITLES: [&str; 4] = ["dvb_subtitle", "hdmv_pgs_subtitle", "dvd_subtitle", "xsub"];
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VideoTrack {
+    pub index: u32,
+    pub codec: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    // Frames per second
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frame_rate: Option<f64>,
+    // Carries CEA-608/708 closed captions, for get_captions_url
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub closed_captions: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioTrack {
+    pub index: u32,
+    pub codec: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    pub channels: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample_rate: Option<u32>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub default: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubtitleTrack {
+    pub index: u32,
+    pub codec: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub default: bool,
+    // Can be served as WebVTT
+    pub text: bool,
+}
+
+// How the player should load the stream
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Playback {
+    #[default]
+    Direct,
+    // Through the proxy's remux, for the container or the audio codec
+    Remux,
+    // Through a transcode, for the video codec
+    Transcode,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamProbe {
+    pub url: String,
+    // ffprobe's format names, e.g. "mpegts" or "mov,mp4,m4a,3gp,3g2,mj2"
+    pub container: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bitrate: Option<u64>,
+    pub video: Vec<VideoTrack>,
+    pub audio: Vec<AudioTrack>,
+    pub subtitles: Vec<SubtitleTrack>,
+    pub playback: Playback,
+}
+
+// ffprobe's -show_format -show_streams JSON, the parts read
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Output {
+    streams: Vec<ProbedStream>,
+    format: Format,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Format {
+    format_name: String,
+    #[serde(deserialize_with = "de::string")]
+    duration: String,
+    #[serde(deserialize_with = "de::string")]
+    bit_rate: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ProbedStream {
+    index: u32,
+    codec_type: String,
+    codec_name: String,
+    profile: Option<String>,
+    width: u32,
+    height: u32,
+    avg_frame_rate: String,
+    r_frame_rate: String,
+    channels: u32,
+    #[serde(deserialize_with = "de::string")]
+    sample_rate: String,
+    closed_captions: u8,
+    disposition: Disposition,
+    tags: Tags,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Disposition {
+    default: u8,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Tags {
+    language: Option<String>,
+    title: Option<String>,
+}
+
+// "30000/1001" -> 29.97; ffprobe reports "0/0" when it doesn't know
+fn frame_rate(rate: &str) -> Option<f64> {
+    let (numerator, denominator) = rate.split_once('/')?;
+    let (numerator, denominator): (f64, f64) = (numerator.parse().ok()?, denominator.parse().ok()?);
+    (numerator > 0.0 && denominator > 0.0).then(|| (numerator / denominator * 100.0).round() / 100.0)
+}
+
+// Languages ffprobe leaves undetermined are left out
+fn language(tags: &Tags) -> Option<String> {
+    tags.language.clone().filter(|language| !language.is_empty() && language != "und")
+}
+
+fn playback(probe: &StreamProbe) -> Playback {
+    if probe.video.iter().any(|track| !VIDEO_CODECS.contains(&track.codec.as_str())) {
+        return Playback::Transcode;
+    }
+    let container = probe.container.split(',').any(|name| CONTAINERS.contains(&name));
+    let audio = probe.audio.iter().all(|track| AUDIO_CODECS.contains(&track.codec.as_str()));
+    match container && audio {
+        true => Playback::Direct,
+        false => Playback::Remux,
+    }
+}
+
+fn parse(url: &str, json: &str) -> Result<StreamProbe, String> {
+    let output: Output = serde_json::from_str(json).map_err(|e| format!("Invalid ffprobe output: {}", e))?;
+    let mut probe = StreamProbe {
+        url: url.to_string(),
+        container: output.format.format_name,
+        duration: output.format.duration.parse().ok().filter(|duration: &f64| *duration > 0.0),
+        bitrate: output.format.bit_rate.parse().ok().filter(|bitrate| *bitrate > 0),
+        ..Default::default()
+    };
+    for stream in output.streams {
+        let language = language(&stream.tags);
+        let default = stream.disposition.default == 1;
+        match stream.codec_type.as_str() {
+            "video" => probe.video.push(VideoTrack {
+                index: stream.index,
+                codec: stream.codec_name,
+                profile: stream.profile,
+                width: stream.width,
+                height: stream.height,
+                frame_rate: frame_rate(&stream.avg_frame_rate).or_else(|| frame_rate(&stream.r_frame_rate)),
+                closed_captions: stream.closed_captions != 0,
+            }),
+            "audio" => probe.audio.push(AudioTrack {
+                index: stream.index,
+                codec: stream.codec_name,
+                language,
+                title: stream.tags.title,
+                channels: stream.channels,
+                sample_rate: stream.sample_rate.parse().ok(),
+                default,
+            }),
+            "subtitle" => probe.subtitles.push(SubtitleTrack {
+                index: stream.index,
+                text: !BITMAP_SUBTITLES.contains(&stream.codec_name.as_str()),
+                codec: stream.codec_name,
+                language,
+                title: stream.tags.title,
+                default,
+            }),
+            _ => {}
+        }
+    }
+    probe.playback = playback(&probe);
+    Ok(probe)
+}
+
+// ffprobe from the same place as ffmpeg: "/opt/ffmpeg/bin/ffmpeg.exe" ->
+// "/opt/ffmpeg/bin/ffprobe.exe", anything else named -> "ffprobe"
+fn ffprobe(ffmpeg: &str) -> String {
+    let name = Path::new(ffmpeg).file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    match name.contains("ffmpeg") {
+        true => format!("{}{}", &ffmpeg[..ffmpeg.len() - name.len()], name.replacen("ffmpeg", "ffprobe", 1)),
+        false => "ffprobe".to_string(),
+    }
+}
+
+// A channel's URL and HTTP options, from the given playlist or else any
+// configured one that's loaded
+pub(super) fn find_channel(
+    app: &AppHandle,
+    channel_id: &str,
+    playlist_id: Option<&str>,
+) -> Result<(String, HttpOptions), String> {
+    let ids = match playlist_id {
+        Some(id) => vec![id.to_string()],
+        None => app.state::<PlaylistLibrary>().list().into_iter().map(|config| config.id).collect(),
+    };
+    let store = app.state::<PlaylistStore>();
+    ids.iter()
+        .filter_map(|id| store.get(id))
+        .find_map(|entries| {
+            let entry = entries.iter().find(|entry| entry.channel_id == channel_id)?;
+            Some((entry.url.clone(), entry.http.clone()))
+        })
+        .ok_or_else(|| format!("Channel '{}' isn't in a loaded playlist", channel_id))
+}
+
+// Command handler probing a stream URL, or the stream of a channel by id;
+// `http` adds to the channel's HTTP options
+#[tauri::command]
+pub async fn probe_stream(
+    app: AppHandle,
+    url_or_channel_id: String,
+    playlist_id: Option<String>,
+    http: Option<HttpOptions>,
+) -> Result<StreamProbe, String> {
+    validate_string_length(&url_or_channel_id, MAX_INPUT_LENGTH)?;
+    let input = url_or_channel_id.trim();
+    let (url, mut options) = match input.contains("://") {
+        true => {
+            let (url, suffix) = http::split_url_options(input);
+            (url, suffix.unwrap_or_default())
+        }
+        false => find_channel(&app, input, playlist_id.as_deref())?,
+    };
+    options.extend(http.unwrap_or_default());
+    check_http_options(&options)?;
+    let options = playlist::stream_options(&app, &url, &options);
+
+    let program = ffprobe(&app.state::<Transcoding>().get().ffmpeg);
+    let mut arguments: Vec<String> = ["-hide_banner", "-v", "error", "-print_format", "json"]
+        .iter()
+        .chain(&["-show_format", "-show_streams", "-probesize", PROBE_SIZE, "-analyzeduration", PROBE_SIZE])
+        .map(|argument| argument.to_string())
+        .collect();
+    arguments.extend(ffmpeg::input(&url, &options));
+    let output = Command::new(&program)
+        .args(&arguments)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::time::timeout(PROBE_TIMEOUT, output)
+        .await
+        .map_err(|_| format!("{} timed out", program))?
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(format!("Failed to probe the stream: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    parse(&url, &String::from_utf8_lossy(&output.stdout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ffprobe_output() {
+        // Test that tracks are read from ffprobe's JSON and the playback mode follows the codecs
+        let json = r#"{
+            "streams": [
+                {"index": 0, "codec_type": "video", "codec_name": "h264", "profile": "High",
+                 "width": 1920, "height": 1080, "avg_frame_rate": "0/0", "r_frame_rate": "30000/1001",
+                 "closed_captions": 1},
+                {"index": 1, "codec_type": "audio", "codec_name": "mp2", "channels": 2, "sample_rate": "48000",
+                 "disposition": {"default": 1}, "tags": {"language": "eng"}},
+                {"index": 2, "codec_type": "audio", "codec_name": "ac3", "channels": 6, "tags": {"language": "und"}},
+                {"index": 3, "codec_type": "subtitle", "codec_name": "dvb_subtitle", "tags": {"language": "tur"}},
+                {"index": 4, "codec_type": "subtitle", "codec_name": "dvb_teletext", "tags": {"language": "ger"}},
+                {"index": 5, "codec_type": "data", "codec_name": "scte_35"}
+            ],
+            "format": {"format_name": "mpegts", "bit_rate": "5000000"}
+        }"#;
+        let probe = parse("http://host/1.ts", json).unwrap();
+        assert_eq!(probe.container, "mpegts");
+        assert_eq!((probe.duration, probe.bitrate), (None, Some(5_000_000)));
+        let video = &probe.video[0];
+        assert_eq!((video.width, video.height, video.frame_rate), (1920, 1080, Some(29.97)));
+        assert!(video.closed_captions);
+        let audio: Vec<(&str, Option<&str>, u32, bool)> = probe
+            .audio
+            .iter()
+            .map(|track| (track.codec.as_str(), track.language.as_deref(), track.channels, track.default))
+            .collect();
+        assert_eq!(audio, vec![("mp2", Some("eng"), 2, true), ("ac3", None, 6, false)]);
+        assert_eq!(probe.subtitles[0].language.as_deref(), Some("tur"));
+        assert_eq!(probe.subtitles.iter().map(|track| track.text).collect::<Vec<_>>(), vec![false, true]);
+        assert_eq!(probe.playback, Playback::Remux);
+
+        let hevc = r#"{"streams": [{"codec_type": "video", "codec_name": "hevc"}], "format": {"format_name": "hls"}}"#;
+        assert_eq!(parse("http://host/1.m3u8", hevc).unwrap().playback, Playback::Transcode);
+        let mp4 = r#"{"streams": [{"codec_type": "audio", "codec_name": "aac"}],
+            "format": {"format_name": "mov,mp4"}}"#;
+        assert_eq!(parse("http://host/1.mp4", mp4).unwrap().playback, Playback::Direct);
+
+        assert_eq!(ffprobe("/opt/ffmpeg/bin/ffmpeg.exe"), "/opt/ffmpeg/bin/ffprobe.exe");
+        assert_eq!(ffprobe("ffmpeg"), "ffprobe");
+        assert_eq!(ffprobe("/usr/bin/avconv"), "ffprobe");
+    }
+}
@@ -0,0 +1,343 @@
+// Named transcode profiles, chosen per provider or per channel. A profile
+// becomes the codec arguments of an ffmpeg transcode, used by the stream
+// proxy for channels it's chosen for and kept with their recordings. A
+// channel's own choice wins over its provider's
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use super::hwaccel;
+use crate::playlist::content_hash;
+use crate::storage;
+use crate::{unix_now, validate_string_length};
+
+const PROFILES_FILE: &str = "transcode_profiles.json";
+
+const MAX_PROFILES: usize = 50;
+const MAX_NAME_LENGTH: usize = 100;
+const MAX_ID_LENGTH: usize = 500;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VideoCodec {
+    Copy,
+    #[default]
+    H264,
+    H265,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioHandling {
+    Copy,
+    #[default]
+    Aac,
+    // Drop the audio
+    None,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranscodeProfile {
+    // Empty for a new profile
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub video: VideoCodec,
+    // kbit/s; None keeps the encoder's default quality
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub video_bitrate: Option<u32>,
+    // Scaled down to this height, keeping the aspect ratio
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_height: Option<u32>,
+    // Hardware encoder (see hwaccel), None to encode in software
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hw_accel: Option<String>,
+    #[serde(default)]
+    pub audio: AudioHandling,
+    // kbit/s of AAC audio
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_bitrate: Option<u32>,
+}
+
+impl TranscodeProfile {
+    // Codec arguments of a transcode with the profile
+    pub fn arguments(&self) -> Vec<String> {
+        let mut arguments: Vec<String> = Vec::new();
+        let mut filters: Vec<String> = Vec::new();
+        let codec = match self.video {
+            VideoCodec::Copy => None,
+            VideoCodec::H264 => Some("h264"),
+            VideoCodec::H265 => Some("hevc"),
+        };
+        match (codec, self.hw_accel.as_deref()) {
+            (None, _) => arguments.extend(["-c:v".to_string(), "copy".to_string()]),
+            (Some(codec), accelerator) => {
+                if let Some(height) = self.max_height {
+                    filters.push(format!("scale=-2:'min({},ih)'", height));
+                }
+                let encoder = match accelerator {
+                    Some("vaapi") => {
+                        arguments.extend(["-vaapi_device", "/dev/dri/renderD128"].map(str::to_string));
+                        filters.push("format=nv12,hwupload".to_string());
+                        format!("{}_vaapi", codec)
+                    }
+                    Some(accelerator) => format!("{}_{}", codec, accelerator),
+                    None if codec == "h264" => "libx264".to_string(),
+                    None => "libx265".to_string(),
+                };
+                if !filters.is_empty() {
+                    arguments.extend(["-vf".to_string(), filters.join(",")]);
+                }
+                let software = accelerator.is_none();
+                arguments.extend(["-c:v".to_string(), encoder]);
+                if software {
+                    arguments.extend(["-preset", "veryfast"].map(str::to_string));
+                }
+                match self.video_bitrate {
+                    Some(bitrate) => arguments.extend(["-b:v".to_string(), format!("{}k", bitrate)]),
+                    None if software => arguments.extend(["-crf", "23"].map(str::to_string)),
+                    None => {}
+                }
+            }
+        }
+        match self.audio {
+            AudioHandling::Copy => arguments.extend(["-c:a", "copy"].map(str::to_string)),
+            AudioHandling::Aac => {
+                let bitrate = format!("{}k", self.audio_bitrate.unwrap_or(160));
+                arguments.extend(["-c:a".to_string(), "aac".to_string(), "-b:a".to_string(), bitrate]);
+            }
+            AudioHandling::None => arguments.push("-an".to_string()),
+        }
+        arguments
+    }
+}
+
+fn check_range(value: Option<u32>, range: std::ops::RangeInclusive<u32>, what: &str) -> Result<(), String> {
+    match value {
+        Some(value) if !range.contains(&value) => Err(format!(
+            "{} must be between {} and {}",
+            what,
+            range.start(),
+            range.end()
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn check_profile(profile: TranscodeProfile) -> Result<TranscodeProfile, String> {
+    let profile = TranscodeProfile {
+        name: profile.name.trim().to_string(),
+        hw_accel: profile.hw_accel.map(|name| name.trim().to_ascii_lowercase()).filter(|name| !name.is_empty()),
+        ..profile
+    };
+    if profile.name.is_empty() {
+        return Err("A profile name is required".to_string());
+    }
+    validate_string_length(&profile.name, MAX_NAME_LENGTH)?;
+    check_range(profile.video_bitrate, 100..=100_000, "The video bitrate")?;
+    check_range(profile.max_height, 144..=4320, "The maximum height")?;
+    check_range(profile.audio_bitrate, 32..=512, "The audio bitrate")?;
+    if let Some(name) = profile.hw_accel.as_deref().filter(|name| !hwaccel::is_accelerator(name)) {
+        return Err(format!("Unknown hardware encoder '{}'", name));
+    }
+    Ok(profile)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProfileScope {
+    Provider,
+    Channel,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ProfilesFile {
+    pub profiles: Vec<TranscodeProfile>,
+    // Provider id -> profile id
+    pub providers: BTreeMap<String, String>,
+    // Channel id -> profile id
+    pub channels: BTreeMap<String, String>,
+}
+
+pub struct TranscodeProfiles {
+    path: PathBuf,
+    file: Mutex<ProfilesFile>,
+}
+
+impl TranscodeProfiles {
+    pub fn open(data_dir: &Path) -> Self {
+        let path = data_dir.join(PROFILES_FILE);
+        let file = storage::read_json(&path).unwrap_or_else(|e| {
+            log::error!("Failed to load transcode profiles: {}", e);
+            ProfilesFile::default()
+        });
+        Self {
+            path,
+            file: Mutex::new(file),
+        }
+    }
+
+    fn update<T>(&self, change: impl FnOnce(&mut ProfilesFile) -> Result<T, String>) -> Result<T, String> {
+        let mut file = self.file.lock().unwrap();
+        let mut updated = file.clone();
+        let result = change(&mut updated)?;
+        storage::write_json(&self.path, &updated)?;
+        *file = updated;
+        Ok(result)
+    }
+
+    pub fn list(&self) -> ProfilesFile {
+        self.file.lock().unwrap().clone()
+    }
+
+    pub fn get(&self, id: &str) -> Option<TranscodeProfile> {
+        self.file.lock().unwrap().profiles.iter().find(|profile| profile.id == id).cloned()
+    }
+
+    // The profile chosen for a channel, or else for its provider
+    pub fn resolve(&self, provider_id: Option<&str>, channel_id: Option<&str>) -> Option<TranscodeProfile> {
+        let file = self.file.lock().unwrap();
+        let chosen = channel_id
+            .and_then(|id| file.channels.get(id))
+            .or_else(|| provider_id.and_then(|id| file.providers.get(id)))?;
+        file.profiles.iter().find(|profile| &profile.id == chosen).cloned()
+    }
+
+    // Add a profile, or replace the one with its id
+    pub fn save(&self, profile: TranscodeProfile) -> Result<TranscodeProfile, String> {
+        let mut profile = check_profile(profile)?;
+        self.update(|file| {
+            let count = file.profiles.len();
+            match file.profiles.iter_mut().find(|existing| existing.id == profile.id) {
+                Some(existing) => *existing = profile.clone(),
+                None if !profile.id.is_empty() => return Err(format!("Unknown profile '{}'", profile.id)),
+                None if count >= MAX_PROFILES => {
+                    return Err(format!("At most {} profiles can be kept", MAX_PROFILES))
+                }
+                None => {
+                    profile.id = content_hash(format!("{}|{}", profile.name, unix_now()).as_bytes());
+                    file.profiles.push(profile.clone());
+                }
+            }
+            Ok(profile)
+        })
+    }
+
+    // Remove a profile and the choices of it
+    pub fn remove(&self, id: &str) -> Result<bool, String> {
+        self.update(|file| {
+            let before = file.profiles.len();
+            file.profiles.retain(|profile| profile.id != id);
+            file.providers.retain(|_, profile| profile != id);
+            file.channels.retain(|_, profile| profile != id);
+            Ok(file.profiles.len() != before)
+        })
+    }
+
+    // Choose a profile for a provider or channel, or clear the choice
+    pub fn assign(&self, scope: ProfileScope, id: &str, profile_id: Option<&str>) -> Result<(), String> {
+        validate_string_length(id, MAX_ID_LENGTH)?;
+        self.update(|file| {
+            if profile_id.is_some_and(|profile| !file.profiles.iter().any(|existing| existing.id == profile)) {
+                return Err(format!("Unknown profile '{}'", profile_id.unwrap_or_default()));
+            }
+            let choices = match scope {
+                ProfileScope::Provider => &mut file.providers,
+                ProfileScope::Channel => &mut file.channels,
+            };
+            match profile_id {
+                Some(profile) => choices.insert(id.to_string(), profile.to_string()),
+                None => choices.remove(id),
+            };
+            Ok(())
+        })
+    }
+}
+
+// Command handler listing the transcode profiles and where they're chosen
+#[tauri::command]
+pub fn list_transcode_profiles(profiles: State<'_, TranscodeProfiles>) -> ProfilesFile {
+    profiles.list()
+}
+
+// Command handler adding a transcode profile (empty id) or updating one
+#[tauri::command]
+pub fn save_transcode_profile(
+    profiles: State<'_, TranscodeProfiles>,
+    profile: TranscodeProfile,
+) -> Result<TranscodeProfile, String> {
+    profiles.save(profile)
+}
+
+// Command handler deleting a transcode profile
+#[tauri::command]
+pub fn delete_transcode_profile(profiles: State<'_, TranscodeProfiles>, id: String) -> Result<bool, String> {
+    profiles.remove(id.trim())
+}
+
+// Command handler choosing the transcode profile of a provider or channel;
+// no profile clears the choice
+#[tauri::command]
+pub fn set_transcode_profile_choice(
+    profiles: State<'_, TranscodeProfiles>,
+    scope: ProfileScope,
+    id: String,
+    profile_id: Option<String>,
+) -> Result<(), String> {
+    profiles.assign(scope, id.trim(), profile_id.as_deref().map(str::trim))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn test_profiles_chosen_and_turned_into_arguments() {
+        // Test that profiles build ffmpeg arguments and channel choices win over provider ones
+        let profiles = TranscodeProfiles::open(&temp_dir("transcode-profiles"));
+        let mobile = profiles
+            .save(TranscodeProfile {
+                name: " Mobile ".to_string(),
+                video_bitrate: Some(1500),
+                max_height: Some(480),
+                hw_accel: Some("NVENC".to_string()),
+                audio_bitrate: Some(96),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(
+            mobile.arguments(),
+            ["-vf", "scale=-2:'min(480,ih)'", "-c:v", "h264_nvenc", "-b:v", "1500k", "-c:a", "aac", "-b:a", "96k"]
+        );
+        let audio = profiles
+            .save(TranscodeProfile {
+                name: "Audio fix".to_string(),
+                video: VideoCodec::Copy,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(audio.arguments(), ["-c:v", "copy", "-c:a", "aac", "-b:a", "160k"]);
+        let bad = TranscodeProfile {
+            name: "Bad".to_string(),
+            hw_accel: Some("cuda".to_string()),
+            ..Default::default()
+        };
+        assert!(profiles.save(bad).is_err());
+
+        profiles.assign(ProfileScope::Provider, "p1", Some(&audio.id)).unwrap();
+        profiles.assign(ProfileScope::Channel, "c1", Some(&mobile.id)).unwrap();
+        assert!(profiles.assign(ProfileScope::Channel, "c2", Some("missing")).is_err());
+        assert_eq!(profiles.resolve(Some("p1"), Some("c1")), Some(mobile.clone()));
+        assert_eq!(profiles.resolve(Some("p1"), Some("c2")), Some(audio.clone()));
+        assert!(profiles.remove(&mobile.id).unwrap());
+        assert_eq!(profiles.resolve(Some("p1"), Some("c1")), Some(audio));
+        assert_eq!(TranscodeProfiles::open(profiles.path.parent().unwrap()).list(), profiles.list());
+    }
+}
@@ -0,0 +1,155 @@
+// Stream quality pinned per channel, for channels whose top variant
+// stutters: the proxy serves a pinned channel's HLS master with only the
+// variant the pin allows (or, streaming adaptively, only the variants it
+// allows to pick from)
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use super::adaptive::Variant;
+use crate::storage;
+use crate::validate_string_length;
+
+const QUALITY_FILE: &str = "stream_quality.json";
+
+const MAX_PINS: usize = 10_000;
+const MAX_ID_LENGTH: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum QualityPin {
+    // A variant by its position in the master playlist, from 0
+    Variant(usize),
+    // Variants up to this many lines tall
+    MaxHeight(u32),
+}
+
+// The variants a pin allows, best first. A height pin keeps variants
+// without a RESOLUTION only when none has one, and the shortest variant
+// when none is short enough; a missing variant allows them all
+pub fn allowed(variants: Vec<Variant>, pin: Option<QualityPin>) -> Vec<Variant> {
+    let mut allowed = match pin {
+        Some(QualityPin::Variant(index)) if index < variants.len() => vec![variants[index].clone()],
+        Some(QualityPin::MaxHeight(height)) if variants.iter().any(|variant| variant.height.is_some()) => {
+            let fitting: Vec<Variant> = variants
+                .iter()
+                .filter(|variant| variant.height.is_some_and(|tall| tall <= height))
+                .cloned()
+                .collect();
+            if fitting.is_empty() {
+                let shortest = variants.into_iter().filter(|variant| variant.height.is_some());
+                shortest.min_by_key(|variant| variant.height).into_iter().collect()
+            } else {
+                fitting
+            }
+        }
+        _ => variants,
+    };
+    allowed.sort_by_key(|variant| std::cmp::Reverse(variant.bandwidth));
+    allowed
+}
+
+pub struct StreamQualities {
+    path: PathBuf,
+    pins: Mutex<BTreeMap<String, QualityPin>>,
+}
+
+impl StreamQualities {
+    pub fn open(data_dir: &Path) -> Self {
+        let path = data_dir.join(QUALITY_FILE);
+        let pins = storage::read_json(&path).unwrap_or_else(|e| {
+            log::error!("Failed to load stream quality pins: {}", e);
+            BTreeMap::new()
+        });
+        Self {
+            path,
+            pins: Mutex::new(pins),
+        }
+    }
+
+    pub fn get(&self, channel_id: &str) -> Option<QualityPin> {
+        self.pins.lock().unwrap().get(channel_id).copied()
+    }
+
+    pub fn list(&self) -> BTreeMap<String, QualityPin> {
+        self.pins.lock().unwrap().clone()
+    }
+
+    // Pin a channel's quality, or unpin it
+    pub fn set(&self, channel_id: &str, pin: Option<QualityPin>) -> Result<(), String> {
+        validate_string_length(channel_id, MAX_ID_LENGTH)?;
+        if channel_id.is_empty() {
+            return Err("A channel id is required".to_string());
+        }
+        if pin == Some(QualityPin::MaxHeight(0)) {
+            return Err("The height must be more than 0".to_string());
+        }
+        let mut pins = self.pins.lock().unwrap();
+        let mut updated = pins.clone();
+        match pin {
+            Some(pin) => updated.insert(channel_id.to_string(), pin),
+            None => updated.remove(channel_id),
+        };
+        if updated.len() > MAX_PINS {
+            return Err(format!("At most {} channels can be pinned", MAX_PINS));
+        }
+        storage::write_json(&self.path, &updated)?;
+        *pins = updated;
+        Ok(())
+    }
+}
+
+// Command handler listing the pinned channels
+#[tauri::command]
+pub fn get_stream_qualities(qualities: State<'_, StreamQualities>) -> BTreeMap<String, QualityPin> {
+    qualities.list()
+}
+
+// Command handler pinning a channel to a variant or a maximum height; no
+// quality unpins it
+#[tauri::command]
+pub fn set_stream_quality(
+    qualities: State<'_, StreamQualities>,
+    channel_id: String,
+    quality: Option<QualityPin>,
+) -> Result<(), String> {
+    qualities.set(channel_id.trim(), quality)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn test_quality_pins() {
+        // Test that pins persist and narrow the variants to the ones allowed
+        let variant = |bandwidth: u64, height: Option<u32>| Variant {
+            url: format!("http://host/{}.m3u8", bandwidth),
+            bandwidth,
+            height,
+            info: String::new(),
+        };
+        let variants = vec![variant(6000, Some(1080)), variant(3000, Some(720)), variant(1000, Some(360))];
+        let bandwidths = |pin| -> Vec<u64> {
+            allowed(variants.clone(), pin).iter().map(|variant| variant.bandwidth).collect()
+        };
+        assert_eq!(bandwidths(None), vec![6000, 3000, 1000]);
+        assert_eq!(bandwidths(Some(QualityPin::MaxHeight(720))), vec![3000, 1000]);
+        assert_eq!(bandwidths(Some(QualityPin::MaxHeight(240))), vec![1000]);
+        assert_eq!(bandwidths(Some(QualityPin::Variant(1))), vec![3000]);
+        assert_eq!(bandwidths(Some(QualityPin::Variant(9))), vec![6000, 3000, 1000]);
+
+        let dir = temp_dir("stream_quality");
+        let qualities = StreamQualities::open(&dir);
+        qualities.set("p:one", Some(QualityPin::MaxHeight(720))).unwrap();
+        assert!(qualities.set("p:two", Some(QualityPin::MaxHeight(0))).is_err());
+        let reopened = StreamQualities::open(&dir);
+        assert_eq!(reopened.get("p:one"), Some(QualityPin::MaxHeight(720)));
+        reopened.set("p:one", None).unwrap();
+        assert_eq!(StreamQualities::open(&dir).list(), BTreeMap::new());
+    }
+}
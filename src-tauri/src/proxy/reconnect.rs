@@ -0,0 +1,185 @@
+// Streams relayed through the proxy reconnect when the upstream connection
+// drops: the request is made again with a growing delay between attempts,
+// resuming at the byte reached when the server takes ranges, and the
+// player keeps reading the same response. A live stream (no length) ending
+// counts as a drop. The app hears "stream-reconnecting" on every attempt
+// and "stream-recovered" once data flows again
+use std::pin::Pin;
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
+use reqwest::header::{ACCEPT_RANGES, CONTENT_LENGTH, RANGE};
+use reqwest::{Response, StatusCode};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+use crate::http::{self, HttpOptions};
+use crate::playlist;
+
+const FIRST_DELAY: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(8);
+const MAX_ATTEMPTS: u32 = 8;
+
+type Chunks = Pin<Box<dyn Stream<Item = reqwest::Result<Bytes>> + Send>>;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Reconnecting {
+    url: String,
+    attempt: u32,
+    delay_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Recovered {
+    url: String,
+    attempts: u32,
+}
+
+// Delay before an attempt, from 1
+pub fn delay(attempt: u32) -> Duration {
+    FIRST_DELAY.saturating_mul(1 << attempt.saturating_sub(1).min(16)).min(MAX_DELAY)
+}
+
+// The first byte a request asks for: 0 without a Range, N for "bytes=N-";
+// None for other ranges, which aren't resumed
+pub fn range_start(range: Option<&str>) -> Option<u64> {
+    let Some(range) = range else {
+        return Some(0);
+    };
+    let (start, end) = range.trim().strip_prefix("bytes=")?.split_once('-')?;
+    end.is_empty().then(|| start.trim().parse().ok()).flatten()
+}
+
+struct Relayed {
+    app: AppHandle,
+    url: String,
+    http: HttpOptions,
+    chunks: Chunks,
+    // Where the response started and how much has been relayed since
+    start: u64,
+    received: u64,
+    // The upstream length, None for live streams
+    length: Option<u64>,
+    ranges: bool,
+    failed: bool,
+}
+
+impl Relayed {
+    // Request the stream again from where it got to
+    async fn reconnect(&mut self) -> Result<(), String> {
+        let resume = self.start + self.received;
+        if self.length.is_some() && !self.ranges {
+            return Err("The server can't resume the stream".to_string());
+        }
+        for attempt in 1..=MAX_ATTEMPTS {
+            let delay = delay(attempt);
+            let reconnecting = Reconnecting {
+                url: self.url.clone(),
+                attempt,
+                delay_ms: delay.as_millis() as u64,
+            };
+            let _ = self.app.emit("stream-reconnecting", reconnecting);
+            tokio::time::sleep(delay).await;
+            let options = playlist::stream_options(&self.app, &self.url, &self.http);
+            let mut request = http::get(&self.url, Some(&options));
+            let ranged = self.length.is_some() && resume > 0;
+            if ranged {
+                request = request.header(RANGE, format!("bytes={}-", resume));
+            }
+            let response = match request.send().await {
+                Ok(response) => response,
+                Err(e) => {
+                    log::debug!("Reconnecting to {} failed: {}", self.url, e);
+                    continue;
+                }
+            };
+            let expected = if ranged { StatusCode::PARTIAL_CONTENT } else { StatusCode::OK };
+            if response.status() != expected {
+                log::debug!("Reconnecting to {} got {}", self.url, response.status());
+                continue;
+            }
+            self.chunks = Box::pin(response.bytes_stream());
+            let recovered = Recovered {
+                url: self.url.clone(),
+                attempts: attempt,
+            };
+            let _ = self.app.emit("stream-recovered", recovered);
+            log::info!("Reconnected to {} after {} attempt(s)", self.url, attempt);
+            return Ok(());
+        }
+        Err(format!("Gave up reconnecting after {} attempts", MAX_ATTEMPTS))
+    }
+
+    async fn next(&mut self) -> Option<Result<Bytes, std::io::Error>> {
+        if self.failed {
+            return None;
+        }
+        loop {
+            match self.chunks.next().await {
+                Some(Ok(chunk)) => {
+                    self.received += chunk.len() as u64;
+                    return Some(Ok(chunk));
+                }
+                Some(Err(e)) => log::info!("Stream {} dropped: {}", self.url, e),
+                None if self.length.is_some_and(|length| self.received >= length) => return None,
+                None => log::info!("Stream {} ended early", self.url),
+            }
+            if let Err(e) = self.reconnect().await {
+                self.failed = true;
+                return Some(Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, e)));
+            }
+        }
+    }
+}
+
+// The body of an upstream response that reconnects when it drops; `start`
+// is the byte the response begins at
+pub fn relay(
+    app: AppHandle,
+    url: &str,
+    http: &HttpOptions,
+    response: Response,
+    start: u64,
+) -> impl Stream<Item = Result<Bytes, std::io::Error>> + Send {
+    let headers = response.headers();
+    let ranges = headers
+        .get(ACCEPT_RANGES)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("bytes"));
+    let length = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse().ok());
+    let relayed = Relayed {
+        app,
+        url: url.to_string(),
+        http: http.clone(),
+        chunks: Box::pin(response.bytes_stream()),
+        start,
+        received: 0,
+        length,
+        ranges,
+        failed: false,
+    };
+    futures_util::stream::unfold(relayed, |mut relayed| async move {
+        let chunk = relayed.next().await?;
+        Some((chunk, relayed))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconnect_backoff() {
+        // Test that the delay doubles up to its cap and resumed ranges start where the request did
+        let delays: Vec<u64> = (1..=7).map(|attempt| delay(attempt).as_millis() as u64).collect();
+        assert_eq!(delays, vec![500, 1000, 2000, 4000, 8000, 8000, 8000]);
+        assert_eq!(range_start(Some("bytes=1000-")), Some(1000));
+        assert_eq!(range_start(Some("bytes=0-99")), None);
+        assert_eq!(range_start(None), Some(0));
+    }
+}
@@ -0,0 +1,160 @@
+// Stream URLs with short-lived tokens. A proxy URL can say where its stream
+// came from; when the upstream answers as though the token expired, the
+// URL is asked for again there (an Xtream account logs in again, a Stalker
+// portal hands out a new link, a playlist is fetched again) and the request
+// is made with the new one. Later requests for the stream use the new URL
+// until that runs out in turn
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use super::StreamProxy;
+use crate::validate_string_length;
+use crate::xtream::StreamKind;
+use crate::{playlist, stalker, xtream};
+
+// A stream isn't renewed more often than this, so a URL that keeps being
+// refused doesn't turn every retry of the player into a login
+const MIN_INTERVAL: Duration = Duration::from_secs(10);
+
+// Streams remembered before the oldest renewal is dropped
+const MAX_STREAMS: usize = 64;
+
+const MAX_FIELD_LENGTH: usize = 2048;
+
+// Where a stream's URL is asked for again
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Renewal {
+    #[serde(rename_all = "camelCase")]
+    Xtream {
+        provider_id: String,
+        stream_kind: StreamKind,
+        stream_id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        extension: Option<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Stalker { provider_id: String, cmd: String },
+    #[serde(rename_all = "camelCase")]
+    Playlist { playlist_id: String, channel_id: String },
+}
+
+impl Renewal {
+    pub fn check(&self) -> Result<(), String> {
+        let fields: Vec<&String> = match self {
+            Renewal::Xtream {
+                provider_id,
+                stream_id,
+                extension,
+                ..
+            } => [provider_id, stream_id].into_iter().chain(extension).collect(),
+            Renewal::Stalker { provider_id, cmd } => vec![provider_id, cmd],
+            Renewal::Playlist {
+                playlist_id,
+                channel_id,
+            } => vec![playlist_id, channel_id],
+        };
+        fields.into_iter().try_for_each(|field| validate_string_length(field, MAX_FIELD_LENGTH))
+    }
+}
+
+// Whether a response looks like an expired token
+pub fn expired(status: StatusCode) -> bool {
+    matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::GONE)
+}
+
+// The URLs streams were renewed to
+#[derive(Default)]
+pub struct Renewed {
+    urls: Mutex<HashMap<Renewal, (String, Instant)>>,
+}
+
+impl Renewed {
+    pub fn get(&self, renewal: &Renewal) -> Option<String> {
+        self.urls.lock().unwrap().get(renewal).map(|(url, _)| url.clone())
+    }
+
+    // Whether a stream may be renewed now
+    fn due(&self, renewal: &Renewal) -> bool {
+        let urls = self.urls.lock().unwrap();
+        urls.get(renewal).map_or(true, |(_, at)| at.elapsed() >= MIN_INTERVAL)
+    }
+
+    fn insert(&self, renewal: &Renewal, url: String) {
+        let mut urls = self.urls.lock().unwrap();
+        if urls.len() >= MAX_STREAMS && !urls.contains_key(renewal) {
+            let oldest = urls.iter().min_by_key(|(_, (_, at))| *at).map(|(renewal, _)| renewal.clone());
+            if let Some(oldest) = oldest {
+                urls.remove(&oldest);
+            }
+        }
+        urls.insert(renewal.clone(), (url, Instant::now()));
+    }
+}
+
+// A new URL for a stream whose token expired; None when it was renewed
+// moments ago or its source couldn't give one
+pub async fn renew(app: &AppHandle, renewal: &Renewal) -> Option<String> {
+    let renewed = &app.state::<StreamProxy>().renewed;
+    if !renewed.due(renewal) {
+        return None;
+    }
+    let result = match renewal {
+        Renewal::Xtream {
+            provider_id,
+            stream_kind,
+            stream_id,
+            extension,
+        } => xtream::renew_stream_url(app, provider_id, *stream_kind, stream_id, extension.as_deref()).await,
+        Renewal::Stalker { provider_id, cmd } => stalker::renew_link(app, provider_id, cmd).await,
+        Renewal::Playlist {
+            playlist_id,
+            channel_id,
+        } => playlist::renew_stream_url(app, playlist_id, channel_id).await,
+    };
+    match result {
+        Ok(url) => {
+            log::info!("Renewed an expired stream URL");
+            renewed.insert(renewal, url.clone());
+            Some(url)
+        }
+        Err(e) => {
+            log::warn!("Failed to renew an expired stream URL: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_renewed_urls() {
+        // Test that renewed URLs are kept per stream and a stream isn't renewed again right away
+        let renewal = Renewal::Stalker {
+            provider_id: "p".to_string(),
+            cmd: "ffmpeg http://portal/ch/1".to_string(),
+        };
+        let other = Renewal::Playlist {
+            playlist_id: "p".to_string(),
+            channel_id: "c".to_string(),
+        };
+        let renewed = Renewed::default();
+        assert!(renewed.due(&renewal) && renewed.get(&renewal).is_none());
+        renewed.insert(&renewal, "http://portal/ch/1?play_token=new".to_string());
+        assert_eq!(renewed.get(&renewal).as_deref(), Some("http://portal/ch/1?play_token=new"));
+        assert!(!renewed.due(&renewal) && renewed.due(&other));
+
+        assert!(expired(StatusCode::FORBIDDEN) && !expired(StatusCode::NOT_FOUND));
+        let parsed: Renewal =
+            serde_json::from_str(r#"{"kind":"xtream","providerId":"x","streamKind":"live","streamId":"7"}"#).unwrap();
+        assert!(matches!(parsed, Renewal::Xtream { stream_kind: StreamKind::Live, .. }));
+        assert!(parsed.check().is_ok());
+    }
+}
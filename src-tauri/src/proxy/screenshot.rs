@@ -0,0 +1,135 @@
+// Screenshots of the stream a channel plays through the proxy, for sharing
+// and for reporting a broken picture. get_proxy_url remembers each
+// channel's stream; ffmpeg reads it back through the proxy (so decryption,
+// failover to backups and token renewal apply as they do for the player)
+// and writes one frame as PNG or JPEG
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use tauri::{AppHandle, Manager};
+
+use super::transcode::Transcoding;
+use super::{encode, ffmpeg, http_input, StreamProxy, Target};
+use crate::http::HttpOptions;
+use crate::{unix_now, validate_string_length};
+
+const MAX_PATH_LENGTH: usize = 4096;
+
+const EXTENSIONS: [&str; 3] = ["png", "jpg", "jpeg"];
+
+// Streams remembered before the oldest is dropped
+const MAX_STREAMS: usize = 64;
+
+// The stream each channel was last handed out for
+#[derive(Default)]
+pub struct Playing {
+    streams: Mutex<HashMap<String, (Target, Instant)>>,
+}
+
+impl Playing {
+    pub(super) fn remember(&self, id: &str, target: &Target) {
+        let mut streams = self.streams.lock().unwrap();
+        if streams.len() >= MAX_STREAMS && !streams.contains_key(id) {
+            let oldest = streams.iter().min_by_key(|(_, (_, used))| *used).map(|(id, _)| id.clone());
+            if let Some(oldest) = oldest {
+                streams.remove(&oldest);
+            }
+        }
+        streams.insert(id.to_string(), (target.clone(), Instant::now()));
+    }
+
+    pub(super) fn get(&self, id: &str) -> Option<Target> {
+        self.streams.lock().unwrap().get(id).map(|(target, _)| target.clone())
+    }
+}
+
+// Where ffmpeg reads a stream from: HTTP streams through the proxy as they
+// come, without remuxing, transcoding or counting towards the statistics
+fn input(app: &AppHandle, target: &Target) -> (String, HttpOptions) {
+    let proxy = app.state::<StreamProxy>();
+    if !target.url.starts_with("http://") && !target.url.starts_with("https://") {
+        return http_input(app, target);
+    }
+    let relayed = Target {
+        url: target.url.clone(),
+        http: target.http.clone(),
+        decrypt: target.decrypt,
+        quality: target.quality,
+        sources: target.sources.clone(),
+        renewal: target.renewal.clone(),
+        provider: target.provider.clone(),
+        ..Default::default()
+    };
+    (encode(&proxy.key, proxy.port.get().copied().unwrap_or_default(), &relayed), HttpOptions::default())
+}
+
+// The file to write: the given path, which needs an image extension and
+// an existing folder, or else a new file named after the channel in `dir`
+fn output(path: Option<&str>, channel_id: &str, dir: &Path, now: i64) -> Result<PathBuf, String> {
+    let Some(path) = path.map(str::trim).filter(|path| !path.is_empty()) else {
+        let name: String = channel_id
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+            .take(100)
+            .collect();
+        return Ok(dir.join(format!("{}-{}.png", if name.is_empty() { "stream" } else { &name }, now)));
+    };
+    validate_string_length(path, MAX_PATH_LENGTH)?;
+    let path = PathBuf::from(path);
+    let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default();
+    if !EXTENSIONS.iter().any(|known| known.eq_ignore_ascii_case(extension)) {
+        return Err("Screenshots are saved as .png, .jpg or .jpeg".to_string());
+    }
+    let parent = path.parent().filter(|parent| parent.is_absolute() && parent.is_dir());
+    if parent.is_none() {
+        return Err(format!("{} is not in an existing folder", path.display()));
+    }
+    Ok(path)
+}
+
+// Command handler saving a frame of the stream a channel is playing
+// through the proxy; without a path it goes to the pictures folder.
+// Returns where it was saved
+#[tauri::command]
+pub async fn capture_screenshot(app: AppHandle, channel_id: String, path: Option<String>) -> Result<String, String> {
+    let proxy = app.state::<StreamProxy>();
+    let target = proxy
+        .playing
+        .get(channel_id.trim())
+        .ok_or_else(|| format!("Channel '{}' isn't playing through the proxy", channel_id))?;
+    let dir = match app.path().picture_dir() {
+        Ok(pictures) => pictures.join("TIPTV"),
+        Err(_) => app.path().app_data_dir().map_err(|e| e.to_string())?.join("screenshots"),
+    };
+    if path.as_deref().map_or(true, |path| path.trim().is_empty()) {
+        std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let path = output(path.as_deref(), channel_id.trim(), &dir, unix_now())?;
+    let (url, http) = input(&app, &target);
+    let program = app.state::<Transcoding>().get().ffmpeg;
+    ffmpeg::screenshot(&program, &url, &http, &path, None).await?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn test_screenshot_output_path() {
+        // Test that given paths need an image extension and a folder, and others are named after the channel
+        let dir = temp_dir("screenshots");
+        let named = output(None, "bbc one/hd", &dir, 1700000000).unwrap();
+        assert_eq!(named, dir.join("bbconehd-1700000000.png"));
+        assert_eq!(output(Some(" "), "", &dir, 1).unwrap(), dir.join("stream-1.png"));
+
+        let jpeg = dir.join("frame.JPG");
+        assert_eq!(output(jpeg.to_str(), "c1", &dir, 1).unwrap(), jpeg);
+        assert!(output(dir.join("frame.gif").to_str(), "c1", &dir, 1).is_err());
+        assert!(output(dir.join("missing").join("frame.png").to_str(), "c1", &dir, 1).is_err());
+        assert!(output(Some("frame.png"), "c1", &dir, 1).is_err());
+    }
+}
@@ -0,0 +1,437 @@
+// SCTE-35 splice markers of transport streams: the cues broadcasters put
+// in their streams for ad breaks and programme boundaries. splice_insert
+// commands announce breaks, time_signal commands carry segmentation
+// descriptors telling what starts or ends (programme, chapter, break, ad).
+// TS bodies played through the proxy are read for them, the app hears
+// "splice-marker" for each new one, and markers are kept per channel for a
+// while so recordings of the channel can be split at them later
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use futures_util::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::mpsc;
+
+use super::ts::{self, Demuxer, ElementaryStream};
+use crate::{storage, unix_now};
+
+const MARKERS_FILE: &str = "splice_markers.json";
+
+// The stream type of SCTE-35 in transport streams, and its table id
+const SPLICE_STREAM: u8 = 0x86;
+const SPLICE_INFO_TABLE: u8 = 0xFC;
+
+const SPLICE_INSERT: u8 = 0x05;
+const TIME_SIGNAL: u8 = 0x06;
+
+const SEGMENTATION_DESCRIPTOR: u8 = 0x02;
+
+const PTS_MASK: u64 = (1 << 33) - 1;
+
+// Markers kept per channel, and for how long
+const MAX_MARKERS: usize = 256;
+const MAX_AGE_SECONDS: i64 = 2 * 24 * 60 * 60;
+
+// Cues are repeated until they happen; the same event seen again within
+// this long is the same marker
+const REPEAT_SECONDS: i64 = 5 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MarkerKind {
+    AdStart,
+    AdEnd,
+    ProgramStart,
+    ProgramEnd,
+    ChapterStart,
+    ChapterEnd,
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpliceMarker {
+    pub stream_id: String,
+    pub kind: MarkerKind,
+    pub event_id: u32,
+    // When the proxy read it, a little ahead of playback
+    pub seen_at: i64,
+    // When it happens in the stream's timeline, in seconds; None for
+    // splices meant to happen right away
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pts: Option<f64>,
+    // How long the break or segment lasts, in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    // The segmentation_type_id of time_signal markers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub segmentation_type: Option<u8>,
+}
+
+// What a segmentation_type_id marks
+fn segmentation_kind(segmentation_type: u8) -> MarkerKind {
+    match segmentation_type {
+        // Program start, overlap start, start in progress
+        0x10 | 0x17 | 0x19 => MarkerKind::ProgramStart,
+        // Program end, early termination
+        0x11 | 0x12 => MarkerKind::ProgramEnd,
+        0x20 => MarkerKind::ChapterStart,
+        0x21 => MarkerKind::ChapterEnd,
+        // Breaks, provider and distributor ads and placement opportunities
+        0x22 | 0x30 | 0x32 | 0x34 | 0x36 => MarkerKind::AdStart,
+        0x23 | 0x31 | 0x33 | 0x35 | 0x37 => MarkerKind::AdEnd,
+        _ => MarkerKind::Other,
+    }
+}
+
+// 33 bits of 90kHz ticks after a flag byte's low bit
+fn ticks(bytes: &[u8]) -> u64 {
+    bytes[1..5].iter().fold((bytes[0] & 0x01) as u64, |ticks, byte| ticks << 8 | *byte as u64)
+}
+
+// A splice_time(): the PTS if specified, and its size
+fn splice_time(bytes: &[u8]) -> Option<(Option<u64>, usize)> {
+    match *bytes.first()? & 0x80 != 0 {
+        true => Some((Some(ticks(bytes.get(..5)?)), 5)),
+        false => Some((None, 1)),
+    }
+}
+
+// The event id, whether it's out of network, and its PTS and duration,
+// of a splice_insert that isn't cancelling one
+fn splice_insert(command: &[u8]) -> Option<(u32, bool, Option<u64>, Option<u64>)> {
+    let event_id = u32::from_be_bytes(command.get(..4)?.try_into().ok()?);
+    if *command.get(4)? & 0x80 != 0 {
+        return None;
+    }
+    let flags = *command.get(5)?;
+    let (out_of_network, program, has_duration, immediate) =
+        (flags & 0x80 != 0, flags & 0x40 != 0, flags & 0x20 != 0, flags & 0x10 != 0);
+    let mut offset = 6;
+    let mut pts = None;
+    if program && !immediate {
+        let (time, size) = splice_time(command.get(offset..)?)?;
+        pts = time;
+        offset += size;
+    } else if !program {
+        let count = *command.get(offset)? as usize;
+        offset += 1;
+        for _ in 0..count {
+            offset += 1;
+            if !immediate {
+                let (time, size) = splice_time(command.get(offset..)?)?;
+                // The first component's, for the programme
+                pts = pts.or(time);
+                offset += size;
+            }
+        }
+    }
+    let duration = match has_duration {
+        true => Some(ticks(command.get(offset..offset + 5)?)),
+        false => None,
+    };
+    Some((event_id, out_of_network, pts, duration))
+}
+
+// The event id, duration and type of a segmentation_descriptor that isn't
+// cancelling one
+fn segmentation(descriptor: &[u8]) -> Option<(u32, Option<u64>, u8)> {
+    if descriptor.get(..4)? != b"CUEI" {
+        return None;
+    }
+    let event_id = u32::from_be_bytes(descriptor.get(4..8)?.try_into().ok()?);
+    if *descriptor.get(8)? & 0x80 != 0 {
+        return None;
+    }
+    let flags = *descriptor.get(9)?;
+    let mut offset = 10;
+    if flags & 0x80 == 0 {
+        offset += 1 + 6 * *descriptor.get(offset)? as usize;
+    }
+    let mut duration = None;
+    if flags & 0x40 != 0 {
+        let bytes = descriptor.get(offset..offset + 5)?;
+        duration = Some(bytes.iter().fold(0, |ticks, byte| ticks << 8 | *byte as u64));
+        offset += 5;
+    }
+    // The UPID, then the type
+    let upid_length = *descriptor.get(offset + 1)? as usize;
+    let segmentation_type = *descriptor.get(offset + 2 + upid_length)?;
+    Some((event_id, duration, segmentation_type))
+}
+
+// The markers of a splice_info_section, without its CRC
+fn markers(stream_id: &str, section: &[u8], seen_at: i64) -> Vec<SpliceMarker> {
+    let parsed = || -> Option<Vec<SpliceMarker>> {
+        // Encrypted commands can't be read
+        if *section.get(4)? & 0x80 != 0 {
+            return None;
+        }
+        let adjustment = section.get(4..9).map(ticks)?;
+        let length = ((section.get(11)? & 0x0F) as usize) << 8 | *section.get(12)? as usize;
+        let command_type = *section.get(13)?;
+        let command = section.get(14..)?;
+        let seconds = |ticks: u64| ticks as f64 / 90_000.0;
+        let at = |pts: Option<u64>| pts.map(|pts| seconds(pts.wrapping_add(adjustment) & PTS_MASK));
+        let marker = |kind, event_id, pts, duration: Option<u64>, segmentation_type| SpliceMarker {
+            stream_id: stream_id.to_string(),
+            kind,
+            event_id,
+            seen_at,
+            pts: at(pts),
+            duration: duration.map(seconds),
+            segmentation_type,
+        };
+        match command_type {
+            SPLICE_INSERT => {
+                let (event_id, out_of_network, pts, duration) = splice_insert(command)?;
+                let kind = if out_of_network { MarkerKind::AdStart } else { MarkerKind::AdEnd };
+                Some(vec![marker(kind, event_id, pts, duration, None)])
+            }
+            TIME_SIGNAL => {
+                let (pts, size) = splice_time(command)?;
+                // Old encoders leave the length 0xFFF; a time_signal is just
+                // its splice_time
+                let length = if length == 0xFFF { size } else { length };
+                let loop_length = command.get(length..length + 2)?;
+                let loop_length = (loop_length[0] as usize) << 8 | loop_length[1] as usize;
+                let mut rest = command.get(length + 2..length + 2 + loop_length)?;
+                let mut markers = Vec::new();
+                while let (Some(tag), Some(size)) = (rest.first().copied(), rest.get(1).map(|size| *size as usize)) {
+                    let Some(body) = rest.get(2..2 + size) else {
+                        break;
+                    };
+                    if let Some((event_id, duration, segmentation_type)) =
+                        segmentation(body).filter(|_| tag == SEGMENTATION_DESCRIPTOR)
+                    {
+                        let kind = segmentation_kind(segmentation_type);
+                        markers.push(marker(kind, event_id, pts, duration, Some(segmentation_type)));
+                    }
+                    rest = &rest[2 + size..];
+                }
+                Some(markers)
+            }
+            // splice_null heartbeats, schedules and bandwidth reservations
+            _ => None,
+        }
+    };
+    parsed().unwrap_or_default()
+}
+
+type ChannelMarkers = BTreeMap<String, Vec<SpliceMarker>>;
+
+struct Saved {
+    path: PathBuf,
+    channels: Mutex<ChannelMarkers>,
+}
+
+impl Saved {
+    // Write the markers as they are now; blocking
+    fn save(&self) -> Result<(), String> {
+        let channels = self.channels.lock().unwrap().clone();
+        storage::write_json(&self.path, &channels)
+    }
+}
+
+// The markers seen on each channel. Markers are added while proxied bodies
+// are polled, so they're written to disk by a task of their own
+pub struct SpliceMarkers {
+    saved: Arc<Saved>,
+    changed: mpsc::UnboundedSender<()>,
+}
+
+// Save the markers after each change, once for changes that come together
+async fn save_changes(saved: Arc<Saved>, mut changed: mpsc::UnboundedReceiver<()>) {
+    while changed.recv().await.is_some() {
+        while changed.try_recv().is_ok() {}
+        let saved = saved.clone();
+        match tauri::async_runtime::spawn_blocking(move || saved.save()).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => log::error!("Failed to save splice markers: {}", e),
+            Err(e) => log::error!("Failed to save splice markers: {}", e),
+        }
+    }
+}
+
+impl SpliceMarkers {
+    pub fn open(data_dir: &Path) -> Self {
+        let path = data_dir.join(MARKERS_FILE);
+        let channels = storage::read_json(&path).unwrap_or_else(|e| {
+            log::error!("Failed to load splice markers: {}", e);
+            BTreeMap::new()
+        });
+        let saved = Arc::new(Saved {
+            path,
+            channels: Mutex::new(channels),
+        });
+        let (changed, receiver) = mpsc::unbounded_channel();
+        tauri::async_runtime::spawn(save_changes(saved.clone(), receiver));
+        Self { saved, changed }
+    }
+
+    // Keep a marker, saved soon after; false when it repeats one already kept
+    fn add(&self, marker: &SpliceMarker) -> bool {
+        let mut channels = self.saved.channels.lock().unwrap();
+        let repeated = channels.get(&marker.stream_id).is_some_and(|markers| {
+            markers.iter().any(|known| {
+                known.event_id == marker.event_id
+                    && known.kind == marker.kind
+                    && known.pts == marker.pts
+                    && marker.seen_at - known.seen_at < REPEAT_SECONDS
+            })
+        });
+        if repeated {
+            return false;
+        }
+        let markers = channels.entry(marker.stream_id.clone()).or_default();
+        markers.push(marker.clone());
+        markers.drain(..markers.len().saturating_sub(MAX_MARKERS));
+        for markers in channels.values_mut() {
+            markers.retain(|known| marker.seen_at - known.seen_at < MAX_AGE_SECONDS);
+        }
+        channels.retain(|_, markers| !markers.is_empty());
+        let _ = self.changed.send(());
+        true
+    }
+
+    // A channel's markers seen from `start` to `stop`, oldest first
+    pub fn between(&self, channel_id: &str, start: i64, stop: i64) -> Vec<SpliceMarker> {
+        let channels = self.saved.channels.lock().unwrap();
+        let markers = channels.get(channel_id).map(Vec::as_slice).unwrap_or_default();
+        markers.iter().filter(|marker| (start..=stop).contains(&marker.seen_at)).cloned().collect()
+    }
+}
+
+// Reads a TS body for splice markers
+pub struct Scanner {
+    app: AppHandle,
+    id: String,
+    // None once the body turns out not to be a transport stream
+    demuxer: Option<Box<Demuxer>>,
+    started: bool,
+}
+
+impl Scanner {
+    pub fn new(app: &AppHandle, id: &str) -> Self {
+        Scanner {
+            app: app.clone(),
+            id: id.to_string(),
+            demuxer: Some(Box::default()),
+            started: false,
+        }
+    }
+
+    pub fn feed(&mut self, data: &[u8]) {
+        if !self.started && !data.is_empty() {
+            self.started = true;
+            if data[0] != 0x47 {
+                self.demuxer = None;
+            }
+        }
+        let Some(demuxer) = &mut self.demuxer else {
+            return;
+        };
+        let mut select = |streams: &[ElementaryStream]| {
+            streams.iter().find(|stream| stream.stream_type == SPLICE_STREAM).map(|stream| stream.pid)
+        };
+        for unit in demuxer.feed(data, &mut select) {
+            let Some(section) = ts::section(&unit, SPLICE_INFO_TABLE) else {
+                continue;
+            };
+            for marker in markers(&self.id, section, unix_now()) {
+                if self.app.state::<SpliceMarkers>().add(&marker) {
+                    let _ = self.app.emit("splice-marker", marker);
+                }
+            }
+        }
+    }
+}
+
+// A TS body read for splice markers
+pub fn scanned<S, T, E>(scanner: Option<Scanner>, chunks: S) -> impl Stream<Item = Result<T, E>>
+where
+    S: Stream<Item = Result<T, E>>,
+    T: AsRef<[u8]>,
+{
+    let mut scanner = scanner;
+    chunks.inspect(move |chunk| {
+        if let (Some(scanner), Ok(chunk)) = (&mut scanner, chunk) {
+            scanner.feed(chunk.as_ref());
+        }
+    })
+}
+
+// Command handler returning the splice markers seen on a channel played
+// through the proxy in the last `seconds` (all kept ones without)
+#[tauri::command]
+pub fn list_splice_markers(
+    markers: State<'_, SpliceMarkers>,
+    stream_id: String,
+    seconds: Option<i64>,
+) -> Vec<SpliceMarker> {
+    let now = unix_now();
+    markers.between(&stream_id, now - seconds.unwrap_or(MAX_AGE_SECONDS), now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::ts::tests::{packet, tables};
+    use crate::test_support::temp_dir;
+
+    // A splice_info_section with a pts_adjustment of 900000 ticks
+    fn section(command_type: u8, command: &[u8], descriptors: &[u8]) -> Vec<u8> {
+        let length = 11 + command.len() + 2 + descriptors.len() + 4;
+        let mut section = vec![0, SPLICE_INFO_TABLE, 0x30 | (length >> 8) as u8, length as u8, 0];
+        section.extend([0x00, 0x00, 0x0D, 0xBB, 0xA0, 0xFF, 0xFF, 0xF0 | (command.len() >> 8) as u8]);
+        section.extend([command.len() as u8, command_type]);
+        section.extend_from_slice(command);
+        section.extend([(descriptors.len() >> 8) as u8, descriptors.len() as u8]);
+        section.extend_from_slice(descriptors);
+        section.extend([0; 4]);
+        section
+    }
+
+    #[test]
+    fn test_read_splice_markers() {
+        // Test that splice_insert and segmentation descriptors become markers, kept once per event
+        let mut stream = tables(SPLICE_STREAM, 0x1F0, &[]);
+        // Out of network at 10s, for 30s
+        let insert = [0, 0, 0, 7, 0x7F, 0xEF, 0xFE, 0, 0x0D, 0xBB, 0xA0, 0xFE, 0, 0x29, 0x32, 0xE0, 0, 1, 0, 0];
+        stream.extend(packet(0x1F0, true, &section(SPLICE_INSERT, &insert, &[])));
+        // Program start at 20s, with a 4 byte UPID, and a descriptor of another kind
+        let mut descriptors = vec![SEGMENTATION_DESCRIPTOR, 19];
+        descriptors.extend(b"CUEI");
+        descriptors.extend([0, 0, 0, 42, 0x7F, 0xBF, 0x0C, 4, 1, 2, 3, 4, 0x10, 0, 0]);
+        descriptors.extend([0x00, 8, b'C', b'U', b'E', b'I', 0, 0, 0, 1]);
+        let signal = [0xFE, 0, 0x1B, 0x77, 0x40];
+        stream.extend(packet(0x1F0, true, &section(TIME_SIGNAL, &signal, &descriptors)));
+        let mut demuxer = Demuxer::default();
+        let mut select = |streams: &[ElementaryStream]| Some(streams[0].pid);
+        let found: Vec<SpliceMarker> = demuxer
+            .feed(&stream, &mut select)
+            .iter()
+            .filter_map(|unit| ts::section(unit, SPLICE_INFO_TABLE))
+            .flat_map(|section| markers("c1", section, 1000))
+            .collect();
+        assert_eq!(found.len(), 2);
+        assert_eq!((found[0].kind, found[0].event_id), (MarkerKind::AdStart, 7));
+        assert_eq!((found[0].pts, found[0].duration), (Some(20.0), Some(30.0)));
+        assert_eq!((found[1].kind, found[1].event_id), (MarkerKind::ProgramStart, 42));
+        assert_eq!((found[1].pts, found[1].segmentation_type), (Some(30.0), Some(0x10)));
+
+        let dir = temp_dir("splice_markers");
+        let store = SpliceMarkers::open(&dir);
+        assert!(store.add(&found[0]) && store.add(&found[1]));
+        let repeated = SpliceMarker {
+            seen_at: 1010,
+            ..found[0].clone()
+        };
+        assert!(!store.add(&repeated));
+        store.saved.save().unwrap();
+        assert_eq!(SpliceMarkers::open(&dir).between("c1", 0, 2000), found);
+        assert_eq!(store.between("c1", 1001, 2000), Vec::new());
+    }
+}
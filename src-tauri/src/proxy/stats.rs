@@ -0,0 +1,238 @@
+// Statistics of the streams relayed through the proxy, for a stats overlay.
+// Everything fetched for a stream (its segments too) counts towards it;
+// while data flows the app hears "stream-stats" every second with the
+// bitrate over the last few seconds, the bytes so far, recent segment
+// download times, stalls (gaps in the data) and failed upstream requests.
+// A stream nothing was fetched for in a while is dropped
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures_util::{Stream, StreamExt};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+const INTERVAL: Duration = Duration::from_secs(1);
+
+// Bytes within this window make up the bitrate
+const BITRATE_WINDOW: Duration = Duration::from_secs(5);
+
+// A gap this long between chunks of a response is a stall
+const STALL_AFTER: Duration = Duration::from_secs(2);
+
+// Streams without data for this long are no longer reported
+const IDLE_AFTER: Duration = Duration::from_secs(30);
+
+// Segment download times kept
+const MAX_SEGMENTS: usize = 10;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamStats {
+    pub stream_id: String,
+    // Bits per second over the last BITRATE_WINDOW
+    pub bitrate: u64,
+    pub bytes: u64,
+    // Latest segment downloads, oldest first
+    pub segment_times_ms: Vec<u64>,
+    pub segments: u64,
+    pub stalls: u32,
+    pub http_errors: u32,
+}
+
+struct Counters {
+    stats: StreamStats,
+    // When bytes arrived, within the bitrate window
+    recent: VecDeque<(Instant, u64)>,
+    started: Instant,
+    used: Instant,
+}
+
+impl Counters {
+    fn snapshot(&mut self, now: Instant) -> StreamStats {
+        while self.recent.front().is_some_and(|(at, _)| now.duration_since(*at) > BITRATE_WINDOW) {
+            self.recent.pop_front();
+        }
+        let window = now.duration_since(self.started).min(BITRATE_WINDOW).as_secs_f64().max(1.0);
+        let bytes: u64 = self.recent.iter().map(|(_, bytes)| bytes).sum();
+        StreamStats {
+            bitrate: (bytes as f64 * 8.0 / window) as u64,
+            ..self.stats.clone()
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Stats {
+    streams: Mutex<HashMap<String, Counters>>,
+}
+
+impl Stats {
+    // Update a stream's counters; true when it wasn't tracked yet
+    fn update(&self, id: &str, update: impl FnOnce(&mut Counters)) -> bool {
+        let mut streams = self.streams.lock().unwrap();
+        let now = Instant::now();
+        let mut added = false;
+        let counters = streams.entry(id.to_string()).or_insert_with(|| {
+            added = true;
+            Counters {
+                stats: StreamStats {
+                    stream_id: id.to_string(),
+                    ..Default::default()
+                },
+                recent: VecDeque::new(),
+                started: now,
+                used: now,
+            }
+        });
+        counters.used = now;
+        update(counters);
+        added
+    }
+
+    // The stream's statistics now, None once it went idle
+    fn snapshot(&self, id: &str) -> Option<StreamStats> {
+        let mut streams = self.streams.lock().unwrap();
+        let now = Instant::now();
+        let counters = streams.get_mut(id)?;
+        if now.duration_since(counters.used) > IDLE_AFTER {
+            streams.remove(id);
+            return None;
+        }
+        Some(counters.snapshot(now))
+    }
+
+    fn record_error(&self, id: &str) -> bool {
+        self.update(id, |counters| counters.stats.http_errors += 1)
+    }
+}
+
+// Report a stream every INTERVAL until it goes idle
+fn report(app: &AppHandle, stats: &Arc<Stats>, id: &str) {
+    let (app, stats, id) = (app.clone(), stats.clone(), id.to_string());
+    tauri::async_runtime::spawn(async move {
+        let mut ticks = tokio::time::interval(INTERVAL);
+        loop {
+            ticks.tick().await;
+            match stats.snapshot(&id) {
+                Some(snapshot) => {
+                    let _ = app.emit("stream-stats", snapshot);
+                }
+                None => break,
+            }
+        }
+    });
+}
+
+// A failed upstream request of a stream
+pub fn error(app: &AppHandle, stats: &Arc<Stats>, id: &str) {
+    if stats.record_error(id) {
+        report(app, stats, id);
+    }
+}
+
+// Counts the body of one response; a segment's download time is recorded
+// once its body is done
+pub struct Counter {
+    stats: Arc<Stats>,
+    id: String,
+    segment: bool,
+    started: Instant,
+    last: Option<Instant>,
+}
+
+impl Counter {
+    pub fn new(app: &AppHandle, stats: &Arc<Stats>, id: &str, segment: bool) -> Self {
+        if stats.update(id, |_| {}) {
+            report(app, stats, id);
+        }
+        Counter {
+            stats: stats.clone(),
+            id: id.to_string(),
+            segment,
+            started: Instant::now(),
+            last: None,
+        }
+    }
+
+    pub fn add(&mut self, bytes: usize) {
+        let now = Instant::now();
+        let stalled = self.last.is_some_and(|last| now.duration_since(last) >= STALL_AFTER);
+        self.last = Some(now);
+        self.stats.update(&self.id, |counters| {
+            counters.stats.bytes += bytes as u64;
+            counters.recent.push_back((now, bytes as u64));
+            if stalled {
+                counters.stats.stalls += 1;
+            }
+        });
+    }
+}
+
+impl Drop for Counter {
+    fn drop(&mut self) {
+        if !self.segment || self.last.is_none() {
+            return;
+        }
+        let elapsed = self.started.elapsed().as_millis() as u64;
+        self.stats.update(&self.id, |counters| {
+            let times = &mut counters.stats.segment_times_ms;
+            if times.len() >= MAX_SEGMENTS {
+                times.remove(0);
+            }
+            times.push(elapsed);
+            counters.stats.segments += 1;
+        });
+    }
+}
+
+// A response body counted towards its stream
+pub fn counted<S, T, E>(counter: Option<Counter>, chunks: S) -> impl Stream<Item = Result<T, E>>
+where
+    S: Stream<Item = Result<T, E>>,
+    T: AsRef<[u8]>,
+{
+    let mut counter = counter;
+    chunks.inspect(move |chunk| {
+        if let (Some(counter), Ok(chunk)) = (&mut counter, chunk) {
+            counter.add(chunk.as_ref().len());
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_counters() {
+        // Test that bytes, segment downloads, stalls and errors add up per stream
+        let stats = Arc::new(Stats::default());
+        let track = |segment: bool| {
+            stats.update("s", |_| {});
+            Counter {
+                stats: stats.clone(),
+                id: "s".to_string(),
+                segment,
+                started: Instant::now(),
+                last: None,
+            }
+        };
+        let mut segment = track(true);
+        segment.add(1000);
+        segment.last = segment.last.map(|last| last - STALL_AFTER);
+        segment.add(500);
+        drop(segment);
+        drop(track(true));
+        let mut live = track(false);
+        live.add(250);
+        drop(live);
+        assert!(!stats.record_error("s"));
+
+        let snapshot = stats.snapshot("s").unwrap();
+        assert_eq!((snapshot.bytes, snapshot.segments, snapshot.stalls, snapshot.http_errors), (1750, 1, 1, 1));
+        assert_eq!(snapshot.segment_times_ms.len(), 1);
+        assert_eq!(snapshot.bitrate, 1750 * 8);
+        assert_eq!(stats.snapshot("other"), None);
+    }
+}
@@ -0,0 +1,315 @@
+// Teletext subtitles of DVB transport streams (EN 300 472, EN 300 706),
+// served as WebVTT. European channels send subtitles as teletext pages
+// (888, 777, ...) rather than DVB subtitles; the page is decoded from the
+// stream here and every version of it put on screen becomes a cue, lasting
+// until the page is sent again. Cues are timed by the PES timestamps, from
+// the first one seen. Without a page number the first subtitle page the
+// PMT announces is decoded
+use std::collections::BTreeMap;
+
+use super::ts::{self, Clock, Demuxer, ElementaryStream};
+use super::webvtt::{self, Cue};
+
+// private_stream_1, which DVB carries teletext in
+const PES_STREAM_ID: u8 = 0xBD;
+
+// PMT descriptors listing a stream's teletext pages
+const TELETEXT_DESCRIPTORS: [u8; 2] = [0x46, 0x56];
+
+// Page types of the descriptors: subtitles, and subtitles for the hard of
+// hearing
+const SUBTITLE_PAGES: [u8; 2] = [0x02, 0x05];
+
+// EBU teletext data units (non-subtitle and subtitle), always 44 bytes
+const DATA_UNITS: [u8; 2] = [0x02, 0x03];
+const DATA_UNIT_LENGTH: usize = 44;
+
+// Rows of a page that hold text; row 0 is the page header
+const TEXT_ROWS: std::ops::RangeInclusive<u8> = 1..=23;
+
+// Hamming 8/4 code words of the nibbles 0 to 15, least significant bit
+// first as transmitted
+const HAMMING_8_4: [u8; 16] = [
+    0x15, 0x02, 0x49, 0x5E, 0x64, 0x73, 0x38, 0x2F, 0xD0, 0xC7, 0x8C, 0x9B, 0xA1, 0xB6, 0xFD, 0xEA,
+];
+
+// Characters of G0 set positions that change with the national option
+// subset the page header selects
+const NATIONAL_POSITIONS: [u8; 13] = [0x23, 0x24, 0x40, 0x5B, 0x5C, 0x5D, 0x5E, 0x5F, 0x60, 0x7B, 0x7C, 0x7D, 0x7E];
+const NATIONAL_SUBSETS: [[char; 13]; 7] = [
+    // English
+    ['£', '$', '@', '←', '½', '→', '↑', '#', '—', '¼', '‖', '¾', '÷'],
+    // German
+    ['#', '$', '§', 'Ä', 'Ö', 'Ü', '^', '_', '°', 'ä', 'ö', 'ü', 'ß'],
+    // Swedish, Finnish, Hungarian
+    ['#', '¤', 'É', 'Ä', 'Ö', 'Å', 'Ü', '_', 'é', 'ä', 'ö', 'å', 'ü'],
+    // Italian
+    ['£', '$', 'é', '°', 'ç', '→', '↑', '#', 'ù', 'à', 'ò', 'è', 'ì'],
+    // French
+    ['é', 'ï', 'à', 'ë', 'ê', 'ù', 'î', '#', 'è', 'â', 'ô', 'û', 'ç'],
+    // Portuguese, Spanish
+    ['ç', '$', '¡', 'á', 'é', 'í', 'ó', 'ú', '¿', 'ü', 'ñ', 'è', 'à'],
+    // Czech, Slovak
+    ['#', 'ů', 'č', 'ť', 'ž', 'ý', 'í', 'ř', 'é', 'á', 'ě', 'ú', 'š'],
+];
+
+// A page number as shown on screen, 100 to 899, in the hex form teletext
+// addresses pages by: 888 -> 0x888
+pub fn page_number(page: u16) -> Option<u16> {
+    let digits = [page / 100, page / 10 % 10, page % 10];
+    (100..=899).contains(&page).then(|| digits[0] << 8 | digits[1] << 4 | digits[2])
+}
+
+// The nibble of a Hamming 8/4 byte, single bit errors corrected
+fn unham(byte: u8) -> Option<u8> {
+    HAMMING_8_4.iter().position(|word| (word ^ byte).count_ones() <= 1).map(|nibble| nibble as u8)
+}
+
+fn character(byte: u8, charset: usize) -> char {
+    let code = byte & 0x7F;
+    // Odd parity; control codes are spacing attributes, shown as spaces
+    if byte.count_ones() % 2 == 0 || code < 0x20 {
+        return ' ';
+    }
+    if code == 0x7F {
+        return '■';
+    }
+    match NATIONAL_POSITIONS.iter().position(|position| *position == code) {
+        Some(index) => NATIONAL_SUBSETS.get(charset).unwrap_or(&NATIONAL_SUBSETS[0])[index],
+        None => code as char,
+    }
+}
+
+// The page being put on screen
+struct Screen {
+    start: u64,
+    charset: usize,
+    rows: BTreeMap<u8, String>,
+}
+
+impl Screen {
+    fn text(&self) -> String {
+        let rows: Vec<&str> = self.rows.values().map(|row| row.trim()).filter(|row| !row.is_empty()).collect();
+        rows.join("\n")
+    }
+}
+
+// The stream carrying the page, or else the first subtitle page, and its
+// stream
+fn select(page: &mut Option<u16>, streams: &[ElementaryStream]) -> Option<u16> {
+    let mut first = None;
+    for stream in streams {
+        for (_, body) in stream.descriptors().filter(|(tag, _)| TELETEXT_DESCRIPTORS.contains(tag)) {
+            first = first.or(Some(stream.pid));
+            for entry in body.chunks_exact(5) {
+                let magazine = match entry[3] & 0x07 {
+                    0 => 8,
+                    magazine => magazine as u16,
+                };
+                let number = magazine << 8 | entry[4] as u16;
+                if page.is_none() && SUBTITLE_PAGES.contains(&(entry[3] >> 3)) {
+                    *page = Some(number);
+                }
+                if *page == Some(number) {
+                    return Some(stream.pid);
+                }
+            }
+        }
+    }
+    first.filter(|_| page.is_some())
+}
+
+pub struct Decoder {
+    // The page decoded, None until the PMT announces a subtitle page
+    page: Option<u16>,
+    demuxer: Demuxer,
+    clock: Clock,
+    // Of the latest timestamp
+    time: u64,
+    screen: Option<Screen>,
+    // Rows sent belong to the page, until another page's header
+    receiving: bool,
+}
+
+impl Decoder {
+    pub fn new(page: Option<u16>) -> Self {
+        Decoder {
+            page,
+            demuxer: Demuxer::default(),
+            clock: Clock::default(),
+            time: 0,
+            screen: None,
+            receiving: false,
+        }
+    }
+
+    fn pes_packet(&mut self, pes: &[u8], cues: &mut Vec<Cue>) {
+        let Some(pes) = ts::pes(pes).filter(|pes| pes.stream_id == PES_STREAM_ID) else {
+            return;
+        };
+        if let Some(pts) = pes.pts {
+            self.time = self.clock.ms(pts);
+        }
+        let data = pes.payload;
+        if !data.first().is_some_and(|identifier| (0x10..=0x1F).contains(identifier)) {
+            return;
+        }
+        let mut units = &data[1..];
+        while units.len() >= 2 {
+            let (id, length) = (units[0], units[1] as usize);
+            let Some(unit) = units.get(2..2 + length) else {
+                return;
+            };
+            if DATA_UNITS.contains(&id) && length == DATA_UNIT_LENGTH {
+                let unit: Vec<u8> = unit.iter().map(|byte| byte.reverse_bits()).collect();
+                self.teletext_packet(&unit, cues);
+            }
+            units = &units[2 + length..];
+        }
+    }
+
+    // A teletext packet: the framing, the magazine and row address, and
+    // 40 bytes of the row
+    fn teletext_packet(&mut self, unit: &[u8], cues: &mut Vec<Cue>) {
+        let (Some(low), Some(high)) = (unham(unit[2]), unham(unit[3])) else {
+            return;
+        };
+        let address = high << 4 | low;
+        let magazine = match address & 0x07 {
+            0 => 8,
+            magazine => magazine as u16,
+        };
+        let row = address >> 3;
+        let data = &unit[4..];
+        let Some(page) = self.page else {
+            return;
+        };
+        if row == 0 {
+            let (Some(units), Some(tens), Some(control)) = (unham(data[0]), unham(data[1]), unham(data[7])) else {
+                return;
+            };
+            let header = magazine << 8 | (tens as u16) << 4 | units as u16;
+            // In serial mode every header ends the page before it,
+            // otherwise only those of its magazine
+            let serial = control & 0x01 != 0;
+            if header == page {
+                if let Some(cue) = self.take_cue() {
+                    cues.push(cue);
+                }
+                self.screen = Some(Screen {
+                    start: self.time,
+                    charset: (control >> 1) as usize,
+                    rows: BTreeMap::new(),
+                });
+                self.receiving = true;
+            } else if serial || magazine == page >> 8 {
+                self.receiving = false;
+            }
+        } else if TEXT_ROWS.contains(&row) && self.receiving && magazine == page >> 8 {
+            if let Some(screen) = &mut self.screen {
+                let text = data.iter().map(|byte| character(*byte, screen.charset)).collect();
+                screen.rows.insert(row, text);
+            }
+        }
+    }
+
+    // The page on screen until now, if it showed anything
+    fn take_cue(&mut self) -> Option<Cue> {
+        let screen = self.screen.take()?;
+        let text = screen.text();
+        let cue = Cue {
+            start: screen.start,
+            end: self.time,
+            text,
+        };
+        (!cue.text.is_empty() && cue.end > cue.start).then_some(cue)
+    }
+}
+
+impl webvtt::Decoder for Decoder {
+    fn feed(&mut self, data: &[u8]) -> Vec<Cue> {
+        let page = &mut self.page;
+        let packets = self.demuxer.feed(data, &mut |streams| select(page, streams));
+        let mut cues = Vec::new();
+        for packet in packets {
+            self.pes_packet(&packet, &mut cues);
+        }
+        cues
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::ts::tests::{pes_packet, tables};
+    use webvtt::Decoder as _;
+
+    fn ham(nibble: u8) -> u8 {
+        HAMMING_8_4[nibble as usize]
+    }
+
+    // A character with odd parity
+    fn parity(code: u8) -> u8 {
+        match code.count_ones() % 2 {
+            0 => code | 0x80,
+            _ => code,
+        }
+    }
+
+    // A PES packet with one teletext packet, of row `row` of magazine 8
+    fn teletext(pts: u64, row: u8, data: [u8; 40]) -> Vec<u8> {
+        let address = row << 3;
+        let mut unit = vec![0x00, 0x27, ham(address & 0x0F), ham(address >> 4)];
+        unit.extend(data);
+        let mut data = vec![0x10, 0x03, DATA_UNIT_LENGTH as u8];
+        data.extend(unit.iter().map(|byte| byte.reverse_bits()));
+        pes_packet(0x100, PES_STREAM_ID, pts, &data)
+    }
+
+    fn header(pts: u64, units: u8, charset: u8) -> Vec<u8> {
+        let mut data = [parity(b' '); 40];
+        data[..8].copy_from_slice(&[ham(units), ham(8), ham(0), ham(0), ham(0), ham(0), ham(0), ham(charset << 1)]);
+        teletext(pts, 0, data)
+    }
+
+    fn row(pts: u64, row: u8, text: &[u8]) -> Vec<u8> {
+        let mut data = [parity(b' '); 40];
+        for (byte, code) in data.iter_mut().zip(text) {
+            *byte = parity(*code);
+        }
+        teletext(pts, row, data)
+    }
+
+    #[test]
+    fn test_decode_teletext_subtitles() {
+        // Test that the PMT's subtitle page is decoded into cues timed from the first timestamp
+        let mut stream = tables(0x06, 0x100, &[0x56, 5, b'd', b'e', b'u', 0x02 << 3, 0x88]);
+        let start = 900_000;
+        stream.extend(header(start, 8, 1));
+        stream.extend(row(start, 22, b"Sch\x7Cne Gr\x7D\x7Ee & <Ton>"));
+        stream.extend(row(start, 23, b"  zweite Zeile  "));
+        // Another page of the magazine doesn't add to the subtitle
+        stream.extend(header(start + 90_000, 9, 0));
+        stream.extend(row(start + 90_000, 1, b"Index"));
+        stream.extend(header(start + 180_000, 8, 1));
+        stream.extend(header(start + 270_000, 8, 1));
+
+        let mut decoder = Decoder::new(None);
+        let (first, rest) = stream.split_at(300);
+        let mut cues = decoder.feed(first);
+        cues.extend(decoder.feed(rest));
+        assert_eq!(decoder.page, Some(0x888));
+        let cue = Cue {
+            start: 0,
+            end: 2000,
+            text: "Schöne Grüße & <Ton>\nzweite Zeile".to_string(),
+        };
+        assert_eq!(cues, vec![cue.clone()]);
+        assert_eq!(cue.webvtt(), "00:00:00.000 --> 00:00:02.000\nSchöne Grüße &amp; &lt;Ton&gt;\nzweite Zeile\n\n");
+
+        assert_eq!(page_number(888), Some(0x888));
+        assert_eq!(page_number(150), Some(0x150));
+        assert_eq!(page_number(999), None);
+        assert_eq!(unham(ham(0x0B) ^ 0x04), Some(0x0B));
+    }
+}
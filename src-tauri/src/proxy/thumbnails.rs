@@ -0,0 +1,291 @@
+// Live preview thumbnails for the channel list. A background job takes one
+// small frame of each channel in the configured set (favourites, say), at
+// most once per interval a channel, one channel at a time with a pause
+// between them so it stays out of the way of playback. Thumbnails are
+// kept in the app cache, one file a channel replaced by the next capture,
+// and handed to the webview as asset URLs
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use super::probe::find_channel;
+use super::transcode::Transcoding;
+use super::{ffmpeg, http_input, provider_of, Target};
+use crate::epg::artwork::asset_url;
+use crate::playlist::content_hash;
+use crate::providers::{self, ConnectionError};
+use crate::{storage, unix_now, validate_string_length};
+
+const SETTINGS_FILE: &str = "thumbnails.json";
+
+const EXTENSION: &str = "jpg";
+
+// Thumbnail width in pixels; the height follows the picture
+const WIDTH: u32 = 320;
+
+const MAX_CHANNELS: usize = 200;
+const MAX_ID_LENGTH: usize = 2048;
+
+// Bounds of the interval between two captures of a channel, in minutes
+const MIN_INTERVAL_MINUTES: u32 = 5;
+const MAX_INTERVAL_MINUTES: u32 = 24 * 60;
+
+// How often the job looks for channels that are due, and the pause after
+// each capture
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const CAPTURE_SPACING: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ThumbnailSettings {
+    // Playlist channel ids, empty to take none
+    pub channel_ids: Vec<String>,
+    pub interval_minutes: u32,
+}
+
+impl Default for ThumbnailSettings {
+    fn default() -> Self {
+        ThumbnailSettings {
+            channel_ids: Vec::new(),
+            interval_minutes: 15,
+        }
+    }
+}
+
+fn check_settings(settings: ThumbnailSettings) -> Result<ThumbnailSettings, String> {
+    if !(MIN_INTERVAL_MINUTES..=MAX_INTERVAL_MINUTES).contains(&settings.interval_minutes) {
+        return Err(format!(
+            "The interval must be {} to {} minutes",
+            MIN_INTERVAL_MINUTES, MAX_INTERVAL_MINUTES
+        ));
+    }
+    let mut channel_ids: Vec<String> = Vec::new();
+    for id in settings.channel_ids.iter().map(|id| id.trim()).filter(|id| !id.is_empty()) {
+        validate_string_length(id, MAX_ID_LENGTH)?;
+        if !channel_ids.iter().any(|known| known == id) {
+            channel_ids.push(id.to_string());
+        }
+    }
+    if channel_ids.len() > MAX_CHANNELS {
+        return Err(format!("At most {} channels get thumbnails", MAX_CHANNELS));
+    }
+    Ok(ThumbnailSettings {
+        channel_ids,
+        interval_minutes: settings.interval_minutes,
+    })
+}
+
+// Payload of the channel-thumbnail event, sent when a thumbnail is taken
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelThumbnail {
+    pub channel_id: String,
+    pub asset_url: String,
+}
+
+pub struct Thumbnails {
+    path: PathBuf,
+    dir: PathBuf,
+    settings: Mutex<ThumbnailSettings>,
+    // Channel id -> when a failed capture may be tried again
+    failed: Mutex<HashMap<String, i64>>,
+}
+
+impl Thumbnails {
+    pub fn open(data_dir: &Path, dir: PathBuf) -> Self {
+        let path = data_dir.join(SETTINGS_FILE);
+        let settings = storage::read_json(&path).unwrap_or_else(|e| {
+            log::error!("Failed to load thumbnail settings: {}", e);
+            ThumbnailSettings::default()
+        });
+        Self {
+            path,
+            dir,
+            settings: Mutex::new(settings),
+            failed: Mutex::default(),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn settings(&self) -> ThumbnailSettings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    // Save the settings and delete the thumbnails of channels left out
+    pub fn set_settings(&self, settings: ThumbnailSettings) -> Result<ThumbnailSettings, String> {
+        let settings = check_settings(settings)?;
+        let mut current = self.settings.lock().unwrap();
+        storage::write_json(&self.path, &settings)?;
+        for channel_id in current.channel_ids.iter().filter(|id| !settings.channel_ids.contains(id)) {
+            let _ = fs::remove_file(self.file(channel_id));
+        }
+        *current = settings.clone();
+        Ok(settings)
+    }
+
+    fn file(&self, channel_id: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", content_hash(channel_id.as_bytes()), EXTENSION))
+    }
+
+    // Unix seconds of a channel's thumbnail, None when it has none
+    fn taken_at(&self, channel_id: &str) -> Option<i64> {
+        let modified = fs::metadata(self.file(channel_id)).and_then(|metadata| metadata.modified()).ok()?;
+        Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs() as i64)
+    }
+
+    // The channels whose thumbnail is older than the interval, oldest first,
+    // leaving out ones that failed recently
+    fn due(&self, now: i64) -> Vec<String> {
+        let settings = self.settings();
+        let failed = self.failed.lock().unwrap();
+        let interval = settings.interval_minutes as i64 * 60;
+        let mut due: Vec<(i64, String)> = settings
+            .channel_ids
+            .into_iter()
+            .filter(|id| failed.get(id).map_or(true, |retry| *retry <= now))
+            .map(|id| (self.taken_at(&id).unwrap_or(0), id))
+            .filter(|(taken_at, _)| now - taken_at >= interval)
+            .collect();
+        due.sort();
+        due.into_iter().map(|(_, id)| id).collect()
+    }
+
+    // The channel's thumbnail, taken again
+    async fn capture(&self, app: &AppHandle, channel_id: &str) -> Result<PathBuf, String> {
+        let (url, http) = find_channel(app, channel_id, None)?;
+        let target = Target {
+            url,
+            http,
+            ..Default::default()
+        };
+        // Held while ffmpeg reads the stream, and never taken from a
+        // provider that's full
+        let provider_id = provider_of(channel_id).unwrap_or_default();
+        let _claim = match providers::claim(app, provider_id, channel_id, channel_id).await {
+            Ok(claim) => claim,
+            Err(ConnectionError::LimitReached { .. }) => return Err("The provider has no connection free".to_string()),
+            Err(ConnectionError::Failed { message }) => return Err(message),
+        };
+        let (url, http) = http_input(app, &target);
+        fs::create_dir_all(&self.dir).map_err(|e| format!("Failed to create {}: {}", self.dir.display(), e))?;
+        // Written aside, so the list never shows half a picture
+        let path = self.file(channel_id);
+        let partial = path.with_extension(format!("part.{}", EXTENSION));
+        let program = app.state::<Transcoding>().get().ffmpeg;
+        let captured = ffmpeg::screenshot(&program, &url, &http, &partial, Some(WIDTH)).await;
+        let result = captured.and_then(|()| {
+            fs::rename(&partial, &path).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+        });
+        if result.is_err() {
+            let _ = fs::remove_file(&partial);
+            let retry = unix_now() + self.settings().interval_minutes as i64 * 60;
+            self.failed.lock().unwrap().insert(channel_id.to_string(), retry);
+        }
+        result.map(|()| path)
+    }
+
+    // Asset URLs of the thumbnails there are, by channel id
+    pub fn list(&self) -> BTreeMap<String, String> {
+        let settings = self.settings();
+        settings
+            .channel_ids
+            .into_iter()
+            .filter_map(|id| {
+                let path = self.file(&id);
+                path.exists().then(|| (id, asset_url(&path)))
+            })
+            .collect()
+    }
+}
+
+// Start the thumbnail job; runs for the lifetime of the app
+pub fn start(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let thumbnails = app.state::<Thumbnails>();
+            for channel_id in thumbnails.due(unix_now()) {
+                match thumbnails.capture(&app, &channel_id).await {
+                    Ok(path) => {
+                        let asset_url = asset_url(&path);
+                        let _ = app.emit("channel-thumbnail", ChannelThumbnail { channel_id, asset_url });
+                    }
+                    Err(e) => log::debug!("No thumbnail of channel '{}': {}", channel_id, e),
+                }
+                tokio::time::sleep(CAPTURE_SPACING).await;
+            }
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    });
+}
+
+// Command handler returning the thumbnails taken so far, as asset URLs by
+// channel id
+#[tauri::command]
+pub fn get_channel_thumbnails(thumbnails: State<'_, Thumbnails>) -> BTreeMap<String, String> {
+    thumbnails.list()
+}
+
+// Command handler returning which channels get thumbnails and how often
+#[tauri::command]
+pub fn get_thumbnail_settings(thumbnails: State<'_, Thumbnails>) -> ThumbnailSettings {
+    thumbnails.settings()
+}
+
+// Command handler changing which channels get thumbnails and how often
+#[tauri::command]
+pub fn set_thumbnail_settings(
+    thumbnails: State<'_, Thumbnails>,
+    settings: ThumbnailSettings,
+) -> Result<ThumbnailSettings, String> {
+    thumbnails.set_settings(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn test_thumbnails_due() {
+        // Test that channels are due once their thumbnail is older than the interval, unless they just failed
+        let dir = temp_dir("thumbnails");
+        let thumbnails = Thumbnails::open(&dir, dir.join("cache"));
+        let settings = ThumbnailSettings {
+            channel_ids: vec![" c1 ".to_string(), "c2".to_string(), "c1".to_string(), String::new()],
+            interval_minutes: 30,
+        };
+        assert_eq!(thumbnails.set_settings(settings).unwrap().channel_ids, vec!["c1", "c2"]);
+        let too_often = ThumbnailSettings {
+            interval_minutes: 1,
+            ..Default::default()
+        };
+        assert!(thumbnails.set_settings(too_often).is_err());
+
+        let now = unix_now();
+        fs::create_dir_all(thumbnails.dir()).unwrap();
+        fs::write(thumbnails.file("c2"), b"jpeg").unwrap();
+        assert_eq!(thumbnails.due(now), vec!["c1"]);
+        assert_eq!(thumbnails.due(now + 30 * 60 + 5), vec!["c1", "c2"]);
+        thumbnails.failed.lock().unwrap().insert("c1".to_string(), now + 60);
+        assert_eq!(thumbnails.due(now), Vec::<String>::new());
+        assert_eq!(thumbnails.list().keys().collect::<Vec<_>>(), vec!["c2"]);
+
+        let reopened = Thumbnails::open(&dir, dir.join("cache"));
+        assert_eq!(reopened.settings().channel_ids, vec!["c1", "c2"]);
+        reopened
+            .set_settings(ThumbnailSettings {
+                channel_ids: vec!["c1".to_string()],
+                interval_minutes: 30,
+            })
+            .unwrap();
+        assert!(!thumbnails.file("c2").exists());
+    }
+}
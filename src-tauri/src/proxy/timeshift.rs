@@ -0,0 +1,688 @@
+// Timeshift: live streams kept on disk so they can be paused and rewound.
+// The first request for a timeshifted channel starts recording its stream
+// into a ring file in the app cache, as many minutes as the settings keep
+// (and at most their size); the player reads from the file instead of the
+// upstream, so pausing only stops reading and resuming carries on where it
+// left off. seek_timeshift hands out a URL reading from further back, and
+// seek_back one replaying the last seconds; players hear
+// "timeshift-offset" as how far behind live they are changes.
+// Continuous streams are buffered (MPEG-TS over HTTP, multicast); HLS and
+// DASH keep their own window, and RTSP and RTMP aren't. A buffer nobody
+// reads from for a minute is deleted
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use futures_util::{stream, Stream, StreamExt};
+use reqwest::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::watch;
+
+use super::stats::{self, Counter};
+use super::{claim, dash, encode, fetch, ffmpeg, hls, http_input, scanned, scte35, StreamProxy, Target};
+use crate::{storage, validate_string_length};
+
+const SETTINGS_FILE: &str = "timeshift.json";
+
+// Bounds of the settings
+const MAX_MINUTES: u32 = 4 * 60;
+const MIN_MEGABYTES: u32 = 64;
+const MAX_MEGABYTES: u32 = 64 * 1024;
+
+const MAX_ID_LENGTH: usize = 2048;
+
+const PACKET_SIZE: usize = 188;
+const SYNC_BYTE: u8 = 0x47;
+
+// Most read from the buffer at once for a player
+const READ_SIZE: usize = 64 * 1024;
+
+// Positions in the buffer are remembered about this often
+const INDEX_INTERVAL: Duration = Duration::from_secs(1);
+
+// A buffer without readers for this long is deleted
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+// Pause before the upstream is requested again once it ends or fails
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+// The least time between two timeshift-offset events of a reader
+const REPORT_INTERVAL: Duration = Duration::from_secs(2);
+
+// How far seek_back goes without being told
+const REPLAY_SECONDS: u64 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TimeshiftSettings {
+    // How far back a channel can be paused or rewound
+    pub minutes: u32,
+    // Most disk space a channel's buffer takes, whatever the minutes
+    pub max_megabytes: u32,
+}
+
+impl Default for TimeshiftSettings {
+    fn default() -> Self {
+        TimeshiftSettings {
+            minutes: 30,
+            max_megabytes: 2048,
+        }
+    }
+}
+
+fn check_settings(settings: TimeshiftSettings) -> Result<TimeshiftSettings, String> {
+    if !(1..=MAX_MINUTES).contains(&settings.minutes) {
+        return Err(format!("Timeshift keeps 1 to {} minutes", MAX_MINUTES));
+    }
+    if !(MIN_MEGABYTES..=MAX_MEGABYTES).contains(&settings.max_megabytes) {
+        return Err(format!("Timeshift buffers take {} to {} MB", MIN_MEGABYTES, MAX_MEGABYTES));
+    }
+    Ok(settings)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeshiftStatus {
+    // How far back the buffer goes
+    pub depth_seconds: u64,
+    pub bytes: u64,
+    // The upstream is being read; false while it's requested again
+    pub recording: bool,
+}
+
+// Payload of the timeshift-offset event
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeshiftOffset {
+    pub channel_id: String,
+    // How far behind live the player is, 0 when it's live
+    pub behind_seconds: u64,
+    pub depth_seconds: u64,
+}
+
+// The first TS packet boundary in a chunk, where a player can start
+fn packet_start(data: &[u8]) -> Option<usize> {
+    (0..data.len()).find(|&i| {
+        data[i] == SYNC_BYTE && data.get(i + PACKET_SIZE).map_or(true, |next| *next == SYNC_BYTE)
+    })
+}
+
+// The bytes of a stream in a file used round and round; offsets count
+// every byte ever written
+struct Ring {
+    path: PathBuf,
+    file: Option<File>,
+    capacity: u64,
+    max_age: Duration,
+    written: u64,
+    // Offsets where packets start and when they were written, oldest first
+    index: VecDeque<(u64, Instant)>,
+}
+
+impl Ring {
+    fn create(path: PathBuf, capacity: u64, max_age: Duration) -> io::Result<Self> {
+        let file = File::options().read(true).write(true).create(true).truncate(true).open(&path)?;
+        Ok(Ring {
+            path,
+            file: Some(file),
+            capacity,
+            max_age,
+            written: 0,
+            index: VecDeque::new(),
+        })
+    }
+
+    fn file(&mut self) -> io::Result<&mut File> {
+        self.file.as_mut().ok_or_else(|| io::Error::other("The buffer is closed"))
+    }
+
+    // The oldest byte still kept
+    fn oldest(&self) -> u64 {
+        self.index.front().map_or(self.written, |(offset, _)| *offset)
+    }
+
+    fn append(&mut self, data: &[u8], now: Instant) -> io::Result<()> {
+        // Only the end of a chunk bigger than the whole buffer
+        let data = &data[data.len().saturating_sub(self.capacity as usize)..];
+        let start = self.written;
+        let mut rest = data;
+        while !rest.is_empty() {
+            let position = self.written % self.capacity;
+            let size = rest.len().min((self.capacity - position) as usize);
+            let file = self.file()?;
+            file.seek(SeekFrom::Start(position))?;
+            file.write_all(&rest[..size])?;
+            self.written += size as u64;
+            rest = &rest[size..];
+        }
+        let due = self.index.back().map_or(true, |(_, at)| now.duration_since(*at) >= INDEX_INTERVAL);
+        if let Some(offset) = packet_start(data).filter(|_| due) {
+            self.index.push_back((start + offset as u64, now));
+        }
+        let overwritten = self.written.saturating_sub(self.capacity);
+        while self
+            .index
+            .front()
+            .is_some_and(|(offset, at)| *offset < overwritten || now.duration_since(*at) > self.max_age)
+        {
+            self.index.pop_front();
+        }
+        Ok(())
+    }
+
+    fn depth(&self, now: Instant) -> Duration {
+        self.index.front().map_or(Duration::ZERO, |(_, at)| now.duration_since(*at))
+    }
+
+    // Where to read from to be `behind` the live edge
+    fn position(&self, behind: Duration, now: Instant) -> u64 {
+        let wanted = now.checked_sub(behind).unwrap_or(now);
+        match behind.is_zero() {
+            true => self.index.back().map_or(self.written, |(offset, _)| *offset),
+            false => {
+                let later = self.index.iter().find(|(_, at)| *at >= wanted).or(self.index.back());
+                later.map_or(self.written, |(offset, _)| *offset)
+            }
+        }
+    }
+
+    // How far behind live the byte at `position` was written
+    fn behind(&self, position: u64, now: Instant) -> Duration {
+        let written = self.index.iter().rev().find(|(offset, _)| *offset <= position).or(self.index.front());
+        written.map_or(Duration::ZERO, |(_, at)| now.duration_since(*at))
+    }
+
+    // Up to `max` bytes from `from`, or from the oldest kept if that's gone;
+    // returns where they start
+    fn read(&mut self, from: u64, max: usize) -> io::Result<(u64, Vec<u8>)> {
+        let from = from.max(self.oldest());
+        let size = (self.written.saturating_sub(from) as usize).min(max);
+        let mut data = vec![0; size];
+        let mut filled = 0;
+        while filled < size {
+            let position = (from + filled as u64) % self.capacity;
+            let part = (size - filled).min((self.capacity - position) as usize);
+            let file = self.file()?;
+            file.seek(SeekFrom::Start(position))?;
+            file.read_exact(&mut data[filled..filled + part])?;
+            filled += part;
+        }
+        Ok((from, data))
+    }
+}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        // Closed first, for platforms that can't delete open files
+        self.file.take();
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// A channel's buffer, shared by its recorder and readers
+pub struct Buffer {
+    ring: Mutex<Ring>,
+    // Bytes written so far, waking readers at the live edge
+    written: watch::Sender<u64>,
+    readers: AtomicUsize,
+    // When the last reader went
+    idle_since: Mutex<Instant>,
+    recording: AtomicBool,
+    // Seconds the reader that read last is behind live
+    behind: AtomicU64,
+    // Why recording stopped for good, ending every reader
+    failed: Mutex<Option<String>>,
+}
+
+impl Buffer {
+    fn new(ring: Ring) -> Self {
+        Buffer {
+            ring: Mutex::new(ring),
+            written: watch::Sender::new(0),
+            readers: AtomicUsize::new(0),
+            idle_since: Mutex::new(Instant::now()),
+            recording: AtomicBool::new(true),
+            behind: AtomicU64::new(0),
+            failed: Mutex::default(),
+        }
+    }
+
+    // Blocking: writes the ring file
+    fn append(&self, data: &[u8]) -> io::Result<()> {
+        let mut ring = self.ring.lock().unwrap();
+        ring.append(data, Instant::now())?;
+        self.written.send_replace(ring.written);
+        Ok(())
+    }
+
+    // Stop the buffer for good, waking readers so they end
+    fn fail(&self, error: &io::Error) {
+        *self.failed.lock().unwrap() = Some(error.to_string());
+        self.written.send_modify(|_| {});
+    }
+
+    fn failure(&self) -> Option<io::Error> {
+        let failed = self.failed.lock().unwrap();
+        failed.as_ref().map(|e| io::Error::other(format!("Timeshift recording failed: {}", e)))
+    }
+
+    fn status(&self) -> TimeshiftStatus {
+        let ring = self.ring.lock().unwrap();
+        TimeshiftStatus {
+            depth_seconds: ring.depth(Instant::now()).as_secs(),
+            bytes: ring.written - ring.oldest(),
+            recording: self.recording.load(Ordering::Relaxed),
+        }
+    }
+
+    fn idle(&self) -> bool {
+        self.readers.load(Ordering::Relaxed) == 0 && self.idle_since.lock().unwrap().elapsed() >= IDLE_TIMEOUT
+    }
+}
+
+// A player reading a buffer, counted while it's alive
+struct Reader {
+    app: AppHandle,
+    id: String,
+    buffer: Arc<Buffer>,
+    // The offset last reported and when
+    reported: Option<(u64, Instant)>,
+}
+
+impl Reader {
+    fn new(app: &AppHandle, id: &str, buffer: Arc<Buffer>) -> Self {
+        buffer.readers.fetch_add(1, Ordering::Relaxed);
+        *app.state::<Timeshift>().latest.lock().unwrap() = Some(id.to_string());
+        Reader {
+            app: app.clone(),
+            id: id.to_string(),
+            buffer,
+            reported: None,
+        }
+    }
+
+    // Tell the app how far behind live the player is, when that changed
+    fn report(&mut self, behind: u64, depth: u64) {
+        let now = Instant::now();
+        self.buffer.behind.store(behind, Ordering::Relaxed);
+        let due = self.reported.map_or(true, |(known, at)| known != behind && at.elapsed() >= REPORT_INTERVAL);
+        if due {
+            self.reported = Some((behind, now));
+            let offset = TimeshiftOffset {
+                channel_id: self.id.clone(),
+                behind_seconds: behind,
+                depth_seconds: depth,
+            };
+            let _ = self.app.emit("timeshift-offset", offset);
+        }
+    }
+}
+
+impl Drop for Reader {
+    fn drop(&mut self) {
+        if self.buffer.readers.fetch_sub(1, Ordering::Relaxed) == 1 {
+            *self.buffer.idle_since.lock().unwrap() = Instant::now();
+        }
+    }
+}
+
+// A channel's buffer from `behind` seconds back, following the live edge
+pub(super) fn read(
+    app: &AppHandle,
+    id: &str,
+    buffer: Arc<Buffer>,
+    behind: u64,
+) -> impl Stream<Item = io::Result<Bytes>> {
+    let mut reader = Reader::new(app, id, buffer.clone());
+    follow(buffer, behind, move |behind, depth| reader.report(behind, depth))
+}
+
+// A buffer's bytes from `behind` seconds back, following the live edge;
+// `progress` hears how far behind live each chunk ends and how deep the
+// buffer is. Ends with an error once recording fails. The file is read on
+// the blocking pool
+fn follow(
+    buffer: Arc<Buffer>,
+    behind: u64,
+    progress: impl FnMut(u64, u64) + Send + 'static,
+) -> impl Stream<Item = io::Result<Bytes>> {
+    let written = buffer.written.subscribe();
+    let state = (buffer, None, written, progress);
+    stream::unfold(state, move |(buffer, mut position, mut written, mut progress)| async move {
+        loop {
+            if position == Some(u64::MAX) {
+                return None;
+            }
+            if let Some(e) = buffer.failure() {
+                return Some((Err(e), (buffer, Some(u64::MAX), written, progress)));
+            }
+            let shared = buffer.clone();
+            let read = tauri::async_runtime::spawn_blocking(move || -> io::Result<_> {
+                let mut ring = shared.ring.lock().unwrap();
+                let now = Instant::now();
+                let from = position.unwrap_or_else(|| ring.position(Duration::from_secs(behind), now));
+                let (start, data) = ring.read(from, READ_SIZE)?;
+                let end = start + data.len() as u64;
+                Ok((end, data, ring.behind(end, now).as_secs(), ring.depth(now).as_secs()))
+            })
+            .await
+            .unwrap_or_else(|e| Err(io::Error::other(e.to_string())));
+            match read {
+                Ok((end, data, late, depth)) => {
+                    position = Some(end);
+                    if !data.is_empty() {
+                        progress(late, depth);
+                        return Some((Ok(Bytes::from(data)), (buffer, position, written, progress)));
+                    }
+                }
+                Err(e) => return Some((Err(e), (buffer, Some(u64::MAX), written, progress))),
+            }
+            if written.changed().await.is_err() {
+                return None;
+            }
+        }
+    })
+}
+
+pub struct Timeshift {
+    path: PathBuf,
+    dir: PathBuf,
+    settings: Mutex<TimeshiftSettings>,
+    buffers: Mutex<HashMap<String, Arc<Buffer>>>,
+    // The channel whose buffer was last played from
+    latest: Mutex<Option<String>>,
+}
+
+impl Timeshift {
+    // Buffers left by the last run are deleted
+    pub fn open(data_dir: &Path, dir: PathBuf) -> Self {
+        let path = data_dir.join(SETTINGS_FILE);
+        let settings = storage::read_json(&path).unwrap_or_else(|e| {
+            log::error!("Failed to load timeshift settings: {}", e);
+            TimeshiftSettings::default()
+        });
+        let _ = fs::remove_dir_all(&dir);
+        Self {
+            path,
+            dir,
+            settings: Mutex::new(settings),
+            buffers: Mutex::default(),
+            latest: Mutex::default(),
+        }
+    }
+
+    pub fn settings(&self) -> TimeshiftSettings {
+        *self.settings.lock().unwrap()
+    }
+
+    // New settings apply to buffers started from then on
+    pub fn set_settings(&self, settings: TimeshiftSettings) -> Result<(), String> {
+        let settings = check_settings(settings)?;
+        storage::write_json(&self.path, &settings)?;
+        *self.settings.lock().unwrap() = settings;
+        Ok(())
+    }
+
+    fn get(&self, id: &str) -> Option<Arc<Buffer>> {
+        self.buffers.lock().unwrap().get(id).cloned()
+    }
+
+    // A new buffer for a channel; None if another request started one
+    fn create(&self, id: &str) -> Result<Option<Arc<Buffer>>, String> {
+        let mut buffers = self.buffers.lock().unwrap();
+        if buffers.contains_key(id) {
+            return Ok(None);
+        }
+        fs::create_dir_all(&self.dir).map_err(|e| format!("Failed to create {}: {}", self.dir.display(), e))?;
+        let settings = self.settings();
+        let path = self.dir.join(format!("{}.ts", crate::playlist::content_hash(id.as_bytes())));
+        let capacity = settings.max_megabytes as u64 * 1024 * 1024;
+        let max_age = Duration::from_secs(settings.minutes as u64 * 60);
+        let ring = Ring::create(path, capacity, max_age).map_err(|e| format!("Failed to create a buffer: {}", e))?;
+        let buffer = Arc::new(Buffer::new(ring));
+        buffers.insert(id.to_string(), buffer.clone());
+        Ok(Some(buffer))
+    }
+
+    // Drop a channel's buffer once nobody reads it; true if it's gone
+    fn release(&self, id: &str, buffer: &Arc<Buffer>) -> bool {
+        let mut buffers = self.buffers.lock().unwrap();
+        if !buffer.idle() {
+            return false;
+        }
+        if buffers.get(id).is_some_and(|known| Arc::ptr_eq(known, buffer)) {
+            buffers.remove(id);
+        }
+        true
+    }
+}
+
+// The buffer a timeshifted target plays from, started if the channel
+// has none; None for streams that aren't buffered
+pub(super) async fn attach(app: &AppHandle, target: &Target) -> Result<Option<Arc<Buffer>>, String> {
+    let Some(id) = target.stats.clone() else {
+        return Ok(None);
+    };
+    if ffmpeg::is_rtsp(&target.url) || ffmpeg::is_rtmp(&target.url) {
+        return Ok(None);
+    }
+    let timeshift = app.state::<Timeshift>();
+    if let Some(buffer) = timeshift.get(&id) {
+        return Ok(Some(buffer));
+    }
+    let mut source = Target {
+        timeshift: None,
+        ..target.clone()
+    };
+    (source.url, source.http) = http_input(app, &source);
+    let upstream = fetch(app, &mut source, None, None).await.map_err(|e| format!("Request failed: {}", e))?;
+    if !upstream.status().is_success() {
+        return Err(format!("Upstream answered {}", upstream.status()));
+    }
+    let content_type = upstream.headers().get(CONTENT_TYPE).and_then(|value| value.to_str().ok());
+    if hls::is_playlist(content_type, upstream.url()) || dash::is_manifest(content_type, upstream.url()) {
+        return Ok(None);
+    }
+    let Some(buffer) = timeshift.create(&id)? else {
+        return Ok(timeshift.get(&id));
+    };
+    tauri::async_runtime::spawn(record(app.clone(), id, buffer.clone(), source, upstream));
+    Ok(Some(buffer))
+}
+
+// Write the upstream into the buffer, requesting it again whenever it
+// ends, until nobody reads the buffer. A failed write ends the buffer and
+// its readers
+async fn record(app: AppHandle, id: String, buffer: Arc<Buffer>, mut source: Target, upstream: reqwest::Response) {
+    let timeshift = app.state::<Timeshift>();
+    let proxy = app.state::<StreamProxy>();
+    // Joins the connection of the request that started the buffer
+    let _claim = claim(&app, &proxy.key, &source).await.ok().flatten();
+    let mut upstream = Some(upstream);
+    loop {
+        if let Some(response) = upstream.take() {
+            buffer.recording.store(true, Ordering::Relaxed);
+            let counter = Some(Counter::new(&app, &proxy.stats, &id, false));
+            let markers = Some(scte35::Scanner::new(&app, &id));
+            let mut chunks = Box::pin(stats::counted(counter, scanned(None, markers, response.bytes_stream())));
+            while let Some(Ok(chunk)) = chunks.next().await {
+                let writer = buffer.clone();
+                let appended = tauri::async_runtime::spawn_blocking(move || writer.append(&chunk))
+                    .await
+                    .unwrap_or_else(|e| Err(io::Error::other(e.to_string())));
+                if let Err(e) = appended {
+                    log::error!("Failed to write the timeshift buffer of '{}': {}", id, e);
+                    buffer.fail(&e);
+                    timeshift.buffers.lock().unwrap().remove(&id);
+                    return;
+                }
+                if timeshift.release(&id, &buffer) {
+                    return;
+                }
+            }
+            buffer.recording.store(false, Ordering::Relaxed);
+        }
+        if timeshift.release(&id, &buffer) {
+            return;
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+        match fetch(&app, &mut source, None, None).await {
+            Ok(response) if response.status().is_success() => upstream = Some(response),
+            Ok(response) => log::debug!("Timeshift upstream of '{}' answered {}", id, response.status()),
+            Err(e) => log::debug!("Timeshift upstream of '{}' failed: {}", id, e),
+        }
+    }
+}
+
+// Command handler returning how much of a channel's stream its timeshift
+// buffer holds; None when it has none
+#[tauri::command]
+pub fn get_timeshift_status(timeshift: State<'_, Timeshift>, channel_id: String) -> Option<TimeshiftStatus> {
+    timeshift.get(channel_id.trim()).map(|buffer| buffer.status())
+}
+
+// The URL playing a timeshifted channel from `behind` seconds before
+// live, given how far back the current one is
+fn seek(
+    proxy: &StreamProxy,
+    timeshift: &Timeshift,
+    channel_id: &str,
+    behind: impl FnOnce(u64) -> u64,
+) -> Result<String, String> {
+    validate_string_length(channel_id, MAX_ID_LENGTH)?;
+    let buffer = timeshift
+        .get(channel_id)
+        .ok_or_else(|| format!("Channel '{}' has no timeshift buffer", channel_id))?;
+    let mut target = proxy
+        .playing
+        .get(channel_id)
+        .filter(|target| target.timeshift.is_some())
+        .ok_or_else(|| format!("Channel '{}' isn't timeshifted", channel_id))?;
+    let behind = behind(buffer.behind.load(Ordering::Relaxed));
+    target.timeshift = Some(behind.min(buffer.status().depth_seconds));
+    let port = *proxy.port.get().ok_or("The stream proxy isn't running")?;
+    Ok(encode(&proxy.key, port, &target))
+}
+
+// Command handler returning the URL playing a timeshifted channel from
+// `behind_seconds` before live (0 for live), within what the buffer holds
+#[tauri::command]
+pub fn seek_timeshift(
+    proxy: State<'_, StreamProxy>,
+    timeshift: State<'_, Timeshift>,
+    channel_id: String,
+    behind_seconds: u64,
+) -> Result<String, String> {
+    seek(&proxy, &timeshift, channel_id.trim(), |_| behind_seconds)
+}
+
+// Command handler for an instant replay: the URL playing the channel
+// `seconds` (30 without) further back than it plays now, within the local
+// buffer rather than the provider's archive. Without a channel id, the
+// channel last played from its buffer
+#[tauri::command]
+pub fn seek_back(
+    proxy: State<'_, StreamProxy>,
+    timeshift: State<'_, Timeshift>,
+    seconds: Option<u64>,
+    channel_id: Option<String>,
+) -> Result<String, String> {
+    let channel_id = match channel_id.as_deref().map(str::trim).filter(|id| !id.is_empty()) {
+        Some(id) => id.to_string(),
+        None => timeshift.latest.lock().unwrap().clone().ok_or("No timeshifted channel is playing")?,
+    };
+    let seconds = seconds.unwrap_or(REPLAY_SECONDS);
+    seek(&proxy, &timeshift, &channel_id, |behind| behind.saturating_add(seconds))
+}
+
+// Command handler returning how far back channels can be rewound
+#[tauri::command]
+pub fn get_timeshift_settings(timeshift: State<'_, Timeshift>) -> TimeshiftSettings {
+    timeshift.settings()
+}
+
+// Command handler changing how far back channels can be rewound
+#[tauri::command]
+pub fn set_timeshift_settings(
+    timeshift: State<'_, Timeshift>,
+    settings: TimeshiftSettings,
+) -> Result<TimeshiftSettings, String> {
+    timeshift.set_settings(settings)?;
+    Ok(timeshift.settings())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    fn packets(count: usize, fill: u8) -> Vec<u8> {
+        (0..count).flat_map(|_| std::iter::once(SYNC_BYTE).chain(std::iter::repeat(fill).take(187))).collect()
+    }
+
+    #[test]
+    fn test_timeshift_ring_buffer() {
+        // Test that the ring wraps, forgets what it overwrote or is too old, and finds positions by time
+        let path = temp_dir("timeshift").join("ring.ts");
+        let mut ring = Ring::create(path.clone(), 188 * 10, Duration::from_secs(60)).unwrap();
+        let start = Instant::now();
+        for second in 0..4 {
+            ring.append(&packets(3, second as u8), start + Duration::from_secs(second)).unwrap();
+        }
+        // 12 packets in room for 10: the first second is gone
+        assert_eq!(ring.written, 188 * 12);
+        assert_eq!(ring.oldest(), 188 * 3);
+        let now = start + Duration::from_secs(3);
+        assert_eq!(ring.depth(now), Duration::from_secs(2));
+        assert_eq!(ring.position(Duration::from_secs(2), now), 188 * 3);
+        assert_eq!(ring.position(Duration::ZERO, now), 188 * 9);
+        assert_eq!(ring.behind(188 * 10, now), Duration::ZERO);
+        assert_eq!(ring.behind(188 * 8, now), Duration::from_secs(1));
+        assert_eq!(ring.behind(0, now), Duration::from_secs(2));
+
+        let (from, data) = ring.read(0, 188 * 4).unwrap();
+        assert_eq!((from, data.len()), (188 * 3, 188 * 4));
+        assert_eq!((data[1], data[188 * 3 + 1]), (1, 2));
+        // Across the end of the file
+        let (_, data) = ring.read(188 * 9, READ_SIZE).unwrap();
+        assert_eq!((data.len(), data[0], data[1]), (188 * 3, SYNC_BYTE, 3));
+
+        ring.append(&packets(1, 9), start + Duration::from_secs(200)).unwrap();
+        assert_eq!(ring.oldest(), 188 * 12);
+        assert_eq!(packet_start(&[0, 1, SYNC_BYTE]), Some(2));
+        assert!(path.exists());
+        drop(ring);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_timeshift_write_failure() {
+        // Test that a reader waiting at the live edge ends with an error once a write fails
+        let path = temp_dir("timeshift").join("ring.ts");
+        let buffer = Arc::new(Buffer::new(Ring::create(path, 188 * 10, Duration::from_secs(60)).unwrap()));
+        buffer.append(&packets(2, 1)).unwrap();
+        let mut chunks = Box::pin(follow(buffer.clone(), 0, |_, _| {}));
+        tauri::async_runtime::block_on(async {
+            let first = chunks.next().await.unwrap().unwrap();
+            assert_eq!(first.len(), 188 * 2);
+
+            let writer = buffer.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(100));
+                // The file going away makes the next write fail
+                writer.ring.lock().unwrap().file.take();
+                let error = writer.append(&packets(1, 2)).unwrap_err();
+                writer.fail(&error);
+            });
+            let failed = chunks.next().await.unwrap().unwrap_err();
+            assert!(failed.to_string().contains("recording failed"));
+            assert!(chunks.next().await.is_none());
+        });
+    }
+}
@@ -0,0 +1,139 @@
+// Settings for the stream proxy's ffmpeg runs: the ffmpeg to use and the
+// codec arguments of a transcode
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use crate::storage;
+use crate::validate_string_length;
+
+const TRANSCODE_FILE: &str = "transcode.json";
+
+const MAX_ARGUMENTS: usize = 50;
+const MAX_ARGUMENT_LENGTH: usize = 500;
+
+// The output is always fragmented MP4 on stdout
+const RESERVED_ARGUMENTS: [&str; 4] = ["-i", "-f", "-movflags", "pipe:1"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranscodeSettings {
+    // Looked up on PATH unless it's a path
+    #[serde(default = "default_ffmpeg")]
+    pub ffmpeg: String,
+    // Codec arguments, between the input and the output
+    #[serde(default = "default_arguments")]
+    pub arguments: Vec<String>,
+}
+
+fn default_ffmpeg() -> String {
+    "ffmpeg".to_string()
+}
+
+// H.264 and AAC, which every platform's webview plays
+fn default_arguments() -> Vec<String> {
+    ["-c:v", "libx264", "-preset", "veryfast", "-crf", "23", "-c:a", "aac", "-b:a", "160k"]
+        .map(str::to_string)
+        .to_vec()
+}
+
+impl Default for TranscodeSettings {
+    fn default() -> Self {
+        Self {
+            ffmpeg: default_ffmpeg(),
+            arguments: default_arguments(),
+        }
+    }
+}
+
+pub struct Transcoding {
+    path: PathBuf,
+    settings: Mutex<TranscodeSettings>,
+}
+
+impl Transcoding {
+    pub fn open(data_dir: &Path) -> Self {
+        let path = data_dir.join(TRANSCODE_FILE);
+        let settings = storage::read_json(&path).unwrap_or_else(|e| {
+            log::error!("Failed to load transcode settings: {}", e);
+            TranscodeSettings::default()
+        });
+        Self {
+            path,
+            settings: Mutex::new(settings),
+        }
+    }
+
+    pub fn get(&self) -> TranscodeSettings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    pub fn set(&self, settings: TranscodeSettings) -> Result<TranscodeSettings, String> {
+        let settings = TranscodeSettings {
+            ffmpeg: settings.ffmpeg.trim().to_string(),
+            arguments: settings.arguments.iter().map(|argument| argument.trim().to_string()).collect(),
+        };
+        if settings.ffmpeg.is_empty() {
+            return Err("The ffmpeg executable is required".to_string());
+        }
+        validate_string_length(&settings.ffmpeg, MAX_ARGUMENT_LENGTH)?;
+        if settings.arguments.len() > MAX_ARGUMENTS {
+            return Err(format!("At most {} arguments are allowed", MAX_ARGUMENTS));
+        }
+        for argument in &settings.arguments {
+            validate_string_length(argument, MAX_ARGUMENT_LENGTH)?;
+            if RESERVED_ARGUMENTS.contains(&argument.as_str()) {
+                return Err(format!("'{}' is set by the proxy", argument));
+            }
+        }
+        let mut current = self.settings.lock().unwrap();
+        storage::write_json(&self.path, &settings)?;
+        *current = settings.clone();
+        Ok(settings)
+    }
+}
+
+// Command handler returning the transcode settings
+#[tauri::command]
+pub fn get_transcode_settings(transcoding: State<'_, Transcoding>) -> TranscodeSettings {
+    transcoding.get()
+}
+
+// Command handler replacing the transcode settings
+#[tauri::command]
+pub fn set_transcode_settings(
+    transcoding: State<'_, Transcoding>,
+    settings: TranscodeSettings,
+) -> Result<TranscodeSettings, String> {
+    transcoding.set(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn test_transcode_settings() {
+        // Test that settings persist and arguments the proxy sets itself are refused
+        let transcoding = Transcoding::open(&temp_dir("transcode"));
+        assert_eq!(transcoding.get(), TranscodeSettings::default());
+        let settings = TranscodeSettings {
+            ffmpeg: " /opt/ffmpeg/bin/ffmpeg ".to_string(),
+            arguments: vec!["-c:v".to_string(), "libx265".to_string(), "-c:a".to_string(), " opus".to_string()],
+        };
+        let saved = transcoding.set(settings).unwrap();
+        assert_eq!(saved.ffmpeg, "/opt/ffmpeg/bin/ffmpeg");
+        assert_eq!(saved.arguments[3], "opus");
+        assert_eq!(Transcoding::open(transcoding.path.parent().unwrap()).get(), saved);
+
+        let output = TranscodeSettings {
+            arguments: vec!["-f".to_string(), "mpegts".to_string()],
+            ..saved.clone()
+        };
+        assert!(transcoding.set(output).is_err());
+        assert_eq!(transcoding.get(), saved);
+    }
+}
@@ -0,0 +1,294 @@
+// Just enough of MPEG transport streams to follow one elementary stream:
+// the PAT leads to the PMTs, the streams of a PMT are offered to a select
+// function until it picks one, and that stream's PES packets (or sections,
+// for streams of PSI like SCTE-35) are put back together. PATs and PMTs
+// are expected to fit in one packet, as they practically always do
+const PACKET_SIZE: usize = 188;
+const SYNC_BYTE: u8 = 0x47;
+
+const PTS_MASK: u64 = (1 << 33) - 1;
+
+// A stream listed by a PMT
+pub struct ElementaryStream<'a> {
+    pub stream_type: u8,
+    pub pid: u16,
+    descriptors: &'a [u8],
+}
+
+impl<'a> ElementaryStream<'a> {
+    // The stream's descriptors, tag and body
+    pub fn descriptors(&self) -> impl Iterator<Item = (u8, &'a [u8])> {
+        let mut rest = self.descriptors;
+        std::iter::from_fn(move || {
+            let (tag, length) = (*rest.first()?, *rest.get(1)? as usize);
+            let body = rest.get(2..2 + length)?;
+            rest = &rest[2 + length..];
+            Some((tag, body))
+        })
+    }
+}
+
+pub struct Pes<'a> {
+    pub stream_id: u8,
+    pub pts: Option<u64>,
+    pub payload: &'a [u8],
+}
+
+// The header fields and payload of a PES packet
+pub fn pes(bytes: &[u8]) -> Option<Pes<'_>> {
+    if bytes.len() < 9 || bytes[..3] != [0, 0, 1] {
+        return None;
+    }
+    let pts = bytes.get(9..14).filter(|_| bytes[7] & 0x80 != 0).map(|pts| {
+        ((pts[0] as u64 >> 1) & 0x07) << 30
+            | (pts[1] as u64) << 22
+            | (pts[2] as u64 >> 1) << 15
+            | (pts[3] as u64) << 7
+            | pts[4] as u64 >> 1
+    });
+    Some(Pes {
+        stream_id: bytes[3],
+        pts,
+        payload: bytes.get(9 + bytes[8] as usize..)?,
+    })
+}
+
+// The PSI section a payload unit starts, without its CRC
+pub fn section(payload: &[u8], table: u8) -> Option<&[u8]> {
+    let section = payload.get(1 + *payload.first()? as usize..)?;
+    let length = ((section.get(1)? & 0x0F) as usize) << 8 | *section.get(2)? as usize;
+    section.get(..(3 + length).checked_sub(4)?).filter(|section| section.first() == Some(&table))
+}
+
+fn pmt_streams(section: &[u8]) -> Vec<ElementaryStream<'_>> {
+    let Some(info_length) = section.get(10..12).map(|bytes| ((bytes[0] & 0x0F) as usize) << 8 | bytes[1] as usize)
+    else {
+        return Vec::new();
+    };
+    let mut rest = section.get(12 + info_length..).unwrap_or_default();
+    let mut streams = Vec::new();
+    while rest.len() >= 5 {
+        let length = ((rest[3] & 0x0F) as usize) << 8 | rest[4] as usize;
+        streams.push(ElementaryStream {
+            stream_type: rest[0],
+            pid: ((rest[1] & 0x1F) as u16) << 8 | rest[2] as u16,
+            descriptors: rest.get(5..5 + length).unwrap_or_default(),
+        });
+        rest = rest.get(5 + length..).unwrap_or_default();
+    }
+    streams
+}
+
+#[derive(Default)]
+pub struct Demuxer {
+    pid: Option<u16>,
+    pmts: Vec<u16>,
+    // The rest of the last chunk, short of a whole packet
+    pending: Vec<u8>,
+    unit: Option<Vec<u8>>,
+}
+
+// Whether a payload unit is all there: a PES packet has the length its
+// header gives (video may leave it 0 and end with the next one), a section
+// the length after its pointer field
+fn complete(unit: &[u8]) -> bool {
+    let length = match unit.starts_with(&[0, 0, 1]) {
+        true => {
+            let header = unit.get(4..6);
+            header.map(|length| 6 + ((length[0] as usize) << 8 | length[1] as usize)).filter(|length| *length > 6)
+        }
+        false => {
+            let start = 1 + *unit.first().unwrap_or(&0) as usize;
+            let header = unit.get(start + 1..start + 3);
+            header.map(|length| start + 3 + (((length[0] & 0x0F) as usize) << 8 | length[1] as usize))
+        }
+    };
+    length.is_some_and(|length| unit.len() >= length)
+}
+
+impl Demuxer {
+    // The payload units of the selected stream the next bytes complete
+    pub fn feed(&mut self, data: &[u8], select: &mut dyn FnMut(&[ElementaryStream]) -> Option<u16>) -> Vec<Vec<u8>> {
+        self.pending.extend_from_slice(data);
+        let pending = std::mem::take(&mut self.pending);
+        let mut packets = Vec::new();
+        let mut offset = 0;
+        while pending.len() - offset >= PACKET_SIZE {
+            if pending[offset] != SYNC_BYTE {
+                offset += 1;
+                continue;
+            }
+            self.packet(&pending[offset..offset + PACKET_SIZE], select, &mut packets);
+            offset += PACKET_SIZE;
+        }
+        self.pending = pending[offset..].to_vec();
+        packets
+    }
+
+    fn packet(
+        &mut self,
+        packet: &[u8],
+        select: &mut dyn FnMut(&[ElementaryStream]) -> Option<u16>,
+        packets: &mut Vec<Vec<u8>>,
+    ) {
+        let pid = ((packet[1] & 0x1F) as u16) << 8 | packet[2] as u16;
+        let start = packet[1] & 0x40 != 0;
+        if packet[3] & 0x10 == 0 {
+            return;
+        }
+        let offset = match packet[3] & 0x20 != 0 {
+            true => 5 + packet[4] as usize,
+            false => 4,
+        };
+        let Some(payload) = packet.get(offset..) else {
+            return;
+        };
+        if pid == 0 && start {
+            let Some(section) = section(payload, 0x00) else {
+                return;
+            };
+            for program in section.get(8..).unwrap_or_default().chunks_exact(4) {
+                let number = (program[0] as u16) << 8 | program[1] as u16;
+                let pid = ((program[2] & 0x1F) as u16) << 8 | program[3] as u16;
+                if number != 0 && !self.pmts.contains(&pid) {
+                    self.pmts.push(pid);
+                }
+            }
+        } else if self.pid.is_none() && start && self.pmts.contains(&pid) {
+            if let Some(section) = section(payload, 0x02) {
+                self.pid = select(&pmt_streams(section));
+            }
+        } else if Some(pid) == self.pid {
+            if start {
+                packets.extend(self.unit.take());
+                self.unit = Some(Vec::new());
+            }
+            let Some(unit) = &mut self.unit else {
+                return;
+            };
+            unit.extend_from_slice(payload);
+            if complete(unit) {
+                packets.extend(self.unit.take());
+            }
+        }
+    }
+}
+
+// Timestamps as milliseconds from the first one, across wraparounds of
+// the 33-bit counter; earlier ones (B-frames come after the frames they
+// show before) are 0
+#[derive(Default)]
+pub struct Clock {
+    // The latest timestamp and its ticks from the first
+    last: Option<(u64, i64)>,
+}
+
+impl Clock {
+    pub fn ms(&mut self, pts: u64) -> u64 {
+        let ticks = match self.last {
+            None => 0,
+            Some((last, ticks)) => {
+                let delta = pts.wrapping_sub(last) & PTS_MASK;
+                match delta > PTS_MASK / 2 {
+                    true => ticks + delta as i64 - (PTS_MASK + 1) as i64,
+                    false => ticks + delta as i64,
+                }
+            }
+        };
+        self.last = Some((pts, ticks));
+        ticks.max(0) as u64 / 90
+    }
+}
+
+// Test streams
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    // A packet of the payload, stuffed to size
+    pub fn packet(pid: u16, start: bool, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![SYNC_BYTE, (pid >> 8) as u8 | if start { 0x40 } else { 0 }, pid as u8, 0x30];
+        let stuffing = 183 - payload.len();
+        packet.push(stuffing as u8);
+        if stuffing > 0 {
+            packet.push(0);
+            packet.extend(std::iter::repeat(0xFF).take(stuffing - 1));
+        }
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    // A PAT with one program, whose PMT lists one stream
+    pub fn tables(stream_type: u8, pid: u16, descriptors: &[u8]) -> Vec<u8> {
+        let psi = |table: u8, body: &[u8]| {
+            let length = body.len() + 5 + 4;
+            let mut section = vec![0, table, 0xB0 | (length >> 8) as u8, length as u8, 0, 1, 0xC1, 0, 0];
+            section.extend_from_slice(body);
+            section.extend([0; 4]);
+            section
+        };
+        let mut stream = packet(0, true, &psi(0x00, &[0, 1, 0xE0, 0x20]));
+        let mut pmt = vec![0xE1, 0x00, 0xF0, 0x00, stream_type, 0xE0 | (pid >> 8) as u8, pid as u8];
+        pmt.extend([0xF0, descriptors.len() as u8]);
+        pmt.extend_from_slice(descriptors);
+        stream.extend(packet(0x20, true, &psi(0x02, &pmt)));
+        stream
+    }
+
+    // A PES packet in one TS packet
+    pub fn pes_packet(pid: u16, stream_id: u8, pts: u64, payload: &[u8]) -> Vec<u8> {
+        let mut pes = vec![0, 0, 1, stream_id, 0, 8 + payload.len() as u8, 0x80, 0x80, 5];
+        pes.extend([
+            0x21 | ((pts >> 29) as u8 & 0x0E),
+            (pts >> 22) as u8,
+            (pts >> 14) as u8 | 1,
+            (pts >> 7) as u8,
+            (pts << 1) as u8 | 1,
+        ]);
+        pes.extend_from_slice(payload);
+        packet(pid, true, &pes)
+    }
+
+    #[test]
+    fn test_demux_selected_stream() {
+        // Test that the PMT's stream is picked and its PES packets come back whole, across chunks
+        let mut stream = tables(0x06, 0x100, &[0x56, 0]);
+        stream.extend(pes_packet(0x100, 0xBD, 900_000, b"first"));
+        stream.extend(pes_packet(0x101, 0xBD, 900_000, b"other"));
+        stream.extend(pes_packet(0x100, 0xBD, 990_000, b"second"));
+        let mut demuxer = Demuxer::default();
+        let mut select = |streams: &[ElementaryStream]| {
+            let stream = &streams[0];
+            let tags: Vec<u8> = stream.descriptors().map(|(tag, _)| tag).collect();
+            (stream.stream_type == 0x06 && tags == [0x56]).then_some(stream.pid)
+        };
+        let (first, rest) = stream.split_at(300);
+        let mut packets = demuxer.feed(&[0xFF], &mut select);
+        packets.extend(demuxer.feed(first, &mut select));
+        packets.extend(demuxer.feed(rest, &mut select));
+        assert_eq!(packets.len(), 2);
+
+        let mut clock = Clock::default();
+        let times: Vec<(u64, &[u8])> = packets
+            .iter()
+            .filter_map(|packet| pes(packet))
+            .map(|pes| (clock.ms(pes.pts.unwrap()), pes.payload))
+            .collect();
+        assert_eq!(times, vec![(0, &b"first"[..]), (1000, &b"second"[..])]);
+        assert_eq!(clock.ms(0), 0);
+        let mut wrapping = Clock::default();
+        assert_eq!(wrapping.ms(PTS_MASK - 89_999), 0);
+        assert_eq!(wrapping.ms(90_000), 2000);
+    }
+
+    #[test]
+    fn test_short_sections_are_ignored() {
+        // Test that PATs too short to hold a table id give no section and don't stop the demuxer
+        for length in [0, 1] {
+            let pat = [0, 0x00, 0xB0, length, 0];
+            assert_eq!(section(&pat, 0x00), None);
+            let mut demuxer = Demuxer::default();
+            assert!(demuxer.feed(&packet(0, true, &pat), &mut |_| None).is_empty());
+        }
+    }
+}
@@ -0,0 +1,40 @@
+// WebVTT documents of subtitles decoded from a stream as it plays. The
+// document starts right away and every cue follows once it's off screen,
+// so the player sees each cue just after it was due; fine for its text
+// track, which keeps cues that arrive late
+use futures_util::{stream, Stream, StreamExt};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cue {
+    // Milliseconds from the start of the stream
+    pub start: u64,
+    pub end: u64,
+    pub text: String,
+}
+
+fn timestamp(ms: u64) -> String {
+    format!("{:02}:{:02}:{:02}.{:03}", ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60, ms % 1000)
+}
+
+impl Cue {
+    pub fn webvtt(&self) -> String {
+        let text = self.text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+        format!("{} --> {}\n{}\n\n", timestamp(self.start), timestamp(self.end), text)
+    }
+}
+
+// Decodes the cues of a stream from its bytes
+pub trait Decoder: Send {
+    fn feed(&mut self, data: &[u8]) -> Vec<Cue>;
+}
+
+pub fn document<S, T, E>(mut decoder: Box<dyn Decoder>, chunks: S) -> impl Stream<Item = Result<String, E>>
+where
+    S: Stream<Item = Result<T, E>>,
+    T: AsRef<[u8]>,
+{
+    let cues = chunks.map(move |chunk| {
+        chunk.map(|chunk| decoder.feed(chunk.as_ref()).iter().map(Cue::webvtt).collect::<String>())
+    });
+    stream::once(async { Ok("WEBVTT\n\n".to_string()) }).chain(cues)
+}
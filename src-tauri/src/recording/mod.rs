@@ -0,0 +1,356 @@
+// Recording schedule: jobs built from guide programmes on a playlist
+// channel. A job carries everything the recorder needs without going back
+// to the guide or the playlist (the stream URL and its HTTP options, the
+// padded time span, and the programme's metadata for tagging the file)
+pub mod series;
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+
+use crate::epg::{programme_id, ContentRating, EpgStore, Genre, Programme};
+use crate::http::HttpOptions;
+use crate::playlist::{content_hash, ChannelEntry, PlaylistStore};
+use crate::proxy::profiles::TranscodeProfiles;
+use crate::proxy::scte35::{MarkerKind, SpliceMarkers};
+use crate::{storage, unix_now, validate_string_length};
+
+use series::SeriesRule;
+
+const RECORDINGS_FILE: &str = "recordings.json";
+
+// Most padding either side, in minutes
+const MAX_PADDING_MINUTES: u32 = 120;
+
+// Limit for ids sent by the UI
+const MAX_ID_LENGTH: usize = 2048;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RecordingSettings {
+    // Minutes recorded before the start and after the end, since guides
+    // and broadcasters rarely agree to the minute
+    pub padding_before_minutes: u32,
+    pub padding_after_minutes: u32,
+}
+
+impl Default for RecordingSettings {
+    fn default() -> Self {
+        RecordingSettings {
+            padding_before_minutes: 2,
+            padding_after_minutes: 5,
+        }
+    }
+}
+
+// What the programme was, for naming and tagging the recording
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RecordingMetadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub genres: Vec<Genre>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ratings: Vec<ContentRating>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub premiere: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordingJob {
+    pub id: String,
+    // Guide programme, see epg::programme_id
+    pub programme_id: String,
+    pub title: String,
+    // Playlist channel and its stream
+    pub channel_id: String,
+    pub channel_name: String,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "HttpOptions::is_empty")]
+    pub http: HttpOptions,
+    pub epg_channel_id: String,
+    // The programme's own times
+    pub programme_start: i64,
+    pub programme_stop: i64,
+    // What gets recorded, padding included
+    pub start: i64,
+    pub stop: i64,
+    #[serde(default)]
+    pub metadata: RecordingMetadata,
+    // The series rule that scheduled it, see series
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub series_rule: Option<String>,
+    // Transcode profile to record with (see proxy::profiles), None to
+    // record the stream as it is
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transcode_profile: Option<String>,
+    pub created_at: i64,
+}
+
+// The job recording `programme` from a playlist channel; the same
+// programme on the same channel always gets the same id
+pub fn job(programme: Programme, channel: &ChannelEntry, settings: RecordingSettings, now: i64) -> RecordingJob {
+    let programme_id = programme_id(&programme.channel, programme.start);
+    RecordingJob {
+        id: content_hash(format!("{}|{}", channel.channel_id, programme_id).as_bytes()),
+        programme_id,
+        title: programme.title,
+        channel_id: channel.channel_id.clone(),
+        channel_name: channel.name.clone(),
+        url: channel.url.clone(),
+        http: channel.http.clone(),
+        epg_channel_id: programme.channel,
+        programme_start: programme.start,
+        programme_stop: programme.stop,
+        start: programme.start - i64::from(settings.padding_before_minutes) * 60,
+        stop: programme.stop + i64::from(settings.padding_after_minutes) * 60,
+        metadata: RecordingMetadata {
+            description: programme.description,
+            categories: programme.categories,
+            genres: programme.genres,
+            ratings: programme.ratings,
+            icon: programme.icon,
+            premiere: programme.premiere,
+        },
+        series_rule: None,
+        transcode_profile: None,
+        created_at: now,
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct Schedule {
+    settings: RecordingSettings,
+    jobs: Vec<RecordingJob>,
+    series: Vec<SeriesRule>,
+}
+
+fn check_settings(settings: RecordingSettings) -> Result<RecordingSettings, String> {
+    if settings.padding_before_minutes > MAX_PADDING_MINUTES || settings.padding_after_minutes > MAX_PADDING_MINUTES {
+        return Err(format!("Padding can be at most {} minutes", MAX_PADDING_MINUTES));
+    }
+    Ok(settings)
+}
+
+// Where a recording could be split, from a splice marker its channel
+// carried while it was recorded
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordingChapter {
+    // Seconds into the recording
+    pub offset: i64,
+    pub kind: MarkerKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+}
+
+pub struct RecordingSchedule {
+    path: PathBuf,
+    schedule: Mutex<Schedule>,
+}
+
+impl RecordingSchedule {
+    pub fn open(data_dir: &Path) -> Self {
+        let path = data_dir.join(RECORDINGS_FILE);
+        let schedule = storage::read_json(&path).unwrap_or_else(|e| {
+            log::error!("Failed to load the recording schedule: {}", e);
+            Schedule::default()
+        });
+        Self {
+            path,
+            schedule: Mutex::new(schedule),
+        }
+    }
+
+    fn update<T>(&self, change: impl FnOnce(&mut Schedule) -> T) -> Result<T, String> {
+        let mut schedule = self.schedule.lock().unwrap();
+        let mut updated = schedule.clone();
+        let result = change(&mut updated);
+        if updated != *schedule {
+            storage::write_json(&self.path, &updated)?;
+            *schedule = updated;
+        }
+        Ok(result)
+    }
+
+    pub fn settings(&self) -> RecordingSettings {
+        self.schedule.lock().unwrap().settings
+    }
+
+    pub fn set_settings(&self, settings: RecordingSettings) -> Result<(), String> {
+        let settings = check_settings(settings)?;
+        self.update(|schedule| schedule.settings = settings)
+    }
+
+    // Jobs by start time
+    pub fn list(&self) -> Vec<RecordingJob> {
+        let mut jobs = self.schedule.lock().unwrap().jobs.clone();
+        jobs.sort_by_key(|job| job.start);
+        jobs
+    }
+
+    // Add a job, replacing an earlier one for the same programme and channel
+    pub fn add(&self, job: RecordingJob) -> Result<(), String> {
+        self.update(|schedule| {
+            schedule.jobs.retain(|existing| existing.id != job.id);
+            schedule.jobs.push(job);
+        })
+    }
+
+    pub fn cancel(&self, id: &str) -> Result<bool, String> {
+        self.update(|schedule| {
+            let Some(index) = schedule.jobs.iter().position(|job| job.id == id) else {
+                return false;
+            };
+            let job = schedule.jobs.remove(index);
+            // Keep its series rule from scheduling it again
+            if let Some(rule) = job
+                .series_rule
+                .and_then(|rule| schedule.series.iter_mut().find(|existing| existing.id == rule))
+            {
+                rule.cancelled.push(job.id);
+            }
+            true
+        })
+    }
+}
+
+// Command handler scheduling a guide programme for recording from a
+// loaded playlist's channel, padded as the recording settings say unless
+// `padding` is given, with the transcode profile chosen for the channel
+#[tauri::command]
+pub fn schedule_recording(
+    app: AppHandle,
+    playlists: State<'_, PlaylistStore>,
+    schedule: State<'_, RecordingSchedule>,
+    program_id: String,
+    playlist_id: String,
+    channel_id: String,
+    padding: Option<RecordingSettings>,
+) -> Result<RecordingJob, String> {
+    validate_string_length(&program_id, MAX_ID_LENGTH)?;
+    validate_string_length(&channel_id, MAX_ID_LENGTH)?;
+    let settings = check_settings(padding.unwrap_or_else(|| schedule.settings()))?;
+    let entries = playlists
+        .get(&playlist_id)
+        .ok_or_else(|| format!("Playlist '{}' is not loaded", playlist_id))?;
+    let channel = entries
+        .iter()
+        .find(|entry| entry.channel_id == channel_id.trim())
+        .ok_or_else(|| format!("Channel '{}' isn't in the playlist", channel_id))?;
+    let programme = app.state::<EpgStore>().find_programme(&program_id)?;
+    let now = unix_now();
+    if programme.stop <= now {
+        return Err("That programme is already over".to_string());
+    }
+    let profile = app
+        .state::<TranscodeProfiles>()
+        .resolve(Some(&playlist_id), Some(&channel.channel_id));
+    let job = RecordingJob {
+        transcode_profile: profile.map(|profile| profile.id),
+        ..job(programme, channel, settings, now)
+    };
+    schedule.add(job.clone())?;
+    Ok(job)
+}
+
+// Command handler listing the scheduled recordings, soonest first
+#[tauri::command]
+pub fn list_recordings(schedule: State<'_, RecordingSchedule>) -> Vec<RecordingJob> {
+    schedule.list()
+}
+
+// Command handler removing a scheduled recording
+#[tauri::command]
+pub fn cancel_recording(schedule: State<'_, RecordingSchedule>, id: String) -> Result<bool, String> {
+    schedule.cancel(id.trim())
+}
+
+// Command handler returning the default padding
+#[tauri::command]
+pub fn get_recording_settings(schedule: State<'_, RecordingSchedule>) -> RecordingSettings {
+    schedule.settings()
+}
+
+// Command handler changing the default padding
+#[tauri::command]
+pub fn set_recording_settings(
+    schedule: State<'_, RecordingSchedule>,
+    settings: RecordingSettings,
+) -> Result<RecordingSettings, String> {
+    schedule.set_settings(settings)?;
+    Ok(schedule.settings())
+}
+
+// Command handler returning the chapter candidates of a recording: the
+// splice markers (see proxy::scte35) seen on its channel while it ran
+#[tauri::command]
+pub fn get_recording_chapters(
+    schedule: State<'_, RecordingSchedule>,
+    markers: State<'_, SpliceMarkers>,
+    id: String,
+) -> Result<Vec<RecordingChapter>, String> {
+    let job = schedule.list().into_iter().find(|job| job.id == id.trim()).ok_or("Recording not found")?;
+    let chapters = markers.between(&job.channel_id, job.start, job.stop).into_iter().map(|marker| RecordingChapter {
+        offset: marker.seen_at - job.start,
+        kind: marker.kind,
+        duration: marker.duration,
+    });
+    Ok(chapters.collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn test_job_from_programme() {
+        // Test that jobs carry the padded span, the stream and metadata, and replace duplicates
+        let programme = Programme {
+            channel: "one.uk".to_string(),
+            start: 10_000,
+            stop: 13_600,
+            title: "Final".to_string(),
+            description: Some("Live".to_string()),
+            genres: vec![Genre::Sports],
+            ..Default::default()
+        };
+        let channel = ChannelEntry {
+            channel_id: "p:one".to_string(),
+            name: "One HD".to_string(),
+            url: "http://stream/one".to_string(),
+            ..Default::default()
+        };
+        let schedule = RecordingSchedule::open(&temp_dir("recordings"));
+        assert!(schedule
+            .set_settings(RecordingSettings {
+                padding_before_minutes: MAX_PADDING_MINUTES + 1,
+                padding_after_minutes: 0,
+            })
+            .is_err());
+
+        let first = job(programme.clone(), &channel, schedule.settings(), 5);
+        assert_eq!((first.start, first.stop), (10_000 - 120, 13_600 + 300));
+        assert_eq!((first.programme_id.as_str(), first.url.as_str()), ("one.uk@10000", "http://stream/one"));
+        assert_eq!(first.metadata.genres, vec![Genre::Sports]);
+        schedule.add(first.clone()).unwrap();
+        let again = job(programme, &channel, RecordingSettings::default(), 6);
+        assert_eq!(again.id, first.id);
+        schedule.add(again).unwrap();
+
+        let reopened = RecordingSchedule::open(schedule.path.parent().unwrap());
+        assert_eq!(reopened.list().len(), 1);
+        assert_eq!(reopened.list()[0].created_at, 6);
+        assert!(reopened.cancel(&first.id).unwrap() && reopened.list().is_empty());
+    }
+}
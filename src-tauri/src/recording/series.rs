@@ -0,0 +1,366 @@
+// Series links: a rule recording every airing of a recurring programme on
+// one guide channel. Airings belong to the series when the guide's series
+// ids agree, or by title when the guide has none. Rules are expanded into
+// ordinary jobs whenever a guide is stored, so episodes get scheduled as
+// the guide reaches them; jobs cancelled by hand aren't scheduled again
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Listener, Manager, State};
+
+use super::{check_settings, job, RecordingJob, RecordingSchedule, RecordingSettings, MAX_ID_LENGTH};
+use crate::epg::{programme_id, EpgStore, Programme};
+use crate::http::HttpOptions;
+use crate::playlist::{content_hash, ChannelEntry, PlaylistStore};
+use crate::proxy::profiles::TranscodeProfiles;
+use crate::{unix_now, validate_string_length};
+
+// Seconds ahead looked at for recurring programmes
+const DETECT_HORIZON: i64 = 14 * 24 * 60 * 60;
+
+const MAX_RULES: usize = 200;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeriesRule {
+    pub id: String,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub series_id: Option<String>,
+    pub epg_channel_id: String,
+    // Playlist channel and its stream, as in RecordingJob
+    pub channel_id: String,
+    pub channel_name: String,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "HttpOptions::is_empty")]
+    pub http: HttpOptions,
+    // First showings only, no repeats
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub new_only: bool,
+    // Instead of the default padding
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub padding: Option<RecordingSettings>,
+    // Ids of this rule's jobs cancelled by hand
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cancelled: Vec<String>,
+    // As in RecordingJob
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transcode_profile: Option<String>,
+    pub created_at: i64,
+}
+
+// Titles compare by their lowercased words, so "Doctor Who" and "DOCTOR
+// WHO!" are the same series
+fn title_key(title: &str) -> String {
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Programmes of one series share this
+fn series_key(programme: &Programme) -> String {
+    match &programme.series_id {
+        Some(id) => format!("id:{}", id),
+        None => format!("title:{}", title_key(&programme.title)),
+    }
+}
+
+impl SeriesRule {
+    // The rule recording `programme`'s series from a playlist channel
+    pub fn new(
+        programme: &Programme,
+        channel: &ChannelEntry,
+        new_only: bool,
+        padding: Option<RecordingSettings>,
+        now: i64,
+    ) -> Self {
+        let key = series_key(programme);
+        SeriesRule {
+            id: content_hash(format!("{}|{}|{}", channel.channel_id, programme.channel, key).as_bytes()),
+            title: programme.title.clone(),
+            series_id: programme.series_id.clone(),
+            epg_channel_id: programme.channel.clone(),
+            channel_id: channel.channel_id.clone(),
+            channel_name: channel.name.clone(),
+            url: channel.url.clone(),
+            http: channel.http.clone(),
+            new_only,
+            padding,
+            cancelled: Vec::new(),
+            transcode_profile: None,
+            created_at: now,
+        }
+    }
+
+    fn matches(&self, programme: &Programme) -> bool {
+        if programme.channel != self.epg_channel_id || self.new_only && !programme.premiere {
+            return false;
+        }
+        match (&self.series_id, &programme.series_id) {
+            (Some(rule), Some(series)) => rule == series,
+            _ => title_key(&self.title) == title_key(&programme.title),
+        }
+    }
+
+    fn channel(&self) -> ChannelEntry {
+        ChannelEntry {
+            channel_id: self.channel_id.clone(),
+            name: self.channel_name.clone(),
+            url: self.url.clone(),
+            http: self.http.clone(),
+            ..Default::default()
+        }
+    }
+
+    // Jobs for the rule's airings among `programmes` that haven't ended
+    fn airings(&self, programmes: &[Programme], settings: RecordingSettings, now: i64) -> Vec<RecordingJob> {
+        let channel = self.channel();
+        let settings = self.padding.unwrap_or(settings);
+        programmes
+            .iter()
+            .filter(|programme| programme.stop > now && self.matches(programme))
+            .map(|programme| RecordingJob {
+                series_rule: Some(self.id.clone()),
+                transcode_profile: self.transcode_profile.clone(),
+                ..job(programme.clone(), &channel, settings, now)
+            })
+            .collect()
+    }
+}
+
+// A programme shown more than once on its channel
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeriesCandidate {
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub series_id: Option<String>,
+    pub epg_channel_id: String,
+    pub airings: usize,
+    // The next airing, to create the rule from
+    pub programme_id: String,
+    pub next_start: i64,
+}
+
+// The recurring programmes among a channel's, soonest first
+pub fn detect(programmes: &[Programme]) -> Vec<SeriesCandidate> {
+    let mut found: HashMap<String, SeriesCandidate> = HashMap::new();
+    for programme in programmes {
+        found
+            .entry(series_key(programme))
+            .and_modify(|candidate| {
+                candidate.airings += 1;
+                if programme.start < candidate.next_start {
+                    candidate.programme_id = programme_id(&programme.channel, programme.start);
+                    candidate.next_start = programme.start;
+                }
+            })
+            .or_insert_with(|| SeriesCandidate {
+                title: programme.title.clone(),
+                series_id: programme.series_id.clone(),
+                epg_channel_id: programme.channel.clone(),
+                airings: 1,
+                programme_id: programme_id(&programme.channel, programme.start),
+                next_start: programme.start,
+            });
+    }
+    let mut candidates: Vec<SeriesCandidate> = found.into_values().filter(|candidate| candidate.airings > 1).collect();
+    candidates.sort_by(|a, b| a.next_start.cmp(&b.next_start).then_with(|| a.title.cmp(&b.title)));
+    candidates
+}
+
+impl RecordingSchedule {
+    pub fn series_rules(&self) -> Vec<SeriesRule> {
+        self.schedule.lock().unwrap().series.clone()
+    }
+
+    // Add a rule, replacing an earlier one for the same series and channel
+    pub fn add_series_rule(&self, rule: SeriesRule) -> Result<(), String> {
+        self.update(|schedule| {
+            schedule.series.retain(|existing| existing.id != rule.id);
+            if schedule.series.len() >= MAX_RULES {
+                return Err(format!("At most {} series can be recorded", MAX_RULES));
+            }
+            schedule.series.push(rule);
+            Ok(())
+        })?
+    }
+
+    // Remove a rule along with the jobs it scheduled
+    pub fn remove_series_rule(&self, id: &str) -> Result<bool, String> {
+        self.update(|schedule| {
+            let before = schedule.series.len();
+            schedule.series.retain(|rule| rule.id != id);
+            schedule.jobs.retain(|job| job.series_rule.as_deref() != Some(id));
+            schedule.series.len() != before
+        })
+    }
+
+    // Schedule the rules' airings in the guide that aren't scheduled or
+    // cancelled yet; returns the jobs added
+    pub fn expand_series(&self, store: &EpgStore, now: i64) -> Result<Vec<RecordingJob>, String> {
+        let settings = self.settings();
+        let airings: Vec<(String, Vec<RecordingJob>)> = self
+            .series_rules()
+            .into_iter()
+            .map(|rule| {
+                let programmes = store.programmes(&rule.epg_channel_id, now, i64::MAX);
+                let jobs = rule.airings(&programmes, settings, now);
+                (rule.id, jobs)
+            })
+            .collect();
+        self.update(|schedule| {
+            let mut added = Vec::new();
+            for (rule_id, jobs) in airings {
+                let Some(rule) = schedule.series.iter_mut().find(|rule| rule.id == rule_id) else {
+                    continue;
+                };
+                // Cancellations of airings no longer ahead aren't needed
+                let ids: HashSet<&str> = jobs.iter().map(|job| job.id.as_str()).collect();
+                rule.cancelled.retain(|id| ids.contains(id.as_str()));
+                let cancelled = rule.cancelled.clone();
+                for job in jobs {
+                    if !cancelled.contains(&job.id) && !schedule.jobs.iter().any(|existing| existing.id == job.id) {
+                        schedule.jobs.push(job.clone());
+                        added.push(job);
+                    }
+                }
+            }
+            added
+        })
+    }
+}
+
+fn expand(app: &AppHandle) {
+    match app.state::<RecordingSchedule>().expand_series(&app.state::<EpgStore>(), unix_now()) {
+        Ok(added) if !added.is_empty() => {
+            let _ = app.emit("recordings-scheduled", added);
+        }
+        Ok(_) => {}
+        Err(e) => log::warn!("Failed to schedule series recordings: {}", e),
+    }
+}
+
+// Expand the rules now and again whenever a guide is stored
+pub fn start(app: AppHandle) {
+    let handle = app.clone();
+    app.listen("epg-updated", move |_| {
+        let handle = handle.clone();
+        tauri::async_runtime::spawn_blocking(move || expand(&handle));
+    });
+    tauri::async_runtime::spawn_blocking(move || expand(&app));
+}
+
+// Command handler listing a guide channel's recurring programmes over the
+// next two weeks
+#[tauri::command]
+pub fn list_series_candidates(store: State<'_, EpgStore>, epg_channel_id: String) -> Vec<SeriesCandidate> {
+    let now = unix_now();
+    detect(&store.programmes(epg_channel_id.trim(), now, now + DETECT_HORIZON))
+}
+
+// Command handler recording every episode of a guide programme's series
+// from a loaded playlist's channel; the airings already in the guide are
+// scheduled straight away
+#[tauri::command]
+pub fn add_series_rule(
+    app: AppHandle,
+    program_id: String,
+    playlist_id: String,
+    channel_id: String,
+    new_only: Option<bool>,
+    padding: Option<RecordingSettings>,
+) -> Result<SeriesRule, String> {
+    validate_string_length(&program_id, MAX_ID_LENGTH)?;
+    validate_string_length(&channel_id, MAX_ID_LENGTH)?;
+    let padding = padding.map(check_settings).transpose()?;
+    let entries = app
+        .state::<PlaylistStore>()
+        .get(&playlist_id)
+        .ok_or_else(|| format!("Playlist '{}' is not loaded", playlist_id))?;
+    let channel = entries
+        .iter()
+        .find(|entry| entry.channel_id == channel_id.trim())
+        .ok_or_else(|| format!("Channel '{}' isn't in the playlist", channel_id))?;
+    let store = app.state::<EpgStore>();
+    let programme = store.find_programme(&program_id)?;
+    let now = unix_now();
+    let profile = app
+        .state::<TranscodeProfiles>()
+        .resolve(Some(&playlist_id), Some(&channel.channel_id));
+    let rule = SeriesRule {
+        transcode_profile: profile.map(|profile| profile.id),
+        ..SeriesRule::new(&programme, channel, new_only.unwrap_or(false), padding, now)
+    };
+    let schedule = app.state::<RecordingSchedule>();
+    schedule.add_series_rule(rule.clone())?;
+    let added = schedule.expand_series(&store, now)?;
+    if !added.is_empty() {
+        let _ = app.emit("recordings-scheduled", added);
+    }
+    Ok(rule)
+}
+
+// Command handler listing the series being recorded
+#[tauri::command]
+pub fn list_series_rules(schedule: State<'_, RecordingSchedule>) -> Vec<SeriesRule> {
+    schedule.series_rules()
+}
+
+// Command handler no longer recording a series; its scheduled jobs go too
+#[tauri::command]
+pub fn remove_series_rule(schedule: State<'_, RecordingSchedule>, id: String) -> Result<bool, String> {
+    schedule.remove_series_rule(id.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epg::Guide;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn test_series_rule_expanded() {
+        // Test that recurring programmes are detected and rules schedule new airings but not cancelled ones
+        let airing = |start: i64, title: &str| Programme {
+            channel: "one.uk".to_string(),
+            start,
+            stop: start + 1800,
+            title: title.to_string(),
+            ..Default::default()
+        };
+        let store = EpgStore::open(temp_dir("recording-series"));
+        let programmes = vec![airing(1000, "Quiz Night"), airing(5000, "News"), airing(90_000, "QUIZ night!")];
+        store.replace("s", Guide::from(programmes), 0).unwrap();
+        let candidates = detect(&store.programmes("one.uk", 0, i64::MAX));
+        assert_eq!(candidates.len(), 1);
+        assert_eq!((candidates[0].airings, candidates[0].programme_id.as_str()), (2, "one.uk@1000"));
+
+        let channel = ChannelEntry {
+            channel_id: "p:one".to_string(),
+            name: "One".to_string(),
+            url: "http://stream/one".to_string(),
+            ..Default::default()
+        };
+        let schedule = RecordingSchedule::open(&temp_dir("recording-series"));
+        let rule = SeriesRule::new(&store.find_programme("one.uk@1000").unwrap(), &channel, false, None, 0);
+        schedule.add_series_rule(rule.clone()).unwrap();
+        assert_eq!(schedule.expand_series(&store, 3000).unwrap().len(), 1);
+        assert!(schedule.expand_series(&store, 3000).unwrap().is_empty());
+
+        let first = schedule.list()[0].clone();
+        assert_eq!((first.programme_start, first.series_rule.as_deref()), (90_000, Some(rule.id.as_str())));
+        assert!(schedule.cancel(&first.id).unwrap());
+        store
+            .replace("s", Guide::from(vec![airing(90_000, "Quiz Night"), airing(180_000, "Quiz Night")]), 1)
+            .unwrap();
+        let added = schedule.expand_series(&store, 3000).unwrap();
+        assert_eq!(added.iter().map(|job| job.programme_start).collect::<Vec<_>>(), vec![180_000]);
+
+        assert!(schedule.remove_series_rule(&rule.id).unwrap());
+        assert!(schedule.list().is_empty() && schedule.series_rules().is_empty());
+    }
+}
@@ -0,0 +1,188 @@
+// Redirect resolution for playlist and stream URLs
+use std::collections::HashSet;
+
+use reqwest::header::LOCATION;
+use serde::Serialize;
+use url::Url;
+
+use crate::http::{self, HttpOptions};
+use crate::validate_string_length;
+
+// Maximum accepted length for a URL to resolve
+const MAX_URL_LENGTH: usize = 2048;
+
+// Redirects followed when the caller doesn't set a limit
+const DEFAULT_MAX_REDIRECTS: u32 = 10;
+
+// Upper bound for a caller-provided limit
+const MAX_REDIRECTS_LIMIT: u32 = 30;
+
+// One request in a redirect chain
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RedirectHop {
+    pub url: String,
+    pub status: u16,
+}
+
+// Where a URL ends up and how it got there
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolvedUrl {
+    pub final_url: String,
+    pub status: u16,
+    // Every request made, including the final one
+    pub chain: Vec<RedirectHop>,
+}
+
+fn parse_http_url(url: &str) -> Result<Url, String> {
+    let parsed = Url::parse(url).map_err(|e| format!("Invalid URL '{}': {}", url, e))?;
+    match parsed.scheme() {
+        "http" | "https" => Ok(parsed),
+        scheme => Err(format!("Refusing to follow redirect to a {}:// URL", scheme)),
+    }
+}
+
+// Where a redirect may go from `current`: https never downgrades to http,
+// which would send the options and the request in the clear
+fn check_hop(current: &Url, next: &Url) -> Result<(), String> {
+    if current.scheme() == "https" && next.scheme() == "http" {
+        return Err(format!("Refusing to follow a redirect from https to {}", next));
+    }
+    Ok(())
+}
+
+// Follow redirects one hop at a time so every step can be checked and
+// reported; only http(s) targets are allowed, https is never downgraded and
+// loops are detected. Custom headers (logins, cookies) stay with the first
+// origin, other hosts only get the user agent and referrer
+pub async fn resolve(url: &str, options: Option<&HttpOptions>, max_redirects: u32) -> Result<ResolvedUrl, String> {
+    let mut current = parse_http_url(url)?;
+    let origin = current.origin();
+    let public = options.map(HttpOptions::public);
+    let mut chain = Vec::new();
+    let mut seen = HashSet::new();
+
+    loop {
+        seen.insert(current.to_string());
+        let sent = if current.origin() == origin { options } else { public.as_ref() };
+        let request = http::apply_options(http::no_redirect_client().get(current.clone()), sent);
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("Request to {} failed: {}", current, e))?;
+        let status = response.status();
+        chain.push(RedirectHop {
+            url: current.to_string(),
+            status: status.as_u16(),
+        });
+
+        let location = response.headers().get(LOCATION).and_then(|v| v.to_str().ok());
+        let location = match location {
+            Some(location) if status.is_redirection() => location.to_string(),
+            _ => {
+                return Ok(ResolvedUrl {
+                    final_url: current.to_string(),
+                    status: status.as_u16(),
+                    chain,
+                })
+            }
+        };
+        // The body of a redirect is never needed
+        drop(response);
+
+        if chain.len() > max_redirects as usize {
+            return Err(format!("Too many redirects (limit is {})", max_redirects));
+        }
+        let next = current
+            .join(&location)
+            .map_err(|e| format!("Invalid redirect target '{}': {}", location, e))?;
+        let next = parse_http_url(next.as_str())?;
+        check_hop(&current, &next)?;
+        if seen.contains(next.as_str()) {
+            return Err(format!("Redirect loop detected at {}", next));
+        }
+        current = next;
+    }
+}
+
+// Command handler reporting the final URL and the redirect chain of a URL
+#[tauri::command]
+pub async fn resolve_url(url: String, max_redirects: Option<u32>) -> Result<ResolvedUrl, String> {
+    validate_string_length(&url, MAX_URL_LENGTH)?;
+    let max_redirects = max_redirects.unwrap_or(DEFAULT_MAX_REDIRECTS).min(MAX_REDIRECTS_LIMIT);
+    // Stream URLs may carry Kodi-style `|User-Agent=...` options
+    let (url, options) = http::split_url_options(url.trim());
+    resolve(&url, options.as_ref(), max_redirects).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{response, serve};
+
+    fn run(url: &str, max_redirects: u32) -> Result<ResolvedUrl, String> {
+        tauri::async_runtime::block_on(resolve(url, None, max_redirects))
+    }
+
+    #[test]
+    fn test_resolve_follows_chain() {
+        // Test that relative redirects are followed and every hop is reported
+        let (base, _) = serve(vec![
+            response("302 Found", &[("Location", "/b")], ""),
+            response("301 Moved Permanently", &[("Location", "c?x=1")], ""),
+            response("200 OK", &[], "ok"),
+        ]);
+        let resolved = run(&format!("{}/a", base), 10).unwrap();
+
+        assert_eq!(resolved.final_url, format!("{}/c?x=1", base));
+        assert_eq!(resolved.status, 200);
+        let statuses: Vec<u16> = resolved.chain.iter().map(|hop| hop.status).collect();
+        assert_eq!(statuses, vec![302, 301, 200]);
+    }
+
+    #[test]
+    fn test_resolve_blocks_file_scheme() {
+        // Test that a redirect to file:// is refused
+        let (base, _) = serve(vec![response("302 Found", &[("Location", "file:///etc/passwd")], "")]);
+        let error = run(&format!("{}/a", base), 10).unwrap_err();
+        assert!(error.contains("file://"));
+        assert!(run("file:///etc/passwd", 10).is_err());
+    }
+
+    #[test]
+    fn test_resolve_enforces_limit_and_loops() {
+        // Test that the redirect limit and loops are detected
+        let (base, _) = serve(vec![
+            response("302 Found", &[("Location", "/b")], ""),
+            response("302 Found", &[("Location", "/c")], ""),
+        ]);
+        assert!(run(&format!("{}/a", base), 1).unwrap_err().contains("Too many redirects"));
+
+        let (base, _) = serve(vec![
+            response("302 Found", &[("Location", "/b")], ""),
+            response("302 Found", &[("Location", "/a")], ""),
+        ]);
+        assert!(run(&format!("{}/a", base), 10).unwrap_err().contains("loop"));
+    }
+
+    #[test]
+    fn test_resolve_keeps_https_and_headers() {
+        // Test that https never redirects to http and custom headers stay with the first origin
+        let secure = Url::parse("https://a/list.m3u").unwrap();
+        assert!(check_hop(&secure, &Url::parse("http://a/list.m3u").unwrap()).is_err());
+        assert!(check_hop(&secure, &Url::parse("https://b/list.m3u").unwrap()).is_ok());
+        assert!(check_hop(&Url::parse("http://a/").unwrap(), &secure).is_ok());
+
+        let (other, requests) = serve(vec![response("200 OK", &[], "ok")]);
+        let (base, _) = serve(vec![response("302 Found", &[("Location", &format!("{}/b", other))], "")]);
+        let mut options = HttpOptions::default();
+        options.set_header("Authorization", "Basic dTpw");
+        options.set_header("User-Agent", "VLC/3.0");
+        let resolved = tauri::async_runtime::block_on(resolve(&format!("{}/a", base), Some(&options), 10)).unwrap();
+        assert_eq!(resolved.status, 200);
+        let request = requests.lock().unwrap()[0].to_ascii_lowercase();
+        assert!(request.contains("user-agent: vlc/3.0"));
+        assert!(!request.contains("authorization"));
+    }
+}
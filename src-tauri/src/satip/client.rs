@@ -0,0 +1,125 @@
+// HTTP side of a SAT>IP server: its device description and channel list
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::model::{self, SatipChannel, ServerInfo, Tuning};
+use crate::http;
+use crate::identity;
+use crate::playlist::{self, PlaylistFormat};
+
+// A server, by the URL of its device description as SSDP announces it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SatipServer {
+    pub url: String,
+}
+
+// Accept the announced location with or without its scheme
+pub fn normalize_location(location: &str) -> Result<Url, String> {
+    let location = location.trim();
+    if location.is_empty() {
+        return Err("SAT>IP server address cannot be empty".to_string());
+    }
+    let with_scheme = if location.contains("://") {
+        location.to_string()
+    } else {
+        format!("http://{}", location)
+    };
+    let mut url =
+        Url::parse(&with_scheme).map_err(|e| format!("Invalid SAT>IP server address '{}': {}", location, e))?;
+    if !matches!(url.scheme(), "http" | "https") || !url.has_host() {
+        return Err(format!("Invalid SAT>IP server address '{}'", location));
+    }
+    url.set_fragment(None);
+    Ok(url)
+}
+
+pub struct SatipClient {
+    location: Url,
+}
+
+impl SatipClient {
+    pub fn new(server: &SatipServer) -> Result<Self, String> {
+        Ok(Self {
+            location: normalize_location(&server.url)?,
+        })
+    }
+
+    pub fn server(&self) -> &str {
+        self.location.as_str()
+    }
+
+    async fn get_text(&self, url: &str) -> Result<String, String> {
+        let response = http::get(url, None)
+            .send()
+            .await
+            .map_err(|e| format!("Couldn't reach the SAT>IP server at {}: {}", url, e.without_url()))?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!("The SAT>IP server answered {} with HTTP {}", url, status.as_u16()));
+        }
+        response
+            .text()
+            .await
+            .map_err(|e| format!("Couldn't read the SAT>IP server's answer: {}", e.without_url()))
+    }
+
+    pub async fn describe(&self) -> Result<ServerInfo, String> {
+        let xml = self.get_text(self.server()).await?;
+        model::parse_description(&xml, &self.location)
+    }
+
+    // The channels the server lists in its M3U; entries that aren't RTSP
+    // aren't tunable and are dropped
+    pub async fn channels(&self, info: &ServerInfo, provider_id: &str) -> Result<Vec<SatipChannel>, String> {
+        let url = info
+            .m3u_url
+            .as_deref()
+            .ok_or_else(|| format!("{} doesn't publish a channel list", self.server()))?;
+        let body = self.get_text(url).await?;
+        let entries = playlist::read_entries(body.as_bytes(), Some(PlaylistFormat::M3u), |_, _| {}).await?;
+        Ok(entries
+            .into_iter()
+            .filter_map(|entry| {
+                let tuning = Tuning::from_url(&entry.url)?;
+                Some(SatipChannel {
+                    channel_id: identity::channel_id(provider_id, &identity::satip_key(&entry.url)),
+                    number: entry.attributes.get("tvg-chno").and_then(|number| number.parse().ok()),
+                    name: entry.name,
+                    url: entry.url,
+                    logo: entry.tvg_logo,
+                    tuning,
+                })
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{response, serve};
+
+    #[test]
+    fn test_describe_and_channels() {
+        // Test that the channel list linked from the description is read and non-RTSP entries dropped
+        let description = r#"<root><device><friendlyName>Telestar Digibit R1</friendlyName>
+            <X_SATIPCAP>DVBS2-2</X_SATIPCAP><X_SATIPM3U>channels.m3u</X_SATIPM3U></device></root>"#;
+        let m3u = "#EXTM3U\n#EXTINF:0 tvg-chno=\"1\",Das Erste HD\nrtsp://satip/?src=1&freq=11494&pol=h&msys=dvbs2&sr=22000\n\
+                   #EXTINF:0,Web\nhttp://example.com/live.ts\n";
+        let (base, requests) = serve(vec![response("200 OK", &[], description), response("200 OK", &[], m3u)]);
+        let client = SatipClient::new(&SatipServer {
+            url: format!("{}/desc.xml", base),
+        })
+        .unwrap();
+
+        let info = tauri::async_runtime::block_on(client.describe()).unwrap();
+        assert_eq!(info.tuner_count(), 2);
+        let channels = tauri::async_runtime::block_on(client.channels(&info, "p")).unwrap();
+        assert_eq!(channels.len(), 1);
+        assert_eq!((channels[0].name.as_str(), channels[0].number), ("Das Erste HD", Some(1)));
+        assert_eq!(channels[0].tuning.freq, Some(11494.0));
+        assert!(requests.lock().unwrap()[1].starts_with("GET /channels.m3u "));
+        assert!(normalize_location(" ").is_err());
+    }
+}
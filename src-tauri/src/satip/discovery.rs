@@ -0,0 +1,119 @@
+// Finding SAT>IP servers on the local network with SSDP: a multicast
+// M-SEARCH for the SAT>IP device type that every server answers with the
+// location of its device description
+use std::net::{Ipv4Addr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+const SSDP_ADDRESS: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+const SSDP_PORT: u16 = 1900;
+
+const SEARCH_TARGET: &str = "urn:ses-com:device:SatIPServer:1";
+
+// A server that answered a search
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Discovered {
+    // Unique service name, stable across restarts of the server
+    pub usn: String,
+    // URL of the device description
+    pub location: String,
+}
+
+fn request(wait: Duration) -> String {
+    format!(
+        "M-SEARCH * HTTP/1.1\r\nHOST: {}:{}\r\nMAN: \"ssdp:discover\"\r\nMX: {}\r\nST: {}\r\n\r\n",
+        SSDP_ADDRESS,
+        SSDP_PORT,
+        wait.as_secs().clamp(1, 5),
+        SEARCH_TARGET
+    )
+}
+
+// A search answer, None for anything that isn't a SAT>IP server's
+fn parse_reply(packet: &str) -> Option<Discovered> {
+    let mut lines = packet.lines();
+    if !lines.next()?.split_whitespace().nth(1).is_some_and(|status| status == "200") {
+        return None;
+    }
+    let mut target = None;
+    let mut usn = None;
+    let mut location = None;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim().to_string();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "st" => target = Some(value),
+            "usn" => usn = Some(value),
+            "location" => location = Some(value),
+            _ => {}
+        }
+    }
+    if target.as_deref() != Some(SEARCH_TARGET) {
+        return None;
+    }
+    let location = location.filter(|location| !location.is_empty())?;
+    Some(Discovered {
+        usn: usn.unwrap_or_else(|| location.clone()),
+        location,
+    })
+}
+
+// Send a search and collect answers until the timeout; blocking, so it
+// runs off the async runtime
+pub fn discover(timeout: Duration) -> Result<Vec<Discovered>, String> {
+    let error = |e: std::io::Error| format!("SAT>IP discovery failed: {}", e);
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).map_err(error)?;
+    // UPnP asks for a small TTL so searches stay on the local network
+    socket.set_multicast_ttl_v4(2).map_err(error)?;
+    socket
+        .send_to(request(timeout).as_bytes(), (SSDP_ADDRESS, SSDP_PORT))
+        .map_err(error)?;
+
+    let deadline = Instant::now() + timeout;
+    let mut servers: Vec<Discovered> = Vec::new();
+    let mut buffer = [0u8; 2048];
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        socket.set_read_timeout(Some(left)).map_err(error)?;
+        match socket.recv_from(&mut buffer) {
+            Ok((size, _)) => {
+                let Some(server) = parse_reply(&String::from_utf8_lossy(&buffer[..size])) else {
+                    continue;
+                };
+                if !servers.iter().any(|known| known.usn == server.usn) {
+                    servers.push(server);
+                }
+            }
+            Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => break,
+            Err(e) => return Err(error(e)),
+        }
+    }
+    Ok(servers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_replies() {
+        // Test the search request and that only SAT>IP servers' answers are kept
+        assert!(request(Duration::from_secs(2)).contains("\r\nST: urn:ses-com:device:SatIPServer:1\r\n"));
+
+        let reply = "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age=1800\r\nLocation: http://192.168.178.1:49000/satipdesc.xml\r\n\
+                     ST: urn:ses-com:device:SatIPServer:1\r\nUSN: uuid:1234::urn:ses-com:device:SatIPServer:1\r\n\r\n";
+        let server = parse_reply(reply).unwrap();
+        assert_eq!(server.location, "http://192.168.178.1:49000/satipdesc.xml");
+        assert_eq!(server.usn, "uuid:1234::urn:ses-com:device:SatIPServer:1");
+
+        assert!(parse_reply(&reply.replace("SatIPServer", "MediaServer")).is_none());
+        assert!(parse_reply(&reply.replace("200 OK", "404 Not Found")).is_none());
+    }
+}
@@ -0,0 +1,366 @@
+// SAT>IP servers (network gateways for satellite, cable and terrestrial
+// tuners such as the Fritz!Box or Telestar Digibit): SSDP discovery, the
+// published channel list and RTSP sessions that tune and stream a channel
+mod client;
+mod discovery;
+mod model;
+mod rtsp;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+
+use crate::playlist::content_hash;
+use crate::storage;
+use crate::validate_string_length;
+
+pub use client::{SatipClient, SatipServer};
+pub use model::{SatipChannel, ServerInfo};
+pub use rtsp::RtspSession;
+
+const PROVIDERS_FILE: &str = "satip_providers.json";
+
+// Limits for user-entered server details
+const MAX_FIELD_LENGTH: usize = 2048;
+const MAX_NAME_LENGTH: usize = 200;
+
+// How long discovery waits for servers to answer
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+// Keep-alives go out this long before the session would time out
+const KEEP_ALIVE_MARGIN: u64 = 10;
+const MIN_KEEP_ALIVE: Duration = Duration::from_secs(5);
+
+// A saved server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SatipProvider {
+    pub id: String,
+    pub name: String,
+    #[serde(flatten)]
+    pub server: SatipServer,
+}
+
+// Same description URL always maps to the same provider
+fn provider_id(client: &SatipClient) -> String {
+    content_hash(format!("satip:{}", client.server()).as_bytes())
+}
+
+impl SatipProvider {
+    // A provider with a normalized address; an empty name becomes the
+    // address
+    pub fn new(client: &SatipClient, name: &str) -> Self {
+        SatipProvider {
+            id: provider_id(client),
+            name: match name.trim() {
+                "" => client.server().to_string(),
+                name => name.to_string(),
+            },
+            server: SatipServer {
+                url: client.server().to_string(),
+            },
+        }
+    }
+}
+
+pub struct SatipProviders {
+    path: PathBuf,
+    providers: Mutex<Vec<SatipProvider>>,
+}
+
+impl SatipProviders {
+    pub fn open(data_dir: &Path) -> Self {
+        let path = data_dir.join(PROVIDERS_FILE);
+        let providers = storage::read_json(&path).unwrap_or_else(|e| {
+            log::error!("Failed to load SAT>IP servers: {}", e);
+            Vec::new()
+        });
+        Self {
+            path,
+            providers: Mutex::new(providers),
+        }
+    }
+
+    pub fn list(&self) -> Vec<SatipProvider> {
+        self.providers.lock().unwrap().clone()
+    }
+
+    pub fn get(&self, id: &str) -> Result<SatipProvider, String> {
+        self.providers
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|p| p.id == id)
+            .cloned()
+            .ok_or_else(|| format!("SAT>IP server '{}' doesn't exist", id))
+    }
+
+    pub fn upsert(&self, provider: SatipProvider) -> Result<(), String> {
+        let mut providers = self.providers.lock().unwrap();
+        match providers.iter_mut().find(|p| p.id == provider.id) {
+            Some(existing) => *existing = provider,
+            None => providers.push(provider),
+        }
+        storage::write_json(&self.path, &*providers)
+    }
+
+    pub fn remove(&self, id: &str) -> Result<bool, String> {
+        let mut providers = self.providers.lock().unwrap();
+        let before = providers.len();
+        providers.retain(|p| p.id != id);
+        if providers.len() == before {
+            return Ok(false);
+        }
+        storage::write_json(&self.path, &*providers)?;
+        Ok(true)
+    }
+}
+
+#[derive(Default)]
+struct Streams {
+    next_id: u64,
+    open: HashMap<u64, (String, RtspSession)>,
+}
+
+// RTSP sessions the app holds open, by stream id
+#[derive(Default)]
+pub struct SatipStreams {
+    inner: Mutex<Streams>,
+}
+
+impl SatipStreams {
+    fn insert(&self, provider_id: &str, session: RtspSession) -> u64 {
+        let mut inner = self.inner.lock().unwrap();
+        inner.next_id += 1;
+        let id = inner.next_id;
+        inner.open.insert(id, (provider_id.to_string(), session));
+        id
+    }
+
+    fn get(&self, id: u64) -> Option<RtspSession> {
+        self.inner.lock().unwrap().open.get(&id).map(|(_, session)| session.clone())
+    }
+
+    fn take(&self, id: u64) -> Option<RtspSession> {
+        self.inner.lock().unwrap().open.remove(&id).map(|(_, session)| session)
+    }
+
+    fn take_provider(&self, provider_id: &str) -> Vec<RtspSession> {
+        let mut inner = self.inner.lock().unwrap();
+        let ids: Vec<u64> = inner
+            .open
+            .iter()
+            .filter(|(_, (provider, _))| provider == provider_id)
+            .map(|(id, _)| *id)
+            .collect();
+        ids.into_iter().filter_map(|id| inner.open.remove(&id)).map(|(_, session)| session).collect()
+    }
+}
+
+pub fn check_server(server: &SatipServer) -> Result<(), String> {
+    validate_string_length(&server.url, MAX_FIELD_LENGTH)
+}
+
+fn provider_client(providers: &SatipProviders, provider_id: &str) -> Result<SatipClient, String> {
+    SatipClient::new(&providers.get(provider_id)?.server)
+}
+
+// Tear down the provider's streams in the background and delete it
+pub fn forget_provider(app: &AppHandle, provider_id: &str) -> Result<bool, String> {
+    let sessions = app.state::<SatipStreams>().take_provider(provider_id);
+    if !sessions.is_empty() {
+        tauri::async_runtime::spawn_blocking(move || sessions.iter().for_each(rtsp::close));
+    }
+    app.state::<SatipProviders>().remove(provider_id)
+}
+
+// Refresh a stream's session until it's closed or the server drops it
+fn keep_alive(app: AppHandle, id: u64, timeout: u64) {
+    let every = Duration::from_secs(timeout.saturating_sub(KEEP_ALIVE_MARGIN)).max(MIN_KEEP_ALIVE);
+    tauri::async_runtime::spawn(async move {
+        loop {
+            tokio::time::sleep(every).await;
+            let Some(session) = app.state::<SatipStreams>().get(id) else {
+                return;
+            };
+            let result = tauri::async_runtime::spawn_blocking(move || rtsp::keep_alive(&session))
+                .await
+                .map_err(|e| e.to_string())
+                .and_then(|result| result);
+            if let Err(e) = result {
+                log::warn!("SAT>IP stream {} ended: {}", id, e);
+                app.state::<SatipStreams>().take(id);
+                return;
+            }
+        }
+    });
+}
+
+// A server found on the network, with its description when it answered
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveredServer {
+    pub usn: String,
+    pub location: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server: Option<ServerInfo>,
+}
+
+// An open stream: its id for satip_close_stream and the RTSP session
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SatipStream {
+    pub id: u64,
+    #[serde(flatten)]
+    pub session: RtspSession,
+}
+
+// Command handler looking for SAT>IP servers on the local network
+#[tauri::command]
+pub async fn satip_discover() -> Result<Vec<DiscoveredServer>, String> {
+    let found = tauri::async_runtime::spawn_blocking(|| discovery::discover(DISCOVERY_TIMEOUT))
+        .await
+        .map_err(|e| format!("SAT>IP discovery failed: {}", e))??;
+    let mut servers = Vec::new();
+    for found in found {
+        let server = SatipServer {
+            url: found.location.clone(),
+        };
+        let info = match SatipClient::new(&server) {
+            Ok(client) => client.describe().await,
+            Err(e) => Err(e),
+        };
+        let info = info
+            .inspect_err(|e| log::info!("No description from {}: {}", found.location, e))
+            .ok();
+        servers.push(DiscoveredServer {
+            usn: found.usn,
+            location: found.location,
+            server: info,
+        });
+    }
+    Ok(servers)
+}
+
+// Command handler that checks a server answers and saves it as a provider
+#[tauri::command]
+pub async fn satip_add_provider(
+    providers: State<'_, SatipProviders>,
+    name: String,
+    server: SatipServer,
+) -> Result<SatipProvider, String> {
+    check_server(&server)?;
+    validate_string_length(&name, MAX_NAME_LENGTH)?;
+    let client = SatipClient::new(&server)?;
+    let info = client.describe().await?;
+
+    let name = match name.trim() {
+        "" => info.friendly_name.as_str(),
+        name => name,
+    };
+    let provider = SatipProvider::new(&client, name);
+    providers.upsert(provider.clone())?;
+    Ok(provider)
+}
+
+// Command handler listing the saved servers
+#[tauri::command]
+pub fn satip_list_providers(providers: State<'_, SatipProviders>) -> Vec<SatipProvider> {
+    providers.list()
+}
+
+// Command handler that deletes a saved server and ends its streams
+#[tauri::command]
+pub fn satip_remove_provider(app: AppHandle, provider_id: String) -> Result<bool, String> {
+    forget_provider(&app, &provider_id)
+}
+
+// Command handler describing a saved server: name, model, front ends
+#[tauri::command]
+pub async fn satip_get_server(providers: State<'_, SatipProviders>, provider_id: String) -> Result<ServerInfo, String> {
+    provider_client(&providers, &provider_id)?.describe().await
+}
+
+// Command handler listing a server's channels with their tuning parameters
+#[tauri::command]
+pub async fn satip_get_channels(
+    providers: State<'_, SatipProviders>,
+    provider_id: String,
+) -> Result<Vec<SatipChannel>, String> {
+    let client = provider_client(&providers, &provider_id)?;
+    client.channels(&client.describe().await?, &provider_id).await
+}
+
+// Command handler that tunes a channel and streams it over RTP to
+// `client_port` (and RTCP to the port after it) until it's closed
+#[tauri::command]
+pub async fn satip_open_stream(
+    app: AppHandle,
+    providers: State<'_, SatipProviders>,
+    provider_id: String,
+    url: String,
+    client_port: u16,
+) -> Result<SatipStream, String> {
+    validate_string_length(&url, MAX_FIELD_LENGTH)?;
+    providers.get(&provider_id)?;
+    let session = tauri::async_runtime::spawn_blocking(move || rtsp::open(&url, client_port))
+        .await
+        .map_err(|e| format!("SAT>IP stream setup failed: {}", e))??;
+    let timeout = session.timeout;
+    let id = app.state::<SatipStreams>().insert(&provider_id, session.clone());
+    keep_alive(app, id, timeout);
+    Ok(SatipStream { id, session })
+}
+
+// Command handler that ends a stream and frees its tuner
+#[tauri::command]
+pub async fn satip_close_stream(streams: State<'_, SatipStreams>, stream_id: u64) -> Result<bool, String> {
+    let Some(session) = streams.take(stream_id) else {
+        return Ok(false);
+    };
+    tauri::async_runtime::spawn_blocking(move || rtsp::close(&session))
+        .await
+        .map_err(|e| format!("SAT>IP teardown failed: {}", e))?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn test_providers_and_streams() {
+        // Test that saved servers survive reopening and a provider's streams are taken together
+        let dir = temp_dir("satip-providers");
+        let providers = SatipProviders::open(&dir);
+        let client = SatipClient::new(&SatipServer {
+            url: "192.168.178.1:49000/satipdesc.xml".to_string(),
+        })
+        .unwrap();
+        let provider = SatipProvider::new(&client, "");
+        assert_eq!(provider.name, "http://192.168.178.1:49000/satipdesc.xml");
+        providers.upsert(provider.clone()).unwrap();
+        assert_eq!(SatipProviders::open(&dir).get(&provider.id).unwrap(), provider);
+
+        let session = |stream_id: &str| RtspSession {
+            control: format!("rtsp://192.168.178.1/stream={}", stream_id),
+            session: "1".to_string(),
+            stream_id: stream_id.to_string(),
+            timeout: 60,
+            client_port: 5000,
+        };
+        let streams = SatipStreams::default();
+        let first = streams.insert(&provider.id, session("1"));
+        streams.insert("other", session("2"));
+        streams.insert(&provider.id, session("3"));
+        assert_eq!(streams.take(first).unwrap().stream_id, "1");
+        assert_eq!(streams.take_provider(&provider.id).len(), 1);
+        assert!(streams.get(first).is_none());
+        assert_eq!(streams.take_provider("other").len(), 1);
+    }
+}
@@ -0,0 +1,203 @@
+// SAT>IP device descriptions and the tuning parameters of channel URLs
+use std::collections::BTreeMap;
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde::Serialize;
+use url::Url;
+
+use crate::xml::append_text;
+
+// Front ends of one delivery system, from X_SATIPCAP ("DVBS2-2")
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Frontend {
+    pub system: String,
+    pub count: u32,
+}
+
+// What a server's device description says about it
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerInfo {
+    pub friendly_name: String,
+    pub manufacturer: String,
+    pub model_name: String,
+    pub udn: String,
+    pub frontends: Vec<Frontend>,
+    // Channel list the server publishes, absolute
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub m3u_url: Option<String>,
+}
+
+impl ServerInfo {
+    // Streams the server can tune at once; 0 when it doesn't say
+    pub fn tuner_count(&self) -> u32 {
+        self.frontends.iter().map(|frontend| frontend.count).sum()
+    }
+}
+
+fn parse_frontends(capabilities: &str) -> Vec<Frontend> {
+    capabilities
+        .split(',')
+        .filter_map(|item| {
+            let (system, count) = item.trim().rsplit_once('-')?;
+            Some(Frontend {
+                system: system.to_string(),
+                count: count.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+// Read the fields we need from a device description; relative links are
+// resolved against the description's own URL
+pub fn parse_description(xml: &str, location: &Url) -> Result<ServerInfo, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut info = ServerInfo::default();
+    let mut field: Option<Vec<u8>> = None;
+    let mut text = String::new();
+    let mut m3u = String::new();
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| format!("Invalid SAT>IP device description: {}", e))?;
+        match &event {
+            Event::Start(start) => {
+                field = Some(start.local_name().as_ref().to_vec());
+                text.clear();
+            }
+            Event::End(end) => {
+                let value = text.trim();
+                let target = match end.local_name().as_ref() {
+                    b"friendlyName" => Some(&mut info.friendly_name),
+                    b"manufacturer" => Some(&mut info.manufacturer),
+                    b"modelName" => Some(&mut info.model_name),
+                    b"UDN" => Some(&mut info.udn),
+                    b"X_SATIPM3U" => Some(&mut m3u),
+                    b"X_SATIPCAP" if info.frontends.is_empty() => {
+                        info.frontends = parse_frontends(value);
+                        None
+                    }
+                    _ => None,
+                };
+                // Embedded devices come later; the root device's values win
+                if let Some(target) = target.filter(|target| target.is_empty()) {
+                    *target = value.to_string();
+                }
+                field = None;
+                text.clear();
+            }
+            Event::Eof => break,
+            event => {
+                if field.is_some() {
+                    append_text(&mut text, event);
+                }
+            }
+        }
+    }
+    if !m3u.is_empty() {
+        let url = location
+            .join(&m3u)
+            .map_err(|e| format!("Invalid channel list link '{}': {}", m3u, e))?;
+        info.m3u_url = Some(url.to_string());
+    }
+    Ok(info)
+}
+
+// Tuning parameters of an rtsp://server/?src=1&freq=11494&pol=h... URL.
+// Well-known ones are typed, the rest (pids, plp, t2id ...) kept as given
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Tuning {
+    // dvbs, dvbs2, dvbt, dvbt2, dvbc ...
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub msys: Option<String>,
+    // Satellite position (DiSEqC input), 1-based
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub src: Option<u32>,
+    // MHz
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub freq: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pol: Option<String>,
+    // Symbol rate, kSymb/s
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sr: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fec: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mtype: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub extra: BTreeMap<String, String>,
+}
+
+impl Tuning {
+    // None for URLs that aren't RTSP
+    pub fn from_url(url: &str) -> Option<Self> {
+        let url = Url::parse(url.trim()).ok().filter(|url| url.scheme() == "rtsp")?;
+        let mut tuning = Tuning::default();
+        for (key, value) in url.query_pairs() {
+            let key = key.to_ascii_lowercase();
+            let value = value.trim().to_string();
+            match key.as_str() {
+                "msys" => tuning.msys = Some(value.to_ascii_lowercase()),
+                "src" => tuning.src = value.parse().ok(),
+                "freq" => tuning.freq = value.parse().ok(),
+                "pol" => tuning.pol = Some(value.to_ascii_lowercase()),
+                "sr" => tuning.sr = value.parse().ok(),
+                "fec" => tuning.fec = Some(value),
+                "mtype" => tuning.mtype = Some(value.to_ascii_lowercase()),
+                _ => {
+                    tuning.extra.insert(key, value);
+                }
+            }
+        }
+        Some(tuning)
+    }
+}
+
+// A channel of the server's list
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SatipChannel {
+    // Stable identity (see crate::identity)
+    pub channel_id: String,
+    pub name: String,
+    // rtsp:// URL carrying the tuning parameters
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub number: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logo: Option<String>,
+    pub tuning: Tuning,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_description_and_tuning() {
+        // Test reading a description with namespaced SAT>IP fields and a channel URL's parameters
+        let xml = r#"<?xml version="1.0"?>
+            <root xmlns="urn:schemas-upnp-org:device-1-0"><device>
+              <deviceType>urn:ses-com:device:SatIPServer:1</deviceType>
+              <friendlyName>FRITZ!Box 6591 Cable</friendlyName><manufacturer>AVM</manufacturer>
+              <UDN>uuid:1234</UDN>
+              <satip:X_SATIPCAP xmlns:satip="urn:ses-com:satip">DVBC-4,DVBC2-2</satip:X_SATIPCAP>
+              <satip:X_SATIPM3U xmlns:satip="urn:ses-com:satip">/dvb/m3u/tvhd.m3u</satip:X_SATIPM3U>
+            </device></root>"#;
+        let location = Url::parse("http://192.168.178.1:49000/satipdesc.xml").unwrap();
+        let info = parse_description(xml, &location).unwrap();
+        assert_eq!(info.friendly_name, "FRITZ!Box 6591 Cable");
+        assert_eq!(info.tuner_count(), 6);
+        assert_eq!(info.m3u_url.as_deref(), Some("http://192.168.178.1:49000/dvb/m3u/tvhd.m3u"));
+
+        let tuning = Tuning::from_url("rtsp://192.168.178.1:554/?src=1&freq=11494&pol=H&msys=DVBS2&sr=22000&pids=0,17").unwrap();
+        assert_eq!((tuning.src, tuning.freq, tuning.sr), (Some(1), Some(11494.0), Some(22000)));
+        assert_eq!((tuning.pol.as_deref(), tuning.msys.as_deref()), (Some("h"), Some("dvbs2")));
+        assert_eq!(tuning.extra["pids"], "0,17");
+        assert!(Tuning::from_url("http://192.168.178.1/?freq=1").is_none());
+    }
+}
@@ -0,0 +1,193 @@
+// Minimal RTSP client for SAT>IP stream setup: SETUP tunes a front end and
+// names the UDP ports the RTP stream goes to, PLAY starts it, OPTIONS keeps
+// the session alive and TEARDOWN frees the tuner. Blocking, one TCP
+// connection per request, so callers run it off the async runtime
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use serde::Serialize;
+use url::Url;
+
+const RTSP_PORT: u16 = 554;
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Servers drop sessions not refreshed within this many seconds unless
+// they say otherwise
+const DEFAULT_SESSION_TIMEOUT: u64 = 60;
+
+// Longest response head accepted
+const MAX_HEAD_LINES: usize = 64;
+
+// A running stream on a server
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RtspSession {
+    // rtsp://server/stream=<id>, the URL later requests go to
+    pub control: String,
+    pub session: String,
+    pub stream_id: String,
+    // Seconds the server keeps the session without a request
+    pub timeout: u64,
+    // First of the two local UDP ports (RTP, RTCP) the stream is sent to
+    pub client_port: u16,
+}
+
+struct Response {
+    status: u16,
+    reason: String,
+    headers: Vec<(String, String)>,
+}
+
+impl Response {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn request(url: &Url, method: &str, headers: &[(&str, String)]) -> Result<Response, String> {
+    let host = url.host_str().ok_or_else(|| format!("Invalid RTSP address '{}'", url))?;
+    let port = url.port().unwrap_or(RTSP_PORT);
+    let error = |e: std::io::Error| format!("RTSP {} to {}:{} failed: {}", method, host, port, e);
+    let address = (host, port)
+        .to_socket_addrs()
+        .map_err(error)?
+        .next()
+        .ok_or_else(|| format!("Couldn't resolve {}", host))?;
+    let mut stream = TcpStream::connect_timeout(&address, IO_TIMEOUT).map_err(error)?;
+    stream.set_read_timeout(Some(IO_TIMEOUT)).map_err(error)?;
+    stream.set_write_timeout(Some(IO_TIMEOUT)).map_err(error)?;
+
+    let mut head = format!("{} {} RTSP/1.0\r\nCSeq: 1\r\n", method, url);
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).map_err(error)?;
+
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(error)?;
+    let mut status_line = line.trim().splitn(3, ' ');
+    if !status_line.next().is_some_and(|version| version.starts_with("RTSP/")) {
+        return Err(format!("{}:{} didn't answer as an RTSP server", host, port));
+    }
+    let status = status_line.next().and_then(|code| code.parse().ok()).unwrap_or(0);
+    let reason = status_line.next().unwrap_or_default().to_string();
+
+    let mut headers = Vec::new();
+    for _ in 0..MAX_HEAD_LINES {
+        line.clear();
+        if reader.read_line(&mut line).map_err(error)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    let response = Response { status, reason, headers };
+    // Drain any body so the server isn't reset mid-write
+    let length: u64 = response.header("Content-Length").and_then(|v| v.parse().ok()).unwrap_or(0);
+    let _ = std::io::copy(&mut reader.by_ref().take(length), &mut std::io::sink());
+
+    match response.status {
+        200 => Ok(response),
+        // No free front end
+        503 => Err("The SAT>IP server has no free tuner".to_string()),
+        status => Err(format!("The SAT>IP server refused {}: {} {}", method, status, response.reason)),
+    }
+}
+
+// Session id and timeout from "Session: 12345678;timeout=30"
+fn parse_session(value: &str) -> (String, u64) {
+    let mut parts = value.split(';');
+    let id = parts.next().unwrap_or_default().trim().to_string();
+    let timeout = parts
+        .filter_map(|part| part.trim().strip_prefix("timeout="))
+        .find_map(|timeout| timeout.parse().ok())
+        .unwrap_or(DEFAULT_SESSION_TIMEOUT);
+    (id, timeout)
+}
+
+// Tune the channel at `url` and start streaming it to `client_port` and
+// the port after it
+pub fn open(url: &str, client_port: u16) -> Result<RtspSession, String> {
+    let url = Url::parse(url.trim()).map_err(|e| format!("Invalid channel URL '{}': {}", url, e))?;
+    if url.scheme() != "rtsp" {
+        return Err(format!("Channel URL '{}' isn't an rtsp:// URL", url));
+    }
+    let rtcp_port = client_port
+        .checked_add(1)
+        .ok_or_else(|| format!("Client port {} leaves no room for RTCP", client_port))?;
+    let transport = format!("RTP/AVP;unicast;client_port={}-{}", client_port, rtcp_port);
+    let setup = request(&url, "SETUP", &[("Transport", transport)])?;
+    let (session, timeout) = parse_session(setup.header("Session").unwrap_or_default());
+    let stream_id = setup.header("com.ses.streamID").unwrap_or_default().trim().to_string();
+    if session.is_empty() || stream_id.is_empty() {
+        return Err("The SAT>IP server didn't start a session".to_string());
+    }
+
+    let mut control = url.clone();
+    control.set_path(&format!("/stream={}", stream_id));
+    control.set_query(None);
+    let session = RtspSession {
+        control: control.to_string(),
+        session,
+        stream_id,
+        timeout,
+        client_port,
+    };
+    if let Err(e) = request(&control, "PLAY", &[("Session", session.session.clone())]) {
+        close(&session);
+        return Err(e);
+    }
+    Ok(session)
+}
+
+// Refresh the session before the server's timeout ends it
+pub fn keep_alive(session: &RtspSession) -> Result<(), String> {
+    let url = Url::parse(&session.control).map_err(|e| e.to_string())?;
+    request(&url, "OPTIONS", &[("Session", session.session.clone())]).map(|_| ())
+}
+
+// End the stream; failures only mean the server already dropped it
+pub fn close(session: &RtspSession) {
+    let result = Url::parse(&session.control)
+        .map_err(|e| e.to_string())
+        .and_then(|url| request(&url, "TEARDOWN", &[("Session", session.session.clone())]));
+    if let Err(e) = result {
+        log::info!("SAT>IP teardown of stream {} failed: {}", session.stream_id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::serve;
+
+    #[test]
+    fn test_open_and_close() {
+        // Test that SETUP names the client ports and PLAY and TEARDOWN use the session
+        let answer = |headers: &str| format!("RTSP/1.0 200 OK\r\nCSeq: 1\r\n{}\r\n", headers);
+        let (base, requests) = serve(vec![
+            answer("Session: 12345678;timeout=30\r\ncom.ses.streamID: 3\r\n"),
+            answer(""),
+            answer(""),
+        ]);
+        let url = format!("{}/?src=1&freq=11494&pol=h&msys=dvbs2", base.replace("http://", "rtsp://"));
+        let session = open(&url, 5000).unwrap();
+        assert_eq!((session.session.as_str(), session.stream_id.as_str(), session.timeout), ("12345678", "3", 30));
+        assert!(session.control.ends_with("/stream=3"));
+        close(&session);
+
+        let requests = requests.lock().unwrap();
+        assert!(requests[0].starts_with(&format!("SETUP {} RTSP/1.0\r\n", url)));
+        assert!(requests[0].contains("Transport: RTP/AVP;unicast;client_port=5000-5001\r\n"));
+        assert!(requests[1].starts_with(&format!("PLAY {} ", session.control)));
+        assert!(requests[2].starts_with("TEARDOWN ") && requests[2].contains("Session: 12345678\r\n"));
+        assert!(open(&url.replace("rtsp://", "http://"), 5000).is_err());
+    }
+}
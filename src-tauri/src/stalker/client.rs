@@ -0,0 +1,379 @@
+// HTTP client for Stalker / Ministra middleware portals, which identify
+// set-top boxes by MAC address and hand out a session token on handshake
+use std::sync::Mutex;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use url::Url;
+
+use super::model::{self, Genre, Profile, RawProgramme, StalkerChannel};
+use super::stb::{StbIdentity, StbModel};
+use crate::de;
+use crate::epg::Programme;
+use crate::http::{self, HttpOptions};
+
+// Timezone the box reports when none is configured
+const DEFAULT_TIMEZONE: &str = "UTC";
+
+// Portal address plus the MAC address registered with the provider, and how
+// the emulated box presents itself
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StalkerPortal {
+    pub url: String,
+    pub mac: String,
+    #[serde(default)]
+    pub model: StbModel,
+    // Serial number registered with the portal, generated from the MAC
+    // when missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serial: Option<String>,
+    // IANA timezone like "Europe/Istanbul", used for the portal's EPG times
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+}
+
+// Why a portal call failed; refusals can be retried with a fresh session
+enum CallError {
+    Unauthorized,
+    Failed(String),
+}
+
+// "00:1a:79:12:34:56" or "00-1A-79-12-34-56" → "00:1A:79:12:34:56"
+pub fn normalize_mac(mac: &str) -> Result<String, String> {
+    let pairs: Vec<&str> = mac.trim().split([':', '-']).collect();
+    let valid = pairs.len() == 6 && pairs.iter().all(|p| p.len() == 2 && p.bytes().all(|b| b.is_ascii_hexdigit()));
+    if !valid {
+        return Err(format!("'{}' isn't a MAC address like 00:1A:79:12:34:56", mac.trim()));
+    }
+    Ok(pairs.join(":").to_ascii_uppercase())
+}
+
+// The API script and the portal root (used as the Referer) of a portal
+// address: "host/c/", ".../stalker_portal/c/" or a pasted portal.php /
+// server/load.php URL
+pub fn normalize_portal(portal: &str) -> Result<(Url, Url), String> {
+    let portal = portal.trim();
+    if portal.is_empty() {
+        return Err("Portal address cannot be empty".to_string());
+    }
+    let with_scheme = if portal.contains("://") {
+        portal.to_string()
+    } else {
+        format!("http://{}", portal)
+    };
+    let mut url = Url::parse(&with_scheme).map_err(|e| format!("Invalid portal address '{}': {}", portal, e))?;
+    if !matches!(url.scheme(), "http" | "https") || !url.has_host() {
+        return Err(format!("Invalid portal address '{}'", portal));
+    }
+    url.set_query(None);
+    url.set_fragment(None);
+
+    let path = url.path().trim_end_matches('/').to_string();
+    let (root, api) = match path.rsplit_once('/') {
+        Some((dir, script)) if script.ends_with(".php") => {
+            let root = dir.strip_suffix("/server").unwrap_or(dir).to_string();
+            (root, path.clone())
+        }
+        _ => {
+            let root = path.strip_suffix("/c").unwrap_or(&path).to_string();
+            let api = if root.ends_with("stalker_portal") {
+                format!("{}/server/load.php", root)
+            } else {
+                format!("{}/portal.php", root)
+            };
+            (root, api)
+        }
+    };
+    let mut api_url = url.clone();
+    api_url.set_path(&api);
+    url.set_path(&format!("{}/", root));
+    Ok((api_url, url))
+}
+
+pub struct StalkerClient {
+    api: Url,
+    root: Url,
+    mac: String,
+    identity: StbIdentity,
+    timezone: String,
+    token: Mutex<Option<String>>,
+}
+
+impl StalkerClient {
+    pub fn new(portal: &StalkerPortal) -> Result<Self, String> {
+        let (api, root) = normalize_portal(&portal.url)?;
+        let mac = normalize_mac(&portal.mac)?;
+        Ok(Self {
+            api,
+            root,
+            identity: StbIdentity::new(portal.model, &mac, portal.serial.as_deref()),
+            timezone: match portal.timezone.as_deref().map(str::trim) {
+                Some(timezone) if !timezone.is_empty() => timezone.to_string(),
+                _ => DEFAULT_TIMEZONE.to_string(),
+            },
+            mac,
+            token: Mutex::new(None),
+        })
+    }
+
+    pub fn api(&self) -> &str {
+        self.api.as_str()
+    }
+
+    pub fn mac(&self) -> &str {
+        &self.mac
+    }
+
+    fn headers(&self) -> HttpOptions {
+        let model = self.identity.model;
+        let mut options = HttpOptions {
+            user_agent: Some(model.user_agent().to_string()),
+            referrer: Some(format!("{}c/", self.root)),
+            ..Default::default()
+        };
+        let encode = |value: &str| url::form_urlencoded::byte_serialize(value.as_bytes()).collect::<String>();
+        options.set_header("X-User-Agent", &model.x_user_agent());
+        options.set_header(
+            "Cookie",
+            &format!("mac={}; stb_lang=en; timezone={}", encode(&self.mac), encode(&self.timezone)),
+        );
+        if let Some(token) = self.token.lock().unwrap().as_deref() {
+            options.set_header("Authorization", &format!("Bearer {}", token));
+        }
+        options
+    }
+
+    pub fn api_url(&self, kind: &str, action: &str, params: &[(&str, &str)]) -> Url {
+        let mut url = self.api.clone();
+        url.query_pairs_mut()
+            .append_pair("type", kind)
+            .append_pair("action", action)
+            .extend_pairs(params)
+            .append_pair("JsHttpRequest", "1-xml");
+        url
+    }
+
+    fn describe(&self, error: CallError) -> String {
+        match error {
+            CallError::Unauthorized => format!("The portal refused MAC {}", self.mac),
+            CallError::Failed(message) => message,
+        }
+    }
+
+    async fn fetch<T: DeserializeOwned>(&self, kind: &str, action: &str, params: &[(&str, &str)]) -> Result<T, CallError> {
+        let failed = |message: String| CallError::Failed(message);
+        let url = self.api_url(kind, action, params);
+        let response = http::get(url.as_str(), Some(&self.headers()))
+            .send()
+            .await
+            .map_err(|e| failed(format!("Stalker request '{}' failed: {}", action, e.without_url())))?;
+        let status = response.status();
+        if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+            return Err(CallError::Unauthorized);
+        }
+        if !status.is_success() {
+            return Err(failed(format!("Stalker request '{}' failed with HTTP {}", action, status.as_u16())));
+        }
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| failed(format!("Stalker request '{}' failed: {}", action, e.without_url())))?;
+        // Unauthorized sessions get a plain-text "Authorization failed."
+        let mut answer: Value = serde_json::from_slice(&body).map_err(|_| match String::from_utf8_lossy(&body).trim() {
+            text if text.to_ascii_lowercase().contains("authorization failed") => CallError::Unauthorized,
+            _ => failed(format!("Invalid Stalker response to '{}'", action)),
+        })?;
+        let js = answer.get_mut("js").map(Value::take).unwrap_or_default();
+        serde_json::from_value(js).map_err(|e| failed(format!("Invalid Stalker response to '{}': {}", action, e)))
+    }
+
+    // Call an action and decode the "js" member of the answer. Session
+    // tokens expire on the portal's schedule, so a refusal after login
+    // renews the session once and repeats the call
+    pub async fn call<T: DeserializeOwned>(&self, kind: &str, action: &str, params: &[(&str, &str)]) -> Result<T, String> {
+        match self.fetch(kind, action, params).await {
+            Err(CallError::Unauthorized) if self.token.lock().unwrap().is_some() => {
+                log::info!("Stalker session for MAC {} expired, logging in again", self.mac);
+                self.connect().await?;
+                self.fetch(kind, action, params).await
+            }
+            result => result,
+        }
+        .map_err(|e| self.describe(e))
+    }
+
+    // Handshake for a session token, then load the box profile, which is
+    // what actually activates the session on most portals
+    pub async fn connect(&self) -> Result<Profile, String> {
+        #[derive(Default, Deserialize)]
+        #[serde(default)]
+        struct Handshake {
+            #[serde(deserialize_with = "de::string")]
+            token: String,
+        }
+
+        *self.token.lock().unwrap() = None;
+        let handshake: Handshake = self
+            .fetch("stb", "handshake", &[("token", "")])
+            .await
+            .map_err(|e| self.describe(e))?;
+        if handshake.token.is_empty() {
+            return Err(format!("Stalker login failed: the portal didn't accept MAC {}", self.mac));
+        }
+        *self.token.lock().unwrap() = Some(handshake.token);
+
+        let box_params = self.identity.profile_params();
+        let mut params = vec![("hd", "1")];
+        params.extend(box_params.iter().map(|(key, value)| (*key, value.as_str())));
+        let profile: Profile =
+            de::object_value(self.fetch("stb", "get_profile", &params).await.map_err(|e| self.describe(e))?);
+        if profile.blocked {
+            return Err(format!("Stalker login failed: MAC {} is blocked", self.mac));
+        }
+        Ok(profile)
+    }
+
+    pub async fn genres(&self) -> Result<Vec<Genre>, String> {
+        Ok(de::list(self.call("itv", "get_genres", &[]).await?))
+    }
+
+    pub async fn channels(&self) -> Result<Vec<StalkerChannel>, String> {
+        let mut answer: Value = self.call("itv", "get_all_channels", &[]).await?;
+        let data = answer.get_mut("data").map(Value::take).unwrap_or(answer);
+        Ok(de::list(data))
+    }
+
+    // Playable URL for a channel's player command; portals answer with a
+    // command carrying a short-lived play token
+    pub async fn create_link(&self, cmd: &str) -> Result<String, String> {
+        #[derive(Default, Deserialize)]
+        #[serde(default)]
+        struct Link {
+            #[serde(deserialize_with = "de::string")]
+            cmd: String,
+        }
+
+        let link: Link = de::object_value(
+            self.call("itv", "create_link", &[("cmd", cmd), ("forced_storage", "0"), ("disable_ad", "0")])
+                .await?,
+        );
+        match model::stream_url(&link.cmd) {
+            url if url.is_empty() => Err("The portal didn't return a stream link".to_string()),
+            url => Ok(url),
+        }
+    }
+
+    pub async fn short_epg(&self, channel_id: &str, size: u32) -> Result<Vec<Programme>, String> {
+        let size = size.to_string();
+        let answer: Value = self
+            .call("itv", "get_short_epg", &[("ch_id", channel_id), ("size", &size)])
+            .await?;
+        let mut programmes: Vec<Programme> = de::list::<RawProgramme>(answer)
+            .into_iter()
+            .filter_map(RawProgramme::into_programme)
+            .collect();
+        programmes.sort_by_key(|programme| programme.start);
+        Ok(programmes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{response, serve};
+
+    fn client(url: &str) -> StalkerClient {
+        StalkerClient::new(&StalkerPortal {
+            url: url.to_string(),
+            mac: "00-1a-79-aa-bb-cc".to_string(),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_normalize_portal() {
+        // Test that the common ways of writing a portal address find the API
+        let api = |s: &str| normalize_portal(s).unwrap().0.to_string();
+        assert_eq!(api("example.com:8080/c/"), "http://example.com:8080/portal.php");
+        assert_eq!(
+            api("http://example.com/stalker_portal/c/"),
+            "http://example.com/stalker_portal/server/load.php"
+        );
+        assert_eq!(api("http://example.com/stalker_portal/server/load.php?x=1"), "http://example.com/stalker_portal/server/load.php");
+        assert_eq!(normalize_portal("http://example.com/portal.php").unwrap().1.as_str(), "http://example.com/");
+        assert!(normalize_mac("00:1A:79:12:34").is_err());
+        assert_eq!(normalize_mac("00-1a-79-12-34-56").unwrap(), "00:1A:79:12:34:56");
+    }
+
+    #[test]
+    fn test_connect_and_channels() {
+        // Test the handshake token, box headers and channel lookups
+        let (base, requests) = serve(vec![
+            response("200 OK", &[], r#"{"js":{"token":"T0K"}}"#),
+            response("200 OK", &[], r#"{"js":{"id":"7","name":"Box","blocked":"0"}}"#),
+            response("200 OK", &[], r#"{"js":{"total_items":1,"data":[{"id":"1","name":"One","cmd":"ffmpeg http://s/1"}]}}"#),
+            response("200 OK", &[], r#"{"js":{"id":"1","cmd":"ffmpeg http://s/1?play_token=abc"}}"#),
+        ]);
+        let client = client(&format!("{}/c/", base));
+
+        let profile = tauri::async_runtime::block_on(client.connect()).unwrap();
+        assert_eq!(profile.id, "7");
+        let channels = tauri::async_runtime::block_on(client.channels()).unwrap();
+        assert_eq!(channels[0].name, "One");
+        let link = tauri::async_runtime::block_on(client.create_link(&channels[0].cmd)).unwrap();
+        assert_eq!(link, "http://s/1?play_token=abc");
+
+        let requests = requests.lock().unwrap();
+        assert!(requests[0].starts_with("GET /portal.php?type=stb&action=handshake&token=&JsHttpRequest=1-xml "));
+        assert!(requests[0].contains("mac=00%3A1A%3A79%3AAA%3ABB%3ACC"));
+        assert!(requests[0].contains(&format!("{}/c/", base)));
+        assert!(!requests[0].to_ascii_lowercase().contains("authorization:"));
+        assert!(requests[0].contains("timezone=UTC"));
+        assert!(requests[0].contains("Model: MAG250; Link: WiFi"));
+        assert!(requests[1].contains("Bearer T0K"));
+        assert!(requests[1].contains(&format!("&sn={}&", StbIdentity::new(StbModel::Mag250, client.mac(), None).serial)));
+    }
+
+    #[test]
+    fn test_expired_session_renews() {
+        // Test that a refused call logs in again and repeats the request
+        let (base, requests) = serve(vec![
+            response("200 OK", &[], r#"{"js":{"token":"OLD"}}"#),
+            response("200 OK", &[], r#"{"js":{"id":"7"}}"#),
+            response("403 Forbidden", &[], ""),
+            response("200 OK", &[], r#"{"js":{"token":"NEW"}}"#),
+            response("200 OK", &[], r#"{"js":{"id":"7"}}"#),
+            response("200 OK", &[], r#"{"js":[{"id":"*","title":"All"}]}"#),
+        ]);
+        let client = StalkerClient::new(&StalkerPortal {
+            url: format!("{}/c/", base),
+            mac: "00:1A:79:AA:BB:CC".to_string(),
+            model: StbModel::Mag322,
+            serial: None,
+            timezone: Some("Europe/Istanbul".to_string()),
+        })
+        .unwrap();
+
+        tauri::async_runtime::block_on(client.connect()).unwrap();
+        let genres = tauri::async_runtime::block_on(client.genres()).unwrap();
+        assert_eq!(genres[0].title, "All");
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 6);
+        assert!(requests[0].contains("timezone=Europe%2FIstanbul"));
+        assert!(requests[0].contains("Model: MAG322; Link: Ethernet"));
+        assert!(requests[3].contains("action=handshake"));
+        assert!(requests[5].contains("Bearer NEW"));
+    }
+
+    #[test]
+    fn test_refused_mac() {
+        // Test that the plain-text refusal becomes a readable error
+        let (base, _) = serve(vec![response("200 OK", &[], "Authorization failed.")]);
+        let error = tauri::async_runtime::block_on(client(&base).connect()).unwrap_err();
+        assert_eq!(error, "The portal refused MAC 00:1A:79:AA:BB:CC");
+    }
+}
@@ -0,0 +1,269 @@
+// Stalker / Ministra portals: saved set-top box identities and the portal
+// client, alongside the Xtream providers
+mod client;
+mod model;
+mod session;
+mod stb;
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+
+use crate::epg::Programme;
+use crate::identity;
+use crate::playlist::content_hash;
+use crate::storage;
+use crate::validate_string_length;
+
+pub use client::{StalkerClient, StalkerPortal};
+pub use model::{Genre, Profile, StalkerChannel};
+pub use session::StalkerSessions;
+
+const PROVIDERS_FILE: &str = "stalker_providers.json";
+
+// Limits for user-entered portal details
+const MAX_FIELD_LENGTH: usize = 2048;
+const MAX_NAME_LENGTH: usize = 200;
+
+// Programmes get_short_epg returns by default and at most
+const DEFAULT_EPG_SIZE: u32 = 4;
+const MAX_EPG_SIZE: u32 = 50;
+
+// A saved portal account
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StalkerProvider {
+    pub id: String,
+    pub name: String,
+    #[serde(flatten)]
+    pub portal: StalkerPortal,
+}
+
+// Same portal and MAC always map to the same provider
+fn provider_id(client: &StalkerClient) -> String {
+    content_hash(format!("stalker:{}:{}", client.api(), client.mac()).as_bytes())
+}
+
+impl StalkerProvider {
+    // A provider with a normalized portal; an empty name becomes the API
+    // address
+    pub fn new(client: &StalkerClient, name: &str, portal: &StalkerPortal) -> Self {
+        let trimmed = |value: Option<&str>| value.map(str::trim).filter(|v| !v.is_empty()).map(str::to_string);
+        StalkerProvider {
+            id: provider_id(client),
+            name: match name.trim() {
+                "" => client.api().to_string(),
+                name => name.to_string(),
+            },
+            portal: StalkerPortal {
+                url: portal.url.trim().to_string(),
+                mac: client.mac().to_string(),
+                model: portal.model,
+                serial: trimmed(portal.serial.as_deref()),
+                timezone: trimmed(portal.timezone.as_deref()),
+            },
+        }
+    }
+}
+
+pub struct StalkerProviders {
+    path: PathBuf,
+    providers: Mutex<Vec<StalkerProvider>>,
+}
+
+impl StalkerProviders {
+    pub fn open(data_dir: &Path) -> Self {
+        let path = data_dir.join(PROVIDERS_FILE);
+        let providers = storage::read_json(&path).unwrap_or_else(|e| {
+            log::error!("Failed to load Stalker providers: {}", e);
+            Vec::new()
+        });
+        Self {
+            path,
+            providers: Mutex::new(providers),
+        }
+    }
+
+    pub fn list(&self) -> Vec<StalkerProvider> {
+        self.providers.lock().unwrap().clone()
+    }
+
+    pub fn get(&self, id: &str) -> Result<StalkerProvider, String> {
+        self.providers
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|p| p.id == id)
+            .cloned()
+            .ok_or_else(|| format!("Stalker provider '{}' doesn't exist", id))
+    }
+
+    pub fn upsert(&self, provider: StalkerProvider) -> Result<(), String> {
+        let mut providers = self.providers.lock().unwrap();
+        match providers.iter_mut().find(|p| p.id == provider.id) {
+            Some(existing) => *existing = provider,
+            None => providers.push(provider),
+        }
+        storage::write_json(&self.path, &*providers)
+    }
+
+    pub fn remove(&self, id: &str) -> Result<bool, String> {
+        let mut providers = self.providers.lock().unwrap();
+        let before = providers.len();
+        providers.retain(|p| p.id != id);
+        if providers.len() == before {
+            return Ok(false);
+        }
+        storage::write_json(&self.path, &*providers)?;
+        Ok(true)
+    }
+}
+
+pub fn check_portal(portal: &StalkerPortal) -> Result<(), String> {
+    validate_string_length(&portal.url, MAX_FIELD_LENGTH)?;
+    validate_string_length(&portal.mac, MAX_FIELD_LENGTH)?;
+    validate_string_length(portal.serial.as_deref().unwrap_or_default(), MAX_FIELD_LENGTH)?;
+    validate_string_length(portal.timezone.as_deref().unwrap_or_default(), MAX_NAME_LENGTH)
+}
+
+// A client with an open session for a saved provider
+async fn connected(
+    providers: &StalkerProviders,
+    sessions: &StalkerSessions,
+    provider_id: &str,
+) -> Result<Arc<StalkerClient>, String> {
+    sessions.client(provider_id, &providers.get(provider_id)?.portal).await
+}
+
+// A new link for a channel whose play token expired, from a fresh session
+pub async fn renew_link(app: &AppHandle, provider_id: &str, cmd: &str) -> Result<String, String> {
+    let sessions = app.state::<StalkerSessions>();
+    sessions.invalidate(provider_id);
+    connected(&app.state::<StalkerProviders>(), &sessions, provider_id).await?.create_link(cmd.trim()).await
+}
+
+// Remove a saved provider and its session
+pub fn forget_provider(app: &AppHandle, provider_id: &str) -> Result<bool, String> {
+    app.state::<StalkerSessions>().invalidate(provider_id);
+    app.state::<StalkerProviders>().remove(provider_id)
+}
+
+// Command handler that logs in to a portal without saving it
+#[tauri::command]
+pub async fn stalker_login(portal: StalkerPortal) -> Result<Profile, String> {
+    check_portal(&portal)?;
+    StalkerClient::new(&portal)?.connect().await
+}
+
+// Command handler that verifies a portal account and saves it as a provider
+#[tauri::command]
+pub async fn stalker_add_provider(
+    providers: State<'_, StalkerProviders>,
+    sessions: State<'_, StalkerSessions>,
+    name: String,
+    portal: StalkerPortal,
+) -> Result<StalkerProvider, String> {
+    check_portal(&portal)?;
+    validate_string_length(&name, MAX_NAME_LENGTH)?;
+    let client = StalkerClient::new(&portal)?;
+    client.connect().await?;
+
+    let provider = StalkerProvider::new(&client, &name, &portal);
+    providers.upsert(provider.clone())?;
+    sessions.invalidate(&provider.id);
+    Ok(provider)
+}
+
+// Command handler listing the saved Stalker providers
+#[tauri::command]
+pub fn stalker_list_providers(providers: State<'_, StalkerProviders>) -> Vec<StalkerProvider> {
+    providers.list()
+}
+
+// Command handler that deletes a saved Stalker provider
+#[tauri::command]
+pub fn stalker_remove_provider(app: AppHandle, provider_id: String) -> Result<bool, String> {
+    forget_provider(&app, &provider_id)
+}
+
+// Command handler listing a portal's channel genres
+#[tauri::command]
+pub async fn stalker_get_genres(
+    providers: State<'_, StalkerProviders>,
+    sessions: State<'_, StalkerSessions>,
+    provider_id: String,
+) -> Result<Vec<Genre>, String> {
+    connected(&providers, &sessions, &provider_id).await?.genres().await
+}
+
+// Command handler listing every channel of a portal
+#[tauri::command]
+pub async fn stalker_get_channels(
+    providers: State<'_, StalkerProviders>,
+    sessions: State<'_, StalkerSessions>,
+    provider_id: String,
+) -> Result<Vec<StalkerChannel>, String> {
+    let mut channels = connected(&providers, &sessions, &provider_id).await?.channels().await?;
+    for channel in &mut channels {
+        channel.channel_id = identity::channel_id(&provider_id, &identity::stalker_key(&channel.id));
+    }
+    Ok(channels)
+}
+
+// Command handler turning a channel's player command into a playable URL
+#[tauri::command]
+pub async fn stalker_create_link(
+    providers: State<'_, StalkerProviders>,
+    sessions: State<'_, StalkerSessions>,
+    provider_id: String,
+    cmd: String,
+) -> Result<String, String> {
+    validate_string_length(&cmd, MAX_FIELD_LENGTH)?;
+    connected(&providers, &sessions, &provider_id).await?.create_link(cmd.trim()).await
+}
+
+// Command handler returning the next few programmes of a portal channel
+#[tauri::command]
+pub async fn stalker_get_short_epg(
+    providers: State<'_, StalkerProviders>,
+    sessions: State<'_, StalkerSessions>,
+    provider_id: String,
+    channel_id: String,
+    size: Option<u32>,
+) -> Result<Vec<Programme>, String> {
+    validate_string_length(&channel_id, MAX_FIELD_LENGTH)?;
+    let size = size.unwrap_or(DEFAULT_EPG_SIZE).clamp(1, MAX_EPG_SIZE);
+    connected(&providers, &sessions, &provider_id).await?.short_epg(channel_id.trim(), size).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn test_providers_persist() {
+        // Test that saved portals survive reopening and ids ignore MAC spelling
+        let portal = |mac: &str| StalkerPortal {
+            url: "http://example.com/c/".to_string(),
+            mac: mac.to_string(),
+            ..Default::default()
+        };
+        let id = provider_id(&StalkerClient::new(&portal("00:1a:79:00:00:01")).unwrap());
+        assert_eq!(id, provider_id(&StalkerClient::new(&portal("00-1A-79-00-00-01")).unwrap()));
+
+        let dir = temp_dir("stalker-providers");
+        let providers = StalkerProviders::open(&dir);
+        let provider = StalkerProvider {
+            id: id.clone(),
+            name: "Portal".to_string(),
+            portal: portal("00:1A:79:00:00:01"),
+        };
+        providers.upsert(provider.clone()).unwrap();
+        assert_eq!(StalkerProviders::open(&dir).get(&id).unwrap(), provider);
+        assert!(providers.remove(&id).unwrap());
+        assert!(providers.get(&id).is_err());
+    }
+}
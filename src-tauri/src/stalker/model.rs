@@ -0,0 +1,134 @@
+// Typed views of Stalker portal responses (the "js" member of each answer)
+use serde::{Deserialize, Serialize};
+
+use crate::de;
+use crate::epg::{time, Programme};
+
+// Answer of the stb get_profile action
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all(serialize = "camelCase"))]
+pub struct Profile {
+    #[serde(deserialize_with = "de::string")]
+    pub id: String,
+    #[serde(deserialize_with = "de::string")]
+    pub name: String,
+    #[serde(deserialize_with = "de::string")]
+    pub login: String,
+    #[serde(deserialize_with = "de::bool")]
+    pub blocked: bool,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub expire_billing_date: Option<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub tariff_plan: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all(serialize = "camelCase"))]
+pub struct Genre {
+    // "*" is the portal's "All channels" pseudo genre
+    #[serde(deserialize_with = "de::string")]
+    pub id: String,
+    #[serde(deserialize_with = "de::string")]
+    pub title: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all(serialize = "camelCase"))]
+pub struct StalkerChannel {
+    #[serde(deserialize_with = "de::string")]
+    pub id: String,
+    #[serde(deserialize_with = "de::string")]
+    pub name: String,
+    #[serde(deserialize_with = "de::opt_u32")]
+    pub number: Option<u32>,
+    // Player command ("ffmpeg http://..."); usually needs create_link
+    // before it can be played
+    #[serde(deserialize_with = "de::string")]
+    pub cmd: String,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub logo: Option<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub tv_genre_id: Option<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub xmltv_id: Option<String>,
+    #[serde(deserialize_with = "de::bool")]
+    pub tv_archive: bool,
+    #[serde(deserialize_with = "de::opt_u32")]
+    pub tv_archive_duration: Option<u32>,
+    // Stable identity (see crate::identity), filled in per provider
+    #[serde(skip_deserializing)]
+    pub channel_id: String,
+}
+
+// Item of an itv get_short_epg answer
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RawProgramme {
+    #[serde(deserialize_with = "de::string")]
+    ch_id: String,
+    #[serde(deserialize_with = "de::string")]
+    name: String,
+    #[serde(deserialize_with = "de::opt_string")]
+    descr: Option<String>,
+    #[serde(deserialize_with = "de::opt_i64")]
+    start_timestamp: Option<i64>,
+    #[serde(deserialize_with = "de::opt_i64")]
+    stop_timestamp: Option<i64>,
+    #[serde(deserialize_with = "de::string")]
+    time: String,
+    #[serde(deserialize_with = "de::string")]
+    time_to: String,
+}
+
+impl RawProgramme {
+    pub fn into_programme(self) -> Option<Programme> {
+        let start = self.start_timestamp.or_else(|| time::parse_datetime(&self.time))?;
+        let stop = self.stop_timestamp.or_else(|| time::parse_datetime(&self.time_to))?;
+        (stop > start && !self.name.is_empty()).then_some(Programme {
+            channel: self.ch_id,
+            start,
+            stop,
+            title: self.name,
+            description: self.descr,
+            ..Default::default()
+        })
+    }
+}
+
+// The stream URL inside a player command, dropping the "ffmpeg " / "ffrt "
+// style prefix the set-top box uses to pick a player
+pub fn stream_url(cmd: &str) -> String {
+    let cmd = cmd.trim();
+    match cmd.split_once(char::is_whitespace) {
+        Some((player, rest)) if !player.contains("://") => rest.trim().to_string(),
+        _ => cmd.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_and_programme_shapes() {
+        // Test messy channel fields and programmes falling back to date strings
+        let channel: StalkerChannel = serde_json::from_str(
+            r#"{"id": 10, "name": "News", "number": "5", "cmd": "ffrt http://localhost/ch/10_", "tv_archive": "1",
+                "xmltv_id": "", "logo": null}"#,
+        )
+        .unwrap();
+        assert_eq!((channel.id.as_str(), channel.number), ("10", Some(5)));
+        assert!(channel.tv_archive);
+        assert_eq!(channel.xmltv_id, None);
+        assert_eq!(stream_url(&channel.cmd), "http://localhost/ch/10_");
+        assert_eq!(stream_url("http://a/b.ts"), "http://a/b.ts");
+
+        let raw: RawProgramme = serde_json::from_str(
+            r#"{"ch_id": "10", "name": "Bulletin", "time": "2024-01-01 10:00:00", "time_to": "2024-01-01 10:30:00"}"#,
+        )
+        .unwrap();
+        let programme = raw.into_programme().unwrap();
+        assert_eq!((programme.start, programme.stop), (1_704_103_200, 1_704_105_000));
+        assert_eq!(programme.channel, "10");
+    }
+}
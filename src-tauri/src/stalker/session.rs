@@ -0,0 +1,98 @@
+// Logged-in portal clients kept between commands, so browsing reuses the
+// session token instead of handshaking on every click
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::client::{StalkerClient, StalkerPortal};
+
+// Calls renew a token the portal refuses, so this only bounds how long a
+// session lives; afterwards the box logs in again and picks up profile
+// changes
+const SESSION_TTL: Duration = Duration::from_secs(60 * 60);
+
+struct Session {
+    // What the session logged in with; a changed portal needs a new one
+    portal: StalkerPortal,
+    client: Arc<StalkerClient>,
+    started: Instant,
+}
+
+#[derive(Default)]
+pub struct StalkerSessions {
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl StalkerSessions {
+    fn cached(&self, provider_id: &str, portal: &StalkerPortal) -> Option<Arc<StalkerClient>> {
+        let mut sessions = self.sessions.lock().unwrap();
+        match sessions.get(provider_id) {
+            Some(session) if session.portal == *portal && session.started.elapsed() < SESSION_TTL => {
+                Some(session.client.clone())
+            }
+            Some(_) => {
+                sessions.remove(provider_id);
+                None
+            }
+            None => None,
+        }
+    }
+
+    // A client with an open session for a provider's portal
+    pub async fn client(&self, provider_id: &str, portal: &StalkerPortal) -> Result<Arc<StalkerClient>, String> {
+        if let Some(client) = self.cached(provider_id, portal) {
+            return Ok(client);
+        }
+        let client = Arc::new(StalkerClient::new(portal)?);
+        client.connect().await?;
+        let session = Session {
+            portal: portal.clone(),
+            client: client.clone(),
+            started: Instant::now(),
+        };
+        self.sessions.lock().unwrap().insert(provider_id.to_string(), session);
+        Ok(client)
+    }
+
+    pub fn invalidate(&self, provider_id: &str) {
+        self.sessions.lock().unwrap().remove(provider_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{response, serve};
+
+    #[test]
+    fn test_sessions_are_reused() {
+        // Test that one login serves later calls until invalidated or changed
+        let login = || {
+            vec![
+                response("200 OK", &[], r#"{"js":{"token":"T0K"}}"#),
+                response("200 OK", &[], r#"{"js":{"id":"7"}}"#),
+            ]
+        };
+        let (base, requests) = serve([login(), login(), login()].concat());
+        let portal = StalkerPortal {
+            url: format!("{}/c/", base),
+            mac: "00:1A:79:AA:BB:CC".to_string(),
+            ..Default::default()
+        };
+        let sessions = StalkerSessions::default();
+        let client = |portal: &StalkerPortal| tauri::async_runtime::block_on(sessions.client("p", portal)).unwrap();
+
+        let first = client(&portal);
+        assert!(Arc::ptr_eq(&first, &client(&portal)));
+        assert_eq!(requests.lock().unwrap().len(), 2);
+
+        sessions.invalidate("p");
+        assert!(!Arc::ptr_eq(&first, &client(&portal)));
+        let moved = StalkerPortal {
+            timezone: Some("Europe/Istanbul".to_string()),
+            ..portal.clone()
+        };
+        client(&moved);
+        assert_eq!(requests.lock().unwrap().len(), 6);
+    }
+}
@@ -0,0 +1,135 @@
+// Set-top box emulation: the model strings and device identifiers a real
+// MAG box presents, since many portals fingerprint them
+use md5::Md5;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StbModel {
+    #[default]
+    Mag250,
+    Mag254,
+    Mag322,
+}
+
+impl StbModel {
+    pub fn name(self) -> &'static str {
+        match self {
+            StbModel::Mag250 => "MAG250",
+            StbModel::Mag254 => "MAG254",
+            StbModel::Mag322 => "MAG322",
+        }
+    }
+
+    pub fn user_agent(self) -> &'static str {
+        match self {
+            StbModel::Mag250 => {
+                "Mozilla/5.0 (QtEmbedded; U; Linux; C) AppleWebKit/533.3 (KHTML, like Gecko) MAG200 stbapp ver: 2 rev: 250 Safari/533.3"
+            }
+            StbModel::Mag254 => {
+                "Mozilla/5.0 (QtEmbedded; U; Linux; C) AppleWebKit/533.3 (KHTML, like Gecko) MAG200 stbapp ver: 4 rev: 2721 Mobile Safari/533.3"
+            }
+            StbModel::Mag322 => {
+                "Mozilla/5.0 (QtEmbedded; U; Linux; C) AppleWebKit/533.3 (KHTML, like Gecko) MAG200 stbapp ver: 4 rev: 2116 Mobile Safari/533.3"
+            }
+        }
+    }
+
+    pub fn x_user_agent(self) -> String {
+        let link = match self {
+            StbModel::Mag250 => "WiFi",
+            StbModel::Mag254 | StbModel::Mag322 => "Ethernet",
+        };
+        format!("Model: {}; Link: {}", self.name(), link)
+    }
+
+    fn hw_version(self) -> &'static str {
+        match self {
+            StbModel::Mag250 => "1.7-BD-00",
+            StbModel::Mag254 => "2.6-IB-00",
+            StbModel::Mag322 => "1.0-BD-00",
+        }
+    }
+}
+
+fn hex_upper(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+// Identifiers derived the way emulators commonly do, so the same MAC always
+// presents the same box
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StbIdentity {
+    pub model: StbModel,
+    pub serial: String,
+    pub device_id: String,
+    pub device_id2: String,
+    pub signature: String,
+}
+
+impl StbIdentity {
+    // `serial` overrides the generated one for boxes registered by serial
+    pub fn new(model: StbModel, mac: &str, serial: Option<&str>) -> Self {
+        let serial = match serial.map(str::trim).filter(|s| !s.is_empty()) {
+            Some(serial) => serial.to_ascii_uppercase(),
+            None => hex_upper(&Md5::digest(mac.as_bytes()))[..13].to_string(),
+        };
+        let sha = |input: &str| hex_upper(&Sha256::digest(input.as_bytes()));
+        StbIdentity {
+            model,
+            device_id: sha(&serial),
+            device_id2: sha(mac),
+            signature: sha(&format!("{}{}", serial, mac)),
+            serial,
+        }
+    }
+
+    // Extra get_profile parameters describing the box
+    pub fn profile_params(&self) -> Vec<(&'static str, String)> {
+        let version = format!(
+            "ImageDescription: 0.2.18-r23-{}; ImageDate: Fri Jan 15 15:20:44 EET 2016; PORTAL version: 5.6.1; \
+             API Version: JS API version: 343; STB API version: 146; Player Engine version: 0x58c",
+            &self.model.name()[3..]
+        );
+        vec![
+            ("ver", version),
+            ("num_banks", "2".to_string()),
+            ("sn", self.serial.clone()),
+            ("stb_type", self.model.name().to_string()),
+            ("client_type", "STB".to_string()),
+            ("image_version", "218".to_string()),
+            ("video_out", "hdmi".to_string()),
+            ("device_id", self.device_id.clone()),
+            ("device_id2", self.device_id2.clone()),
+            ("signature", self.signature.clone()),
+            ("auth_second_step", "1".to_string()),
+            ("hw_version", self.model.hw_version().to_string()),
+            ("not_valid_token", "0".to_string()),
+            ("api_signature", "262".to_string()),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identity_is_stable() {
+        // Test that identifiers derive from the MAC and a given serial wins
+        let mac = "00:1A:79:00:00:01";
+        let identity = StbIdentity::new(StbModel::Mag254, mac, None);
+        assert_eq!(identity, StbIdentity::new(StbModel::Mag254, mac, Some(" ")));
+        assert_eq!(identity.serial.len(), 13);
+        assert!(identity.serial.bytes().all(|b| b.is_ascii_hexdigit() && !b.is_ascii_lowercase()));
+        assert_eq!(identity.device_id.len(), 64);
+        assert_ne!(identity.device_id, identity.device_id2);
+
+        let given = StbIdentity::new(StbModel::Mag254, mac, Some("abc123"));
+        assert_eq!(given.serial, "ABC123");
+        assert_eq!(StbModel::Mag322.x_user_agent(), "Model: MAG322; Link: Ethernet");
+        let params = given.profile_params();
+        assert!(params.contains(&("stb_type", "MAG254".to_string())));
+    }
+}
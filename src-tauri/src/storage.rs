@@ -0,0 +1,108 @@
+// JSON persistence helpers for backend state stored in the app data dir
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+// Read a JSON file, returning the default value when it doesn't exist yet
+pub fn read_json<T: DeserializeOwned + Default>(path: &Path) -> Result<T, String> {
+    match fs::read(path) {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(T::default()),
+        Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+    }
+}
+
+// Write a JSON file atomically (temp file + rename) so a crash never
+// leaves half-written state behind
+pub fn write_json<T: Serialize + ?Sized>(path: &Path, value: &T) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+
+    let bytes = serde_json::to_vec(value).map_err(|e| format!("Failed to serialize state: {}", e))?;
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, bytes).map_err(|e| format!("Failed to write {}: {}", tmp.display(), e))?;
+    fs::rename(&tmp, path).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+// Write already serialized JSON gzip-compressed, for large snapshots
+pub fn write_json_gz(path: &Path, json: &[u8]) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    let tmp = path.with_extension("tmp");
+    encoder
+        .write_all(json)
+        .and_then(|_| encoder.finish())
+        .and_then(|compressed| fs::write(&tmp, compressed))
+        .and_then(|_| fs::rename(&tmp, path))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+pub fn read_json_gz<T: DeserializeOwned>(path: &Path) -> Result<T, String> {
+    let compressed = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let mut json = Vec::new();
+    GzDecoder::new(compressed.as_slice())
+        .read_to_end(&mut json)
+        .map_err(|e| format!("Failed to decompress {}: {}", path.display(), e))?;
+    serde_json::from_slice(&json).map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_read_missing_returns_default() {
+        // Test that a missing file yields the default value
+        let dir = temp_dir("storage-missing");
+        let value: HashMap<String, u32> = read_json(&dir.join("nope.json")).unwrap();
+        assert!(value.is_empty());
+    }
+
+    #[test]
+    fn test_write_then_read_roundtrip() {
+        // Test that written state reads back and parent dirs are created
+        let dir = temp_dir("storage-roundtrip");
+        let path = dir.join("nested").join("state.json");
+        let mut value = HashMap::new();
+        value.insert("a".to_string(), 1u32);
+
+        write_json(&path, &value).unwrap();
+        let read: HashMap<String, u32> = read_json(&path).unwrap();
+        assert_eq!(read, value);
+        assert!(!path.with_extension("tmp").exists());
+    }
+
+    #[test]
+    fn test_gzip_roundtrip() {
+        // Test that compressed JSON reads back
+        let path = temp_dir("storage-gzip").join("state.json.gz");
+        write_json_gz(&path, br#"{"a":1}"#).unwrap();
+        let read: HashMap<String, u32> = read_json_gz(&path).unwrap();
+        assert_eq!(read.get("a"), Some(&1));
+    }
+
+    #[test]
+    fn test_read_corrupt_file_errors() {
+        // Test that corrupt JSON is reported instead of silently reset
+        let dir = temp_dir("storage-corrupt");
+        let path = dir.join("state.json");
+        std::fs::write(&path, b"{not json").unwrap();
+
+        let result: Result<HashMap<String, u32>, String> = read_json(&path);
+        assert!(result.is_err());
+    }
+}
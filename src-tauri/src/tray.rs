@@ -0,0 +1,214 @@
+// Tray icon naming what's playing: the tooltip and the top of the tray
+// menu show the channel the UI reported with its current and next guide
+// programme, kept up to date as programmes end
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tauri::menu::{Menu, MenuItem, PredefinedMenuItem};
+use tauri::tray::TrayIconBuilder;
+use tauri::{AppHandle, Manager, State, Wry};
+
+use crate::epg::{self, NowNext};
+use crate::{unix_now, validate_string_length};
+
+const TRAY_ID: &str = "main";
+
+// How often the programmes shown are checked
+const REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+const MAX_NAME_LENGTH: usize = 500;
+
+#[derive(Debug, Clone, PartialEq)]
+struct NowPlaying {
+    channel_name: String,
+    epg_channel_id: Option<String>,
+}
+
+// The lines shown, kept to skip rebuilding an unchanged menu
+#[derive(Debug, Clone, Default, PartialEq)]
+struct TrayText {
+    channel: Option<String>,
+    now: Option<String>,
+    next: Option<String>,
+}
+
+impl TrayText {
+    fn tooltip(&self) -> String {
+        let mut tooltip = "TIPTV".to_string();
+        if let Some(channel) = &self.channel {
+            tooltip.push_str(" - ");
+            tooltip.push_str(channel);
+        }
+        for line in self.now.iter().chain(&self.next) {
+            tooltip.push('\n');
+            tooltip.push_str(line);
+        }
+        tooltip
+    }
+}
+
+fn tray_text(playing: Option<&NowPlaying>, now_next: &NowNext, now: i64) -> TrayText {
+    let Some(playing) = playing else {
+        return TrayText::default();
+    };
+    TrayText {
+        channel: Some(playing.channel_name.clone()),
+        now: now_next.now.as_ref().map(|programme| {
+            let minutes = (programme.stop - now + 59) / 60;
+            format!("Now: {} ({} min left)", programme.title, minutes)
+        }),
+        next: now_next.next.as_ref().map(|programme| format!("Next: {}", programme.title)),
+    }
+}
+
+#[derive(Default)]
+pub struct TrayState {
+    playing: Mutex<Option<NowPlaying>>,
+    shown: Mutex<Option<TrayText>>,
+}
+
+fn menu(app: &AppHandle, text: &TrayText) -> tauri::Result<Menu<Wry>> {
+    let menu = Menu::new(app)?;
+    let lines: Vec<&String> = text.channel.iter().chain(&text.now).chain(&text.next).collect();
+    for line in &lines {
+        menu.append(&MenuItem::new(app, line.as_str(), false, None::<&str>)?)?;
+    }
+    if !lines.is_empty() {
+        menu.append(&PredefinedMenuItem::separator(app)?)?;
+    }
+    menu.append(&MenuItem::with_id(app, "tray-show", "Show TIPTV", true, None::<&str>)?)?;
+    menu.append(&MenuItem::with_id(app, "tray-quit", "Quit", true, None::<&str>)?)?;
+    Ok(menu)
+}
+
+fn show_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+}
+
+// Create the tray icon
+pub fn create(app: &AppHandle) -> tauri::Result<()> {
+    let text = TrayText::default();
+    let mut builder = TrayIconBuilder::with_id(TRAY_ID)
+        .tooltip(text.tooltip())
+        .menu(&menu(app, &text)?)
+        .on_menu_event(|app, event| match event.id().as_ref() {
+            "tray-show" => show_window(app),
+            "tray-quit" => app.exit(0),
+            _ => {}
+        });
+    if let Some(icon) = app.default_window_icon() {
+        builder = builder.icon(icon.clone());
+    }
+    builder.build(app)?;
+    Ok(())
+}
+
+// Show the playing channel's programmes when they changed
+fn refresh(app: &AppHandle) {
+    let state = app.state::<TrayState>();
+    let playing = state.playing.lock().unwrap().clone();
+    let now = unix_now();
+    let now_next = match playing.as_ref().and_then(|playing| playing.epg_channel_id.as_deref()) {
+        Some(channel) => epg::now_next(app, channel, now),
+        None => NowNext::default(),
+    };
+    let text = tray_text(playing.as_ref(), &now_next, now);
+    {
+        let mut shown = state.shown.lock().unwrap();
+        if shown.as_ref() == Some(&text) {
+            return;
+        }
+        *shown = Some(text.clone());
+    }
+    // Menus belong to the main thread on some platforms
+    let handle = app.clone();
+    let _ = app.run_on_main_thread(move || {
+        let Some(tray) = handle.tray_by_id(TRAY_ID) else {
+            return;
+        };
+        if let Err(e) = tray.set_tooltip(Some(text.tooltip())) {
+            log::debug!("Failed to update the tray tooltip: {}", e);
+        }
+        match menu(&handle, &text) {
+            Ok(menu) => {
+                if let Err(e) = tray.set_menu(Some(menu)) {
+                    log::debug!("Failed to update the tray menu: {}", e);
+                }
+            }
+            Err(e) => log::debug!("Failed to build the tray menu: {}", e),
+        }
+    });
+}
+
+// Start the refresh job; runs for the lifetime of the app
+pub fn start(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let handle = app.clone();
+            let _ = tauri::async_runtime::spawn_blocking(move || refresh(&handle)).await;
+            tokio::time::sleep(REFRESH_INTERVAL).await;
+        }
+    });
+}
+
+// Command handler the UI calls when it starts playing a channel; the
+// guide channel is the one the channel is matched to, if any
+#[tauri::command]
+pub fn set_now_playing(
+    app: AppHandle,
+    tray: State<'_, TrayState>,
+    channel_name: String,
+    epg_channel_id: Option<String>,
+) -> Result<(), String> {
+    validate_string_length(&channel_name, MAX_NAME_LENGTH)?;
+    let epg_channel_id = epg_channel_id.map(|id| id.trim().to_string()).filter(|id| !id.is_empty());
+    if let Some(id) = &epg_channel_id {
+        validate_string_length(id, MAX_NAME_LENGTH)?;
+    }
+    *tray.playing.lock().unwrap() = Some(NowPlaying {
+        channel_name: channel_name.trim().to_string(),
+        epg_channel_id,
+    });
+    tauri::async_runtime::spawn_blocking(move || refresh(&app));
+    Ok(())
+}
+
+// Command handler the UI calls when playback stops
+#[tauri::command]
+pub fn clear_now_playing(app: AppHandle, tray: State<'_, TrayState>) {
+    *tray.playing.lock().unwrap() = None;
+    tauri::async_runtime::spawn_blocking(move || refresh(&app));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epg::Programme;
+
+    #[test]
+    fn test_tray_text() {
+        // Test that the tooltip names the channel and its programmes, and nothing while stopped
+        let programme = |title: &str, start: i64, stop: i64| Programme {
+            title: title.to_string(),
+            start,
+            stop,
+            ..Default::default()
+        };
+        let now_next = NowNext {
+            now: Some(programme("News", 0, 1800)),
+            next: Some(programme("Film", 1800, 7200)),
+        };
+        let playing = NowPlaying {
+            channel_name: "One HD".to_string(),
+            epg_channel_id: Some("one.uk".to_string()),
+        };
+        let text = tray_text(Some(&playing), &now_next, 1000);
+        assert_eq!(text.tooltip(), "TIPTV - One HD\nNow: News (14 min left)\nNext: Film");
+        assert_eq!(tray_text(None, &now_next, 1000).tooltip(), "TIPTV");
+        assert_eq!(tray_text(Some(&playing), &NowNext::default(), 1000).now, None);
+    }
+}
@@ -0,0 +1,387 @@
+// HTTP client for the TVHeadend JSON API. Servers ask for HTTP digest
+// authentication by default and may be set to plain (basic) logins; the
+// password is only sent once the server said which it wants
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use md5::{Digest as _, Md5};
+use reqwest::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use reqwest::{Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use url::Url;
+
+use super::model::{ChannelTag, Grid, RawEvent, ServerInfo, TvheadendChannel};
+use crate::de;
+use crate::epg::Programme;
+use crate::http::{self, BasicAuth};
+use crate::playlist::content_hash;
+
+// Port TVHeadend's web interface listens on unless configured otherwise
+const DEFAULT_PORT: u16 = 9981;
+
+// Events requested per page of the guide, and at most in total
+const EVENT_PAGE: u32 = 2000;
+const MAX_EVENTS: u32 = 100_000;
+
+// Grids answer 50 rows unless asked for more
+const ALL_ROWS: &str = "100000";
+
+// Server address plus the login of a TVHeadend user (empty for servers
+// that allow anonymous access)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TvheadendServer {
+    pub url: String,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub password: String,
+    // Streaming profile such as "pass" or "webtv-h264-aac-matroska"; the
+    // user's default profile when missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+}
+
+// How the server asked to log in, remembered for later requests
+enum Scheme {
+    Basic,
+    // Reused with a growing count until the server sends a new nonce
+    Digest(Challenge),
+}
+
+struct Challenge {
+    realm: String,
+    nonce: String,
+    opaque: Option<String>,
+    qop: bool,
+    count: u32,
+}
+
+// Accept "tvh.lan", "tvh.lan:9981" or "http://tvh.lan:9981/tvh/" (servers
+// behind a reverse proxy keep their path)
+pub fn normalize_server(server: &str) -> Result<Url, String> {
+    let server = server.trim();
+    if server.is_empty() {
+        return Err("Server address cannot be empty".to_string());
+    }
+    let has_scheme = server.contains("://");
+    let with_scheme = if has_scheme {
+        server.to_string()
+    } else {
+        format!("http://{}", server)
+    };
+    let mut url = Url::parse(&with_scheme).map_err(|e| format!("Invalid server address '{}': {}", server, e))?;
+    if !matches!(url.scheme(), "http" | "https") || !url.has_host() {
+        return Err(format!("Invalid server address '{}'", server));
+    }
+    if !has_scheme && url.port().is_none() {
+        let _ = url.set_port(Some(DEFAULT_PORT));
+    }
+    let path = url.path().trim_end_matches('/').to_string();
+    url.set_path(&format!("{}/", path));
+    url.set_query(None);
+    url.set_fragment(None);
+    Ok(url)
+}
+
+fn md5_hex(text: &str) -> String {
+    Md5::digest(text.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Quoted or bare parameters of a WWW-Authenticate: Digest header
+fn parse_challenge(header: &str) -> Option<Challenge> {
+    let (scheme, params) = header.trim().split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("digest") {
+        return None;
+    }
+    let mut realm = None;
+    let mut nonce = None;
+    let mut opaque = None;
+    let mut qop = false;
+    let mut rest = params.trim();
+    while let Some((key, after)) = rest.split_once('=') {
+        let key = key.trim().trim_start_matches(',').trim().to_ascii_lowercase();
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => after.split_once(',').unwrap_or((after, "")),
+        };
+        match key.as_str() {
+            "realm" => realm = Some(value.to_string()),
+            "nonce" => nonce = Some(value.to_string()),
+            "opaque" => opaque = Some(value.to_string()),
+            "qop" => qop = value.split(',').any(|q| q.trim() == "auth"),
+            _ => {}
+        }
+        rest = after.trim_start_matches([',', ' ']);
+    }
+    Some(Challenge {
+        realm: realm?,
+        nonce: nonce?,
+        opaque,
+        qop,
+        count: 0,
+    })
+}
+
+// Authorization header answering a challenge for a GET of `uri`
+fn digest_header(challenge: &Challenge, username: &str, password: &str, uri: &str, cnonce: &str) -> String {
+    let ha1 = md5_hex(&format!("{}:{}:{}", username, challenge.realm, password));
+    let ha2 = md5_hex(&format!("GET:{}", uri));
+    let mut header = format!(
+        r#"Digest username="{}", realm="{}", nonce="{}", uri="{}", algorithm=MD5"#,
+        username, challenge.realm, challenge.nonce, uri
+    );
+    let response = if challenge.qop {
+        let count = format!("{:08x}", challenge.count);
+        header.push_str(&format!(r#", qop=auth, nc={}, cnonce="{}""#, count, cnonce));
+        md5_hex(&format!("{}:{}:{}:{}:auth:{}", ha1, challenge.nonce, count, cnonce, ha2))
+    } else {
+        md5_hex(&format!("{}:{}:{}", ha1, challenge.nonce, ha2))
+    };
+    header.push_str(&format!(r#", response="{}""#, response));
+    if let Some(opaque) = &challenge.opaque {
+        header.push_str(&format!(r#", opaque="{}""#, opaque));
+    }
+    header
+}
+
+pub struct TvheadendClient {
+    base: Url,
+    username: String,
+    password: String,
+    profile: Option<String>,
+    scheme: Mutex<Option<Scheme>>,
+}
+
+impl TvheadendClient {
+    pub fn new(server: &TvheadendServer) -> Result<Self, String> {
+        Ok(Self {
+            base: normalize_server(&server.url)?,
+            username: server.username.trim().to_string(),
+            password: server.password.clone(),
+            profile: server.profile.as_deref().map(str::trim).filter(|p| !p.is_empty()).map(str::to_string),
+            scheme: Mutex::new(None),
+        })
+    }
+
+    pub fn server(&self) -> &str {
+        self.base.as_str().trim_end_matches('/')
+    }
+
+    fn url(&self, path: &str, params: &[(&str, &str)]) -> Url {
+        let mut url = self.base.join(path).expect("API paths are valid relative URLs");
+        if !params.is_empty() {
+            url.query_pairs_mut().extend_pairs(params);
+        }
+        url
+    }
+
+    // Authorization for a request in the scheme the server asked for
+    fn authorization(&self, url: &Url) -> Option<String> {
+        let mut scheme = self.scheme.lock().unwrap();
+        let challenge = match scheme.as_mut()? {
+            Scheme::Basic => {
+                let basic = BasicAuth {
+                    username: self.username.clone(),
+                    password: self.password.clone(),
+                };
+                return Some(basic.header_value());
+            }
+            Scheme::Digest(challenge) => challenge,
+        };
+        challenge.count += 1;
+        let uri = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or_default();
+        let cnonce = content_hash(format!("{}:{}", nanos, challenge.count).as_bytes());
+        Some(digest_header(challenge, &self.username, &self.password, &uri, &cnonce))
+    }
+
+    async fn send(&self, url: &Url) -> Result<Response, String> {
+        let mut request = http::get(url.as_str(), None);
+        if let Some(authorization) = self.authorization(url) {
+            request = request.header(AUTHORIZATION, authorization);
+        }
+        request
+            .send()
+            .await
+            .map_err(|e| format!("TVHeadend request '{}' failed: {}", url.path(), e.without_url()))
+    }
+
+    // GET a path, answering a login challenge (or a stale nonce) once
+    pub async fn get(&self, path: &str, params: &[(&str, &str)]) -> Result<Response, String> {
+        let url = self.url(path, params);
+        let response = self.send(&url).await?;
+        if response.status() != StatusCode::UNAUTHORIZED || self.username.is_empty() {
+            return check(response, &url);
+        }
+        let offers: Vec<&str> = response
+            .headers()
+            .get_all(WWW_AUTHENTICATE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect();
+        let scheme = match offers.iter().find_map(|offer| parse_challenge(offer)) {
+            Some(challenge) => Scheme::Digest(challenge),
+            None if offers.iter().any(|offer| offer.trim().to_ascii_lowercase().starts_with("basic")) => Scheme::Basic,
+            None => return check(response, &url),
+        };
+        *self.scheme.lock().unwrap() = Some(scheme);
+        check(self.send(&url).await?, &url)
+    }
+
+    async fn json(&self, path: &str, params: &[(&str, &str)]) -> Result<Value, String> {
+        let body = self
+            .get(path, params)
+            .await?
+            .bytes()
+            .await
+            .map_err(|e| format!("TVHeadend request '{}' failed: {}", path, e.without_url()))?;
+        serde_json::from_slice(&body).map_err(|e| format!("Invalid TVHeadend response to '{}': {}", path, e))
+    }
+
+    pub async fn server_info(&self) -> Result<ServerInfo, String> {
+        Ok(de::object_value(self.json("api/serverinfo", &[]).await?))
+    }
+
+    pub async fn tags(&self) -> Result<Vec<ChannelTag>, String> {
+        let grid: Grid = de::object_value(self.json("api/channeltag/grid", &[("limit", ALL_ROWS)]).await?);
+        let mut tags: Vec<ChannelTag> = de::list(grid.entries);
+        tags.retain(|tag| !tag.internal);
+        tags.sort_by_key(|tag| tag.index);
+        Ok(tags)
+    }
+
+    // Enabled channels in channel number order
+    pub async fn channels(&self) -> Result<Vec<TvheadendChannel>, String> {
+        let params = [("limit", ALL_ROWS), ("sort", "number"), ("dir", "ASC")];
+        let grid: Grid = de::object_value(self.json("api/channel/grid", &params).await?);
+        let mut channels: Vec<TvheadendChannel> = de::list(grid.entries);
+        channels.retain(|channel| channel.enabled);
+        for channel in &mut channels {
+            channel.icon_public_url = channel.icon_public_url.take().map(|icon| self.absolute(&icon));
+        }
+        Ok(channels)
+    }
+
+    // Icons come as paths like "imagecache/12"
+    fn absolute(&self, path: &str) -> String {
+        match self.base.join(path) {
+            Ok(url) => url.to_string(),
+            Err(_) => path.to_string(),
+        }
+    }
+
+    // Upcoming programmes, of one channel or of all of them page by page
+    pub async fn events(&self, channel_uuid: Option<&str>, limit: u32) -> Result<Vec<Programme>, String> {
+        let limit = limit.min(MAX_EVENTS);
+        let mut programmes = Vec::new();
+        let mut start = 0;
+        while start < limit {
+            let page = EVENT_PAGE.min(limit - start);
+            let (offset, count) = (start.to_string(), page.to_string());
+            let mut params = vec![("start", offset.as_str()), ("limit", count.as_str())];
+            if let Some(channel) = channel_uuid {
+                params.push(("channel", channel));
+            }
+            let grid: Grid = de::object_value(self.json("api/epg/events/grid", &params).await?);
+            let events: Vec<RawEvent> = de::list(grid.entries);
+            let received = u32::try_from(events.len()).unwrap_or(u32::MAX);
+            programmes.extend(events.into_iter().filter_map(RawEvent::into_programme));
+            start += received;
+            if received < page || grid.total.is_some_and(|total| start >= total) {
+                break;
+            }
+        }
+        programmes.sort_by_key(|programme| programme.start);
+        Ok(programmes)
+    }
+
+    // Playable URL of a channel. Players can't answer digest challenges, so
+    // logged-in users get a ticketed URL from the server's playlist
+    pub async fn stream_url(&self, channel_uuid: &str) -> Result<String, String> {
+        let path = format!("stream/channel/{}", channel_uuid);
+        let mut url = if self.username.is_empty() {
+            self.url(&path, &[])
+        } else {
+            let playlist = self.get(&format!("playlist/ticket/channel/{}", channel_uuid), &[]).await?;
+            let body = playlist
+                .text()
+                .await
+                .map_err(|e| format!("TVHeadend request 'playlist' failed: {}", e.without_url()))?;
+            let line = body.lines().map(str::trim).find(|line| !line.is_empty() && !line.starts_with('#'));
+            Url::parse(line.ok_or("TVHeadend didn't return a stream link")?)
+                .map_err(|e| format!("Invalid TVHeadend stream link: {}", e))?
+        };
+        if let Some(profile) = &self.profile {
+            url.query_pairs_mut().append_pair("profile", profile);
+        }
+        Ok(url.to_string())
+    }
+}
+
+fn check(response: Response, url: &Url) -> Result<Response, String> {
+    let status = response.status();
+    if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+        return Err("TVHeadend rejected the username or password".to_string());
+    }
+    if !status.is_success() {
+        return Err(format!("TVHeadend request '{}' failed with HTTP {}", url.path(), status.as_u16()));
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{response, serve};
+
+    #[test]
+    fn test_digest_response() {
+        // Test the RFC 2617 example and challenge parsing
+        let challenge = parse_challenge(
+            r#"Digest realm="testrealm@host.com", qop="auth,auth-int", nonce="dcd98b7102dd2f0e8b11d0f600bfb0c093", opaque="5ccc069c403ebaf9f0171e9517f40e41""#,
+        )
+        .unwrap();
+        let challenge = Challenge { count: 1, ..challenge };
+        let header = digest_header(&challenge, "Mufasa", "Circle Of Life", "/dir/index.html", "0a4f113b");
+        assert!(header.contains(r#"response="6629fae49393a05397450978507c4ef1""#));
+        assert!(header.contains("nc=00000001") && header.contains(r#"opaque="5ccc069c403ebaf9f0171e9517f40e41""#));
+        assert!(parse_challenge(r#"Basic realm="tvheadend""#).is_none());
+
+        assert_eq!(normalize_server("tvh.lan").unwrap().as_str(), "http://tvh.lan:9981/");
+        assert_eq!(normalize_server("https://example.com/tvh").unwrap().as_str(), "https://example.com/tvh/");
+    }
+
+    #[test]
+    fn test_digest_login_and_ticket() {
+        // Test that a digest challenge is answered and reused, and tickets become stream URLs
+        let challenge = r#"Digest realm="tvheadend", qop="auth", nonce="abc", opaque="xyz""#;
+        let (base, requests) = serve(vec![
+            response("401 Unauthorized", &[("WWW-Authenticate", challenge)], ""),
+            response("200 OK", &[], r#"{"entries":[{"uuid":"c1","name":"One","enabled":true,"icon_public_url":"imagecache/1"},{"uuid":"c2","enabled":false}]}"#),
+            response("200 OK", &[], "#EXTM3U\n#EXTINF:-1,One\nhttp://tvh/stream/channel/c1?ticket=T\n"),
+        ]);
+        let client = TvheadendClient::new(&TvheadendServer {
+            url: base.clone(),
+            username: "user".to_string(),
+            password: "pass".to_string(),
+            profile: Some("pass".to_string()),
+        })
+        .unwrap();
+
+        let channels = tauri::async_runtime::block_on(client.channels()).unwrap();
+        assert_eq!(channels.len(), 1);
+        assert_eq!(channels[0].icon_public_url.as_deref(), Some(format!("{}/imagecache/1", base).as_str()));
+        let url = tauri::async_runtime::block_on(client.stream_url("c1")).unwrap();
+        assert_eq!(url, "http://tvh/stream/channel/c1?ticket=T&profile=pass");
+
+        let requests = requests.lock().unwrap();
+        assert!(!requests[0].to_ascii_lowercase().contains("authorization:"));
+        assert!(requests[1].contains(r#"Digest username="user", realm="tvheadend", nonce="abc""#));
+        assert!(requests[2].contains("nc=00000002"));
+    }
+}
@@ -0,0 +1,280 @@
+// TVHeadend servers: saved logins and the JSON API client. Home setups put
+// their tuners behind TVHeadend and use this app as the player
+mod client;
+mod model;
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::epg::{EpgSource, EpgStore, Guide, Programme};
+use crate::identity;
+use crate::playlist::content_hash;
+use crate::storage;
+use crate::{unix_now, validate_string_length};
+
+pub use client::{TvheadendClient, TvheadendServer};
+pub use model::{ChannelTag, ServerInfo, TvheadendChannel};
+
+const PROVIDERS_FILE: &str = "tvheadend_providers.json";
+
+// Limits for user-entered server details
+const MAX_FIELD_LENGTH: usize = 2048;
+const MAX_NAME_LENGTH: usize = 200;
+
+// Programmes returned per channel by default and at most
+const DEFAULT_EPG_LIMIT: u32 = 4;
+const MAX_EPG_LIMIT: u32 = 500;
+
+// Programmes imported into the guide at most
+const MAX_GUIDE_EVENTS: u32 = 100_000;
+
+// A saved server login
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TvheadendProvider {
+    pub id: String,
+    pub name: String,
+    #[serde(flatten)]
+    pub server: TvheadendServer,
+}
+
+// Same server and user always map to the same provider
+fn provider_id(client: &TvheadendClient, username: &str) -> String {
+    content_hash(format!("tvheadend:{}:{}", client.server(), username.trim()).as_bytes())
+}
+
+impl TvheadendProvider {
+    // A provider with a normalized server; an empty name becomes the server
+    // address
+    pub fn new(client: &TvheadendClient, name: &str, server: &TvheadendServer) -> Self {
+        TvheadendProvider {
+            id: provider_id(client, &server.username),
+            name: match name.trim() {
+                "" => client.server().to_string(),
+                name => name.to_string(),
+            },
+            server: TvheadendServer {
+                url: client.server().to_string(),
+                username: server.username.trim().to_string(),
+                password: server.password.clone(),
+                profile: server.profile.as_deref().map(str::trim).filter(|p| !p.is_empty()).map(str::to_string),
+            },
+        }
+    }
+}
+
+pub struct TvheadendProviders {
+    path: PathBuf,
+    providers: Mutex<Vec<TvheadendProvider>>,
+}
+
+impl TvheadendProviders {
+    pub fn open(data_dir: &Path) -> Self {
+        let path = data_dir.join(PROVIDERS_FILE);
+        let providers = storage::read_json(&path).unwrap_or_else(|e| {
+            log::error!("Failed to load TVHeadend providers: {}", e);
+            Vec::new()
+        });
+        Self {
+            path,
+            providers: Mutex::new(providers),
+        }
+    }
+
+    pub fn list(&self) -> Vec<TvheadendProvider> {
+        self.providers.lock().unwrap().clone()
+    }
+
+    pub fn get(&self, id: &str) -> Result<TvheadendProvider, String> {
+        self.providers
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|p| p.id == id)
+            .cloned()
+            .ok_or_else(|| format!("TVHeadend provider '{}' doesn't exist", id))
+    }
+
+    pub fn upsert(&self, provider: TvheadendProvider) -> Result<(), String> {
+        let mut providers = self.providers.lock().unwrap();
+        match providers.iter_mut().find(|p| p.id == provider.id) {
+            Some(existing) => *existing = provider,
+            None => providers.push(provider),
+        }
+        storage::write_json(&self.path, &*providers)
+    }
+
+    fn remove(&self, id: &str) -> Result<bool, String> {
+        let mut providers = self.providers.lock().unwrap();
+        let before = providers.len();
+        providers.retain(|p| p.id != id);
+        if providers.len() == before {
+            return Ok(false);
+        }
+        storage::write_json(&self.path, &*providers)?;
+        Ok(true)
+    }
+}
+
+pub fn check_server(server: &TvheadendServer) -> Result<(), String> {
+    validate_string_length(&server.url, MAX_FIELD_LENGTH)?;
+    validate_string_length(&server.username, MAX_FIELD_LENGTH)?;
+    validate_string_length(&server.password, MAX_FIELD_LENGTH)?;
+    validate_string_length(server.profile.as_deref().unwrap_or_default(), MAX_NAME_LENGTH)
+}
+
+// EPG store id of a provider's guide
+fn source_id(provider_id: &str) -> String {
+    format!("tvheadend:{}", provider_id)
+}
+
+fn provider_client(providers: &TvheadendProviders, provider_id: &str) -> Result<TvheadendClient, String> {
+    TvheadendClient::new(&providers.get(provider_id)?.server)
+}
+
+// Remove a saved provider with its imported guide
+pub fn forget_provider(app: &AppHandle, provider_id: &str) -> Result<bool, String> {
+    app.state::<EpgStore>().remove(&source_id(provider_id))?;
+    app.state::<TvheadendProviders>().remove(provider_id)
+}
+
+// Command handler that logs in to a server without saving it
+#[tauri::command]
+pub async fn tvheadend_login(server: TvheadendServer) -> Result<ServerInfo, String> {
+    check_server(&server)?;
+    TvheadendClient::new(&server)?.server_info().await
+}
+
+// Command handler that verifies a server login and saves it as a provider
+#[tauri::command]
+pub async fn tvheadend_add_provider(
+    providers: State<'_, TvheadendProviders>,
+    name: String,
+    server: TvheadendServer,
+) -> Result<TvheadendProvider, String> {
+    check_server(&server)?;
+    validate_string_length(&name, MAX_NAME_LENGTH)?;
+    let client = TvheadendClient::new(&server)?;
+    client.server_info().await?;
+
+    let provider = TvheadendProvider::new(&client, &name, &server);
+    providers.upsert(provider.clone())?;
+    Ok(provider)
+}
+
+// Command handler listing the saved TVHeadend providers
+#[tauri::command]
+pub fn tvheadend_list_providers(providers: State<'_, TvheadendProviders>) -> Vec<TvheadendProvider> {
+    providers.list()
+}
+
+// Command handler that deletes a saved TVHeadend provider
+#[tauri::command]
+pub fn tvheadend_remove_provider(app: AppHandle, provider_id: String) -> Result<bool, String> {
+    forget_provider(&app, &provider_id)
+}
+
+// Command handler listing a server's channel tags, which group channels
+#[tauri::command]
+pub async fn tvheadend_get_tags(
+    providers: State<'_, TvheadendProviders>,
+    provider_id: String,
+) -> Result<Vec<ChannelTag>, String> {
+    provider_client(&providers, &provider_id)?.tags().await
+}
+
+// Command handler listing a server's enabled channels
+#[tauri::command]
+pub async fn tvheadend_get_channels(
+    providers: State<'_, TvheadendProviders>,
+    provider_id: String,
+) -> Result<Vec<TvheadendChannel>, String> {
+    let mut channels = provider_client(&providers, &provider_id)?.channels().await?;
+    for channel in &mut channels {
+        channel.channel_id = identity::channel_id(&provider_id, &identity::tvheadend_key(&channel.uuid));
+    }
+    Ok(channels)
+}
+
+// Command handler returning a channel's next programmes
+#[tauri::command]
+pub async fn tvheadend_get_epg(
+    providers: State<'_, TvheadendProviders>,
+    provider_id: String,
+    channel_uuid: String,
+    limit: Option<u32>,
+) -> Result<Vec<Programme>, String> {
+    validate_string_length(&channel_uuid, MAX_FIELD_LENGTH)?;
+    let limit = limit.unwrap_or(DEFAULT_EPG_LIMIT).clamp(1, MAX_EPG_LIMIT);
+    provider_client(&providers, &provider_id)?
+        .events(Some(channel_uuid.trim()), limit)
+        .await
+}
+
+// Command handler that imports a server's whole guide into the EPG store;
+// programmes are keyed by channel uuid
+#[tauri::command]
+pub async fn tvheadend_refresh_epg(
+    app: AppHandle,
+    providers: State<'_, TvheadendProviders>,
+    provider_id: String,
+) -> Result<EpgSource, String> {
+    let programmes = provider_client(&providers, &provider_id)?
+        .events(None, MAX_GUIDE_EVENTS)
+        .await?;
+    let handle = app.clone();
+    let id = source_id(&provider_id);
+    let source = tauri::async_runtime::spawn_blocking(move || {
+        handle.state::<EpgStore>().replace(&id, Guide::from(programmes), unix_now())
+    })
+    .await
+    .map_err(|e| format!("Failed to store guide: {}", e))??;
+    let _ = app.emit("epg-updated", source.clone());
+    Ok(source)
+}
+
+// Command handler returning a playable URL for a channel, authenticated
+// with a ticket when the server needs a login
+#[tauri::command]
+pub async fn tvheadend_stream_url(
+    providers: State<'_, TvheadendProviders>,
+    provider_id: String,
+    channel_uuid: String,
+) -> Result<String, String> {
+    validate_string_length(&channel_uuid, MAX_FIELD_LENGTH)?;
+    provider_client(&providers, &provider_id)?
+        .stream_url(channel_uuid.trim())
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn test_providers_persist() {
+        // Test that saved servers survive reopening and ids ignore address spelling
+        let server = |url: &str| TvheadendServer {
+            url: url.to_string(),
+            username: "user".to_string(),
+            ..Default::default()
+        };
+        let id = |url: &str| provider_id(&TvheadendClient::new(&server(url)).unwrap(), " user");
+        assert_eq!(id("tvh.lan"), id("http://tvh.lan:9981/"));
+        assert_ne!(id("tvh.lan"), id("tvh.lan:9982"));
+
+        let dir = temp_dir("tvheadend-providers");
+        let providers = TvheadendProviders::open(&dir);
+        let client = TvheadendClient::new(&server("tvh.lan")).unwrap();
+        let provider = TvheadendProvider::new(&client, "", &server("tvh.lan"));
+        assert_eq!(provider.name, "http://tvh.lan:9981");
+        providers.upsert(provider.clone()).unwrap();
+        assert_eq!(TvheadendProviders::open(&dir).get(&provider.id).unwrap(), provider);
+        assert!(providers.remove(&provider.id).unwrap());
+        assert!(providers.get(&provider.id).is_err());
+    }
+}
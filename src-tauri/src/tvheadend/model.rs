@@ -0,0 +1,123 @@
+// Typed views of TVHeadend JSON API answers
+use serde::{Deserialize, Serialize};
+
+use crate::de;
+use crate::epg::Programme;
+
+// Answer of api/serverinfo
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all(serialize = "camelCase"))]
+pub struct ServerInfo {
+    #[serde(deserialize_with = "de::string")]
+    pub name: String,
+    #[serde(deserialize_with = "de::string")]
+    pub sw_version: String,
+    #[serde(deserialize_with = "de::u32")]
+    pub api_version: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all(serialize = "camelCase"))]
+pub struct ChannelTag {
+    #[serde(deserialize_with = "de::string")]
+    pub uuid: String,
+    #[serde(deserialize_with = "de::string")]
+    pub name: String,
+    #[serde(deserialize_with = "de::u32")]
+    pub index: u32,
+    // Hidden from clients by the server's admin
+    #[serde(deserialize_with = "de::bool")]
+    pub internal: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all(serialize = "camelCase"))]
+pub struct TvheadendChannel {
+    // Also the channel id of its guide programmes
+    #[serde(deserialize_with = "de::string")]
+    pub uuid: String,
+    #[serde(deserialize_with = "de::string")]
+    pub name: String,
+    #[serde(deserialize_with = "de::opt_u32")]
+    pub number: Option<u32>,
+    // Icon URL the server proxies, relative to the server address
+    #[serde(deserialize_with = "de::opt_string")]
+    pub icon_public_url: Option<String>,
+    // Uuids of the channel's tags
+    #[serde(deserialize_with = "de::string_list")]
+    pub tags: Vec<String>,
+    #[serde(deserialize_with = "de::bool")]
+    pub enabled: bool,
+    // Stable identity (see crate::identity), filled in per provider
+    #[serde(skip_deserializing)]
+    pub channel_id: String,
+}
+
+// Item of an api/epg/events/grid answer
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RawEvent {
+    #[serde(deserialize_with = "de::string")]
+    channel_uuid: String,
+    #[serde(deserialize_with = "de::opt_i64")]
+    start: Option<i64>,
+    #[serde(deserialize_with = "de::opt_i64")]
+    stop: Option<i64>,
+    #[serde(deserialize_with = "de::string")]
+    title: String,
+    #[serde(deserialize_with = "de::opt_string")]
+    subtitle: Option<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    summary: Option<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    description: Option<String>,
+}
+
+impl RawEvent {
+    pub fn into_programme(self) -> Option<Programme> {
+        let (start, stop) = (self.start?, self.stop?);
+        (stop > start && !self.title.is_empty()).then_some(Programme {
+            channel: self.channel_uuid,
+            start,
+            stop,
+            title: self.title,
+            description: self.description.or(self.summary).or(self.subtitle),
+            ..Default::default()
+        })
+    }
+}
+
+// Paged grid answers: {"entries": [...], "total": n} ("totalCount" for events)
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Grid {
+    pub entries: serde_json::Value,
+    #[serde(alias = "totalCount", deserialize_with = "de::opt_u32")]
+    pub total: Option<u32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_and_event_shapes() {
+        // Test channel fields and events falling back to their summary
+        let channel: TvheadendChannel = serde_json::from_str(
+            r#"{"uuid": "c1", "name": "One", "number": 101, "icon_public_url": "imagecache/4", "tags": ["t1"],
+                "enabled": true}"#,
+        )
+        .unwrap();
+        assert_eq!((channel.number, channel.tags.len()), (Some(101), 1));
+
+        let event: RawEvent = serde_json::from_str(
+            r#"{"eventId": 9, "channelUuid": "c1", "start": 100, "stop": 200, "title": "News", "summary": "Today"}"#,
+        )
+        .unwrap();
+        let programme = event.into_programme().unwrap();
+        assert_eq!((programme.channel.as_str(), programme.description.as_deref()), ("c1", Some("Today")));
+
+        let grid: Grid = serde_json::from_str(r#"{"entries": [], "totalCount": 5}"#).unwrap();
+        assert_eq!(grid.total, Some(5));
+    }
+}
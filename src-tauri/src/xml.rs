@@ -0,0 +1,60 @@
+// Small helpers shared by the quick-xml based parsers
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::Event;
+
+// Append the character data carried by an event (text, CDATA or an entity
+// reference) to `out`; returns false for any other event
+pub fn append_text(out: &mut String, event: &Event<'_>) -> bool {
+    match event {
+        Event::Text(text) => {
+            if let Ok(text) = text.xml_content() {
+                out.push_str(&text);
+            }
+        }
+        Event::CData(data) => {
+            if let Ok(data) = data.decode() {
+                out.push_str(&data);
+            }
+        }
+        Event::GeneralRef(reference) => match reference.resolve_char_ref() {
+            Ok(Some(ch)) => out.push(ch),
+            _ => {
+                if let Ok(name) = reference.decode() {
+                    match resolve_predefined_entity(&name) {
+                        Some(value) => out.push_str(value),
+                        // Unknown entities are kept verbatim
+                        None => {
+                            out.push('&');
+                            out.push_str(&name);
+                            out.push(';');
+                        }
+                    }
+                }
+            }
+        },
+        _ => return false,
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quick_xml::Reader;
+
+    #[test]
+    fn test_append_text_resolves_entities() {
+        // Test that text, CDATA, predefined and numeric references are decoded
+        let mut reader = Reader::from_str("<a>x &amp; y &#65;<![CDATA[<b>]]>&nbsp;</a>");
+        let mut out = String::new();
+        loop {
+            match reader.read_event().unwrap() {
+                Event::Eof => break,
+                event => {
+                    append_text(&mut out, &event);
+                }
+            }
+        }
+        assert_eq!(out, "x & y A<b>&nbsp;");
+    }
+}
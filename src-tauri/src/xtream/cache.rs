@@ -0,0 +1,72 @@
+// In-memory cache of Xtream API answers so browsing doesn't re-download
+// large lists on every click
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Provider id and request (e.g. a category id)
+type CacheKey = (String, String);
+
+pub struct TtlCache<V> {
+    ttl: Duration,
+    entries: Mutex<HashMap<CacheKey, (Instant, Arc<V>)>>,
+}
+
+impl<V> TtlCache<V> {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, provider_id: &str, key: &str) -> Option<Arc<V>> {
+        let mut entries = self.entries.lock().unwrap();
+        let cache_key = (provider_id.to_string(), key.to_string());
+        match entries.get(&cache_key) {
+            Some((stored, value)) if stored.elapsed() < self.ttl => Some(value.clone()),
+            Some(_) => {
+                entries.remove(&cache_key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(&self, provider_id: &str, key: &str, value: V) -> Arc<V> {
+        let value = Arc::new(value);
+        self.entries
+            .lock()
+            .unwrap()
+            .insert((provider_id.to_string(), key.to_string()), (Instant::now(), value.clone()));
+        value
+    }
+
+    // Forget everything cached for a provider
+    pub fn invalidate(&self, provider_id: &str) {
+        self.entries.lock().unwrap().retain(|(id, _), _| id != provider_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ttl_cache() {
+        // Test expiry and per-provider invalidation
+        let cache = TtlCache::new(Duration::from_secs(60));
+        cache.insert("a", "1", 1);
+        cache.insert("b", "1", 2);
+        assert_eq!(cache.get("a", "1").as_deref(), Some(&1));
+        assert_eq!(cache.get("a", "2"), None);
+
+        cache.invalidate("a");
+        assert_eq!(cache.get("a", "1"), None);
+        assert_eq!(cache.get("b", "1").as_deref(), Some(&2));
+
+        let expired = TtlCache::new(Duration::ZERO);
+        expired.insert("a", "1", 1);
+        assert_eq!(expired.get("a", "1"), None);
+    }
+}
@@ -0,0 +1,397 @@
+// HTTP client for the Xtream Codes player_api.php protocol
+use futures_util::TryStreamExt;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio_util::io::StreamReader;
+use url::Url;
+
+use super::epg::{self, EpgListing};
+use super::model::{AccountInfo, Category, LiveStream, VodInfo, VodStream};
+use super::series::{Series, SeriesInfo};
+use crate::compression;
+use crate::de;
+use crate::epg::{xmltv, Guide};
+use crate::http;
+
+// Server address plus login of one Xtream account
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct XtreamCredentials {
+    pub server: String,
+    pub username: String,
+    pub password: String,
+}
+
+// Container used for live playback URLs
+const LIVE_EXTENSION: &str = "ts";
+// Assumed when a movie or episode doesn't state its container
+const DEFAULT_VOD_EXTENSION: &str = "mp4";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamKind {
+    Live,
+    Movie,
+    Series,
+}
+
+impl StreamKind {
+    pub fn path(self) -> &'static str {
+        match self {
+            StreamKind::Live => "live",
+            StreamKind::Movie => "movie",
+            StreamKind::Series => "series",
+        }
+    }
+
+    pub fn default_extension(self) -> &'static str {
+        match self {
+            StreamKind::Live => LIVE_EXTENSION,
+            StreamKind::Movie | StreamKind::Series => DEFAULT_VOD_EXTENSION,
+        }
+    }
+}
+
+pub struct XtreamClient {
+    // Scheme, host and port, e.g. http://example.com:8080
+    base: Url,
+    username: String,
+    password: String,
+}
+
+// Accept "example.com:8080", "http://example.com:8080/" or a pasted
+// player_api.php / get.php URL, keeping only the server part
+pub fn normalize_server(server: &str) -> Result<Url, String> {
+    let server = server.trim();
+    if server.is_empty() {
+        return Err("Server address cannot be empty".to_string());
+    }
+    let with_scheme = if server.contains("://") {
+        server.to_string()
+    } else {
+        format!("http://{}", server)
+    };
+    let mut url = Url::parse(&with_scheme).map_err(|e| format!("Invalid server address '{}': {}", server, e))?;
+    if !matches!(url.scheme(), "http" | "https") || !url.has_host() {
+        return Err(format!("Invalid server address '{}'", server));
+    }
+
+    // Panels installed in a subdirectory keep it; the script name goes
+    let path = url.path().trim_end_matches('/').to_string();
+    let path = ["/player_api.php", "/get.php", "/xmltv.php"]
+        .iter()
+        .find_map(|script| path.strip_suffix(script))
+        .unwrap_or(&path)
+        .to_string();
+    url.set_path(&format!("{}/", path));
+    url.set_query(None);
+    url.set_fragment(None);
+    Ok(url)
+}
+
+impl XtreamClient {
+    pub fn new(credentials: &XtreamCredentials) -> Result<Self, String> {
+        if credentials.username.trim().is_empty() {
+            return Err("Username cannot be empty".to_string());
+        }
+        Ok(Self {
+            base: normalize_server(&credentials.server)?,
+            username: credentials.username.trim().to_string(),
+            password: credentials.password.trim().to_string(),
+        })
+    }
+
+    pub fn server(&self) -> &str {
+        self.base.as_str().trim_end_matches('/')
+    }
+
+    fn script_url(&self, script: &str, params: &[(&str, &str)]) -> Url {
+        let mut url = self.base.join(script).expect("script names are valid relative URLs");
+        url.query_pairs_mut()
+            .append_pair("username", &self.username)
+            .append_pair("password", &self.password)
+            .extend_pairs(params);
+        url
+    }
+
+    // player_api.php URL for an action (None for the login request)
+    pub fn api_url(&self, action: Option<&str>, params: &[(&str, &str)]) -> Url {
+        let mut url = self.script_url("player_api.php", &[]);
+        if let Some(action) = action {
+            url.query_pairs_mut().append_pair("action", action);
+        }
+        url.query_pairs_mut().extend_pairs(params);
+        url
+    }
+
+    // Playback URL of a stream, e.g. http://host/live/user/pass/1234.ts
+    pub fn stream_url(&self, kind: StreamKind, stream_id: &str, extension: &str) -> String {
+        let file = format!("{}.{}", stream_id, extension.trim_start_matches('.'));
+        let mut url = self.base.clone();
+        url.path_segments_mut()
+            .expect("server URLs have a path")
+            .pop_if_empty()
+            .extend([kind.path(), &self.username, &self.password, &file]);
+        url.to_string()
+    }
+
+    // GET an API action and decode its JSON answer
+    pub async fn get_json<T: DeserializeOwned>(&self, action: Option<&str>, params: &[(&str, &str)]) -> Result<T, String> {
+        let url = self.api_url(action, params);
+        let what = action.unwrap_or("login");
+        let response = http::get(url.as_str(), None)
+            .send()
+            .await
+            .map_err(|e| format!("Xtream request '{}' failed: {}", what, e.without_url()))?;
+        let status = response.status();
+        if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+            return Err("The Xtream server rejected the username or password".to_string());
+        }
+        if !status.is_success() {
+            return Err(format!("Xtream request '{}' failed with HTTP {}", what, status.as_u16()));
+        }
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| format!("Xtream request '{}' failed: {}", what, e.without_url()))?;
+        serde_json::from_slice(&body).map_err(|e| format!("Invalid Xtream response to '{}': {}", what, e))
+    }
+
+    // Log in and return the account and server details
+    pub async fn authenticate(&self) -> Result<AccountInfo, String> {
+        let info: AccountInfo = self.get_json(None, &[]).await?;
+        if !info.user_info.auth {
+            let reason = match info.user_info.message.as_str() {
+                "" => "invalid username or password",
+                message => message,
+            };
+            return Err(format!("Xtream login failed: {}", reason));
+        }
+        Ok(info)
+    }
+
+    pub async fn live_categories(&self) -> Result<Vec<Category>, String> {
+        Ok(de::list(self.get_json(Some("get_live_categories"), &[]).await?))
+    }
+
+    // Live streams of one category, or of all of them with None
+    pub async fn live_streams(&self, category_id: Option<&str>) -> Result<Vec<LiveStream>, String> {
+        let params: Vec<(&str, &str)> = category_id.map(|id| ("category_id", id)).into_iter().collect();
+        let mut streams: Vec<LiveStream> = de::list(self.get_json(Some("get_live_streams"), &params).await?);
+        for stream in &mut streams {
+            stream.url = self.stream_url(StreamKind::Live, &stream.stream_id, LIVE_EXTENSION);
+        }
+        Ok(streams)
+    }
+
+    pub async fn vod_categories(&self) -> Result<Vec<Category>, String> {
+        Ok(de::list(self.get_json(Some("get_vod_categories"), &[]).await?))
+    }
+
+    pub async fn vod_streams(&self, category_id: Option<&str>) -> Result<Vec<VodStream>, String> {
+        let params: Vec<(&str, &str)> = category_id.map(|id| ("category_id", id)).into_iter().collect();
+        let mut streams: Vec<VodStream> = de::list(self.get_json(Some("get_vod_streams"), &params).await?);
+        for stream in &mut streams {
+            stream.url = self.movie_url(&stream.stream_id, stream.container_extension.as_deref());
+        }
+        Ok(streams)
+    }
+
+    pub async fn vod_info(&self, vod_id: &str) -> Result<VodInfo, String> {
+        let mut info: VodInfo = self.get_json(Some("get_vod_info"), &[("vod_id", vod_id)]).await?;
+        if info.movie_data.stream_id.is_empty() {
+            return Err(format!("Xtream movie '{}' doesn't exist", vod_id));
+        }
+        info.url = self.movie_url(&info.movie_data.stream_id, info.movie_data.container_extension.as_deref());
+        Ok(info)
+    }
+
+    fn movie_url(&self, stream_id: &str, extension: Option<&str>) -> String {
+        self.stream_url(StreamKind::Movie, stream_id, extension.unwrap_or(DEFAULT_VOD_EXTENSION))
+    }
+
+    pub async fn series_categories(&self) -> Result<Vec<Category>, String> {
+        Ok(de::list(self.get_json(Some("get_series_categories"), &[]).await?))
+    }
+
+    pub async fn series(&self, category_id: Option<&str>) -> Result<Vec<Series>, String> {
+        let params: Vec<(&str, &str)> = category_id.map(|id| ("category_id", id)).into_iter().collect();
+        Ok(de::list(self.get_json(Some("get_series"), &params).await?))
+    }
+
+    // Now/next style listings of a live stream
+    pub async fn short_epg(&self, stream_id: &str, limit: u32) -> Result<Vec<EpgListing>, String> {
+        let limit = limit.to_string();
+        let value = self
+            .get_json(Some("get_short_epg"), &[("stream_id", stream_id), ("limit", &limit)])
+            .await?;
+        Ok(epg::listings(value))
+    }
+
+    // Full programme table of one live stream
+    pub async fn simple_data_table(&self, stream_id: &str) -> Result<Vec<EpgListing>, String> {
+        let value = self
+            .get_json(Some("get_simple_data_table"), &[("stream_id", stream_id)])
+            .await?;
+        Ok(epg::listings(value))
+    }
+
+    // The whole guide as XMLTV from xmltv.php, streamed rather than buffered
+    // since it can be hundreds of megabytes
+    pub async fn xmltv(&self) -> Result<Guide, String> {
+        let url = self.script_url("xmltv.php", &[]);
+        let response = http::get(url.as_str(), None)
+            .send()
+            .await
+            .map_err(|e| format!("Xtream guide download failed: {}", e.without_url()))?;
+        if !response.status().is_success() {
+            return Err(format!("Xtream guide download failed with HTTP {}", response.status().as_u16()));
+        }
+        let encoding = response
+            .headers()
+            .get(reqwest::header::CONTENT_ENCODING)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let body = StreamReader::new(response.bytes_stream().map_err(std::io::Error::other));
+        let (reader, _) = compression::decompress(Box::new(body), encoding.as_deref())
+            .await
+            .map_err(|e| format!("Xtream guide download failed: {}", e))?;
+        xmltv::read_guide(reader).await
+    }
+
+    // Seasons and episodes of a series, with playback URLs
+    pub async fn series_info(&self, series_id: &str) -> Result<SeriesInfo, String> {
+        let value = self.get_json(Some("get_series_info"), &[("series_id", series_id)]).await?;
+        let mut info = SeriesInfo::from_value(value);
+        if info.seasons.is_empty() && info.info.name.is_empty() {
+            return Err(format!("Xtream series '{}' doesn't exist", series_id));
+        }
+        if info.info.series_id.is_empty() {
+            info.info.series_id = series_id.to_string();
+        }
+        for episode in info.episodes_mut() {
+            let extension = episode.container_extension.as_deref().unwrap_or(DEFAULT_VOD_EXTENSION);
+            episode.url = self.stream_url(StreamKind::Series, &episode.id, extension);
+        }
+        Ok(info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{response, serve};
+
+    fn client(server: &str) -> XtreamClient {
+        XtreamClient::new(&XtreamCredentials {
+            server: server.to_string(),
+            username: "user".to_string(),
+            password: "p&ss".to_string(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_normalize_server() {
+        // Test that pasted API URLs and bare hosts reduce to the server base
+        let normalize = |s: &str| normalize_server(s).unwrap().to_string();
+        assert_eq!(normalize("example.com:8080"), "http://example.com:8080/");
+        assert_eq!(normalize("https://example.com/player_api.php?username=a"), "https://example.com/");
+        assert_eq!(normalize("http://example.com/panel/get.php"), "http://example.com/panel/");
+        assert!(normalize_server("ftp://example.com").is_err());
+        assert!(normalize_server(" ").is_err());
+    }
+
+    #[test]
+    fn test_urls() {
+        // Test API and playback URL construction with escaped credentials
+        let client = client("http://example.com:8080/panel");
+        assert_eq!(
+            client.api_url(Some("get_live_streams"), &[("category_id", "5")]).as_str(),
+            "http://example.com:8080/panel/player_api.php?username=user&password=p%26ss&action=get_live_streams&category_id=5"
+        );
+        assert_eq!(
+            client.stream_url(StreamKind::Live, "42", "ts"),
+            "http://example.com:8080/panel/live/user/p&ss/42.ts"
+        );
+        assert_eq!(
+            client.stream_url(StreamKind::Movie, "7", ".mkv"),
+            "http://example.com:8080/panel/movie/user/p&ss/7.mkv"
+        );
+    }
+
+    #[test]
+    fn test_authenticate() {
+        // Test that a successful login decodes and auth=0 is reported as an error
+        let ok = r#"{"user_info":{"username":"user","auth":1,"status":"Active"},"server_info":{"url":"example.com"}}"#;
+        let denied = r#"{"user_info":{"auth":0}}"#;
+        let (base, requests) = serve(vec![
+            response("200 OK", &[("Content-Type", "application/json")], ok),
+            response("200 OK", &[], denied),
+        ]);
+        let client = client(&base);
+
+        let info = tauri::async_runtime::block_on(client.authenticate()).unwrap();
+        assert_eq!(info.user_info.status, "Active");
+        assert!(requests.lock().unwrap()[0].starts_with("GET /player_api.php?username=user&password=p%26ss "));
+
+        let error = tauri::async_runtime::block_on(client.authenticate()).unwrap_err();
+        assert_eq!(error, "Xtream login failed: invalid username or password");
+    }
+
+    #[test]
+    fn test_live_streams() {
+        // Test that streams get playback URLs and the category is requested
+        let body = r#"[{"name": "One", "stream_id": 1, "category_id": "2"}, {"name": "Two", "stream_id": "2"}]"#;
+        let (base, requests) = serve(vec![response("200 OK", &[], body)]);
+        let streams = tauri::async_runtime::block_on(client(&base).live_streams(Some("2"))).unwrap();
+
+        assert_eq!(streams.len(), 2);
+        assert_eq!(streams[0].url, format!("{}/live/user/p&ss/1.ts", base));
+        assert!(requests.lock().unwrap()[0].contains("action=get_live_streams&category_id=2 "));
+    }
+
+    #[test]
+    fn test_vod_info() {
+        // Test that movie details get a playback URL and unknown ids fail
+        let body = r#"{"info": {"plot": "A film."}, "movie_data": {"stream_id": "77", "container_extension": "mkv"}}"#;
+        let (base, requests) = serve(vec![
+            response("200 OK", &[], body),
+            response("200 OK", &[], r#"{"info": [], "movie_data": []}"#),
+        ]);
+        let client = client(&base);
+
+        let info = tauri::async_runtime::block_on(client.vod_info("77")).unwrap();
+        assert_eq!(info.url, format!("{}/movie/user/p&ss/77.mkv", base));
+        assert!(requests.lock().unwrap()[0].contains("action=get_vod_info&vod_id=77 "));
+
+        let error = tauri::async_runtime::block_on(client.vod_info("78")).unwrap_err();
+        assert_eq!(error, "Xtream movie '78' doesn't exist");
+    }
+
+    #[test]
+    fn test_series_info() {
+        // Test that episodes get series playback URLs and the id is kept
+        let body = r#"{"info": {"name": "Show"}, "episodes": {"1": [{"id": "9", "container_extension": "mkv"}]}}"#;
+        let (base, _) = serve(vec![response("200 OK", &[], body), response("200 OK", &[], "[]")]);
+        let client = client(&base);
+
+        let info = tauri::async_runtime::block_on(client.series_info("5")).unwrap();
+        assert_eq!(info.info.series_id, "5");
+        assert_eq!(info.seasons[0].episodes[0].url, format!("{}/series/user/p&ss/9.mkv", base));
+        assert!(tauri::async_runtime::block_on(client.series_info("6")).is_err());
+    }
+
+    #[test]
+    fn test_xmltv_download() {
+        // Test that xmltv.php is requested with the credentials and parsed
+        let guide = r#"<tv><programme start="20240101100000 +0000" stop="20240101110000 +0000" channel="one.uk">
+            <title>News</title></programme></tv>"#;
+        let (base, requests) = serve(vec![response("200 OK", &[("Content-Type", "text/xml")], guide)]);
+        let programmes = tauri::async_runtime::block_on(client(&base).xmltv()).unwrap().programmes;
+
+        assert_eq!(programmes.len(), 1);
+        assert_eq!(programmes[0].channel, "one.uk");
+        assert!(requests.lock().unwrap()[0].starts_with("GET /xmltv.php?username=user&password=p%26ss "));
+    }
+}
@@ -0,0 +1,95 @@
+// Programme listings from get_short_epg, whose text fields are base64 and
+// whose times come both as Unix seconds and as server-local date strings
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::de;
+use crate::epg::time;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EpgListing {
+    pub title: String,
+    pub description: String,
+    // Unix seconds
+    pub start: i64,
+    pub stop: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct RawListing {
+    #[serde(deserialize_with = "de::string")]
+    title: String,
+    #[serde(deserialize_with = "de::string")]
+    description: String,
+    #[serde(deserialize_with = "de::opt_string")]
+    lang: Option<String>,
+    #[serde(deserialize_with = "de::opt_i64")]
+    start_timestamp: Option<i64>,
+    #[serde(deserialize_with = "de::opt_i64")]
+    stop_timestamp: Option<i64>,
+    #[serde(deserialize_with = "de::string")]
+    start: String,
+    #[serde(deserialize_with = "de::string")]
+    end: String,
+}
+
+impl RawListing {
+    // The timestamps are exact; the date strings are only a fallback since
+    // they're in the server's timezone
+    fn into_listing(self) -> Option<EpgListing> {
+        let start = self.start_timestamp.or_else(|| time::parse_datetime(&self.start))?;
+        let stop = self.stop_timestamp.or_else(|| time::parse_datetime(&self.end))?;
+        (stop > start).then(|| EpgListing {
+            title: de::base64_text(&self.title),
+            description: de::base64_text(&self.description),
+            start,
+            stop,
+            lang: self.lang,
+        })
+    }
+}
+
+// Listings of an {"epg_listings": [...]} answer, in start order
+pub fn listings(value: Value) -> Vec<EpgListing> {
+    let items = match value {
+        Value::Object(mut map) => map.remove("epg_listings").unwrap_or_default(),
+        other => other,
+    };
+    let mut listings: Vec<EpgListing> = de::list::<RawListing>(items)
+        .into_iter()
+        .filter_map(RawListing::into_listing)
+        .collect();
+    listings.sort_by_key(|listing| listing.start);
+    listings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_short_epg_listings() {
+        // Test base64 decoding, timestamp preference and dropping bad entries
+        let found = listings(json!({"epg_listings": [
+            {"title": "TGF0ZXI=", "description": "", "start": "2024-01-01 11:00:00",
+                "end": "2024-01-01 12:00:00"},
+            {"title": "TmV3cw==", "description": "SGVhZGxpbmVz", "lang": "en",
+                "start": "2024-01-01 10:00:00", "end": "2024-01-01 11:00:00",
+                "start_timestamp": "1704099600", "stop_timestamp": 1704103200},
+            {"title": "QnJva2Vu", "start": "", "end": ""}
+        ]}));
+
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].title, "News");
+        assert_eq!(found[0].description, "Headlines");
+        assert_eq!((found[0].start, found[0].stop), (1_704_099_600, 1_704_103_200));
+        assert_eq!(found[1].title, "Later");
+        assert_eq!(found[1].start, 1_704_106_800);
+        assert!(listings(json!({"epg_listings": []})).is_empty());
+    }
+}
@@ -0,0 +1,162 @@
+// Background import of each Xtream provider's full guide into the EPG store,
+// so Xtream users get a guide without configuring an XMLTV URL
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use tauri::{AppHandle, Emitter, Manager};
+
+use super::{EpgListing, XtreamClient, XtreamProvider, XtreamProviders};
+use crate::epg::{EpgSource, EpgStore, Guide, Programme};
+use crate::providers::ProviderRegistry;
+use crate::unix_now;
+
+// How often the loop looks for guides that are due
+const CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+// Age after which a provider's guide is downloaded again, in seconds
+const REFRESH_INTERVAL: i64 = 12 * 60 * 60;
+
+// Wait before retrying a provider whose import failed, in seconds
+const RETRY_INTERVAL: i64 = 60 * 60;
+
+// get_simple_data_table requests in flight at once
+const TABLE_CONCURRENCY: usize = 4;
+
+// EPG store id of a provider's guide
+pub fn source_id(provider_id: &str) -> String {
+    format!("xtream:{}", provider_id)
+}
+
+fn programme(channel: &str, listing: EpgListing) -> Programme {
+    Programme {
+        channel: channel.to_string(),
+        start: listing.start,
+        stop: listing.stop,
+        title: listing.title,
+        description: Some(listing.description).filter(|d| !d.is_empty()),
+        ..Default::default()
+    }
+}
+
+// The guide via xmltv.php, or stream by stream through get_simple_data_table
+// on panels that disable or break the XMLTV export
+pub async fn fetch(client: &XtreamClient) -> Result<Guide, String> {
+    match client.xmltv().await {
+        Ok(guide) if !guide.programmes.is_empty() => return Ok(guide),
+        Ok(_) => log::info!("Xtream xmltv.php at {} is empty, reading stream tables", client.server()),
+        Err(e) => log::info!("Xtream xmltv.php at {} unavailable ({}), reading stream tables", client.server(), e),
+    }
+
+    // One request per guide channel, made with the first stream carrying it
+    let channels: BTreeMap<String, String> = client
+        .live_streams(None)
+        .await?
+        .into_iter()
+        .filter_map(|stream| Some((stream.epg_channel_id?, stream.stream_id)))
+        .rev()
+        .collect();
+    let results: Vec<_> = futures_util::stream::iter(channels)
+        .map(|(channel, stream_id)| async move { (channel, client.simple_data_table(&stream_id).await) })
+        .buffer_unordered(TABLE_CONCURRENCY)
+        .collect()
+        .await;
+
+    let mut programmes = Vec::new();
+    let mut first_error = None;
+    let attempted = results.len();
+    for (channel, result) in results {
+        match result {
+            Ok(listings) => programmes.extend(listings.into_iter().map(|listing| programme(&channel, listing))),
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    match first_error {
+        Some(e) if programmes.is_empty() && attempted > 0 => Err(e),
+        _ => Ok(Guide::from(programmes)),
+    }
+}
+
+// Download a provider's guide and store it
+pub async fn ingest(app: &AppHandle, provider: &XtreamProvider) -> Result<EpgSource, String> {
+    let guide = fetch(&XtreamClient::new(&provider.credentials)?).await?;
+    let handle = app.clone();
+    let id = source_id(&provider.id);
+    let source = tauri::async_runtime::spawn_blocking(move || {
+        handle.state::<EpgStore>().replace(&id, guide, unix_now())
+    })
+    .await
+    .map_err(|e| format!("Failed to store guide: {}", e))??;
+    let _ = app.emit("epg-updated", source.clone());
+    Ok(source)
+}
+
+// Start the import loop; runs for the lifetime of the app
+pub fn start(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut failed_at: HashMap<String, i64> = HashMap::new();
+        loop {
+            let now = unix_now();
+            for provider in app.state::<XtreamProviders>().list() {
+                let interval = match app.state::<ProviderRegistry>().refresh_interval(&provider.id) {
+                    Some(0) => continue,
+                    Some(hours) => i64::from(hours) * 3600,
+                    None => REFRESH_INTERVAL,
+                };
+                let updated_at = app.state::<EpgStore>().updated_at(&source_id(&provider.id));
+                let fresh = updated_at.is_some_and(|at| now - at < interval);
+                let backing_off = failed_at.get(&provider.id).is_some_and(|at| now - at < RETRY_INTERVAL);
+                if fresh || backing_off {
+                    continue;
+                }
+                match ingest(&app, &provider).await {
+                    Ok(source) => {
+                        failed_at.remove(&provider.id);
+                        log::info!("Imported {} programmes from Xtream provider '{}'", source.programmes, provider.name);
+                    }
+                    Err(e) => {
+                        failed_at.insert(provider.id.clone(), now);
+                        log::warn!("Guide import from Xtream provider '{}' failed: {}", provider.name, e);
+                    }
+                }
+            }
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{response, serve};
+    use crate::xtream::XtreamCredentials;
+
+    #[test]
+    fn test_fetch_falls_back_to_stream_tables() {
+        // Test that a failing xmltv.php falls back to one table per EPG channel
+        let streams = r#"[{"stream_id": 1, "epg_channel_id": "one.uk"}, {"stream_id": 2, "epg_channel_id": "one.uk"},
+            {"stream_id": 3, "epg_channel_id": ""}]"#;
+        let table = r#"{"epg_listings": [{"title": "TmV3cw==", "description": "",
+            "start_timestamp": "1704103200", "stop_timestamp": "1704106800"}]}"#;
+        let (base, requests) = serve(vec![
+            response("404 Not Found", &[], ""),
+            response("200 OK", &[], streams),
+            response("200 OK", &[], table),
+        ]);
+        let client = XtreamClient::new(&XtreamCredentials {
+            server: base,
+            username: "user".to_string(),
+            password: "pass".to_string(),
+        })
+        .unwrap();
+
+        let programmes = tauri::async_runtime::block_on(fetch(&client)).unwrap().programmes;
+        assert_eq!(programmes.len(), 1);
+        assert_eq!(programmes[0].channel, "one.uk");
+        assert_eq!(programmes[0].title, "News");
+        assert_eq!(programmes[0].description, None);
+        assert!(requests.lock().unwrap()[2].contains("action=get_simple_data_table&stream_id=1 "));
+    }
+}
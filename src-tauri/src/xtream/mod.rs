@@ -0,0 +1,729 @@
+// Xtream Codes providers: saved accounts and the player_api.php client
+mod cache;
+mod client;
+mod epg;
+pub mod guide;
+mod model;
+mod rotation;
+mod series;
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+
+use crate::epg::{EpgSource, EpgStore};
+use crate::identity;
+use crate::playlist::content_hash;
+use crate::storage;
+use crate::{unix_now, validate_string_length};
+
+use cache::TtlCache;
+pub use client::{StreamKind, XtreamClient, XtreamCredentials};
+pub use epg::EpgListing;
+pub use model::{AccountInfo, AccountStatus, Category, LiveStream, VodInfo, VodStream};
+pub use rotation::AccountRotation;
+pub use series::{Series, SeriesInfo};
+
+const PROVIDERS_FILE: &str = "xtream_providers.json";
+
+// Limits for user-entered account details
+const MAX_FIELD_LENGTH: usize = 2048;
+const MAX_NAME_LENGTH: usize = 200;
+
+// How long category and stream lists are reused before asking again
+const LIST_TTL: Duration = Duration::from_secs(30 * 60);
+// Movie details hardly ever change
+const INFO_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+// Login answers (account limits, server details) are reused this long
+const ACCOUNT_TTL: Duration = Duration::from_secs(15 * 60);
+// Short enough that "now playing" moves on soon after a programme ends
+const EPG_TTL: Duration = Duration::from_secs(5 * 60);
+
+// Listings get_short_epg returns by default and at most
+const DEFAULT_EPG_LIMIT: u32 = 4;
+const MAX_EPG_LIMIT: u32 = 50;
+
+// Cache key for the unfiltered stream list
+const ALL_CATEGORIES: &str = "";
+// Cache key for the login answer
+const LOGIN: &str = "login";
+
+// Another login on the same server, used when the main one runs out of
+// connections
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct XtreamAccount {
+    pub username: String,
+    pub password: String,
+}
+
+// A saved Xtream account
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct XtreamProvider {
+    pub id: String,
+    pub name: String,
+    #[serde(flatten)]
+    pub credentials: XtreamCredentials,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_accounts: Vec<XtreamAccount>,
+}
+
+// Same server and username always map to the same provider
+fn provider_id(client: &XtreamClient, username: &str) -> String {
+    content_hash(format!("xtream:{}:{}", client.server(), username.trim()).as_bytes())
+}
+
+impl XtreamProvider {
+    // A provider with normalized credentials; an empty name becomes the
+    // server address
+    pub fn new(client: &XtreamClient, name: &str, credentials: &XtreamCredentials) -> Self {
+        XtreamProvider {
+            id: provider_id(client, &credentials.username),
+            name: match name.trim() {
+                "" => client.server().to_string(),
+                name => name.to_string(),
+            },
+            credentials: XtreamCredentials {
+                server: client.server().to_string(),
+                username: credentials.username.trim().to_string(),
+                password: credentials.password.trim().to_string(),
+            },
+            extra_accounts: Vec::new(),
+        }
+    }
+
+    // Every login of the provider, the main one first
+    pub fn logins(&self) -> Vec<XtreamCredentials> {
+        let extra = self.extra_accounts.iter().map(|account| XtreamCredentials {
+            server: self.credentials.server.clone(),
+            username: account.username.clone(),
+            password: account.password.clone(),
+        });
+        std::iter::once(self.credentials.clone()).chain(extra).collect()
+    }
+}
+
+pub struct XtreamProviders {
+    path: PathBuf,
+    providers: Mutex<Vec<XtreamProvider>>,
+}
+
+impl XtreamProviders {
+    pub fn open(data_dir: &Path) -> Self {
+        let path = data_dir.join(PROVIDERS_FILE);
+        let providers: Vec<XtreamProvider> = storage::read_json(&path).unwrap_or_else(|e| {
+            log::error!("Failed to load Xtream providers: {}", e);
+            Vec::new()
+        });
+        Self {
+            path,
+            providers: Mutex::new(providers),
+        }
+    }
+
+    pub fn list(&self) -> Vec<XtreamProvider> {
+        self.providers.lock().unwrap().clone()
+    }
+
+    pub fn get(&self, id: &str) -> Result<XtreamProvider, String> {
+        self.providers
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|p| p.id == id)
+            .cloned()
+            .ok_or_else(|| format!("Xtream provider '{}' doesn't exist", id))
+    }
+
+    pub fn upsert(&self, provider: XtreamProvider) -> Result<(), String> {
+        let mut providers = self.providers.lock().unwrap();
+        match providers.iter_mut().find(|p| p.id == provider.id) {
+            Some(existing) => *existing = provider,
+            None => providers.push(provider),
+        }
+        storage::write_json(&self.path, &*providers)
+    }
+
+    fn remove(&self, id: &str) -> Result<bool, String> {
+        let mut providers = self.providers.lock().unwrap();
+        let before = providers.len();
+        providers.retain(|p| p.id != id);
+        if providers.len() == before {
+            return Ok(false);
+        }
+        storage::write_json(&self.path, &*providers)?;
+        Ok(true)
+    }
+}
+
+// Recently fetched lists per provider
+pub struct XtreamCache {
+    account: TtlCache<AccountInfo>,
+    live_categories: TtlCache<Vec<Category>>,
+    live_streams: TtlCache<Vec<LiveStream>>,
+    vod_categories: TtlCache<Vec<Category>>,
+    vod_streams: TtlCache<Vec<VodStream>>,
+    vod_info: TtlCache<VodInfo>,
+    series_categories: TtlCache<Vec<Category>>,
+    series: TtlCache<Vec<Series>>,
+    series_info: TtlCache<SeriesInfo>,
+    short_epg: TtlCache<Vec<EpgListing>>,
+}
+
+impl Default for XtreamCache {
+    fn default() -> Self {
+        Self {
+            account: TtlCache::new(ACCOUNT_TTL),
+            live_categories: TtlCache::new(LIST_TTL),
+            live_streams: TtlCache::new(LIST_TTL),
+            vod_categories: TtlCache::new(LIST_TTL),
+            vod_streams: TtlCache::new(LIST_TTL),
+            vod_info: TtlCache::new(INFO_TTL),
+            series_categories: TtlCache::new(LIST_TTL),
+            series: TtlCache::new(LIST_TTL),
+            // New episodes appear, so this is kept no longer than the lists
+            series_info: TtlCache::new(LIST_TTL),
+            short_epg: TtlCache::new(EPG_TTL),
+        }
+    }
+}
+
+impl XtreamCache {
+    // Every live stream of a provider, if the full list was fetched lately
+    pub fn all_live_streams(&self, provider_id: &str) -> Option<Arc<Vec<LiveStream>>> {
+        self.live_streams.get(provider_id, ALL_CATEGORIES)
+    }
+
+    pub fn invalidate(&self, provider_id: &str) {
+        self.account.invalidate(provider_id);
+        self.live_categories.invalidate(provider_id);
+        self.live_streams.invalidate(provider_id);
+        self.vod_categories.invalidate(provider_id);
+        self.vod_streams.invalidate(provider_id);
+        self.vod_info.invalidate(provider_id);
+        self.series_categories.invalidate(provider_id);
+        self.series.invalidate(provider_id);
+        self.series_info.invalidate(provider_id);
+        self.short_epg.invalidate(provider_id);
+    }
+}
+
+pub fn check_credentials(credentials: &XtreamCredentials) -> Result<(), String> {
+    validate_string_length(&credentials.server, MAX_FIELD_LENGTH)?;
+    validate_string_length(&credentials.username, MAX_FIELD_LENGTH)?;
+    validate_string_length(&credentials.password, MAX_FIELD_LENGTH)
+}
+
+// Command handler that logs in without saving anything, to check details
+#[tauri::command]
+pub async fn xtream_login(credentials: XtreamCredentials) -> Result<AccountInfo, String> {
+    check_credentials(&credentials)?;
+    XtreamClient::new(&credentials)?.authenticate().await
+}
+
+// Command handler that verifies an account and saves it as a provider
+#[tauri::command]
+pub async fn xtream_add_provider(
+    providers: State<'_, XtreamProviders>,
+    cache: State<'_, XtreamCache>,
+    name: String,
+    credentials: XtreamCredentials,
+) -> Result<XtreamProvider, String> {
+    check_credentials(&credentials)?;
+    validate_string_length(&name, MAX_NAME_LENGTH)?;
+    let client = XtreamClient::new(&credentials)?;
+    let info = client.authenticate().await?;
+
+    let mut provider = XtreamProvider::new(&client, &name, &credentials);
+    provider.extra_accounts = providers.get(&provider.id).map(|p| p.extra_accounts).unwrap_or_default();
+    providers.upsert(provider.clone())?;
+    // Re-adding may change the password, so cached URLs are stale
+    cache.invalidate(&provider.id);
+    cache.account.insert(&provider.id, LOGIN, info);
+    Ok(provider)
+}
+
+// Command handler listing the saved Xtream providers
+#[tauri::command]
+pub fn xtream_list_providers(providers: State<'_, XtreamProviders>) -> Vec<XtreamProvider> {
+    providers.list()
+}
+
+// Remove a saved provider with its cached lists and imported guide
+pub fn forget_provider(app: &AppHandle, provider_id: &str) -> Result<bool, String> {
+    app.state::<XtreamCache>().invalidate(provider_id);
+    app.state::<AccountRotation>().forget(provider_id);
+    app.state::<EpgStore>().remove(&guide::source_id(provider_id))?;
+    app.state::<XtreamProviders>().remove(provider_id)
+}
+
+// Command handler that deletes a saved Xtream provider
+#[tauri::command]
+pub fn xtream_remove_provider(app: AppHandle, provider_id: String) -> Result<bool, String> {
+    forget_provider(&app, &provider_id)
+}
+
+// A saved provider's login answer, logging in only when none is cached
+pub async fn account_info(
+    providers: &XtreamProviders,
+    cache: &XtreamCache,
+    provider_id: &str,
+) -> Result<Arc<AccountInfo>, String> {
+    if let Some(info) = cache.account.get(provider_id, LOGIN) {
+        return Ok(info);
+    }
+    let info = provider_client(providers, provider_id)?.authenticate().await?;
+    Ok(cache.account.insert(provider_id, LOGIN, info))
+}
+
+// Command handler reporting a saved provider's subscription state; always
+// asks the server since connection counts change constantly
+#[tauri::command]
+pub async fn xtream_get_account_info(
+    providers: State<'_, XtreamProviders>,
+    cache: State<'_, XtreamCache>,
+    provider_id: String,
+) -> Result<AccountStatus, String> {
+    let info = provider_client(&providers, &provider_id)?.authenticate().await?;
+    let info = cache.account.insert(&provider_id, LOGIN, info);
+    Ok(AccountStatus::new(info.user_info.clone(), unix_now()))
+}
+
+// Where a stream plays and with which of the provider's accounts
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamLease {
+    pub url: String,
+    pub username: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountUsage {
+    pub username: String,
+    // Streams currently playing on the account, as "<kind>:<stream id>"
+    pub streams: Vec<String>,
+    // Hit its connection limit lately and isn't handed out for now
+    pub at_limit: bool,
+}
+
+fn stream_key(kind: StreamKind, stream_id: &str) -> String {
+    format!("{}:{}", kind.path(), stream_id.trim())
+}
+
+fn lease(
+    login: &XtreamCredentials,
+    kind: StreamKind,
+    stream_id: &str,
+    extension: Option<&str>,
+) -> Result<StreamLease, String> {
+    let extension = extension.map(str::trim).filter(|e| !e.is_empty()).unwrap_or(kind.default_extension());
+    Ok(StreamLease {
+        url: XtreamClient::new(login)?.stream_url(kind, stream_id.trim(), extension),
+        username: login.username.clone(),
+    })
+}
+
+// A stream's URL made anew once the server stopped taking it: its account
+// logs in again, which hands out a fresh token where the server issues them
+pub async fn renew_stream_url(
+    app: &AppHandle,
+    provider_id: &str,
+    kind: StreamKind,
+    stream_id: &str,
+    extension: Option<&str>,
+) -> Result<String, String> {
+    let logins = app.state::<XtreamProviders>().get(provider_id)?.logins();
+    let key = stream_key(kind, stream_id);
+    let account = app
+        .state::<AccountRotation>()
+        .assign(provider_id, &key, logins.len(), unix_now())
+        .ok_or("Every account of this provider is at its connection limit")?;
+    let login = &logins[account];
+    let info = XtreamClient::new(login)?.authenticate().await?;
+    if account == 0 {
+        app.state::<XtreamCache>().account.insert(provider_id, LOGIN, info);
+    }
+    Ok(lease(login, kind, stream_id, extension)?.url)
+}
+
+// Command handler picking the account a stream plays on: the one it
+// already uses, else the least busy one
+#[tauri::command]
+pub fn xtream_open_stream(
+    providers: State<'_, XtreamProviders>,
+    rotation: State<'_, AccountRotation>,
+    provider_id: String,
+    kind: StreamKind,
+    stream_id: String,
+    extension: Option<String>,
+) -> Result<StreamLease, String> {
+    let logins = providers.get(&provider_id)?.logins();
+    let key = stream_key(kind, &stream_id);
+    let account = rotation
+        .assign(&provider_id, &key, logins.len(), unix_now())
+        .ok_or("Every account of this provider is at its connection limit")?;
+    lease(&logins[account], kind, &stream_id, extension.as_deref())
+}
+
+// Command handler for a stream refused over the connection limit: its
+// account is set aside for a while and the stream moves to another
+#[tauri::command]
+pub fn xtream_rotate_account(
+    providers: State<'_, XtreamProviders>,
+    rotation: State<'_, AccountRotation>,
+    provider_id: String,
+    kind: StreamKind,
+    stream_id: String,
+    extension: Option<String>,
+) -> Result<StreamLease, String> {
+    let logins = providers.get(&provider_id)?.logins();
+    let key = stream_key(kind, &stream_id);
+    let account = rotation
+        .rotate(&provider_id, &key, logins.len(), unix_now())
+        .ok_or("Every account of this provider is at its connection limit")?;
+    lease(&logins[account], kind, &stream_id, extension.as_deref())
+}
+
+// Command handler for a stream that stopped playing
+#[tauri::command]
+pub fn xtream_close_stream(
+    rotation: State<'_, AccountRotation>,
+    provider_id: String,
+    kind: StreamKind,
+    stream_id: String,
+) -> bool {
+    rotation.release(&provider_id, &stream_key(kind, &stream_id))
+}
+
+// Command handler listing which streams use which account
+#[tauri::command]
+pub fn xtream_get_account_usage(
+    providers: State<'_, XtreamProviders>,
+    rotation: State<'_, AccountRotation>,
+    provider_id: String,
+) -> Result<Vec<AccountUsage>, String> {
+    let logins = providers.get(&provider_id)?.logins();
+    let usage = rotation.usage(&provider_id, logins.len(), unix_now());
+    Ok(logins
+        .into_iter()
+        .zip(usage)
+        .map(|(login, usage)| AccountUsage {
+            username: login.username,
+            streams: usage.streams,
+            at_limit: usage.at_limit,
+        })
+        .collect())
+}
+
+// Command handler replacing a provider's extra accounts; blank usernames
+// and repeats of another account are dropped
+#[tauri::command]
+pub fn xtream_set_accounts(
+    providers: State<'_, XtreamProviders>,
+    rotation: State<'_, AccountRotation>,
+    provider_id: String,
+    accounts: Vec<XtreamAccount>,
+) -> Result<XtreamProvider, String> {
+    let mut provider = providers.get(&provider_id)?;
+    let mut extra: Vec<XtreamAccount> = Vec::new();
+    for account in accounts {
+        validate_string_length(&account.username, MAX_FIELD_LENGTH)?;
+        validate_string_length(&account.password, MAX_FIELD_LENGTH)?;
+        let username = account.username.trim();
+        if username.is_empty()
+            || username == provider.credentials.username
+            || extra.iter().any(|a| a.username == username)
+        {
+            continue;
+        }
+        extra.push(XtreamAccount {
+            username: username.to_string(),
+            password: account.password.trim().to_string(),
+        });
+    }
+    provider.extra_accounts = extra;
+    providers.upsert(provider.clone())?;
+    // Account indexes changed, so the old assignments mean nothing
+    rotation.forget(&provider_id);
+    Ok(provider)
+}
+
+// Command handler that imports a provider's guide now instead of waiting
+// for the background refresh
+#[tauri::command]
+pub async fn xtream_refresh_epg(
+    app: AppHandle,
+    providers: State<'_, XtreamProviders>,
+    provider_id: String,
+) -> Result<EpgSource, String> {
+    let provider = providers.get(&provider_id)?;
+    guide::ingest(&app, &provider).await
+}
+
+fn provider_client(providers: &XtreamProviders, provider_id: &str) -> Result<XtreamClient, String> {
+    XtreamClient::new(&providers.get(provider_id)?.credentials)
+}
+
+// Command handler listing a provider's live TV categories
+#[tauri::command]
+pub async fn xtream_get_live_categories(
+    providers: State<'_, XtreamProviders>,
+    cache: State<'_, XtreamCache>,
+    provider_id: String,
+) -> Result<Arc<Vec<Category>>, String> {
+    if let Some(categories) = cache.live_categories.get(&provider_id, ALL_CATEGORIES) {
+        return Ok(categories);
+    }
+    let categories = provider_client(&providers, &provider_id)?.live_categories().await?;
+    Ok(cache.live_categories.insert(&provider_id, ALL_CATEGORIES, categories))
+}
+
+// Command handler listing a provider's live streams, all of them when no
+// category is given
+#[tauri::command]
+pub async fn xtream_get_live_streams(
+    providers: State<'_, XtreamProviders>,
+    cache: State<'_, XtreamCache>,
+    provider_id: String,
+    category_id: Option<String>,
+) -> Result<Arc<Vec<LiveStream>>, String> {
+    let category_id = category_id.filter(|id| !id.trim().is_empty());
+    let key = category_id.as_deref().unwrap_or(ALL_CATEGORIES);
+    if let Some(streams) = cached_streams(&cache.live_streams, &provider_id, key, |s| s.category_id.as_deref()) {
+        return Ok(streams);
+    }
+    let mut streams = provider_client(&providers, &provider_id)?
+        .live_streams(category_id.as_deref())
+        .await?;
+    for stream in &mut streams {
+        stream.channel_id = identity::channel_id(&provider_id, &identity::xtream_key(&stream.stream_id));
+    }
+    Ok(cache.live_streams.insert(&provider_id, key, streams))
+}
+
+// Command handler listing a provider's movie categories
+#[tauri::command]
+pub async fn xtream_get_vod_categories(
+    providers: State<'_, XtreamProviders>,
+    cache: State<'_, XtreamCache>,
+    provider_id: String,
+) -> Result<Arc<Vec<Category>>, String> {
+    if let Some(categories) = cache.vod_categories.get(&provider_id, ALL_CATEGORIES) {
+        return Ok(categories);
+    }
+    let categories = provider_client(&providers, &provider_id)?.vod_categories().await?;
+    Ok(cache.vod_categories.insert(&provider_id, ALL_CATEGORIES, categories))
+}
+
+// Command handler listing a provider's movies, all of them when no category
+// is given
+#[tauri::command]
+pub async fn xtream_get_vod_streams(
+    providers: State<'_, XtreamProviders>,
+    cache: State<'_, XtreamCache>,
+    provider_id: String,
+    category_id: Option<String>,
+) -> Result<Arc<Vec<VodStream>>, String> {
+    let category_id = category_id.filter(|id| !id.trim().is_empty());
+    let key = category_id.as_deref().unwrap_or(ALL_CATEGORIES);
+    if let Some(streams) = cached_streams(&cache.vod_streams, &provider_id, key, |s| s.category_id.as_deref()) {
+        return Ok(streams);
+    }
+    let streams = provider_client(&providers, &provider_id)?
+        .vod_streams(category_id.as_deref())
+        .await?;
+    Ok(cache.vod_streams.insert(&provider_id, key, streams))
+}
+
+// Command handler returning the details of one movie
+#[tauri::command]
+pub async fn xtream_get_vod_info(
+    providers: State<'_, XtreamProviders>,
+    cache: State<'_, XtreamCache>,
+    provider_id: String,
+    vod_id: String,
+) -> Result<Arc<VodInfo>, String> {
+    validate_string_length(&vod_id, MAX_FIELD_LENGTH)?;
+    let vod_id = vod_id.trim();
+    if let Some(info) = cache.vod_info.get(&provider_id, vod_id) {
+        return Ok(info);
+    }
+    let info = provider_client(&providers, &provider_id)?.vod_info(vod_id).await?;
+    Ok(cache.vod_info.insert(&provider_id, vod_id, info))
+}
+
+// Command handler listing a provider's series categories
+#[tauri::command]
+pub async fn xtream_get_series_categories(
+    providers: State<'_, XtreamProviders>,
+    cache: State<'_, XtreamCache>,
+    provider_id: String,
+) -> Result<Arc<Vec<Category>>, String> {
+    if let Some(categories) = cache.series_categories.get(&provider_id, ALL_CATEGORIES) {
+        return Ok(categories);
+    }
+    let categories = provider_client(&providers, &provider_id)?.series_categories().await?;
+    Ok(cache.series_categories.insert(&provider_id, ALL_CATEGORIES, categories))
+}
+
+// Command handler listing a provider's series, all of them when no category
+// is given
+#[tauri::command]
+pub async fn xtream_get_series(
+    providers: State<'_, XtreamProviders>,
+    cache: State<'_, XtreamCache>,
+    provider_id: String,
+    category_id: Option<String>,
+) -> Result<Arc<Vec<Series>>, String> {
+    let category_id = category_id.filter(|id| !id.trim().is_empty());
+    let key = category_id.as_deref().unwrap_or(ALL_CATEGORIES);
+    if let Some(series) = cached_streams(&cache.series, &provider_id, key, |s| s.category_id.as_deref()) {
+        return Ok(series);
+    }
+    let series = provider_client(&providers, &provider_id)?.series(category_id.as_deref()).await?;
+    Ok(cache.series.insert(&provider_id, key, series))
+}
+
+// Command handler returning the seasons and episodes of one series
+#[tauri::command]
+pub async fn xtream_get_series_info(
+    providers: State<'_, XtreamProviders>,
+    cache: State<'_, XtreamCache>,
+    provider_id: String,
+    series_id: String,
+) -> Result<Arc<SeriesInfo>, String> {
+    validate_string_length(&series_id, MAX_FIELD_LENGTH)?;
+    let series_id = series_id.trim();
+    if let Some(info) = cache.series_info.get(&provider_id, series_id) {
+        return Ok(info);
+    }
+    let info = provider_client(&providers, &provider_id)?.series_info(series_id).await?;
+    Ok(cache.series_info.insert(&provider_id, series_id, info))
+}
+
+// Command handler returning the next few programmes of a live stream
+#[tauri::command]
+pub async fn xtream_get_short_epg(
+    providers: State<'_, XtreamProviders>,
+    cache: State<'_, XtreamCache>,
+    provider_id: String,
+    stream_id: String,
+    limit: Option<u32>,
+) -> Result<Arc<Vec<EpgListing>>, String> {
+    validate_string_length(&stream_id, MAX_FIELD_LENGTH)?;
+    let stream_id = stream_id.trim();
+    let limit = limit.unwrap_or(DEFAULT_EPG_LIMIT).clamp(1, MAX_EPG_LIMIT);
+    let key = format!("{}:{}", stream_id, limit);
+    if let Some(listings) = cache.short_epg.get(&provider_id, &key) {
+        return Ok(listings);
+    }
+    let listings = provider_client(&providers, &provider_id)?.short_epg(stream_id, limit).await?;
+    Ok(cache.short_epg.insert(&provider_id, &key, listings))
+}
+
+// A cached list for `key`, or one filtered from the cached full list since
+// having every stream already is enough to answer for one category
+fn cached_streams<S: Clone>(
+    cache: &TtlCache<Vec<S>>,
+    provider_id: &str,
+    key: &str,
+    category_of: fn(&S) -> Option<&str>,
+) -> Option<Arc<Vec<S>>> {
+    if let Some(streams) = cache.get(provider_id, key) {
+        return Some(streams);
+    }
+    let all = cache.get(provider_id, ALL_CATEGORIES)?;
+    let streams = all.iter().filter(|s| category_of(s) == Some(key)).cloned().collect();
+    Some(Arc::new(streams))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    fn provider(id: &str) -> XtreamProvider {
+        XtreamProvider {
+            id: id.to_string(),
+            name: "Provider".to_string(),
+            credentials: XtreamCredentials {
+                server: "http://example.com:8080".to_string(),
+                username: "user".to_string(),
+                password: "pass".to_string(),
+            },
+            extra_accounts: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_provider_id_is_stable() {
+        // Test that the same account typed differently gets the same id
+        let make = |server: &str| {
+            let credentials = XtreamCredentials {
+                server: server.to_string(),
+                username: "user".to_string(),
+                password: "x".to_string(),
+            };
+            provider_id(&XtreamClient::new(&credentials).unwrap(), " user ")
+        };
+        assert_eq!(make("example.com:8080"), make("http://example.com:8080/player_api.php"));
+        assert_ne!(make("example.com:8080"), make("example.com:8081"));
+    }
+
+    #[test]
+    fn test_providers_persist() {
+        // Test that saved providers survive reopening and can be removed
+        let dir = temp_dir("xtream-providers");
+        let providers = XtreamProviders::open(&dir);
+        providers.upsert(provider("a")).unwrap();
+        providers.upsert(provider("b")).unwrap();
+        assert!(providers.remove("b").unwrap());
+        assert!(!providers.remove("b").unwrap());
+
+        let reopened = XtreamProviders::open(&dir);
+        assert_eq!(reopened.list(), vec![provider("a")]);
+        assert_eq!(reopened.get("a").unwrap(), provider("a"));
+        assert!(reopened.get("b").is_err());
+    }
+
+    #[test]
+    fn test_logins_share_server() {
+        // Test that extra accounts log in to the main account's server
+        let mut provider = provider("a");
+        provider.extra_accounts.push(XtreamAccount {
+            username: "second".to_string(),
+            password: "pw".to_string(),
+        });
+        let logins = provider.logins();
+        assert_eq!(logins[0], provider.credentials);
+        assert_eq!((logins[1].server.as_str(), logins[1].username.as_str()), ("http://example.com:8080", "second"));
+
+        let leased = lease(&logins[1], StreamKind::Live, " 42 ", None).unwrap();
+        assert_eq!(leased.url, "http://example.com:8080/live/second/pw/42.ts");
+        assert_eq!(stream_key(StreamKind::Movie, "7"), "movie:7");
+    }
+
+    #[test]
+    fn test_cached_streams() {
+        // Test that a cached full stream list answers for single categories
+        let stream = |id: &str, category: Option<&str>| LiveStream {
+            stream_id: id.to_string(),
+            category_id: category.map(str::to_string),
+            ..Default::default()
+        };
+        let cache = TtlCache::new(LIST_TTL);
+        let category: fn(&LiveStream) -> Option<&str> = |s| s.category_id.as_deref();
+        assert!(cached_streams(&cache, "p", "5", category).is_none());
+
+        let all = vec![stream("1", Some("5")), stream("2", None), stream("3", Some("5")), stream("4", Some("6"))];
+        cache.insert("p", ALL_CATEGORIES, all);
+        let found = cached_streams(&cache, "p", "5", category).unwrap();
+        assert_eq!(found.iter().map(|s| s.stream_id.as_str()).collect::<Vec<_>>(), vec!["1", "3"]);
+        assert!(cached_streams(&cache, "q", "5", category).is_none());
+    }
+}
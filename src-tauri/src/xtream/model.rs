@@ -0,0 +1,309 @@
+// Typed views of player_api.php responses; input keys are the snake_case
+// names Xtream uses, output is camelCase for the frontend
+use serde::{Deserialize, Serialize};
+
+use crate::de;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all(serialize = "camelCase"))]
+pub struct UserInfo {
+    #[serde(deserialize_with = "de::string")]
+    pub username: String,
+    #[serde(deserialize_with = "de::bool")]
+    pub auth: bool,
+    // "Active", "Expired", "Banned", "Disabled"
+    #[serde(deserialize_with = "de::string")]
+    pub status: String,
+    #[serde(deserialize_with = "de::string")]
+    pub message: String,
+    // Unix seconds, None for unlimited subscriptions
+    #[serde(deserialize_with = "de::opt_i64")]
+    pub exp_date: Option<i64>,
+    #[serde(deserialize_with = "de::opt_i64")]
+    pub created_at: Option<i64>,
+    #[serde(deserialize_with = "de::bool")]
+    pub is_trial: bool,
+    #[serde(deserialize_with = "de::u32")]
+    pub active_cons: u32,
+    #[serde(deserialize_with = "de::u32")]
+    pub max_connections: u32,
+    #[serde(deserialize_with = "de::string_list")]
+    pub allowed_output_formats: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all(serialize = "camelCase"))]
+pub struct ServerInfo {
+    #[serde(deserialize_with = "de::string")]
+    pub url: String,
+    #[serde(deserialize_with = "de::opt_u32")]
+    pub port: Option<u32>,
+    #[serde(deserialize_with = "de::opt_u32")]
+    pub https_port: Option<u32>,
+    #[serde(deserialize_with = "de::string")]
+    pub server_protocol: String,
+    #[serde(deserialize_with = "de::string")]
+    pub timezone: String,
+    #[serde(deserialize_with = "de::opt_i64")]
+    pub timestamp_now: Option<i64>,
+}
+
+// Response of an authentication request (player_api.php without action)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all(serialize = "camelCase"))]
+pub struct AccountInfo {
+    pub user_info: UserInfo,
+    pub server_info: ServerInfo,
+}
+
+// Subscription expiring within this many seconds gets flagged
+const EXPIRY_WARNING: i64 = 7 * 24 * 60 * 60;
+
+// Subscription state of an account, with the checks the UI warns about
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountStatus {
+    pub status: String,
+    // Unix seconds, None for subscriptions that don't expire
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub days_left: Option<i64>,
+    pub expired: bool,
+    pub expiring_soon: bool,
+    pub is_trial: bool,
+    pub active_connections: u32,
+    // 0 when the server doesn't say
+    pub max_connections: u32,
+    pub at_connection_limit: bool,
+    pub allowed_output_formats: Vec<String>,
+}
+
+impl AccountStatus {
+    pub fn new(user: UserInfo, now: i64) -> Self {
+        let expires_at = user.exp_date.filter(|at| *at > 0);
+        let expired = expires_at.is_some_and(|at| at <= now) || user.status.eq_ignore_ascii_case("expired");
+        AccountStatus {
+            days_left: expires_at.map(|at| ((at - now) / 86_400).max(0)),
+            expiring_soon: !expired && expires_at.is_some_and(|at| at - now <= EXPIRY_WARNING),
+            expired,
+            expires_at,
+            is_trial: user.is_trial,
+            active_connections: user.active_cons,
+            max_connections: user.max_connections,
+            at_connection_limit: user.max_connections > 0 && user.active_cons >= user.max_connections,
+            allowed_output_formats: user.allowed_output_formats,
+            status: user.status,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all(serialize = "camelCase"))]
+pub struct Category {
+    #[serde(deserialize_with = "de::string")]
+    pub category_id: String,
+    #[serde(deserialize_with = "de::string")]
+    pub category_name: String,
+    #[serde(deserialize_with = "de::opt_string", skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all(serialize = "camelCase"))]
+pub struct LiveStream {
+    #[serde(deserialize_with = "de::opt_u32")]
+    pub num: Option<u32>,
+    #[serde(deserialize_with = "de::string")]
+    pub name: String,
+    #[serde(deserialize_with = "de::string")]
+    pub stream_id: String,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub stream_icon: Option<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub epg_channel_id: Option<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub category_id: Option<String>,
+    // Catch-up is available for tv_archive_duration days
+    #[serde(deserialize_with = "de::bool")]
+    pub tv_archive: bool,
+    #[serde(deserialize_with = "de::opt_u32")]
+    pub tv_archive_duration: Option<u32>,
+    #[serde(deserialize_with = "de::opt_i64")]
+    pub added: Option<i64>,
+    // Playback URL, filled in by the client
+    #[serde(skip_deserializing)]
+    pub url: String,
+    // Stable identity (see crate::identity), filled in per provider
+    #[serde(skip_deserializing)]
+    pub channel_id: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all(serialize = "camelCase"))]
+pub struct VodStream {
+    #[serde(deserialize_with = "de::opt_u32")]
+    pub num: Option<u32>,
+    #[serde(deserialize_with = "de::string")]
+    pub name: String,
+    #[serde(deserialize_with = "de::string")]
+    pub stream_id: String,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub stream_icon: Option<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub category_id: Option<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub rating: Option<String>,
+    #[serde(deserialize_with = "de::opt_i64")]
+    pub added: Option<i64>,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub container_extension: Option<String>,
+    #[serde(skip_deserializing)]
+    pub url: String,
+}
+
+// The "info" part of get_vod_info, taken from TMDB by most panels
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all(serialize = "camelCase"))]
+pub struct VodDetails {
+    #[serde(deserialize_with = "de::opt_string")]
+    pub plot: Option<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub cast: Option<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub director: Option<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub genre: Option<String>,
+    #[serde(deserialize_with = "de::opt_string", rename(deserialize = "releasedate"))]
+    pub release_date: Option<String>,
+    // "01:52:10"
+    #[serde(deserialize_with = "de::opt_string")]
+    pub duration: Option<String>,
+    #[serde(deserialize_with = "de::opt_u32")]
+    pub duration_secs: Option<u32>,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub rating: Option<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub movie_image: Option<String>,
+    #[serde(deserialize_with = "de::string_list")]
+    pub backdrop_path: Vec<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub youtube_trailer: Option<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub tmdb_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all(serialize = "camelCase"))]
+pub struct VodData {
+    #[serde(deserialize_with = "de::string")]
+    pub stream_id: String,
+    #[serde(deserialize_with = "de::string")]
+    pub name: String,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub container_extension: Option<String>,
+    #[serde(deserialize_with = "de::opt_i64")]
+    pub added: Option<i64>,
+}
+
+// Response of get_vod_info
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all(serialize = "camelCase"))]
+pub struct VodInfo {
+    #[serde(deserialize_with = "de::object")]
+    pub info: VodDetails,
+    #[serde(deserialize_with = "de::object")]
+    pub movie_data: VodData,
+    #[serde(skip_deserializing)]
+    pub url: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account_info_from_messy_json() {
+        // Test that string-typed numbers and missing fields deserialize
+        let json = r#"{
+            "user_info": {"username": "bob", "auth": 1, "status": "Active", "exp_date": "1767225600",
+                "is_trial": "0", "active_cons": "1", "max_connections": 2,
+                "allowed_output_formats": ["m3u8", "ts"]},
+            "server_info": {"url": "example.com", "port": "8080", "https_port": "", "timestamp_now": 1700000000}
+        }"#;
+        let info: AccountInfo = serde_json::from_str(json).unwrap();
+        assert!(info.user_info.auth);
+        assert_eq!(info.user_info.exp_date, Some(1_767_225_600));
+        assert!(!info.user_info.is_trial);
+        assert_eq!(info.user_info.max_connections, 2);
+        assert_eq!(info.server_info.port, Some(8080));
+        assert_eq!(info.server_info.https_port, None);
+
+        let output = serde_json::to_string(&info.user_info).unwrap();
+        assert!(output.contains(r#""maxConnections":2"#));
+        let failed: AccountInfo = serde_json::from_str(r#"{"user_info": {"auth": 0}}"#).unwrap();
+        assert!(!failed.user_info.auth);
+    }
+
+    #[test]
+    fn test_account_status() {
+        // Test expiry and connection-limit flags
+        let user = |exp_date: Option<i64>, active_cons: u32| UserInfo {
+            status: "Active".to_string(),
+            exp_date,
+            active_cons,
+            max_connections: 2,
+            ..Default::default()
+        };
+        let now = 1_700_000_000;
+        let soon = AccountStatus::new(user(Some(now + 3 * 86_400 + 10), 1), now);
+        assert_eq!(soon.days_left, Some(3));
+        assert!(soon.expiring_soon && !soon.expired && !soon.at_connection_limit);
+
+        let lapsed = AccountStatus::new(user(Some(now - 1), 2), now);
+        assert!(lapsed.expired && !lapsed.expiring_soon && lapsed.at_connection_limit);
+        assert_eq!(lapsed.days_left, Some(0));
+
+        let unlimited = AccountStatus::new(user(None, 0), now);
+        assert_eq!((unlimited.expires_at, unlimited.expired, unlimited.expiring_soon), (None, false, false));
+    }
+
+    #[test]
+    fn test_live_stream_coercion() {
+        // Test that numeric ids become strings and blanks become None
+        let json = r#"{"num": "3", "name": "BBC One", "stream_id": 1234, "stream_icon": "",
+            "epg_channel_id": null, "category_id": 5, "tv_archive": 1, "tv_archive_duration": "7"}"#;
+        let stream: LiveStream = serde_json::from_str(json).unwrap();
+        assert_eq!(stream.num, Some(3));
+        assert_eq!(stream.stream_id, "1234");
+        assert_eq!(stream.stream_icon, None);
+        assert_eq!(stream.category_id.as_deref(), Some("5"));
+        assert!(stream.tv_archive);
+        assert_eq!(stream.tv_archive_duration, Some(7));
+
+        let category: Category = serde_json::from_str(r#"{"category_id": 1, "category_name": "UK", "parent_id": 0}"#).unwrap();
+        assert_eq!(category.category_id, "1");
+        assert_eq!(category.parent_id.as_deref(), Some("0"));
+    }
+
+    #[test]
+    fn test_vod_info_shapes() {
+        // Test a full get_vod_info answer and the empty-array one for unknown ids
+        let json = r#"{
+            "info": {"plot": "A film.", "releasedate": "2019-05-01", "duration_secs": "6730",
+                "duration": "01:52:10", "backdrop_path": "http://img/b.jpg", "movie_image": "http://img/c.jpg",
+                "cover_big": "http://img/c.jpg", "tmdb_id": 12, "tmdb": 12},
+            "movie_data": {"stream_id": 77, "name": "Film", "container_extension": "mkv"}
+        }"#;
+        let info: VodInfo = serde_json::from_str(json).unwrap();
+        assert_eq!(info.info.release_date.as_deref(), Some("2019-05-01"));
+        assert_eq!(info.info.duration_secs, Some(6730));
+        assert_eq!(info.info.backdrop_path, vec!["http://img/b.jpg"]);
+        assert_eq!(info.info.movie_image.as_deref(), Some("http://img/c.jpg"));
+        assert_eq!(info.info.tmdb_id.as_deref(), Some("12"));
+        assert_eq!(info.movie_data.stream_id, "77");
+
+        let missing: VodInfo = serde_json::from_str(r#"{"info": [], "movie_data": []}"#).unwrap();
+        assert_eq!(missing, VodInfo::default());
+    }
+}
@@ -0,0 +1,135 @@
+// Which of a provider's accounts each open stream uses, so a stream that
+// runs into an account's connection limit can move to another account
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+// An account that hit its limit isn't handed out again for this long
+const LIMIT_COOLDOWN: i64 = 5 * 60;
+
+// Streams on one account and whether it recently hit its limit
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountUse {
+    pub streams: Vec<String>,
+    pub at_limit: bool,
+}
+
+#[derive(Default)]
+struct ProviderUse {
+    // Stream key -> account index
+    streams: HashMap<String, usize>,
+    // Account index -> unix seconds it last hit the limit
+    limited: HashMap<usize, i64>,
+}
+
+impl ProviderUse {
+    fn at_limit(&self, account: usize, now: i64) -> bool {
+        self.limited.get(&account).is_some_and(|at| now - at < LIMIT_COOLDOWN)
+    }
+
+    // Least busy account that isn't at its limit, main account first on ties
+    fn pick(&self, accounts: usize, now: i64, except: Option<usize>) -> Option<usize> {
+        (0..accounts)
+            .filter(|account| Some(*account) != except && !self.at_limit(*account, now))
+            .min_by_key(|account| (self.streams.values().filter(|a| *a == account).count(), *account))
+    }
+}
+
+#[derive(Default)]
+pub struct AccountRotation {
+    providers: Mutex<HashMap<String, ProviderUse>>,
+}
+
+impl AccountRotation {
+    // Account for a stream: the one it already uses, else the least busy
+    // one; None when every account is at its limit
+    pub fn assign(&self, provider_id: &str, stream: &str, accounts: usize, now: i64) -> Option<usize> {
+        let mut providers = self.providers.lock().unwrap();
+        let usage = providers.entry(provider_id.to_string()).or_default();
+        if let Some(&account) = usage.streams.get(stream).filter(|account| **account < accounts) {
+            return Some(account);
+        }
+        let account = usage.pick(accounts, now, None)?;
+        usage.streams.insert(stream.to_string(), account);
+        Some(account)
+    }
+
+    // The stream's account hit its connection limit: set that account
+    // aside for a while and move the stream to another
+    pub fn rotate(&self, provider_id: &str, stream: &str, accounts: usize, now: i64) -> Option<usize> {
+        let mut providers = self.providers.lock().unwrap();
+        let usage = providers.entry(provider_id.to_string()).or_default();
+        let current = usage.streams.remove(stream);
+        if let Some(current) = current {
+            usage.limited.insert(current, now);
+        }
+        let account = usage.pick(accounts, now, current)?;
+        usage.streams.insert(stream.to_string(), account);
+        Some(account)
+    }
+
+    pub fn release(&self, provider_id: &str, stream: &str) -> bool {
+        let mut providers = self.providers.lock().unwrap();
+        let usage = providers.get_mut(provider_id);
+        usage.is_some_and(|usage| usage.streams.remove(stream).is_some())
+    }
+
+    // Drop the bookkeeping of a provider whose accounts changed
+    pub fn forget(&self, provider_id: &str) {
+        self.providers.lock().unwrap().remove(provider_id);
+    }
+
+    pub fn usage(&self, provider_id: &str, accounts: usize, now: i64) -> Vec<AccountUse> {
+        let providers = self.providers.lock().unwrap();
+        let usage = providers.get(provider_id);
+        (0..accounts)
+            .map(|account| {
+                let Some(usage) = usage else {
+                    return AccountUse {
+                        streams: Vec::new(),
+                        at_limit: false,
+                    };
+                };
+                let mut streams: Vec<String> = usage
+                    .streams
+                    .iter()
+                    .filter(|(_, a)| **a == account)
+                    .map(|(stream, _)| stream.clone())
+                    .collect();
+                streams.sort();
+                AccountUse {
+                    streams,
+                    at_limit: usage.at_limit(account, now),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_streams_spread_and_rotate() {
+        // Test that streams spread over accounts and move away from a full one
+        let rotation = AccountRotation::default();
+        assert_eq!(rotation.assign("p", "live:1", 2, 0), Some(0));
+        assert_eq!(rotation.assign("p", "live:2", 2, 0), Some(1));
+        assert_eq!(rotation.assign("p", "live:1", 2, 0), Some(0));
+
+        assert_eq!(rotation.rotate("p", "live:1", 2, 10), Some(1));
+        let usage = rotation.usage("p", 2, 10);
+        assert!(usage[0].at_limit && usage[0].streams.is_empty());
+        assert_eq!(usage[1].streams, vec!["live:1", "live:2"]);
+
+        // The only other account is busy but not limited, so it's shared
+        assert_eq!(rotation.assign("p", "live:3", 2, 20), Some(1));
+        assert_eq!(rotation.rotate("p", "live:3", 2, 20), None);
+        assert_eq!(rotation.assign("p", "live:4", 2, 20 + LIMIT_COOLDOWN), Some(0));
+
+        assert!(rotation.release("p", "live:2"));
+        assert!(!rotation.release("p", "live:2"));
+        rotation.forget("p");
+        assert!(rotation.usage("p", 2, 0).iter().all(|account| account.streams.is_empty()));
+    }
+}
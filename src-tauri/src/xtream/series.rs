@@ -0,0 +1,245 @@
+// Series → Season → Episode model built from get_series_info, whose answer
+// spreads seasons over a "seasons" list and an "episodes" map keyed by the
+// season number (or a plain array of arrays on some panels)
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::de;
+
+// Item of get_series, also the "info" part of get_series_info
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all(serialize = "camelCase"))]
+pub struct Series {
+    #[serde(deserialize_with = "de::opt_u32")]
+    pub num: Option<u32>,
+    #[serde(deserialize_with = "de::string")]
+    pub series_id: String,
+    #[serde(deserialize_with = "de::string")]
+    pub name: String,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub cover: Option<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub plot: Option<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub cast: Option<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub director: Option<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub genre: Option<String>,
+    #[serde(deserialize_with = "de::opt_string", rename(deserialize = "releaseDate"))]
+    pub release_date: Option<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub rating: Option<String>,
+    #[serde(deserialize_with = "de::string_list")]
+    pub backdrop_path: Vec<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub youtube_trailer: Option<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub category_id: Option<String>,
+    #[serde(deserialize_with = "de::opt_i64")]
+    pub last_modified: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all(serialize = "camelCase"))]
+pub struct EpisodeDetails {
+    #[serde(deserialize_with = "de::opt_string")]
+    pub plot: Option<String>,
+    #[serde(deserialize_with = "de::opt_string", rename(deserialize = "releasedate"))]
+    pub release_date: Option<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub duration: Option<String>,
+    #[serde(deserialize_with = "de::opt_u32")]
+    pub duration_secs: Option<u32>,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub rating: Option<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub movie_image: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all(serialize = "camelCase"))]
+pub struct Episode {
+    // Stream id used in the playback URL
+    #[serde(deserialize_with = "de::string")]
+    pub id: String,
+    #[serde(deserialize_with = "de::opt_u32")]
+    pub episode_num: Option<u32>,
+    #[serde(deserialize_with = "de::string")]
+    pub title: String,
+    #[serde(deserialize_with = "de::opt_u32")]
+    pub season: Option<u32>,
+    #[serde(deserialize_with = "de::opt_string")]
+    pub container_extension: Option<String>,
+    #[serde(deserialize_with = "de::opt_i64")]
+    pub added: Option<i64>,
+    #[serde(deserialize_with = "de::object")]
+    pub info: EpisodeDetails,
+    #[serde(skip_deserializing)]
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Season {
+    pub number: u32,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overview: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub air_date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cover: Option<String>,
+    pub episodes: Vec<Episode>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeriesInfo {
+    pub info: Series,
+    pub seasons: Vec<Season>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct RawSeason {
+    #[serde(deserialize_with = "de::opt_u32")]
+    season_number: Option<u32>,
+    #[serde(deserialize_with = "de::string")]
+    name: String,
+    #[serde(deserialize_with = "de::opt_string")]
+    overview: Option<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    air_date: Option<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    cover: Option<String>,
+    #[serde(deserialize_with = "de::opt_string")]
+    cover_big: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct RawSeriesInfo {
+    #[serde(deserialize_with = "de::object")]
+    info: Series,
+    seasons: Value,
+    episodes: Value,
+}
+
+// Episode lists with the season number their position implies, if any
+fn episode_groups(episodes: Value) -> Vec<(Option<u32>, Value)> {
+    match episodes {
+        Value::Object(map) => map.into_iter().map(|(key, items)| (key.trim().parse().ok(), items)).collect(),
+        Value::Array(items) => {
+            let (nested, flat): (Vec<Value>, Vec<Value>) = items.into_iter().partition(Value::is_array);
+            let mut groups: Vec<_> = nested.into_iter().map(|items| (None, items)).collect();
+            if !flat.is_empty() {
+                groups.push((None, Value::Array(flat)));
+            }
+            groups
+        }
+        _ => Vec::new(),
+    }
+}
+
+impl SeriesInfo {
+    // Seasons in order with their episodes sorted; seasons the server lists
+    // without episodes are dropped since there's nothing to play
+    pub fn from_value(value: Value) -> Self {
+        let raw: RawSeriesInfo = serde_json::from_value(value).unwrap_or_default();
+        let mut listed: BTreeMap<u32, RawSeason> = de::list::<RawSeason>(raw.seasons)
+            .into_iter()
+            .filter_map(|season| Some((season.season_number?, season)))
+            .collect();
+
+        let mut seasons: BTreeMap<u32, Vec<Episode>> = BTreeMap::new();
+        for (key, items) in episode_groups(raw.episodes) {
+            for mut episode in de::list::<Episode>(items) {
+                if episode.id.is_empty() {
+                    continue;
+                }
+                let number = episode.season.or(key).unwrap_or(1);
+                episode.season = Some(number);
+                seasons.entry(number).or_default().push(episode);
+            }
+        }
+
+        let seasons = seasons
+            .into_iter()
+            .map(|(number, mut episodes)| {
+                episodes.sort_by_key(|episode| episode.episode_num.unwrap_or(u32::MAX));
+                let raw = listed.remove(&number).unwrap_or_default();
+                Season {
+                    number,
+                    name: match raw.name.as_str() {
+                        "" => format!("Season {}", number),
+                        name => name.to_string(),
+                    },
+                    overview: raw.overview,
+                    air_date: raw.air_date,
+                    cover: raw.cover_big.or(raw.cover),
+                    episodes,
+                }
+            })
+            .collect();
+        SeriesInfo { info: raw.info, seasons }
+    }
+
+    pub fn episodes_mut(&mut self) -> impl Iterator<Item = &mut Episode> {
+        self.seasons.iter_mut().flat_map(|season| season.episodes.iter_mut())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_series_info_from_keyed_episodes() {
+        // Test the common shape: season metadata plus episodes keyed by season
+        let info = SeriesInfo::from_value(json!({
+            "seasons": [
+                {"season_number": 2, "name": "Season 2", "cover_big": "http://img/s2.jpg"},
+                {"season_number": 3, "name": "Season 3"}
+            ],
+            "info": {"name": "Show", "releaseDate": "2020-01-01", "backdrop_path": ["http://img/b.jpg"]},
+            "episodes": {
+                "2": [
+                    {"id": "202", "episode_num": "2", "title": "S2E2", "container_extension": "mkv"},
+                    {"id": 201, "episode_num": 1, "title": "S2E1", "info": {"duration_secs": 1500}}
+                ],
+                "1": [{"id": "101", "episode_num": 1, "title": "S1E1", "info": []}]
+            }
+        }));
+
+        assert_eq!(info.info.name, "Show");
+        assert_eq!(info.info.release_date.as_deref(), Some("2020-01-01"));
+        assert_eq!(info.seasons.iter().map(|s| s.number).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(info.seasons[0].name, "Season 1");
+        let second = &info.seasons[1];
+        assert_eq!(second.cover.as_deref(), Some("http://img/s2.jpg"));
+        assert_eq!(second.episodes.iter().map(|e| e.id.as_str()).collect::<Vec<_>>(), vec!["201", "202"]);
+        assert_eq!(second.episodes[0].info.duration_secs, Some(1500));
+        assert_eq!(second.episodes[0].season, Some(2));
+    }
+
+    #[test]
+    fn test_series_info_from_nested_arrays() {
+        // Test panels that send episodes as an array of arrays, or nothing
+        let info = SeriesInfo::from_value(json!({
+            "info": [],
+            "episodes": [
+                [{"id": "1", "season": 1, "episode_num": 1}],
+                [{"id": "2", "season": "4", "episode_num": 1}, {"title": "no id"}]
+            ]
+        }));
+        assert_eq!(info.seasons.iter().map(|s| s.number).collect::<Vec<_>>(), vec![1, 4]);
+        assert_eq!(info.seasons[1].episodes.len(), 1);
+
+        let empty = SeriesInfo::from_value(json!({"seasons": [], "info": [], "episodes": []}));
+        assert_eq!(empty, SeriesInfo::default());
+    }
+}